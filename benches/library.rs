@@ -0,0 +1,117 @@
+//! Benchmarks of the hot paths of the library: adding songs, reading and searching every song, and
+//! walking the music dir for a scan
+//!
+//! `cargo bench --features debug -- --bench-fixtures <dir>` writes the fixtures to the dir instead
+//! of benchmarking, a seeded database and a music dir of placeholder files to run muzik against.
+//! Debug builds read `./dev.db`, so copy the database there to use it
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result};
+use criterion::{criterion_group, BatchSize, BenchmarkId, Criterion};
+use muzik::{
+  config::{Config, ScanConfig},
+  database::Database,
+  query::Query,
+  scan::{self, IgnoreRules},
+  seed::{self, SeedOptions},
+};
+
+/// The artists of the seeded libraries, of about sixteen songs each
+const ARTISTS: [usize; 3] = [10, 50, 200];
+/// The files of the walked music dirs
+const FILES: [usize; 3] = [100, 1_000, 10_000];
+
+fn seeded(artists: usize) -> Database {
+  let mut database = Database::open_migrated(":memory:", Config::default()).expect("migrations run");
+  seed::seed(&mut database, Path::new(""), SeedOptions { artists, seed: 0, files: false }).expect("library seeded");
+  database
+}
+
+fn bulk_insert(c: &mut Criterion) {
+  let mut group = c.benchmark_group("bulk_insert");
+  group.sample_size(10);
+  for artists in ARTISTS {
+    group.bench_with_input(BenchmarkId::from_parameter(artists), &artists, |b, &artists| {
+      b.iter_batched(
+        || Database::open_migrated(":memory:", Config::default()).expect("migrations run"),
+        |mut database| seed::seed(&mut database, Path::new(""), SeedOptions { artists, seed: 0, files: false }),
+        BatchSize::PerIteration,
+      )
+    });
+  }
+  group.finish();
+}
+
+fn all_songs(c: &mut Criterion) {
+  let mut group = c.benchmark_group("all_songs");
+  for artists in ARTISTS {
+    let mut database = seeded(artists);
+    group.bench_function(BenchmarkId::from_parameter(artists), |b| {
+      b.iter(|| database.for_each_song(None, None, 0, |_| Ok(())).expect("songs read"))
+    });
+  }
+  group.finish();
+}
+
+fn search(c: &mut Criterion) {
+  let mut group = c.benchmark_group("search");
+  let query = Query::search("midnight");
+  for artists in ARTISTS {
+    let mut database = seeded(artists);
+    group.bench_function(BenchmarkId::from_parameter(artists), |b| {
+      b.iter(|| database.for_each_song(Some(&query), None, 0, |_| Ok(())).expect("songs searched"))
+    });
+  }
+  group.finish();
+}
+
+fn scan_music_dir(c: &mut Criterion) {
+  let mut group = c.benchmark_group("scan_music_dir");
+  let rules = IgnoreRules::new(&ScanConfig::default()).expect("default rules are valid");
+  for files in FILES {
+    let music_dir = std::env::temp_dir().join(format!("muzik-bench-{}", uuid::Uuid::new_v4()));
+    seed::write_placeholder_files(&music_dir, files).expect("files written");
+    group.bench_function(BenchmarkId::from_parameter(files), |b| {
+      b.iter(|| scan::music_files(&music_dir, &rules).expect("music dir walked"))
+    });
+    std::fs::remove_dir_all(&music_dir).expect("files removed");
+  }
+  group.finish();
+}
+
+/// Write the largest fixtures into the dir, `database.db` and the `music` dir
+fn write_fixtures(directory: &Path) -> Result<()> {
+  std::fs::create_dir_all(directory)?;
+  let path = directory.join("database.db");
+  if path.exists() {
+    return Err(eyre!("{} exists already", path.display()));
+  }
+  // seeded in memory, as every insert into a file waits for the disk
+  let mut database = Database::open_migrated(":memory:", Config::default())?;
+  let artists = ARTISTS[ARTISTS.len() - 1];
+  let seeded = seed::seed(&mut database, Path::new(""), SeedOptions { artists, seed: 0, files: false })?;
+  database.backup_into(&path)?;
+  let files = FILES[FILES.len() - 1];
+  seed::write_placeholder_files(&directory.join("music"), files)?;
+  println!(
+    "wrote {} songs to {} and {files} files to {}",
+    seeded.songs,
+    path.display(),
+    directory.join("music").display()
+  );
+  Ok(())
+}
+
+criterion_group!(benches, bulk_insert, all_songs, search, scan_music_dir);
+
+fn main() -> Result<()> {
+  let mut args = std::env::args().skip_while(|arg| arg != "--bench-fixtures");
+  if args.next().is_some() {
+    let directory = args.next().map(PathBuf::from).ok_or_else(|| eyre!("pass the dir to write the fixtures to"))?;
+    return write_fixtures(&directory);
+  }
+  benches();
+  Criterion::default().configure_from_args().final_summary();
+  Ok(())
+}
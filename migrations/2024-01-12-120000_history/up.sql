@@ -0,0 +1,17 @@
+-- Your SQL goes here
+ALTER TABLE "song" ADD COLUMN "added_at" TIMESTAMP;
+
+CREATE TABLE "play" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "song_id" INTEGER NOT NULL,
+    "played_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TABLE "download" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "youtube_id" TEXT NOT NULL,
+    "title" TEXT NOT NULL,
+    "error" TEXT,
+    "finished_at" TIMESTAMP NOT NULL
+);
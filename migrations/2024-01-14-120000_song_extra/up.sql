@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE "song_extra" (
+    "song_id" INTEGER NOT NULL PRIMARY KEY,
+    "description" TEXT,
+    "comments" TEXT,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
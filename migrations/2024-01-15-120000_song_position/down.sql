@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "song" DROP COLUMN "disc_number";
+ALTER TABLE "song" DROP COLUMN "track_number";
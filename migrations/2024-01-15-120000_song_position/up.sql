@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "song" ADD COLUMN "track_number" INTEGER;
+ALTER TABLE "song" ADD COLUMN "disc_number" INTEGER;
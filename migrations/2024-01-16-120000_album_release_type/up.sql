@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "album" ADD COLUMN "release_type" TEXT;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE "pinned" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "kind" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+  UNIQUE("kind", "name")
+);
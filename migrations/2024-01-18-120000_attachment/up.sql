@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "attachment" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "song_id" INTEGER,
+    "album_id" INTEGER,
+    "kind" TEXT NOT NULL,
+    "relative_path" TEXT NOT NULL UNIQUE,
+    "added_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("album_id") REFERENCES album("id"),
+  CHECK(("song_id" IS NULL) <> ("album_id" IS NULL))
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE "song_version" (
+    "song_id" INTEGER NOT NULL PRIMARY KEY,
+    "original_id" INTEGER NOT NULL,
+    "kind" TEXT NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("original_id") REFERENCES song("id"),
+  CHECK("song_id" <> "original_id")
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "file" ADD COLUMN "codec" TEXT;
+ALTER TABLE "file" ADD COLUMN "bitrate" INTEGER;
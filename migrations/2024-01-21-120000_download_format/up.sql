@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "download" ADD COLUMN "format" TEXT;
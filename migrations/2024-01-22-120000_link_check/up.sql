@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "link_check" (
+    "song_id" INTEGER NOT NULL PRIMARY KEY,
+    "checked_at" TIMESTAMP NOT NULL,
+    "dead_reason" TEXT,
+    "suggestion_id" TEXT,
+    "suggestion_title" TEXT,
+    "suggestion_score" INTEGER,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
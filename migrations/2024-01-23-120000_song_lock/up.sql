@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE "song_lock" (
+    "song_id" INTEGER NOT NULL,
+    "field" TEXT NOT NULL,
+  PRIMARY KEY("song_id", "field"),
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
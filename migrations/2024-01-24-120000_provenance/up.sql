@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "provenance" (
+    "song_id" INTEGER NOT NULL,
+    "field" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "value" TEXT,
+    "previous_source" TEXT,
+    "previous_value" TEXT,
+    "recorded_at" TIMESTAMP NOT NULL,
+  PRIMARY KEY("song_id", "field"),
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "download_params" (
+    "file_id" INTEGER PRIMARY KEY NOT NULL,
+    "youtube_id" TEXT NOT NULL,
+    "format" TEXT NOT NULL,
+    "formats" TEXT NOT NULL,
+    "comments" BOOLEAN NOT NULL,
+    "proxy" BOOLEAN NOT NULL,
+    "trim_silence" BOOLEAN NOT NULL,
+    "yt_dlp_version" TEXT,
+    "downloaded_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("file_id") REFERENCES file("id")
+);
@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER "song_search_song_insert";
+DROP TRIGGER "song_search_song_update";
+DROP TRIGGER "song_search_song_delete";
+DROP TRIGGER "song_search_artist_insert";
+DROP TRIGGER "song_search_artist_update";
+DROP TRIGGER "song_search_artist_delete";
+DROP TRIGGER "song_search_album_insert";
+DROP TRIGGER "song_search_album_update";
+DROP TRIGGER "song_search_album_delete";
+DROP TRIGGER "song_search_genre_insert";
+DROP TRIGGER "song_search_genre_update";
+DROP TRIGGER "song_search_genre_delete";
+DROP TRIGGER "song_search_artist_rename";
+DROP TRIGGER "song_search_album_rename";
+DROP TRIGGER "song_search_genre_rename";
+DROP VIEW "song_search_source";
+DROP TABLE "song_search";
@@ -0,0 +1,117 @@
+-- Your SQL goes here
+-- The text of every song searched by the Manager. The trigram tokenizer matches any part of a
+-- word, regardless of case
+CREATE VIRTUAL TABLE "song_search" USING fts5("title", "artists", "album", "genres", tokenize = 'trigram');
+
+-- What is searched of every song, kept in "song_search" by the triggers below
+CREATE VIEW "song_search_source" AS
+SELECT
+    "song"."id",
+    "song"."title",
+    (SELECT group_concat("artist"."name", ' ') FROM "songs_artists" JOIN "artist" ON "artist"."id" = "songs_artists"."artist_id"
+        WHERE "songs_artists"."song_id" = "song"."id") AS "artists",
+    (SELECT group_concat("album"."name", ' ') FROM "songs_albums" JOIN "album" ON "album"."id" = "songs_albums"."album_id"
+        WHERE "songs_albums"."song_id" = "song"."id") AS "album",
+    (SELECT group_concat("genre"."name", ' ') FROM "songs_genres" JOIN "genre" ON "genre"."id" = "songs_genres"."genre_id"
+        WHERE "songs_genres"."song_id" = "song"."id") AS "genres"
+FROM "song";
+
+INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+    SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source";
+
+CREATE TRIGGER "song_search_song_insert" AFTER INSERT ON "song" BEGIN
+    DELETE FROM "song_search" WHERE rowid = new."id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."id";
+END;
+
+CREATE TRIGGER "song_search_song_update" AFTER UPDATE OF "title" ON "song" BEGIN
+    DELETE FROM "song_search" WHERE rowid = new."id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."id";
+END;
+
+CREATE TRIGGER "song_search_song_delete" AFTER DELETE ON "song" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."id";
+END;
+
+CREATE TRIGGER "song_search_artist_insert" AFTER INSERT ON "songs_artists" BEGIN
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_artist_update" AFTER UPDATE ON "songs_artists" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_artist_delete" AFTER DELETE ON "songs_artists" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+END;
+
+CREATE TRIGGER "song_search_album_insert" AFTER INSERT ON "songs_albums" BEGIN
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_album_update" AFTER UPDATE ON "songs_albums" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_album_delete" AFTER DELETE ON "songs_albums" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+END;
+
+CREATE TRIGGER "song_search_genre_insert" AFTER INSERT ON "songs_genres" BEGIN
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_genre_update" AFTER UPDATE ON "songs_genres" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+    DELETE FROM "song_search" WHERE rowid = new."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = new."song_id";
+END;
+
+CREATE TRIGGER "song_search_genre_delete" AFTER DELETE ON "songs_genres" BEGIN
+    DELETE FROM "song_search" WHERE rowid = old."song_id";
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" = old."song_id";
+END;
+
+CREATE TRIGGER "song_search_artist_rename" AFTER UPDATE OF "name" ON "artist" BEGIN
+    DELETE FROM "song_search" WHERE rowid IN (SELECT "song_id" FROM "songs_artists" WHERE "artist_id" = new."id");
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" IN (SELECT "song_id" FROM "songs_artists" WHERE "artist_id" = new."id");
+END;
+
+CREATE TRIGGER "song_search_album_rename" AFTER UPDATE OF "name" ON "album" BEGIN
+    DELETE FROM "song_search" WHERE rowid IN (SELECT "song_id" FROM "songs_albums" WHERE "album_id" = new."id");
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" IN (SELECT "song_id" FROM "songs_albums" WHERE "album_id" = new."id");
+END;
+
+CREATE TRIGGER "song_search_genre_rename" AFTER UPDATE OF "name" ON "genre" BEGIN
+    DELETE FROM "song_search" WHERE rowid IN (SELECT "song_id" FROM "songs_genres" WHERE "genre_id" = new."id");
+    INSERT INTO "song_search"(rowid, "title", "artists", "album", "genres")
+        SELECT "id", "title", "artists", "album", "genres" FROM "song_search_source" WHERE "id" IN (SELECT "song_id" FROM "songs_genres" WHERE "genre_id" = new."id");
+END;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- The export profiles of the settings a playlist is exported with, by name
+CREATE TABLE "playlist_profiles" (
+    "playlist_id" INTEGER NOT NULL,
+    "profile" TEXT NOT NULL,
+  PRIMARY KEY("playlist_id", "profile"),
+  FOREIGN KEY("playlist_id") REFERENCES playlist("id")
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- The SHA-256 checksum of the file when it was last verified, and what was wrong with it if anything
+ALTER TABLE "file" ADD COLUMN "checksum" TEXT;
+ALTER TABLE "file" ADD COLUMN "verified_at" TIMESTAMP;
+ALTER TABLE "file" ADD COLUMN "problem" TEXT;
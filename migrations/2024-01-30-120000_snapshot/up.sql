@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- The state of the library at a point in time, to tell what changed since
+CREATE TABLE "snapshot" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "name" TEXT NOT NULL,
+    "taken_at" TIMESTAMP NOT NULL
+);
+
+-- Every song of the library when the snapshot was taken, with hashes of its metadata and its file
+CREATE TABLE "snapshot_song" (
+    "snapshot_id" INTEGER NOT NULL,
+    "song_id" INTEGER NOT NULL,
+    "title" TEXT NOT NULL,
+    "artists" TEXT NOT NULL,
+    "metadata_hash" TEXT NOT NULL,
+    "file_hash" TEXT,
+  PRIMARY KEY("snapshot_id", "song_id"),
+  FOREIGN KEY("snapshot_id") REFERENCES snapshot("id")
+);
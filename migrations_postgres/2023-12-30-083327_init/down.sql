@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "songs_genres";
+DROP TABLE "songs_albums";
+DROP TABLE "songs_artists";
+DROP TABLE "genre";
+DROP TABLE "album";
+DROP TABLE "artist";
+DROP TABLE "song";
+DROP TABLE "file";
+DROP FUNCTION "count_data_change";
+DROP TABLE "data_changes";
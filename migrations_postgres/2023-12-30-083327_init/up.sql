@@ -0,0 +1,97 @@
+-- Your SQL goes here
+-- The tables of the SQLite migrations, written for Postgres
+CREATE TABLE "file" (
+    "id" SERIAL PRIMARY KEY,
+    "relative_path" TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE "song" (
+    "id" SERIAL PRIMARY KEY,
+    "title" TEXT NOT NULL,
+    "source" TEXT,
+    "youtube_id" TEXT,
+    "thumbnail_url" TEXT,
+    "file_id" INTEGER UNIQUE,
+  FOREIGN KEY("file_id") REFERENCES file("id")
+);
+
+CREATE TABLE "artist" (
+    "id" SERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE "album" (
+    "id" SERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE "genre" (
+    "id" SERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE "songs_artists" (
+    "song_id" INTEGER NOT NULL,
+    "artist_id" INTEGER NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("artist_id") REFERENCES artist("id"),
+  PRIMARY KEY("song_id", "artist_id")
+);
+
+CREATE TABLE "songs_albums" (
+    "song_id" INTEGER NOT NULL,
+    "album_id" INTEGER NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("album_id") REFERENCES album("id"),
+  PRIMARY KEY("song_id", "album_id")
+);
+
+CREATE TABLE "songs_genres" (
+    "song_id" INTEGER NOT NULL,
+    "genre_id" INTEGER NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("genre_id") REFERENCES genre("id"),
+  PRIMARY KEY("song_id", "genre_id")
+);
+
+-- How many statements changing the database every connection ran, by the id of its server process.
+-- A connection tells that another one changed the library by the sum of the others, as SQLite tells
+-- it with `PRAGMA data_version`
+CREATE TABLE "data_changes" (
+    "pid" INTEGER NOT NULL PRIMARY KEY,
+    "changes" BIGINT NOT NULL
+);
+
+CREATE FUNCTION "count_data_change"() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO "data_changes"("pid", "changes") VALUES (pg_backend_pid(), 1)
+        ON CONFLICT ("pid") DO UPDATE SET "changes" = "data_changes"."changes" + 1;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Every table counts the changes made to it, the tables of later migrations included
+
+CREATE TRIGGER "file_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "file"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "song_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "song"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "artist_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "artist"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "album_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "album"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "genre_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "genre"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "songs_artists_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "songs_artists"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "songs_albums_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "songs_albums"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "songs_genres_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "songs_genres"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "song" ADD COLUMN "play_count" INTEGER NOT NULL DEFAULT 0;
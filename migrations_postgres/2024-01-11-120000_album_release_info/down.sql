@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "album" DROP COLUMN "year";
+ALTER TABLE "album" DROP COLUMN "label";
+ALTER TABLE "album" DROP COLUMN "catalog_number";
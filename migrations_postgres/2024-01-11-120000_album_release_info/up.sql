@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE "album" ADD COLUMN "year" INTEGER;
+ALTER TABLE "album" ADD COLUMN "label" TEXT;
+ALTER TABLE "album" ADD COLUMN "catalog_number" TEXT;
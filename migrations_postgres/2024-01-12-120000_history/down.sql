@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "download";
+DROP TABLE "play";
+ALTER TABLE "song" DROP COLUMN "added_at";
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+ALTER TABLE "song" ADD COLUMN "added_at" TIMESTAMP;
+
+CREATE TABLE "play" (
+    "id" SERIAL PRIMARY KEY,
+    "song_id" INTEGER NOT NULL,
+    "played_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TABLE "download" (
+    "id" SERIAL PRIMARY KEY,
+    "youtube_id" TEXT NOT NULL,
+    "title" TEXT NOT NULL,
+    "error" TEXT,
+    "finished_at" TIMESTAMP NOT NULL
+);
+
+CREATE TRIGGER "play_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "play"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "download_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "download"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "song_extra" (
+    "song_id" INTEGER NOT NULL PRIMARY KEY,
+    "description" TEXT,
+    "comments" TEXT,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TRIGGER "song_extra_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "song_extra"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "pinned" (
+    "id" SERIAL PRIMARY KEY,
+    "kind" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+  UNIQUE("kind", "name")
+);
+
+CREATE TRIGGER "pinned_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "pinned"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "attachment" (
+    "id" SERIAL PRIMARY KEY,
+    "song_id" INTEGER,
+    "album_id" INTEGER,
+    "kind" TEXT NOT NULL,
+    "relative_path" TEXT NOT NULL UNIQUE,
+    "added_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("song_id") REFERENCES song("id"),
+  FOREIGN KEY("album_id") REFERENCES album("id"),
+  CHECK(("song_id" IS NULL) <> ("album_id" IS NULL))
+);
+
+CREATE TRIGGER "attachment_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "attachment"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
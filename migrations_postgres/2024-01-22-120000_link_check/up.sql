@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "link_check" (
+    "song_id" INTEGER NOT NULL PRIMARY KEY,
+    "checked_at" TIMESTAMP NOT NULL,
+    "dead_reason" TEXT,
+    "suggestion_id" TEXT,
+    "suggestion_title" TEXT,
+    "suggestion_score" INTEGER,
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TRIGGER "link_check_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "link_check"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
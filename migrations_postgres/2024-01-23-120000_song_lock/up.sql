@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "song_lock" (
+    "song_id" INTEGER NOT NULL,
+    "field" TEXT NOT NULL,
+  PRIMARY KEY("song_id", "field"),
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TRIGGER "song_lock_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "song_lock"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
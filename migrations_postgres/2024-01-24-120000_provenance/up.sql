@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "provenance" (
+    "song_id" INTEGER NOT NULL,
+    "field" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "value" TEXT,
+    "previous_source" TEXT,
+    "previous_value" TEXT,
+    "recorded_at" TIMESTAMP NOT NULL,
+  PRIMARY KEY("song_id", "field"),
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE TRIGGER "provenance_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "provenance"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE "download_params" (
+    "file_id" INTEGER PRIMARY KEY NOT NULL,
+    "youtube_id" TEXT NOT NULL,
+    "format" TEXT NOT NULL,
+    "formats" TEXT NOT NULL,
+    "comments" BOOLEAN NOT NULL,
+    "proxy" BOOLEAN NOT NULL,
+    "trim_silence" BOOLEAN NOT NULL,
+    "yt_dlp_version" TEXT,
+    "downloaded_at" TIMESTAMP NOT NULL,
+  FOREIGN KEY("file_id") REFERENCES file("id")
+);
+
+CREATE TRIGGER "download_params_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "download_params"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- The text of every song searched by the Manager. SQLite keeps it in a full-text index, Postgres
+-- searches this view with `ILIKE`
+CREATE VIEW "song_search" AS
+SELECT
+    "song"."id",
+    "song"."title",
+    (SELECT string_agg("artist"."name", ' ') FROM "songs_artists" JOIN "artist" ON "artist"."id" = "songs_artists"."artist_id"
+        WHERE "songs_artists"."song_id" = "song"."id") AS "artists",
+    (SELECT string_agg("album"."name", ' ') FROM "songs_albums" JOIN "album" ON "album"."id" = "songs_albums"."album_id"
+        WHERE "songs_albums"."song_id" = "song"."id") AS "album",
+    (SELECT string_agg("genre"."name", ' ') FROM "songs_genres" JOIN "genre" ON "genre"."id" = "songs_genres"."genre_id"
+        WHERE "songs_genres"."song_id" = "song"."id") AS "genres"
+FROM "song";
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE "playlist" (
+    "id" SERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE,
+    "created_at" TIMESTAMP NOT NULL
+);
+
+-- A song can be in a playlist more than once, at different positions
+CREATE TABLE "playlist_songs" (
+    "id" SERIAL PRIMARY KEY,
+    "playlist_id" INTEGER NOT NULL,
+    "song_id" INTEGER NOT NULL,
+    "position" INTEGER NOT NULL,
+  FOREIGN KEY("playlist_id") REFERENCES playlist("id"),
+  FOREIGN KEY("song_id") REFERENCES song("id")
+);
+
+CREATE INDEX "playlist_songs_position" ON "playlist_songs"("playlist_id", "position");
+
+CREATE TRIGGER "playlist_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "playlist"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
+
+CREATE TRIGGER "playlist_songs_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "playlist_songs"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
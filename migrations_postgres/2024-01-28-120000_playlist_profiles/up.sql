@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- The export profiles of the settings a playlist is exported with, by name
+CREATE TABLE "playlist_profiles" (
+    "playlist_id" INTEGER NOT NULL,
+    "profile" TEXT NOT NULL,
+  PRIMARY KEY("playlist_id", "profile"),
+  FOREIGN KEY("playlist_id") REFERENCES playlist("id")
+);
+
+CREATE TRIGGER "playlist_profiles_data_change" AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON "playlist_profiles"
+    FOR EACH STATEMENT EXECUTE FUNCTION "count_data_change"();
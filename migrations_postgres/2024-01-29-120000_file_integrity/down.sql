@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "file" DROP COLUMN "problem";
+ALTER TABLE "file" DROP COLUMN "verified_at";
+ALTER TABLE "file" DROP COLUMN "checksum";
@@ -1,5 +1,7 @@
-use std::{fmt, string::ToString};
+use std::{collections::HashMap, fmt, path::PathBuf, string::ToString, time::Duration};
 
+#[cfg(feature = "tui")]
+use crossterm::event::KeyEvent;
 use serde::{
   de::{self, Deserializer, Visitor},
   Deserialize, Serialize,
@@ -7,7 +9,37 @@ use serde::{
 use strum::Display;
 use youtube_dl::SingleVideo;
 
-use crate::{components::download::YoutubeVideo, layouts::Focus, mode::Mode};
+#[cfg(feature = "player")]
+use crate::player::NowPlaying;
+use crate::{
+  availability::Availability,
+  backup::Backup,
+  config::DuplicatePolicy,
+  dashboard::Dashboard,
+  diagnostics::{Diagnostic, DiagnosticFix},
+  download_logs::DownloadLogList,
+  enrichment::{Provider, ReleaseMetadata, ReleaseQuery},
+  gaps::AlbumGap,
+  health::Health,
+  import::ImportMatch,
+  jobs::{JobId, JobInfo},
+  layouts::{Focus, ManagerTabs, TabFilter},
+  links::DeadLink,
+  merge::MergeCandidate,
+  mode::Mode,
+  models::{Attachment, Pin, Playlist, Snapshot, Song},
+  mpd::MpdStatus,
+  queue::{DownloadRequest, QueueItem, Verdict},
+  scan::ScanResult,
+  snapshots::SnapshotDiff,
+  song_edit::SongEdit,
+  song_status::{DisplayMode, SongListRow, SongPage},
+  trim::{TrimRange, TrimSong},
+  upgrade::UpgradeOffer,
+  verify::IntegrityProblem,
+};
+#[cfg(feature = "tui")]
+use crate::{components::download::YoutubeVideo, keymap::Binding};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Display, Deserialize)]
 pub enum Action {
@@ -23,7 +55,16 @@ pub enum Action {
   Quit,
   Refresh,
   Error(String),
+  /// Show a message to the user
+  Notify(String),
+  /// Show the key bindings of the current mode, to bind their actions to other keys
   Help,
+  /// The key bindings of the mode the help is shown in, followed by the global ones
+  #[cfg(feature = "tui")]
+  HelpUpdate(#[serde(skip)] Vec<Binding>),
+  /// Bind the action of the mode to other keys, in place of the keys it was bound to
+  #[cfg(feature = "tui")]
+  Rebind(Mode, Box<Action>, #[serde(skip)] Vec<KeyEvent>),
   /// Switch to the given scene
   FocusSwitch(#[serde(skip)] Focus),
   FocusBack,
@@ -45,14 +86,302 @@ pub enum Action {
   /// # Arguments
   ///
   /// * String (Optional): A string will indicate successful input, otherwise a cancellation or
-  /// other errors
+  ///   other errors
   /// * String: the buffer contents upon exit from Input Mode
   // InputModeOff(#[serde(skip)] (Option<String>, String)),
   InputModeOff(#[serde(skip)] InputOut),
 
   DownloadSearchYoutube,
+  #[cfg(feature = "tui")]
   DownloadShowSearchDetails(#[serde(skip)] Option<YoutubeVideo>),
   DownloadSearchToDetails,
+  /// Search youtube for the given query
+  DownloadSearch(String),
+  /// Add the given songs to the download queue
+  DownloadEnqueue(#[serde(skip)] Vec<DownloadRequest>),
+  /// Check that the given songs can be downloaded and queue the ones that can
+  DownloadCheck(#[serde(skip)] Vec<DownloadRequest>),
+  /// The items of the download queue, with the progress of the download in flight. Sent by the
+  /// run loop while the Download scene is shown
+  DownloadProgress(#[serde(skip)] Vec<QueueItem>),
+  /// Review the metadata of the marked search results before checking and queueing them
+  DownloadConfirm(#[serde(skip)] Vec<DownloadRequest>),
+  /// Whether the video with the given id can be downloaded. Sent by the availability check
+  DownloadAvailability(String, #[serde(skip)] Availability),
+
+  /// Prompt for a playlist to import
+  ImportPlaylist,
+  /// Import the playlist at the given source, a Spotify playlist or the path to a CSV/TSV file
+  ImportStart(String),
+  /// The tracks of an imported playlist have been matched and are ready for review
+  ImportReview(#[serde(skip)] Vec<ImportMatch>),
+  /// Search for the tracks of the album of the song with the given id missing from the library
+  AlbumComplete(i32),
+  /// Search for the instrumental of the song with the given id and queue it for download
+  InstrumentalFind(i32),
+
+  /// Switch to the next Manager tab
+  ManagerTabNext,
+  /// Switch to the previous Manager tab
+  ManagerTabPrevious,
+  /// Prompt for a filter and open a new Manager tab with it
+  ManagerTabNew,
+  /// Close the active Manager tab
+  ManagerTabClose,
+  /// Open a new Manager tab with the given filter
+  ManagerTabOpen(#[serde(skip)] TabFilter),
+  /// Store the selection and scroll offset of the active Manager tab
+  ///
+  /// # Arguments
+  ///
+  /// * `Option<usize>`: the selected row
+  /// * usize: the scroll offset
+  ManagerTabSaveState(#[serde(skip)] (Option<usize>, usize)),
+  /// The Manager tabs have changed. Sent by the run loop with the current state of the tabs
+  ManagerTabsUpdate(#[serde(skip)] ManagerTabs),
+  /// The songs of the active Manager tab along with their status. Sent by the run loop
+  ManagerSongs(#[serde(skip)] Vec<SongListRow>),
+  /// A page of the songs of the active Manager tab, when they are loaded a page at a time. Sent by
+  /// the run loop
+  ManagerSongsPage(#[serde(skip)] SongPage),
+  /// Load a page of the songs of the active Manager tab. Sent by the song list as it is scrolled
+  ///
+  /// # Arguments
+  ///
+  /// * u64: the generation of the pages loaded so far, the request is dropped when it is outdated
+  /// * usize: the index in the tab of the first song of the page
+  ManagerSongsLoadPage(#[serde(skip)] (u64, usize)),
+  /// List the songs of the Manager as the display mode picks them
+  ManagerDisplayMode(DisplayMode),
+  /// Only list the songs of the active Manager tab matching the text, or every song of it
+  ManagerSearch(Option<String>),
+  /// The summary shown on the Home screen has changed. Sent by the run loop
+  DashboardUpdate(#[serde(skip)] Dashboard),
+
+  /// Pin the artist, album or playlist of the active Manager tab to the Home screen
+  ManagerTabPin,
+  /// Unpin the artist, album or playlist of the active Manager tab
+  ManagerTabUnpin,
+  /// The pinned artists, albums and playlists have changed. Sent by the run loop with all pins
+  PinsUpdate(#[serde(skip)] Vec<Pin>),
+  /// Look for duplicate songs or artists and open the first pair found in the compare view
+  ManagerFindDuplicates,
+  /// Show the given entities side by side in the compare view
+  ManagerCompare(#[serde(skip)] MergeCandidate),
+  /// Merge the compared entities with the picked fields
+  ManagerApplyMerge(#[serde(skip)] MergeCandidate),
+  /// Look up the albums of every artist on MusicBrainz to find the albums missing from the library
+  ManagerFindAlbumGaps,
+  /// Show the albums missing from the library
+  ManagerAlbumGaps(#[serde(skip)] Vec<AlbumGap>),
+  /// Show the files attached to the album of the active Manager tab
+  ManagerAttachmentsShow,
+  /// The files attached to the album of the active Manager tab
+  ManagerAttachments(#[serde(skip)] Vec<Attachment>),
+  /// Copy the given file into the music dir and attach it to the album of the active Manager tab
+  ManagerAttach(#[serde(skip)] PathBuf),
+  /// Remove the attachment with the given id along with its file
+  ManagerDetach(#[serde(skip)] i32),
+  /// Open the trim view for the song with the given id
+  TrimShow(i32),
+  /// The song to trim was loaded
+  TrimLoaded(#[serde(skip)] TrimSong),
+  /// Play the song with the given id from the position, to preview a trim
+  TrimPreview(i32, #[serde(skip)] Duration),
+  /// Render the part of the song into a new trimmed version of it
+  TrimRender(#[serde(skip)] TrimRange),
+  /// Open the metadata editor for the song with the given id
+  SongEditorShow(i32),
+  /// The song to edit was loaded
+  SongEditorLoaded(#[serde(skip)] SongEdit),
+  /// Save the edited metadata of a song
+  SongEditorSave(#[serde(skip)] SongEdit),
+  /// Edit the metadata of the songs with the given ids as a file in the external editor
+  SongEditorExternal(Vec<i32>),
+  /// Look up the release of the song being edited from the provider
+  SongEditorLookup(Provider, #[serde(skip)] ReleaseQuery),
+  /// The release found for the song being edited, to fill in its fields
+  SongEditorRelease(#[serde(skip)] ReleaseMetadata),
+  /// Copy the songs with the given ids into the folder, named as set in the `export` settings
+  ExportSongs(Vec<i32>, PathBuf),
+  /// Copy every song of the active Manager tab into the folder, for when they are not all loaded
+  ExportTab(PathBuf),
+  /// Write the metadata of the songs with the given ids into the tags of their files
+  WriteTags(Vec<i32>),
+  /// Check that yt-dlp, ffmpeg, the music dir and the database are ready, as done on launch
+  DiagnosticsRun,
+  /// The failed checks of the setup. Sent by the run loop
+  Diagnostics(#[serde(skip)] Vec<Diagnostic>),
+  /// Fix a failed check of the setup
+  DiagnosticsFix(DiagnosticFix),
+  /// Check the library for problems and show what to do about them
+  HealthShow,
+  /// The health of the library has been checked
+  HealthReport(#[serde(skip)] Health),
+  /// List the queued and finished downloads to read the output of yt-dlp for them
+  DownloadLogsShow,
+  /// Show the output of yt-dlp for the video with the given id
+  DownloadLogShow(String),
+  /// The downloads whose logs can be read. Sent by the run loop
+  DownloadLogs(#[serde(skip)] DownloadLogList),
+  /// List the downloads of songs in the library already that wait to be told what to do
+  DuplicatesShow,
+  /// The downloads waiting for a decision. Sent by the run loop
+  Duplicates(#[serde(skip)] Vec<QueueItem>),
+  /// Handle the download with the given queue id with the policy
+  DuplicateResolve(u64, DuplicatePolicy),
+  /// List the downloads whose metadata was guessed, held to be approved, corrected or rejected
+  ReviewShow,
+  /// The downloads held for review. Sent by the run loop
+  Reviews(#[serde(skip)] Vec<QueueItem>),
+  /// Handle the download held for review with the given queue id as the verdict says
+  ReviewVerdict(u64, Verdict),
+  /// Check whether the videos of the songs are still up, least recently checked first
+  LinksCheck,
+  /// List the songs whose video is gone, along with the videos found to replace them
+  DeadLinksShow,
+  /// The songs whose video is gone. Sent by the run loop
+  DeadLinks(#[serde(skip)] Vec<DeadLink>),
+  /// Link the song with the given id to the video with the given id
+  DeadLinkRelink(i32, String),
+  /// Verify the files of the songs: that they are there, readable and unchanged
+  VerifyLibrary,
+  /// List the songs whose file is gone or was found damaged
+  IntegrityShow,
+  /// Download every song whose file is gone again from its video
+  RedownloadMissing,
+  /// The songs whose file is gone or damaged. Sent by the run loop
+  Integrity(#[serde(skip)] Vec<IntegrityProblem>),
+  /// Search for a better upload of the low quality song with the given id
+  UpgradeFind(i32),
+  /// The better uploads found for a low quality song, to pick one from
+  UpgradeOffer(#[serde(skip)] UpgradeOffer),
+  /// Replace the file of the song with the given id with the upload with the given id
+  UpgradeReplace(i32, String),
+  /// Scan the music dir for songs missing from the library
+  ScanMusicDir,
+  /// Scan the given files again, as paths from the music dir
+  ScanRetry(#[serde(skip)] Vec<PathBuf>),
+  /// A scan of the music dir has finished, with what became of every file
+  ScanResults(#[serde(skip)] Vec<ScanResult>),
+  /// Files have been scanned again, with their new results
+  ScanRetried(#[serde(skip)] Vec<ScanResult>),
+
+  /// Switch to the list of background jobs
+  JobsShow,
+  /// The background jobs have changed. Sent by the run loop with a snapshot of all jobs
+  JobsUpdate(#[serde(skip)] Vec<JobInfo>),
+  /// Show the logs of the given job
+  JobsShowLogs(#[serde(skip)] Option<JobId>),
+  /// Cancel a running job
+  JobsCancel(#[serde(skip)] JobId),
+  /// Run a failed or cancelled job again
+  JobsRetry(#[serde(skip)] JobId),
+
+  /// Import the ListenBrainz listen history to seed play counts
+  ListenBrainzImport,
+
+  /// Fetch the release metadata of albums missing it from the enrichment providers
+  EnrichAlbums,
+  /// Look up the release type of albums missing it on MusicBrainz
+  ClassifyAlbums,
+
+  /// Regenerate the playlists for external players
+  PlaylistsExport,
+  /// Write the playlists for external players into the given directory
+  PlaylistsExportTo(#[serde(skip)] PathBuf),
+  /// Fetch the cover art of every song again, at the resolution of the `download.art` settings
+  RefetchArt,
+  /// Fetch the missing pictures of every artist
+  ArtistImagesFetch,
+  /// Fetch the picture of the artist with the given name if it is missing
+  ArtistImageFetch(String),
+  /// Pictures of artists were fetched. Sent by the fetching job
+  ArtistImagesFetched,
+
+  /// Another connection, such as the daemon or a background job, changed the library since it was
+  /// last checked. Data loaded from the library before may be outdated
+  LibraryChanged,
+
+  /// Show the backups of the database to pick one to restore
+  BackupsShow,
+  /// The backups of the database, newest first
+  BackupsUpdate(#[serde(skip)] Vec<Backup>),
+  /// Back the database up now
+  BackupCreate,
+  /// Replace the database with the given backup
+  BackupRestore(#[serde(skip)] PathBuf),
+
+  /// Show the snapshots of the library, to compare them
+  SnapshotsShow,
+  /// The snapshots of the library, the latest first
+  SnapshotsUpdate(#[serde(skip)] Vec<Snapshot>),
+  /// Take a snapshot of the library with the given name, or one made of the time when empty
+  SnapshotTake(String),
+  /// Compare the snapshot of the first id with the one of the second, or with the library now
+  SnapshotCompare(i32, Option<i32>),
+  /// The songs added, removed and changed between two snapshots
+  SnapshotDiffUpdate(#[serde(skip)] SnapshotDiff),
+  /// Delete the snapshot of the given id
+  SnapshotDelete(i32),
+
+  /// Show the playlists stored in the library, to arrange them
+  PlaylistsShow,
+  /// The playlists stored in the library with their songs in order, ordered by name
+  PlaylistsUpdate(#[serde(skip)] Vec<(Playlist, Vec<Song>)>),
+  /// Create an empty playlist with the given name
+  PlaylistCreate(String),
+  /// Rename the playlist with the given id
+  PlaylistRename(i32, String),
+  /// Delete the playlist with the given id, leaving its songs in the library
+  PlaylistDelete(i32),
+  /// Add the songs with the given ids to the end of the named playlist, creating it if needed
+  PlaylistAddSongs(String, Vec<i32>),
+  /// Remove the song at the given index of a playlist
+  PlaylistRemoveSong(i32, usize),
+  /// Move the song of a playlist from one index to another
+  PlaylistMoveSong(i32, usize, usize),
+  /// The names of the export profiles of the playlists, by playlist id
+  PlaylistsProfiles(#[serde(skip)] HashMap<i32, Vec<String>>),
+  /// Export the playlist with the given id with the named profiles from now on
+  PlaylistSetProfiles(i32, Vec<String>),
+  /// Export the playlist with the given id with every profile picked for it
+  PlaylistExportProfiles(i32),
+
+  /// Switch to the Now Playing scene, showing the lyrics of the playing song
+  NowPlayingShow,
+  /// Play the song queued for download last, streaming it if it is not downloaded yet
+  PlayerPlayQueued,
+  /// Play the downloaded song with the given id from the start
+  PlayerPlaySong(i32),
+  /// Stream the audio of a search result, along with its duration if known
+  PlayerPreview(#[serde(skip)] DownloadRequest, #[serde(skip)] Option<Duration>),
+  /// Pause the playing song, or resume it when paused
+  PlayerPause,
+  /// The playing song was paused or resumed. Sent by the run loop
+  PlayerPaused(bool),
+  /// A song started playing, or playback stopped. Sent by the run loop
+  #[cfg(feature = "player")]
+  PlayerNowPlaying(#[serde(skip)] Option<NowPlaying>),
+  /// The position in the playing song. Sent by the run loop
+  PlayerPosition(#[serde(skip)] Option<Duration>),
+  /// Show the lyrics a bit earlier
+  LyricsOffsetIncrease,
+  /// Show the lyrics a bit later
+  LyricsOffsetDecrease,
+  /// Stop the playing song
+  PlayerStop,
+  /// Skip forward in the playing song
+  PlayerSeekForward,
+  /// Skip back in the playing song
+  PlayerSeekBackward,
+
+  /// Play the song with the given id with MPD
+  MpdPlaySong(i32),
+  /// Load the stored playlists into MPD
+  MpdLoadPlaylists,
+  /// What MPD is playing, or `None` when it can not be reached. Sent by the run loop
+  MpdNowPlaying(#[serde(skip)] Option<MpdStatus>),
 }
 
 #[derive(Clone, Debug, Eq, Default, PartialEq)]
@@ -0,0 +1,123 @@
+//! Album completion
+//!
+//! A song whose album is known can have the rest of the album downloaded along with it. The track
+//! list of the album is fetched from MusicBrainz, and the tracks without a song in the library are
+//! searched for on YouTube. The matches are reviewed like an imported playlist before being
+//! queued, with the album, disc and track number of every track filled in.
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+  action::Action,
+  config::Config,
+  database::Database,
+  enrichment::{
+    musicbrainz::{AlbumTrack, MusicBrainzClient},
+    ReleaseQuery,
+  },
+  import::{self, matching::normalize, ImportTrack},
+  jobs::JobContext,
+  models::{MetadataSource, Song},
+};
+
+/// The tracks of the album that have no song in the library, as tracks to search for
+///
+/// # Arguments
+///
+/// * `album` - the name of the album in the library, kept so that the new songs join it
+/// * `artist` - the artist of the album, for tracks that credit no artist
+/// * `tracks` - the track list of the album
+/// * `owned` - the songs of the album in the library
+pub fn missing_tracks(album: &str, artist: Option<&str>, tracks: Vec<AlbumTrack>, owned: &[Song]) -> Vec<ImportTrack> {
+  let owned: Vec<_> = owned.iter().map(|song| normalize(&song.title)).collect();
+  tracks
+    .into_iter()
+    .filter(|track| !owned.contains(&normalize(&track.title)))
+    .map(|track| {
+      let artists = match track.artists.is_empty() {
+        true => artist.map(str::to_string).into_iter().collect(),
+        false => track.artists,
+      };
+      ImportTrack {
+        title: track.title,
+        artists,
+        album: Some(album.to_string()),
+        duration: track.duration,
+        disc_number: Some(track.disc_number),
+        track_number: Some(track.track_number),
+        source: MetadataSource::MusicBrainz,
+      }
+    })
+    .collect()
+}
+
+/// Find the rest of the album of the song and send the matches for review, to be run as a job
+pub async fn complete_album(
+  config: Config,
+  song_id: i32,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let mut database = Database::new(config).await?;
+  let song = database.get_song_from_id(song_id)?;
+  let album = database
+    .get_song_albums()?
+    .into_iter()
+    .find(|(id, _)| *id == song_id)
+    .map(|(_, album)| album)
+    .ok_or_else(|| eyre!("the album of {} is not known, enrich it first", song.title))?;
+  let artist = database.get_all_artists_for_album(album.id)?.into_iter().next().map(|artist| artist.name);
+
+  context.log(format!("looking up the tracks of {} on MusicBrainz", album.name));
+  let query = ReleaseQuery { title: album.name.clone(), artist: artist.clone() };
+  let tracks = MusicBrainzClient::new()
+    .album_tracks(&query)
+    .await?
+    .ok_or_else(|| eyre!("{} was not found on MusicBrainz", album.name))?;
+  let owned = database.get_all_songs_for_album(album.id)?;
+  let missing = missing_tracks(&album.name, artist.as_deref(), tracks, &owned);
+  if missing.is_empty() {
+    return Err(eyre!("{} is complete already", album.name));
+  }
+  let matches = import::match_tracks(missing, &context).await;
+  action_tx.send(Action::ImportReview(matches))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_missing_tracks() {
+    let track = |title: &str, artists: &[&str], track_number| {
+      AlbumTrack {
+        title: title.to_string(),
+        artists: artists.iter().map(|artist| artist.to_string()).collect(),
+        disc_number: 1,
+        track_number,
+        duration: Some(240),
+      }
+    };
+    let tracks = vec![
+      track("Stellar Stellar", &["Hoshimachi Suisei"], 1),
+      track("Bluerose", &[], 2),
+      track("comet", &["Hoshimachi Suisei"], 3),
+    ];
+    let owned = vec![Song { title: "Stellar stellar".to_string(), ..Default::default() }];
+    let missing = missing_tracks("Still Still Stellar", Some("Hoshimachi Suisei"), tracks, &owned);
+    assert_eq!(missing.iter().map(|track| track.title.as_str()).collect::<Vec<_>>(), vec!["Bluerose", "comet"]);
+    assert_eq!(missing[0], ImportTrack {
+      title: "Bluerose".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      duration: Some(240),
+      disc_number: Some(1),
+      track_number: Some(2),
+      source: MetadataSource::MusicBrainz,
+    });
+  }
+}
@@ -1,27 +1,68 @@
-use std::sync::Arc;
+use std::{
+  collections::HashMap,
+  path::PathBuf,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use color_eyre::eyre::{ContextCompat, Result};
+use color_eyre::eyre::{eyre, ContextCompat, Result};
 use crossterm::event::KeyEvent;
 use ratatui::prelude::Rect;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
-use tracing::error;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
 
+#[cfg(feature = "musicbrainz")]
+use crate::album_completion;
 use crate::{
   action::Action,
+  art, artist_images,
+  attachments::{self, AttachmentOwner},
+  availability, backup, cache,
   components::{
     download,
     fps::FpsCounter,
-    general::{InputArea, TitleBar},
-    home::Intro,
-    manager, Component,
+    general::{Help, InputArea, TitleBar},
+    home::{Dashboard, Diagnostics},
+    jobs, manager, Component,
+  },
+  config::{Config, DuplicatePolicy, SuspendMode},
+  dashboard,
+  database::{Database, DatabaseHandle},
+  diagnostics, download_logs, enrichment, export, gaps, health, import, instrumental,
+  ipc::{self, IpcClient, IpcRequest, IpcResponse, Remote},
+  jobs::{JobKind, JobPriority, JobRegistry},
+  keymap,
+  layouts::{
+    DownloadLayouts, Focus, HomeLayouts, JobsLayouts, LayoutManager, ManagerLayouts, ManagerTab, NowPlayingLayouts,
+    Scenes, TabFilter,
   },
-  config::Config,
-  database::Database,
-  layouts::{Focus, HomeLayouts, LayoutManager, Scenes},
+  links, listenbrainz, lyrics,
+  merge::MergeCandidate,
   mode::Mode,
-  tui,
+  models::{Attachment, MetadataSource},
+  mpd::{self, MpdClient, MpdState, MpdStatus},
+  playlist_export, playlist_sync, playlists,
+  plays::{PlayCounter, PlayedSong},
+  queue::{self, DownloadQueue, QueueItem, QueueStatus, Verdict},
+  scan, snapshots,
+  song_edit::{self, SongEdit},
+  song_status::{self, DisplayMode, SongPage},
+  tagging, trim, tui, upgrade, verify,
 };
+#[cfg(feature = "player")]
+use crate::{
+  components::now_playing,
+  player::{NowPlaying, Player, Seek},
+};
+
+/// How far the player skips forward or back, in seconds
+const SEEK_STEP: i64 = 10;
+/// How often the dashboard is refreshed while it is shown, to follow the downloads
+const DASHBOARD_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the download queue is refreshed while the Download scene is shown
+const QUEUE_INTERVAL: Duration = Duration::from_millis(500);
 
 pub struct App {
   /// App config
@@ -34,47 +75,159 @@ pub struct App {
   pub components: Vec<Box<dyn Component>>,
   pub should_quit: bool,
   pub should_suspend: bool,
+  /// the songs to edit in the external editor, with the file they are written into
+  pub external_edit: Option<(PathBuf, Vec<SongEdit>)>,
   /// layout manager
   pub layout_manager: LayoutManager,
   pub last_tick_key_events: Vec<KeyEvent>,
   pub focus_buffer: Vec<Focus>,
 
-  pub database: Database,
+  pub database: DatabaseHandle,
+  /// the data version of the database when last checked, see [`Database::data_version`]
+  pub library_version: i64,
+  /// the data version of the database when the compared entities were loaded
+  pub compare_version: Option<i64>,
+  /// which songs the Manager lists
+  pub display_mode: DisplayMode,
+  /// the text the songs of the active Manager tab are searched for
+  pub manager_search: Option<String>,
+  /// counts the loads of the songs of the active Manager tab, to drop the requests for pages of an
+  /// older one
+  manager_generation: u64,
+  /// background jobs
+  pub jobs: JobRegistry,
+  /// the version of the job registry last sent to the components
+  pub jobs_version: u64,
+  /// songs waiting to be downloaded, when no daemon is running
+  pub download_queue: DownloadQueue,
+  /// the running daemon, which downloads the songs and runs the scheduled jobs instead
+  pub daemon: Option<IpcClient>,
+  /// when the playlists were last exported on schedule
+  pub last_playlists_export: Instant,
+  /// when it was last checked whether a scheduled backup is due
+  pub last_backup_check: Instant,
+  /// when it was last checked whether the videos of the songs are due for a check
+  pub last_link_check: Instant,
+  /// when the cache was last cleaned
+  pub last_cache_clean: Instant,
+  /// when the playlists were last synced with their directory
+  pub last_playlists_sync: Instant,
+  /// when MPD was last asked what it plays
+  pub last_mpd_poll: Instant,
+  /// the file MPD was told to play last, until it plays another
+  pub mpd_file: Option<String>,
+  /// counts the plays of the songs played with MPD
+  pub mpd_plays: PlayCounter,
+  /// when the dashboard was last refreshed
+  pub last_dashboard_refresh: Instant,
+  pub last_queue_refresh: Instant,
+  #[cfg(feature = "player")]
+  pub player: Player,
+  /// counts the plays of the songs played with the built-in player
+  #[cfg(feature = "player")]
+  pub player_plays: PlayCounter,
 }
 
 impl App {
   /// create new instance of app
-  pub async fn new(tick_rate: f64, frame_rate: f64) -> Result<Self> {
-    let home = Intro::new();
+  ///
+  /// # Arguments
+  ///
+  /// * `remote` - a daemon on another machine to download on, instead of the local daemon or the
+  ///   app itself
+  pub async fn new(tick_rate: f64, frame_rate: f64, remote: Option<Remote>) -> Result<Self> {
+    let home = Dashboard::new();
     let fps = FpsCounter::default();
     let config = Config::new()?;
     let mode = Mode::Home;
-    let first_focus = Focus { mode, scene: Scenes::Home(HomeLayouts::Intro) };
+    let first_focus = Focus { mode, scene: Scenes::Home(HomeLayouts::Dashboard) };
     let layout_manager = LayoutManager::new();
     // TODO: optimize this with a macro or something
-    let components: Vec<Box<(dyn Component + 'static)>> = vec![
+    #[cfg_attr(not(feature = "player"), allow(unused_mut))]
+    let mut components: Vec<Box<dyn Component + 'static>> = vec![
       Box::new(home),
+      // drawn over the dashboard
+      Box::new(Diagnostics::new()),
       Box::new(fps),
       Box::new(TitleBar::new()),
       Box::new(InputArea::new()),
       Box::new(download::SearchBar::new()),
       Box::new(download::SearchResult::new()),
       Box::new(download::SearchResultDetails::new()),
+      Box::new(download::QueueList::new()),
+      // drawn over the components above
+      Box::new(download::ImportReview::new()),
+      Box::new(download::QueueConfirm::new()),
+      Box::new(manager::TabBar::new()),
       Box::new(manager::SongList::new()),
+      Box::new(manager::ArtistDetails::new()),
+      Box::new(manager::Compare::new()),
+      Box::new(manager::AlbumGaps::new()),
+      Box::new(manager::Backups::new()),
+      Box::new(manager::Snapshots::new()),
+      Box::new(manager::Playlists::new()),
+      Box::new(manager::ScanResults::new()),
+      Box::new(manager::Attachments::new()),
+      Box::new(manager::Trim::new()),
+      Box::new(manager::HealthReport::new()),
+      Box::new(manager::DownloadLogs::new()),
+      Box::new(manager::DeadLinks::new()),
+      Box::new(manager::Integrity::new()),
+      Box::new(manager::UpgradePicker::new()),
+      Box::new(manager::Duplicates::new()),
+      Box::new(manager::Review::new()),
+      Box::new(manager::SongEditor::new()),
+      Box::new(jobs::JobList::new()),
+      Box::new(jobs::JobLogs::new()),
     ];
+    #[cfg(feature = "player")]
+    components.push(Box::new(now_playing::NowPlaying::new()));
+    #[cfg(feature = "player")]
+    components.push(Box::new(now_playing::TransportBar::new()));
+    // drawn over every screen
+    components.push(Box::new(Help::new()));
 
-    let database = Database::new(config.clone()).await?;
+    let database = DatabaseHandle::new(config.clone()).await?;
+    let library_version = database.call(Database::data_version).await?;
+    let daemon = match remote {
+      Some(remote) => Some(IpcClient::connect_remote(&remote).await?),
+      None => IpcClient::connect(&ipc::socket_path(&config)).await?,
+    };
     Ok(Self {
       tick_rate,
       frame_rate,
       components,
       should_quit: false,
       should_suspend: false,
-      config,
+      external_edit: None,
       layout_manager,
       last_tick_key_events: Vec::new(),
       focus_buffer: vec![first_focus],
       database,
+      library_version,
+      compare_version: None,
+      display_mode: DisplayMode::default(),
+      manager_search: None,
+      manager_generation: 0,
+      jobs: JobRegistry::with_config(&config.config.jobs),
+      jobs_version: 0,
+      download_queue: DownloadQueue::new(),
+      daemon,
+      last_playlists_export: Instant::now(),
+      last_backup_check: Instant::now(),
+      last_link_check: Instant::now(),
+      last_cache_clean: Instant::now(),
+      last_playlists_sync: Instant::now(),
+      last_mpd_poll: Instant::now(),
+      mpd_file: None,
+      mpd_plays: PlayCounter::new(&config.config.listenbrainz),
+      last_dashboard_refresh: Instant::now(),
+      last_queue_refresh: Instant::now(),
+      #[cfg(feature = "player")]
+      player: Player::new(config.paths.cache_dir.clone()),
+      #[cfg(feature = "player")]
+      player_plays: PlayCounter::new(&config.config.listenbrainz),
+      config,
     })
   }
 
@@ -99,42 +252,56 @@ impl App {
     }
 
     self.layout_manager.init(tui.size()?)?;
+    action_tx.send(Action::ManagerTabsUpdate(self.layout_manager.manager_tabs.clone()))?;
+    action_tx.send(Action::PinsUpdate(self.database.call(Database::get_pins).await?))?;
+    self.refresh_dashboard(&action_tx).await?;
+    self.check_setup(&action_tx);
+
+    let worker_cancellation = CancellationToken::new();
+    if self.daemon.is_none() {
+      tokio::spawn(self.download_queue.clone().run(self.config.clone(), worker_cancellation.clone()));
+    } else {
+      action_tx.send(Action::Notify("connected to the daemon".to_string()))?;
+    }
 
     // main loop
     loop {
       if let Some(e) = tui.next().await {
+        // background jobs leave the machine to the TUI while keys are pressed
+        if matches!(e, tui::Event::Key(_)) {
+          self.jobs.user_active();
+        }
         match e {
           tui::Event::Quit => action_tx.send(Action::Quit)?,
           tui::Event::Tick => action_tx.send(Action::Tick)?,
           tui::Event::Render => action_tx.send(Action::Render)?,
           tui::Event::Resize(x, y) => action_tx.send(Action::Resize(x, y))?,
-          tui::Event::Key(key) => {
-            if self.get_focused().scene != Scenes::InputBar {
-              // Check global keybinds first
-              if let Some(keymap) = self.config.keybindings.get(&Mode::Global) {
-                // check for global keybindings
-                if let Some(action) = keymap.get(&vec![key]) {
-                  log::info!("Got action: {action:?}");
-                  action_tx.send(action.clone())?;
-                }
+          // the keys typed into the input bar or pressed to rebind an action do not run actions
+          tui::Event::Key(key) if !matches!(self.get_focused().scene, Scenes::InputBar | Scenes::Help) => {
+            // Check global keybinds first
+            if let Some(keymap) = self.config.keybindings.get(&Mode::Global) {
+              // check for global keybindings
+              if let Some(action) = keymap.get(&vec![key]) {
+                log::info!("Got action: {action:?}");
+                action_tx.send(action.clone())?;
               }
-              if let Some(keymap) = self.config.keybindings.get(&self.get_focused().mode) {
-                if let Some(action) = keymap.get(&vec![key]) {
+            }
+            if let Some(keymap) = self.config.keybindings.get(&self.get_focused().mode) {
+              if let Some(action) = keymap.get(&vec![key]) {
+                log::info!("Got action: {action:?}");
+                action_tx.send(action.clone())?;
+              } else {
+                // If the key was not handled as a single key action,
+                // then consider it for multi-key combinations.
+                self.last_tick_key_events.push(key);
+
+                // Check for multi-key combinations
+                if let Some(action) = keymap.get(&self.last_tick_key_events) {
                   log::info!("Got action: {action:?}");
                   action_tx.send(action.clone())?;
-                } else {
-                  // If the key was not handled as a single key action,
-                  // then consider it for multi-key combinations.
-                  self.last_tick_key_events.push(key);
-
-                  // Check for multi-key combinations
-                  if let Some(action) = keymap.get(&self.last_tick_key_events) {
-                    log::info!("Got action: {action:?}");
-                    action_tx.send(action.clone())?;
-                  }
                 }
-              };
-            }
+              }
+            };
           },
           _ => {},
         }
@@ -170,6 +337,105 @@ impl App {
         match action {
           Action::Tick => {
             self.last_tick_key_events.drain(..);
+            for notification in self.jobs.take_notifications() {
+              action_tx.send(Action::Notify(notification))?;
+            }
+            match self.database.call(Database::data_version).await {
+              Ok(version) if version != self.library_version => {
+                self.library_version = version;
+                action_tx.send(Action::LibraryChanged)?;
+              },
+              Ok(_) => {},
+              Err(e) => action_tx.send(Action::Error(format!("failed to check for changes to the library: {e}")))?,
+            }
+            let jobs_version = self.jobs.version();
+            if jobs_version != self.jobs_version {
+              self.jobs_version = jobs_version;
+              action_tx.send(Action::JobsUpdate(self.jobs.snapshot()))?;
+            }
+            // the daemon exports the playlists on its own schedule
+            if let (Some(minutes), None) = (self.config.config.playlists.export_interval_minutes, &self.daemon) {
+              if self.last_playlists_export.elapsed() >= Duration::from_secs(minutes * 60) {
+                self.last_playlists_export = Instant::now();
+                action_tx.send(Action::PlaylistsExport)?;
+              }
+            }
+            // and backs the database up on its own schedule as well
+            if self.daemon.is_none() && self.last_backup_check.elapsed() >= backup::CHECK_INTERVAL {
+              self.last_backup_check = Instant::now();
+              match backup::is_due(&self.config) {
+                Ok(true) => {
+                  let config = self.config.clone();
+                  self.jobs.spawn(JobKind::Sync, "back up the database", move |context| {
+                    backup::run_backup(config.clone(), None, context)
+                  });
+                },
+                Ok(false) => {},
+                Err(e) => action_tx.send(Action::Error(format!("failed to check for a due backup: {e}")))?,
+              }
+            }
+            // and the videos of the songs too
+            if self.daemon.is_none() && self.last_link_check.elapsed() >= links::CHECK_INTERVAL {
+              self.last_link_check = Instant::now();
+              let config = self.config.clone();
+              match self.database.call(move |database| links::is_due(&config, database)).await {
+                Ok(true) => {
+                  links::spawn_check(&self.jobs, self.config.clone(), false);
+                },
+                Ok(false) => {},
+                Err(e) => action_tx.send(Action::Error(format!("failed to check for due videos: {e}")))?,
+              }
+            }
+            if self.daemon.is_none() && self.last_cache_clean.elapsed() >= cache::CLEAN_INTERVAL {
+              self.last_cache_clean = Instant::now();
+              let (cache_dir, settings) = (self.config.paths.cache_dir.clone(), self.config.config.cache.clone());
+              self.jobs.spawn(JobKind::Sync, "clean the cache", move |context| {
+                cache::run_clean(cache_dir.clone(), settings.clone(), context)
+              });
+            }
+            if self.daemon.is_none() && self.last_playlists_sync.elapsed() >= playlist_sync::SYNC_INTERVAL {
+              self.last_playlists_sync = Instant::now();
+              self.sync_playlists(&action_tx).await?;
+            }
+            if self.config.config.mpd.host.is_some() && self.last_mpd_poll.elapsed() >= mpd::POLL_INTERVAL {
+              self.last_mpd_poll = Instant::now();
+              let status = self.mpd_status().await;
+              self.count_mpd_play(status.as_ref(), &action_tx).await?;
+              action_tx.send(Action::MpdNowPlaying(status))?;
+            }
+            if self.get_focused().mode == Mode::Home && self.last_dashboard_refresh.elapsed() >= DASHBOARD_INTERVAL {
+              self.refresh_dashboard(&action_tx).await?;
+            }
+            if self.get_focused().mode == Mode::Download && self.last_queue_refresh.elapsed() >= QUEUE_INTERVAL {
+              self.last_queue_refresh = Instant::now();
+              match self.queue_items().await {
+                Ok(items) => action_tx.send(Action::DownloadProgress(items))?,
+                Err(e) => action_tx.send(Action::Error(format!("failed to load the download queue: {e}")))?,
+              }
+            }
+            #[cfg(feature = "player")]
+            self.tick_player(&action_tx).await?;
+          },
+          Action::LibraryChanged => {
+            self.refresh_dashboard(&action_tx).await?;
+            self.load_manager_songs(&action_tx).await?;
+          },
+          Action::ManagerDisplayMode(mode) => {
+            self.display_mode = mode;
+            self.load_manager_songs(&action_tx).await?;
+          },
+          Action::ManagerSongsLoadPage((generation, offset)) if generation == self.manager_generation => {
+            self.load_manager_page(&action_tx, offset).await?;
+          },
+          Action::ManagerSearch(ref text) => {
+            self.manager_search = text.clone();
+            self.load_manager_songs(&action_tx).await?;
+          },
+          Action::ManagerTabsUpdate(_) => {
+            self.manager_search = None;
+            // artist tabs have a pane of their own
+            self.layout_manager.update(tui.size()?)?;
+            self.load_manager_songs(&action_tx).await?;
           },
           Action::Quit => self.should_quit = true,
           Action::Suspend => self.should_suspend = true,
@@ -208,6 +474,24 @@ impl App {
               }
             })?;
           },
+          Action::Help if self.get_focused().scene != Scenes::Help => {
+            let mode = self.get_focused().mode;
+            action_tx.send(Action::HelpUpdate(keymap::bindings(&self.config.keybindings, mode)))?;
+            action_tx.send(Action::FocusSwitch(Focus { mode, scene: Scenes::Help }))?;
+          },
+          Action::Rebind(mode, ref action, ref keys) => {
+            let rebound = keymap::rebind(&mut self.config.keybindings, mode, action, keys).and_then(|_| {
+              let rebind = keymap::Rebind { mode, action: *action.clone(), keys: keymap::keys_to_string(keys) };
+              keymap::save_rebind(&self.config.paths.config_dir, rebind)
+            });
+            let keys = keymap::keys_to_string(keys);
+            match rebound {
+              Ok(()) => action_tx.send(Action::Notify(format!("bound {action} to {keys}")))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to bind {action} to {keys}: {e}")))?,
+            }
+            let mode = self.get_focused().mode;
+            action_tx.send(Action::HelpUpdate(keymap::bindings(&self.config.keybindings, mode)))?;
+          },
           Action::InputModeOn { .. } => {
             self.focus_buffer.push(Focus { mode: self.get_focused().mode, scene: Scenes::InputBar });
           },
@@ -220,6 +504,842 @@ impl App {
           Action::FocusBack => {
             self.focus_buffer.pop();
           },
+          Action::ManagerTabNext => {
+            self.layout_manager.manager_tabs.next();
+            action_tx.send(Action::ManagerTabsUpdate(self.layout_manager.manager_tabs.clone()))?;
+          },
+          Action::ManagerTabPrevious => {
+            self.layout_manager.manager_tabs.previous();
+            action_tx.send(Action::ManagerTabsUpdate(self.layout_manager.manager_tabs.clone()))?;
+          },
+          Action::ManagerTabClose => {
+            self.layout_manager.manager_tabs.close_active();
+            action_tx.send(Action::ManagerTabsUpdate(self.layout_manager.manager_tabs.clone()))?;
+          },
+          Action::ManagerTabOpen(ref filter) => {
+            self.layout_manager.manager_tabs.open(ManagerTab::new(filter.clone()));
+            action_tx.send(Action::ManagerTabsUpdate(self.layout_manager.manager_tabs.clone()))?;
+          },
+          Action::ManagerTabPin | Action::ManagerTabUnpin => {
+            let filter = self.layout_manager.manager_tabs.active().filter.clone();
+            match filter.to_pin() {
+              Some(pin) => {
+                let unpin = action == Action::ManagerTabUnpin;
+                let pins = self
+                  .database
+                  .call(move |database| {
+                    match unpin {
+                      true => database.remove_pin(pin.kind, &pin.name)?,
+                      false => database.add_pin(pin)?,
+                    }
+                    database.get_pins()
+                  })
+                  .await;
+                match pins {
+                  Ok(pins) => action_tx.send(Action::PinsUpdate(pins))?,
+                  Err(e) => action_tx.send(Action::Error(format!("failed to update the pins: {e}")))?,
+                }
+              },
+              None => {
+                action_tx.send(Action::Error("only artist, album and playlist tabs can be pinned".to_string()))?
+              },
+            }
+          },
+          Action::ManagerTabSaveState((selected, offset)) => {
+            let tab = self.layout_manager.manager_tabs.active_mut();
+            tab.selected = selected;
+            tab.offset = offset;
+          },
+          Action::ManagerAttachmentsShow | Action::ManagerAttach(_) | Action::ManagerDetach(_) => {
+            match self.manage_attachments(&action).await {
+              Ok(attachments) => action_tx.send(Action::ManagerAttachments(attachments))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to update the attachments: {e}")))?,
+            }
+            if action == Action::ManagerAttachmentsShow {
+              action_tx.send(Action::FocusSwitch(Focus {
+                mode: Mode::Manager,
+                scene: Scenes::Manager(ManagerLayouts::Attachments),
+              }))?;
+            }
+          },
+          Action::TrimShow(song_id) => {
+            let music_dir = self.config.config.music_dir.clone();
+            let loaded = match self.database.call(move |database| trim::song_file(database, song_id)).await {
+              Ok((title, relative_path)) => trim::load_song(&music_dir, song_id, title, &relative_path).await,
+              Err(e) => Err(e),
+            };
+            match loaded {
+              Ok(song) => {
+                action_tx.send(Action::TrimLoaded(song))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::Trim),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to open the song to trim: {e}")))?,
+            }
+          },
+          #[cfg(feature = "player")]
+          Action::TrimPreview(song_id, position) => {
+            if let Err(e) = self.preview(song_id, position).await {
+              action_tx.send(Action::Error(format!("failed to preview the trim: {e}")))?;
+            }
+          },
+          Action::TrimRender(range) => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Edit, format!("trim song {}", range.song_id), move |context| {
+              trim::render_trim(config.clone(), range, context)
+            });
+            action_tx.send(Action::FocusBack)?;
+          },
+          Action::SongEditorShow(song_id) => {
+            match self.database.call(move |database| song_edit::load_song(database, song_id)).await {
+              Ok(edit) => {
+                action_tx.send(Action::SongEditorLoaded(edit))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::SongEditor),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to open the song to edit: {e}")))?,
+            }
+          },
+          Action::SongEditorSave(ref edit) => {
+            // the editor stays open when the edit is refused
+            let saved = edit.clone();
+            match self.database.call(move |database| song_edit::save_song(database, &saved)).await {
+              Ok(()) => {
+                action_tx.send(Action::Notify(format!("saved the metadata of {}", edit.title)))?;
+                action_tx.send(Action::FocusBack)?;
+                action_tx.send(Action::LibraryChanged)?;
+                if edit.write_tags {
+                  action_tx.send(Action::WriteTags(vec![edit.song_id]))?;
+                }
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to save the metadata of {}: {e}", edit.title)))?,
+            }
+          },
+          Action::SongEditorExternal(ref song_ids) => {
+            let song_ids = song_ids.clone();
+            let opened = self
+              .database
+              .call(move |database| song_ids.iter().map(|song_id| song_edit::load_song(database, *song_id)).collect())
+              .await;
+            let written = opened.and_then(|opened: Vec<SongEdit>| {
+              let path = std::env::temp_dir().join(format!("muzik-edit-{}.toml", uuid::Uuid::new_v4()));
+              std::fs::write(&path, song_edit::to_toml(&opened)?)?;
+              Ok((path, opened))
+            });
+            match written {
+              // the editor is opened once the actions are handled
+              Ok(edit) => self.external_edit = Some(edit),
+              Err(e) => action_tx.send(Action::Error(format!("failed to open the songs to edit: {e}")))?,
+            }
+          },
+          Action::SongEditorLookup(provider, ref query) => {
+            let (config, query, lookup_tx) = (self.config.clone(), query.clone(), action_tx.clone());
+            // the editor waits for the release, so the lookup is not left behind the background jobs
+            self.jobs.spawn_with_priority(
+              JobKind::Enrich,
+              JobPriority::Interactive,
+              format!("look up {} on {provider}", query.title),
+              move |context| {
+                enrichment::lookup_release(config.clone(), provider, query.clone(), lookup_tx.clone(), context)
+              },
+            );
+          },
+          Action::ExportSongs(ref song_ids, ref destination) => {
+            let config = self.config.clone();
+            let (song_ids, destination) = (song_ids.clone(), destination.clone());
+            let name = format!("export {} songs to {}", song_ids.len(), destination.display());
+            // transcoding is heavy and the songs are not needed right away
+            self.jobs.spawn_with_priority(JobKind::Edit, JobPriority::Background, name, move |context| {
+              export::export_songs(config.clone(), song_ids.clone(), destination.clone(), context)
+            });
+          },
+          Action::ExportTab(ref destination) => {
+            let filter = self.layout_manager.manager_tabs.active().filter.clone();
+            match self.database.call(move |database| song_status::tab_song_ids(database, &filter)).await {
+              Ok(song_ids) => action_tx.send(Action::ExportSongs(song_ids, destination.clone()))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to load the songs to export: {e}")))?,
+            }
+          },
+          Action::WriteTags(ref song_ids) => {
+            let config = self.config.clone();
+            let song_ids = song_ids.clone();
+            self.jobs.spawn(JobKind::Edit, format!("write the tags of {} songs", song_ids.len()), move |context| {
+              tagging::tag_songs(config.clone(), song_ids.clone(), context)
+            });
+          },
+          Action::ManagerFindDuplicates => {
+            let found = self
+              .database
+              .call(|database| {
+                let candidate = match database.find_duplicate_songs()?.first() {
+                  Some((left, right)) => {
+                    let (left_locks, right_locks) =
+                      (database.get_song_locks(left.id)?, database.get_song_locks(right.id)?);
+                    let (left_sources, right_sources) =
+                      (database.get_song_provenance(left.id)?, database.get_song_provenance(right.id)?);
+                    Some(
+                      MergeCandidate::from_songs(left, right)
+                        .with_locks(&left_locks, &right_locks)
+                        .with_provenance(&left_sources, &right_sources),
+                    )
+                  },
+                  None => {
+                    database
+                      .find_duplicate_artists()?
+                      .first()
+                      .map(|(left, right)| MergeCandidate::from_artists(left, right))
+                  },
+                };
+                Ok((candidate, database.data_version()?))
+              })
+              .await?;
+            match found {
+              (Some(candidate), version) => {
+                self.compare_version = Some(version);
+                action_tx.send(Action::ManagerCompare(candidate))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::Compare),
+                }))?;
+              },
+              (None, _) => info!("no duplicates found"),
+            }
+          },
+          Action::ManagerApplyMerge(ref candidate) => {
+            // merging outdated entities would undo the changes made to them since
+            let (compare_version, candidate) = (self.compare_version, candidate.clone());
+            let merged = self
+              .database
+              .call(move |database| {
+                match compare_version == Some(database.data_version()?) {
+                  true => database.apply_merge(&candidate).map(|()| true),
+                  false => Ok(false),
+                }
+              })
+              .await;
+            match merged {
+              Ok(true) => {},
+              Ok(false) => {
+                action_tx.send(Action::Error(
+                  "not merged, the library was changed by another process since the comparison was loaded".to_string(),
+                ))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to merge: {e}")))?,
+            }
+            action_tx.send(Action::FocusBack)?;
+          },
+          #[cfg(feature = "musicbrainz")]
+          Action::ManagerFindAlbumGaps => {
+            let config = self.config.clone();
+            let gaps_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Enrich, "find missing albums", move |context| {
+              gaps::find_album_gaps(config.clone(), gaps_tx.clone(), context)
+            });
+          },
+          Action::ManagerAlbumGaps(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::AlbumGaps),
+            }))?;
+          },
+          Action::ScanMusicDir => {
+            let config = self.config.clone();
+            let scan_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Scan, "scan the music dir", move |context| {
+              scan::scan_music_dir(config.clone(), None, scan_tx.clone(), context)
+            });
+          },
+          Action::ScanRetry(ref paths) => {
+            let config = self.config.clone();
+            let paths = paths.clone();
+            let scan_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Scan, format!("scan {} files again", paths.len()), move |context| {
+              scan::scan_music_dir(config.clone(), Some(paths.clone()), scan_tx.clone(), context)
+            });
+          },
+          Action::DiagnosticsRun => {
+            self.check_setup(&action_tx);
+            if self.get_focused().scene != Scenes::Home(HomeLayouts::Diagnostics) {
+              action_tx
+                .send(Action::FocusSwitch(Focus { mode: Mode::Home, scene: Scenes::Home(HomeLayouts::Diagnostics) }))?;
+            }
+          },
+          // the screen is only opened by itself when a check failed
+          Action::Diagnostics(ref diagnostics)
+            if !diagnostics.is_empty() && self.get_focused().scene != Scenes::Home(HomeLayouts::Diagnostics) =>
+          {
+            action_tx
+              .send(Action::FocusSwitch(Focus { mode: Mode::Home, scene: Scenes::Home(HomeLayouts::Diagnostics) }))?;
+          },
+          Action::DiagnosticsFix(fix) => {
+            let config = self.config.clone();
+            let fix_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Edit, fix.to_string(), move |context| {
+              diagnostics::apply_fix(config.clone(), fix, fix_tx.clone(), context)
+            });
+          },
+          Action::HealthShow => {
+            let config = self.config.clone();
+            let health_tx = action_tx.clone();
+            // the health screen waits on it
+            self.jobs.spawn_with_priority(
+              JobKind::Verify,
+              JobPriority::UiCritical,
+              "check the library health",
+              move |context| health::check_health(config.clone(), health_tx.clone(), context),
+            );
+          },
+          Action::DownloadLogsShow | Action::DownloadLogShow(_) => {
+            let entries = match self.queue_items().await {
+              Ok(queue) => self.database.call(move |database| download_logs::load_entries(database, queue)).await,
+              Err(e) => Err(e),
+            };
+            match entries {
+              Ok(entries) => {
+                let selected = match action {
+                  Action::DownloadLogShow(ref youtube_id) => {
+                    entries.iter().position(|entry| entry.youtube_id == *youtube_id)
+                  },
+                  _ => None,
+                };
+                let selected = selected.or((!entries.is_empty()).then_some(0));
+                action_tx.send(Action::DownloadLogs(download_logs::DownloadLogList { entries, selected }))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::DownloadLogs),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the downloads: {e}")))?,
+            }
+          },
+          Action::DuplicatesShow => {
+            match self.held_downloads().await {
+              Ok(items) => {
+                action_tx.send(Action::Duplicates(items))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::Duplicates),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the downloads: {e}")))?,
+            }
+          },
+          Action::DuplicateResolve(id, policy) => {
+            match self.resolve_duplicate(id, policy).await {
+              Ok(()) => {
+                action_tx.send(Action::Notify(format!("handling the download with the {policy} policy")))?;
+                action_tx.send(Action::Duplicates(self.held_downloads().await?))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to resolve the download: {e}")))?,
+            }
+          },
+          Action::ReviewShow => {
+            match self.reviewed_downloads().await {
+              Ok(items) => {
+                action_tx.send(Action::Reviews(items))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::Review),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the downloads: {e}")))?,
+            }
+          },
+          Action::ReviewVerdict(id, ref verdict) => {
+            let message = match verdict {
+              Verdict::Reject => "dropped the download",
+              _ => "queued the download again",
+            };
+            match self.review_download(id, verdict.clone()).await {
+              Ok(()) => {
+                action_tx.send(Action::Notify(message.to_string()))?;
+                action_tx.send(Action::Reviews(self.reviewed_downloads().await?))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to review the download: {e}")))?,
+            }
+          },
+          Action::LinksCheck => {
+            let started = links::spawn_check(&self.jobs, self.config.clone(), true);
+            if !started {
+              action_tx.send(Action::Notify("the videos are being checked already".to_string()))?;
+            }
+          },
+          Action::DeadLinksShow => {
+            match self.database.call(links::load_dead_links).await {
+              Ok(dead_links) => {
+                action_tx.send(Action::DeadLinks(dead_links))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::DeadLinks),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the dead videos: {e}")))?,
+            }
+          },
+          Action::DeadLinkRelink(song_id, ref youtube_id) => {
+            let relinked = youtube_id.clone();
+            let dead_links = self
+              .database
+              .call(move |database| {
+                database.relink_song(song_id, &relinked, MetadataSource::Manual)?;
+                links::load_dead_links(database)
+              })
+              .await;
+            match dead_links {
+              Ok(dead_links) => {
+                action_tx.send(Action::Notify(format!("linked song {song_id} to {youtube_id}")))?;
+                action_tx.send(Action::DeadLinks(dead_links))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to link song {song_id} to {youtube_id}: {e}")))?,
+            }
+          },
+          Action::VerifyLibrary => {
+            let started = verify::spawn_verify(&self.jobs, self.config.clone());
+            if !started {
+              action_tx.send(Action::Notify("the files are being verified already".to_string()))?;
+            }
+          },
+          Action::RedownloadMissing => {
+            let config = self.config.clone();
+            let redownload_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Verify, "download the missing songs again", move |context| {
+              verify::redownload_missing(config.clone(), redownload_tx.clone(), context)
+            });
+          },
+          Action::IntegrityShow => {
+            let music_dir = self.config.config.music_dir.clone();
+            match self.database.call(move |database| verify::load_problems(database, &music_dir)).await {
+              Ok(problems) => {
+                action_tx.send(Action::Integrity(problems))?;
+                action_tx.send(Action::FocusSwitch(Focus {
+                  mode: Mode::Manager,
+                  scene: Scenes::Manager(ManagerLayouts::Integrity),
+                }))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the damaged files: {e}")))?,
+            }
+          },
+          Action::UpgradeFind(song_id) => {
+            let config = self.config.clone();
+            let upgrade_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Import, format!("find a better version of song {song_id}"), move |context| {
+              upgrade::find_upgrades(config.clone(), song_id, upgrade_tx.clone(), context)
+            });
+          },
+          Action::UpgradeOffer(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::Upgrade),
+            }))?;
+          },
+          Action::UpgradeReplace(song_id, ref youtube_id) => {
+            let config = self.config.clone();
+            let youtube_id = youtube_id.clone();
+            self.jobs.spawn(JobKind::Edit, format!("replace the file of song {song_id}"), move |context| {
+              upgrade::replace_file(config.clone(), song_id, youtube_id.clone(), context)
+            });
+          },
+          Action::HealthReport(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::Health),
+            }))?;
+          },
+          Action::ScanResults(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::ScanResults),
+            }))?;
+          },
+          Action::JobsShow if self.get_focused().mode != Mode::Jobs => {
+            action_tx.send(Action::FocusSwitch(Focus { mode: Mode::Jobs, scene: Scenes::Jobs(JobsLayouts::List) }))?;
+          },
+          Action::JobsCancel(id) => {
+            if let Err(e) = self.jobs.cancel(id) {
+              action_tx.send(Action::Error(format!("failed to cancel job: {e}")))?;
+            }
+          },
+          Action::JobsRetry(id) => {
+            if let Err(e) = self.jobs.retry(id) {
+              action_tx.send(Action::Error(format!("failed to retry job: {e}")))?;
+            }
+          },
+          Action::ListenBrainzImport => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Sync, "import ListenBrainz listens", move |context| {
+              listenbrainz::import_history(config.clone(), context)
+            });
+          },
+          Action::EnrichAlbums => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Enrich, "enrich albums", move |context| {
+              enrichment::enrich_albums(config.clone(), context)
+            });
+          },
+          #[cfg(feature = "musicbrainz")]
+          Action::ClassifyAlbums => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Enrich, "classify albums", move |context| {
+              enrichment::classify_albums(config.clone(), context)
+            });
+          },
+          Action::ImportStart(ref source) => {
+            let config = self.config.clone();
+            let source = source.clone();
+            let import_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Import, format!("import {source}"), move |context| {
+              import::import_playlist(config.clone(), source.clone(), import_tx.clone(), context)
+            });
+          },
+          #[cfg(feature = "musicbrainz")]
+          Action::AlbumComplete(song_id) => {
+            let config = self.config.clone();
+            let album_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Import, format!("complete the album of song {song_id}"), move |context| {
+              album_completion::complete_album(config.clone(), song_id, album_tx.clone(), context)
+            });
+          },
+          Action::InstrumentalFind(song_id) => {
+            let config = self.config.clone();
+            let instrumental_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Import, format!("find the instrumental of song {song_id}"), move |context| {
+              instrumental::find_instrumental(config.clone(), song_id, instrumental_tx.clone(), context)
+            });
+          },
+          Action::ImportReview(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Download,
+              scene: Scenes::Download(DownloadLayouts::ImportReview),
+            }))?;
+          },
+          Action::DownloadConfirm(_) => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Download,
+              scene: Scenes::Download(DownloadLayouts::QueueConfirm),
+            }))?;
+          },
+          Action::DownloadEnqueue(ref requests) => {
+            if let Some(daemon) = self.daemon.as_mut() {
+              match daemon.request(&IpcRequest::Enqueue { requests: requests.clone() }).await {
+                Ok(IpcResponse::Ok) => {},
+                response => {
+                  // download here instead, the daemon stopped or is misbehaving
+                  action_tx.send(Action::Error(format!("failed to queue on the daemon: {response:?}")))?;
+                  self.daemon = None;
+                  tokio::spawn(self.download_queue.clone().run(self.config.clone(), worker_cancellation.clone()));
+                },
+              }
+            }
+            if self.daemon.is_none() {
+              self.download_queue.enqueue(requests.iter().cloned());
+            }
+            action_tx.send(Action::Notify(format!("queued {} songs for download", requests.len())))?;
+          },
+          Action::DownloadCheck(ref requests) => {
+            let config = self.config.clone();
+            let requests = requests.clone();
+            let check_tx = action_tx.clone();
+            let name = format!("check the availability of {} videos", requests.len());
+            // the download waits on it
+            self.jobs.spawn_with_priority(JobKind::Verify, JobPriority::UiCritical, name, move |context| {
+              availability::check_and_enqueue(config.clone(), requests.clone(), check_tx.clone(), context)
+            });
+          },
+          Action::RefetchArt => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Enrich, "fetch the cover art again", move |context| {
+              art::refetch_art(config.clone(), context)
+            });
+          },
+          Action::ArtistImagesFetch => {
+            let config = self.config.clone();
+            let fetch_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Enrich, "fetch the pictures of the artists", move |context| {
+              artist_images::fetch_artist_images(config.clone(), None, fetch_tx.clone(), context)
+            });
+          },
+          Action::ArtistImageFetch(ref artist) => {
+            let config = self.config.clone();
+            let artists = vec![artist.clone()];
+            let fetch_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Enrich, format!("fetch the picture of {artist}"), move |context| {
+              artist_images::fetch_artist_images(config.clone(), Some(artists.clone()), fetch_tx.clone(), context)
+            });
+          },
+          Action::PlaylistsExport => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Sync, "export playlists", move |context| {
+              playlists::export_playlists(config.clone(), None, context)
+            });
+          },
+          Action::PlaylistsExportTo(ref directory) => {
+            let config = self.config.clone();
+            let directory = directory.clone();
+            self.jobs.spawn(JobKind::Sync, format!("export playlists to {}", directory.display()), move |context| {
+              playlists::export_playlists(config.clone(), Some(directory.clone()), context)
+            });
+          },
+          Action::BackupsShow => {
+            match backup::list_backups(&self.config) {
+              Ok(backups) => action_tx.send(Action::BackupsUpdate(backups))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the backups: {e}")))?,
+            }
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::Backups),
+            }))?;
+          },
+          Action::SnapshotsShow => {
+            match self.database.call(Database::get_snapshots).await {
+              Ok(snapshots) => action_tx.send(Action::SnapshotsUpdate(snapshots))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to list the snapshots: {e}")))?,
+            }
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::Snapshots),
+            }))?;
+          },
+          Action::SnapshotTake(ref name) => {
+            let (config, name, snapshot_tx) = (self.config.clone(), name.clone(), action_tx.clone());
+            let priority = JobPriority::Interactive;
+            self.jobs.spawn_with_priority(JobKind::Scan, priority, "take a snapshot of the library", move |context| {
+              snapshots::take_snapshot(config.clone(), name.clone(), snapshot_tx.clone(), context)
+            });
+          },
+          Action::SnapshotCompare(from, to) => {
+            let (config, diff_tx) = (self.config.clone(), action_tx.clone());
+            // the Snapshots view waits on it
+            self.jobs.spawn_with_priority(
+              JobKind::Scan,
+              JobPriority::UiCritical,
+              "compare snapshots",
+              move |context| snapshots::compare_snapshots(config.clone(), from, to, diff_tx.clone(), context),
+            );
+          },
+          Action::SnapshotDelete(snapshot_id) => {
+            let deleted = self
+              .database
+              .call(move |database| {
+                database.delete_snapshot(snapshot_id)?;
+                database.get_snapshots()
+              })
+              .await;
+            match deleted {
+              Ok(snapshots) => action_tx.send(Action::SnapshotsUpdate(snapshots))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to delete the snapshot: {e}")))?,
+            }
+          },
+          Action::PlaylistsShow => {
+            self.load_playlists(&action_tx).await?;
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::Manager,
+              scene: Scenes::Manager(ManagerLayouts::Playlists),
+            }))?;
+          },
+          Action::PlaylistCreate(ref name) => {
+            let created = name.clone();
+            match self.database.call(move |database| database.create_playlist(&created)).await {
+              Ok(_) => action_tx.send(Action::Notify(format!("created the playlist {}", name.trim())))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to create the playlist: {e}")))?,
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistRename(playlist_id, ref name) => {
+            let renamed = name.clone();
+            if let Err(e) = self.database.call(move |database| database.rename_playlist(playlist_id, &renamed)).await {
+              action_tx.send(Action::Error(format!("failed to rename the playlist: {e}")))?;
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistDelete(playlist_id) => {
+            if let Err(e) = self.database.call(move |database| database.delete_playlist(playlist_id)).await {
+              action_tx.send(Action::Error(format!("failed to delete the playlist: {e}")))?;
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistAddSongs(ref name, ref song_ids) => {
+            let (playlist, added_ids) = (name.clone(), song_ids.clone());
+            let added = self
+              .database
+              .call(move |database| {
+                let playlist_id = match database.get_playlist_by_name(&playlist)? {
+                  Some(playlist) => playlist.id,
+                  None => database.create_playlist(&playlist)?,
+                };
+                database.add_playlist_songs(playlist_id, &added_ids)
+              })
+              .await;
+            match added {
+              Ok(()) => action_tx.send(Action::Notify(format!("added {} songs to {}", song_ids.len(), name.trim())))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to add the songs to {name}: {e}")))?,
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistRemoveSong(playlist_id, index) => {
+            if let Err(e) = self.database.call(move |database| database.remove_playlist_song(playlist_id, index)).await
+            {
+              action_tx.send(Action::Error(format!("failed to remove the song from the playlist: {e}")))?;
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistMoveSong(playlist_id, from, to) => {
+            if let Err(e) = self.database.call(move |database| database.move_playlist_song(playlist_id, from, to)).await
+            {
+              action_tx.send(Action::Error(format!("failed to move the song of the playlist: {e}")))?;
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistSetProfiles(playlist_id, ref profiles) => {
+            let set = match playlist_export::validate_profiles(&self.config, profiles) {
+              Ok(()) => {
+                let profiles = profiles.clone();
+                self.database.call(move |database| database.set_playlist_profiles(playlist_id, &profiles)).await
+              },
+              Err(e) => Err(e),
+            };
+            match set {
+              Ok(()) if profiles.is_empty() => {
+                action_tx.send(Action::Notify("the playlist is no longer exported".to_string()))?
+              },
+              Ok(()) => {
+                action_tx.send(Action::Notify(format!("the playlist is exported with {}", profiles.join(", "))))?
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to pick the export profiles: {e}")))?,
+            }
+            self.load_playlists(&action_tx).await?;
+          },
+          Action::PlaylistExportProfiles(playlist_id) => {
+            let playlist = self
+              .database
+              .call(Database::get_playlists)
+              .await?
+              .into_iter()
+              .find(|playlist| playlist.id == playlist_id);
+            if let Some(playlist) = playlist {
+              let config = self.config.clone();
+              let name = format!("export the playlist {}", playlist.name);
+              // copying and transcoding is heavy and the songs are not needed right away
+              self.jobs.spawn_with_priority(JobKind::Edit, JobPriority::Background, name, move |context| {
+                playlist_export::export_playlist(config.clone(), playlist_id, context)
+              });
+            }
+          },
+          Action::MpdPlaySong(song_id) => {
+            match mpd::play_song(&self.config.config.mpd, &self.database, song_id).await {
+              Ok((title, file)) => {
+                self.mpd_file = Some(file);
+                self.mpd_plays.start(&self.database, PlayedSong::Song(song_id), None);
+                action_tx.send(Action::Notify(format!("playing {title} with MPD")))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to play the song with MPD: {e}")))?,
+            }
+          },
+          Action::MpdLoadPlaylists => {
+            match mpd::load_playlists(&self.config.config.mpd, &self.database).await {
+              Ok(loaded) => action_tx.send(Action::Notify(format!("loaded {loaded} playlists into MPD")))?,
+              Err(e) => action_tx.send(Action::Error(format!("failed to load the playlists into MPD: {e}")))?,
+            }
+          },
+          Action::BackupCreate => {
+            let config = self.config.clone();
+            let backup_tx = action_tx.clone();
+            self.jobs.spawn(JobKind::Sync, "back up the database", move |context| {
+              backup::run_backup(config.clone(), Some(backup_tx.clone()), context)
+            });
+          },
+          Action::BackupRestore(ref path) => {
+            let (config, restored) = (self.config.clone(), path.clone());
+            match self.database.call(move |database| backup::restore(&config, database, &restored)).await {
+              Ok(()) => {
+                self.database = DatabaseHandle::new(self.config.clone()).await?;
+                self.library_version = self.database.call(Database::data_version).await?;
+                action_tx.send(Action::LibraryChanged)?;
+                action_tx.send(Action::Notify(format!("restored the database from {}", path.display())))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to restore the backup: {e}")))?,
+            }
+            action_tx.send(Action::BackupsShow)?;
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerPlayQueued => {
+            match self.play_queued().await {
+              Ok((message, now_playing)) => {
+                action_tx.send(Action::PlayerNowPlaying(now_playing))?;
+                action_tx.send(Action::Notify(message))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to play: {e}")))?,
+            }
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerPlaySong(song_id) => {
+            match self.play_song(song_id).await {
+              Ok(now_playing) => {
+                action_tx.send(Action::Notify(format!("playing {}", now_playing.title)))?;
+                action_tx.send(Action::PlayerNowPlaying(Some(now_playing)))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to play the song: {e}")))?,
+            }
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerPreview(ref request, duration) => {
+            self.player_plays.stop();
+            let played = match self.player.play_stream(request).await {
+              Ok(()) => self.now_playing(&request.youtube_id, &request.title, duration).await,
+              Err(e) => Err(e),
+            };
+            match played {
+              Ok(now_playing) => {
+                action_tx.send(Action::Notify(format!("previewing {}", request.title)))?;
+                action_tx.send(Action::PlayerNowPlaying(Some(now_playing)))?;
+              },
+              Err(e) => action_tx.send(Action::Error(format!("failed to preview the video: {e}")))?,
+            }
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerPause => {
+            match self.player.toggle_pause() {
+              Some(paused) => action_tx.send(Action::PlayerPaused(paused))?,
+              None => action_tx.send(Action::Notify("nothing is playing".to_string()))?,
+            }
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerStop => {
+            self.player.stop();
+            self.player_plays.stop();
+            action_tx.send(Action::PlayerNowPlaying(None))?;
+          },
+          #[cfg(feature = "player")]
+          Action::NowPlayingShow if self.get_focused().mode != Mode::NowPlaying => {
+            action_tx.send(Action::FocusSwitch(Focus {
+              mode: Mode::NowPlaying,
+              scene: Scenes::NowPlaying(NowPlayingLayouts::Lyrics),
+            }))?;
+          },
+          #[cfg(feature = "player")]
+          Action::PlayerSeekForward | Action::PlayerSeekBackward => {
+            let seconds = if action == Action::PlayerSeekForward { SEEK_STEP } else { -SEEK_STEP };
+            match self.player.seek(seconds) {
+              Ok(Seek::Pending) => action_tx.send(Action::Notify("seeking once the song is downloaded".to_string()))?,
+              Ok(_) => {},
+              Err(e) => action_tx.send(Action::Error(format!("failed to seek: {e}")))?,
+            }
+          },
+          #[cfg(not(feature = "player"))]
+          Action::PlayerPlayQueued
+          | Action::PlayerPlaySong(_)
+          | Action::PlayerPreview(..)
+          | Action::PlayerPause
+          | Action::TrimPreview(..) => {
+            action_tx.send(Action::Error("muzik was built without the player".to_string()))?;
+          },
+          #[cfg(not(feature = "musicbrainz"))]
+          Action::ManagerFindAlbumGaps | Action::ClassifyAlbums | Action::AlbumComplete(_) => {
+            action_tx.send(Action::Error("muzik was built without MusicBrainz".to_string()))?;
+          },
           Action::Error(ref error) => error!("error in program: {}", error),
           _ => {},
         }
@@ -230,8 +1350,37 @@ impl App {
           };
         }
       }
+      if let Some((path, opened)) = self.external_edit.take() {
+        let editor = song_edit::editor(self.config.config.editor.as_deref());
+        let edited = tui.edit(&editor, &path).await;
+        tui = tui::Tui::new()?.tick_rate(self.tick_rate).frame_rate(self.frame_rate);
+        tui.enter()?;
+        let edited = edited.and_then(|()| song_edit::from_toml(&std::fs::read_to_string(&path)?, &opened));
+        let _ = std::fs::remove_file(&path);
+        let saved = match edited {
+          Ok(edits) => {
+            self.database.call(move |database| song_edit::save_songs(database, &edits).map(|()| edits)).await
+          },
+          Err(e) => Err(e),
+        };
+        match saved {
+          Ok(edits) if edits.is_empty() => action_tx.send(Action::Notify("no metadata was changed".to_string()))?,
+          Ok(edits) => {
+            action_tx.send(Action::Notify(format!("saved the metadata of {} songs", edits.len())))?;
+            action_tx.send(Action::LibraryChanged)?;
+            let tagged: Vec<_> = edits.iter().filter(|edit| edit.write_tags).map(|edit| edit.song_id).collect();
+            if !tagged.is_empty() {
+              action_tx.send(Action::WriteTags(tagged))?;
+            }
+          },
+          Err(e) => action_tx.send(Action::Error(format!("the edited metadata was not saved: {e}")))?,
+        }
+      }
       if self.should_suspend {
-        tui.suspend()?;
+        match self.config.config.suspend_mode {
+          SuspendMode::Shell => tui.shell().await?,
+          SuspendMode::Stop => tui.suspend()?,
+        }
         action_tx.send(Action::Resume)?;
         tui = tui::Tui::new()?.tick_rate(self.tick_rate).frame_rate(self.frame_rate);
         // tui.mouse(true);
@@ -241,10 +1390,384 @@ impl App {
         break;
       }
     }
+    worker_cancellation.cancel();
     tui.exit()?;
     Ok(())
   }
 
+  /// Resume the streamed song once it is downloaded, clear the song once it ends, and send the
+  /// position of playback
+  #[cfg(feature = "player")]
+  async fn tick_player(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    if let Err(e) = self.resume_playback().await {
+      self.player.stop();
+      self.player_plays.stop();
+      action_tx.send(Action::PlayerNowPlaying(None))?;
+      action_tx.send(Action::Error(format!("failed to seek in the playing song: {e}")))?;
+    }
+    if let Some(position) = self.player.position() {
+      if let Err(e) = self.player_plays.reached(&self.database, position, None).await {
+        action_tx.send(Action::Error(format!("failed to record the play of the song: {e}")))?;
+      }
+    }
+    if self.player.finished() {
+      self.player.stop();
+      self.player_plays.stop();
+      action_tx.send(Action::PlayerNowPlaying(None))?;
+    }
+    if let Some(position) = self.player.position() {
+      action_tx.send(Action::PlayerPosition(Some(position)))?;
+    }
+    Ok(())
+  }
+
+  /// Play the song queued for download last, streaming it if it is not downloaded yet
+  ///
+  /// # Returns
+  ///
+  /// * a message telling what is played, and the song played if any
+  #[cfg(feature = "player")]
+  async fn play_queued(&mut self) -> Result<(String, Option<NowPlaying>)> {
+    let items = self.queue_items().await?;
+    let (message, request, duration) =
+      match items.into_iter().rev().find(|item| !matches!(item.status, QueueStatus::Failed(_))) {
+        Some(QueueItem { request, status: QueueStatus::Finished, .. }) => {
+          let (music_dir, youtube_id) = (self.config.config.music_dir.clone(), request.youtube_id.clone());
+          let path = self.database.call(move |database| queue::song_file(database, &music_dir, &youtube_id)).await?;
+          self.player.play_file(&request.youtube_id, &path, Duration::ZERO).await?;
+          let duration = scan::probe(&path).await.ok().and_then(|tags| tags.duration);
+          (format!("playing {}", request.title), request, duration)
+        },
+        Some(QueueItem { request, .. }) => {
+          self.player.play_stream(&request).await?;
+          (format!("streaming {} while it downloads", request.title), request, None)
+        },
+        None => return Ok(("nothing is queued for download".to_string(), None)),
+      };
+    self.player_plays.start(&self.database, PlayedSong::Video(request.youtube_id.clone()), duration);
+    Ok((message, Some(self.now_playing(&request.youtube_id, &request.title, duration).await?)))
+  }
+
+  /// Play the downloaded song with the id from the start
+  #[cfg(feature = "player")]
+  async fn play_song(&mut self, song_id: i32) -> Result<NowPlaying> {
+    let song = self.database.call(move |database| database.get_song_from_id(song_id)).await?;
+    let path = self.preview(song_id, Duration::ZERO).await?;
+    let duration = scan::probe(&path).await.ok().and_then(|tags| tags.duration);
+    self.player_plays.start(&self.database, PlayedSong::Song(song_id), duration);
+    self.now_playing(song.youtube_id.as_deref().unwrap_or_default(), &song.title, duration).await
+  }
+
+  /// The playing song as shown, with its lyrics when they are shown
+  #[cfg(feature = "player")]
+  async fn now_playing(&mut self, youtube_id: &str, title: &str, duration: Option<Duration>) -> Result<NowPlaying> {
+    let lyrics = match self.config.config.player.show_lyrics && !youtube_id.is_empty() {
+      true => {
+        let (music_dir, youtube_id) = (self.config.config.music_dir.clone(), youtube_id.to_string());
+        self.database.call(move |database| lyrics::find_lyrics(&music_dir, database, &youtube_id)).await?
+      },
+      false => None,
+    };
+    Ok(NowPlaying { youtube_id: youtube_id.to_string(), title: title.to_string(), duration, lyrics })
+  }
+
+  /// Continue the streamed song from its file once it is downloaded, if it was seeked in
+  #[cfg(feature = "player")]
+  async fn resume_playback(&mut self) -> Result<()> {
+    let Some(youtube_id) = self.player.waiting_for().map(str::to_string) else {
+      return Ok(());
+    };
+    match self.download_status(&youtube_id).await? {
+      Some(QueueStatus::Finished) => {
+        let music_dir = self.config.config.music_dir.clone();
+        let path = self.database.call(move |database| queue::song_file(database, &music_dir, &youtube_id)).await?;
+        self.player.resume_from_file(&path).await
+      },
+      Some(QueueStatus::Failed(error)) => Err(eyre!("the download failed: {error}")),
+      _ => Ok(()),
+    }
+  }
+
+  /// Play the downloaded song from the position
+  ///
+  /// # Returns
+  ///
+  /// * the file played
+  #[cfg(feature = "player")]
+  async fn preview(&mut self, song_id: i32, position: Duration) -> Result<PathBuf> {
+    let (song, relative_path) = self
+      .database
+      .call(move |database| {
+        let song = database.get_song_from_id(song_id)?;
+        let relative_path =
+          database.get_song_file(song_id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+        Ok((song, relative_path))
+      })
+      .await?;
+    let path = self.config.config.music_dir.join(relative_path);
+    // a preview of the trim is not counted, playing the song from its start counts it again
+    self.player_plays.stop();
+    self.player.play_file(song.youtube_id.as_deref().unwrap_or_default(), &path, position).await?;
+    Ok(path)
+  }
+
+  /// Attach or detach a file for the album of the active Manager tab
+  ///
+  /// # Returns
+  ///
+  /// * the files attached to the album
+  async fn manage_attachments(&mut self, action: &Action) -> Result<Vec<Attachment>> {
+    let TabFilter::Album(name) = self.layout_manager.manager_tabs.active().filter.clone() else {
+      return Err(eyre!("open an album tab to attach files to it"));
+    };
+    let (music_dir, action) = (self.config.config.music_dir.clone(), action.clone());
+    self
+      .database
+      .call(move |database| {
+        let album = database.get_album_by_name(&name)?.ok_or_else(|| eyre!("no album named {name:?}"))?;
+        match action {
+          Action::ManagerAttach(path) => {
+            attachments::attach(&music_dir, database, &AttachmentOwner::Album(album.clone()), &path)?;
+          },
+          Action::ManagerDetach(attachment_id) => attachments::detach(&music_dir, database, attachment_id)?,
+          _ => {},
+        }
+        database.get_album_attachments(album.id)
+      })
+      .await
+  }
+
+  /// Check that yt-dlp, ffmpeg, the music dir and the database are ready, sending the failed checks
+  /// to be shown
+  fn check_setup(&self, action_tx: &mpsc::UnboundedSender<Action>) {
+    let config = self.config.clone();
+    let check_tx = action_tx.clone();
+    // the diagnostics screen waits on it
+    self.jobs.spawn_with_priority(JobKind::Verify, JobPriority::UiCritical, "check the setup", move |context| {
+      diagnostics::check_setup(config.clone(), check_tx.clone(), context)
+    });
+  }
+
+  /// Load the dashboard again and send it to the components
+  async fn refresh_dashboard(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    self.last_dashboard_refresh = Instant::now();
+    let dashboard = match self.queue_items().await {
+      Ok(queue) => self.database.call(move |database| dashboard::Dashboard::load(database, queue)).await,
+      Err(e) => Err(e),
+    };
+    match dashboard {
+      Ok(dashboard) => action_tx.send(Action::DashboardUpdate(dashboard))?,
+      Err(e) => action_tx.send(Action::Error(format!("failed to load the dashboard: {e}")))?,
+    }
+    Ok(())
+  }
+
+  /// Load the songs of the active Manager tab and send them to the components
+  async fn load_manager_songs(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let filter = self.layout_manager.manager_tabs.active().filter.clone();
+    self.manager_generation += 1;
+    if song_status::is_paged(&filter, self.display_mode, self.manager_search.is_some()) {
+      return self.load_manager_page(action_tx, 0).await;
+    }
+    let now = chrono::Utc::now().naive_utc();
+    let rules = scan::IgnoreRules::new(&self.config.config.scan)?;
+    let music_dir = self.config.config.music_dir.clone();
+    let (display_mode, disambiguation) = (self.display_mode, self.config.config.disambiguation);
+    let (loaded, search) = (filter.clone(), self.manager_search.clone());
+    let rows = self
+      .database
+      .call(move |database| {
+        let rows = song_status::load_rows(database, &music_dir, &loaded, display_mode, &rules, now, disambiguation)?;
+        match search {
+          Some(text) => {
+            let found: Vec<_> = database.search_songs(&text)?.into_iter().map(|song| song.id).collect();
+            Ok(song_status::keep_found(rows, &found))
+          },
+          None => Ok(rows),
+        }
+      })
+      .await;
+    match rows {
+      Ok(rows) => action_tx.send(Action::ManagerSongs(rows))?,
+      Err(e) => action_tx.send(Action::Error(format!("failed to load the songs of {filter}: {e}")))?,
+    }
+    Ok(())
+  }
+
+  /// Send the page of the songs of the active Manager tab starting at the index
+  async fn load_manager_page(&mut self, action_tx: &mpsc::UnboundedSender<Action>, offset: usize) -> Result<()> {
+    let filter = self.layout_manager.manager_tabs.active().filter.clone();
+    let now = chrono::Utc::now().naive_utc();
+    let (music_dir, disambiguation) = (self.config.config.music_dir.clone(), self.config.config.disambiguation);
+    let loaded = filter.clone();
+    let page = self
+      .database
+      .call(move |database| {
+        song_status::load_page(database, &music_dir, &loaded, offset, song_status::PAGE_SIZE, now, disambiguation)
+      })
+      .await;
+    match page {
+      Ok((total, rows)) => {
+        let generation = self.manager_generation;
+        action_tx.send(Action::ManagerSongsPage(SongPage { generation, total, offset, rows }))?;
+      },
+      Err(e) => action_tx.send(Action::Error(format!("failed to load the songs of {filter}: {e}")))?,
+    }
+    Ok(())
+  }
+
+  /// What MPD plays, or `None` when it can not be reached
+  async fn mpd_status(&self) -> Option<MpdStatus> {
+    let status = match MpdClient::connect(&self.config.config.mpd).await {
+      Ok(Some(mut client)) => client.status().await,
+      Ok(None) => return None,
+      Err(e) => Err(e),
+    };
+    status.map_err(|e| debug!("failed to ask MPD what it plays: {e}")).ok()
+  }
+
+  /// Record a play of the song MPD was told to play once enough of it was heard, forgetting it once
+  /// MPD stops or plays another song
+  async fn count_mpd_play(
+    &mut self,
+    status: Option<&MpdStatus>,
+    action_tx: &mpsc::UnboundedSender<Action>,
+  ) -> Result<()> {
+    // MPD gone away for a moment does not stop the song
+    let Some(status) = status else {
+      return Ok(());
+    };
+    if status.state == MpdState::Stop || self.mpd_file.is_none() || status.file != self.mpd_file {
+      self.mpd_file = None;
+      self.mpd_plays.stop();
+      return Ok(());
+    }
+    let Some(elapsed) = status.elapsed else {
+      return Ok(());
+    };
+    if let Err(e) = self.mpd_plays.reached(&self.database, elapsed, status.duration).await {
+      action_tx.send(Action::Error(format!("failed to record the play of the song: {e}")))?;
+    }
+    Ok(())
+  }
+
+  /// Sync the playlists with the directory of `playlists.sync_directory`, if it is set
+  async fn sync_playlists(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let Some(directory) = playlist_sync::directory(&self.config) else {
+      return Ok(());
+    };
+    let (config, synced) = (self.config.clone(), directory.clone());
+    if let Err(e) =
+      self.database.call(move |database| playlist_sync::sync(&config, &synced, database, false).map(|_| ())).await
+    {
+      action_tx.send(Action::Error(format!("failed to sync the playlists with {}: {e}", directory.display())))?;
+    }
+    Ok(())
+  }
+
+  /// Sync the stored playlists with their directory, then send them to the components along with
+  /// the songs of the active Manager tab when it is a playlist, as it may have changed
+  async fn load_playlists(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    self.sync_playlists(action_tx).await?;
+    let playlists = self
+      .database
+      .call(|database| {
+        database
+          .get_playlists()?
+          .into_iter()
+          .map(|playlist| Ok((playlist.clone(), database.get_playlist_songs(playlist.id)?)))
+          .collect::<Result<Vec<_>>>()
+      })
+      .await;
+    match playlists {
+      Ok(playlists) => action_tx.send(Action::PlaylistsUpdate(playlists))?,
+      Err(e) => action_tx.send(Action::Error(format!("failed to load the playlists: {e}")))?,
+    }
+    match self.database.call(Database::get_playlist_profiles).await {
+      Ok(stored) => {
+        let mut profiles: HashMap<i32, Vec<String>> = HashMap::new();
+        for profile in stored {
+          profiles.entry(profile.playlist_id).or_default().push(profile.profile);
+        }
+        action_tx.send(Action::PlaylistsProfiles(profiles))?;
+      },
+      Err(e) => action_tx.send(Action::Error(format!("failed to load the export profiles of the playlists: {e}")))?,
+    }
+    if matches!(self.layout_manager.manager_tabs.active().filter, TabFilter::Playlist(_)) {
+      self.load_manager_songs(action_tx).await?;
+    }
+    Ok(())
+  }
+
+  /// The items of the download queue of the daemon, or of the app if no daemon is running
+  async fn queue_items(&mut self) -> Result<Vec<QueueItem>> {
+    match self.daemon.as_mut() {
+      Some(daemon) => {
+        match daemon.request(&IpcRequest::Queue).await? {
+          IpcResponse::Queue { items } => Ok(items),
+          response => Err(eyre!("unexpected response from the daemon: {response:?}")),
+        }
+      },
+      None => Ok(self.download_queue.items()),
+    }
+  }
+
+  /// The downloads of songs in the library already waiting for a decision
+  async fn held_downloads(&mut self) -> Result<Vec<QueueItem>> {
+    let items = self.queue_items().await?;
+    Ok(items.into_iter().filter(|item| matches!(item.status, QueueStatus::Duplicate(_))).collect())
+  }
+
+  /// Tell the queue of the daemon, or of the app if no daemon is running, what to do with the
+  /// download waiting for a decision
+  async fn resolve_duplicate(&mut self, id: u64, policy: DuplicatePolicy) -> Result<()> {
+    let resolved = match self.daemon.as_mut() {
+      Some(daemon) => {
+        match daemon.request(&IpcRequest::Resolve { id, policy }).await? {
+          IpcResponse::Ok => true,
+          IpcResponse::Error { message } => return Err(eyre!("the daemon failed to resolve the download: {message}")),
+          response => return Err(eyre!("unexpected response from the daemon: {response:?}")),
+        }
+      },
+      None => self.download_queue.resolve(id, policy),
+    };
+    match resolved {
+      true => Ok(()),
+      false => Err(eyre!("download {id} is not waiting for a decision")),
+    }
+  }
+
+  /// The downloads whose metadata was guessed, held for review
+  async fn reviewed_downloads(&mut self) -> Result<Vec<QueueItem>> {
+    let items = self.queue_items().await?;
+    Ok(items.into_iter().filter(|item| matches!(item.status, QueueStatus::Review(_))).collect())
+  }
+
+  /// Tell the queue of the daemon, or of the app if no daemon is running, what to do with the
+  /// download held for review
+  async fn review_download(&mut self, id: u64, verdict: Verdict) -> Result<()> {
+    let reviewed = match self.daemon.as_mut() {
+      Some(daemon) => {
+        match daemon.request(&IpcRequest::Review { id, verdict }).await? {
+          IpcResponse::Ok => true,
+          IpcResponse::Error { message } => return Err(eyre!("the daemon failed to review the download: {message}")),
+          response => return Err(eyre!("unexpected response from the daemon: {response:?}")),
+        }
+      },
+      None => self.download_queue.review(id, verdict),
+    };
+    match reviewed {
+      true => Ok(()),
+      false => Err(eyre!("download {id} is not held for review")),
+    }
+  }
+
+  /// The status of the last download of the song
+  async fn download_status(&mut self, youtube_id: &str) -> Result<Option<QueueStatus>> {
+    let items = self.queue_items().await?;
+    Ok(items.into_iter().rev().find(|item| item.request.youtube_id == youtube_id).map(|item| item.status))
+  }
+
   fn get_focused(&self) -> Focus {
     self.focus_buffer.last().expect("focus buffer should never be empty").clone()
   }
@@ -0,0 +1,238 @@
+//! Cover art
+//!
+//! The cover of a song is the thumbnail YouTube makes of its video. It is fetched once the song is
+//! downloaded, at the resolution of the `download.art` settings, or smaller when the video has no
+//! thumbnail that large or it is over the size limit. It is kept where the settings say: embedded
+//! in the file, as a `cover.jpg` attached to the album of the song, and in the cache dir. A job
+//! fetches the art of every song again, for libraries downloaded before the art was kept or kept
+//! at a lower resolution.
+
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use color_eyre::eyre::{eyre, Result};
+use reqwest::StatusCode;
+
+use crate::{
+  attachments::{self, AttachmentOwner},
+  config::{ArtConfig, ArtResolution, ArtStore, Config},
+  database::Database,
+  jobs::JobContext,
+  models::{AttachmentKind, MetadataSource, Song, SongField},
+  paths::Paths,
+  postprocess,
+};
+
+/// The name of the cover attached to albums
+pub const COVER_FILE: &str = "cover.jpg";
+
+/// The names of the thumbnails of a video, smallest first
+const THUMBNAILS: [(ArtResolution, &str); 4] = [
+  (ArtResolution::Low, "mqdefault"),
+  (ArtResolution::Medium, "hqdefault"),
+  (ArtResolution::High, "sddefault"),
+  (ArtResolution::Max, "maxresdefault"),
+];
+
+/// The cover art of a song
+#[derive(Debug, Clone)]
+pub struct Art {
+  pub url: String,
+  pub bytes: Bytes,
+}
+
+/// The URLs of the thumbnails of the video up to the resolution, largest first
+pub fn thumbnail_urls(youtube_id: &str, resolution: ArtResolution) -> Vec<String> {
+  THUMBNAILS
+    .iter()
+    .rev()
+    .filter(|(size, _)| *size <= resolution)
+    .map(|(_, name)| format!("https://i.ytimg.com/vi/{youtube_id}/{name}.jpg"))
+    .collect()
+}
+
+/// Where the art of the video is cached
+pub fn cache_path(paths: &Paths, youtube_id: &str) -> PathBuf {
+  paths.cache_dir.join("art").join(format!("{youtube_id}.jpg"))
+}
+
+/// Fetch the largest thumbnail of the video the settings allow
+///
+/// # Returns
+///
+/// * `None` if the video has no thumbnail under the size limit
+pub async fn fetch(client: &reqwest::Client, youtube_id: &str, settings: &ArtConfig) -> Result<Option<Art>> {
+  let max_size = settings.max_size_kb.map(|size| size * 1024);
+  for url in thumbnail_urls(youtube_id, settings.resolution) {
+    let response = client.get(&url).send().await?;
+    // the larger thumbnails are only made for some videos
+    if response.status() == StatusCode::NOT_FOUND {
+      continue;
+    }
+    let bytes = response.error_for_status()?.bytes().await?;
+    if max_size.is_some_and(|max_size| bytes.len() as u64 > max_size) {
+      continue;
+    }
+    return Ok(Some(Art { url, bytes }));
+  }
+  Ok(None)
+}
+
+/// Fetch the art of the song and keep it where the settings say. The thumbnail of the song is set
+/// to it unless it is locked
+///
+/// # Returns
+///
+/// * the URL of the art, `None` if the song has no video or the video has no art to fetch
+pub async fn update_art(
+  client: &reqwest::Client,
+  config: &Config,
+  database: &mut Database,
+  song: &Song,
+) -> Result<Option<String>> {
+  let Some(youtube_id) = song.youtube_id.as_deref() else {
+    return Ok(None);
+  };
+  let Some(art) = fetch(client, youtube_id, &config.config.download.art).await? else {
+    return Ok(None);
+  };
+  store(config, database, song, youtube_id, &art.bytes).await?;
+  if !database.is_song_field_locked(song.id, SongField::ThumbnailUrl)? {
+    database.set_song_thumbnail(song.id, &art.url, MetadataSource::YtDlp)?;
+  }
+  Ok(Some(art.url))
+}
+
+/// Keep the art of the song where the settings say
+async fn store(config: &Config, database: &mut Database, song: &Song, youtube_id: &str, bytes: &[u8]) -> Result<()> {
+  let store = &config.config.download.art.store;
+  let directory = std::env::temp_dir().join(format!("muzik-art-{}", uuid::Uuid::new_v4()));
+  std::fs::create_dir_all(&directory)?;
+  let cover = directory.join(COVER_FILE);
+  let result = async {
+    std::fs::write(&cover, bytes)?;
+    if store.contains(&ArtStore::Embedded) {
+      if let Some(relative_path) = database.get_song_file(song.id)? {
+        postprocess::embed_cover(&config.config.music_dir.join(relative_path), &cover).await?;
+      }
+    }
+    if store.contains(&ArtStore::File) {
+      attach_cover(&config.config.music_dir, database, song, &cover)?;
+    }
+    if store.contains(&ArtStore::Cache) {
+      let cache = cache_path(&config.paths, youtube_id);
+      if let Some(parent) = cache.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      std::fs::copy(&cover, cache)?;
+    }
+    Ok(())
+  }
+  .await;
+  let _ = std::fs::remove_dir_all(&directory);
+  result
+}
+
+/// Attach the cover to the album of the song, or to the song if it has no album, in place of the
+/// cover attached before
+fn attach_cover(music_dir: &Path, database: &mut Database, song: &Song, cover: &Path) -> Result<()> {
+  let album = database.get_album_for_song(song.id)?;
+  let (attachments, owner) = match album {
+    Some(album) => (database.get_album_attachments(album.id)?, AttachmentOwner::Album(album)),
+    None => (database.get_song_attachments(song.id)?, AttachmentOwner::Song(song.clone())),
+  };
+  let attached = attachments.into_iter().find(|attachment| {
+    attachment.kind == AttachmentKind::Cover
+      && Path::new(&attachment.relative_path).file_name().is_some_and(|name| name == COVER_FILE)
+  });
+  match attached {
+    Some(attachment) => {
+      std::fs::copy(cover, music_dir.join(attachment.relative_path))?;
+    },
+    None => {
+      attachments::attach(music_dir, database, &owner, cover)?;
+    },
+  }
+  Ok(())
+}
+
+/// Fetch the art of every song again, to be run as a job. The songs whose art is at the resolution
+/// of the settings already are left alone
+pub async fn refetch_art(config: Config, context: JobContext) -> Result<()> {
+  if config.config.download.art.store.is_empty() {
+    return Err(eyre!("the art is not kept anywhere, set `store` in the `download.art` settings"));
+  }
+  let resolution = config.config.download.art.resolution;
+  let client = reqwest::Client::new();
+  let mut database = Database::new(config.clone()).await?;
+  let songs: Vec<_> = database
+    .get_all_songs()?
+    .into_iter()
+    .filter(|song| {
+      let Some(youtube_id) = song.youtube_id.as_deref() else {
+        return false;
+      };
+      song.thumbnail_url.as_ref() != thumbnail_urls(youtube_id, resolution).first()
+    })
+    .collect();
+  let total = songs.len() as u64;
+  let mut fetched = 0;
+  for (index, song) in songs.into_iter().enumerate() {
+    context.pace().await;
+    match update_art(&client, &config, &mut database, &song).await {
+      Ok(Some(_)) => fetched += 1,
+      Ok(None) => context.log(format!("{} has no art under the size limit", song.title)),
+      Err(e) => context.log(format!("failed to fetch the art of {}: {e}", song.title)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  context.log(format!("fetched the art of {fetched} of {total} songs at up to {resolution}"));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewSong, SongAlbum},
+  };
+
+  #[test]
+  fn test_thumbnail_urls() {
+    assert_eq!(thumbnail_urls("a51VH9BYzZA", ArtResolution::High), vec![
+      "https://i.ytimg.com/vi/a51VH9BYzZA/sddefault.jpg",
+      "https://i.ytimg.com/vi/a51VH9BYzZA/hqdefault.jpg",
+      "https://i.ytimg.com/vi/a51VH9BYzZA/mqdefault.jpg",
+    ]);
+    assert_eq!(thumbnail_urls("a51VH9BYzZA", ArtResolution::Low), vec![
+      "https://i.ytimg.com/vi/a51VH9BYzZA/mqdefault.jpg"
+    ]);
+  }
+
+  #[test]
+  fn test_attach_cover() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-art-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    let cover = music_dir.join(COVER_FILE);
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.insert_song_album(SongAlbum { song_id, album_id })?;
+    let song = database.get_song_from_id(song_id)?;
+
+    std::fs::write(&cover, "small")?;
+    attach_cover(&music_dir, &mut database, &song, &cover)?;
+    // fetching the art again replaces the cover rather than attaching another one
+    std::fs::write(&cover, "large")?;
+    attach_cover(&music_dir, &mut database, &song, &cover)?;
+    let attachments = database.get_album_attachments(album_id)?;
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].relative_path, "Attachments/Still Still Stellar/cover.jpg");
+    assert_eq!(std::fs::read_to_string(music_dir.join(&attachments[0].relative_path))?, "large");
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
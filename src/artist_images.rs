@@ -0,0 +1,169 @@
+//! Pictures of artists
+//!
+//! When `enrichment.artist_images` is on, the picture of an artist is looked up on Deezer by its
+//! name and kept in the cache dir along with the cover art. The artist pane of the Manager fetches
+//! the picture of the artist of its tab if it is missing, and a job fetches the missing pictures of
+//! every artist. Terminals that draw images inline, iTerm2 and WezTerm, show the picture with the
+//! inline image protocol of iTerm2, the others only tell whether there is one.
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use bytes::Bytes;
+use color_eyre::eyre::{eyre, Result};
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+  action::Action, config::Config, database::Database, enrichment::RateLimiter, import::matching::normalize,
+  jobs::JobContext, paths::Paths,
+};
+
+const SEARCH_URL: &str = "https://api.deezer.com/search/artist";
+/// Deezer allows 50 requests every 5 seconds
+const REQUESTS_PER_MINUTE: u32 = 300;
+
+#[derive(Debug, Deserialize)]
+struct DeezerSearch {
+  #[serde(default)]
+  data: Vec<DeezerArtist>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeezerArtist {
+  name: String,
+  /// 500x500
+  picture_big: Option<String>,
+}
+
+/// Where the picture of the artist is cached, `None` if the name has nothing to name a file by
+pub fn cache_path(paths: &Paths, artist: &str) -> Option<PathBuf> {
+  let name = normalize(artist).replace(' ', "-");
+  (!name.is_empty()).then(|| paths.cache_dir.join("artists").join(format!("{name}.jpg")))
+}
+
+/// The URL of the picture of the artist among the results of a Deezer search. Only an artist of
+/// the same name counts, and Deezer gives a placeholder to the artists without a picture
+fn picture_url(json: &str, artist: &str) -> Result<Option<String>> {
+  let search: DeezerSearch = serde_json::from_str(json)?;
+  let name = normalize(artist);
+  Ok(
+    search
+      .data
+      .into_iter()
+      .find(|found| normalize(&found.name) == name)
+      .and_then(|found| found.picture_big)
+      .filter(|url| !url.contains("/artist//")),
+  )
+}
+
+/// Look the picture of the artist up on Deezer
+///
+/// # Returns
+///
+/// * `None` if Deezer has no picture of the artist
+pub async fn fetch(client: &reqwest::Client, artist: &str) -> Result<Option<Bytes>> {
+  let json = client.get(SEARCH_URL).query(&[("q", artist)]).send().await?.error_for_status()?.text().await?;
+  let Some(url) = picture_url(&json, artist)? else {
+    return Ok(None);
+  };
+  Ok(Some(client.get(url).send().await?.error_for_status()?.bytes().await?))
+}
+
+/// Fetch the picture of the artist into the cache dir
+///
+/// # Returns
+///
+/// * whether the artist has a picture
+pub async fn update_image(client: &reqwest::Client, paths: &Paths, artist: &str) -> Result<bool> {
+  let path = cache_path(paths, artist).ok_or_else(|| eyre!("{artist:?} has no name to cache its picture by"))?;
+  let Some(bytes) = fetch(client, artist).await? else {
+    return Ok(false);
+  };
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  std::fs::write(path, bytes)?;
+  Ok(true)
+}
+
+/// Fetch the pictures of the artists that have none cached, every artist if `artists` is `None`,
+/// to be run as a job
+pub async fn fetch_artist_images(
+  config: Config,
+  artists: Option<Vec<String>>,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  if !config.config.enrichment.artist_images {
+    return Err(eyre!("the pictures of artists are off, set `artist_images` in the `enrichment` settings"));
+  }
+  let artists = match artists {
+    Some(artists) => artists,
+    None => Database::new(config.clone()).await?.get_all_artists()?.into_iter().map(|artist| artist.name).collect(),
+  };
+  let artists: Vec<_> =
+    artists.into_iter().filter(|artist| cache_path(&config.paths, artist).is_some_and(|path| !path.exists())).collect();
+  let client = reqwest::Client::new();
+  let rate_limiter = RateLimiter::per_minute(REQUESTS_PER_MINUTE);
+  let total = artists.len() as u64;
+  let mut fetched = 0;
+  for (index, artist) in artists.iter().enumerate() {
+    if context.is_cancelled() {
+      break;
+    }
+    rate_limiter.wait().await;
+    match update_image(&client, &config.paths, artist).await {
+      Ok(true) => fetched += 1,
+      Ok(false) => context.log(format!("Deezer has no picture of {artist}")),
+      Err(e) => context.log(format!("failed to fetch the picture of {artist}: {e}")),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  context.log(format!("fetched the pictures of {fetched} of {total} artists"));
+  action_tx.send(Action::ArtistImagesFetched)?;
+  Ok(())
+}
+
+/// Whether the terminal draws images sent with the inline image protocol of iTerm2
+pub fn inline_images_supported() -> bool {
+  let var = |name| std::env::var(name).unwrap_or_default();
+  // tmux and screen do not pass the images through
+  var("TMUX").is_empty()
+    && !var("TERM").starts_with("screen")
+    && (matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm") || var("LC_TERMINAL") == "iTerm2")
+}
+
+/// The escape sequence drawing the image over the given number of cells, keeping its aspect ratio
+pub fn inline_image(bytes: &[u8], width: u16, height: u16) -> String {
+  let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+  format!("\x1b]1337;File=inline=1;size={};width={width};height={height};preserveAspectRatio=1:{data}\x07", bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_picture_url() -> Result<()> {
+    let json = r#"{ "data": [
+      { "name": "Suisei Cover Band", "picture_big": "https://e-cdns-images.dzcdn.net/images/artist/1/500x500.jpg" },
+      { "name": "Hoshimachi Suisei", "picture_big": "https://e-cdns-images.dzcdn.net/images/artist/2/500x500.jpg" },
+      { "name": "YOASOBI", "picture_big": "https://e-cdns-images.dzcdn.net/images/artist//500x500.jpg" }
+    ] }"#;
+    assert_eq!(
+      picture_url(json, "hoshimachi suisei")?.as_deref(),
+      Some("https://e-cdns-images.dzcdn.net/images/artist/2/500x500.jpg")
+    );
+    assert_eq!(picture_url(json, "YOASOBI")?, None);
+    assert_eq!(picture_url(json, "LiSA")?, None);
+    assert_eq!(picture_url("{}", "LiSA")?, None);
+
+    let paths = Paths { cache_dir: PathBuf::from("/cache"), ..Default::default() };
+    assert_eq!(cache_path(&paths, "Hoshimachi Suisei"), Some(PathBuf::from("/cache/artists/hoshimachi-suisei.jpg")));
+    assert_eq!(cache_path(&paths, "?!"), None);
+    Ok(())
+  }
+}
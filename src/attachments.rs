@@ -0,0 +1,190 @@
+//! Files attached to songs and albums
+//!
+//! Booklets, lyrics, cover scans and other files that are not songs are copied into the
+//! `Attachments` folder of the music dir, in a folder named after the song or album, so that they
+//! are kept and synced along with the songs. Lyrics attached to a song are also written next to its
+//! file when the playlists are exported, where players such as MPD and Navidrome look for them.
+
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{
+  database::Database,
+  models::{Album, Attachment, AttachmentKind, NewAttachment, Song},
+  utils::safe_file_name,
+};
+
+/// The folder of the music dir the attachments are copied into
+pub const ATTACHMENTS_DIR: &str = "Attachments";
+
+/// What a file is attached to
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachmentOwner {
+  Song(Song),
+  Album(Album),
+}
+
+impl AttachmentOwner {
+  fn name(&self) -> &str {
+    match self {
+      AttachmentOwner::Song(song) => &song.title,
+      AttachmentOwner::Album(album) => &album.name,
+    }
+  }
+}
+
+/// The path from the music dir to copy an attached file to, numbered when the name is taken
+///
+/// # Arguments
+///
+/// * `owner` - the name of the song or album
+/// * `file_name` - the name of the attached file
+pub fn attachment_path(music_dir: &Path, owner: &str, file_name: &str) -> PathBuf {
+  let directory = Path::new(ATTACHMENTS_DIR).join(safe_file_name(owner));
+  let file_name = Path::new(&safe_file_name(file_name)).to_path_buf();
+  let stem = file_name.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+  let extension = file_name.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+  let mut path = directory.join(&file_name);
+  let mut number = 2;
+  while music_dir.join(&path).exists() {
+    path = directory.join(format!("{stem} ({number}){}", extension.as_deref().unwrap_or_default()));
+    number += 1;
+  }
+  path
+}
+
+/// The attachment the file would become, copied to a free path of the music dir
+pub fn plan(music_dir: &Path, owner: &AttachmentOwner, source: &Path) -> Result<NewAttachment> {
+  let file_name = source
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .ok_or_else(|| eyre!("{} is not a file", source.display()))?;
+  if !source.is_file() {
+    return Err(eyre!("{} is not a file", source.display()));
+  }
+  let relative_path = attachment_path(music_dir, owner.name(), &file_name).to_string_lossy().to_string();
+  let kind = AttachmentKind::from_extension(
+    &source.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default(),
+  );
+  let (song_id, album_id) = match owner {
+    AttachmentOwner::Song(song) => (Some(song.id), None),
+    AttachmentOwner::Album(album) => (None, Some(album.id)),
+  };
+  Ok(NewAttachment { song_id, album_id, kind, relative_path })
+}
+
+/// Copy the file into the music dir and attach it to the song or album
+pub fn attach(music_dir: &Path, database: &mut Database, owner: &AttachmentOwner, source: &Path) -> Result<Attachment> {
+  let attachment = plan(music_dir, owner, source)?;
+  let destination = music_dir.join(&attachment.relative_path);
+  if let Some(parent) = destination.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  std::fs::copy(source, &destination)?;
+  match database.insert_attachment(attachment) {
+    Ok(attachment) => Ok(attachment),
+    Err(e) => {
+      let _ = std::fs::remove_file(&destination);
+      Err(e)
+    },
+  }
+}
+
+/// Remove the attachment from the library along with its copy in the music dir
+pub fn detach(music_dir: &Path, database: &mut Database, attachment_id: i32) -> Result<()> {
+  let attachment = database.get_attachment(attachment_id)?.ok_or_else(|| eyre!("no attachment {attachment_id}"))?;
+  database.remove_attachment(attachment_id)?;
+  let path = music_dir.join(&attachment.relative_path);
+  match std::fs::remove_file(&path) {
+    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+    _ => {},
+  }
+  // the folder of the song or album goes once its last attachment is gone
+  if let Some(parent) = path.parent() {
+    let _ = std::fs::remove_dir(parent);
+  }
+  Ok(())
+}
+
+/// Copy the `.lrc` lyrics attached to songs next to their files, named after them. The lyrics
+/// attached last win when a song has several
+///
+/// # Returns
+///
+/// * the number of songs the lyrics were written for
+pub fn write_lyrics(music_dir: &Path, database: &mut Database) -> Result<usize> {
+  let mut lyrics = BTreeMap::new();
+  for attachment in database.get_all_attachments()? {
+    let is_lrc =
+      Path::new(&attachment.relative_path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("lrc"));
+    if let (Some(song_id), AttachmentKind::Lyrics, true) = (attachment.song_id, attachment.kind, is_lrc) {
+      lyrics.insert(song_id, attachment.relative_path);
+    }
+  }
+  let mut written = 0;
+  for (song_id, relative_path) in lyrics {
+    let Some(song_file) = database.get_song_file(song_id)? else {
+      continue;
+    };
+    std::fs::copy(music_dir.join(relative_path), music_dir.join(song_file).with_extension("lrc"))?;
+    written += 1;
+  }
+  Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewFile, NewSong},
+  };
+
+  #[test]
+  fn test_attach_and_detach() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-attachments-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    let source = music_dir.join("lyrics.lrc");
+    std::fs::write(&source, "[00:01.00]Stellar Stellar")?;
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "a51VH9BYzZA.opus".to_string() })?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      file_id: Some(file_id),
+      ..Default::default()
+    })?;
+    let song = AttachmentOwner::Song(database.get_song_from_id(song_id)?);
+    assert_eq!(plan(&music_dir, &song, &source)?.relative_path, "Attachments/Stellar Stellar/lyrics.lrc");
+    assert!(!music_dir.join("Attachments").exists());
+
+    let first = attach(&music_dir, &mut database, &song, &source)?;
+    let second = attach(&music_dir, &mut database, &song, &source)?;
+    assert_eq!(first.kind, AttachmentKind::Lyrics);
+    assert_eq!(first.relative_path, "Attachments/Stellar Stellar/lyrics.lrc");
+    assert_eq!(second.relative_path, "Attachments/Stellar Stellar/lyrics (2).lrc");
+    assert!(attach(&music_dir, &mut database, &song, &music_dir.join("missing.pdf")).is_err());
+
+    assert_eq!(write_lyrics(&music_dir, &mut database)?, 1);
+    assert_eq!(std::fs::read_to_string(music_dir.join("a51VH9BYzZA.lrc"))?, "[00:01.00]Stellar Stellar");
+
+    detach(&music_dir, &mut database, first.id)?;
+    assert!(!music_dir.join(&first.relative_path).exists());
+    assert_eq!(database.get_song_attachments(song_id)?, vec![second]);
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_attachment_path() {
+    assert_eq!(
+      attachment_path(&std::env::temp_dir(), "THE BOOK: 3", "scan: front.png"),
+      PathBuf::from("Attachments/THE BOOK_ 3/scan_ front.png")
+    );
+  }
+}
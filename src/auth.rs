@@ -0,0 +1,178 @@
+//! Access control for the clients of the daemon connecting over TCP
+//!
+//! Clients on the local socket are trusted. With `daemon.tokens` set, clients from other machines
+//! authenticate with a token first, and can only make the requests the scope of the token allows,
+//! at the rate it allows. Without tokens, only clients on the same machine, connecting from a
+//! loopback address, are accepted, and they can make any request.
+
+use std::{
+  collections::HashMap,
+  net::IpAddr,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::config::TokenConfig;
+
+/// The period the rate limits of tokens apply to
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// What a client may do. Every scope allows what the scopes before it allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+  /// Browse the library and the download queue
+  ReadOnly,
+  /// Also queue songs for download
+  QueueDownloads,
+  /// Also run jobs and stop the daemon
+  Admin,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denied {
+  UnknownToken,
+  Unauthenticated,
+  /// the client is on another machine and the daemon has no tokens to authenticate it with
+  NoTokens,
+  Scope(Scope),
+  RateLimited,
+}
+
+impl std::fmt::Display for Denied {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Denied::UnknownToken => write!(f, "unknown token"),
+      Denied::Unauthenticated => write!(f, "authenticate with a token first"),
+      Denied::NoTokens => write!(f, "clients from other machines need daemon.tokens to be set"),
+      Denied::Scope(scope) => write!(f, "the token does not have the {scope:?} scope"),
+      Denied::RateLimited => write!(f, "too many requests, try again later"),
+    }
+  }
+}
+
+/// The rights of a connected client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Client {
+  /// may do anything, such as clients on the local socket
+  Trusted,
+  Unauthenticated,
+  Token(TokenConfig),
+}
+
+/// The tokens accepted by the daemon and how much they were used lately
+#[derive(Default)]
+pub struct AccessControl {
+  tokens: Vec<TokenConfig>,
+  /// the start of the current window and the requests made in it, by token
+  usage: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl AccessControl {
+  pub fn new(tokens: Vec<TokenConfig>) -> Self {
+    Self { tokens, ..Default::default() }
+  }
+
+  /// The rights of a client connecting over TCP from the address, before it authenticates. Without
+  /// tokens, clients from other machines can never authenticate and are refused
+  pub fn remote_client(&self, address: IpAddr) -> Client {
+    if self.tokens.is_empty() && address.to_canonical().is_loopback() {
+      Client::Trusted
+    } else {
+      Client::Unauthenticated
+    }
+  }
+
+  pub fn authenticate(&self, token: &str) -> Result<Client, Denied> {
+    self.authenticate_matching(|candidate| candidate == token)
+  }
+
+  /// Authenticate with the first token `matches` accepts, for clients proving they know a token
+  /// without sending it
+  pub fn authenticate_matching(&self, matches: impl Fn(&str) -> bool) -> Result<Client, Denied> {
+    if self.tokens.is_empty() {
+      return Err(Denied::NoTokens);
+    }
+    self
+      .tokens
+      .iter()
+      .find(|config| matches(&config.token))
+      .map(|config| Client::Token(config.clone()))
+      .ok_or(Denied::UnknownToken)
+  }
+
+  /// Check that the client may make a request needing the scope, counting it against the rate
+  /// limit of its token
+  pub fn authorize(&self, client: &Client, scope: Scope) -> Result<(), Denied> {
+    let token = match client {
+      Client::Trusted => return Ok(()),
+      Client::Unauthenticated if self.tokens.is_empty() => return Err(Denied::NoTokens),
+      Client::Unauthenticated => return Err(Denied::Unauthenticated),
+      Client::Token(token) => token,
+    };
+    if token.scope < scope {
+      return Err(Denied::Scope(scope));
+    }
+    if let Some(limit) = token.requests_per_minute {
+      let mut usage = self.usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+      let (window_start, count) = usage.entry(token.token.clone()).or_insert((Instant::now(), 0));
+      if window_start.elapsed() >= RATE_WINDOW {
+        *window_start = Instant::now();
+        *count = 0;
+      }
+      if *count >= limit {
+        return Err(Denied::RateLimited);
+      }
+      *count += 1;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, Ipv6Addr};
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  const LAN: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+  const LOOPBACK: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+  #[test]
+  fn test_access_control() {
+    let token =
+      |token: &str, scope, requests_per_minute| TokenConfig { token: token.to_string(), scope, requests_per_minute };
+    let access =
+      AccessControl::new(vec![token("reader", Scope::ReadOnly, Some(2)), token("admin", Scope::Admin, None)]);
+    assert_eq!(access.remote_client(LOOPBACK), Client::Unauthenticated);
+    assert_eq!(access.authorize(&access.remote_client(LAN), Scope::ReadOnly), Err(Denied::Unauthenticated));
+    assert_eq!(access.authenticate("guess"), Err(Denied::UnknownToken));
+
+    let reader = access.authenticate("reader").expect("known token");
+    assert_eq!(access.authorize(&reader, Scope::QueueDownloads), Err(Denied::Scope(Scope::QueueDownloads)));
+    assert_eq!(access.authorize(&reader, Scope::ReadOnly), Ok(()));
+    assert_eq!(access.authorize(&reader, Scope::ReadOnly), Ok(()));
+    assert_eq!(access.authorize(&reader, Scope::ReadOnly), Err(Denied::RateLimited));
+
+    let admin = access.authenticate("admin").expect("known token");
+    assert_eq!(access.authorize(&admin, Scope::Admin), Ok(()));
+  }
+
+  #[test]
+  fn test_access_control_without_tokens() {
+    let access = AccessControl::default();
+    assert_eq!(access.remote_client(LOOPBACK), Client::Trusted);
+    assert_eq!(access.remote_client(IpAddr::V6(Ipv6Addr::LOCALHOST)), Client::Trusted);
+    assert_eq!(access.remote_client(IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped())), Client::Trusted);
+
+    let remote = access.remote_client(LAN);
+    assert_eq!(remote, Client::Unauthenticated);
+    assert_eq!(access.authorize(&remote, Scope::ReadOnly), Err(Denied::NoTokens));
+    assert_eq!(access.authenticate(""), Err(Denied::NoTokens));
+  }
+}
@@ -0,0 +1,175 @@
+//! Checking whether videos can be downloaded before queueing them
+//!
+//! yt-dlp is asked for the id of the video without downloading it. When it fails, its error tells
+//! whether the video is locked in this region, private or removed. Region-locked videos are tried
+//! again through the proxy of the `download` settings when one is set, and downloaded through it.
+
+use std::{fmt, process::Stdio};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::{process::Command, sync::mpsc::UnboundedSender};
+
+use crate::{action::Action, config::Config, jobs::JobContext, queue::DownloadRequest};
+
+/// Whether a video can be downloaded, and why not
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Availability {
+  #[default]
+  Available,
+  /// available through the proxy only
+  ViaProxy,
+  RegionLocked,
+  Private,
+  Removed,
+  /// unavailable for another reason, with the error of yt-dlp
+  Unavailable(String),
+}
+
+impl Availability {
+  pub fn is_available(&self) -> bool {
+    matches!(self, Availability::Available | Availability::ViaProxy)
+  }
+}
+
+impl fmt::Display for Availability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Availability::Available => write!(f, "available"),
+      Availability::ViaProxy => write!(f, "available through the proxy"),
+      Availability::RegionLocked => write!(f, "not available in this region"),
+      Availability::Private => write!(f, "private"),
+      Availability::Removed => write!(f, "removed"),
+      Availability::Unavailable(reason) => write!(f, "unavailable: {reason}"),
+    }
+  }
+}
+
+/// The last error yt-dlp wrote
+fn last_error(stderr: &str) -> &str {
+  stderr.lines().rev().find(|line| line.starts_with("ERROR")).unwrap_or(stderr).trim()
+}
+
+/// Whether yt-dlp failed to reach YouTube rather than to find the video, so that the video may
+/// well be up
+fn is_network_error(stderr: &str) -> bool {
+  let lowercase = last_error(stderr).to_lowercase();
+  ["unable to download webpage", "timed out", "name resolution", "connection", "http error 5"]
+    .iter()
+    .any(|marker| lowercase.contains(marker))
+}
+
+/// Tell why a video is unavailable from the error of yt-dlp
+pub fn classify(stderr: &str) -> Availability {
+  let error = last_error(stderr);
+  let lowercase = error.to_lowercase();
+  if lowercase.contains("your country") || lowercase.contains("geo restrict") || lowercase.contains("in your region") {
+    Availability::RegionLocked
+  } else if lowercase.contains("private video") {
+    Availability::Private
+  } else if lowercase.contains("removed")
+    || lowercase.contains("terminated")
+    || lowercase.contains("no longer available")
+  {
+    Availability::Removed
+  } else {
+    Availability::Unavailable(error.trim_start_matches("ERROR:").trim().to_string())
+  }
+}
+
+/// Ask yt-dlp whether the video can be downloaded, through the proxy if given
+pub async fn probe(request: &DownloadRequest, proxy: Option<&str>) -> Result<Availability> {
+  let mut command = Command::new("yt-dlp");
+  command.args(["--skip-download", "--no-warnings", "--print", "id"]);
+  if let Some(proxy) = proxy {
+    command.args(["--proxy", proxy]);
+  }
+  let output = command.arg(request.url()).stdin(Stdio::null()).kill_on_drop(true).output().await?;
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  match output.status.success() {
+    true => Ok(Availability::Available),
+    false if is_network_error(&stderr) => Err(eyre!("failed to reach YouTube: {}", last_error(&stderr))),
+    false => Ok(classify(&stderr)),
+  }
+}
+
+/// Check whether the video can be downloaded, falling back on the proxy when it is region-locked
+pub async fn check(config: &Config, request: &DownloadRequest) -> Result<Availability> {
+  let availability = probe(request, None).await?;
+  match (&availability, &config.config.download.proxy) {
+    (Availability::RegionLocked, Some(proxy)) if probe(request, Some(proxy)).await?.is_available() => {
+      Ok(Availability::ViaProxy)
+    },
+    _ => Ok(availability),
+  }
+}
+
+/// Check the videos and queue the available ones, to be run as a job. Every video is reported with
+/// its availability
+pub async fn check_and_enqueue(
+  config: Config,
+  requests: Vec<DownloadRequest>,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let total = requests.len() as u64;
+  let mut available = vec![];
+  for (index, mut request) in requests.into_iter().enumerate() {
+    let availability = match check(&config, &request).await {
+      Ok(availability) => availability,
+      Err(e) => {
+        context.log(format!("failed to check {}: {e}", request.title));
+        // let the download tell
+        Availability::Available
+      },
+    };
+    match &availability {
+      Availability::Available => {},
+      Availability::ViaProxy => context.log(format!("{} is {availability}", request.title)),
+      _ => context.log(format!("leaving out {}, it is {availability}", request.title)),
+    }
+    action_tx.send(Action::DownloadAvailability(request.youtube_id.clone(), availability.clone()))?;
+    if availability.is_available() {
+      request.proxy = availability == Availability::ViaProxy;
+      available.push(request);
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  if !available.is_empty() {
+    action_tx.send(Action::DownloadEnqueue(available))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_classify() {
+    assert_eq!(
+      classify(
+        "ERROR: [youtube] abcdefghijk: Video unavailable. The uploader has not made this video available in your \
+         country"
+      ),
+      Availability::RegionLocked
+    );
+    assert_eq!(
+      classify("ERROR: [youtube] abcdefghijk: Private video. Sign in if you've been granted access"),
+      Availability::Private
+    );
+    assert_eq!(
+      classify("WARNING: falling back\nERROR: [youtube] abcdefghijk: Video unavailable. This video has been removed by the uploader"),
+      Availability::Removed
+    );
+    assert_eq!(
+      classify("ERROR: [youtube] abcdefghijk: Sign in to confirm your age"),
+      Availability::Unavailable("[youtube] abcdefghijk: Sign in to confirm your age".to_string())
+    );
+    assert!(is_network_error("ERROR: [youtube] abcdefghijk: Unable to download webpage: timed out"));
+    assert!(!is_network_error("ERROR: [youtube] abcdefghijk: Private video"));
+    assert!(Availability::ViaProxy.is_available());
+    assert!(!Availability::Removed.is_available());
+  }
+}
@@ -0,0 +1,162 @@
+//! Automatic backups of the database
+//!
+//! With `backup.interval` set, a copy of the database is written into the backup directory once a
+//! day or once a week while the application or the daemon runs, and only the newest
+//! `backup.keep` backups are kept. Backups are named after the time they were made, so they can
+//! be listed without opening them.
+
+use std::{
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use chrono::{Local, NaiveDateTime};
+use color_eyre::eyre::Result;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{action::Action, config::Config, database::Database, jobs::JobContext};
+
+/// How often the schedulers check whether a backup is due
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const NAME_PREFIX: &str = "muzik-";
+const NAME_FORMAT: &str = "%Y%m%d-%H%M%S";
+const EXTENSION: &str = "db";
+
+/// A backup of the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+  pub path: PathBuf,
+  pub created_at: NaiveDateTime,
+  /// in bytes
+  pub size: u64,
+}
+
+/// Where the backups are written
+pub fn backup_dir(config: &Config) -> PathBuf {
+  config.config.backup.directory.clone().unwrap_or_else(|| config.paths.data_dir.join("backups"))
+}
+
+/// The backups in the directory, newest first. Other files are ignored
+fn backups_in(directory: &Path) -> Result<Vec<Backup>> {
+  if !directory.exists() {
+    return Ok(vec![]);
+  }
+  let mut backups = vec![];
+  for entry in std::fs::read_dir(directory)? {
+    let path = entry?.path();
+    if path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+      continue;
+    }
+    let created_at = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .and_then(|stem| stem.strip_prefix(NAME_PREFIX))
+      .and_then(|date| NaiveDateTime::parse_from_str(date, NAME_FORMAT).ok());
+    if let Some(created_at) = created_at {
+      let size = std::fs::metadata(&path)?.len();
+      backups.push(Backup { path, created_at, size });
+    }
+  }
+  backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+  Ok(backups)
+}
+
+/// The backups of the database, newest first
+pub fn list_backups(config: &Config) -> Result<Vec<Backup>> {
+  backups_in(&backup_dir(config))
+}
+
+/// Delete the backups older than the newest `keep`
+fn rotate(directory: &Path, keep: usize) -> Result<()> {
+  for backup in backups_in(directory)?.iter().skip(keep) {
+    std::fs::remove_file(&backup.path)?;
+  }
+  Ok(())
+}
+
+/// Back the database up and delete the backups that are no longer kept
+///
+/// # Returns
+///
+/// * the path of the new backup
+pub fn create_backup(config: &Config, database: &mut Database) -> Result<PathBuf> {
+  let directory = backup_dir(config);
+  std::fs::create_dir_all(&directory)?;
+  let name = format!("{NAME_PREFIX}{}.{EXTENSION}", Local::now().format(NAME_FORMAT));
+  let path = directory.join(name);
+  database.backup_into(&path)?;
+  rotate(&directory, config.config.backup.keep.max(1))?;
+  Ok(path)
+}
+
+/// Whether a scheduled backup is due, `false` if backups are not scheduled or the library is kept
+/// in Postgres, which is backed up with pg_dump instead
+pub fn is_due(config: &Config) -> Result<bool> {
+  let Some(interval) = config.config.backup.interval else {
+    return Ok(false);
+  };
+  if config.config.database.url.is_some() {
+    return Ok(false);
+  }
+  let due = match list_backups(config)?.first() {
+    Some(latest) => Local::now().naive_local() - latest.created_at >= interval.period(),
+    None => true,
+  };
+  Ok(due)
+}
+
+/// Back the database up, to be run as a job
+///
+/// # Arguments
+///
+/// * `action_tx` - receives the backups once the new one is written, if given
+pub async fn run_backup(config: Config, action_tx: Option<UnboundedSender<Action>>, context: JobContext) -> Result<()> {
+  let mut database = Database::new(config.clone()).await?;
+  let path = create_backup(&config, &mut database)?;
+  context.log(format!("backed the database up into {}", path.display()));
+  if let Some(action_tx) = action_tx {
+    action_tx.send(Action::BackupsUpdate(list_backups(&config)?))?;
+  }
+  Ok(())
+}
+
+/// Replace the database with a backup. The current database is backed up first, so the restore
+/// can be undone. Connections opened before keep reading the replaced database until reopened
+pub fn restore(config: &Config, database: &mut Database, backup: &Path) -> Result<()> {
+  create_backup(config, database)?;
+  database.checkpoint()?;
+  let path = Database::path(config);
+  // copied next to the database first, so that it is replaced at once
+  let restoring = path.with_extension("restoring");
+  std::fs::copy(backup, &restoring)?;
+  std::fs::rename(&restoring, &path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_backup_rotation() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-backups-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    // only SQLite databases are backed up
+    let mut database = Database::open_migrated(":memory:", Config::default())?;
+    for name in ["muzik-20240101-120000.db", "muzik-20240103-120000.db", "muzik-20240102-120000.db"] {
+      database.backup_into(&directory.join(name))?;
+    }
+    std::fs::write(directory.join("notes.txt"), "not a backup")?;
+
+    rotate(&directory, 2)?;
+    let backups = backups_in(&directory)?;
+    let dates: Vec<_> = backups.iter().map(|backup| backup.created_at.format("%Y-%m-%d").to_string()).collect();
+    assert_eq!(dates, vec!["2024-01-03", "2024-01-02"]);
+    assert!(backups.iter().all(|backup| backup.size > 0));
+    assert!(directory.join("notes.txt").exists());
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,168 @@
+//! Limits on the size and age of the cache
+//!
+//! Everything in the cache dir can be fetched again, such as cover art and the pictures of artists,
+//! so its files are removed once they are older than `cache.max_age_days`, then the least recently
+//! used ones until the cache fits in `cache.max_size_mb`. A file is used when it is written or read
+//! from the cache, which sets its modification time, as the access time is often not kept. The
+//! schedulers clean the cache every hour, and `muzik cache clean` cleans it on demand.
+
+use std::{
+  fs::File,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use color_eyre::eyre::Result;
+use tracing::debug;
+
+use crate::{config::CacheConfig, jobs::JobContext};
+
+/// How often the schedulers clean the cache
+pub const CLEAN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// The limits that empty the cache
+pub const EVERYTHING: CacheConfig = CacheConfig { max_size_mb: Some(0), max_age_days: Some(0) };
+
+/// A file of the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFile {
+  pub path: PathBuf,
+  /// in bytes
+  pub size: u64,
+  pub used_at: SystemTime,
+}
+
+/// The files removed from the cache and what is left of it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cleaned {
+  pub removed: Vec<CachedFile>,
+  /// the size of the cache left, in bytes
+  pub size: u64,
+}
+
+impl Cleaned {
+  /// The bytes freed by the removed files
+  pub fn freed(&self) -> u64 {
+    self.removed.iter().map(|file| file.size).sum()
+  }
+}
+
+/// Mark the cached file as used just now, so that it is kept over the files unused for longer
+pub fn touch(path: &Path) {
+  if let Err(e) = File::options().write(true).open(path).and_then(|file| file.set_modified(SystemTime::now())) {
+    debug!("failed to mark {} as used: {e}", path.display());
+  }
+}
+
+/// Every file under the directory, least recently used first
+pub fn cached_files(cache_dir: &Path) -> Result<Vec<CachedFile>> {
+  let mut files = vec![];
+  let mut directories = vec![cache_dir.to_path_buf()];
+  while let Some(directory) = directories.pop() {
+    let entries = match std::fs::read_dir(&directory) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+      Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+      let entry = entry?;
+      let metadata = entry.metadata()?;
+      if metadata.is_dir() {
+        directories.push(entry.path());
+      } else if metadata.is_file() {
+        files.push(CachedFile { path: entry.path(), size: metadata.len(), used_at: metadata.modified()? });
+      }
+    }
+  }
+  files.sort_by(|left, right| left.used_at.cmp(&right.used_at).then_with(|| left.path.cmp(&right.path)));
+  Ok(files)
+}
+
+/// The files to remove for the cache to be within the limits: those older than the maximum age,
+/// then the least recently used until the rest fits in the maximum size
+///
+/// # Arguments
+///
+/// * `files` - least recently used first, as listed by [`cached_files`]
+pub fn plan(files: Vec<CachedFile>, settings: &CacheConfig, now: SystemTime) -> Cleaned {
+  let max_age = settings.max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+  let max_size = settings.max_size_mb.map(|size| size * 1024 * 1024);
+  let mut size: u64 = files.iter().map(|file| file.size).sum();
+  let mut removed = vec![];
+  for file in files {
+    let age = now.duration_since(file.used_at).unwrap_or_default();
+    if max_age.is_some_and(|max_age| age > max_age) || max_size.is_some_and(|max_size| size > max_size) {
+      size -= file.size;
+      removed.push(file);
+    }
+  }
+  Cleaned { removed, size }
+}
+
+/// Remove the files of the cache over the limits
+pub fn clean(cache_dir: &Path, settings: &CacheConfig) -> Result<Cleaned> {
+  let cleaned = plan(cached_files(cache_dir)?, settings, SystemTime::now());
+  for file in &cleaned.removed {
+    match std::fs::remove_file(&file.path) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+      _ => {},
+    }
+  }
+  Ok(cleaned)
+}
+
+/// Clean the cache, to be run as a job
+pub async fn run_clean(cache_dir: PathBuf, settings: CacheConfig, context: JobContext) -> Result<()> {
+  let cleaned = tokio::task::spawn_blocking(move || clean(&cache_dir, &settings)).await??;
+  if !cleaned.removed.is_empty() {
+    context.log(format!(
+      "removed {} files from the cache, freeing {} KB",
+      cleaned.removed.len(),
+      cleaned.freed().div_ceil(1024)
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_clean() -> Result<()> {
+    let cache_dir = std::env::temp_dir().join(format!("muzik-cache-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(cache_dir.join("art"))?;
+    std::fs::create_dir_all(cache_dir.join("artists"))?;
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 60 * 60);
+    for (path, size, days) in [("art/old.jpg", 10, 40), ("art/a.jpg", 600 * 1024, 3), ("artists/b.jpg", 600 * 1024, 1)]
+    {
+      let path = cache_dir.join(path);
+      std::fs::write(&path, vec![0; size])?;
+      File::options().write(true).open(&path)?.set_modified(now - day * days)?;
+    }
+
+    let names = |files: &[CachedFile]| -> Vec<String> {
+      files.iter().map(|file| file.path.file_name().unwrap_or_default().to_string_lossy().to_string()).collect()
+    };
+    let files = cached_files(&cache_dir)?;
+    assert_eq!(names(&files), vec!["old.jpg", "a.jpg", "b.jpg"]);
+    let settings = CacheConfig { max_size_mb: Some(1), max_age_days: Some(30) };
+    let planned = plan(files.clone(), &settings, now);
+    assert_eq!(names(&planned.removed), vec!["old.jpg", "a.jpg"]);
+    assert_eq!(planned.size, 600 * 1024);
+    assert_eq!(plan(files, &CacheConfig { max_size_mb: None, max_age_days: None }, now).removed, vec![]);
+
+    // a file read again is kept over the others
+    touch(&cache_dir.join("art/a.jpg"));
+    let cleaned = clean(&cache_dir, &settings)?;
+    assert_eq!(names(&cleaned.removed), vec!["old.jpg", "b.jpg"]);
+    assert_eq!(cleaned.freed(), 10 + 600 * 1024);
+    assert_eq!(names(&cached_files(&cache_dir)?), vec!["a.jpg"]);
+    assert_eq!(clean(&cache_dir, &EVERYTHING)?.size, 0);
+    assert_eq!(cached_files(&cache_dir)?, vec![]);
+    std::fs::remove_dir_all(&cache_dir)?;
+    Ok(())
+  }
+}
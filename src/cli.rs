@@ -1,21 +1,375 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
 
-use crate::utils::version;
+use crate::{
+  config::{DuplicatePolicy, PlaylistPaths},
+  errors::ErrorCategory,
+  ipc::Remote,
+  listing::ListFormat,
+  models::{MetadataSource, ReleaseType, SongField},
+  report::{ReportFormat, ReportPeriod},
+  utils::version,
+};
 
 #[derive(Parser, Debug)]
-#[command(author, version = version(), about)]
+#[command(author, version = version(), about, after_help = ErrorCategory::help())]
 pub struct Cli {
-  #[arg(short, long, value_name = "FLOAT", help = "Tick rate, i.e. number of ticks per second", default_value_t = 4.0)]
-  pub tick_rate: f64,
+  #[arg(
+    short,
+    long,
+    value_name = "FLOAT",
+    help = "Tick rate, i.e. number of ticks per second [default: 4, 1 in Termux]"
+  )]
+  pub tick_rate: Option<f64>,
 
   #[arg(
     short,
     long,
     value_name = "FLOAT",
-    help = "Frame rate, i.e. number of frames per second",
-    default_value_t = 24.0
+    help = "Frame rate, i.e. number of frames per second [default: 24, 8 in Termux]"
+  )]
+  pub frame_rate: Option<f64>,
+
+  #[arg(long, global = true, help = "Write errors to stderr as a JSON object with a category and an exit code")]
+  pub json_errors: bool,
+
+  #[arg(
+    long,
+    global = true,
+    help = "Print what the command would change in the library and the music dir, without changing anything"
+  )]
+  pub dry_run: bool,
+
+  #[arg(
+    long,
+    global = true,
+    value_name = "HOST:PORT",
+    help = "Use the library of the daemon listening on this address instead of the local one"
+  )]
+  pub remote: Option<String>,
+
+  #[arg(
+    long,
+    global = true,
+    env = "MUZIK_TOKEN",
+    hide_env_values = true,
+    help = "The token to authenticate with the remote daemon, if it requires one"
+  )]
+  pub token: Option<String>,
+
+  #[arg(
+    long,
+    global = true,
+    value_name = "FILE",
+    help = "Connect to the remote daemon over TLS, trusting it if it presents this certificate"
   )]
-  pub frame_rate: f64,
+  pub remote_certificate: Option<PathBuf>,
+
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+impl Cli {
+  /// The daemon on another machine to use, if any
+  pub fn remote(&self) -> Option<Remote> {
+    let address = self.remote.clone()?;
+    Some(Remote { address, token: self.token.clone(), certificate: self.remote_certificate.clone() })
+  }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Write a summary of the new songs, most played songs and failed downloads of a period into the
+  /// data dir
+  Report {
+    #[arg(short, long, value_enum, default_value_t)]
+    period: ReportPeriod,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: ReportFormat,
+
+    #[arg(short, long, value_name = "YYYY-MM-DD", help = "A day in the period to report on [default: today]")]
+    date: Option<NaiveDate>,
+  },
+
+  /// Add the songs in the music dir missing from the library, printing what became of every file
+  /// that was imported or left out
+  Scan {
+    #[arg(help = "The files to scan again, from the music dir [default: the whole music dir]")]
+    paths: Vec<PathBuf>,
+  },
+  /// Check the library for missing files, dead videos, duplicates, files missing from the library
+  /// and missing metadata or art, and print what to do about them
+  Health,
+  /// List the songs in the library
+  List {
+    #[arg(
+      short,
+      long,
+      help = "Only list the songs matching a query, such as 'artist:\"Suisei\" AND year>=2021 AND genre:JPop'"
+    )]
+    query: Option<String>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+  },
+  /// List the songs with the text in their title or artist names, the songs of an artist named
+  /// exactly as searched first
+  Search {
+    text: String,
+
+    #[command(flatten)]
+    output: OutputArgs,
+  },
+  /// Download the songs of a video or a playlist into the library without starting the TUI. While
+  /// a daemon runs, they are queued on it instead
+  Download {
+    #[arg(help = "The URL of a video or a playlist, or text to search YouTube for and download the first result of")]
+    target: String,
+
+    #[arg(long, help = "The title of the song [default: the track or the title of the video]")]
+    title: Option<String>,
+
+    #[arg(
+      long = "artist",
+      value_name = "ARTIST",
+      help = "An artist of the songs, repeated for more [default: the artist or the channel of the video]"
+    )]
+    artists: Vec<String>,
+
+    #[arg(long, help = "The album of the songs [default: the album of the video]")]
+    album: Option<String>,
+
+    #[arg(
+      long,
+      value_enum,
+      help = "What to do with a song in the library already [default: `download.duplicates` in the config, skipping instead of asking]"
+    )]
+    duplicates: Option<DuplicatePolicy>,
+  },
+  /// Download a DJ mix and split it into its tracks, adding them to an album named after the mix
+  SplitMix {
+    #[arg(help = "The id or URL of the video of the mix")]
+    video: String,
+
+    #[arg(
+      short,
+      long,
+      value_name = "FILE",
+      help = "The timestamped tracklist, one '4:51 Artist - Title' per line, or - to read it from stdin"
+    )]
+    tracklist: PathBuf,
+
+    #[arg(short, long, help = "The name of the album [default: the title of the video]")]
+    album: Option<String>,
+  },
+  /// Set what kind of release an album is, or clear it when no type is given
+  AlbumType {
+    #[arg(help = "The name of the album")]
+    album: String,
+
+    #[arg(value_enum)]
+    release_type: Option<ReleaseType>,
+  },
+  /// Lock a metadata field of a song corrected by hand, so that enrichment and re-tagging leave it
+  /// alone
+  Lock {
+    #[arg(help = "The id of the song, as listed by `muzik list`")]
+    song: i32,
+
+    #[arg(value_enum)]
+    field: SongField,
+
+    #[arg(long, help = "Unlock the field instead")]
+    unlock: bool,
+  },
+  /// Show where the metadata fields of a song came from, and the values they replaced
+  Provenance {
+    #[arg(help = "The id of the song, as listed by `muzik list`")]
+    song: i32,
+  },
+  /// Show how the file of a song was downloaded: the format obtained, the formats tried and the
+  /// version of yt-dlp
+  DownloadParams {
+    #[arg(help = "The id of the song, as listed by `muzik list`")]
+    song: i32,
+  },
+  /// Put back the metadata values a source replaced, for when the source turns out to be wrong.
+  /// Locked fields are left alone
+  Revert {
+    #[arg(value_enum)]
+    source: MetadataSource,
+  },
+  /// Copy a file, such as a PDF booklet or .lrc lyrics, into the music dir and attach it to a song
+  /// or an album
+  Attach {
+    file: PathBuf,
+
+    #[arg(long, required_unless_present = "song", conflicts_with = "song", help = "The name of the album")]
+    album: Option<String>,
+
+    #[arg(long, help = "The id of the song, as listed by `muzik list`")]
+    song: Option<i32>,
+  },
+  /// Bring the database up to date with this version of muzik, for when `auto_migrate` is off in
+  /// the config
+  Migrate,
+  /// Download the queued songs and run the scheduled jobs without any UI. A TUI started while the
+  /// daemon runs queues its downloads on the daemon
+  Daemon,
+  /// Tools for working on muzik
+  #[cfg(feature = "debug")]
+  Dev {
+    #[command(subcommand)]
+    command: DevCommand,
+  },
+  /// Manage the cache of cover art and pictures of artists
+  Cache {
+    #[command(subcommand)]
+    command: CacheCommand,
+  },
+  /// Write the playlists for external players
+  Playlists {
+    #[command(subcommand)]
+    command: PlaylistsCommand,
+  },
+  /// Take snapshots of the library and list the songs added, removed and changed since, such as
+  /// around a large import or a bulk edit
+  Snapshots {
+    #[command(subcommand)]
+    command: SnapshotsCommand,
+  },
+  /// Print the completions of the CLI for a shell, such as
+  /// `muzik completions fish > ~/.config/fish/completions/muzik.fish`
+  Completions {
+    #[arg(value_enum)]
+    shell: Shell,
+  },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PlaylistsCommand {
+  /// Write an M3U8 playlist of every genre, artist and rating, one of the whole library and one of
+  /// every playlist stored in the library
+  Export {
+    #[arg(short, long, help = "Where to write the playlists [default: `playlists.directory` in the config]")]
+    directory: Option<PathBuf>,
+
+    #[arg(
+      long,
+      value_enum,
+      help = "How the files of the songs are written [default: `playlists.paths` in the config]"
+    )]
+    paths: Option<PlaylistPaths>,
+  },
+  /// Sync the playlists stored in the library with the M3U playlists of a directory, both ways
+  Sync {
+    #[arg(short, long, help = "The directory of the playlists [default: `playlists.sync_directory` in the config]")]
+    directory: Option<PathBuf>,
+  },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotsCommand {
+  /// Record the songs of the library as they are now, with hashes of their metadata and files
+  Take {
+    #[arg(help = "The name of the snapshot [default: the time it is taken]")]
+    name: Option<String>,
+  },
+  /// List the snapshots, the latest first
+  List,
+  /// List the songs added, removed and changed from a snapshot to another, or to the library now
+  Diff {
+    #[arg(help = "The id of the snapshot to compare from, as listed by `muzik snapshots list`")]
+    from: i32,
+
+    #[arg(help = "The id of the snapshot to compare to [default: the library now]")]
+    to: Option<i32>,
+  },
+  /// Delete a snapshot. The library is left as it is
+  Delete {
+    #[arg(help = "The id of the snapshot, as listed by `muzik snapshots list`")]
+    snapshot: i32,
+  },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+  /// Remove the files of the cache unused for longer than `cache.max_age_days`, then the least
+  /// recently used ones until it fits in `cache.max_size_mb`
+  Clean {
+    #[arg(long, help = "Remove every file of the cache, whatever the limits")]
+    all: bool,
+  },
+}
+
+#[cfg(feature = "debug")]
+#[derive(Subcommand, Debug)]
+pub enum DevCommand {
+  /// Fill the database with made up artists, albums and songs, for UI work and screenshots
+  Seed {
+    #[arg(long, default_value_t = 20, help = "The number of artists to make up")]
+    artists: usize,
+
+    #[arg(long, default_value_t = 0, help = "The same seed makes the same library")]
+    seed: u64,
+
+    #[arg(long, help = "Write a silent audio file into the music dir for every song")]
+    files: bool,
+  },
+}
+
+/// How listed songs are written
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+  #[arg(long, value_enum, default_value_t)]
+  pub format: ListFormat,
+
+  #[arg(long, help = "The maximum number of songs to list")]
+  pub limit: Option<i64>,
+
+  #[arg(long, default_value_t = 0, help = "The number of songs to skip")]
+  pub offset: i64,
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::{CommandFactory, ValueEnum};
+
+  use super::*;
+
+  #[test]
+  fn test_cli() {
+    Cli::command().debug_assert();
+  }
+
+  #[test]
+  fn test_completions() {
+    for shell in Shell::value_variants() {
+      let mut completions = vec![];
+      clap_complete::generate(*shell, &mut Cli::command(), "muzik", &mut completions);
+      let completions = String::from_utf8(completions).expect("completions are utf-8");
+      for subcommand in [
+        "report",
+        "scan",
+        "health",
+        "list",
+        "search",
+        "download",
+        "split-mix",
+        "album-type",
+        "lock",
+        "provenance",
+        "revert",
+        "attach",
+        "daemon",
+        "completions",
+      ] {
+        assert!(completions.contains(subcommand), "{shell} completions are missing {subcommand}");
+      }
+    }
+  }
 }
@@ -0,0 +1,173 @@
+//! Ordering of names in lists
+//!
+//! Names are compared with the collation rules of the configured locale, so accented letters sort
+//! along with their base letter and every script sorts in its own order. Japanese names written in
+//! kana can be sorted by their Hepburn romanization instead, among the names in Latin letters.
+//! Kanji have no single reading, so they are left as they are and sort after the Latin letters.
+
+use std::{borrow::Cow, cmp::Ordering};
+
+use color_eyre::eyre::{eyre, Result};
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use icu_normalizer::DecomposingNormalizer;
+
+use crate::config::CollationConfig;
+
+/// The romanization of the hiragana from U+3041 to U+3096, in order
+const HIRAGANA: [&str; 86] = [
+  "a", "a", "i", "i", "u", "u", "e", "e", "o", "o", "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go", "sa",
+  "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo", "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to",
+  "do", "na", "ni", "nu", "ne", "no", "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu", "he", "be", "pe", "ho",
+  "bo", "po", "ma", "mi", "mu", "me", "mo", "ya", "ya", "yu", "yu", "yo", "yo", "ra", "ri", "ru", "re", "ro", "wa",
+  "wa", "i", "e", "o", "n", "vu", "ka", "ke",
+];
+const SMALL_TSU: char = 'っ';
+const LONG_VOWEL: char = 'ー';
+/// Katakana are placed this far after the hiragana of the same sound
+const KATAKANA_OFFSET: u32 = 0x60;
+
+/// Compares names as the collation settings ask
+pub struct Collation {
+  collator: Collator,
+  romanize: bool,
+}
+
+impl Collation {
+  pub fn new(config: &CollationConfig) -> Result<Self> {
+    let locale = match &config.locale {
+      Some(locale) => locale.parse().map_err(|e| eyre!("invalid collation locale {locale:?}: {e}"))?,
+      None => Locale::UND,
+    };
+    let collator = Collator::try_new(&(&locale).into(), CollatorOptions::new())
+      .map_err(|e| eyre!("no collation rules for {locale}: {e}"))?;
+    Ok(Self { collator, romanize: config.romanize })
+  }
+
+  /// The text the name is sorted and grouped by
+  pub fn key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+    if self.romanize && name.chars().any(|c| hiragana(c).is_some()) {
+      Cow::Owned(romanize(name))
+    } else {
+      Cow::Borrowed(name)
+    }
+  }
+
+  pub fn compare(&self, left: &str, right: &str) -> Ordering {
+    self.collator.compare(&self.key(left), &self.key(right))
+  }
+
+  /// Sort the items by the names they are known by
+  pub fn sort_by_name<T>(&self, items: &mut [T], name: impl Fn(&T) -> &str) {
+    items.sort_by(|left, right| self.compare(name(left), name(right)));
+  }
+
+  /// The heading of the group of the name in an index: its first letter in upper case without
+  /// accents, or `#` for names starting with anything else
+  pub fn group(&self, name: &str) -> String {
+    let first = self.key(name).chars().next();
+    match first {
+      Some(letter) if letter.is_alphabetic() => {
+        let base = DecomposingNormalizer::new_nfd().normalize(&letter.to_string()).chars().next().unwrap_or(letter);
+        base.to_uppercase().to_string()
+      },
+      _ => "#".to_string(),
+    }
+  }
+}
+
+/// The hiragana of the same sound as the kana
+fn hiragana(c: char) -> Option<char> {
+  match c {
+    '\u{3041}'..='\u{3096}' => Some(c),
+    '\u{30a1}'..='\u{30f6}' => char::from_u32(c as u32 - KATAKANA_OFFSET),
+    _ => None,
+  }
+}
+
+/// Spell the kana of the text in Latin letters. A small tsu doubles the next consonant, a small ya,
+/// yu or yo joins the syllable before it, as in `kya` and `sha`, and a long vowel mark repeats the
+/// vowel before it
+fn romanize(text: &str) -> String {
+  let mut romanized = String::new();
+  let mut previous = "";
+  let mut double = false;
+  for c in text.chars() {
+    if c == LONG_VOWEL && !previous.is_empty() {
+      romanized.extend(previous.chars().last());
+      continue;
+    }
+    let Some(kana) = hiragana(c) else {
+      romanized.push(c);
+      previous = "";
+      double = false;
+      continue;
+    };
+    if kana == SMALL_TSU {
+      double = true;
+      continue;
+    }
+    let syllable = HIRAGANA[(kana as u32 - 'ぁ' as u32) as usize];
+    if matches!(kana, 'ゃ' | 'ゅ' | 'ょ') && previous.len() > 1 && previous.ends_with('i') {
+      romanized.pop();
+      // shi, chi and ji lose their i, the others turn it into a y
+      if !(previous.ends_with("hi") && previous != "hi" || previous == "ji") {
+        romanized.push('y');
+      }
+      romanized.push_str(&syllable[1..]);
+    } else {
+      if double {
+        romanized.extend(syllable.chars().next());
+      }
+      romanized.push_str(syllable);
+    }
+    previous = syllable;
+    double = false;
+  }
+  romanized
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn sorted(config: &CollationConfig, names: &[&'static str]) -> Result<Vec<&'static str>> {
+    let mut names = names.to_vec();
+    Collation::new(config)?.sort_by_name(&mut names, |name| name);
+    Ok(names)
+  }
+
+  #[test]
+  fn test_collation() -> Result<()> {
+    let names = ["Zedd", "ヨルシカ", "Ado", "Émilie", "YOASOBI", "aiko", "Åsa"];
+    assert_eq!(sorted(&CollationConfig::default(), &names)?, vec![
+      "Ado",
+      "aiko",
+      "Åsa",
+      "Émilie",
+      "YOASOBI",
+      "Zedd",
+      "ヨルシカ"
+    ]);
+    let swedish = CollationConfig { locale: Some("sv".to_string()), ..Default::default() };
+    assert_eq!(sorted(&swedish, &names)?, vec!["Ado", "aiko", "Émilie", "YOASOBI", "Zedd", "Åsa", "ヨルシカ"]);
+    let romanized = CollationConfig { romanize: true, ..Default::default() };
+    assert_eq!(sorted(&romanized, &names)?, vec!["Ado", "aiko", "Åsa", "Émilie", "YOASOBI", "ヨルシカ", "Zedd"]);
+    assert!(
+      Collation::new(&CollationConfig { locale: Some("not a locale".to_string()), ..Default::default() }).is_err()
+    );
+
+    let collation = Collation::new(&romanized)?;
+    assert_eq!(collation.key("ずっと真夜中でいいのに。"), "zutto真夜中deiinoni。");
+    assert_eq!(collation.key("しゃちょう ジャンキー キャット"), "shachou jankii kyatto");
+    assert_eq!(collation.key("LiSA"), "LiSA");
+    assert_eq!(
+      ["Émilie", "ヨルシカ", "2SIDE", "星街すいせい"].map(|name| collation.group(name)),
+      ["E", "Y", "#", "星"].map(str::to_string)
+    );
+    assert_eq!(Collation::new(&CollationConfig::default())?.group("ヨルシカ"), "ヨ");
+    Ok(())
+  }
+}
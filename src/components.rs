@@ -15,7 +15,10 @@ pub mod download;
 pub mod fps;
 pub mod general;
 pub mod home;
+pub mod jobs;
 pub mod manager;
+#[cfg(feature = "player")]
+pub mod now_playing;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 /// Implementors of this trait can be registered with the main application loop and will be able to receive events,
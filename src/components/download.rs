@@ -1,10 +1,14 @@
 //! This module contains components related to the download mode of the program
 
+use std::{collections::HashMap, time::Duration};
+
 use color_eyre::eyre::Result;
 use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
   layout::{Constraint, Layout},
-  widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+  style::{Color, Style, Stylize},
+  text::{Line, Span},
+  widgets::{block, Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap},
 };
 use tokio::sync::{mpsc::UnboundedSender, oneshot};
 use tracing::{debug, info, trace, warn};
@@ -13,8 +17,12 @@ use youtube_dl::{SearchOptions, SingleVideo, YoutubeDl, YoutubeDlOutput};
 use super::Component;
 use crate::{
   action::{Action, InputIn, InputOut},
-  layouts::{Focus, Scenes},
+  availability::Availability,
+  import::{ImportMatch, MatchStatus},
+  layouts::{DownloadLayouts, Focus, Scenes},
   mode::Mode,
+  quality,
+  queue::{Doubt, DownloadRequest, QueueItem, QueueStatus},
 };
 
 #[derive(Default)]
@@ -70,26 +78,57 @@ impl Component for SearchBar {
   }
 
   fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    // woah that collapsible matching clippy hint was cool af
     match action {
-      // woah that collapsible matching clippy hint was cool af
-      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) => {
-        if input_name == *"youtube_search" {
-          self.search_query = buffer;
-          // we will not be the component that sends the search request
-        }
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"youtube_search" => {
+        // we will not be the component that sends the search request
+        self.search_query = buffer;
       },
+      Action::DownloadSearch(query) => self.search_query = query,
       _ => {},
     }
     Ok(None)
   }
 }
 
+/// The bitrate in kbit/s below which the best audio of a video is shown as poor
+const LOW_BITRATE: u32 = 96;
+
+/// The length of the video, if YouTube tells it
+fn video_duration(video: &SingleVideo) -> Option<Duration> {
+  video.duration.as_ref()?.as_f64().filter(|seconds| *seconds >= 0.0).map(Duration::from_secs_f64)
+}
+
+/// A length as hours, minutes and seconds, leaving out the hours of songs shorter than an hour
+fn clock(length: Duration) -> String {
+  let seconds = length.as_secs();
+  match seconds / 3600 {
+    0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+    hours => format!("{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60),
+  }
+}
+
+/// A view count shortened to thousands, millions or billions, such as 1.2M
+fn views(count: i64) -> String {
+  let count = count.max(0) as f64;
+  match count {
+    count if count >= 1e9 => format!("{:.1}B", count / 1e9),
+    count if count >= 1e6 => format!("{:.1}M", count / 1e6),
+    count if count >= 1e3 => format!("{:.1}K", count / 1e3),
+    count => format!("{count}"),
+  }
+}
+
 #[derive(Default, Debug)]
 pub struct SearchResult {
   search_query: String,
   search_rx: Option<oneshot::Receiver<Result<YoutubeDlOutput, youtube_dl::Error>>>,
   search_result_videos: Option<Vec<SingleVideo>>,
-  search_result_list_state: ListState,
+  search_result_table_state: TableState,
+  /// the availability of the videos checked before queueing, by id
+  availability: HashMap<String, Availability>,
+  /// the results marked to be queued together, kept across searches
+  marked: Vec<DownloadRequest>,
 }
 
 impl SearchResult {
@@ -99,38 +138,104 @@ impl SearchResult {
 
   pub fn list_next(&mut self) {
     if let Some(videos) = &self.search_result_videos {
-      if let Some(index) = self.search_result_list_state.selected() {
+      if let Some(index) = self.search_result_table_state.selected() {
         if index >= videos.len() - 1 {
-          self.search_result_list_state.select(Some(0));
+          self.search_result_table_state.select(Some(0));
         } else {
-          self.search_result_list_state.select(Some(index + 1));
+          self.search_result_table_state.select(Some(index + 1));
         }
         return;
       }
     }
-    self.search_result_list_state.select(Some(0));
+    self.search_result_table_state.select(Some(0));
   }
 
   pub fn previous_list(&mut self) {
     if let Some(videos) = &self.search_result_videos {
-      if let Some(index) = self.search_result_list_state.selected() {
+      if let Some(index) = self.search_result_table_state.selected() {
         if index == 0 {
-          self.search_result_list_state.select(Some(videos.len() - 1));
+          self.search_result_table_state.select(Some(videos.len() - 1));
         } else {
-          self.search_result_list_state.select(Some(index - 1))
+          self.search_result_table_state.select(Some(index - 1))
         }
         return;
       }
     }
-    self.search_result_list_state.select(Some(0));
+    self.search_result_table_state.select(Some(0));
   }
 
   pub fn unselect_list(&mut self) {
-    self.search_result_list_state.select(None);
+    self.search_result_table_state.select(None);
+  }
+
+  /// Start a youtube search for the query in the background
+  fn search(&mut self, query: String) {
+    self.search_query = query;
+    // build the search request
+    let search_query = self.search_query.clone();
+    let (ys_tx, ys_rx) = tokio::sync::oneshot::channel();
+    self.search_rx = Some(ys_rx);
+    tokio::spawn(async move {
+      let youtube_search =
+        YoutubeDl::search_for(&SearchOptions::youtube(search_query).with_count(15)).run_async().await;
+      ys_tx.send(youtube_search).unwrap();
+    });
+    debug!("started youtube search task");
+  }
+
+  /// Mark the selected video to be queued along with the others, or unmark it
+  fn toggle_marked(&mut self) {
+    let Some(request) = self.selected_request() else {
+      return;
+    };
+    match self.marked.iter().position(|marked| marked.youtube_id == request.youtube_id) {
+      Some(index) => {
+        self.marked.remove(index);
+      },
+      None => self.marked.push(request),
+    }
+  }
+
+  /// The request to download the selected video
+  fn selected_request(&self) -> Option<DownloadRequest> {
+    let video = self.search_result_videos.as_ref()?.get(self.search_result_table_state.selected()?)?;
+    Some(DownloadRequest::from_video(video))
+  }
+
+  /// The row of the video: whether it is marked, its title and how it can be downloaded, its
+  /// length, its views and the best audio YouTube has of it
+  fn video_row(&self, video: &SingleVideo) -> Row<'static> {
+    let checkbox = match self.marked.iter().any(|marked| marked.youtube_id == video.id) {
+      true => "[x]",
+      false => "[ ]",
+    };
+    let title = Span::raw(video.title.clone().unwrap_or("Unknown".to_string()));
+    let mark = match self.availability.get(&video.id) {
+      None | Some(Availability::Available) => None,
+      Some(availability @ Availability::ViaProxy) => Some(format!(" ({availability})").yellow()),
+      Some(availability) => Some(format!(" ({availability})").red()),
+    };
+    let title = Line::from([Some(title), mark].into_iter().flatten().collect::<Vec<_>>());
+    let length = video_duration(video).map(clock).unwrap_or_default();
+    let views = video.view_count.map(views).unwrap_or_default();
+    let quality = video.formats.as_deref().and_then(quality::best_audio);
+    let quality = match quality {
+      Some(quality) if quality.bitrate.is_some_and(|bitrate| bitrate < LOW_BITRATE) => {
+        Cell::from(quality.to_string()).yellow()
+      },
+      Some(quality) => Cell::from(quality.to_string()),
+      None => Cell::from("unknown").fg(Color::DarkGray),
+    };
+    Row::new(vec![Cell::from(checkbox), Cell::from(title), Cell::from(length), Cell::from(views), quality])
+  }
+
+  /// The length of the selected search result, if YouTube tells it
+  fn selected_duration(&self) -> Option<Duration> {
+    video_duration(self.search_result_videos.as_ref()?.get(self.search_result_table_state.selected()?)?)
   }
 
   fn get_current_selected_list_youtube_video(&self) -> Option<YoutubeVideo> {
-    if let Some(index) = self.search_result_list_state.selected() {
+    if let Some(index) = self.search_result_table_state.selected() {
       if let Some(videos) = &self.search_result_videos {
         match videos.get(index) {
           Some(video) => return Some(video.to_owned().into()),
@@ -144,12 +249,24 @@ impl SearchResult {
 
 impl Component for SearchResult {
   fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, focus: Focus) -> Result<()> {
-    let divider = Block::default().borders(Borders::RIGHT);
+    let hint = match self.marked.len() {
+      0 => "<space> mark, <l> listen, <Enter> check and queue".to_string(),
+      marked => format!("<space> mark, <Enter> review {marked} marked"),
+    };
+    let divider =
+      Block::default().borders(Borders::RIGHT).title(block::Title::from(hint).position(block::Position::Bottom));
     if let Some(videos) = &self.search_result_videos {
-      let list_item: Vec<_> =
-        videos.iter().map(|e| ListItem::new(e.title.clone().unwrap_or("Unknown".to_string()))).collect();
-      let list = List::new(list_item).highlight_symbol(">>").block(divider);
-      f.render_stateful_widget(list, area, &mut self.search_result_list_state);
+      let rows: Vec<_> = videos.iter().map(|video| self.video_row(video)).collect();
+      let widths = [
+        Constraint::Length(3),
+        Constraint::Min(20),
+        Constraint::Length(7),
+        Constraint::Length(6),
+        Constraint::Length(15),
+      ];
+      let header = Row::new(vec!["", "Title", "Length", "Views", "Best audio"]).bold();
+      let table = Table::new(rows, widths).header(header).highlight_symbol(">>").block(divider);
+      f.render_stateful_widget(table, area, &mut self.search_result_table_state);
     } else {
       f.render_widget(Paragraph::new("Nothing searched yet"), area);
     }
@@ -190,22 +307,12 @@ impl Component for SearchResult {
           }
         }
       },
-      Action::InputModeOff(InputOut { input_name, buffer }) => {
-        if let Some(input_name) = input_name {
-          if input_name == *"youtube_search" {
-            self.search_query = buffer;
-            // build the search request
-            let search_query = self.search_query.clone();
-            let (ys_tx, ys_rx) = tokio::sync::oneshot::channel();
-            self.search_rx = Some(ys_rx);
-            tokio::spawn(async move {
-              let youtube_search =
-                YoutubeDl::search_for(&SearchOptions::youtube(search_query).with_count(15)).run_async().await;
-              ys_tx.send(youtube_search).unwrap();
-            });
-            debug!("started youtube search task");
-          };
-        }
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"youtube_search" => {
+        self.search(buffer);
+      },
+      Action::DownloadSearch(query) => self.search(query),
+      Action::DownloadAvailability(youtube_id, availability) => {
+        self.availability.insert(youtube_id, availability);
       },
       _ => {},
     }
@@ -215,6 +322,20 @@ impl Component for SearchResult {
   fn handle_key_events(&mut self, key: crossterm::event::KeyEvent, focus: Focus) -> Result<Option<Action>> {
     if self.is_focused(focus) && key.modifiers == KeyModifiers::NONE {
       match key.code {
+        KeyCode::Enter if !self.marked.is_empty() => {
+          return Ok(Some(Action::DownloadConfirm(std::mem::take(&mut self.marked))));
+        },
+        KeyCode::Enter => {
+          if let Some(request) = self.selected_request() {
+            return Ok(Some(Action::DownloadCheck(vec![request])));
+          }
+        },
+        KeyCode::Char(' ') => self.toggle_marked(),
+        KeyCode::Char('l') => {
+          if let Some(request) = self.selected_request() {
+            return Ok(Some(Action::PlayerPreview(request, self.selected_duration())));
+          }
+        },
         KeyCode::Char('j') | KeyCode::Down => {
           self.list_next();
           return Ok(Some(Action::DownloadShowSearchDetails(self.get_current_selected_list_youtube_video())));
@@ -224,7 +345,7 @@ impl Component for SearchResult {
           return Ok(Some(Action::DownloadShowSearchDetails(self.get_current_selected_list_youtube_video())));
         },
         KeyCode::Esc => {
-          if self.search_result_list_state.selected().is_some() {
+          if self.search_result_table_state.selected().is_some() {
             self.unselect_list();
           } else {
             return Ok(Some(Action::FocusBack));
@@ -253,8 +374,12 @@ impl SearchResultDetails {
 impl Component for SearchResultDetails {
   fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, _focus: Focus) -> Result<()> {
     if let Some(video) = &self.selected_search_result {
-      let layout =
-        Layout::new(ratatui::layout::Direction::Vertical, [Constraint::Length(1), Constraint::Min(1)]).split(area);
+      let layout = Layout::new(ratatui::layout::Direction::Vertical, [
+        Constraint::Length(1),
+        Constraint::Length(5),
+        Constraint::Min(1),
+      ])
+      .split(area);
 
       let desc = Paragraph::new("Details").alignment(ratatui::layout::Alignment::Center);
       f.render_widget(desc, layout[0]);
@@ -266,6 +391,12 @@ impl Component for SearchResultDetails {
       let album = ListItem::new(format!("Album: {}", video.album.clone().unwrap_or("Unknown".to_string())));
       let list = List::new([id, title, channel, artist, album]);
       f.render_widget(list, layout[1]);
+
+      let description = video.description.clone().unwrap_or("No description".to_string());
+      let description = Paragraph::new(description)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::TOP).title("Description"));
+      f.render_widget(description, layout[2]);
     } else {
       let placeholder = Paragraph::new("Nothing to display yet");
       f.render_widget(placeholder, area);
@@ -273,24 +404,359 @@ impl Component for SearchResultDetails {
     Ok(())
   }
 
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::DownloadShowSearchDetails(youtube_details) = action {
+      self.selected_search_result = youtube_details;
+    }
+    Ok(None)
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Download(crate::layouts::DownloadLayouts::SearchResultDetails)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Download
+  }
+}
+
+/// Review of the matches found for an imported playlist. Confident matches are accepted by
+/// default, uncertain ones have to be accepted by hand
+#[derive(Default)]
+pub struct ImportReview {
+  /// the matches along with whether they are accepted
+  matches: Vec<(ImportMatch, bool)>,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl ImportReview {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn match_line((import_match, accepted): &(ImportMatch, bool)) -> ListItem<'static> {
+    let track = &import_match.track;
+    let checkbox = if *accepted { "[x]" } else { "[ ]" };
+    let found = match &import_match.candidate {
+      Some(candidate) => format!("{} ({}%)", candidate.title, import_match.score),
+      None => "not found".to_string(),
+    };
+    let style = match import_match.status() {
+      MatchStatus::Confident => Style::default().fg(Color::Green),
+      MatchStatus::Uncertain => Style::default().fg(Color::Yellow),
+      MatchStatus::NotFound => Style::default().fg(Color::DarkGray),
+    };
+    ListItem::new(format!("{checkbox} {} - {} -> {found}", track.artists.join(", "), track.title)).style(style)
+  }
+
+  fn toggle_selected(&mut self) {
+    if let Some((import_match, accepted)) = self.list_state.selected().and_then(|index| self.matches.get_mut(index)) {
+      *accepted = !*accepted && import_match.candidate.is_some();
+    }
+  }
+}
+
+impl Component for ImportReview {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, focus: Focus) -> Result<()> {
+    if self.matches.is_empty() {
+      return Ok(());
+    }
+    let layout =
+      Layout::new(ratatui::layout::Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let accepted = self.matches.iter().filter(|(_, accepted)| *accepted).count();
+    let block =
+      Block::default().borders(Borders::ALL).title(format!("Import ({accepted} of {} accepted)", self.matches.len()));
+    let items: Vec<_> = self.matches.iter().map(Self::match_line).collect();
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    f.render_widget(Paragraph::new("<space> accept/reject, <Enter> queue accepted, <Esc> cancel"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Download(DownloadLayouts::ImportReview)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Download
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
   fn update(&mut self, action: Action) -> Result<Option<Action>> {
     match action {
-      Action::DownloadShowSearchDetails(youtube_details) => {
-        self.selected_search_result = youtube_details;
-        //
+      Action::ImportPlaylist => {
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "import_playlist".to_string(),
+          initial_value: None,
+        })));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"import_playlist" => {
+        return Ok(Some(Action::ImportStart(buffer)));
+      },
+      Action::ImportReview(matches) => {
+        self.matches = matches
+          .into_iter()
+          .map(|import_match| {
+            let accepted = import_match.status() == MatchStatus::Confident;
+            (import_match, accepted)
+          })
+          .collect();
+        self.list_state.select(Some(0));
       },
       _ => {},
     }
     Ok(None)
   }
 
+  fn handle_key_events(&mut self, key: crossterm::event::KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE || self.matches.is_empty() {
+      return Ok(None);
+    }
+    let count = self.matches.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char(' ') => self.toggle_selected(),
+      KeyCode::Enter => {
+        let requests = std::mem::take(&mut self.matches)
+          .into_iter()
+          .filter(|(_, accepted)| *accepted)
+          .filter_map(|(import_match, _)| import_match.download_request())
+          .collect();
+        if let Some(action_tx) = &self.action_tx {
+          action_tx.send(Action::DownloadCheck(requests))?;
+        }
+        return Ok(Some(Action::FocusBack));
+      },
+      KeyCode::Esc => {
+        self.matches.clear();
+        return Ok(Some(Action::FocusBack));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The download queue, with the progress of the download in flight. The newest items are shown
+/// first, as the finished ones pile up
+#[derive(Default)]
+pub struct QueueList {
+  items: Vec<QueueItem>,
+}
+
+impl QueueList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn item_line(item: &QueueItem) -> ListItem<'static> {
+    let (status, style) = match &item.status {
+      QueueStatus::Pending => ("queued".to_string(), Style::default()),
+      QueueStatus::Downloading => {
+        let status = match item.progress {
+          Some(progress) => progress.to_string(),
+          None => "downloading".to_string(),
+        };
+        (status, Style::default().fg(Color::Cyan))
+      },
+      QueueStatus::Finished => ("done".to_string(), Style::default().fg(Color::Green)),
+      QueueStatus::Failed(error) => (format!("failed: {error}"), Style::default().fg(Color::Red)),
+      QueueStatus::Skipped(reason) => (format!("skipped: {reason}"), Style::default().fg(Color::DarkGray)),
+      QueueStatus::Duplicate(title) => (format!("same as {title}"), Style::default().fg(Color::Yellow)),
+      QueueStatus::Review(doubts) => (format!("to review: {doubts}"), Style::default().fg(Color::Yellow)),
+    };
+    ListItem::new(format!("{} ({status})", item.request.title)).style(style)
+  }
+}
+
+impl Component for QueueList {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, _focus: Focus) -> Result<()> {
+    let active =
+      self.items.iter().filter(|item| matches!(item.status, QueueStatus::Pending | QueueStatus::Downloading));
+    let block = Block::default().borders(Borders::TOP).title(format!("Queue ({} left)", active.count()));
+    if self.items.is_empty() {
+      f.render_widget(Paragraph::new("Nothing queued yet").block(block), area);
+    } else {
+      let items: Vec<_> = self.items.iter().rev().map(Self::item_line).collect();
+      f.render_widget(List::new(items).block(block), area);
+    }
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::DownloadProgress(items) = action {
+      self.items = items;
+    }
+    Ok(None)
+  }
+
   fn scene(&self) -> Scenes {
-    Scenes::Download(crate::layouts::DownloadLayouts::SearchResultDetails)
+    Scenes::Download(DownloadLayouts::Queue)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Download
+  }
+}
+
+/// Confirmation of the metadata of the search results queued together. Every result is kept by
+/// default, its title, artists and album can be corrected before it is queued
+#[derive(Default)]
+pub struct QueueConfirm {
+  /// the requests along with whether they are kept
+  requests: Vec<(DownloadRequest, bool)>,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl QueueConfirm {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn request_line((request, kept): &(DownloadRequest, bool)) -> ListItem<'static> {
+    let checkbox = if *kept { "[x]" } else { "[ ]" };
+    let album = request.album.as_ref().map(|album| format!(" [{album}]")).unwrap_or_default();
+    let line = format!("{checkbox} {} - {}{album}", request.artists.join(", "), request.title);
+    match kept {
+      true => ListItem::new(line),
+      false => ListItem::new(line).style(Style::default().fg(Color::DarkGray)),
+    }
+  }
+
+  fn selected(&mut self) -> Option<&mut (DownloadRequest, bool)> {
+    self.list_state.selected().and_then(|index| self.requests.get_mut(index))
+  }
+
+  /// Prompt for a field of the selected request, starting from its value
+  fn edit(&self, input_name: &str, value: impl FnOnce(&DownloadRequest) -> String) -> Option<Action> {
+    let (request, _) = self.list_state.selected().and_then(|index| self.requests.get(index))?;
+    Some(Action::InputModeOn(InputIn { input_name: input_name.to_string(), initial_value: Some(value(request)) }))
+  }
+}
+
+impl Component for QueueConfirm {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, focus: Focus) -> Result<()> {
+    if self.requests.is_empty() {
+      return Ok(());
+    }
+    let layout =
+      Layout::new(ratatui::layout::Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let kept = self.requests.iter().filter(|(_, kept)| *kept).count();
+    let block = Block::default().borders(Borders::ALL).title(format!("Queue ({kept} of {})", self.requests.len()));
+    let items: Vec<_> = self.requests.iter().map(Self::request_line).collect();
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    f.render_widget(
+      Paragraph::new("<space> keep/drop, <t>itle, <a>rtists, a<l>bum, <Enter> check and queue, <Esc> cancel"),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Download(DownloadLayouts::QueueConfirm)
   }
 
   fn mode(&self) -> Mode {
     Mode::Download
   }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::DownloadConfirm(requests) => {
+        self.requests = requests.into_iter().map(|request| (request, true)).collect();
+        self.list_state.select(Some(0));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) => {
+        let value = buffer.trim().to_string();
+        let Some((request, _)) = self.selected() else {
+          return Ok(None);
+        };
+        // a field set by hand is no longer a guess
+        let corrected = match input_name.as_str() {
+          "confirm_title" if !value.is_empty() => {
+            request.title = value;
+            Doubt::TitleFromVideo
+          },
+          "confirm_artists" => {
+            request.artists =
+              value.split(',').map(str::trim).filter(|artist| !artist.is_empty()).map(str::to_string).collect();
+            Doubt::ArtistFromChannel
+          },
+          "confirm_album" => {
+            request.album = Some(value).filter(|album| !album.is_empty());
+            Doubt::NoAlbum
+          },
+          _ => return Ok(None),
+        };
+        request.doubts.retain(|doubt| *doubt != corrected);
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: crossterm::event::KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE || self.requests.is_empty() {
+      return Ok(None);
+    }
+    let count = self.requests.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char(' ') => {
+        if let Some((_, kept)) = self.selected() {
+          *kept = !*kept;
+        }
+      },
+      KeyCode::Char('t') => return Ok(self.edit("confirm_title", |request| request.title.clone())),
+      KeyCode::Char('a') => return Ok(self.edit("confirm_artists", |request| request.artists.join(", "))),
+      KeyCode::Char('l') => {
+        return Ok(self.edit("confirm_album", |request| request.album.clone().unwrap_or_default()));
+      },
+      KeyCode::Enter => {
+        let requests = std::mem::take(&mut self.requests)
+          .into_iter()
+          .filter(|(_, kept)| *kept)
+          .map(|(request, _)| request)
+          .collect();
+        if let Some(action_tx) = &self.action_tx {
+          action_tx.send(Action::DownloadCheck(requests))?;
+        }
+        return Ok(Some(Action::FocusBack));
+      },
+      KeyCode::Esc => {
+        self.requests.clear();
+        return Ok(Some(Action::FocusBack));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
 }
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
@@ -301,6 +767,8 @@ pub struct YoutubeVideo {
   album: Option<String>,
   artist: Option<String>,
   genre: Option<String>,
+  /// often credits the composer, lyricist and arranger
+  description: Option<String>,
 }
 
 impl From<SingleVideo> for YoutubeVideo {
@@ -312,6 +780,7 @@ impl From<SingleVideo> for YoutubeVideo {
       album: value.album,
       artist: value.artist,
       genre: value.genre,
+      description: value.description,
     }
   }
 }
@@ -1,22 +1,37 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
 use color_eyre::{eyre::Result, owo_colors::OwoColorize};
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{
-  layout::Rect,
-  style::{Color, Style},
-  widgets::{Block, Borders, Paragraph, Wrap},
+  prelude::*,
+  widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
 use tokio::sync::mpsc::UnboundedSender;
 
 use super::Component;
 use crate::{
   action::{Action, InputIn, InputOut},
+  keymap::{self, Binding},
   layouts::{Focus, Scenes},
   mode::Mode,
+  mpd::{MpdState, MpdStatus},
   tui::Frame,
 };
 
+/// How long a notification stays in the title bar
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(5);
+
 #[derive(Default)]
-pub struct TitleBar {}
+pub struct TitleBar {
+  /// notifications waiting to be shown, the first one is currently shown
+  notifications: VecDeque<String>,
+  shown_at: Option<Instant>,
+  /// what MPD plays, if it is set
+  mpd: Option<MpdStatus>,
+}
 
 impl TitleBar {
   pub fn new() -> Self {
@@ -26,11 +41,44 @@ impl TitleBar {
 
 impl Component for TitleBar {
   fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: ratatui::prelude::Rect, _focus: Focus) -> Result<()> {
-    let title = Paragraph::new("muzik-tui").alignment(ratatui::layout::Alignment::Left).wrap(Wrap { trim: true });
+    let mut text = "muzik-tui".to_string();
+    if let Some(status) = self.mpd.as_ref().filter(|status| status.state != MpdState::Stop) {
+      let icon = if status.state == MpdState::Pause { "⏸" } else { "♪" };
+      text.push_str(&format!(" | {icon} {}", status.label().unwrap_or_default()));
+      if let (Some(elapsed), Some(duration)) = (status.elapsed, status.duration) {
+        let clock = |time: Duration| format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60);
+        text.push_str(&format!(" ({} / {})", clock(elapsed), clock(duration)));
+      }
+    }
+    if let Some(notification) = self.notifications.front() {
+      text.push_str(&format!(" | {notification}"));
+      if self.notifications.len() > 1 {
+        text.push_str(&format!(" (+{} more)", self.notifications.len() - 1));
+      }
+    }
+    let title = Paragraph::new(text).alignment(ratatui::layout::Alignment::Left).wrap(Wrap { trim: true });
     f.render_widget(title, area);
     Ok(())
   }
 
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::Notify(notification) => {
+        if self.notifications.is_empty() {
+          self.shown_at = Some(Instant::now());
+        }
+        self.notifications.push_back(notification);
+      },
+      Action::MpdNowPlaying(status) => self.mpd = status,
+      Action::Tick if self.shown_at.is_some_and(|shown_at| shown_at.elapsed() >= NOTIFICATION_DURATION) => {
+        self.notifications.pop_front();
+        self.shown_at = (!self.notifications.is_empty()).then(Instant::now);
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
   fn scene(&self) -> crate::layouts::Scenes {
     Scenes::TitleBar
   }
@@ -100,27 +148,21 @@ impl Component for InputArea {
             buffer: self.input_buffer.clone(),
           })))
         },
-        KeyCode::Right => {
-          if self.position < self.input_buffer.len() {
-            self.position += 1;
-          }
+        KeyCode::Right if self.position < self.input_buffer.len() => {
+          self.position += 1;
         },
-        KeyCode::Left => {
-          if self.position > 0 {
-            self.position -= 1;
-          }
+        KeyCode::Left if self.position > 0 => {
+          self.position -= 1;
         },
-        KeyCode::Backspace => {
-          // out of bounds is a pain
-          if self.position >= 1 {
-            // we cannot remove the end of the string
-            if self.position == self.input_buffer.len() {
-              self.input_buffer.pop();
-            } else {
-              self.input_buffer.remove(self.position - 1);
-            }
-            self.position -= 1;
+        // out of bounds is a pain
+        KeyCode::Backspace if self.position >= 1 => {
+          // we cannot remove the end of the string
+          if self.position == self.input_buffer.len() {
+            self.input_buffer.pop();
+          } else {
+            self.input_buffer.remove(self.position - 1);
           }
+          self.position -= 1;
         },
         KeyCode::Esc => return Ok(Some(Action::InputModeOff(InputOut::default()))),
         _ => {},
@@ -152,3 +194,106 @@ impl Component for InputArea {
     Ok(None)
   }
 }
+
+/// The key bindings of the current mode and the global ones, to bind an action to other keys
+#[derive(Default)]
+pub struct Help {
+  bindings: Vec<Binding>,
+  list_state: ListState,
+  /// the keys pressed so far to bind the selected action to
+  capturing: Option<Vec<KeyEvent>>,
+}
+
+impl Help {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn binding_line(binding: &Binding) -> ListItem<'static> {
+    let keys = keymap::keys_to_string(&binding.keys);
+    let mut line = Line::from(vec![Span::raw(format!("{keys:<20}")), Span::raw(binding.action.to_string())]);
+    if binding.mode == Mode::Global {
+      line.spans.push(Span::raw(" (global)").fg(Color::DarkGray));
+    }
+    ListItem::new(line)
+  }
+}
+
+impl Component for Help {
+  fn draw(&mut self, f: &mut Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus.clone()) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Keys of {:?}", focus.mode));
+    let items: Vec<_> = self.bindings.iter().map(Self::binding_line).collect();
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    let help = match &self.capturing {
+      Some(keys) if keys.is_empty() => Paragraph::new("Press the new keys").fg(Color::Yellow),
+      Some(keys) => {
+        Paragraph::new(format!(
+          "{}, <Enter> to bind, another key to add to the sequence, <Esc> to cancel",
+          keymap::keys_to_string(keys)
+        ))
+        .fg(Color::Yellow)
+      },
+      None => Paragraph::new("<Enter> bind the action to other keys, <Esc> close"),
+    };
+    f.render_widget(help, layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Help
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Global
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::HelpUpdate(bindings) = action {
+      self.bindings = bindings;
+      self.capturing = None;
+      let selected = self.list_state.selected().map(|index| index.min(self.bindings.len().saturating_sub(1)));
+      self.list_state.select(if self.bindings.is_empty() { None } else { selected.or(Some(0)) });
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.kind != KeyEventKind::Press {
+      return Ok(None);
+    }
+    // the first key is always taken, so that <Enter> and <Esc> can be bound too
+    let key = KeyEvent::new(key.code, key.modifiers);
+    if let Some(keys) = &mut self.capturing {
+      match key.code {
+        _ if keys.is_empty() => keys.push(key),
+        KeyCode::Enter if key.modifiers == KeyModifiers::NONE => {
+          let keys = self.capturing.take().unwrap_or_default();
+          let selected = self.list_state.selected().and_then(|index| self.bindings.get(index));
+          return Ok(selected.map(|binding| Action::Rebind(binding.mode, Box::new(binding.action.clone()), keys)));
+        },
+        KeyCode::Esc => self.capturing = None,
+        _ => keys.push(key),
+      }
+      return Ok(None);
+    }
+    let count = self.bindings.len();
+    let selected = self.list_state.selected();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        self.list_state.select(Some(selected.map(|index| (index + 1) % count).unwrap_or_default()));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        self.list_state.select(Some(selected.and_then(|index| index.checked_sub(1)).unwrap_or(count - 1)));
+      },
+      KeyCode::Enter if selected.is_some() => self.capturing = Some(vec![]),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
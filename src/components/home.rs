@@ -1,33 +1,211 @@
-use std::{collections::HashMap, time::Duration};
-
 use color_eyre::eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{prelude::*, widgets::*};
-use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::UnboundedSender;
-use uuid::Uuid;
 
 use super::{Component, Frame};
 use crate::{
   action::Action,
-  config::{Config, KeyBindings},
-  layouts::{Focus, HomeLayouts, Scenes},
+  config::Config,
+  dashboard,
+  diagnostics::Diagnostic,
+  jobs::{JobInfo, JobState},
+  layouts::{DownloadLayouts, Focus, HomeLayouts, ManagerLayouts, Scenes, TabFilter},
   mode::Mode,
+  models::Pin,
+  queue::QueueStatus,
 };
 
+/// The most pins opened with a number key
+const QUICK_ACCESS_KEYS: usize = 9;
+
+/// The sections of the dashboard, in the order `<Tab>` moves through them
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+  #[default]
+  QuickAccess,
+  RecentlyAdded,
+  LastPlayed,
+  Downloads,
+  Jobs,
+}
+
+impl Section {
+  const ALL: [Section; 5] =
+    [Section::QuickAccess, Section::RecentlyAdded, Section::LastPlayed, Section::Downloads, Section::Jobs];
+
+  fn title(&self) -> &'static str {
+    match self {
+      Section::QuickAccess => "Quick Access",
+      Section::RecentlyAdded => "Recently added",
+      Section::LastPlayed => "Last played",
+      Section::Downloads => "Downloads",
+      Section::Jobs => "Jobs",
+    }
+  }
+
+  fn next(self) -> Self {
+    let index = Self::ALL.iter().position(|section| *section == self).unwrap_or_default();
+    Self::ALL[(index + 1) % Self::ALL.len()]
+  }
+
+  fn previous(self) -> Self {
+    let index = Self::ALL.iter().position(|section| *section == self).unwrap_or_default();
+    Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+  }
+}
+
+/// The Home screen, summing up the library and the work in progress
 #[derive(Default)]
-pub struct Intro {
+pub struct Dashboard {
   command_tx: Option<UnboundedSender<Action>>,
   config: Config,
+  /// shown in the Quick Access section, in the order they were pinned
+  pins: Vec<Pin>,
+  dashboard: dashboard::Dashboard,
+  /// the jobs that are queued or running
+  jobs: Vec<JobInfo>,
+  section: Section,
+  /// the selected row of the focused section
+  selected: usize,
 }
 
-impl Intro {
+impl Dashboard {
   pub fn new() -> Self {
     Self::default()
   }
+
+  /// The rows of a section
+  fn rows(&self, section: Section) -> Vec<String> {
+    match section {
+      Section::QuickAccess => {
+        self
+          .pins
+          .iter()
+          .enumerate()
+          .map(|(index, pin)| {
+            match index < QUICK_ACCESS_KEYS {
+              true => format!("<{}> {}", index + 1, TabFilter::from(pin)),
+              false => format!("    {}", TabFilter::from(pin)),
+            }
+          })
+          .collect()
+      },
+      Section::RecentlyAdded => self.dashboard.recently_added.iter().map(ToString::to_string).collect(),
+      Section::LastPlayed => self.dashboard.last_played.iter().map(ToString::to_string).collect(),
+      Section::Downloads => {
+        self
+          .dashboard
+          .downloads
+          .iter()
+          .map(|item| {
+            match &item.status {
+              QueueStatus::Downloading => {
+                match item.progress {
+                  Some(progress) => format!("{} (downloading, {progress})", item.request.title),
+                  None => format!("{} (downloading)", item.request.title),
+                }
+              },
+              QueueStatus::Duplicate(title) => {
+                format!("{} (same as {title}, waiting for a decision)", item.request.title)
+              },
+              QueueStatus::Review(_) => format!("{} (waiting for review)", item.request.title),
+              _ => format!("{} (queued)", item.request.title),
+            }
+          })
+          .collect()
+      },
+      Section::Jobs => {
+        self
+          .jobs
+          .iter()
+          .map(|job| {
+            match job.percent() {
+              Some(percent) => format!("{} ({}, {percent}%)", job.name, job.state),
+              None => format!("{} ({})", job.name, job.state),
+            }
+          })
+          .collect()
+      },
+    }
+  }
+
+  /// The id of the selected song, when a section of songs is focused
+  fn selected_song(&self) -> Option<i32> {
+    let songs = match self.section {
+      Section::RecentlyAdded => &self.dashboard.recently_added,
+      Section::LastPlayed => &self.dashboard.last_played,
+      _ => return None,
+    };
+    songs.get(self.selected).map(|song| song.id)
+  }
+
+  /// The video of the selected download, when the downloads are focused
+  fn selected_download(&self) -> Option<String> {
+    if self.section != Section::Downloads {
+      return None;
+    }
+    self.dashboard.downloads.get(self.selected).map(|item| item.request.youtube_id.clone())
+  }
+
+  /// Open the mode showing the selected row of the focused section
+  fn open_selected(&self) -> Result<Option<Action>> {
+    let manager = Action::FocusSwitch(Focus { mode: Mode::Manager, scene: Scenes::Manager(ManagerLayouts::SongList) });
+    match self.section {
+      Section::QuickAccess => self.open_pin(self.selected),
+      Section::RecentlyAdded | Section::LastPlayed => Ok(Some(manager)),
+      Section::Downloads => {
+        Ok(Some(Action::FocusSwitch(Focus {
+          mode: Mode::Download,
+          scene: Scenes::Download(DownloadLayouts::SearchResult),
+        })))
+      },
+      Section::Jobs => {
+        if let (Some(job), Some(tx)) = (self.jobs.get(self.selected), &self.command_tx) {
+          tx.send(Action::JobsShowLogs(Some(job.id)))?;
+        }
+        Ok(Some(Action::JobsShow))
+      },
+    }
+  }
+
+  /// Open a Manager tab for the pin
+  fn open_pin(&self, index: usize) -> Result<Option<Action>> {
+    if let (Some(pin), Some(tx)) = (self.pins.get(index), &self.command_tx) {
+      tx.send(Action::ManagerTabOpen(TabFilter::from(pin)))?;
+      return Ok(Some(Action::FocusSwitch(Focus {
+        mode: Mode::Manager,
+        scene: Scenes::Manager(ManagerLayouts::SongList),
+      })));
+    }
+    Ok(None)
+  }
+
+  fn draw_section(&self, f: &mut Frame<'_>, area: Rect, section: Section) {
+    let rows = self.rows(section);
+    let focused = section == self.section;
+    let border_style = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    let block = Block::default().borders(Borders::ALL).border_style(border_style).title(format!(
+      "{} ({})",
+      section.title(),
+      rows.len()
+    ));
+    if rows.is_empty() {
+      f.render_widget(
+        Paragraph::new("Nothing here yet").style(Style::default().fg(Color::DarkGray)).block(block),
+        area,
+      );
+      return;
+    }
+    let list = List::new(rows.into_iter().map(ListItem::new).collect::<Vec<_>>())
+      .block(block)
+      .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(focused.then_some(self.selected));
+    f.render_stateful_widget(list, area, &mut state);
+  }
 }
 
-impl Component for Intro {
+impl Component for Dashboard {
   fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
     self.command_tx = Some(tx);
     Ok(())
@@ -40,37 +218,189 @@ impl Component for Intro {
 
   fn update(&mut self, action: Action) -> Result<Option<Action>> {
     match action {
-      Action::Tick => {},
-      _ => {},
+      Action::PinsUpdate(pins) => self.pins = pins,
+      Action::DashboardUpdate(dashboard) => self.dashboard = dashboard,
+      Action::JobsUpdate(jobs) => {
+        self.jobs = jobs.into_iter().filter(|job| matches!(job.state, JobState::Queued | JobState::Running)).collect();
+      },
+      _ => return Ok(None),
     }
+    // the rows may have gone away
+    self.selected = self.selected.min(self.rows(self.section).len().saturating_sub(1));
     Ok(None)
   }
 
   fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
-    if focus.mode == self.mode() && focus.scene == self.scene() {
-      if let KeyCode::Enter = key.code {
+    if !self.is_focused(focus) {
+      return Ok(None);
+    }
+    let row_count = self.rows(self.section).len();
+    match key.code {
+      KeyCode::Tab => {
+        self.section = self.section.next();
+        self.selected = 0;
+      },
+      KeyCode::BackTab => {
+        self.section = self.section.previous();
+        self.selected = 0;
+      },
+      KeyCode::Char('j') | KeyCode::Down if row_count > 0 => self.selected = (self.selected + 1) % row_count,
+      KeyCode::Char('k') | KeyCode::Up if row_count > 0 => {
+        self.selected = self.selected.checked_sub(1).unwrap_or(row_count - 1);
+      },
+      KeyCode::Enter => return self.open_selected(),
+      KeyCode::Char('i') => return Ok(self.selected_song().map(Action::InstrumentalFind)),
+      KeyCode::Char('t') => return Ok(self.selected_song().map(Action::TrimShow)),
+      KeyCode::Char('v') => return Ok(self.selected_download().map(Action::DownloadLogShow)),
+      KeyCode::Char('l') if key.modifiers == KeyModifiers::NONE => {
         return Ok(Some(Action::FocusSwitch(Focus {
-          mode: Mode::Download,
-          // move to search result because its the first interactable
-          scene: Scenes::Download(crate::layouts::DownloadLayouts::SearchResult),
+          mode: Mode::Manager,
+          scene: Scenes::Manager(ManagerLayouts::SongList),
         })));
-      }
+      },
+      KeyCode::Char(c @ '1'..='9') => return self.open_pin(c as usize - '1' as usize),
+      _ => {},
     }
     Ok(None)
   }
 
   fn draw(&mut self, f: &mut Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
-    let intro_text = Paragraph::new("Welcome to muzik-tui!\nPress <Enter> to start download.\nPress <l> to go to the management list.\nPress <q> to exit at anytime")
-      .alignment(Alignment::Center)
-      .block(
-        Block::default().borders(Borders::ALL).padding(Padding { top: (area.height / 2) - 2, ..Default::default() }),
-      );
-    f.render_widget(intro_text, area);
+    let [stats, body, help] = *Layout::default()
+      .direction(Direction::Vertical)
+      .constraints([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+      .split(area)
+    else {
+      return Ok(());
+    };
+    f.render_widget(Paragraph::new(format!("Library: {}", self.dashboard.stats)).alignment(Alignment::Center), stats);
+
+    let columns =
+      Layout::default().direction(Direction::Horizontal).constraints([Constraint::Ratio(1, 2); 2]).split(body);
+    let left =
+      Layout::default().direction(Direction::Vertical).constraints([Constraint::Ratio(1, 3); 3]).split(columns[0]);
+    let right =
+      Layout::default().direction(Direction::Vertical).constraints([Constraint::Ratio(1, 2); 2]).split(columns[1]);
+    self.draw_section(f, left[0], Section::QuickAccess);
+    self.draw_section(f, left[1], Section::RecentlyAdded);
+    self.draw_section(f, left[2], Section::LastPlayed);
+    self.draw_section(f, right[0], Section::Downloads);
+    self.draw_section(f, right[1], Section::Jobs);
+
+    f.render_widget(
+      Paragraph::new("<Tab> next section, <j>/<k> move, <Enter> open, <i> find instrumental, <t> trim, <v> download log, <1>-<9> quick access, <l> manager, <q> quit")
+        .style(Style::default().fg(Color::DarkGray)),
+      help,
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Home(HomeLayouts::Dashboard)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Home
+  }
+}
+
+/// The failed checks of the setup, shown on launch when one failed, with the fixes muzik makes
+#[derive(Default)]
+pub struct Diagnostics {
+  diagnostics: Vec<Diagnostic>,
+  /// the checks are running
+  checking: bool,
+  list_state: ListState,
+}
+
+impl Diagnostics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn diagnostic_line(diagnostic: &Diagnostic) -> ListItem<'static> {
+    let fix = match diagnostic.fix {
+      Some(fix) => Span::raw(format!(" <Enter> {fix}")).fg(Color::Green),
+      None => Span::raw(" fix it by hand").fg(Color::DarkGray),
+    };
+    ListItem::new(Line::from(vec![
+      Span::raw(format!("{}: ", diagnostic.check)).bold(),
+      Span::raw(diagnostic.problem.clone()).fg(Color::Red),
+      fix,
+    ]))
+  }
+}
+
+impl Component for Diagnostics {
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::DiagnosticsRun => self.checking = true,
+      Action::Diagnostics(diagnostics) => {
+        let selected = self.list_state.selected().unwrap_or_default().min(diagnostics.len().saturating_sub(1));
+        self.diagnostics = diagnostics;
+        self.checking = false;
+        self.list_state.select((!self.diagnostics.is_empty()).then_some(selected));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.diagnostics.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        let Some(diagnostic) = self.list_state.selected().and_then(|index| self.diagnostics.get(index)) else {
+          return Ok(None);
+        };
+        return Ok(Some(match diagnostic.fix {
+          Some(fix) => Action::DiagnosticsFix(fix),
+          None => Action::Notify(format!("muzik cannot fix the {} itself", diagnostic.check)),
+        }));
+      },
+      KeyCode::Char('r') => return Ok(Some(Action::DiagnosticsRun)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn draw(&mut self, f: &mut Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block =
+      Block::default().borders(Borders::ALL).title(format!("Diagnostics ({} failed)", self.diagnostics.len()));
+    f.render_widget(Clear, area);
+    if self.checking {
+      f.render_widget(Paragraph::new("Checking the setup...").block(block), layout[0]);
+    } else if self.diagnostics.is_empty() {
+      f.render_widget(Paragraph::new("Every check passed, muzik is ready to download").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.diagnostics.iter().map(Self::diagnostic_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(
+      Paragraph::new("<Enter> fix the selected problem, <r> check again, <Esc> close")
+        .style(Style::default().fg(Color::DarkGray)),
+      layout[1],
+    );
     Ok(())
   }
 
   fn scene(&self) -> Scenes {
-    Scenes::Home(HomeLayouts::Intro)
+    Scenes::Home(HomeLayouts::Diagnostics)
   }
 
   fn mode(&self) -> Mode {
@@ -0,0 +1,144 @@
+//! This module contains components related to the background jobs view
+
+use color_eyre::eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  prelude::*,
+  widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use super::Component;
+use crate::{
+  action::Action,
+  jobs::{JobId, JobInfo, JobState},
+  layouts::{Focus, JobsLayouts, Scenes},
+  mode::Mode,
+};
+
+/// Lists all background jobs with their state and progress
+#[derive(Default)]
+pub struct JobList {
+  jobs: Vec<JobInfo>,
+  list_state: ListState,
+}
+
+impl JobList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn selected_job(&self) -> Option<JobId> {
+    self.list_state.selected().and_then(|index| self.jobs.get(index)).map(|job| job.id)
+  }
+
+  fn job_line(job: &JobInfo) -> ListItem<'static> {
+    let started_at =
+      job.started_at.map(|started_at| started_at.format("%H:%M:%S").to_string()).unwrap_or("--:--:--".to_string());
+    let progress = job.percent().map(|percent| format!(" {percent:>3}%")).unwrap_or_default();
+    let style = match job.state {
+      JobState::Running => Style::default().fg(Color::Yellow),
+      JobState::Finished => Style::default().fg(Color::Green),
+      JobState::Failed(_) => Style::default().fg(Color::Red),
+      JobState::Queued | JobState::Cancelled => Style::default().fg(Color::DarkGray),
+    };
+    ListItem::new(format!("{started_at} [{}] {}{progress} - {}", job.kind, job.name, job.state)).style(style)
+  }
+}
+
+impl Component for JobList {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    let block = Block::default().borders(Borders::ALL).title("Jobs (<c> cancel, <r> retry)");
+    if self.jobs.is_empty() {
+      f.render_widget(Paragraph::new("No background jobs").block(block), area);
+    } else {
+      let items: Vec<_> = self.jobs.iter().map(Self::job_line).collect();
+      let list = List::new(items).highlight_symbol(">>").block(block);
+      f.render_stateful_widget(list, area, &mut self.list_state);
+    }
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Jobs(JobsLayouts::List)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Jobs
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::JobsUpdate(jobs) = action {
+      self.jobs = jobs;
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if !self.jobs.is_empty() => {
+        let index = self.list_state.selected().map(|index| (index + 1) % self.jobs.len()).unwrap_or_default();
+        self.list_state.select(Some(index));
+        return Ok(Some(Action::JobsShowLogs(self.selected_job())));
+      },
+      KeyCode::Char('k') | KeyCode::Up if !self.jobs.is_empty() => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(self.jobs.len() - 1);
+        self.list_state.select(Some(index));
+        return Ok(Some(Action::JobsShowLogs(self.selected_job())));
+      },
+      KeyCode::Char('c') => return Ok(self.selected_job().map(Action::JobsCancel)),
+      KeyCode::Char('r') => return Ok(self.selected_job().map(Action::JobsRetry)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// Shows the logs of the job selected in the `JobList`
+#[derive(Default)]
+pub struct JobLogs {
+  jobs: Vec<JobInfo>,
+  selected_job: Option<JobId>,
+}
+
+impl JobLogs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl Component for JobLogs {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, _focus: Focus) -> Result<()> {
+    let block = Block::default().borders(Borders::ALL).title("Logs");
+    let job = self.selected_job.and_then(|id| self.jobs.iter().find(|job| job.id == id));
+    let text = match job {
+      Some(job) => job.logs.join("\n"),
+      None => "Select a job to view its logs".to_string(),
+    };
+    // keep the latest lines in view
+    let lines = text.lines().count() as u16;
+    let scroll = lines.saturating_sub(area.height.saturating_sub(2));
+    f.render_widget(Paragraph::new(text).block(block).wrap(Wrap { trim: false }).scroll((scroll, 0)), area);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Jobs(JobsLayouts::Logs)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Jobs
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::JobsUpdate(jobs) => self.jobs = jobs,
+      Action::JobsShowLogs(id) => self.selected_job = id,
+      _ => {},
+    }
+    Ok(None)
+  }
+}
@@ -1,35 +1,217 @@
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
 use color_eyre::eyre::{eyre, Result};
-use ratatui::prelude::*;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  prelude::*,
+  widgets::{
+    block, Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Tabs, Wrap,
+  },
+};
+use strum::IntoEnumIterator;
+use tokio::sync::mpsc::UnboundedSender;
 
 use super::Component;
 use crate::{
-  config::Config,
-  layouts::{Focus, ManagerLayouts, Scenes},
+  action::{Action, InputIn, InputOut},
+  artist_images,
+  backup::Backup,
+  cache,
+  config::{Config, DuplicatePolicy},
+  download_logs::{self, DownloadLogEntry, DownloadLogList, DownloadLogStatus},
+  enrichment::Provider,
+  gaps::AlbumGap,
+  health::{Health, HealthItem, Priority},
+  jobs::{JobInfo, JobKind, JobState},
+  layouts::{DownloadLayouts, Focus, ManagerLayouts, ManagerTabs, Scenes, TabFilter},
+  links::DeadLink,
+  lyrics,
+  merge::{MergeCandidate, MergeSide},
   mode::Mode,
+  models::{Attachment, Playlist, Snapshot, SnapshotSong, Song, SongField},
+  playlist_export, playlists,
+  queue::{DownloadRequest, QueueItem, QueueStatus, Verdict},
+  scan::{self, ScanResult},
+  snapshots::SnapshotDiff,
+  song_edit::{SongEdit, EDITED_FIELDS},
+  song_status::{DisplayMode, SongFlags, SongListRow, SongPage, PAGE_SIZE},
+  trim::{TrimRange, TrimSong},
+  upgrade::{Upgrade, UpgradeOffer},
+  verify::IntegrityProblem,
 };
 
-#[derive(Default, Clone, Debug)]
-pub enum DisplayMode {
-  #[default]
-  Local,
-  Database,
-  All,
-}
-
 #[derive(Default)]
 pub struct SongList {
   display_mode: DisplayMode,
   config: Option<Config>,
+  /// the tabs as last reported by the run loop
+  tabs: ManagerTabs,
+  table_state: TableState,
+  /// the songs of the active tab, `None` for those on a page not loaded yet
+  rows: Vec<Option<SongListRow>>,
+  /// the generation of the pages loaded, `None` when the songs of the tab were loaded at once
+  generation: Option<u64>,
+  /// the offsets of the pages asked for
+  requested: HashSet<usize>,
+  /// how many rows were drawn last
+  height: usize,
+  action_tx: Option<UnboundedSender<Action>>,
+  /// the videos queued or being downloaded
+  downloads: HashSet<String>,
+  /// the video of the playing song
+  playing: Option<String>,
+  /// the songs selected to act on together, by id
+  marked: HashSet<i32>,
+  /// the text the songs of the tab are searched for
+  search: Option<String>,
+  /// `g` was pressed, which starts `gg` and the key bindings of the Manager
+  pending_g: bool,
 }
 
 impl SongList {
   pub fn new() -> Self {
     Self::default()
   }
+
+  /// Restore the selection and scroll state of the active tab
+  fn restore_tab_state(&mut self) {
+    let tab = self.tabs.active();
+    self.table_state = TableState::default().with_selected(tab.selected).with_offset(tab.offset);
+  }
+
+  /// The song under the cursor, if it is in the library
+  fn selected_song(&self) -> Option<&SongListRow> {
+    self.table_state.selected().and_then(|index| self.rows.get(index)?.as_ref()).filter(|row| !row.flags.untracked)
+  }
+
+  /// The ids of the marked songs loaded, in the order they are listed
+  fn marked_ids(&self) -> Vec<i32> {
+    self.rows.iter().flatten().map(|row| row.id).filter(|id| self.marked.contains(id)).collect()
+  }
+
+  /// Ask for the pages of the rows around the cursor that are not loaded yet
+  fn request_pages(&mut self) -> Result<()> {
+    let (Some(generation), Some(action_tx)) = (self.generation, &self.action_tx) else {
+      return Ok(());
+    };
+    let Some(last) = self.rows.len().checked_sub(1) else {
+      return Ok(());
+    };
+    let selected = self.table_state.selected().unwrap_or_default();
+    let (first, last) = (selected.saturating_sub(self.height), (selected + self.height).min(last));
+    for offset in (first / PAGE_SIZE * PAGE_SIZE..=last).step_by(PAGE_SIZE) {
+      if self.rows[offset].is_none() && self.requested.insert(offset) {
+        action_tx.send(Action::ManagerSongsLoadPage((generation, offset)))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Put a page of the songs of the tab in place, in place of every song loaded before when it is
+  /// of another load
+  fn load_page(&mut self, page: SongPage) {
+    if self.generation != Some(page.generation) {
+      self.generation = Some(page.generation);
+      self.requested.clear();
+      self.rows = vec![None; page.total];
+      let selected = self.table_state.selected().map(|index| index.min(page.total.saturating_sub(1)));
+      self.table_state.select(if page.total == 0 { None } else { selected.or(Some(0)) });
+    }
+    self.requested.insert(page.offset);
+    let end = (page.offset + page.rows.len()).min(self.rows.len());
+    for (slot, row) in self.rows[page.offset.min(end)..end].iter_mut().zip(page.rows) {
+      *slot = Some(row);
+    }
+  }
+
+  /// The row of a song, led by its status glyphs. The flags that change often are worked out here
+  fn row(&self, row: &SongListRow) -> Row<'static> {
+    let youtube_id = row.youtube_id.as_deref();
+    let flags = SongFlags {
+      playing: youtube_id.is_some() && youtube_id == self.playing.as_deref(),
+      pending_download: youtube_id.is_some_and(|youtube_id| self.downloads.contains(youtube_id)),
+      ..row.flags
+    };
+    let glyphs: Vec<_> = flags
+      .glyphs()
+      .into_iter()
+      .map(|(glyph, color)| Span::styled(glyph.to_string(), Style::default().fg(color)))
+      .collect();
+    let title = match self.marked.contains(&row.id) {
+      true => Cell::from(format!("● {}", row.label)).fg(Color::Magenta),
+      false => Cell::from(row.label.clone()),
+    };
+    let file = match row.file_status() {
+      "local" => Cell::from("local").fg(Color::Green),
+      status => Cell::from(status).fg(Color::DarkGray),
+    };
+    Row::new(vec![
+      Cell::from(Line::from(glyphs)),
+      title,
+      Cell::from(row.artists.join(", ")).fg(Color::DarkGray),
+      Cell::from(row.album.clone().unwrap_or_default()).fg(Color::DarkGray),
+      file,
+    ])
+  }
 }
 
 impl Component for SongList {
   fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> color_eyre::eyre::Result<()> {
+    let search = self.search.as_ref().map(|text| format!(", search \"{text}\"")).unwrap_or_default();
+    let block = Block::default()
+      .borders(Borders::ALL)
+      .title(format!("{} ({}{search}, {} songs)", self.tabs.active().name(), self.display_mode, self.rows.len()))
+      .title(
+        block::Title::from("▶ playing ✗ file missing ⊘ video gone ↓ downloading ! incomplete + new ? not in library")
+          .position(block::Position::Bottom),
+      )
+      .title(
+        block::Title::from(
+          "</> search, <Enter> edit, <E> edit in $EDITOR, <p> play, <o> play with MPD, <Space> select, <a> add to a playlist, <e> export, <t> write tags, <b> find a better \
+           version, <c> complete the album, <m> display mode",
+        )
+        .position(block::Position::Bottom)
+        .alignment(Alignment::Right),
+      );
+    if self.rows.is_empty() {
+      f.render_widget(Paragraph::new("No songs to display").block(block), area);
+      return Ok(());
+    }
+    // only the rows around the cursor are drawn, from wherever the table is scrolled to
+    self.height = area.height as usize;
+    let (offset, selected) = (self.table_state.offset(), self.table_state.selected().unwrap_or_default());
+    let start = match (offset..offset + self.height).contains(&selected) {
+      true => offset,
+      false => selected.saturating_sub(self.height),
+    };
+    let end = (start + 2 * self.height).min(self.rows.len());
+    let rows: Vec<_> = self.rows[start..end]
+      .iter()
+      .map(|row| {
+        match row {
+          Some(row) => self.row(row),
+          None => Row::new(vec!["", "…"]).fg(Color::DarkGray),
+        }
+      })
+      .collect();
+    let widths = [
+      Constraint::Length(7),
+      Constraint::Percentage(40),
+      Constraint::Percentage(25),
+      Constraint::Percentage(25),
+      Constraint::Length(14),
+    ];
+    let header = Row::new(vec!["", "Title", "Artists", "Album", "File"]).bold();
+    let table = Table::new(rows, widths).header(header).highlight_symbol(">>").block(block);
+    let mut state = TableState::default()
+      .with_offset(offset.saturating_sub(start))
+      .with_selected(self.table_state.selected().map(|selected| selected - start));
+    f.render_stateful_widget(table, area, &mut state);
+    *self.table_state.offset_mut() = start + state.offset();
     Ok(())
   }
 
@@ -41,8 +223,2358 @@ impl Component for SongList {
     Mode::Manager
   }
 
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn register_config_handler(&mut self, config: Config) -> Result<()> {
+    self.config = Some(config);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ManagerTabsUpdate(tabs) => {
+        self.tabs = tabs;
+        self.marked.clear();
+        self.search = None;
+        self.restore_tab_state();
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer })
+        if input_name == *"export_destination" =>
+      {
+        let destination = buffer.trim();
+        if destination.is_empty() {
+          return Ok(Some(Action::Error("no folder to export to".to_string())));
+        }
+        // the selected songs, or the whole tab when none are selected
+        if self.marked.is_empty() && self.rows.iter().any(Option::is_none) {
+          return Ok(Some(Action::ExportTab(PathBuf::from(destination))));
+        }
+        let song_ids: Vec<_> = self
+          .rows
+          .iter()
+          .flatten()
+          .filter(|row| !row.flags.untracked)
+          .map(|row| row.id)
+          .filter(|id| self.marked.is_empty() || self.marked.contains(id))
+          .collect();
+        self.marked.clear();
+        return Ok(Some(Action::ExportSongs(song_ids, PathBuf::from(destination))));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"playlist_add" => {
+        let name = buffer.trim();
+        if name.is_empty() {
+          return Ok(Some(Action::Error("no playlist to add the songs to".to_string())));
+        }
+        // the selected songs, or the song under the cursor when none are selected
+        let song_ids: Vec<_> = match self.marked.is_empty() {
+          true => self.selected_song().filter(|row| !row.flags.untracked).map(|row| row.id).into_iter().collect(),
+          false => self.marked_ids(),
+        };
+        self.marked.clear();
+        return Ok((!song_ids.is_empty()).then(|| Action::PlaylistAddSongs(name.to_string(), song_ids)));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"song_search" => {
+        self.search = Some(buffer.trim().to_string()).filter(|text| !text.is_empty());
+        self.table_state.select(Some(0));
+        return Ok(Some(Action::ManagerSearch(self.search.clone())));
+      },
+      Action::ManagerSongs(rows) => {
+        self.rows = rows.into_iter().map(Some).collect();
+        self.generation = None;
+        let selected = self.table_state.selected().map(|index| index.min(self.rows.len().saturating_sub(1)));
+        self.table_state.select(if self.rows.is_empty() { None } else { selected.or(Some(0)) });
+      },
+      Action::ManagerSongsPage(page) => {
+        self.load_page(page);
+        self.request_pages()?;
+      },
+      Action::DashboardUpdate(dashboard) => {
+        self.downloads = dashboard.downloads.into_iter().map(|item| item.request.youtube_id).collect();
+      },
+      #[cfg(feature = "player")]
+      Action::PlayerNowPlaying(now_playing) => self.playing = now_playing.map(|now_playing| now_playing.youtube_id),
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) {
+      return Ok(None);
+    }
+    let count = self.rows.len();
+    // the key after `g` is either `gg` or a key binding of the Manager, such as `<g><t>`
+    if std::mem::take(&mut self.pending_g) {
+      if key.code != KeyCode::Char('g') || key.modifiers != KeyModifiers::NONE || count == 0 {
+        return Ok(None);
+      }
+      self.table_state.select(Some(0));
+      self.request_pages()?;
+      return Ok(Some(Action::ManagerTabSaveState((self.table_state.selected(), self.table_state.offset()))));
+    }
+    match (key.code, key.modifiers) {
+      (KeyCode::Char('G'), KeyModifiers::SHIFT | KeyModifiers::NONE) | (KeyCode::End, KeyModifiers::NONE)
+        if count > 0 =>
+      {
+        self.table_state.select(Some(count - 1));
+        self.request_pages()?;
+        return Ok(Some(Action::ManagerTabSaveState((self.table_state.selected(), self.table_state.offset()))));
+      },
+      (KeyCode::Char('E'), KeyModifiers::SHIFT | KeyModifiers::NONE) if count > 0 => {
+        // the selected songs, or the song under the cursor when none are selected
+        let song_ids: Vec<_> = match self.marked.is_empty() {
+          true => self.selected_song().map(|row| row.id).into_iter().collect(),
+          false => self.marked_ids(),
+        };
+        self.marked.clear();
+        return Ok((!song_ids.is_empty()).then_some(Action::SongEditorExternal(song_ids)));
+      },
+      (_, KeyModifiers::NONE) => {},
+      _ => return Ok(None),
+    }
+    match key.code {
+      KeyCode::Char('g') => {
+        self.pending_g = true;
+        return Ok(None);
+      },
+      KeyCode::Home if count > 0 => self.table_state.select(Some(0)),
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.table_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.table_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.table_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.table_state.select(Some(index));
+      },
+      KeyCode::Char('m') => {
+        self.display_mode = self.display_mode.next();
+        return Ok(Some(Action::ManagerDisplayMode(self.display_mode)));
+      },
+      KeyCode::Char(' ') => {
+        if let Some(id) = self.selected_song().map(|row| row.id) {
+          if !self.marked.remove(&id) {
+            self.marked.insert(id);
+          }
+        }
+        return Ok(None);
+      },
+      KeyCode::Char('e') if count > 0 => {
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "export_destination".to_string(),
+          initial_value: None,
+        })));
+      },
+      KeyCode::Char('t') if count > 0 => {
+        // the selected songs, or the song under the cursor when none are selected
+        let song_ids: Vec<_> = match self.marked.is_empty() {
+          true => self.selected_song().map(|row| row.id).into_iter().collect(),
+          false => self.marked_ids(),
+        };
+        self.marked.clear();
+        return Ok((!song_ids.is_empty()).then_some(Action::WriteTags(song_ids)));
+      },
+      KeyCode::Char('a') if count > 0 => {
+        return Ok(Some(Action::InputModeOn(InputIn { input_name: "playlist_add".to_string(), initial_value: None })));
+      },
+      KeyCode::Char('/') => {
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "song_search".to_string(),
+          initial_value: self.search.clone(),
+        })));
+      },
+      KeyCode::Enter => return Ok(self.selected_song().map(|row| Action::SongEditorShow(row.id))),
+      KeyCode::Char('p') => return Ok(self.selected_song().map(|row| Action::PlayerPlaySong(row.id))),
+      KeyCode::Char('o') => return Ok(self.selected_song().map(|row| Action::MpdPlaySong(row.id))),
+      KeyCode::Char('b') => return Ok(self.selected_song().map(|row| Action::UpgradeFind(row.id))),
+      KeyCode::Char('c') => return Ok(self.selected_song().map(|row| Action::AlbumComplete(row.id))),
+      // the first <Esc> stops searching
+      KeyCode::Esc if self.search.is_some() => {
+        self.search = None;
+        return Ok(Some(Action::ManagerSearch(None)));
+      },
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => return Ok(None),
+    }
+    self.request_pages()?;
+    Ok(Some(Action::ManagerTabSaveState((self.table_state.selected(), self.table_state.offset()))))
+  }
+}
+
+/// The pane of the artist of an artist tab, with its picture on terminals that draw images inline.
+/// A missing picture is fetched once when the tab is opened, if the pictures of artists are on
+#[derive(Default)]
+pub struct ArtistDetails {
+  config: Option<Config>,
+  /// the artist of the active tab, if it is an artist tab
+  artist: Option<String>,
+  /// the cached picture of the artist
+  image: Option<Vec<u8>>,
+  /// the songs of the artist in the library
+  songs: usize,
+  /// the artists whose picture was asked for already
+  requested: HashSet<String>,
+  inline_images: bool,
+}
+
+impl ArtistDetails {
+  pub fn new() -> Self {
+    Self { inline_images: artist_images::inline_images_supported(), ..Default::default() }
+  }
+
+  /// Read the cached picture of the artist, asking for it if it is missing
+  fn load_image(&mut self) -> Option<Action> {
+    self.image = None;
+    let (Some(config), Some(artist)) = (&self.config, &self.artist) else {
+      return None;
+    };
+    let path = artist_images::cache_path(&config.paths, artist)?;
+    match std::fs::read(&path) {
+      Ok(image) => {
+        cache::touch(&path);
+        self.image = Some(image);
+        None
+      },
+      Err(_) if config.config.enrichment.artist_images && self.requested.insert(artist.clone()) => {
+        Some(Action::ArtistImageFetch(artist.clone()))
+      },
+      Err(_) => None,
+    }
+  }
+}
+
+impl Component for ArtistDetails {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    let Some(artist) = &self.artist else {
+      return Ok(());
+    };
+    let block = Block::default().borders(Borders::ALL).title(artist.clone());
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(inner);
+    f.render_widget(Paragraph::new(format!("{} songs", self.songs)), layout[1]);
+    let picture = layout[0];
+    let enabled = self.config.as_ref().is_some_and(|config| config.config.enrichment.artist_images);
+    let message = match (&self.image, self.inline_images) {
+      (Some(image), true) if !picture.is_empty() => {
+        // the terminal draws the picture over the cells, which are left alone by the next frames
+        let buffer = f.buffer_mut();
+        for y in picture.top()..picture.bottom() {
+          for x in picture.left()..picture.right() {
+            buffer.get_mut(x, y).set_skip(true);
+          }
+        }
+        let escape = artist_images::inline_image(image, picture.width, picture.height);
+        buffer.get_mut(picture.x, picture.y).set_skip(false).set_symbol(&escape);
+        return Ok(());
+      },
+      (Some(_), _) => "The terminal cannot show pictures",
+      (None, _) if enabled => "No picture of the artist",
+      (None, _) => "Pictures of artists are off",
+    };
+    f.render_widget(Paragraph::new(message).fg(Color::DarkGray).wrap(Wrap { trim: true }), picture);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::ArtistDetails)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
   fn register_config_handler(&mut self, config: Config) -> Result<()> {
     self.config = Some(config);
     Ok(())
   }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ManagerTabsUpdate(tabs) => {
+        self.artist = match &tabs.active().filter {
+          TabFilter::Artist(artist) => Some(artist.clone()),
+          _ => None,
+        };
+        return Ok(self.load_image());
+      },
+      Action::ArtistImagesFetched => return Ok(self.load_image()),
+      Action::ManagerSongs(rows) => self.songs = rows.len(),
+      Action::ManagerSongsPage(page) => self.songs = page.total,
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// Shows the tabs opened in the Manager, along with how far the scan of the music dir is while it
+/// runs
+#[derive(Default)]
+pub struct TabBar {
+  tabs: ManagerTabs,
+  scans: Vec<JobInfo>,
+}
+
+impl TabBar {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl Component for TabBar {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    let titles = self.tabs.tabs().iter().map(|tab| tab.name()).collect();
+    let tabs = Tabs::new(titles)
+      .select(self.tabs.active_index())
+      .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+    let scans: Vec<_> = self
+      .scans
+      .iter()
+      .map(|job| {
+        match job.percent() {
+          Some(percent) => format!("{} {percent}%", job.name),
+          None => job.name.clone(),
+        }
+      })
+      .collect();
+    let scans = scans.join(", ");
+    let layout =
+      Layout::new(Direction::Horizontal, [Constraint::Min(1), Constraint::Length(scans.len() as u16)]).split(area);
+    f.render_widget(tabs, layout[0]);
+    f.render_widget(Paragraph::new(scans).fg(Color::Cyan), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::TabBar)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ManagerTabsUpdate(tabs) => self.tabs = tabs,
+      Action::JobsUpdate(jobs) => {
+        self.scans =
+          jobs.into_iter().filter(|job| job.kind == JobKind::Scan && job.state == JobState::Running).collect();
+      },
+      Action::ManagerTabNew => {
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "manager_tab_new".to_string(),
+          initial_value: None,
+        })));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"manager_tab_new" => {
+        return match TabFilter::parse(&buffer) {
+          Ok(filter) => Ok(Some(Action::ManagerTabOpen(filter))),
+          Err(e) => Ok(Some(Action::Error(format!("invalid tab filter: {e}")))),
+        };
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// Side by side view of two entities to be merged, where each field can be picked from either side
+#[derive(Default)]
+pub struct Compare {
+  candidate: Option<MergeCandidate>,
+  field_state: ListState,
+  /// the library changed since the candidate was loaded, so it may be outdated
+  stale: bool,
+}
+
+impl Compare {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn pick(&mut self, side: MergeSide) {
+    if let (Some(candidate), Some(index)) = (&mut self.candidate, self.field_state.selected()) {
+      if let Some(field) = candidate.fields.get_mut(index) {
+        field.pick = side;
+      }
+    }
+  }
+
+  fn side_list<'a>(candidate: &'a MergeCandidate, side: MergeSide, title: String) -> List<'a> {
+    let items: Vec<ListItem> = candidate
+      .fields
+      .iter()
+      .map(|field| {
+        let (value, source) = match side {
+          MergeSide::Left => (field.left.clone(), field.left_source),
+          MergeSide::Right => (field.right.clone(), field.right_source),
+        };
+        let lock = if field.locked { " [locked]" } else { "" };
+        let source = source.map(|source| format!(" ({source})")).unwrap_or_default();
+        let item = ListItem::new(format!("{}{lock}{source}: {}", field.name, value.unwrap_or("None".to_string())));
+        if field.pick == side {
+          item.style(Style::default().fg(Color::Green))
+        } else {
+          item
+        }
+      })
+      .collect();
+    List::new(items).highlight_symbol(">>").block(Block::default().borders(Borders::ALL).title(title))
+  }
+}
+
+impl Component for Compare {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if let Some(candidate) = &self.candidate {
+      let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+      let sides = Layout::new(Direction::Horizontal, Constraint::from_percentages([50, 50])).split(layout[0]);
+
+      let left = Self::side_list(candidate, MergeSide::Left, format!("Keep ({})", candidate.left_id));
+      let right = Self::side_list(candidate, MergeSide::Right, format!("Merge ({})", candidate.right_id));
+      f.render_widget(Clear, area);
+      f.render_stateful_widget(left, sides[0], &mut self.field_state.clone());
+      f.render_stateful_widget(right, sides[1], &mut self.field_state);
+      let help = if self.stale {
+        Paragraph::new("The library was changed elsewhere, <Esc> and find the duplicates again").fg(Color::Yellow)
+      } else {
+        Paragraph::new("<h>/<l> pick left/right, <Enter> merge, <Esc> cancel")
+      };
+      f.render_widget(help, layout[1]);
+    }
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Compare)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ManagerCompare(candidate) => {
+        self.candidate = Some(candidate);
+        self.stale = false;
+        self.field_state.select(Some(0));
+      },
+      Action::ManagerApplyMerge(_) => self.candidate = None,
+      Action::LibraryChanged => self.stale = self.candidate.is_some(),
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let field_count = self.candidate.as_ref().map(|candidate| candidate.fields.len()).unwrap_or_default();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if field_count > 0 => {
+        let index = self.field_state.selected().map(|index| (index + 1) % field_count).unwrap_or_default();
+        self.field_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if field_count > 0 => {
+        let index = self.field_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(field_count - 1);
+        self.field_state.select(Some(index));
+      },
+      KeyCode::Char('h') | KeyCode::Left => self.pick(MergeSide::Left),
+      KeyCode::Char('l') | KeyCode::Right => self.pick(MergeSide::Right),
+      KeyCode::Enter if !self.stale => {
+        if let Some(candidate) = &self.candidate {
+          return Ok(Some(Action::ManagerApplyMerge(candidate.clone())));
+        }
+      },
+      KeyCode::Esc => {
+        self.candidate = None;
+        return Ok(Some(Action::FocusBack));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// Albums of artists in the library that are missing entirely or partially
+#[derive(Default)]
+pub struct AlbumGaps {
+  gaps: Vec<AlbumGap>,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl AlbumGaps {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn gap_line(gap: &AlbumGap) -> ListItem<'static> {
+    let style = if gap.is_missing() { Style::default().fg(Color::Red) } else { Style::default().fg(Color::Yellow) };
+    ListItem::new(format!("{} - {} ({}/{} tracks)", gap.artist, gap.album, gap.owned, gap.track_count)).style(style)
+  }
+}
+
+impl Component for AlbumGaps {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Missing albums ({})", self.gaps.len()));
+    f.render_widget(Clear, area);
+    if self.gaps.is_empty() {
+      f.render_widget(Paragraph::new("No albums missing").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.gaps.iter().map(Self::gap_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(Paragraph::new("<s> search for this album, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::AlbumGaps)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::ManagerAlbumGaps(gaps) = action {
+      self.gaps = gaps;
+      self.list_state.select(if self.gaps.is_empty() { None } else { Some(0) });
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.gaps.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('s') => {
+        if let (Some(gap), Some(action_tx)) =
+          (self.list_state.selected().and_then(|index| self.gaps.get(index)), &self.action_tx)
+        {
+          action_tx.send(Action::DownloadSearch(gap.search_query()))?;
+          return Ok(Some(Action::FocusSwitch(Focus {
+            mode: Mode::Download,
+            scene: Scenes::Download(DownloadLayouts::SearchResult),
+          })));
+        }
+      },
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// What became of every file of the last scan of the music dir, to look into the files that were
+/// not added and scan them again
+#[derive(Default)]
+pub struct ScanResults {
+  results: Vec<ScanResult>,
+  /// only list the files that were not added
+  problems_only: bool,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl ScanResults {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The results listed
+  fn shown(&self) -> Vec<&ScanResult> {
+    self.results.iter().filter(|result| !self.problems_only || !result.outcome.is_ok()).collect()
+  }
+
+  fn result_line(result: &ScanResult) -> ListItem<'static> {
+    let style = match &result.outcome {
+      outcome if outcome.is_recoverable() => Style::default().fg(Color::Red),
+      outcome if !outcome.is_ok() => Style::default().fg(Color::Yellow),
+      scan::ScanOutcome::Imported => Style::default().fg(Color::Green),
+      _ => Style::default().fg(Color::DarkGray),
+    };
+    ListItem::new(format!("{}: {}", result.path.display(), result.outcome)).style(style)
+  }
+
+  fn select_first(&mut self) {
+    self.list_state.select(if self.shown().is_empty() { None } else { Some(0) });
+  }
+}
+
+impl Component for ScanResults {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let [imported, duplicate, too_short, unreadable, unsupported, failed] = scan::count_outcomes(&self.results);
+    let block = Block::default().borders(Borders::ALL).title(format!(
+      "Scan: {imported} imported, {duplicate} duplicates, {too_short} too short, {unreadable} unreadable tags, \
+       {unsupported} unsupported, {failed} failed"
+    ));
+    f.render_widget(Clear, area);
+    let items: Vec<_> = self.shown().into_iter().map(Self::result_line).collect();
+    if items.is_empty() {
+      f.render_widget(Paragraph::new("Nothing to show").block(block), layout[0]);
+    } else {
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    let filter = if self.problems_only { "show all" } else { "only show problems" };
+    f.render_widget(Paragraph::new(format!("<r> retry failed files, <f> {filter}, <Esc> close")), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::ScanResults)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ScanResults(results) => {
+        self.results = results;
+        // a scan of a large music dir is mostly songs already in the library
+        self.problems_only = self.results.iter().any(|result| !result.outcome.is_ok());
+        self.select_first();
+      },
+      Action::ScanRetried(results) => {
+        scan::merge_results(&mut self.results, results);
+        self.select_first();
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.shown().len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('f') => {
+        self.problems_only = !self.problems_only;
+        self.select_first();
+      },
+      KeyCode::Char('r') => {
+        let paths: Vec<_> = self
+          .results
+          .iter()
+          .filter(|result| result.outcome.is_recoverable())
+          .map(|result| result.path.clone())
+          .collect();
+        match (paths.is_empty(), &self.action_tx) {
+          (true, _) => return Ok(Some(Action::Notify("no files to retry".to_string()))),
+          (false, Some(action_tx)) => action_tx.send(Action::ScanRetry(paths))?,
+          (false, None) => {},
+        }
+      },
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The files attached to the album of the active tab, such as booklets, lyrics or cover scans
+#[derive(Default)]
+pub struct Attachments {
+  attachments: Vec<Attachment>,
+  list_state: ListState,
+}
+
+impl Attachments {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn attachment_line(attachment: &Attachment) -> ListItem<'static> {
+    let name = Path::new(&attachment.relative_path)
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_else(|| attachment.relative_path.clone());
+    ListItem::new(format!("[{}] {name}", attachment.kind))
+  }
+}
+
+impl Component for Attachments {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Attachments ({})", self.attachments.len()));
+    f.render_widget(Clear, area);
+    if self.attachments.is_empty() {
+      f.render_widget(Paragraph::new("Nothing attached").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.attachments.iter().map(Self::attachment_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(Paragraph::new("<a> attach a file, <d> remove, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Attachments)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::ManagerAttachments(attachments) => {
+        self.attachments = attachments;
+        let selected = self.list_state.selected().unwrap_or_default().min(self.attachments.len().saturating_sub(1));
+        self.list_state.select(if self.attachments.is_empty() { None } else { Some(selected) });
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer })
+        if input_name == *"manager_attach" && !buffer.trim().is_empty() =>
+      {
+        return Ok(Some(Action::ManagerAttach(PathBuf::from(buffer.trim()))));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.attachments.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('a') => {
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "manager_attach".to_string(),
+          initial_value: None,
+        })));
+      },
+      KeyCode::Char('d') => {
+        if let Some(attachment) = self.list_state.selected().and_then(|index| self.attachments.get(index)) {
+          return Ok(Some(Action::ManagerDetach(attachment.id)));
+        }
+      },
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// Which end of the trim the keys move
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum TrimMarker {
+  #[default]
+  Start,
+  End,
+}
+
+/// Set the start and end of a song to cut its intro and outro, preview them and render the trimmed
+/// version
+#[derive(Default)]
+pub struct Trim {
+  song: Option<TrimSong>,
+  start: Duration,
+  end: Duration,
+  marker: TrimMarker,
+}
+
+impl Trim {
+  /// How long the preview of the end plays before it
+  const PREVIEW_LEAD: Duration = Duration::from_secs(5);
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Move the selected marker by `seconds`, backwards if negative, keeping it within the song
+  fn move_marker(&mut self, seconds: i64) {
+    let Some(song) = &self.song else {
+      return;
+    };
+    let marker = match self.marker {
+      TrimMarker::Start => &mut self.start,
+      TrimMarker::End => &mut self.end,
+    };
+    *marker = lyrics::shift(*marker, seconds * 1000).min(song.duration);
+  }
+
+  fn time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+  }
+}
+
+impl Component for Trim {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    f.render_widget(Clear, area);
+    let Some(song) = &self.song else {
+      f.render_widget(Paragraph::new("Loading...").block(Block::default().borders(Borders::ALL).title("Trim")), area);
+      return Ok(());
+    };
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let marker_style = |marker| {
+      match self.marker == marker {
+        true => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        false => Style::default(),
+      }
+    };
+    let lines = vec![
+      Line::from(format!("Length: {}", Self::time(song.duration))),
+      Line::styled(format!("Start: {}", Self::time(self.start)), marker_style(TrimMarker::Start)),
+      Line::styled(format!("End:   {}", Self::time(self.end)), marker_style(TrimMarker::End)),
+      Line::from(format!("Kept:  {}", Self::time(self.end.saturating_sub(self.start)))),
+    ];
+    let block = Block::default().borders(Borders::ALL).title(format!("Trim: {}", song.title));
+    f.render_widget(Paragraph::new(lines).block(block), layout[0]);
+    f.render_widget(
+      Paragraph::new(
+        "<Tab> start/end, <h>/<l> move 1s, <H>/<L> move 10s, <p> preview, <s> stop, <Enter> render, <Esc> close",
+      ),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Trim)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::TrimShow(_) => self.song = None,
+      Action::TrimLoaded(song) => {
+        self.start = Duration::ZERO;
+        self.end = song.duration;
+        self.marker = TrimMarker::Start;
+        self.song = Some(song);
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) {
+      return Ok(None);
+    }
+    if key.code == KeyCode::Esc {
+      return Ok(Some(Action::FocusBack));
+    }
+    let Some(song) = &self.song else {
+      return Ok(None);
+    };
+    let song_id = song.song_id;
+    match key.code {
+      KeyCode::Tab | KeyCode::BackTab => {
+        self.marker = match self.marker {
+          TrimMarker::Start => TrimMarker::End,
+          TrimMarker::End => TrimMarker::Start,
+        };
+      },
+      KeyCode::Char('h') | KeyCode::Left => self.move_marker(-1),
+      KeyCode::Char('l') | KeyCode::Right => self.move_marker(1),
+      KeyCode::Char('H') => self.move_marker(-10),
+      KeyCode::Char('L') => self.move_marker(10),
+      KeyCode::Char('p') => {
+        let position = match self.marker {
+          TrimMarker::Start => self.start,
+          TrimMarker::End => self.end.saturating_sub(Self::PREVIEW_LEAD),
+        };
+        return Ok(Some(Action::TrimPreview(song_id, position)));
+      },
+      KeyCode::Char('s') => return Ok(Some(Action::PlayerStop)),
+      KeyCode::Enter => {
+        return Ok(Some(Action::TrimRender(TrimRange { song_id, start: self.start, end: self.end })));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The health score of the library and its to-do list, each item opening the workflow fixing it
+#[derive(Default)]
+pub struct HealthReport {
+  health: Health,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl HealthReport {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn item_line(item: &HealthItem) -> ListItem<'static> {
+    let style = match item.priority() {
+      Priority::High => Style::default().fg(Color::Red),
+      Priority::Medium => Style::default().fg(Color::Yellow),
+      Priority::Low => Style::default(),
+    };
+    ListItem::new(format!("[{}] {}: {} ({})", item.priority(), item.category, item.summary, item.fix)).style(style)
+  }
+}
+
+impl Component for HealthReport {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Library health: {}/100", self.health.score));
+    f.render_widget(Clear, area);
+    if self.health.items.is_empty() {
+      f.render_widget(Paragraph::new("Nothing to do").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.health.items.iter().map(Self::item_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(Paragraph::new("<Enter> fix, <r> check again, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Health)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::HealthReport(health) = action {
+      self.health = health;
+      self.list_state.select(if self.health.items.is_empty() { None } else { Some(0) });
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.health.items.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        let Some(item) = self.list_state.selected().and_then(|index| self.health.items.get(index)) else {
+          return Ok(None);
+        };
+        let actions = item.fix.actions();
+        if actions.is_empty() {
+          return Ok(Some(Action::Notify(format!("{}: {}", item.category, item.fix))));
+        }
+        if let Some(action_tx) = &self.action_tx {
+          for action in actions {
+            action_tx.send(action)?;
+          }
+        }
+      },
+      KeyCode::Char('r') => return Ok(Some(Action::HealthShow)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The downloads next to the output of yt-dlp for the selected one
+#[derive(Default)]
+pub struct DownloadLogs {
+  config: Config,
+  list: DownloadLogList,
+  list_state: ListState,
+  /// the log of the selected download
+  log: String,
+  /// the first line of the log shown, `None` to follow its end
+  scroll: Option<u16>,
+}
+
+impl DownloadLogs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn entry_line(entry: &DownloadLogEntry) -> ListItem<'static> {
+    let style = match entry.status {
+      DownloadLogStatus::Failed(..) => Style::default().fg(Color::Red),
+      DownloadLogStatus::Downloading => Style::default().fg(Color::Yellow),
+      DownloadLogStatus::Queued | DownloadLogStatus::Finished(..) => Style::default(),
+    };
+    ListItem::new(format!("{} ({})", entry.title, entry.status)).style(style)
+  }
+
+  /// Read the log of the selected download
+  fn load_log(&mut self) {
+    self.scroll = None;
+    let Some(entry) = self.list_state.selected().and_then(|index| self.list.entries.get(index)) else {
+      self.log = String::new();
+      return;
+    };
+    self.log = match download_logs::read(&download_logs::log_dir(&self.config), &entry.youtube_id) {
+      Ok(Some(log)) => log,
+      Ok(None) => format!("yt-dlp has not run for {} yet", entry.youtube_id),
+      Err(e) => format!("failed to read the log of {}: {e}", entry.youtube_id),
+    };
+  }
+
+  fn select(&mut self, index: usize) {
+    self.list_state.select(Some(index));
+    self.load_log();
+  }
+}
+
+impl Component for DownloadLogs {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let columns =
+      Layout::new(Direction::Horizontal, [Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)]).split(layout[0]);
+    f.render_widget(Clear, area);
+    let block = Block::default().borders(Borders::ALL).title("Downloads");
+    if self.list.entries.is_empty() {
+      f.render_widget(Paragraph::new("Nothing downloaded yet").block(block), columns[0]);
+    } else {
+      let items: Vec<_> = self.list.entries.iter().map(Self::entry_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), columns[0], &mut self.list_state);
+    }
+    // show the latest attempt unless scrolled
+    let lines = self.log.lines().count() as u16;
+    let last_page = lines.saturating_sub(columns[1].height.saturating_sub(2));
+    let scroll = self.scroll.unwrap_or(last_page).min(last_page);
+    let log = Paragraph::new(self.log.as_str())
+      .block(Block::default().borders(Borders::ALL).title("yt-dlp output"))
+      .wrap(Wrap { trim: false })
+      .scroll((scroll, 0));
+    f.render_widget(log, columns[1]);
+    f.render_widget(Paragraph::new("<j>/<k> select, <J>/<K> scroll, <r> reload, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::DownloadLogs)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_config_handler(&mut self, config: Config) -> Result<()> {
+    self.config = config;
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::DownloadLogs(list) = action {
+      self.list_state.select(list.selected);
+      self.list = list;
+      self.load_log();
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || !matches!(key.modifiers, KeyModifiers::NONE | KeyModifiers::SHIFT) {
+      return Ok(None);
+    }
+    let count = self.list.entries.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        self.select(self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default());
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        self.select(self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1));
+      },
+      KeyCode::Char('J') | KeyCode::PageDown => {
+        let lines = self.log.lines().count() as u16;
+        self.scroll = Some(self.scroll.unwrap_or(lines).saturating_add(1).min(lines));
+      },
+      KeyCode::Char('K') | KeyCode::PageUp => {
+        let lines = self.log.lines().count() as u16;
+        self.scroll = Some(self.scroll.unwrap_or(lines).saturating_sub(1));
+      },
+      KeyCode::Char('r') => return Ok(Some(Action::DownloadLogsShow)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The backups of the database, to make one or restore one
+#[derive(Default)]
+pub struct Backups {
+  backups: Vec<Backup>,
+  list_state: ListState,
+  /// the backup to restore once confirmed
+  confirming: Option<usize>,
+}
+
+impl Backups {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn backup_line(backup: &Backup) -> ListItem<'static> {
+    let size = backup.size as f64 / (1024.0 * 1024.0);
+    ListItem::new(format!("{}  {size:.1} MiB", backup.created_at.format("%Y-%m-%d %H:%M:%S")))
+  }
+}
+
+impl Component for Backups {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Backups ({})", self.backups.len()));
+    f.render_widget(Clear, area);
+    if self.backups.is_empty() {
+      f.render_widget(Paragraph::new("No backups yet").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.backups.iter().map(Self::backup_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    let help = if self.confirming.is_some() {
+      Paragraph::new("<Enter> replace the database with this backup, any other key to cancel").fg(Color::Yellow)
+    } else {
+      Paragraph::new("<Enter> restore this backup, <b> back up now, <Esc> close")
+    };
+    f.render_widget(help, layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Backups)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::BackupsUpdate(backups) = action {
+      self.backups = backups;
+      self.confirming = None;
+      self.list_state.select(if self.backups.is_empty() { None } else { Some(0) });
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let selected = self.list_state.selected();
+    if let Some(index) = self.confirming.take() {
+      if key.code == KeyCode::Enter && selected == Some(index) {
+        return Ok(self.backups.get(index).map(|backup| Action::BackupRestore(backup.path.clone())));
+      }
+      return Ok(None);
+    }
+    let count = self.backups.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        self.list_state.select(Some(selected.map(|index| (index + 1) % count).unwrap_or_default()));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        self.list_state.select(Some(selected.and_then(|index| index.checked_sub(1)).unwrap_or(count - 1)));
+      },
+      KeyCode::Enter => self.confirming = selected,
+      KeyCode::Char('b') => return Ok(Some(Action::BackupCreate)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The snapshots of the library, to take one before a large import or bulk edit and list the songs
+/// added, removed and changed since
+#[derive(Default)]
+pub struct Snapshots {
+  snapshots: Vec<Snapshot>,
+  list_state: ListState,
+  /// the snapshot marked to be compared with another one
+  marked: Option<i32>,
+  diff: Option<SnapshotDiff>,
+  /// how far the changes are scrolled down
+  scroll: u16,
+  /// the snapshot to delete once confirmed
+  confirming: Option<i32>,
+}
+
+impl Snapshots {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn selected(&self) -> Option<&Snapshot> {
+    self.list_state.selected().and_then(|index| self.snapshots.get(index))
+  }
+
+  fn song_line(symbol: &'static str, song: &SnapshotSong, note: Option<&str>, color: Color) -> Line<'static> {
+    let song = match song.artists.is_empty() {
+      true => song.title.clone(),
+      false => format!("{} - {}", song.artists, song.title),
+    };
+    let note = note.map(|note| Span::raw(format!(" ({note})")).fg(Color::DarkGray)).unwrap_or_default();
+    Line::from(vec![Span::raw(format!("{symbol} {song}")).fg(color), note])
+  }
+
+  fn diff_lines(diff: &SnapshotDiff) -> Vec<Line<'static>> {
+    if diff.is_empty() {
+      return vec![Line::from("Nothing changed")];
+    }
+    let added = diff.added.iter().map(|song| Self::song_line("+", song, None, Color::Green));
+    let removed = diff.removed.iter().map(|song| Self::song_line("-", song, None, Color::Red));
+    let changed = diff.changed.iter().map(|change| {
+      let renamed = (change.before.title != change.after.title).then(|| format!("was {}", change.before.title));
+      let note = renamed.map_or_else(|| change.what().to_string(), |renamed| format!("{}, {renamed}", change.what()));
+      Self::song_line("~", &change.after, Some(&note), Color::Yellow)
+    });
+    added.chain(removed).chain(changed).collect()
+  }
+}
+
+impl Component for Snapshots {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let panes =
+      Layout::new(Direction::Horizontal, [Constraint::Percentage(35), Constraint::Percentage(65)]).split(layout[0]);
+    f.render_widget(Clear, area);
+
+    let block = Block::default().borders(Borders::ALL).title(format!("Snapshots ({})", self.snapshots.len()));
+    if self.snapshots.is_empty() {
+      f.render_widget(Paragraph::new("No snapshots yet, <n> to take one").block(block), panes[0]);
+    } else {
+      let items: Vec<_> = self
+        .snapshots
+        .iter()
+        .map(|snapshot| {
+          let marked = if self.marked == Some(snapshot.id) { Span::raw("* ").fg(Color::Yellow) } else { Span::raw("") };
+          let taken_at = Span::raw(format!("  {}", snapshot.taken_at.format("%Y-%m-%d %H:%M"))).fg(Color::DarkGray);
+          ListItem::new(Line::from(vec![marked, Span::raw(snapshot.name.clone()), taken_at]))
+        })
+        .collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), panes[0], &mut self.list_state);
+    }
+
+    let (title, lines) = match &self.diff {
+      Some(diff) => (format!("{} ({})", diff.name, diff.summary()), Self::diff_lines(diff)),
+      None => ("Changes".to_string(), vec![Line::from("<Enter> to list what changed since the selected snapshot")]),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    f.render_widget(Paragraph::new(lines).block(block).scroll((self.scroll, 0)), panes[1]);
+
+    let help = if self.confirming.is_some() {
+      Paragraph::new("<Enter> delete this snapshot, any other key to cancel").fg(Color::Yellow)
+    } else {
+      Paragraph::new(
+        "<Enter> compare with now, <m> mark, <c> compare with the marked one, <n> take one, <d> delete, <J>/<K> scroll, \
+         <Esc> close",
+      )
+    };
+    f.render_widget(help, layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Snapshots)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::SnapshotsUpdate(snapshots) => {
+        self.snapshots = snapshots;
+        self.confirming = None;
+        self.marked = self.marked.filter(|id| self.snapshots.iter().any(|snapshot| snapshot.id == *id));
+        self.list_state.select(if self.snapshots.is_empty() { None } else { Some(0) });
+      },
+      Action::SnapshotDiffUpdate(diff) => {
+        self.diff = Some(diff);
+        self.scroll = 0;
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"snapshot_name" => {
+        return Ok(Some(Action::SnapshotTake(buffer)));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || !matches!(key.modifiers, KeyModifiers::NONE | KeyModifiers::SHIFT) {
+      return Ok(None);
+    }
+    let selected = self.selected().cloned();
+    if let Some(snapshot_id) = self.confirming.take() {
+      if key.code == KeyCode::Enter && selected.is_some_and(|snapshot| snapshot.id == snapshot_id) {
+        return Ok(Some(Action::SnapshotDelete(snapshot_id)));
+      }
+      return Ok(None);
+    }
+    let count = self.snapshots.len();
+    match (key.code, selected) {
+      (KeyCode::Char('j') | KeyCode::Down, Some(_)) => {
+        self.list_state.select(Some(self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default()));
+      },
+      (KeyCode::Char('k') | KeyCode::Up, Some(_)) => {
+        self
+          .list_state
+          .select(Some(self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1)));
+      },
+      (KeyCode::Char('J'), _) => self.scroll = self.scroll.saturating_add(1),
+      (KeyCode::Char('K'), _) => self.scroll = self.scroll.saturating_sub(1),
+      (KeyCode::Enter, Some(snapshot)) => return Ok(Some(Action::SnapshotCompare(snapshot.id, None))),
+      (KeyCode::Char('m'), Some(snapshot)) => {
+        self.marked = if self.marked == Some(snapshot.id) { None } else { Some(snapshot.id) };
+      },
+      (KeyCode::Char('c'), Some(snapshot)) => {
+        let Some(marked) = self.snapshots.iter().find(|marked| Some(marked.id) == self.marked) else {
+          return Ok(Some(Action::Error("mark a snapshot to compare with <m> first".to_string())));
+        };
+        // the older snapshot is the one compared from
+        let (from, to) = if marked.taken_at <= snapshot.taken_at { (marked, &snapshot) } else { (&snapshot, marked) };
+        return Ok(Some(Action::SnapshotCompare(from.id, Some(to.id))));
+      },
+      (KeyCode::Char('n'), _) => {
+        return Ok(Some(Action::InputModeOn(InputIn { input_name: "snapshot_name".to_string(), initial_value: None })));
+      },
+      (KeyCode::Char('d'), Some(snapshot)) => self.confirming = Some(snapshot.id),
+      (KeyCode::Esc, _) => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The playlists stored in the library, to create them and arrange their songs. The songs are
+/// added to a playlist from the song list
+#[derive(Default)]
+pub struct Playlists {
+  config: Option<Config>,
+  action_tx: Option<UnboundedSender<Action>>,
+  playlists: Vec<(Playlist, Vec<Song>)>,
+  /// the names of the export profiles of the playlists, by playlist id
+  profiles: HashMap<i32, Vec<String>>,
+  playlist_state: ListState,
+  song_state: ListState,
+  /// the songs of the selected playlist are focused rather than the playlists
+  songs_focused: bool,
+  /// the playlist being renamed through the input
+  renaming: Option<i32>,
+  /// the playlist whose export profiles are picked through the input
+  profiling: Option<i32>,
+  /// the playlist to delete once confirmed
+  confirming: Option<i32>,
+}
+
+impl Playlists {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn selected(&self) -> Option<&(Playlist, Vec<Song>)> {
+    self.playlist_state.selected().and_then(|index| self.playlists.get(index))
+  }
+
+  fn select_playlist(&mut self, index: Option<usize>) {
+    self.playlist_state.select(index);
+    let songs = self.selected().map_or(0, |(_, songs)| songs.len());
+    self.song_state.select(if songs == 0 { None } else { Some(0) });
+  }
+}
+
+impl Component for Playlists {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let panes =
+      Layout::new(Direction::Horizontal, [Constraint::Percentage(35), Constraint::Percentage(65)]).split(layout[0]);
+    let border = |focused: bool| if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+      .borders(Borders::ALL)
+      .border_style(border(!self.songs_focused))
+      .title(format!("Playlists ({})", self.playlists.len()));
+    if self.playlists.is_empty() {
+      f.render_widget(Paragraph::new("No playlists yet, <n> to create one").block(block), panes[0]);
+    } else {
+      let items: Vec<_> = self
+        .playlists
+        .iter()
+        .map(|(playlist, songs)| {
+          let profiles = match self.profiles.get(&playlist.id) {
+            Some(profiles) => Span::raw(format!(" -> {}", profiles.join(", "))).fg(Color::DarkGray),
+            None => Span::raw(""),
+          };
+          ListItem::new(Line::from(vec![Span::raw(format!("{} ({})", playlist.name, songs.len())), profiles]))
+        })
+        .collect();
+      f.render_stateful_widget(
+        List::new(items).highlight_symbol(">>").block(block),
+        panes[0],
+        &mut self.playlist_state,
+      );
+    }
+
+    let (name, songs) = match self.selected() {
+      Some((playlist, songs)) => (playlist.name.clone(), songs.iter().map(|song| song.title.clone()).collect()),
+      None => (String::new(), vec![]),
+    };
+    let block = Block::default()
+      .borders(Borders::ALL)
+      .border_style(border(self.songs_focused))
+      .title(format!("{name} ({} songs)", songs.len()));
+    if songs.is_empty() {
+      f.render_widget(Paragraph::new("Add songs with <a> in the song list").block(block), panes[1]);
+    } else {
+      let items: Vec<_> = songs
+        .into_iter()
+        .enumerate()
+        .map(|(index, title)| ListItem::new(format!("{:>3}. {title}", index + 1)))
+        .collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), panes[1], &mut self.song_state);
+    }
+
+    let help = if self.confirming.is_some() {
+      Paragraph::new("<Enter> delete this playlist, any other key to cancel").fg(Color::Yellow)
+    } else if self.songs_focused {
+      Paragraph::new("<J>/<K> move the song down or up, <x> remove it, <Tab> playlists, <Esc> close")
+    } else {
+      Paragraph::new(
+        "<Enter> open in a tab, <n> new, <r> rename, <d> delete, <e> export, <p> pick export profiles, <E> export with \
+         them, <Tab> songs, <Esc> close",
+      )
+    };
+    f.render_widget(help, layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Playlists)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn register_config_handler(&mut self, config: Config) -> Result<()> {
+    self.config = Some(config);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::PlaylistsUpdate(playlists) => {
+        // the selected playlist stays selected, wherever its new name puts it
+        let selected_id = self.selected().map(|(playlist, _)| playlist.id);
+        let song_index = self.song_state.selected();
+        self.playlists = playlists;
+        self.confirming = None;
+        let index = selected_id
+          .and_then(|id| self.playlists.iter().position(|(playlist, _)| playlist.id == id))
+          .or_else(|| self.playlist_state.selected().map(|index| index.min(self.playlists.len().saturating_sub(1))))
+          .or(Some(0))
+          .filter(|_| !self.playlists.is_empty());
+        self.playlist_state.select(index);
+        let songs = self.selected().map_or(0, |(_, songs)| songs.len());
+        self.song_state.select(if songs == 0 { None } else { Some(song_index.unwrap_or_default().min(songs - 1)) });
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"playlist_name" => {
+        return Ok(Some(Action::PlaylistCreate(buffer)));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"playlist_rename" => {
+        return Ok(self.renaming.take().map(|playlist_id| Action::PlaylistRename(playlist_id, buffer)));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == *"playlist_profiles" => {
+        let profiles = playlist_export::parse_profiles(&buffer);
+        return Ok(self.profiling.take().map(|playlist_id| Action::PlaylistSetProfiles(playlist_id, profiles)));
+      },
+      Action::PlaylistsProfiles(profiles) => self.profiles = profiles,
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer })
+        if input_name == *"playlists_destination" =>
+      {
+        let destination = buffer.trim();
+        if destination.is_empty() {
+          return Ok(Some(Action::Error("no folder to export the playlists to".to_string())));
+        }
+        return Ok(Some(Action::PlaylistsExportTo(PathBuf::from(destination))));
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || !matches!(key.modifiers, KeyModifiers::NONE | KeyModifiers::SHIFT) {
+      return Ok(None);
+    }
+    if let Some(playlist_id) = self.confirming.take() {
+      if key.code == KeyCode::Enter && self.selected().is_some_and(|(playlist, _)| playlist.id == playlist_id) {
+        return Ok(Some(Action::PlaylistDelete(playlist_id)));
+      }
+      return Ok(None);
+    }
+    if key.code == KeyCode::Char('e') {
+      let directory = self.config.as_ref().map(|config| playlists::directory(config).display().to_string());
+      return Ok(Some(Action::InputModeOn(InputIn {
+        input_name: "playlists_destination".to_string(),
+        initial_value: directory,
+      })));
+    }
+    let Some((playlist, songs)) = self.selected().cloned() else {
+      return Ok(match key.code {
+        KeyCode::Char('n') => {
+          Some(Action::InputModeOn(InputIn { input_name: "playlist_name".to_string(), initial_value: None }))
+        },
+        KeyCode::Esc => Some(Action::FocusBack),
+        _ => None,
+      });
+    };
+    let song_index = self.song_state.selected();
+    match (key.code, self.songs_focused) {
+      (KeyCode::Tab | KeyCode::BackTab, _) => self.songs_focused = !self.songs_focused,
+      (KeyCode::Char('j') | KeyCode::Down, false) => {
+        let count = self.playlists.len();
+        self.select_playlist(Some(self.playlist_state.selected().map(|index| (index + 1) % count).unwrap_or_default()));
+      },
+      (KeyCode::Char('k') | KeyCode::Up, false) => {
+        let count = self.playlists.len();
+        self.select_playlist(Some(
+          self.playlist_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1),
+        ));
+      },
+      (KeyCode::Char('j') | KeyCode::Down, true) if !songs.is_empty() => {
+        self.song_state.select(Some(song_index.map(|index| (index + 1) % songs.len()).unwrap_or_default()));
+      },
+      (KeyCode::Char('k') | KeyCode::Up, true) if !songs.is_empty() => {
+        self.song_state.select(Some(song_index.and_then(|index| index.checked_sub(1)).unwrap_or(songs.len() - 1)));
+      },
+      (KeyCode::Char('J'), true) => {
+        if let Some(index) = song_index.filter(|index| index + 1 < songs.len()) {
+          self.song_state.select(Some(index + 1));
+          return Ok(Some(Action::PlaylistMoveSong(playlist.id, index, index + 1)));
+        }
+      },
+      (KeyCode::Char('K'), true) => {
+        if let Some(index) = song_index.filter(|index| *index > 0) {
+          self.song_state.select(Some(index - 1));
+          return Ok(Some(Action::PlaylistMoveSong(playlist.id, index, index - 1)));
+        }
+      },
+      (KeyCode::Char('x'), true) => return Ok(song_index.map(|index| Action::PlaylistRemoveSong(playlist.id, index))),
+      (KeyCode::Enter, false) => {
+        if let Some(action_tx) = &self.action_tx {
+          action_tx.send(Action::ManagerTabOpen(TabFilter::Playlist(playlist.name)))?;
+          return Ok(Some(Action::FocusBack));
+        }
+      },
+      (KeyCode::Char('n'), false) => {
+        return Ok(Some(Action::InputModeOn(InputIn { input_name: "playlist_name".to_string(), initial_value: None })));
+      },
+      (KeyCode::Char('r'), false) => {
+        self.renaming = Some(playlist.id);
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "playlist_rename".to_string(),
+          initial_value: Some(playlist.name),
+        })));
+      },
+      (KeyCode::Char('d'), false) => self.confirming = Some(playlist.id),
+      (KeyCode::Char('p'), false) => {
+        self.profiling = Some(playlist.id);
+        let profiles = self.profiles.get(&playlist.id).map(|profiles| profiles.join(", "));
+        return Ok(Some(Action::InputModeOn(InputIn {
+          input_name: "playlist_profiles".to_string(),
+          initial_value: profiles,
+        })));
+      },
+      (KeyCode::Char('E'), false) => {
+        if !self.profiles.contains_key(&playlist.id) {
+          return Ok(Some(Action::Error(format!("pick the export profiles of {} with <p> first", playlist.name))));
+        }
+        return Ok(Some(Action::PlaylistExportProfiles(playlist.id)));
+      },
+      (KeyCode::Esc, _) => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The songs whose video is gone, each with the video found to link it to instead
+#[derive(Default)]
+pub struct DeadLinks {
+  dead_links: Vec<DeadLink>,
+  list_state: ListState,
+}
+
+impl DeadLinks {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn dead_link_line(dead_link: &DeadLink) -> ListItem<'static> {
+    let song = match dead_link.artists.is_empty() {
+      true => dead_link.title.clone(),
+      false => format!("{} - {}", dead_link.artists.join(", "), dead_link.title),
+    };
+    let suggestion = match &dead_link.suggestion {
+      Some(suggestion) => Span::raw(format!(" -> {} ({}%)", suggestion.title, suggestion.score)),
+      None => Span::raw(" -> nothing found").fg(Color::DarkGray),
+    };
+    ListItem::new(Line::from(vec![
+      Span::raw(song),
+      Span::raw(format!(" [{}: {}]", dead_link.youtube_id, dead_link.reason)).fg(Color::Red),
+      suggestion,
+    ]))
+  }
+}
+
+impl Component for DeadLinks {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Dead videos ({})", self.dead_links.len()));
+    f.render_widget(Clear, area);
+    if self.dead_links.is_empty() {
+      f.render_widget(Paragraph::new("No dead videos found").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.dead_links.iter().map(Self::dead_link_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(
+      Paragraph::new("<Enter> link to the video found, <c> check the videos now, <Esc> close"),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::DeadLinks)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::DeadLinks(dead_links) = action {
+      let selected = self.list_state.selected().unwrap_or_default().min(dead_links.len().saturating_sub(1));
+      self.dead_links = dead_links;
+      self.list_state.select((!self.dead_links.is_empty()).then_some(selected));
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.dead_links.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        let Some(dead_link) = self.list_state.selected().and_then(|index| self.dead_links.get(index)) else {
+          return Ok(None);
+        };
+        return match &dead_link.suggestion {
+          Some(suggestion) => Ok(Some(Action::DeadLinkRelink(dead_link.song_id, suggestion.youtube_id.clone()))),
+          None => Ok(Some(Action::Notify(format!("no video was found to replace {}", dead_link.title)))),
+        };
+      },
+      KeyCode::Char('c') => return Ok(Some(Action::LinksCheck)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The songs whose file is gone or was found damaged
+#[derive(Default)]
+pub struct Integrity {
+  problems: Vec<IntegrityProblem>,
+  list_state: ListState,
+}
+
+impl Integrity {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn problem_line(problem: &IntegrityProblem) -> ListItem<'static> {
+    let song = match problem.artists.is_empty() {
+      true => problem.title.clone(),
+      false => format!("{} - {}", problem.artists.join(", "), problem.title),
+    };
+    ListItem::new(Line::from(vec![
+      Span::raw(song),
+      Span::raw(format!(" [{}]", problem.path)).fg(Color::DarkGray),
+      Span::raw(format!(" {}", problem.problem)).fg(Color::Red),
+    ]))
+  }
+}
+
+impl Component for Integrity {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Damaged files ({})", self.problems.len()));
+    f.render_widget(Clear, area);
+    if self.problems.is_empty() {
+      f.render_widget(Paragraph::new("No missing or damaged files found, <v> to verify them").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.problems.iter().map(Self::problem_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(
+      Paragraph::new("<Enter> download a missing song again, <v> verify the files now, <r> refresh, <Esc> close"),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Integrity)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::Integrity(problems) = action {
+      let selected = self.list_state.selected().unwrap_or_default().min(problems.len().saturating_sub(1));
+      self.problems = problems;
+      self.list_state.select((!self.problems.is_empty()).then_some(selected));
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.problems.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        let Some(problem) = self.list_state.selected().and_then(|index| self.problems.get(index)) else {
+          return Ok(None);
+        };
+        if !problem.missing {
+          return Ok(Some(Action::Notify(format!("move {} out of the music dir to download it again", problem.path))));
+        }
+        let Some(youtube_id) = problem.youtube_id.clone() else {
+          return Ok(Some(Action::Notify(format!("{} was not downloaded from a video", problem.title))));
+        };
+        let request = DownloadRequest {
+          youtube_id,
+          title: problem.title.clone(),
+          artists: problem.artists.clone(),
+          duplicates: Some(DuplicatePolicy::KeepBoth),
+          ..Default::default()
+        };
+        return Ok(Some(Action::DownloadEnqueue(vec![request])));
+      },
+      KeyCode::Char('v') => return Ok(Some(Action::VerifyLibrary)),
+      KeyCode::Char('r') => return Ok(Some(Action::IntegrityShow)),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The better uploads found for a low quality song, to replace its file with
+#[derive(Default)]
+pub struct UpgradePicker {
+  offer: UpgradeOffer,
+  list_state: ListState,
+  action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl UpgradePicker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn upgrade_line(upgrade: &Upgrade) -> ListItem<'static> {
+    let channel = upgrade.channel.as_ref().map(|channel| format!(" ({channel})")).unwrap_or_default();
+    let duration = upgrade.duration.map(Trim::time).unwrap_or_else(|| "?:??".to_string());
+    ListItem::new(Line::from(vec![
+      Span::raw(format!("{}{channel}", upgrade.title)),
+      Span::raw(format!(" {} kbit/s, {duration}, {}% match", upgrade.bitrate, upgrade.score)).fg(Color::DarkGray),
+    ]))
+  }
+}
+
+impl Component for UpgradePicker {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let quality = self.offer.quality.as_ref().map(ToString::to_string).unwrap_or_else(|| "unknown quality".to_string());
+    let duration = self.offer.duration.map(Trim::time).unwrap_or_else(|| "?:??".to_string());
+    let block = Block::default()
+      .borders(Borders::ALL)
+      .title(format!("Better versions of {} ({quality}, {duration})", self.offer.title));
+    let items: Vec<_> = self.offer.upgrades.iter().map(Self::upgrade_line).collect();
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    f.render_widget(Paragraph::new("<Enter> replace the file with this upload, <Esc> cancel"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Upgrade)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::UpgradeOffer(offer) = action {
+      self.offer = offer;
+      self.list_state.select((!self.offer.upgrades.is_empty()).then_some(0));
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.offer.upgrades.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        if let (Some(upgrade), Some(action_tx)) =
+          (self.list_state.selected().and_then(|index| self.offer.upgrades.get(index)), &self.action_tx)
+        {
+          action_tx.send(Action::UpgradeReplace(self.offer.song_id, upgrade.youtube_id.clone()))?;
+          return Ok(Some(Action::FocusBack));
+        }
+      },
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The downloads of songs in the library already, waiting to be told what to do
+#[derive(Default)]
+pub struct Duplicates {
+  items: Vec<QueueItem>,
+  list_state: ListState,
+}
+
+impl Duplicates {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn item_line(item: &QueueItem) -> ListItem<'static> {
+    let existing = match &item.status {
+      QueueStatus::Duplicate(title) => title.clone(),
+      _ => String::new(),
+    };
+    let download = match item.request.artists.is_empty() {
+      true => item.request.title.clone(),
+      false => format!("{} - {}", item.request.artists.join(", "), item.request.title),
+    };
+    ListItem::new(Line::from(vec![
+      Span::raw(download),
+      Span::raw(format!(" [{}]", item.request.youtube_id)).fg(Color::DarkGray),
+      Span::raw(format!(" same as {existing}")).fg(Color::Yellow),
+    ]))
+  }
+}
+
+impl Component for Duplicates {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block =
+      Block::default().borders(Borders::ALL).title(format!("Downloads in the library already ({})", self.items.len()));
+    f.render_widget(Clear, area);
+    if self.items.is_empty() {
+      f.render_widget(Paragraph::new("No downloads waiting for a decision").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.items.iter().map(Self::item_line).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(Paragraph::new("<s> skip, <r> replace if better, <b> keep both, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Duplicates)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    if let Action::Duplicates(items) = action {
+      let selected = self.list_state.selected().unwrap_or_default().min(items.len().saturating_sub(1));
+      self.items = items;
+      self.list_state.select((!self.items.is_empty()).then_some(selected));
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.items.len();
+    let policy = match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+        return Ok(None);
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+        return Ok(None);
+      },
+      KeyCode::Char('s') => DuplicatePolicy::Skip,
+      KeyCode::Char('r') => DuplicatePolicy::ReplaceIfBetter,
+      KeyCode::Char('b') => DuplicatePolicy::KeepBoth,
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => return Ok(None),
+    };
+    Ok(
+      self
+        .list_state
+        .selected()
+        .and_then(|index| self.items.get(index))
+        .map(|item| Action::DuplicateResolve(item.id, policy)),
+    )
+  }
+}
+
+/// The downloads whose metadata was guessed, held to be approved, corrected or rejected before
+/// they enter the library. The title, artists and album are corrected here and sent along with the
+/// approval
+#[derive(Default)]
+pub struct Review {
+  items: Vec<QueueItem>,
+  /// the queue ids of the items corrected here
+  edited: HashSet<u64>,
+  list_state: ListState,
+}
+
+impl Review {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn item_line(&self, item: &QueueItem) -> ListItem<'static> {
+    let doubts = match &item.status {
+      QueueStatus::Review(doubts) if !self.edited.contains(&item.id) => doubts.clone(),
+      _ => "corrected".to_string(),
+    };
+    let album = item.request.album.as_ref().map(|album| format!(" [{album}]")).unwrap_or_default();
+    ListItem::new(Line::from(vec![
+      Span::raw(format!("{} - {}{album}", item.request.artists.join(", "), item.request.title)),
+      Span::raw(format!(" [{}]", item.request.youtube_id)).fg(Color::DarkGray),
+      Span::raw(format!(" {doubts}")).fg(Color::Yellow),
+    ]))
+  }
+
+  fn selected(&self) -> Option<&QueueItem> {
+    self.list_state.selected().and_then(|index| self.items.get(index))
+  }
+
+  /// Prompt for a field of the selected download, starting from its value
+  fn edit(&self, input_name: &str, value: impl FnOnce(&DownloadRequest) -> String) -> Option<Action> {
+    let item = self.selected()?;
+    Some(Action::InputModeOn(InputIn { input_name: input_name.to_string(), initial_value: Some(value(&item.request)) }))
+  }
+}
+
+impl Component for Review {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    if !self.is_focused(focus) {
+      return Ok(());
+    }
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Downloads to review ({})", self.items.len()));
+    f.render_widget(Clear, area);
+    if self.items.is_empty() {
+      f.render_widget(Paragraph::new("No downloads waiting for review").block(block), layout[0]);
+    } else {
+      let items: Vec<_> = self.items.iter().map(|item| self.item_line(item)).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    }
+    f.render_widget(Paragraph::new("<Enter> approve, <t>itle, <a>rtists, a<l>bum, <x> reject, <Esc> close"), layout[1]);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::Review)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::Reviews(items) => {
+        // corrections not sent yet survive the refresh
+        let mut previous: Vec<_> = std::mem::take(&mut self.items);
+        self.items = items
+          .into_iter()
+          .map(|item| {
+            match previous.iter().position(|edited| edited.id == item.id && self.edited.contains(&item.id)) {
+              Some(index) => previous.swap_remove(index),
+              None => item,
+            }
+          })
+          .collect();
+        self.edited.retain(|id| self.items.iter().any(|item| item.id == *id));
+        let selected = self.list_state.selected().unwrap_or_default().min(self.items.len().saturating_sub(1));
+        self.list_state.select((!self.items.is_empty()).then_some(selected));
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) => {
+        let value = buffer.trim().to_string();
+        let Some(item) = self.list_state.selected().and_then(|index| self.items.get_mut(index)) else {
+          return Ok(None);
+        };
+        match input_name.as_str() {
+          "review_title" if !value.is_empty() => item.request.title = value,
+          "review_artists" => {
+            item.request.artists =
+              value.split(',').map(str::trim).filter(|artist| !artist.is_empty()).map(str::to_string).collect();
+          },
+          "review_album" => item.request.album = Some(value).filter(|album| !album.is_empty()),
+          _ => return Ok(None),
+        }
+        self.edited.insert(item.id);
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let count = self.items.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down if count > 0 => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up if count > 0 => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('t') => return Ok(self.edit("review_title", |request| request.title.clone())),
+      KeyCode::Char('a') => return Ok(self.edit("review_artists", |request| request.artists.join(", "))),
+      KeyCode::Char('l') => {
+        return Ok(self.edit("review_album", |request| request.album.clone().unwrap_or_default()));
+      },
+      KeyCode::Enter => {
+        return Ok(self.selected().map(|item| {
+          let verdict = match self.edited.contains(&item.id) {
+            true => {
+              Verdict::Edit {
+                title: item.request.title.clone(),
+                artists: item.request.artists.clone(),
+                album: item.request.album.clone(),
+              }
+            },
+            false => Verdict::Approve,
+          };
+          Action::ReviewVerdict(item.id, verdict)
+        }));
+      },
+      KeyCode::Char('x') => return Ok(self.selected().map(|item| Action::ReviewVerdict(item.id, Verdict::Reject))),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
+}
+
+/// The editor of the metadata of a song, a field at a time through the input bar
+#[derive(Default)]
+pub struct SongEditor {
+  /// the metadata as loaded, to show which fields were edited
+  original: SongEdit,
+  edit: Option<SongEdit>,
+  list_state: ListState,
+  /// the provider picked to look up the release of the song, while picking one
+  provider_state: Option<ListState>,
+}
+
+impl SongEditor {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn selected_field(&self) -> Option<SongField> {
+    self.list_state.selected().and_then(|index| EDITED_FIELDS.get(index).copied())
+  }
+
+  fn field_line(&self, edit: &SongEdit, field: SongField) -> ListItem<'static> {
+    let label = match field {
+      SongField::Title => "Title",
+      SongField::Artists => "Artists",
+      SongField::Album => "Album",
+      SongField::Genres => "Genres",
+      _ => "YouTube ID",
+    };
+    let value = edit.value(field);
+    let mut spans = vec![Span::raw(format!("{label:<12}")).bold()];
+    match value != self.original.value(field) {
+      true => spans.extend([Span::raw(value).fg(Color::Yellow), Span::raw(" (edited)").fg(Color::DarkGray)]),
+      false => spans.push(Span::raw(value)),
+    }
+    ListItem::new(Line::from(spans))
+  }
+}
+
+impl Component for SongEditor {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    let Some(edit) = self.edit.as_ref().filter(|_| self.is_focused(focus)) else {
+      return Ok(());
+    };
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let block = Block::default().borders(Borders::ALL).title(format!("Edit song: {}", self.original.title));
+    f.render_widget(Clear, area);
+    if let Some(provider_state) = self.provider_state.as_mut() {
+      let block = block.title(format!("Look up {} on", edit.release_query().title));
+      let items: Vec<_> = Provider::iter().map(|provider| ListItem::new(provider.to_string())).collect();
+      f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], provider_state);
+      f.render_widget(Paragraph::new("<Enter> look up the release, <Esc> back to the fields"), layout[1]);
+      return Ok(());
+    }
+    let items: Vec<_> = EDITED_FIELDS.iter().map(|field| self.field_line(edit, *field)).collect();
+    let write_tags = if edit.write_tags { "on" } else { "off" };
+    f.render_stateful_widget(List::new(items).highlight_symbol(">>").block(block), layout[0], &mut self.list_state);
+    f.render_widget(
+      Paragraph::new(format!(
+        "<Enter> edit the field, <l> look up the release, <w> write the tags: {write_tags}, <s> save, <Esc> cancel"
+      )),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::Manager(ManagerLayouts::SongEditor)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Manager
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::SongEditorLoaded(edit) => {
+        self.original = edit.clone();
+        self.edit = Some(edit);
+        self.list_state.select(Some(0));
+        self.provider_state = None;
+      },
+      Action::SongEditorRelease(release) => {
+        if let Some(edit) = self.edit.as_mut() {
+          edit.apply_release(&release);
+        }
+      },
+      Action::InputModeOff(InputOut { input_name: Some(input_name), buffer }) if input_name == "song_editor" => {
+        if let (Some(field), Some(edit)) = (self.selected_field(), self.edit.as_mut()) {
+          edit.set_value(field, &buffer);
+        }
+      },
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if !self.is_focused(focus) || key.modifiers != KeyModifiers::NONE {
+      return Ok(None);
+    }
+    let field = self.selected_field();
+    let Some(edit) = self.edit.as_mut() else {
+      return Ok(None);
+    };
+    if let Some(provider_state) = self.provider_state.as_mut() {
+      let providers: Vec<_> = Provider::iter().collect();
+      match key.code {
+        KeyCode::Char('j') | KeyCode::Down => {
+          provider_state.select(provider_state.selected().map(|index| (index + 1) % providers.len()));
+        },
+        KeyCode::Char('k') | KeyCode::Up => {
+          let index = provider_state.selected().and_then(|index| index.checked_sub(1));
+          provider_state.select(Some(index.unwrap_or(providers.len() - 1)));
+        },
+        KeyCode::Enter => {
+          let provider = provider_state.selected().and_then(|index| providers.get(index).copied());
+          self.provider_state = None;
+          return Ok(provider.map(|provider| Action::SongEditorLookup(provider, edit.release_query())));
+        },
+        KeyCode::Esc => self.provider_state = None,
+        _ => {},
+      }
+      return Ok(None);
+    }
+    let count = EDITED_FIELDS.len();
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => {
+        let index = self.list_state.selected().map(|index| (index + 1) % count).unwrap_or_default();
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Char('k') | KeyCode::Up => {
+        let index = self.list_state.selected().and_then(|index| index.checked_sub(1)).unwrap_or(count - 1);
+        self.list_state.select(Some(index));
+      },
+      KeyCode::Enter => {
+        return Ok(field.map(|field| {
+          Action::InputModeOn(InputIn { input_name: "song_editor".to_string(), initial_value: Some(edit.value(field)) })
+        }));
+      },
+      KeyCode::Char('w') => edit.write_tags = !edit.write_tags,
+      KeyCode::Char('l') => self.provider_state = Some(ListState::default().with_selected(Some(0))),
+      KeyCode::Char('s') => return Ok(Some(Action::SongEditorSave(edit.clone()))),
+      KeyCode::Esc => return Ok(Some(Action::FocusBack)),
+      _ => {},
+    }
+    Ok(None)
+  }
 }
@@ -0,0 +1,206 @@
+//! This module contains the Now Playing scene, showing the playing song and its synced lyrics, and
+//! the transport bar showing the playing song on every screen
+
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  prelude::*,
+  widgets::{Block, Borders, Paragraph},
+};
+
+use super::Component;
+use crate::{
+  action::Action,
+  config::Config,
+  layouts::{Focus, NowPlayingLayouts, Scenes},
+  lyrics,
+  mode::Mode,
+  player,
+};
+
+/// How far the offset keys shift the lyrics, in milliseconds
+const OFFSET_STEP_MS: i64 = 250;
+/// The width of the progress bar of the transport bar, in cells
+const PROGRESS_WIDTH: usize = 20;
+
+/// The time as minutes and seconds, such as `3:07`
+fn clock(time: Duration) -> String {
+  format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+}
+
+/// The position in the song, followed by its length if known. The position stops at the end of the
+/// song, as it is only approximate
+fn progress(position: Duration, duration: Option<Duration>) -> String {
+  match duration {
+    Some(duration) => format!("{} / {}", clock(position.min(duration)), clock(duration)),
+    None => clock(position),
+  }
+}
+
+/// The playing song with its lyrics scrolling along
+#[derive(Default)]
+pub struct NowPlaying {
+  now_playing: Option<player::NowPlaying>,
+  position: Option<Duration>,
+  paused: bool,
+  show_lyrics: bool,
+  /// positive values show the lines earlier
+  offset_ms: i64,
+}
+
+impl NowPlaying {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn lyrics_lines(&self, lyrics: &lyrics::Lyrics, height: usize) -> Vec<Line<'static>> {
+    let position = lyrics::shift(self.position.unwrap_or_default(), self.offset_ms);
+    let current = lyrics.current_line(position);
+    // the sung line stays in the middle
+    let first = current.unwrap_or_default().saturating_sub(height / 2);
+    lyrics
+      .lines
+      .iter()
+      .enumerate()
+      .skip(first)
+      .take(height)
+      .map(|(index, line)| {
+        let style = match Some(index) == current {
+          true => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+          false => Style::default().fg(Color::DarkGray),
+        };
+        Line::styled(line.text.clone(), style)
+      })
+      .collect()
+  }
+}
+
+impl Component for NowPlaying {
+  fn register_config_handler(&mut self, config: Config) -> Result<()> {
+    self.show_lyrics = config.config.player.show_lyrics;
+    self.offset_ms = config.config.player.lyrics_offset_ms;
+    Ok(())
+  }
+
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, focus: Focus) -> Result<()> {
+    let Some(now_playing) = &self.now_playing else {
+      let block = Block::default().borders(Borders::ALL).title("Now Playing");
+      f.render_widget(Paragraph::new("Nothing is playing").block(block), area);
+      return Ok(());
+    };
+    let block = Block::default().borders(Borders::ALL).title(format!(
+      "{}: {} ({})",
+      if self.paused { "Paused" } else { "Now Playing" },
+      now_playing.title,
+      progress(self.position.unwrap_or_default(), now_playing.duration)
+    ));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let layout = Layout::new(Direction::Vertical, [Constraint::Min(1), Constraint::Length(1)]).split(inner);
+
+    let lyrics = match (&now_playing.lyrics, self.show_lyrics) {
+      (_, false) => Paragraph::new("Lyrics are turned off, see player.show_lyrics"),
+      (None, true) => Paragraph::new("No synced lyrics for this song"),
+      (Some(lyrics), true) => Paragraph::new(self.lyrics_lines(lyrics, layout[0].height as usize)),
+    };
+    f.render_widget(lyrics.alignment(Alignment::Center), layout[0]);
+    f.render_widget(
+      Paragraph::new(format!("<+>/<-> lyrics offset ({:+} ms), <Ctrl-p> pause, <Esc> back", self.offset_ms))
+        .style(Style::default().fg(Color::DarkGray)),
+      layout[1],
+    );
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::NowPlaying(NowPlayingLayouts::Lyrics)
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::NowPlaying
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::PlayerNowPlaying(now_playing) => {
+        self.now_playing = now_playing;
+        self.position = None;
+        self.paused = false;
+      },
+      Action::PlayerPosition(position) => self.position = position,
+      Action::PlayerPaused(paused) => self.paused = paused,
+      Action::LyricsOffsetIncrease => self.offset_ms += OFFSET_STEP_MS,
+      Action::LyricsOffsetDecrease => self.offset_ms -= OFFSET_STEP_MS,
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent, focus: Focus) -> Result<Option<Action>> {
+    if self.is_focused(focus) && key.code == KeyCode::Esc {
+      return Ok(Some(Action::FocusBack));
+    }
+    Ok(None)
+  }
+}
+
+/// The playing song with its position and length, shown on every screen
+#[derive(Default)]
+pub struct TransportBar {
+  now_playing: Option<player::NowPlaying>,
+  position: Option<Duration>,
+  paused: bool,
+}
+
+impl TransportBar {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl Component for TransportBar {
+  fn draw(&mut self, f: &mut crate::tui::Frame<'_>, area: Rect, _focus: Focus) -> Result<()> {
+    let Some(now_playing) = &self.now_playing else {
+      f.render_widget(Paragraph::new("■ Nothing is playing").style(Style::default().fg(Color::DarkGray)), area);
+      return Ok(());
+    };
+    let position = self.position.unwrap_or_default();
+    let filled = match now_playing.duration.filter(|duration| !duration.is_zero()) {
+      Some(duration) => (position.as_secs_f64() / duration.as_secs_f64()).min(1.0) * PROGRESS_WIDTH as f64,
+      None => 0.0,
+    } as usize;
+    let line = Line::from(vec![
+      Span::styled(if self.paused { "⏸ " } else { "▶ " }, Style::default().fg(Color::Yellow)),
+      Span::raw(format!("{} ", progress(position, now_playing.duration))),
+      Span::styled("━".repeat(filled), Style::default().fg(Color::Yellow)),
+      Span::styled("─".repeat(PROGRESS_WIDTH - filled), Style::default().fg(Color::DarkGray)),
+      Span::raw(format!(" {}", now_playing.title)),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+    Ok(())
+  }
+
+  fn scene(&self) -> Scenes {
+    Scenes::TransportBar
+  }
+
+  fn mode(&self) -> Mode {
+    Mode::Global
+  }
+
+  fn update(&mut self, action: Action) -> Result<Option<Action>> {
+    match action {
+      Action::PlayerNowPlaying(now_playing) => {
+        self.now_playing = now_playing;
+        self.position = None;
+        self.paused = false;
+      },
+      Action::PlayerPosition(position) => self.position = position,
+      Action::PlayerPaused(paused) => self.paused = paused,
+      _ => {},
+    }
+    Ok(None)
+  }
+}
@@ -1,9 +1,15 @@
-use std::{collections::HashMap, fmt, path::PathBuf};
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt,
+  path::PathBuf,
+};
 
 use color_eyre::eyre::Result;
 use config::Value;
+#[cfg(feature = "tui")]
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use derive_deref::{Deref, DerefMut};
+#[cfg(feature = "tui")]
 use ratatui::style::{Color, Modifier, Style};
 use serde::{
   de::{self, Deserializer, MapAccess, Visitor},
@@ -11,38 +17,896 @@ use serde::{
 };
 use serde_json::Value as JsonValue;
 
-use crate::{action::Action, mode::Mode};
+#[cfg(feature = "tui")]
+use crate::{action::Action, keymap, mode::Mode};
+use crate::{
+  auth::Scope, disambiguation::Disambiguation, enrichment::Provider, import::playlist_file::DurationUnit, paths::Paths,
+  webhooks::WebhookEventKind,
+};
 
 /// the default config
 /// This is included as a string in the binary
+#[cfg(feature = "tui")]
 const CONFIG: &str = include_str!("../.config/config.json5");
 
 #[derive(Clone, Debug, Deserialize, Default)]
 pub struct AppConfig {
+  /// Where downloaded songs are stored
+  #[serde(default)]
+  pub music_dir: PathBuf,
+  #[serde(default)]
+  pub suspend_mode: SuspendMode,
+  /// The command the metadata of songs is edited with, `$VISUAL` or `$EDITOR` if not set
+  #[serde(default)]
+  pub editor: Option<String>,
+  #[serde(default)]
+  pub listenbrainz: ListenBrainzConfig,
+  #[serde(default)]
+  pub enrichment: EnrichmentConfig,
+  #[serde(default)]
+  pub spotify: SpotifyConfig,
+  #[serde(default)]
+  pub import: ImportConfig,
+  #[serde(default)]
+  pub playlists: PlaylistsConfig,
+  #[serde(default)]
+  pub daemon: DaemonConfig,
+  #[serde(default)]
+  pub jobs: JobsConfig,
+  /// Called by the daemon on library events
+  #[serde(default)]
+  pub webhooks: Vec<WebhookConfig>,
+  #[serde(default)]
+  pub player: PlayerConfig,
+  #[serde(default)]
+  pub mpd: MpdConfig,
+  #[serde(default)]
+  pub download: DownloadConfig,
+  #[serde(default)]
+  pub backup: BackupConfig,
+  #[serde(default)]
+  pub scan: ScanConfig,
+  #[serde(default)]
+  pub transcode: TranscodeConfig,
+  #[serde(default)]
+  pub export: ExportConfig,
+  #[serde(default)]
+  pub links: LinksConfig,
+  #[serde(default)]
+  pub verify: VerifyConfig,
+  #[serde(default)]
+  pub upgrade: UpgradeConfig,
+  #[serde(default)]
+  pub collation: CollationConfig,
+  #[serde(default)]
+  pub database: DatabaseConfig,
+  /// What is added to the titles that songs of a list share
+  #[serde(default)]
+  pub disambiguation: Disambiguation,
+  #[serde(default)]
+  pub cache: CacheConfig,
+}
+
+/// Settings for finding the songs in the music dir
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ScanConfig {
+  /// Glob patterns of the files and folders to leave out. Patterns without a `/` match the name of
+  /// any file or folder, such as `*.m4r` or `Podcasts`, others match the path from the music dir,
+  /// such as `Samples/**/*.wav`
+  #[serde(default)]
+  pub ignore: Vec<String>,
+  /// Regular expressions of the paths from the music dir to leave out
+  #[serde(default)]
+  pub ignore_regex: Vec<String>,
+  /// The extensions of the files that are songs
+  #[serde(default = "ScanConfig::default_extensions")]
+  pub extensions: Vec<String>,
+  /// Leave out songs shorter than this, such as ringtones and samples
+  #[serde(default)]
+  pub min_duration_seconds: Option<u64>,
+}
+
+impl ScanConfig {
+  fn default_extensions() -> Vec<String> {
+    ["opus", "ogg", "mp3", "m4a", "flac", "wav", "webm", "aac"].map(str::to_string).to_vec()
+  }
+}
+
+impl Default for ScanConfig {
+  fn default() -> Self {
+    Self { ignore: vec![], ignore_regex: vec![], extensions: Self::default_extensions(), min_duration_seconds: None }
+  }
+}
+
+/// Settings for the order of names in lists
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct CollationConfig {
+  /// The locale whose rules order the names, such as `sv` or `ja`. The rules common to most
+  /// languages are used if unset
+  #[serde(default)]
+  pub locale: Option<String>,
+  /// Sort Japanese names written in kana by their romanization, among the names in Latin letters
+  #[serde(default)]
+  pub romanize: bool,
+}
+
+/// Settings for the database
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct DatabaseConfig {
+  /// Bring the database up to date with this version of muzik when it is opened. Otherwise the
+  /// migrations are only run by `muzik migrate`
+  #[serde(default = "DatabaseConfig::default_auto_migrate")]
+  pub auto_migrate: bool,
+  /// A Postgres database to keep the library in instead of the SQLite file in the data dir, such as
+  /// `postgres://muzik@nas/muzik`. Needs muzik to be built with the `postgres` feature
+  #[serde(default)]
+  pub url: Option<String>,
+}
+
+impl DatabaseConfig {
+  fn default_auto_migrate() -> bool {
+    true
+  }
+}
+
+impl Default for DatabaseConfig {
+  fn default() -> Self {
+    Self { auto_migrate: Self::default_auto_migrate(), url: None }
+  }
+}
+
+/// Limits on the cache dir, see [cache](crate::cache)
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct CacheConfig {
+  /// The size the cache is kept under, in megabytes. Unlimited if unset
+  #[serde(default = "CacheConfig::default_max_size_mb")]
+  pub max_size_mb: Option<u64>,
+  /// Files unused for longer than this many days are removed. Kept for good if unset
+  #[serde(default = "CacheConfig::default_max_age_days")]
+  pub max_age_days: Option<u64>,
+}
+
+impl CacheConfig {
+  fn default_max_size_mb() -> Option<u64> {
+    Some(256)
+  }
+
+  fn default_max_age_days() -> Option<u64> {
+    Some(90)
+  }
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self { max_size_mb: Self::default_max_size_mb(), max_age_days: Self::default_max_age_days() }
+  }
+}
+
+/// Settings for the automatic backups of the database
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct BackupConfig {
+  /// How often the database is backed up. Only on demand if unset
+  #[serde(default)]
+  pub interval: Option<BackupInterval>,
+  /// The number of backups kept, older ones are deleted
+  #[serde(default = "BackupConfig::default_keep")]
+  pub keep: usize,
+  /// Where the backups are written. Defaults to `backups` in the data dir
+  #[serde(default)]
+  pub directory: Option<PathBuf>,
+}
+
+impl BackupConfig {
+  fn default_keep() -> usize {
+    7
+  }
+}
+
+impl Default for BackupConfig {
+  fn default() -> Self {
+    Self { interval: None, keep: Self::default_keep(), directory: None }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum BackupInterval {
+  Daily,
+  Weekly,
+}
+
+impl BackupInterval {
+  pub fn period(&self) -> chrono::Duration {
+    match self {
+      BackupInterval::Daily => chrono::Duration::days(1),
+      BackupInterval::Weekly => chrono::Duration::weeks(1),
+    }
+  }
+}
+
+/// Settings for downloading songs
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct DownloadConfig {
+  /// Cut off the silence at the start and the end of downloaded songs. Needs ffmpeg
+  #[serde(default)]
+  pub trim_silence: TrimSilenceConfig,
+  /// Also store the top comments of the video that look like tracklists or lyrics, along with
+  /// its description. Makes downloads slower
+  #[serde(default)]
+  pub capture_comments: bool,
+  /// The audio formats to download, the first one the video is available in is picked. One of
+  /// `opus`, `m4a`, `mp3`, `vorbis`, `flac` or `best` for whatever audio is best
+  #[serde(default = "DownloadConfig::default_formats")]
+  pub formats: Vec<String>,
+  /// The proxy to download videos that are not available in this region through, such as
+  /// `socks5://127.0.0.1:1080`
+  #[serde(default)]
+  pub proxy: Option<String>,
+  /// What happens when a download matches a song in the library, by its video or by its title
+  /// and artists
+  #[serde(default)]
+  pub duplicates: DuplicatePolicy,
+  /// Hold the downloads whose metadata was guessed, such as a title taken from the video title or
+  /// no album found, in the queue until they are reviewed in the Manager
+  #[serde(default = "DownloadConfig::default_review")]
+  pub review: bool,
+  /// How the cover art of downloaded songs is fetched and where it is kept
+  #[serde(default)]
+  pub art: ArtConfig,
+  /// The path of every downloaded song from the music dir, without the extension. Takes the
+  /// `{id}` of the video and the placeholders of the export `name_template`, and `/` to make
+  /// folders
+  #[serde(default = "DownloadConfig::default_file_template")]
+  pub file_template: String,
+}
+
+impl DownloadConfig {
+  fn default_formats() -> Vec<String> {
+    ["opus", "m4a", "best"].map(str::to_string).to_vec()
+  }
+
+  fn default_review() -> bool {
+    true
+  }
+
+  fn default_file_template() -> String {
+    "{id}".to_string()
+  }
+}
+
+impl Default for DownloadConfig {
+  fn default() -> Self {
+    Self {
+      trim_silence: TrimSilenceConfig::default(),
+      capture_comments: false,
+      formats: Self::default_formats(),
+      proxy: None,
+      duplicates: DuplicatePolicy::default(),
+      review: Self::default_review(),
+      art: ArtConfig::default(),
+      file_template: Self::default_file_template(),
+    }
+  }
+}
+
+/// Settings for the cover art of songs, the thumbnails of their videos
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct ArtConfig {
+  /// The largest thumbnail to fetch. A smaller one is fetched when the video has none this large
+  #[serde(default)]
+  pub resolution: ArtResolution,
+  /// Thumbnails larger than this, in KiB, are passed over for smaller ones
+  #[serde(default)]
+  pub max_size_kb: Option<u64>,
+  /// Where the art is kept: `Embedded` in the file of the song, as a `cover.jpg` `File` attached
+  /// to its album, and in the `Cache` dir. No art is fetched when empty
+  #[serde(default)]
+  pub store: Vec<ArtStore>,
+}
+
+/// The sizes of the thumbnails YouTube makes of a video
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+pub enum ArtResolution {
+  /// 320x180
+  #[strum(to_string = "320x180")]
+  Low,
+  /// 480x360
+  #[default]
+  #[strum(to_string = "480x360")]
+  Medium,
+  /// 640x480
+  #[strum(to_string = "640x480")]
+  High,
+  /// 1280x720, which only HD videos have
+  #[strum(to_string = "1280x720")]
+  Max,
+}
+
+/// Where the cover art of a song is kept
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum ArtStore {
+  Embedded,
+  File,
+  Cache,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TrimSilenceConfig {
+  #[serde(default)]
+  pub enabled: bool,
+  /// Audio quieter than this, in dB, is silence
+  #[serde(default = "TrimSilenceConfig::default_threshold_db")]
+  pub threshold_db: i32,
+  /// Silence shorter than this, in milliseconds, is kept
+  #[serde(default = "TrimSilenceConfig::default_min_duration_ms")]
+  pub min_duration_ms: u64,
+}
+
+impl TrimSilenceConfig {
+  fn default_threshold_db() -> i32 {
+    -50
+  }
+
+  fn default_min_duration_ms() -> u64 {
+    500
+  }
+}
+
+impl Default for TrimSilenceConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      threshold_db: Self::default_threshold_db(),
+      min_duration_ms: Self::default_min_duration_ms(),
+    }
+  }
+}
+
+/// Settings for transcoding songs streamed at a lower bitrate
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TranscodeConfig {
+  /// Transcoding a lossy file below this bitrate, in kbit/s, is lossy to lossy
+  #[serde(default = "TranscodeConfig::default_min_lossy_bitrate")]
+  pub min_lossy_bitrate: u32,
+  /// What to do when a transcode would be lossy to lossy
+  #[serde(default)]
+  pub below_floor: LossyPolicy,
+}
+
+impl TranscodeConfig {
+  fn default_min_lossy_bitrate() -> u32 {
+    96
+  }
+}
+
+impl Default for TranscodeConfig {
+  fn default() -> Self {
+    Self { min_lossy_bitrate: Self::default_min_lossy_bitrate(), below_floor: LossyPolicy::default() }
+  }
+}
+
+/// Settings for exporting songs to a folder
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ExportConfig {
+  /// The path of every exported song from the folder, without the extension. Takes the `{title}`,
+  /// `{artist}`, `{album}`, `{disc}` and `{track}` placeholders, and `/` to make folders
+  #[serde(default = "ExportConfig::default_name_template")]
+  pub name_template: String,
+  /// Transcode the songs to opus at this bitrate in kbit/s, or copy them as they are if unset
+  #[serde(default)]
+  pub bitrate: Option<u32>,
+  /// Where and how playlists are exported, by name. Every playlist remembers the profiles it is
+  /// exported with
+  #[serde(default)]
+  pub profiles: BTreeMap<String, ExportProfile>,
+}
+
+/// Where and how a playlist is exported, such as to a USB stick for the car or to the playlists of
+/// MPD
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ExportProfile {
+  /// The folder the playlist is written into
+  pub directory: PathBuf,
+  /// Copy the songs of the playlist into the folder along with it, named after the `name_template`.
+  /// Otherwise the playlist points at the files in the music dir, written as `paths` says
+  #[serde(default)]
+  pub copy_songs: bool,
+  /// How the files of the songs are written in the playlist when they are not copied
+  #[serde(default)]
+  pub paths: PlaylistPaths,
+  /// Transcode the copied songs to opus at this bitrate in kbit/s, or copy them as they are if
+  /// unset
+  #[serde(default)]
+  pub bitrate: Option<u32>,
+  /// The path of every copied song from the folder, in place of the `name_template` of the export
+  /// settings
+  #[serde(default)]
+  pub name_template: Option<String>,
+}
+
+impl ExportConfig {
+  fn default_name_template() -> String {
+    "{artist} - {title}".to_string()
+  }
+}
+
+impl Default for ExportConfig {
+  fn default() -> Self {
+    Self { name_template: Self::default_name_template(), bitrate: None, profiles: BTreeMap::new() }
+  }
+}
+
+/// Settings for checking that the videos of the songs are still up
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct LinksConfig {
+  /// Check the video of every song again after this many days while the application is running.
+  /// Only on demand if unset
+  #[serde(default)]
+  pub check_interval_days: Option<u64>,
+  /// The most videos checked in one go, least recently checked first
+  #[serde(default = "LinksConfig::default_batch_size")]
+  pub batch_size: usize,
+}
+
+impl LinksConfig {
+  fn default_batch_size() -> usize {
+    50
+  }
+}
+
+impl Default for LinksConfig {
+  fn default() -> Self {
+    Self { check_interval_days: None, batch_size: Self::default_batch_size() }
+  }
+}
+
+/// Settings for verifying the integrity of the files of the songs
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct VerifyConfig {
+  /// Also decode this many seconds from the start of every file with ffmpeg, which finds damage
+  /// the headers do not show but is much slower. Only the headers are read if unset
+  #[serde(default)]
+  pub decode_seconds: Option<u32>,
+}
+
+/// Settings for finding better versions of low quality songs
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct UpgradeConfig {
+  /// Songs whose lossy file is below this bitrate, in kbit/s, are low quality
+  #[serde(default = "UpgradeConfig::default_min_bitrate")]
+  pub min_bitrate: u32,
+  /// Uploads whose length differs from the file by more than this many seconds are left out, as
+  /// another version of the song
+  #[serde(default = "UpgradeConfig::default_max_duration_difference_seconds")]
+  pub max_duration_difference_seconds: u64,
+}
+
+impl UpgradeConfig {
+  fn default_min_bitrate() -> u32 {
+    128
+  }
+
+  fn default_max_duration_difference_seconds() -> u64 {
+    5
+  }
+}
+
+impl Default for UpgradeConfig {
+  fn default() -> Self {
+    Self {
+      min_bitrate: Self::default_min_bitrate(),
+      max_duration_difference_seconds: Self::default_max_duration_difference_seconds(),
+    }
+  }
+}
+
+/// What happens when a download matches a song in the library
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq, strum::Display, clap::ValueEnum)]
+pub enum DuplicatePolicy {
+  /// Leave the song as it is and drop the download
+  #[strum(to_string = "skip")]
+  Skip,
+  /// Download it and replace the file of the song if the download has better audio
+  #[strum(to_string = "replace if better")]
+  ReplaceIfBetter,
+  /// Add the download as another song. A song of the same video gets the new file instead
+  #[default]
+  #[strum(to_string = "keep both")]
+  KeepBoth,
+  /// Hold the download in the queue until told what to do
+  #[strum(to_string = "ask")]
+  Ask,
+}
+
+/// What happens to a lossy to lossy transcode below the quality floor
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+pub enum LossyPolicy {
+  /// Transcode anyway and log a warning
+  #[default]
+  Warn,
+  /// Serve the file as it is
+  Skip,
+}
+
+/// How songs are played
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct PlayerConfig {
+  /// Show the synced lyrics of the playing song in the Now Playing scene
+  #[serde(default = "PlayerConfig::default_show_lyrics")]
+  pub show_lyrics: bool,
+  /// Shift the lyrics by this many milliseconds, positive values show the lines earlier
+  #[serde(default)]
+  pub lyrics_offset_ms: i64,
+}
+
+impl PlayerConfig {
+  fn default_show_lyrics() -> bool {
+    true
+  }
+}
+
+impl Default for PlayerConfig {
+  fn default() -> Self {
+    Self { show_lyrics: Self::default_show_lyrics(), lyrics_offset_ms: 0 }
+  }
+}
+
+/// A URL the daemon posts events to
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct WebhookConfig {
+  pub url: String,
+  /// The events posted to the URL, every event if empty
+  #[serde(default)]
+  pub events: Vec<WebhookEventKind>,
+  #[serde(default)]
+  pub format: WebhookFormat,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+pub enum WebhookFormat {
+  /// The event as a JSON object, with an `event` field naming it
+  #[default]
+  Json,
+  /// A chat message describing the event, for Discord webhooks
+  Discord,
+}
+
+/// How the background jobs share the machine with the TUI, see [jobs](crate::jobs)
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct JobsConfig {
+  /// The background jobs run at once, such as scans, syncs and exports. The others wait queued
+  #[serde(default = "JobsConfig::default_background_limit")]
+  pub background_limit: usize,
+  /// The interactive jobs run at once, such as imports
+  #[serde(default = "JobsConfig::default_interactive_limit")]
+  pub interactive_limit: usize,
+  /// Background jobs pause between steps until the TUI is left alone for this long, in
+  /// milliseconds. Never paused if 0
+  #[serde(default = "JobsConfig::default_idle_ms")]
+  pub idle_ms: u64,
+  /// The stopped jobs kept in the jobs view, the oldest ones are dropped past this
+  #[serde(default = "JobsConfig::default_keep_finished")]
+  pub keep_finished: usize,
+}
+
+impl JobsConfig {
+  fn default_background_limit() -> usize {
+    2
+  }
+
+  fn default_interactive_limit() -> usize {
+    4
+  }
+
+  fn default_idle_ms() -> u64 {
+    1000
+  }
+
+  fn default_keep_finished() -> usize {
+    50
+  }
+}
+
+impl Default for JobsConfig {
+  fn default() -> Self {
+    Self {
+      background_limit: Self::default_background_limit(),
+      interactive_limit: Self::default_interactive_limit(),
+      idle_ms: Self::default_idle_ms(),
+      keep_finished: Self::default_keep_finished(),
+    }
+  }
+}
+
+/// Settings for `muzik daemon`
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct DaemonConfig {
+  /// The socket the daemon listens on and the TUI connects to. Defaults to `daemon.sock` in the
+  /// data dir
+  #[serde(default)]
+  pub socket: Option<PathBuf>,
+  /// Also accept clients from other machines on this address, such as `0.0.0.0:7700`. Without
+  /// `tokens`, only clients on this machine are accepted on it
+  #[serde(default)]
+  pub listen: Option<String>,
+  /// Stream the songs over HTTP on this address, such as `0.0.0.0:7701`. Clients authenticate
+  /// with `tokens` the same way
+  #[serde(default)]
+  pub http_listen: Option<String>,
+  /// The tokens clients from other machines authenticate with. If any are set, clients without
+  /// a token are refused
+  #[serde(default)]
+  pub tokens: Vec<TokenConfig>,
   #[serde(default)]
-  pub _data_dir: PathBuf,
+  pub tls: TlsConfig,
+}
+
+/// Encryption of the connections of clients from other machines, over IPC and HTTP
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TlsConfig {
+  /// Accept clients over TLS only. Clients connect with the certificate of the daemon
+  #[serde(default = "TlsConfig::default_enabled")]
+  pub enabled: bool,
+  /// The certificate in PEM format. A self-signed one is generated in the data dir if unset
+  #[serde(default)]
+  pub certificate: Option<PathBuf>,
+  /// The private key of the certificate in PEM format
+  #[serde(default)]
+  pub key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+  fn default_enabled() -> bool {
+    true
+  }
+}
+
+impl Default for TlsConfig {
+  fn default() -> Self {
+    Self { enabled: Self::default_enabled(), certificate: None, key: None }
+  }
+}
+
+/// A token for clients of the daemon on other machines
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TokenConfig {
+  pub token: String,
+  pub scope: Scope,
+  /// Refuse the requests beyond this many a minute. Unlimited if unset
+  #[serde(default)]
+  pub requests_per_minute: Option<u32>,
+}
+
+/// A running MPD that songs and playlists can be sent to, see [mpd](crate::mpd)
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct MpdConfig {
+  /// The host of MPD, or the path of its socket. Off if unset
+  #[serde(default)]
+  pub host: Option<String>,
+  #[serde(default = "MpdConfig::default_port")]
+  pub port: u16,
+  #[serde(default)]
+  pub password: Option<String>,
+  /// Prepended to the paths of the files relative to the music dir, e.g. the music dir as seen from
+  /// the `music_directory` of MPD. Nothing when both are the same directory
+  #[serde(default)]
+  pub path_prefix: String,
+}
+
+impl MpdConfig {
+  fn default_port() -> u16 {
+    6600
+  }
+}
+
+impl Default for MpdConfig {
+  fn default() -> Self {
+    Self { host: None, port: Self::default_port(), password: None, path_prefix: String::new() }
+  }
+}
+
+/// Settings for the auto-playlists written for external players
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct PlaylistsConfig {
+  /// Where the playlists are written. Defaults to `playlists` in the data dir
+  #[serde(default)]
+  pub directory: Option<PathBuf>,
+  /// Prepended to the paths in the playlists, e.g. the music directory as seen by the player
+  #[serde(default)]
+  pub path_prefix: String,
+  /// How the files of the songs are written in the playlists
+  #[serde(default)]
+  pub paths: PlaylistPaths,
+  /// Regenerate the playlists this often while the application is running. Only on demand if
+  /// unset
+  #[serde(default)]
+  pub export_interval_minutes: Option<u64>,
+  /// A directory of M3U playlists kept in sync with the playlists stored in the library, both
+  /// ways. Another directory than `directory`, as the export replaces the playlists it wrote. Off
+  /// if unset
+  #[serde(default)]
+  pub sync_directory: Option<PathBuf>,
+}
+
+/// How the files of the songs are written in the exported playlists
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlaylistPaths {
+  /// Relative to the music dir, after the `path_prefix`
+  #[default]
+  MusicDir,
+  /// The absolute path of the file
+  Absolute,
+  /// Relative to the directory of the playlist, for playlists copied along with the music
+  Relative,
+}
+
+/// The columns read from CSV and TSV playlist files, matched case insensitively against the
+/// header. For example the playlists exported by Apple Music use `Name`, `Artist`, `Album` and
+/// `Time`
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ImportConfig {
+  #[serde(default = "ImportConfig::default_title_column")]
+  pub title_column: String,
+  #[serde(default = "ImportConfig::default_artist_column")]
+  pub artist_column: String,
+  #[serde(default = "ImportConfig::default_album_column")]
+  pub album_column: Option<String>,
+  #[serde(default = "ImportConfig::default_duration_column")]
+  pub duration_column: Option<String>,
+  #[serde(default)]
+  pub duration_unit: DurationUnit,
+  /// The column with the disc of multi-disc albums the track is on
+  #[serde(default)]
+  pub disc_column: Option<String>,
+  #[serde(default)]
+  pub track_column: Option<String>,
+  /// Separates multiple artists in the artist column
+  #[serde(default = "ImportConfig::default_artist_separator")]
+  pub artist_separator: String,
+}
+
+impl ImportConfig {
+  fn default_title_column() -> String {
+    "title".to_string()
+  }
+
+  fn default_artist_column() -> String {
+    "artist".to_string()
+  }
+
+  fn default_album_column() -> Option<String> {
+    Some("album".to_string())
+  }
+
+  fn default_duration_column() -> Option<String> {
+    Some("duration".to_string())
+  }
+
+  fn default_artist_separator() -> String {
+    ",".to_string()
+  }
+}
+
+impl Default for ImportConfig {
+  fn default() -> Self {
+    Self {
+      title_column: Self::default_title_column(),
+      artist_column: Self::default_artist_column(),
+      album_column: Self::default_album_column(),
+      duration_column: Self::default_duration_column(),
+      duration_unit: DurationUnit::default(),
+      disc_column: None,
+      track_column: None,
+      artist_separator: Self::default_artist_separator(),
+    }
+  }
+}
+
+/// Credentials of an app from the Spotify developer dashboard, used to read playlists
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct SpotifyConfig {
+  #[serde(default)]
+  pub client_id: Option<String>,
+  #[serde(default)]
+  pub client_secret: Option<String>,
+}
+
+/// Settings for fetching metadata from online providers
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+pub struct EnrichmentConfig {
+  /// The providers to query, in order
+  #[serde(default)]
+  pub providers: Vec<Provider>,
+  #[serde(default)]
+  pub discogs: DiscogsConfig,
+  /// Fetch the pictures of artists from Deezer into the cache dir, shown in the artist pane of
+  /// the Manager
+  #[serde(default)]
+  pub artist_images: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct DiscogsConfig {
+  /// Personal access token from the Discogs developer settings
   #[serde(default)]
-  pub _config_dir: PathBuf,
+  pub token: Option<String>,
+  /// Discogs allows 60 authenticated or 25 unauthenticated requests per minute
+  #[serde(default = "DiscogsConfig::default_requests_per_minute")]
+  pub requests_per_minute: u32,
+}
+
+impl DiscogsConfig {
+  fn default_requests_per_minute() -> u32 {
+    25
+  }
+}
+
+impl Default for DiscogsConfig {
+  fn default() -> Self {
+    Self { token: None, requests_per_minute: Self::default_requests_per_minute() }
+  }
+}
+
+/// Settings for scrobbling to and importing listens from ListenBrainz
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ListenBrainzConfig {
+  /// Whether listens are submitted to ListenBrainz. Independent of other scrobbling services
+  #[serde(default)]
+  pub enabled: bool,
+  /// The user token found in the ListenBrainz settings
+  #[serde(default)]
+  pub token: Option<String>,
+  /// The user whose listens are imported
+  #[serde(default)]
+  pub username: Option<String>,
+  /// The API root, changeable for self hosted instances
+  #[serde(default = "ListenBrainzConfig::default_api_url")]
+  pub api_url: String,
+}
+
+impl ListenBrainzConfig {
+  fn default_api_url() -> String {
+    "https://api.listenbrainz.org".to_string()
+  }
+}
+
+impl Default for ListenBrainzConfig {
+  fn default() -> Self {
+    Self { enabled: false, token: None, username: None, api_url: Self::default_api_url() }
+  }
+}
+
+/// What happens when the application is suspended
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+pub enum SuspendMode {
+  /// Stop the process with SIGTSTP, until `fg`. Background jobs are paused along with it
+  #[default]
+  Stop,
+  /// Drop into `$SHELL` instead, so background jobs keep running until the shell exits. The TUI
+  /// is left until then, the jobs that stopped meanwhile are notified once it is back
+  Shell,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Config {
   #[serde(default, flatten)]
   pub config: AppConfig,
+  #[cfg(feature = "tui")]
   #[serde(default)]
   pub keybindings: KeyBindings,
+  #[cfg(feature = "tui")]
   #[serde(default)]
   pub styles: Styles,
+  /// Resolved when the config is loaded, with the music dir of the config
+  #[serde(skip)]
+  pub paths: Paths,
 }
 
 impl Config {
   pub fn new() -> Result<Self, config::ConfigError> {
-    let default_config: Config = json5::from_str(CONFIG).unwrap();
-    let data_dir = crate::utils::get_data_dir();
-    let config_dir = crate::utils::get_config_dir();
-    let mut builder = config::Config::builder()
-      .set_default("_data_dir", data_dir.to_str().unwrap())?
-      .set_default("_config_dir", config_dir.to_str().unwrap())?;
+    let paths = Paths::current();
+    let config_dir = paths.config_dir.clone();
+    let mut builder =
+      config::Config::builder().set_default("music_dir", paths.music_dir.to_string_lossy().to_string())?;
 
     let config_files = [
       ("config.json5", config::FileFormat::Json5),
@@ -63,27 +927,44 @@ impl Config {
     }
 
     let mut cfg: Self = builder.build()?.try_deserialize()?;
+    cfg.paths = paths.with_music_dir(cfg.config.music_dir.clone());
+    #[cfg(feature = "tui")]
+    cfg.merge_ui_defaults(&config_dir);
+    Ok(cfg)
+  }
 
+  /// Fill in the key bindings and styles left out of the config from the default config, then
+  /// apply the keys rebound from the help overlay
+  #[cfg(feature = "tui")]
+  fn merge_ui_defaults(&mut self, config_dir: &std::path::Path) {
+    let default_config: Config = json5::from_str(CONFIG).unwrap();
     for (mode, default_bindings) in default_config.keybindings.iter() {
-      let user_bindings = cfg.keybindings.entry(*mode).or_default();
+      let user_bindings = self.keybindings.entry(*mode).or_default();
       for (key, cmd) in default_bindings.iter() {
         user_bindings.entry(key.clone()).or_insert_with(|| cmd.clone());
       }
     }
+    keymap::apply_rebinds(
+      &mut self.keybindings,
+      &keymap::load_rebinds(config_dir).unwrap_or_else(|e| {
+        log::error!("Failed to read the rebinds of keys: {e}");
+        vec![]
+      }),
+    );
     for (mode, default_styles) in default_config.styles.iter() {
-      let user_styles = cfg.styles.entry(*mode).or_default();
+      let user_styles = self.styles.entry(*mode).or_default();
       for (style_key, style) in default_styles.iter() {
         user_styles.entry(style_key.clone()).or_insert_with(|| *style);
       }
     }
-
-    Ok(cfg)
   }
 }
 
+#[cfg(feature = "tui")]
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
 pub struct KeyBindings(pub HashMap<Mode, HashMap<Vec<KeyEvent>, Action>>);
 
+#[cfg(feature = "tui")]
 impl<'de> Deserialize<'de> for KeyBindings {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
@@ -104,12 +985,14 @@ impl<'de> Deserialize<'de> for KeyBindings {
   }
 }
 
+#[cfg(feature = "tui")]
 fn parse_key_event(raw: &str) -> Result<KeyEvent, String> {
   let raw_lower = raw.to_ascii_lowercase();
   let (remaining, modifiers) = extract_modifiers(&raw_lower);
   parse_key_code_with_modifiers(remaining, modifiers)
 }
 
+#[cfg(feature = "tui")]
 fn extract_modifiers(raw: &str) -> (&str, KeyModifiers) {
   let mut modifiers = KeyModifiers::empty();
   let mut current = raw;
@@ -135,6 +1018,7 @@ fn extract_modifiers(raw: &str) -> (&str, KeyModifiers) {
   (current, modifiers)
 }
 
+#[cfg(feature = "tui")]
 fn parse_key_code_with_modifiers(raw: &str, mut modifiers: KeyModifiers) -> Result<KeyEvent, String> {
   let c = match raw {
     "esc" => KeyCode::Esc,
@@ -182,6 +1066,7 @@ fn parse_key_code_with_modifiers(raw: &str, mut modifiers: KeyModifiers) -> Resu
   Ok(KeyEvent::new(c, modifiers))
 }
 
+#[cfg(feature = "tui")]
 pub fn key_event_to_string(key_event: &KeyEvent) -> String {
   let char;
   let key_code = match key_event.code {
@@ -200,10 +1085,10 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
     KeyCode::Delete => "delete",
     KeyCode::Insert => "insert",
     KeyCode::F(c) => {
-      char = format!("f({c})");
+      char = format!("f{c}");
       &char
     },
-    KeyCode::Char(c) if c == ' ' => "space",
+    KeyCode::Char(' ') => "space",
     KeyCode::Char(c) => {
       char = c.to_string();
       &char
@@ -245,6 +1130,7 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
   key
 }
 
+#[cfg(feature = "tui")]
 pub fn parse_key_sequence(raw: &str) -> Result<Vec<KeyEvent>, String> {
   if raw.chars().filter(|c| *c == '>').count() != raw.chars().filter(|c| *c == '<').count() {
     return Err(format!("Unable to parse `{}`", raw));
@@ -272,9 +1158,11 @@ pub fn parse_key_sequence(raw: &str) -> Result<Vec<KeyEvent>, String> {
   sequences.into_iter().map(parse_key_event).collect()
 }
 
+#[cfg(feature = "tui")]
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
 pub struct Styles(pub HashMap<Mode, HashMap<String, Style>>);
 
+#[cfg(feature = "tui")]
 impl<'de> Deserialize<'de> for Styles {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
@@ -294,6 +1182,7 @@ impl<'de> Deserialize<'de> for Styles {
   }
 }
 
+#[cfg(feature = "tui")]
 pub fn parse_style(line: &str) -> Style {
   let (foreground, background) = line.split_at(line.to_lowercase().find("on ").unwrap_or(line.len()));
   let foreground = process_color_string(foreground);
@@ -310,6 +1199,7 @@ pub fn parse_style(line: &str) -> Style {
   style
 }
 
+#[cfg(feature = "tui")]
 fn process_color_string(color_str: &str) -> (String, Modifier) {
   let color = color_str
     .replace("grey", "gray")
@@ -332,6 +1222,7 @@ fn process_color_string(color_str: &str) -> (String, Modifier) {
   (color, modifiers)
 }
 
+#[cfg(feature = "tui")]
 fn parse_color(s: &str) -> Option<Color> {
   let s = s.trim_start();
   let s = s.trim_end();
@@ -394,24 +1285,28 @@ mod tests {
 
   use super::*;
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_style_default() {
     let style = parse_style("");
     assert_eq!(style, Style::default());
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_style_foreground() {
     let style = parse_style("red");
     assert_eq!(style.fg, Some(Color::Indexed(1)));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_style_background() {
     let style = parse_style("on blue");
     assert_eq!(style.bg, Some(Color::Indexed(4)));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_style_modifiers() {
     let style = parse_style("underline red on blue");
@@ -419,6 +1314,7 @@ mod tests {
     assert_eq!(style.bg, Some(Color::Indexed(4)));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_process_color_string() {
     let (color, modifiers) = process_color_string("underline bold inverse gray");
@@ -428,6 +1324,7 @@ mod tests {
     assert!(modifiers.contains(Modifier::REVERSED));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_color_rgb() {
     let color = parse_color("rgb123");
@@ -435,12 +1332,14 @@ mod tests {
     assert_eq!(color, Some(Color::Indexed(expected)));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_parse_color_unknown() {
     let color = parse_color("unknown");
     assert_eq!(color, None);
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_config() -> Result<()> {
     let c = Config::new()?;
@@ -451,6 +1350,284 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn test_config_suspend_mode() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.suspend_mode, SuspendMode::Stop);
+
+    let c: Config = json5::from_str(r#"{ "suspend_mode": "Shell" }"#)?;
+    assert_eq!(c.config.suspend_mode, SuspendMode::Shell);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_editor() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.editor, None);
+
+    let c: Config = json5::from_str(r#"{ "editor": "code --wait" }"#)?;
+    assert_eq!(c.config.editor.as_deref(), Some("code --wait"));
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_cache() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.cache, CacheConfig { max_size_mb: Some(256), max_age_days: Some(90) });
+
+    let c: Config = json5::from_str(r#"{ "cache": { "max_size_mb": 64, "max_age_days": null } }"#)?;
+    assert_eq!(c.config.cache, CacheConfig { max_size_mb: Some(64), max_age_days: None });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_disambiguation() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.disambiguation, Disambiguation::Auto);
+
+    let c: Config = json5::from_str(r#"{ "disambiguation": "Album" }"#)?;
+    assert_eq!(c.config.disambiguation, Disambiguation::Album);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_enrichment() -> Result<()> {
+    let c: Config =
+      json5::from_str(r#"{ "enrichment": { "providers": ["Discogs"], "discogs": { "token": "secret" } } }"#)?;
+    assert_eq!(c.config.enrichment.providers, vec![Provider::Discogs]);
+    assert_eq!(c.config.enrichment.discogs.token, Some("secret".to_string()));
+    assert_eq!(c.config.enrichment.discogs.requests_per_minute, 25);
+    assert!(!c.config.enrichment.artist_images);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_backup() -> Result<()> {
+    let c: Config = json5::from_str(r#"{ "backup": { "interval": "Weekly" } }"#)?;
+    assert_eq!(c.config.backup, BackupConfig { interval: Some(BackupInterval::Weekly), keep: 7, directory: None });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_trim_silence() -> Result<()> {
+    let c: Config = json5::from_str(r#"{ "download": { "trim_silence": { "enabled": true, "threshold_db": -40 } } }"#)?;
+    assert_eq!(c.config.download.trim_silence, TrimSilenceConfig {
+      enabled: true,
+      threshold_db: -40,
+      min_duration_ms: 500
+    });
+    assert_eq!(c.config.download.formats, vec!["opus", "m4a", "best"]);
+    assert_eq!(c.config.download.proxy, None);
+    assert_eq!(c.config.download.duplicates, DuplicatePolicy::KeepBoth);
+    assert!(c.config.download.review);
+    assert_eq!(c.config.download.file_template, "{id}");
+
+    let c: Config = json5::from_str(
+      r#"{ "download": { "formats": ["m4a", "best"], "proxy": "socks5://127.0.0.1:1080", "duplicates": "Ask", "review": false, "file_template": "{artist}/{album}/{track} {title}" } }"#,
+    )?;
+    assert_eq!(c.config.download.file_template, "{artist}/{album}/{track} {title}");
+    assert!(!c.config.download.review);
+    assert_eq!(c.config.download.formats, vec!["m4a", "best"]);
+    assert_eq!(c.config.download.proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+    assert_eq!(c.config.download.duplicates, DuplicatePolicy::Ask);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_art() -> Result<()> {
+    let c: Config = json5::from_str(r#"{}"#)?;
+    assert_eq!(c.config.download.art, ArtConfig {
+      resolution: ArtResolution::Medium,
+      max_size_kb: None,
+      store: vec![]
+    });
+
+    let c: Config = json5::from_str(
+      r#"{ "download": { "art": { "resolution": "Max", "max_size_kb": 200, "store": ["Embedded", "Cache"] } } }"#,
+    )?;
+    assert_eq!(c.config.download.art, ArtConfig {
+      resolution: ArtResolution::Max,
+      max_size_kb: Some(200),
+      store: vec![ArtStore::Embedded, ArtStore::Cache]
+    });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_webhooks() -> Result<()> {
+    let c: Config = json5::from_str(
+      r#"{ "webhooks": [
+        { "url": "http://homeassistant.local/api/webhook/muzik" },
+        { "url": "https://discord.com/api/webhooks/1/abc", "events": ["download_failed"], "format": "Discord" }
+      ] }"#,
+    )?;
+    assert_eq!(c.config.webhooks[0].events, vec![]);
+    assert_eq!(c.config.webhooks[1].events, vec![WebhookEventKind::DownloadFailed]);
+    assert_eq!(c.config.webhooks[1].format, WebhookFormat::Discord);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_playlists() -> Result<()> {
+    let c: Config = json5::from_str(r#"{ "playlists": { "path_prefix": "/music/", "export_interval_minutes": 60 } }"#)?;
+    assert_eq!(c.config.playlists, PlaylistsConfig {
+      directory: None,
+      path_prefix: "/music/".to_string(),
+      paths: PlaylistPaths::MusicDir,
+      export_interval_minutes: Some(60),
+      sync_directory: None,
+    });
+    let c: Config =
+      json5::from_str(r#"{ "playlists": { "paths": "Relative", "sync_directory": "/home/suisei/Playlists" } }"#)?;
+    assert_eq!(c.config.playlists.paths, PlaylistPaths::Relative);
+    assert_eq!(c.config.playlists.sync_directory, Some(PathBuf::from("/home/suisei/Playlists")));
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_jobs() -> Result<()> {
+    let c: Config = json5::from_str(r#"{}"#)?;
+    assert_eq!(c.config.jobs, JobsConfig::default());
+    let c: Config = json5::from_str(r#"{ "jobs": { "background_limit": 1, "idle_ms": 0, "keep_finished": 10 } }"#)?;
+    assert_eq!(c.config.jobs, JobsConfig { background_limit: 1, interactive_limit: 4, idle_ms: 0, keep_finished: 10 });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_mpd() -> Result<()> {
+    let c: Config = json5::from_str(r#"{}"#)?;
+    assert_eq!(c.config.mpd.host, None);
+    assert_eq!(c.config.mpd.port, 6600);
+    let c: Config =
+      json5::from_str(r#"{ "mpd": { "host": "localhost", "password": "suisei", "path_prefix": "muzik/" } }"#)?;
+    assert_eq!(c.config.mpd, MpdConfig {
+      host: Some("localhost".to_string()),
+      port: 6600,
+      password: Some("suisei".to_string()),
+      path_prefix: "muzik/".to_string(),
+    });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_import_columns() -> Result<()> {
+    let c: Config = json5::from_str(r#"{ "import": { "title_column": "Name", "duration_unit": "Milliseconds" } }"#)?;
+    assert_eq!(c.config.import.title_column, "Name");
+    assert_eq!(c.config.import.artist_column, "artist");
+    assert_eq!(c.config.import.duration_unit, DurationUnit::Milliseconds);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_lyrics() -> Result<()> {
+    let c = Config::new()?;
+    assert!(c.config.player.show_lyrics);
+    #[cfg(feature = "tui")]
+    assert_eq!(
+      c.keybindings.get(&Mode::NowPlaying).unwrap().get(&parse_key_sequence("<->").unwrap_or_default()).unwrap(),
+      &Action::LyricsOffsetDecrease
+    );
+
+    let c: Config = json5::from_str(r#"{ "player": { "show_lyrics": false, "lyrics_offset_ms": -300 } }"#)?;
+    assert!(!c.config.player.show_lyrics);
+    assert_eq!(c.config.player.lyrics_offset_ms, -300);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_transcode() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.transcode, TranscodeConfig::default());
+
+    let c: Config = json5::from_str(r#"{ "transcode": { "min_lossy_bitrate": 128, "below_floor": "Skip" } }"#)?;
+    assert_eq!(c.config.transcode, TranscodeConfig { min_lossy_bitrate: 128, below_floor: LossyPolicy::Skip });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_export() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.export, ExportConfig::default());
+
+    let c: Config = json5::from_str(r#"{ "export": { "name_template": "{album}/{track} {title}", "bitrate": 128 } }"#)?;
+    assert_eq!(c.config.export, ExportConfig {
+      name_template: "{album}/{track} {title}".to_string(),
+      bitrate: Some(128),
+      profiles: BTreeMap::new(),
+    });
+
+    let c: Config = json5::from_str(
+      r#"{ "export": { "profiles": {
+        "car": { "directory": "/media/usb", "copy_songs": true, "bitrate": 96, "name_template": "{artist} - {title}" },
+        "mpd": { "directory": "/var/lib/mpd/playlists" },
+      } } }"#,
+    )?;
+    assert_eq!(c.config.export.profiles["car"], ExportProfile {
+      directory: PathBuf::from("/media/usb"),
+      copy_songs: true,
+      paths: PlaylistPaths::MusicDir,
+      bitrate: Some(96),
+      name_template: Some("{artist} - {title}".to_string()),
+    });
+    assert_eq!(c.config.export.profiles["mpd"].paths, PlaylistPaths::MusicDir);
+    assert!(!c.config.export.profiles["mpd"].copy_songs);
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_links() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.links, LinksConfig { check_interval_days: None, batch_size: 50 });
+
+    let c: Config = json5::from_str(r#"{ "links": { "check_interval_days": 30 } }"#)?;
+    assert_eq!(c.config.links, LinksConfig { check_interval_days: Some(30), batch_size: 50 });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_verify() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.verify, VerifyConfig { decode_seconds: None });
+
+    let c: Config = json5::from_str(r#"{ "verify": { "decode_seconds": 10 } }"#)?;
+    assert_eq!(c.config.verify, VerifyConfig { decode_seconds: Some(10) });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_upgrade() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.upgrade, UpgradeConfig { min_bitrate: 128, max_duration_difference_seconds: 5 });
+
+    let c: Config = json5::from_str(r#"{ "upgrade": { "min_bitrate": 160 } }"#)?;
+    assert_eq!(c.config.upgrade, UpgradeConfig { min_bitrate: 160, max_duration_difference_seconds: 5 });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_collation() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.collation, CollationConfig { locale: None, romanize: false });
+
+    let c: Config = json5::from_str(r#"{ "collation": { "locale": "ja", "romanize": true } }"#)?;
+    assert_eq!(c.config.collation, CollationConfig { locale: Some("ja".to_string()), romanize: true });
+    Ok(())
+  }
+
+  #[test]
+  fn test_config_database() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.config.database, DatabaseConfig { auto_migrate: true, url: None });
+
+    let c: Config = json5::from_str(r#"{ "database": { "auto_migrate": false } }"#)?;
+    assert_eq!(c.config.database, DatabaseConfig { auto_migrate: false, url: None });
+
+    let c: Config = json5::from_str(r#"{ "database": { "url": "postgres://muzik@nas/muzik" } }"#)?;
+    assert_eq!(c.config.database.url.as_deref(), Some("postgres://muzik@nas/muzik"));
+    Ok(())
+  }
+
+  #[cfg(feature = "tui")]
   #[test]
   fn test_simple_keys() {
     assert_eq!(parse_key_event("a").unwrap(), KeyEvent::new(KeyCode::Char('a'), KeyModifiers::empty()));
@@ -460,6 +1637,7 @@ mod tests {
     assert_eq!(parse_key_event("esc").unwrap(), KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_with_modifiers() {
     assert_eq!(parse_key_event("ctrl-a").unwrap(), KeyEvent::new(KeyCode::Char('a'), KeyModifiers::CONTROL));
@@ -469,6 +1647,7 @@ mod tests {
     assert_eq!(parse_key_event("shift-esc").unwrap(), KeyEvent::new(KeyCode::Esc, KeyModifiers::SHIFT));
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_multiple_modifiers() {
     assert_eq!(
@@ -482,6 +1661,7 @@ mod tests {
     );
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_reverse_multiple_modifiers() {
     assert_eq!(
@@ -490,12 +1670,14 @@ mod tests {
     );
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_invalid_keys() {
     assert!(parse_key_event("invalid-key").is_err());
     assert!(parse_key_event("ctrl-invalid-key").is_err());
   }
 
+  #[cfg(feature = "tui")]
   #[test]
   fn test_case_insensitivity() {
     assert_eq!(parse_key_event("CTRL-a").unwrap(), KeyEvent::new(KeyCode::Char('a'), KeyModifiers::CONTROL));
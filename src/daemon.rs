@@ -0,0 +1,313 @@
+//! `muzik daemon`, running the background work without any UI
+//!
+//! The daemon downloads the queued songs and runs the scheduled jobs, and is controlled over the
+//! IPC socket. A TUI started while the daemon runs hands its downloads over to the daemon, so
+//! closing the terminal does not stop them. With `daemon.listen` set, the daemon also accepts
+//! clients from other machines, which can browse its library and queue downloads on it, over TLS
+//! unless disabled. With `daemon.http_listen` set, it streams the songs over HTTP as well.
+//! Finished downloads and jobs are posted to the configured webhooks.
+
+use std::{
+  collections::HashSet,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::{
+  net::{TcpListener, UnixListener},
+  sync::broadcast::error::RecvError,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+  auth::AccessControl,
+  backup, cache,
+  config::Config,
+  database::Database,
+  ipc::{self, IpcClient, IpcListener, IpcRequest, IpcResponse},
+  jobs::{JobKind, JobRegistry},
+  links,
+  listing::SongRow,
+  playlist_sync, playlists,
+  query::Query,
+  queue::DownloadQueue,
+  tls,
+  webhooks::{WebhookEvent, Webhooks},
+};
+#[cfg(feature = "server")]
+use crate::{database::DatabaseHandle, server};
+
+/// The most songs sent in reply to a single request, clients ask for the rest page by page
+const MAX_SONGS_PER_REQUEST: i64 = 1000;
+/// How often the scheduler checks for work to do
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct Daemon {
+  config: Config,
+  queue: DownloadQueue,
+  jobs: JobRegistry,
+  webhooks: Arc<Webhooks>,
+  shutdown: CancellationToken,
+}
+
+impl Daemon {
+  async fn handle(self, request: IpcRequest) -> IpcResponse {
+    match request {
+      // answered by the IPC server
+      IpcRequest::Authenticate { .. } => IpcResponse::Ok,
+      IpcRequest::Enqueue { requests } => {
+        info!("queued {} songs for download", requests.len());
+        self.queue.enqueue(requests);
+        IpcResponse::Ok
+      },
+      IpcRequest::Queue => IpcResponse::Queue { items: self.queue.items() },
+      IpcRequest::Resolve { id, policy } => {
+        match self.queue.resolve(id, policy) {
+          true => IpcResponse::Ok,
+          false => IpcResponse::Error { message: format!("download {id} is not waiting for a decision") },
+        }
+      },
+      IpcRequest::Review { id, verdict } => {
+        match self.queue.review(id, verdict) {
+          true => IpcResponse::Ok,
+          false => IpcResponse::Error { message: format!("download {id} is not held for review") },
+        }
+      },
+      IpcRequest::Songs { query, limit, offset } => {
+        match self.songs(query.as_ref(), limit, offset).await {
+          Ok(songs) => IpcResponse::Songs { songs },
+          Err(e) => IpcResponse::Error { message: e.to_string() },
+        }
+      },
+      IpcRequest::ExportPlaylists => {
+        self.export_playlists();
+        IpcResponse::Ok
+      },
+      IpcRequest::Shutdown => {
+        self.shutdown.cancel();
+        IpcResponse::Ok
+      },
+    }
+  }
+
+  async fn songs(&self, query: Option<&Query>, limit: i64, offset: i64) -> Result<Vec<SongRow>> {
+    let mut database = Database::new(self.config.clone()).await?;
+    let mut songs = vec![];
+    database.for_each_song(query, Some(limit.min(MAX_SONGS_PER_REQUEST)), offset, |song| {
+      songs.push(SongRow::from(song));
+      Ok(())
+    })?;
+    Ok(songs)
+  }
+
+  fn export_playlists(&self) {
+    let config = self.config.clone();
+    self.jobs.spawn(JobKind::Sync, "export playlists", move |context| {
+      playlists::export_playlists(config.clone(), None, context)
+    });
+  }
+
+  /// Post the event to the webhooks without waiting for them
+  fn notify(&self, event: WebhookEvent) {
+    let webhooks = self.webhooks.clone();
+    tokio::spawn(async move { webhooks.send(&event).await });
+  }
+
+  /// Post the downloads that stopped to the webhooks
+  async fn watch_downloads(self) {
+    let mut stopped = self.queue.subscribe();
+    loop {
+      let item = tokio::select! {
+        item = stopped.recv() => item,
+        _ = self.shutdown.cancelled() => return,
+      };
+      match item {
+        Ok(item) => {
+          if let Some(event) = WebhookEvent::from_download(&item) {
+            self.notify(event);
+          }
+        },
+        Err(RecvError::Lagged(skipped)) => warn!("{skipped} downloads were not posted to the webhooks"),
+        Err(RecvError::Closed) => return,
+      }
+    }
+  }
+
+  /// Start the scheduled jobs when they are due, and log the jobs that stopped and post them to
+  /// the webhooks
+  async fn schedule(self) {
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+    let mut last_playlists_export = Instant::now();
+    let mut last_backup_check = Instant::now();
+    let mut last_link_check = Instant::now();
+    let mut last_cache_clean = Instant::now();
+    let mut last_playlists_sync = Instant::now();
+    // a retried job keeps its id but starts again
+    let mut reported = HashSet::new();
+    loop {
+      tokio::select! {
+        _ = interval.tick() => {},
+        _ = self.shutdown.cancelled() => return,
+      }
+      for notification in self.jobs.take_notifications() {
+        info!("{notification}");
+      }
+      let jobs = self.jobs.snapshot();
+      for job in &jobs {
+        if let Some(event) = WebhookEvent::from_job(job) {
+          if reported.insert((job.id, job.started_at)) {
+            self.notify(event);
+          }
+        }
+      }
+      // forget the jobs the registry dropped
+      reported.retain(|(id, _)| jobs.iter().any(|job| job.id == *id));
+      if let Some(minutes) = self.config.config.playlists.export_interval_minutes {
+        if last_playlists_export.elapsed() >= Duration::from_secs(minutes * 60) {
+          last_playlists_export = Instant::now();
+          self.export_playlists();
+        }
+      }
+      if last_backup_check.elapsed() >= backup::CHECK_INTERVAL {
+        last_backup_check = Instant::now();
+        match backup::is_due(&self.config) {
+          Ok(true) => {
+            let config = self.config.clone();
+            self.jobs.spawn(JobKind::Sync, "back up the database", move |context| {
+              backup::run_backup(config.clone(), None, context)
+            });
+          },
+          Ok(false) => {},
+          Err(e) => error!("failed to check for a due backup: {e}"),
+        }
+      }
+      if self.config.config.playlists.sync_directory.is_some()
+        && last_playlists_sync.elapsed() >= playlist_sync::SYNC_INTERVAL
+      {
+        last_playlists_sync = Instant::now();
+        let config = self.config.clone();
+        self
+          .jobs
+          .spawn(JobKind::Sync, "sync playlists", move |context| playlist_sync::run_sync(config.clone(), context));
+      }
+      if last_cache_clean.elapsed() >= cache::CLEAN_INTERVAL {
+        last_cache_clean = Instant::now();
+        let (cache_dir, settings) = (self.config.paths.cache_dir.clone(), self.config.config.cache.clone());
+        self.jobs.spawn(JobKind::Sync, "clean the cache", move |context| {
+          cache::run_clean(cache_dir.clone(), settings.clone(), context)
+        });
+      }
+      if last_link_check.elapsed() >= links::CHECK_INTERVAL {
+        last_link_check = Instant::now();
+        let due = match Database::new(self.config.clone()).await {
+          Ok(mut database) => links::is_due(&self.config, &mut database),
+          Err(e) => Err(e),
+        };
+        match due {
+          Ok(true) => {
+            links::spawn_check(&self.jobs, self.config.clone(), false);
+          },
+          Ok(false) => {},
+          Err(e) => error!("failed to check for due videos: {e}"),
+        }
+      }
+    }
+  }
+}
+
+/// Run the daemon until it is asked to shut down over IPC or interrupted
+pub async fn run(config: Config) -> Result<()> {
+  let path = ipc::socket_path(&config);
+  if IpcClient::connect(&path).await?.is_some() {
+    return Err(eyre!("a daemon is already listening on {}", path.display()));
+  }
+  // left over by a daemon that did not shut down cleanly
+  if path.exists() {
+    std::fs::remove_file(&path)?;
+  }
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let listener = IpcListener::Unix(UnixListener::bind(&path)?);
+  println!("listening on {}", path.display());
+  let daemon_config = &config.config.daemon;
+  let acceptor =
+    match daemon_config.tls.enabled && (daemon_config.listen.is_some() || daemon_config.http_listen.is_some()) {
+      true => {
+        let (certificate, key) = tls::certificate_paths(&config)?;
+        println!("clients connect with the certificate {}", certificate.display());
+        tls::acceptor(&daemon_config.tls, &certificate, &key)?
+      },
+      false => None,
+    };
+  let encryption = if acceptor.is_some() { "over TLS" } else { "without encryption" };
+  let remote_listener = match &daemon_config.listen {
+    Some(address) => {
+      let listener = TcpListener::bind(address).await?;
+      println!("listening on {} {encryption}", listener.local_addr()?);
+      Some(IpcListener::Tcp(listener, acceptor.clone()))
+    },
+    None => None,
+  };
+  let http_listener: Option<TcpListener> = match &daemon_config.http_listen {
+    #[cfg(not(feature = "server"))]
+    Some(_) => return Err(eyre!("muzik was built without the server, unset daemon.http_listen")),
+    #[cfg(feature = "server")]
+    Some(address) => {
+      let listener = TcpListener::bind(address).await?;
+      println!("streaming over HTTP on {} {encryption}", listener.local_addr()?);
+      Some(listener)
+    },
+    None => None,
+  };
+
+  let access = Arc::new(AccessControl::new(config.config.daemon.tokens.clone()));
+  let daemon = Daemon {
+    config: config.clone(),
+    queue: DownloadQueue::new(),
+    jobs: JobRegistry::with_config(&config.config.jobs),
+    webhooks: Arc::new(Webhooks::new(config.config.webhooks.clone())),
+    shutdown: CancellationToken::new(),
+  };
+  tokio::spawn(daemon.clone().watch_downloads());
+  let worker = tokio::spawn(daemon.queue.clone().run(config.clone(), daemon.shutdown.clone()));
+  tokio::spawn(daemon.clone().schedule());
+
+  let handler = {
+    let daemon = daemon.clone();
+    move |request| daemon.clone().handle(request)
+  };
+  let serve_remote = async {
+    match remote_listener {
+      Some(listener) => ipc::serve(listener, handler.clone(), access.clone(), daemon.shutdown.clone()).await,
+      None => std::future::pending().await,
+    }
+  };
+  let serve_http = async {
+    match http_listener {
+      #[cfg(feature = "server")]
+      Some(listener) => {
+        let database = DatabaseHandle::new(config.clone()).await?;
+        server::serve(config, database, listener, acceptor, access.clone(), daemon.shutdown.clone()).await
+      },
+      _ => std::future::pending().await,
+    }
+  };
+  let result = tokio::select! {
+    result = ipc::serve(listener, handler.clone(), access.clone(), daemon.shutdown.clone()) => result,
+    result = serve_remote => result,
+    result = serve_http => result,
+    _ = tokio::signal::ctrl_c() => Ok(()),
+  };
+  daemon.shutdown.cancel();
+  match worker.await {
+    Ok(Err(e)) => error!("download worker failed: {e}"),
+    Err(e) => error!("download worker panicked: {e}"),
+    Ok(Ok(())) => {},
+  }
+  std::fs::remove_file(&path)?;
+  result
+}
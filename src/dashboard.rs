@@ -0,0 +1,125 @@
+//! The summary of the library and of the work in progress shown on the Home screen
+
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use color_eyre::eyre::Result;
+
+use crate::{
+  database::Database,
+  models::Song,
+  queue::{QueueItem, QueueStatus},
+};
+
+/// The number of songs listed in each section
+const RECENT_SONGS: i64 = 10;
+
+/// The size of the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LibraryStats {
+  pub songs: i64,
+  pub artists: i64,
+  pub albums: i64,
+  pub plays: i64,
+}
+
+impl fmt::Display for LibraryStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} songs, {} artists, {} albums, {} plays", self.songs, self.artists, self.albums, self.plays)
+  }
+}
+
+/// A song listed on the dashboard, with when it was added or last played
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DashboardSong {
+  pub id: i32,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub at: Option<NaiveDateTime>,
+}
+
+impl DashboardSong {
+  fn load(database: &mut Database, song: Song, at: Option<NaiveDateTime>) -> Result<Self> {
+    let id = song.id;
+    let title = song.title.clone();
+    let artists = database.get_all_artists_for_song(song)?.into_iter().map(|artist| artist.name).collect();
+    Ok(Self { id, title, artists, at })
+  }
+}
+
+impl fmt::Display for DashboardSong {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.artists.is_empty() {
+      write!(f, "{}", self.title)?;
+    } else {
+      write!(f, "{} - {}", self.artists.join(", "), self.title)?;
+    }
+    if let Some(at) = self.at {
+      write!(f, " ({})", at.format("%Y-%m-%d %H:%M"))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dashboard {
+  pub recently_added: Vec<DashboardSong>,
+  pub last_played: Vec<DashboardSong>,
+  /// the downloads that are queued, running or waiting for a decision
+  pub downloads: Vec<QueueItem>,
+  pub stats: LibraryStats,
+}
+
+impl Dashboard {
+  /// Load the dashboard from the library and the items of the download queue
+  pub fn load(database: &mut Database, queue: Vec<QueueItem>) -> Result<Self> {
+    let mut recently_added = vec![];
+    for song in database.get_recently_added(RECENT_SONGS)? {
+      let added_at = song.added_at;
+      recently_added.push(DashboardSong::load(database, song, added_at)?);
+    }
+    let mut last_played = vec![];
+    for (song, played_at) in database.get_recently_played(RECENT_SONGS)? {
+      last_played.push(DashboardSong::load(database, song, Some(played_at))?);
+    }
+    let downloads = queue
+      .into_iter()
+      .filter(|item| {
+        matches!(
+          item.status,
+          QueueStatus::Pending | QueueStatus::Downloading | QueueStatus::Duplicate(_) | QueueStatus::Review(_)
+        )
+      })
+      .collect();
+    Ok(Self { recently_added, last_played, downloads, stats: database.get_library_stats()? })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewSong, SongArtist},
+    queue::DownloadRequest,
+  };
+
+  #[test]
+  fn test_dashboard_load() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    let item = |id, status| QueueItem { id, request: DownloadRequest::default(), status, progress: None };
+    let queue = vec![item(1, QueueStatus::Finished), item(2, QueueStatus::Downloading), item(3, QueueStatus::Pending)];
+
+    let dashboard = Dashboard::load(&mut database, queue)?;
+    assert_eq!(dashboard.recently_added[0].to_string().split(" (").next(), Some("Hoshimachi Suisei - Stellar Stellar"));
+    assert_eq!(dashboard.last_played, vec![]);
+    assert_eq!(dashboard.downloads.iter().map(|item| item.id).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(dashboard.stats.to_string(), "1 songs, 1 artists, 0 albums, 0 plays");
+    Ok(())
+  }
+}
@@ -1,22 +1,114 @@
-use std::path::{Path, PathBuf};
+use std::{
+  path::{Path, PathBuf},
+  time::Duration,
+};
 
+use chrono::{NaiveDateTime, Utc};
 use color_eyre::eyre::{eyre, Context, Result};
-use diesel::{prelude::*, Connection, QueryDsl, RunQueryDsl, SelectableHelper, SqliteConnection};
-use tracing::debug;
+use diesel::{
+  connection::{DefaultLoadingMode, SimpleConnection, TransactionManager},
+  prelude::*,
+  Connection, QueryDsl, RunQueryDsl, SelectableHelper, SqliteConnection,
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use tracing::{debug, info, warn};
 
 use crate::{
   config::Config,
+  dashboard::LibraryStats,
+  merge::{MergeCandidate, MergeKind},
   models::{
-    Album, Artist, Genre, NewAlbum, NewArtist, NewFile, NewGenre, NewSong, Song, SongAlbum, SongArtist, SongGenre,
+    Album, Artist, Attachment, Download, DownloadParams, File, Genre, LinkCheck, MetadataSource, NewAlbum, NewArtist,
+    NewAttachment, NewDownload, NewFile, NewGenre, NewPin, NewPlay, NewPlaylist, NewPlaylistSong, NewSong, Pin,
+    PinKind, Playlist, PlaylistProfile, Provenance, ReleaseType, Snapshot, SnapshotSong, Song, SongAlbum, SongArtist,
+    SongExtra, SongField, SongGenre, SongLock, SongVersion, VersionKind,
+  },
+  quality::AudioQuality,
+  query::{like_pattern, Query, SongSort},
+  schema::{
+    album, artist, attachment, download, download_params, file, genre, link_check, pinned, play, playlist,
+    playlist_profiles, playlist_songs, provenance, snapshot, snapshot_song, song, song_extra, song_lock, song_version,
+    songs_albums, songs_artists, songs_genres,
   },
-  schema::{album, artist, genre, song, songs_artists},
 };
 
+diesel::sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+/// The migrations of the database, embedded into the binary
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+/// The migrations of Postgres databases, the same as [MIGRATIONS] written for Postgres
+#[cfg(feature = "postgres")]
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_postgres");
+
+/// How many items of a bulk change are saved in one transaction
+pub const BATCH_SIZE: usize = 200;
+
+/// How long a connection waits for another one to finish writing before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The connection to the database, a SQLite file unless the config sets a Postgres URL. Queries
+/// are written once for both backends, see `on_backend!` for the ones they write differently
+#[cfg(not(feature = "postgres"))]
+#[derive(diesel::MultiConnection)]
+pub enum DbConnection {
+  Sqlite(SqliteConnection),
+}
+
+/// The connection to the database, a SQLite file unless the config sets a Postgres URL. Queries
+/// are written once for both backends, see `on_backend!` for the ones they write differently
+#[cfg(feature = "postgres")]
+#[derive(diesel::MultiConnection)]
+pub enum DbConnection {
+  Sqlite(SqliteConnection),
+  Postgres(diesel::PgConnection),
+}
+
+/// Run a statement on the connection of the backend in use, for statements only the backends
+/// themselves support, such as upserts
+macro_rules! on_backend {
+  ($connection:expr, |$conn:ident| $statement:expr) => {
+    match $connection {
+      DbConnection::Sqlite($conn) => $statement,
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres($conn) => $statement,
+    }
+  };
+}
+
 pub struct Database {
-  connection: SqliteConnection,
+  connection: DbConnection,
   config: Config,
 }
 
+/// The schema versions of the database before and after its pending migrations were run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migrated {
+  /// `None` for a new database
+  pub from: Option<String>,
+  pub to: Option<String>,
+  /// the versions of the migrations run, in order
+  pub applied: Vec<String>,
+}
+
+impl std::fmt::Display for Migrated {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let version = |version: &Option<String>| version.clone().unwrap_or_else(|| "an empty database".to_string());
+    match self.applied.is_empty() {
+      true => write!(f, "the database is up to date at {}", version(&self.to)),
+      false => {
+        write!(
+          f,
+          "migrated the database from {} to {}, {} migrations run",
+          version(&self.from),
+          version(&self.to),
+          self.applied.len()
+        )
+      },
+    }
+  }
+}
+
 impl Database {
   /// Initialize a new instance of Database
   ///
@@ -28,21 +120,201 @@ impl Database {
   ///
   /// * an instance of `Database` wrapped in a `Result`
   pub async fn new(config: Config) -> Result<Self> {
-    // use local database if using debug builds
-    // database should be determined by config otherwise
+    let url = match &config.config.database.url {
+      Some(url) => url.clone(),
+      None => format!("file:{}", Self::path(&config).display()),
+    };
+    let connection = Self::connect(&url)?;
+    let mut database = Self { connection, config };
+
+    match database.config.config.database.auto_migrate {
+      true => {
+        let migrated = database.migrate()?;
+        if !migrated.applied.is_empty() {
+          info!("{migrated}");
+        }
+      },
+      false => {
+        let pending = database.pending_migrations()?;
+        if !pending.is_empty() {
+          warn!("the database is missing {} migrations, run `muzik migrate` to bring it up to date", pending.len());
+        }
+      },
+    }
+    Ok(database)
+  }
+
+  /// Open the database at the url with every migration run, for databases made on the spot such as
+  /// `:memory:` ones for tests and benchmarks
+  pub fn open_migrated(url: &str, config: Config) -> Result<Self> {
+    let connection = Self::connect(url)?;
+    let mut database = Self { connection, config };
+    database.migrate()?;
+    Ok(database)
+  }
+
+  /// Connect to the database at the url, Postgres for `postgres://` urls and SQLite otherwise.
+  /// The TUI, the daemon, background jobs and the HTTP server each write through a connection of
+  /// their own, so a SQLite connection waits for the others to finish writing rather than failing
+  /// with "database is locked". The write-ahead log lets readers go on while one of them writes
+  fn connect(url: &str) -> Result<DbConnection> {
+    if is_postgres_url(url) {
+      #[cfg(feature = "postgres")]
+      return Ok(DbConnection::Postgres(
+        diesel::PgConnection::establish(url).wrap_err("establish postgres connection")?,
+      ));
+      #[cfg(not(feature = "postgres"))]
+      return Err(eyre!("muzik was built without Postgres support, build it with the `postgres` feature"));
+    }
+    let mut connection = SqliteConnection::establish(url).wrap_err("establish sqlite connection")?;
+    connection
+      .batch_execute(&format!("PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;", BUSY_TIMEOUT.as_millis()))
+      .wrap_err("configure sqlite connection")?;
+    Ok(DbConnection::Sqlite(connection))
+  }
 
-    #[cfg(not(debug_assertions))]
-    let connection = {
-      let url = format!("file:{}", config.config._data_dir.join("database.db").display().to_string());
-      SqliteConnection::establish(&url).wrap_err("establish sqlite connection")?
+  /// The migrations written for the backend in use
+  fn migrations(&self) -> EmbeddedMigrations {
+    match self.connection {
+      DbConnection::Sqlite(_) => MIGRATIONS,
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres(_) => POSTGRES_MIGRATIONS,
+    }
+  }
+
+  /// The version of the last migration run on the database, `None` for a new database
+  pub fn schema_version(&mut self) -> Result<Option<String>> {
+    let applied = self.connection.applied_migrations().map_err(|e| eyre!("failed to read the migrations run: {e}"))?;
+    Ok(applied.iter().max().map(ToString::to_string))
+  }
+
+  /// The versions of the migrations of this version of muzik not yet run on the database, in order
+  pub fn pending_migrations(&mut self) -> Result<Vec<String>> {
+    let migrations = self.migrations();
+    let pending =
+      self.connection.pending_migrations(migrations).map_err(|e| eyre!("failed to read the migrations: {e}"))?;
+    Ok(pending.iter().map(|migration| migration.name().version().to_string()).collect())
+  }
+
+  /// Run the pending migrations, bringing the database up to date with this version of muzik
+  pub fn migrate(&mut self) -> Result<Migrated> {
+    let from = self.schema_version()?;
+    let migrations = self.migrations();
+    let applied = self
+      .connection
+      .run_pending_migrations(migrations)
+      .map_err(|e| eyre!("failed to run the migrations: {e}"))?
+      .iter()
+      .map(ToString::to_string)
+      .collect();
+    let to = self.schema_version()?;
+    Ok(Migrated { from, to, applied })
+  }
+
+  /// The file of the database, see [Paths](crate::paths::Paths)
+  pub fn path(config: &Config) -> PathBuf {
+    config.paths.database.clone()
+  }
+
+  /// A counter that changes whenever another connection, in this process or another one such as
+  /// the daemon, commits to the database. Changes made through this connection leave it as is
+  pub fn data_version(&mut self) -> Result<i64> {
+    #[derive(QueryableByName)]
+    struct DataVersion {
+      #[diesel(sql_type = diesel::sql_types::BigInt)]
+      data_version: i64,
+    }
+    // Postgres has no such counter, every connection counts the statements changing the database
+    // it ran in "data_changes" instead, see the migrations of Postgres
+    let query = match self.connection {
+      DbConnection::Sqlite(_) => "PRAGMA data_version",
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres(_) => {
+        "SELECT CAST(COALESCE(SUM(changes), 0) AS BIGINT) AS data_version FROM data_changes WHERE pid <> \
+         pg_backend_pid()"
+      },
     };
+    let version = diesel::sql_query(query).get_result::<DataVersion>(&mut self.connection)?;
+    Ok(version.data_version)
+  }
+
+  /// Write a consistent copy of the database into a new file
+  pub fn backup_into(&mut self, path: &Path) -> Result<()> {
+    match self.connection {
+      DbConnection::Sqlite(_) => {
+        diesel::sql_query("VACUUM INTO ?")
+          .bind::<diesel::sql_types::Text, _>(path.display().to_string())
+          .execute(&mut self.connection)?;
+        Ok(())
+      },
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres(_) => Err(eyre!("muzik does not back Postgres databases up, back it up with pg_dump")),
+    }
+  }
+
+  /// Write the changes held in the write-ahead log into the database file and empty the log, so
+  /// that the file can be replaced without the log being played over its replacement. Nothing to
+  /// do for Postgres
+  pub fn checkpoint(&mut self) -> Result<()> {
+    match &mut self.connection {
+      DbConnection::Sqlite(connection) => connection.batch_execute("PRAGMA wal_checkpoint(TRUNCATE)")?,
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres(_) => {},
+    }
+    Ok(())
+  }
 
-    #[cfg(debug_assertions)]
-    let connection = SqliteConnection::establish("file:./dev.db").wrap_err("establish sqlite connection")?;
+  /// Make changes to the database and undo them, to tell what they would do
+  pub fn rehearse<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    type Manager = <DbConnection as Connection>::TransactionManager;
+    Manager::begin_transaction(&mut self.connection)?;
+    let result = change(self);
+    Manager::rollback_transaction(&mut self.connection)?;
+    result
+  }
 
-    // TODO: run migrations if available
+  /// Make changes to the database as one, undoing all of them if one fails. Changes made as one
+  /// inside of another are undone on their own
+  pub fn atomically<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    type Manager = <DbConnection as Connection>::TransactionManager;
+    Manager::begin_transaction(&mut self.connection)?;
+    match change(self) {
+      Ok(value) => {
+        Manager::commit_transaction(&mut self.connection)?;
+        Ok(value)
+      },
+      Err(e) => {
+        Manager::rollback_transaction(&mut self.connection)?;
+        Err(e)
+      },
+    }
+  }
 
-    Ok(Self { connection, config })
+  /// Make a change for every item, saving the changes in chunks. A chunk is saved whole or not at
+  /// all, and the chunks saved before one fails are kept
+  ///
+  /// # Arguments
+  ///
+  /// * `chunk_size` - how many items are saved in one transaction, usually `BATCH_SIZE`
+  /// * `progress` - told how many of the items were saved once a chunk is saved
+  pub fn batch<T, R>(
+    &mut self,
+    items: Vec<T>,
+    chunk_size: usize,
+    mut progress: impl FnMut(u64, u64),
+    mut change: impl FnMut(&mut Self, T) -> Result<R>,
+  ) -> Result<Vec<R>> {
+    let total = items.len() as u64;
+    let mut saved = Vec::with_capacity(items.len());
+    let mut items = items.into_iter().peekable();
+    while items.peek().is_some() {
+      let chunk: Vec<T> = items.by_ref().take(chunk_size.max(1)).collect();
+      let results =
+        self.atomically(|database| chunk.into_iter().map(|item| change(database, item)).collect::<Result<Vec<_>>>())?;
+      saved.extend(results);
+      progress(saved.len() as u64, total);
+    }
+    Ok(saved)
   }
 
   /// Insert a `NewSong` into the database
@@ -56,10 +328,37 @@ impl Database {
   /// * the id of the new entry wrapped in a `Result`
   pub fn insert_song(&mut self, new_song: NewSong) -> Result<i32> {
     use crate::schema::song::dsl::*;
-    let res = diesel::insert_into(song).values(&new_song).returning(id).get_result::<i32>(&mut self.connection)?;
+    let res = on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(song)
+        .values((&new_song, added_at.eq(Utc::now().naive_utc())))
+        .returning(id)
+        .get_result::<i32>(conn)
+    })?;
     Ok(res)
   }
 
+  /// Insert a song along with its artists, album and genres, adding those missing from the
+  /// library. The song is inserted whole or not at all
+  ///
+  /// # Returns
+  ///
+  /// * the id of the new song
+  pub fn insert_full_song(
+    &mut self,
+    new_song: NewSong,
+    artists: &[String],
+    album: Option<&str>,
+    genres: &[String],
+  ) -> Result<i32> {
+    self.atomically(|database| {
+      let song_id = database.insert_song(new_song)?;
+      database.set_song_artists(song_id, artists)?;
+      database.set_song_album(song_id, album)?;
+      database.set_song_genres(song_id, genres)?;
+      Ok(song_id)
+    })
+  }
+
   /// Insert an `Artist` into the database. If there is an existing entry with the same name, will
   /// return the id of the existing entry
   ///
@@ -79,13 +378,17 @@ impl Database {
       .get_result(&mut self.connection)
     {
       Ok(artist_id) => artist_id,
-      Err(e) => match e {
-        diesel::result::Error::NotFound => {
-          diesel::insert_into(artist).values(&new_artist).returning(id).get_result(&mut self.connection)?
-        },
-        _ => {
-          return Err(e.into());
-        },
+      Err(e) => {
+        match e {
+          diesel::result::Error::NotFound => {
+            on_backend!(&mut self.connection, |conn| {
+              diesel::insert_into(artist).values(&new_artist).returning(id).get_result(conn)
+            })?
+          },
+          _ => {
+            return Err(e.into());
+          },
+        }
       },
     };
     Ok(artist_id)
@@ -107,13 +410,17 @@ impl Database {
     let album_id: i32 =
       match crate::schema::album::table.filter(name.eq(&new_album.name)).select(id).get_result(&mut self.connection) {
         Ok(album_id) => album_id,
-        Err(e) => match e {
-          diesel::result::Error::NotFound => {
-            diesel::insert_into(album).values(&new_album).returning(id).get_result(&mut self.connection)?
-          },
-          _ => {
-            return Err(e.into());
-          },
+        Err(e) => {
+          match e {
+            diesel::result::Error::NotFound => {
+              on_backend!(&mut self.connection, |conn| {
+                diesel::insert_into(album).values(&new_album).returning(id).get_result(conn)
+              })?
+            },
+            _ => {
+              return Err(e.into());
+            },
+          }
         },
       };
     Ok(album_id)
@@ -135,13 +442,17 @@ impl Database {
     let genre_id: i32 =
       match crate::schema::genre::table.filter(name.eq(&new_genre.name)).select(id).get_result(&mut self.connection) {
         Ok(genre_id) => genre_id,
-        Err(e) => match e {
-          diesel::result::Error::NotFound => {
-            diesel::insert_into(genre).values(&new_genre).returning(id).get_result(&mut self.connection)?
-          },
-          _ => {
-            return Err(e.into());
-          },
+        Err(e) => {
+          match e {
+            diesel::result::Error::NotFound => {
+              on_backend!(&mut self.connection, |conn| {
+                diesel::insert_into(genre).values(&new_genre).returning(id).get_result(conn)
+              })?
+            },
+            _ => {
+              return Err(e.into());
+            },
+          }
         },
       };
     Ok(genre_id)
@@ -155,13 +466,17 @@ impl Database {
       .get_result(&mut self.connection)
     {
       Ok(file_id) => file_id,
-      Err(e) => match e {
-        diesel::result::Error::NotFound => {
-          diesel::insert_into(file).values(&new_file).returning(id).get_result(&mut self.connection)?
-        },
-        _ => {
-          return Err(e.into());
-        },
+      Err(e) => {
+        match e {
+          diesel::result::Error::NotFound => {
+            on_backend!(&mut self.connection, |conn| {
+              diesel::insert_into(file).values(&new_file).returning(id).get_result(conn)
+            })?
+          },
+          _ => {
+            return Err(e.into());
+          },
+        }
       },
     };
     Ok(file_id)
@@ -170,21 +485,21 @@ impl Database {
   pub fn insert_song_artist(&mut self, new_song_artist: SongArtist) -> Result<()> {
     use crate::schema::songs_artists::dsl::*;
 
-    diesel::insert_into(songs_artists).values(new_song_artist).execute(&mut self.connection)?;
+    on_backend!(&mut self.connection, |conn| diesel::insert_into(songs_artists).values(new_song_artist).execute(conn))?;
     Ok(())
   }
 
   pub fn insert_song_album(&mut self, new_song_album: SongAlbum) -> Result<()> {
     use crate::schema::songs_albums::dsl::*;
 
-    diesel::insert_into(songs_albums).values(new_song_album).execute(&mut self.connection)?;
+    on_backend!(&mut self.connection, |conn| diesel::insert_into(songs_albums).values(new_song_album).execute(conn))?;
     Ok(())
   }
 
   pub fn insert_song_genre(&mut self, new_song_genre: SongGenre) -> Result<()> {
     use crate::schema::songs_genres::dsl::*;
 
-    diesel::insert_into(songs_genres).values(new_song_genre).execute(&mut self.connection)?;
+    on_backend!(&mut self.connection, |conn| diesel::insert_into(songs_genres).values(new_song_genre).execute(conn))?;
     Ok(())
   }
 
@@ -193,6 +508,16 @@ impl Database {
     Ok(song)
   }
 
+  /// Find the song downloaded from the video
+  pub fn get_song_by_youtube_id(&mut self, youtube_id: &str) -> Result<Option<Song>> {
+    let song = song::table
+      .filter(song::youtube_id.eq(youtube_id))
+      .select(Song::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(song)
+  }
+
   pub fn get_all_songs(&mut self) -> Result<Vec<Song>> {
     let all_songs: Vec<Song> = song::table.select(Song::as_select()).load(&mut self.connection)?;
 
@@ -222,113 +547,2932 @@ impl Database {
       .load(&mut self.connection)?;
     Ok(artists)
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use color_eyre::eyre::{Context, Result};
-  use diesel::prelude::*;
-  use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-  use pretty_assertions::assert_eq;
+  /// The album the song is on, if it is on one
+  pub fn get_album_for_song(&mut self, song_id: i32) -> Result<Option<Album>> {
+    let album = album::table
+      .inner_join(songs_albums::table)
+      .filter(songs_albums::song_id.eq(song_id))
+      .select(Album::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(album)
+  }
 
-  use super::*;
-  use crate::{
-    config::Config,
-    models::{NewAlbum, NewArtist, NewGenre, NewSong, Song, SongArtist},
-  };
+  /// The genres of the song
+  pub fn get_genres_for_song(&mut self, song_id: i32) -> Result<Vec<Genre>> {
+    let genres = genre::table
+      .inner_join(songs_genres::table)
+      .filter(songs_genres::song_id.eq(song_id))
+      .select(Genre::as_select())
+      .load(&mut self.connection)?;
+    Ok(genres)
+  }
 
-  // embed migrations into tests
-  pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+  pub fn get_all_albums(&mut self) -> Result<Vec<Album>> {
+    let albums = album::table.select(Album::as_select()).load(&mut self.connection)?;
+    Ok(albums)
+  }
 
-  /// Spawns an instance of `Database` with a new instance of in memory sqlite database for tests
-  fn setup_database() -> Result<Database> {
-    let mut connection = SqliteConnection::establish(":memory:").wrap_err("establish sqlite connection")?;
-    connection.run_pending_migrations(MIGRATIONS).expect("migration successful");
-    let database = Database { connection, config: Config::default() };
-    Ok(database)
+  pub fn get_all_artists(&mut self) -> Result<Vec<Artist>> {
+    let artists = artist::table.select(Artist::as_select()).load(&mut self.connection)?;
+    Ok(artists)
   }
 
-  #[test]
-  fn test_database_get_all_songs() -> Result<()> {
-    let mut database = setup_database()?;
-    let insert1 = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
-    let insert2 = database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
-    let insert3 = database.insert_song(NewSong { title: "Loli God Requiem".to_string(), ..Default::default() })?;
+  /// Get the albums with songs credited to an artist
+  ///
+  /// # Returns
+  ///
+  /// * the albums with the number of songs of the artist on each album
+  pub fn get_album_song_counts_for_artist(&mut self, artist_id: i32) -> Result<Vec<(Album, i64)>> {
+    let song_ids = songs_artists::table.filter(songs_artists::artist_id.eq(artist_id)).select(songs_artists::song_id);
+    let albums = album::table
+      .inner_join(songs_albums::table)
+      .filter(songs_albums::song_id.eq_any(song_ids))
+      .group_by(album::id)
+      .select((Album::as_select(), diesel::dsl::count(songs_albums::song_id)))
+      .load(&mut self.connection)?;
+    Ok(albums)
+  }
 
-    let songs = database.get_all_songs()?;
-    let songs_check = vec![
-      Song { id: 1, title: "Stellar Stellar".to_string(), ..Default::default() },
-      Song { id: 2, title: "Crossing Field".to_string(), ..Default::default() },
-      Song { id: 3, title: "Loli God Requiem".to_string(), ..Default::default() },
-    ];
+  /// Get the songs of an album in album order, by disc then by track
+  pub fn get_all_songs_for_album(&mut self, album_id: i32) -> Result<Vec<Song>> {
+    let song_ids = songs_albums::table.filter(songs_albums::album_id.eq(album_id)).select(songs_albums::song_id);
+    let mut songs =
+      song::table.filter(song::id.eq_any(song_ids)).select(Song::as_select()).load(&mut self.connection)?;
+    songs.sort_by(|a, b| a.album_position().cmp(&b.album_position()).then_with(|| a.title.cmp(&b.title)));
+    Ok(songs)
+  }
 
-    assert_eq!(songs, songs_check);
+  /// Get the artists credited on any song of an album
+  pub fn get_all_artists_for_album(&mut self, album_id: i32) -> Result<Vec<Artist>> {
+    let song_ids = songs_albums::table.filter(songs_albums::album_id.eq(album_id)).select(songs_albums::song_id);
+    let artists = artist::table
+      .inner_join(songs_artists::table)
+      .filter(songs_artists::song_id.eq_any(song_ids))
+      .select(Artist::as_select())
+      .distinct()
+      .load(&mut self.connection)?;
+    Ok(artists)
+  }
+
+  /// Set the release level metadata of an album
+  ///
+  /// # Arguments
+  ///
+  /// * `album_id` - the id of the album to update
+  /// * `year` - the year the album was released
+  /// * `label` - the label that released the album
+  /// * `catalog_number` - the catalog number given by the label
+  pub fn update_album_release(
+    &mut self,
+    album_id: i32,
+    year: Option<i32>,
+    label: Option<String>,
+    catalog_number: Option<String>,
+  ) -> Result<()> {
+    diesel::update(album::table.find(album_id))
+      .set((album::year.eq(year), album::label.eq(label), album::catalog_number.eq(catalog_number)))
+      .execute(&mut self.connection)?;
     Ok(())
   }
 
-  #[test]
-  fn test_database_get_all_artists_for_song() -> Result<()> {
-    let mut database = setup_database()?;
+  /// Set what kind of release an album is, or clear it
+  pub fn set_album_release_type(&mut self, album_id: i32, release_type: Option<ReleaseType>) -> Result<()> {
+    diesel::update(album::table.find(album_id))
+      .set(album::release_type.eq(release_type))
+      .execute(&mut self.connection)?;
+    Ok(())
+  }
 
-    let new_song = NewSong { title: "Stellar Stellar".to_string(), ..Default::default() };
-    let song_id = database.insert_song(new_song)?;
-    let artist1_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
-    let artist2_id = database.insert_artist(NewArtist { name: "Comet-chan".to_string() })?;
-    database.insert_song_artist(SongArtist { song_id, artist_id: artist1_id })?;
-    database.insert_song_artist(SongArtist { song_id, artist_id: artist2_id })?;
+  /// Find an album by its name, ignoring case
+  pub fn get_album_by_name(&mut self, name: &str) -> Result<Option<Album>> {
+    let album = album::table
+      .filter(lower(album::name).eq(name.to_lowercase()))
+      .select(Album::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(album)
+  }
 
-    let song = database.get_song_from_id(song_id)?;
-    let artists = database.get_all_artists_for_song(song)?;
-    assert_eq!(
-      artists,
-      vec![Artist { id: 1, name: "Hoshimachi Suisei".to_string() }, Artist { name: "Comet-chan".to_string(), id: 2 }]
-    );
+  /// Pin an artist, album or playlist to the Home screen. Pinning it again does nothing
+  pub fn add_pin(&mut self, pin: NewPin) -> Result<()> {
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(pinned::table).values(pin).on_conflict_do_nothing().execute(conn)
+    })?;
     Ok(())
   }
 
-  #[test]
-  fn test_database_artist_insert_conflict() -> Result<()> {
-    let mut database = setup_database()?;
-    let insert1 = database.insert_artist(NewArtist { name: "Suisei".to_string() })?;
-    let insert2 = database.insert_artist(NewArtist { name: "Suisei".to_string() })?;
-    let insert3 = database.insert_artist(NewArtist { name: "LiSA".to_string() })?;
-    assert_eq!(insert1, insert2);
-    assert_eq!(insert3, 2);
+  /// Unpin an artist, album or playlist
+  pub fn remove_pin(&mut self, kind: PinKind, name: &str) -> Result<()> {
+    diesel::delete(pinned::table.filter(pinned::kind.eq(kind)).filter(pinned::name.eq(name)))
+      .execute(&mut self.connection)?;
     Ok(())
   }
 
-  #[test]
-  fn test_database_album_insert_conflict() -> Result<()> {
-    let mut database = setup_database()?;
-    let insert1 = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
-    let insert2 = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
-    let insert3 = database.insert_album(NewAlbum { name: "Sword Art Online OSTs".to_string() })?;
-    assert_eq!(insert1, insert2);
-    assert_eq!(insert3, 2);
+  /// Get the pins in the order they were pinned
+  pub fn get_pins(&mut self) -> Result<Vec<Pin>> {
+    let pins = pinned::table.order(pinned::id).select(Pin::as_select()).load(&mut self.connection)?;
+    Ok(pins)
+  }
+
+  /// Create an empty playlist. Two playlists can not share a name
+  ///
+  /// # Returns
+  ///
+  /// * the id of the new playlist
+  pub fn create_playlist(&mut self, name: &str) -> Result<i32> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(eyre!("a playlist needs a name"));
+    }
+    if self.get_playlist_by_name(name)?.is_some() {
+      return Err(eyre!("there is already a playlist named {name}"));
+    }
+    let id = on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(playlist::table)
+        .values((NewPlaylist { name: name.to_string() }, playlist::created_at.eq(Utc::now().naive_utc())))
+        .returning(playlist::id)
+        .get_result(conn)
+    })?;
+    Ok(id)
+  }
+
+  /// Get the playlists ordered by name
+  pub fn get_playlists(&mut self) -> Result<Vec<Playlist>> {
+    let playlists = playlist::table.order(playlist::name).select(Playlist::as_select()).load(&mut self.connection)?;
+    Ok(playlists)
+  }
+
+  /// Get a playlist by its exact name
+  pub fn get_playlist_by_name(&mut self, name: &str) -> Result<Option<Playlist>> {
+    let playlist = playlist::table
+      .filter(playlist::name.eq(name.trim()))
+      .select(Playlist::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(playlist)
+  }
+
+  /// Rename a playlist. Two playlists can not share a name
+  pub fn rename_playlist(&mut self, playlist_id: i32, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(eyre!("a playlist needs a name"));
+    }
+    if self.get_playlist_by_name(name)?.is_some_and(|playlist| playlist.id != playlist_id) {
+      return Err(eyre!("there is already a playlist named {name}"));
+    }
+    diesel::update(playlist::table.find(playlist_id)).set(playlist::name.eq(name)).execute(&mut self.connection)?;
     Ok(())
   }
 
-  #[test]
-  fn test_database_genre_insert_conflict() -> Result<()> {
-    let mut database = setup_database()?;
-    let insert1 = database.insert_genre(NewGenre { name: "Japanese Pop".to_string() })?;
-    let insert2 = database.insert_genre(NewGenre { name: "Japanese Pop".to_string() })?;
-    let insert3 = database.insert_genre(NewGenre { name: "Japanese Rock".to_string() })?;
-    assert_eq!(insert1, insert2);
-    assert_eq!(insert3, 2);
+  /// Delete a playlist, its entries and its export profiles. The songs stay in the library
+  pub fn delete_playlist(&mut self, playlist_id: i32) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(playlist_songs::table.filter(playlist_songs::playlist_id.eq(playlist_id))).execute(conn)?;
+      diesel::delete(playlist_profiles::table.filter(playlist_profiles::playlist_id.eq(playlist_id))).execute(conn)?;
+      let deleted = diesel::delete(playlist::table.find(playlist_id)).execute(conn)?;
+      match deleted {
+        0 => Err(diesel::result::Error::NotFound),
+        _ => Ok(()),
+      }
+    })?;
     Ok(())
   }
 
-  #[test]
-  fn test_database_song_artist_insert_conflict() -> Result<()> {
-    let mut database = setup_database()?;
-    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
-    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+  /// Get the songs of a playlist in order. A song added twice is listed twice
+  pub fn get_playlist_songs(&mut self, playlist_id: i32) -> Result<Vec<Song>> {
+    let songs = playlist_songs::table
+      .inner_join(song::table)
+      .filter(playlist_songs::playlist_id.eq(playlist_id))
+      .order(playlist_songs::position)
+      .select(Song::as_select())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
 
-    database.insert_song_artist(SongArtist { song_id, artist_id })?;
-    // this should return an error
-    assert!(database.insert_song_artist(SongArtist { song_id, artist_id }).is_err());
+  /// Add songs to the end of a playlist, in the order given
+  pub fn add_playlist_songs(&mut self, playlist_id: i32, song_ids: &[i32]) -> Result<()> {
+    self.connection.transaction(|conn| {
+      let last: Option<i32> = playlist_songs::table
+        .filter(playlist_songs::playlist_id.eq(playlist_id))
+        .select(diesel::dsl::max(playlist_songs::position))
+        .first(conn)?;
+      let first = last.map_or(0, |last| last + 1);
+      let entries: Vec<_> = song_ids
+        .iter()
+        .zip(first..)
+        .map(|(&song_id, position)| NewPlaylistSong { playlist_id, song_id, position })
+        .collect();
+      on_backend!(conn, |conn| diesel::insert_into(playlist_songs::table).values(entries).execute(conn))?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Replace the songs of a playlist with the songs given, in order
+  pub fn set_playlist_songs(&mut self, playlist_id: i32, song_ids: &[i32]) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(playlist_songs::table.filter(playlist_songs::playlist_id.eq(playlist_id))).execute(conn)?;
+      let entries: Vec<_> = song_ids
+        .iter()
+        .zip(0..)
+        .map(|(&song_id, position)| NewPlaylistSong { playlist_id, song_id, position })
+        .collect();
+      on_backend!(conn, |conn| diesel::insert_into(playlist_songs::table).values(entries).execute(conn))?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Replace the export profiles the playlist is exported with
+  pub fn set_playlist_profiles(&mut self, playlist_id: i32, profiles: &[String]) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(playlist_profiles::table.filter(playlist_profiles::playlist_id.eq(playlist_id))).execute(conn)?;
+      for profile in profiles {
+        let profile = PlaylistProfile { playlist_id, profile: profile.clone() };
+        on_backend!(conn, |conn| {
+          diesel::insert_into(playlist_profiles::table).values(&profile).on_conflict_do_nothing().execute(conn)
+        })?;
+      }
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Get the export profiles of every playlist, ordered by name
+  pub fn get_playlist_profiles(&mut self) -> Result<Vec<PlaylistProfile>> {
+    let profiles = playlist_profiles::table
+      .order((playlist_profiles::playlist_id, playlist_profiles::profile))
+      .select(PlaylistProfile::as_select())
+      .load(&mut self.connection)?;
+    Ok(profiles)
+  }
+
+  /// Remove the song at `index` of the playlist, as listed by [`Database::get_playlist_songs`]
+  pub fn remove_playlist_song(&mut self, playlist_id: i32, index: usize) -> Result<()> {
+    self.reorder_playlist(playlist_id, |entries| {
+      if index >= entries.len() {
+        return false;
+      }
+      entries.remove(index);
+      true
+    })
+  }
+
+  /// Move the song at index `from` of the playlist to index `to`, as listed by
+  /// [`Database::get_playlist_songs`]
+  pub fn move_playlist_song(&mut self, playlist_id: i32, from: usize, to: usize) -> Result<()> {
+    self.reorder_playlist(playlist_id, |entries| {
+      if from >= entries.len() || to >= entries.len() {
+        return false;
+      }
+      let entry = entries.remove(from);
+      entries.insert(to, entry);
+      true
+    })
+  }
+
+  /// Change the entries of a playlist in order, then number them again from 0. The change returns
+  /// false when an index is out of the playlist
+  fn reorder_playlist(&mut self, playlist_id: i32, change: impl FnOnce(&mut Vec<i32>) -> bool) -> Result<()> {
+    self.connection.transaction(|conn| {
+      let mut entries: Vec<i32> = playlist_songs::table
+        .filter(playlist_songs::playlist_id.eq(playlist_id))
+        .order(playlist_songs::position)
+        .select(playlist_songs::id)
+        .load(conn)?;
+      let before = entries.clone();
+      if !change(&mut entries) {
+        return Err(eyre!("there is no such song in the playlist"));
+      }
+      let removed: Vec<i32> = before.into_iter().filter(|id| !entries.contains(id)).collect();
+      diesel::delete(playlist_songs::table.filter(playlist_songs::id.eq_any(removed))).execute(conn)?;
+      for (position, id) in (0..).zip(entries) {
+        diesel::update(playlist_songs::table.find(id)).set(playlist_songs::position.eq(position)).execute(conn)?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Record a snapshot of the library made of the songs given, whose snapshot id is set to the
+  /// one of the new snapshot
+  pub fn insert_snapshot(&mut self, name: &str, songs: Vec<SnapshotSong>) -> Result<Snapshot> {
+    let taken_at = Utc::now().naive_utc();
+    let snapshot = self.connection.transaction(|conn| {
+      let snapshot = on_backend!(conn, |conn| {
+        diesel::insert_into(snapshot::table)
+          .values((snapshot::name.eq(name.trim()), snapshot::taken_at.eq(taken_at)))
+          .returning(Snapshot::as_returning())
+          .get_result(conn)
+      })?;
+      for chunk in songs.chunks(BATCH_SIZE) {
+        let chunk: Vec<_> =
+          chunk.iter().map(|song| SnapshotSong { snapshot_id: snapshot.id, ..song.clone() }).collect();
+        on_backend!(conn, |conn| diesel::insert_into(snapshot_song::table).values(chunk).execute(conn))?;
+      }
+      diesel::QueryResult::Ok(snapshot)
+    })?;
+    Ok(snapshot)
+  }
+
+  /// Get the snapshots, the latest first
+  pub fn get_snapshots(&mut self) -> Result<Vec<Snapshot>> {
+    let snapshots = snapshot::table
+      .order((snapshot::taken_at.desc(), snapshot::id.desc()))
+      .select(Snapshot::as_select())
+      .load(&mut self.connection)?;
+    Ok(snapshots)
+  }
+
+  /// Get the songs of a snapshot, ordered by song id
+  pub fn get_snapshot_songs(&mut self, snapshot_id: i32) -> Result<Vec<SnapshotSong>> {
+    let songs = snapshot_song::table
+      .filter(snapshot_song::snapshot_id.eq(snapshot_id))
+      .order(snapshot_song::song_id)
+      .select(SnapshotSong::as_select())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Delete a snapshot and its songs. The library is left as it is
+  pub fn delete_snapshot(&mut self, snapshot_id: i32) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(snapshot_song::table.filter(snapshot_song::snapshot_id.eq(snapshot_id))).execute(conn)?;
+      let deleted = diesel::delete(snapshot::table.find(snapshot_id)).execute(conn)?;
+      match deleted {
+        0 => Err(diesel::result::Error::NotFound),
+        _ => Ok(()),
+      }
+    })?;
+    Ok(())
+  }
+
+  /// Attach a file copied into the music dir to a song or an album
+  pub fn insert_attachment(&mut self, new_attachment: NewAttachment) -> Result<Attachment> {
+    let attachment = on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(attachment::table)
+        .values((&new_attachment, attachment::added_at.eq(Utc::now().naive_utc())))
+        .returning(Attachment::as_returning())
+        .get_result(conn)
+    })?;
+    Ok(attachment)
+  }
+
+  pub fn get_attachment(&mut self, attachment_id: i32) -> Result<Option<Attachment>> {
+    let attachment =
+      attachment::table.find(attachment_id).select(Attachment::as_select()).first(&mut self.connection).optional()?;
+    Ok(attachment)
+  }
+
+  /// Get the files attached to a song, in the order they were attached
+  pub fn get_song_attachments(&mut self, song_id: i32) -> Result<Vec<Attachment>> {
+    let attachments = attachment::table
+      .filter(attachment::song_id.eq(song_id))
+      .order(attachment::id)
+      .select(Attachment::as_select())
+      .load(&mut self.connection)?;
+    Ok(attachments)
+  }
+
+  /// Get the files attached to an album, in the order they were attached
+  pub fn get_album_attachments(&mut self, album_id: i32) -> Result<Vec<Attachment>> {
+    let attachments = attachment::table
+      .filter(attachment::album_id.eq(album_id))
+      .order(attachment::id)
+      .select(Attachment::as_select())
+      .load(&mut self.connection)?;
+    Ok(attachments)
+  }
+
+  pub fn get_all_attachments(&mut self) -> Result<Vec<Attachment>> {
+    let attachments =
+      attachment::table.order(attachment::id).select(Attachment::as_select()).load(&mut self.connection)?;
+    Ok(attachments)
+  }
+
+  /// Remove an attachment from the library, its file is left to the caller
+  pub fn remove_attachment(&mut self, attachment_id: i32) -> Result<()> {
+    diesel::delete(attachment::table.find(attachment_id)).execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Mark a song as an alternate version of another, replacing what it was marked as before
+  pub fn link_version(&mut self, version: SongVersion) -> Result<()> {
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(song_version::table)
+        .values(&version)
+        .on_conflict(song_version::song_id)
+        .do_update()
+        .set((song_version::original_id.eq(version.original_id), song_version::kind.eq(version.kind)))
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get the alternate versions of a song
+  pub fn get_versions(&mut self, original_id: i32) -> Result<Vec<(Song, VersionKind)>> {
+    let versions = song_version::table
+      .inner_join(song::table)
+      .filter(song_version::original_id.eq(original_id))
+      .order(song::id)
+      .select((Song::as_select(), song_version::kind))
+      .load(&mut self.connection)?;
+    Ok(versions)
+  }
+
+  /// Get the song the given song is an alternate version of, if it is one
+  pub fn get_original(&mut self, song_id: i32) -> Result<Option<(Song, VersionKind)>> {
+    let Some(version) =
+      song_version::table.find(song_id).select(SongVersion::as_select()).first(&mut self.connection).optional()?
+    else {
+      return Ok(None);
+    };
+    let original = self.get_song_from_id(version.original_id)?;
+    Ok(Some((original, version.kind)))
+  }
+
+  /// Find a song by its title and one of its artists, ignoring case. If no song is credited to the
+  /// artist, a song is only returned if it is the only one with the title
+  ///
+  /// # Arguments
+  ///
+  /// * `title` - the title of the song
+  /// * `artist_name` - the name of one of the artists of the song
+  ///
+  /// # Returns
+  ///
+  /// * the song if found wrapped in a `Result`
+  pub fn find_song_by_title_and_artist(&mut self, title: &str, artist_name: &str) -> Result<Option<Song>> {
+    let by_artist = song::table
+      .inner_join(songs_artists::table.inner_join(artist::table))
+      .filter(lower(song::title).eq(title.to_lowercase()))
+      .filter(lower(artist::name).eq(artist_name.to_lowercase()))
+      .select(Song::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    if by_artist.is_some() {
+      return Ok(by_artist);
+    }
+
+    let mut by_title: Vec<Song> = song::table
+      .filter(lower(song::title).eq(title.to_lowercase()))
+      .select(Song::as_select())
+      .limit(2)
+      .load(&mut self.connection)?;
+    if by_title.len() == 1 {
+      Ok(by_title.pop())
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// Add to the play count of a song
+  pub fn add_play_count(&mut self, song_id: i32, count: i32) -> Result<()> {
+    diesel::update(song::table.find(song_id))
+      .set(song::play_count.eq(song::play_count + count))
+      .execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Search the titles, artists, albums and genres of the songs for every word of the text, best
+  /// matches first on SQLite. Words are found anywhere in the names, regardless of case
+  pub fn search_songs(&mut self, text: &str) -> Result<Vec<Song>> {
+    #[derive(QueryableByName)]
+    struct Found {
+      #[diesel(sql_type = diesel::sql_types::Integer)]
+      id: i32,
+    }
+    let found: Vec<Found> = match self.connection {
+      DbConnection::Sqlite(_) => {
+        // the trigram tokenizer only finds words of three letters or more, shorter ones are looked
+        // for with `LIKE`
+        let (long, short): (Vec<&str>, Vec<&str>) = text.split_whitespace().partition(|word| word.chars().count() >= 3);
+        if long.is_empty() && short.is_empty() {
+          return Ok(vec![]);
+        }
+        let mut conditions = vec![];
+        if !long.is_empty() {
+          conditions.push("song_search MATCH ?");
+        }
+        conditions.extend(short.iter().map(|_| {
+          "(title LIKE ? ESCAPE '\\' OR artists LIKE ? ESCAPE '\\' OR album LIKE ? ESCAPE '\\' OR genres LIKE ? \
+           ESCAPE '\\')"
+        }));
+        let order = if long.is_empty() { "rowid" } else { "rank, rowid" };
+        let sql = format!("SELECT rowid AS id FROM song_search WHERE {} ORDER BY {order}", conditions.join(" AND "));
+
+        let mut query = diesel::sql_query(sql).into_boxed();
+        if !long.is_empty() {
+          // every word is a phrase of its own, so that the syntax of FTS5 queries is left alone
+          let phrases: Vec<_> = long.iter().map(|word| format!("\"{}\"", word.replace('"', "\"\""))).collect();
+          query = query.bind::<diesel::sql_types::Text, _>(phrases.join(" "));
+        }
+        for word in short {
+          for _ in 0..4 {
+            query = query.bind::<diesel::sql_types::Text, _>(like_pattern(word));
+          }
+        }
+        query.load(&mut self.connection)?
+      },
+      // "song_search" is a view of the names of every song there, every word is looked for with
+      // `ILIKE`
+      #[cfg(feature = "postgres")]
+      DbConnection::Postgres(_) => {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.is_empty() {
+          return Ok(vec![]);
+        }
+        let conditions: Vec<_> = (1..=words.len())
+          .map(|n| format!("(title ILIKE ${n} OR artists ILIKE ${n} OR album ILIKE ${n} OR genres ILIKE ${n})"))
+          .collect();
+        let sql = format!("SELECT id FROM song_search WHERE {} ORDER BY id", conditions.join(" AND "));
+        let mut query = diesel::sql_query(sql).into_boxed();
+        for word in words {
+          query = query.bind::<diesel::sql_types::Text, _>(like_pattern(word));
+        }
+        query.load(&mut self.connection)?
+      },
+    };
+
+    let order: std::collections::HashMap<i32, usize> =
+      found.iter().enumerate().map(|(index, found)| (found.id, index)).collect();
+    let mut songs =
+      song::table.filter(song::id.eq_any(order.keys())).select(Song::as_select()).load(&mut self.connection)?;
+    songs.sort_by_key(|song| order[&song.id]);
+    Ok(songs)
+  }
+
+  /// Get the songs matching a query, ordered by id
+  pub fn query_songs(&mut self, query: &Query) -> Result<Vec<Song>> {
+    let songs = song::table
+      .filter(query.to_predicate())
+      .order(song::id.asc())
+      .select(Song::as_select())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Visit the songs matching a query one at a time, ordered by id. Songs are read from the
+  /// database as they are visited instead of being loaded all at once
+  ///
+  /// # Arguments
+  ///
+  /// * `query` - only visit the songs matching the query, every song if `None`
+  /// * `limit` - the maximum number of songs to visit
+  /// * `offset` - the number of matching songs to skip
+  /// * `visit` - called with every song, stops the iteration when it returns an error
+  pub fn for_each_song(
+    &mut self,
+    query: Option<&Query>,
+    limit: Option<i64>,
+    offset: i64,
+    mut visit: impl FnMut(Song) -> Result<()>,
+  ) -> Result<()> {
+    let mut statement = song::table.select(Song::as_select()).order(song::id.asc()).offset(offset).into_boxed();
+    if let Some(query) = query {
+      statement = statement.filter(query.to_predicate());
+    }
+    // SQLite only accepts an offset after a limit, and Postgres no negative limit to mean none
+    statement = statement.limit(limit.unwrap_or(i64::MAX));
+    for song in statement.load_iter::<Song, DefaultLoadingMode>(&mut self.connection)? {
+      visit(song?)?;
+    }
+    Ok(())
+  }
+
+  /// Get a page of the songs matching a query, in the order given. Songs of the same title or play
+  /// count are ordered by id, so that the pages do not overlap
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the number of matching songs before the page
+  /// * `limit` - the maximum number of songs of the page
+  /// * `filter` - only list the songs matching the query, every song if `None`
+  pub fn get_songs_page(
+    &mut self,
+    offset: i64,
+    limit: i64,
+    sort: SongSort,
+    filter: Option<&Query>,
+  ) -> Result<Vec<Song>> {
+    let mut statement = song::table.select(Song::as_select()).offset(offset).limit(limit).into_boxed();
+    if let Some(filter) = filter {
+      statement = statement.filter(filter.to_predicate());
+    }
+    statement = match sort {
+      SongSort::Added => statement.order(song::id.asc()),
+      SongSort::Title => statement.order((lower(song::title).asc(), song::id.asc())),
+      SongSort::PlayCount => statement.order((song::play_count.desc(), song::id.asc())),
+    };
+    Ok(statement.load(&mut self.connection)?)
+  }
+
+  /// Count the songs matching a query, or every song if `None`
+  pub fn count_songs(&mut self, filter: Option<&Query>) -> Result<i64> {
+    let mut statement = song::table.count().into_boxed();
+    if let Some(filter) = filter {
+      statement = statement.filter(filter.to_predicate());
+    }
+    Ok(statement.get_result(&mut self.connection)?)
+  }
+
+  /// Get the songs matching a query whose title is one of the titles given, regardless of case
+  pub fn get_songs_titled(&mut self, titles: &[String], filter: Option<&Query>) -> Result<Vec<Song>> {
+    let titles: Vec<_> = titles.iter().map(|title| title.to_lowercase()).collect();
+    let mut statement =
+      song::table.select(Song::as_select()).filter(lower(song::title).eq_any(titles)).order(song::id).into_boxed();
+    if let Some(filter) = filter {
+      statement = statement.filter(filter.to_predicate());
+    }
+    Ok(statement.load(&mut self.connection)?)
+  }
+
+  /// Rate a song from 1 to 5 stars, or remove its rating
+  pub fn set_song_rating(&mut self, song_id: i32, rating: Option<i32>) -> Result<()> {
+    if let Some(rating) = rating.filter(|rating| !(1..=5).contains(rating)) {
+      return Err(eyre!("rating must be from 1 to 5, got {rating}"));
+    }
+    diesel::update(song::table.find(song_id)).set(song::rating.eq(rating)).execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Point a song to another file
+  pub fn set_song_file(&mut self, song_id: i32, file_id: i32) -> Result<()> {
+    diesel::update(song::table.find(song_id)).set(song::file_id.eq(file_id)).execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Set the disc and the track of a song on its album
+  pub fn set_song_position(&mut self, song_id: i32, disc_number: Option<i32>, track_number: Option<i32>) -> Result<()> {
+    if let Some(number) = disc_number.into_iter().chain(track_number).find(|number| *number < 1) {
+      return Err(eyre!("disc and track numbers start from 1, got {number}"));
+    }
+    diesel::update(song::table.find(song_id))
+      .set((song::disc_number.eq(disc_number), song::track_number.eq(track_number)))
+      .execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Set the title and the video of a song. A song linked to another video has it checked again
+  pub fn update_song(&mut self, song_id: i32, title: &str, youtube_id: Option<&str>) -> Result<()> {
+    if title.trim().is_empty() {
+      return Err(eyre!("a song needs a title"));
+    }
+    self.connection.transaction(|conn| {
+      let current: Option<String> = song::table.find(song_id).select(song::youtube_id).first(conn)?;
+      diesel::update(song::table.find(song_id))
+        .set((song::title.eq(title), song::youtube_id.eq(youtube_id)))
+        .execute(conn)?;
+      if current.as_deref() != youtube_id {
+        diesel::delete(link_check::table.find(song_id)).execute(conn)?;
+      }
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Credit the song to the artists in place of its current ones, adding the artists missing from
+  /// the library
+  pub fn set_song_artists(&mut self, song_id: i32, names: &[String]) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(songs_artists::table.filter(songs_artists::song_id.eq(song_id))).execute(conn)?;
+      for name in names {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(artist::table)
+            .values(NewArtist { name: name.clone() })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+        let artist_id = artist::table.filter(artist::name.eq(name)).select(artist::id).first(conn)?;
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_artists::table)
+            .values(SongArtist { song_id, artist_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Put the song on the album in place of its current one, adding the album if it is missing from
+  /// the library, or take it off its album
+  pub fn set_song_album(&mut self, song_id: i32, name: Option<&str>) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(songs_albums::table.filter(songs_albums::song_id.eq(song_id))).execute(conn)?;
+      if let Some(name) = name {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(album::table)
+            .values(NewAlbum { name: name.to_string() })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+        let album_id = album::table.filter(album::name.eq(name)).select(album::id).first(conn)?;
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_albums::table).values(SongAlbum { song_id, album_id }).execute(conn)
+        })?;
+      }
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Set the genres of the song in place of its current ones, adding the genres missing from the
+  /// library
+  pub fn set_song_genres(&mut self, song_id: i32, names: &[String]) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::delete(songs_genres::table.filter(songs_genres::song_id.eq(song_id))).execute(conn)?;
+      for name in names {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(genre::table)
+            .values(NewGenre { name: name.clone() })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+        let genre_id = genre::table.filter(genre::name.eq(name)).select(genre::id).first(conn)?;
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_genres::table)
+            .values(SongGenre { song_id, genre_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Delete a song along with its credits, plays, versions, attachments and the record of its file.
+  /// The artists, albums and genres left without songs are deleted too. The file itself is left in
+  /// the music dir
+  ///
+  /// # Returns
+  ///
+  /// * the path of the file of the song from the music dir, if it had one
+  pub fn delete_song(&mut self, song_id: i32) -> Result<Option<String>> {
+    let relative_path = self.connection.transaction(|conn| {
+      let file_id: Option<i32> = song::table.find(song_id).select(song::file_id).first(conn)?;
+      diesel::delete(songs_artists::table.filter(songs_artists::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(songs_albums::table.filter(songs_albums::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(songs_genres::table.filter(songs_genres::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(play::table.filter(play::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(attachment::table.filter(attachment::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(playlist_songs::table.filter(playlist_songs::song_id.eq(song_id))).execute(conn)?;
+      // the versions of the song are versions of nothing anymore
+      diesel::delete(
+        song_version::table.filter(song_version::song_id.eq(song_id).or(song_version::original_id.eq(song_id))),
+      )
+      .execute(conn)?;
+      diesel::delete(song_extra::table.find(song_id)).execute(conn)?;
+      diesel::delete(provenance::table.filter(provenance::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(song_lock::table.filter(song_lock::song_id.eq(song_id))).execute(conn)?;
+      diesel::delete(link_check::table.find(song_id)).execute(conn)?;
+      diesel::delete(song::table.find(song_id)).execute(conn)?;
+      let relative_path = match file_id {
+        Some(file_id) => {
+          let relative_path: String = file::table.find(file_id).select(file::relative_path).first(conn)?;
+          diesel::delete(download_params::table.find(file_id)).execute(conn)?;
+          diesel::delete(file::table.find(file_id)).execute(conn)?;
+          Some(relative_path)
+        },
+        None => None,
+      };
+      delete_orphans(conn)?;
+      diesel::QueryResult::Ok(relative_path)
+    })?;
+    Ok(relative_path)
+  }
+
+  /// Delete the artists, albums and genres that no song is credited to, keeping the albums with
+  /// attachments
+  ///
+  /// # Returns
+  ///
+  /// * the number of entries deleted
+  pub fn delete_orphans(&mut self) -> Result<usize> {
+    Ok(self.connection.transaction(delete_orphans)?)
+  }
+
+  /// Rename an artist, along with its pin. Two artists can not share a name, they are merged instead
+  pub fn update_artist(&mut self, artist_id: i32, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(eyre!("an artist needs a name"));
+    }
+    let taken: i64 = artist::table
+      .filter(artist::name.eq(name))
+      .filter(artist::id.ne(artist_id))
+      .count()
+      .get_result(&mut self.connection)?;
+    if taken > 0 {
+      return Err(eyre!("there is another artist named {name}, merge them instead"));
+    }
+    self.connection.transaction(|conn| {
+      let current: String = artist::table.find(artist_id).select(artist::name).first(conn)?;
+      diesel::update(artist::table.find(artist_id)).set(artist::name.eq(name)).execute(conn)?;
+      diesel::update(pinned::table.filter(pinned::kind.eq(PinKind::Artist)).filter(pinned::name.eq(current)))
+        .set(pinned::name.eq(name))
+        .execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Rename an album, along with its pin. Two albums can not share a name
+  pub fn rename_album(&mut self, album_id: i32, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(eyre!("an album needs a name"));
+    }
+    let taken: i64 = album::table
+      .filter(album::name.eq(name))
+      .filter(album::id.ne(album_id))
+      .count()
+      .get_result(&mut self.connection)?;
+    if taken > 0 {
+      return Err(eyre!("there is another album named {name}"));
+    }
+    self.connection.transaction(|conn| {
+      let current: String = album::table.find(album_id).select(album::name).first(conn)?;
+      diesel::update(album::table.find(album_id)).set(album::name.eq(name)).execute(conn)?;
+      diesel::update(pinned::table.filter(pinned::kind.eq(PinKind::Album)).filter(pinned::name.eq(current)))
+        .set(pinned::name.eq(name))
+        .execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Rename a genre. Two genres can not share a name
+  pub fn rename_genre(&mut self, genre_id: i32, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(eyre!("a genre needs a name"));
+    }
+    let taken: i64 = genre::table
+      .filter(genre::name.eq(name))
+      .filter(genre::id.ne(genre_id))
+      .count()
+      .get_result(&mut self.connection)?;
+    if taken > 0 {
+      return Err(eyre!("there is another genre named {name}"));
+    }
+    let updated =
+      diesel::update(genre::table.find(genre_id)).set(genre::name.eq(name)).execute(&mut self.connection)?;
+    if updated == 0 {
+      return Err(eyre!("there is no genre with id {genre_id}"));
+    }
+    Ok(())
+  }
+
+  /// Store the description and comments of the video of a song, replacing the stored ones
+  pub fn set_song_extra(&mut self, extra: &SongExtra) -> Result<()> {
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(song_extra::table)
+        .values(extra)
+        .on_conflict(song_extra::song_id)
+        .do_update()
+        .set(extra)
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get the description and comments of the video of a song, `None` if none were stored
+  pub fn get_song_extra(&mut self, song_id: i32) -> Result<Option<SongExtra>> {
+    let extra =
+      song_extra::table.find(song_id).select(SongExtra::as_select()).first(&mut self.connection).optional()?;
+    Ok(extra)
+  }
+
+  /// Record the outcome of checking the video of a song, replacing the previous one
+  pub fn set_link_check(&mut self, check: &LinkCheck) -> Result<()> {
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(link_check::table)
+        .values(check)
+        .on_conflict(link_check::song_id)
+        .do_update()
+        .set(check)
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get the last checks of the videos of the songs
+  pub fn get_link_checks(&mut self) -> Result<Vec<LinkCheck>> {
+    Ok(link_check::table.select(LinkCheck::as_select()).load(&mut self.connection)?)
+  }
+
+  /// Link the song to another video, to be checked again
+  pub fn relink_song(&mut self, song_id: i32, youtube_id: &str, source: MetadataSource) -> Result<()> {
+    self.connection.transaction(|conn| {
+      diesel::update(song::table.find(song_id)).set(song::youtube_id.eq(youtube_id)).execute(conn)?;
+      diesel::delete(link_check::table.find(song_id)).execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    self.record_provenance(song_id, SongField::YoutubeId, source, Some(youtube_id.to_string()))
+  }
+
+  /// Set the thumbnail of the song, which is its cover art
+  pub fn set_song_thumbnail(&mut self, song_id: i32, url: &str, source: MetadataSource) -> Result<()> {
+    diesel::update(song::table.find(song_id)).set(song::thumbnail_url.eq(url)).execute(&mut self.connection)?;
+    self.record_provenance(song_id, SongField::ThumbnailUrl, source, Some(url.to_string()))
+  }
+
+  /// Lock or unlock a field of the song against changes by automation
+  pub fn set_song_field_locked(&mut self, song_id: i32, field: SongField, locked: bool) -> Result<()> {
+    match locked {
+      true => {
+        on_backend!(&mut self.connection, |conn| {
+          diesel::insert_into(song_lock::table)
+            .values(SongLock { song_id, field })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      },
+      false => {
+        diesel::delete(song_lock::table.find((song_id, field.to_string()))).execute(&mut self.connection)?;
+      },
+    }
+    Ok(())
+  }
+
+  /// Get the locked fields of the song, in the order of `SongField`
+  pub fn get_song_locks(&mut self, song_id: i32) -> Result<Vec<SongField>> {
+    let mut fields: Vec<SongField> =
+      song_lock::table.filter(song_lock::song_id.eq(song_id)).select(song_lock::field).load(&mut self.connection)?;
+    fields.sort();
+    Ok(fields)
+  }
+
+  /// Whether the field of the song is locked against changes by automation
+  pub fn is_song_field_locked(&mut self, song_id: i32, field: SongField) -> Result<bool> {
+    let count: i64 = song_lock::table.find((song_id, field.to_string())).count().get_result(&mut self.connection)?;
+    Ok(count > 0)
+  }
+
+  /// Get the values of the fields of the song, with artists and genres one name per line
+  pub fn get_song_field_values(&mut self, song_id: i32) -> Result<Vec<(SongField, Option<String>)>> {
+    let song = self.get_song_from_id(song_id)?;
+    let artists: Vec<String> = artist::table
+      .inner_join(songs_artists::table)
+      .filter(songs_artists::song_id.eq(song_id))
+      .select(artist::name)
+      .load(&mut self.connection)?;
+    let album = self.get_album_for_song(song_id)?.map(|album| album.name);
+    let genres: Vec<String> = self.get_genres_for_song(song_id)?.into_iter().map(|genre| genre.name).collect();
+    let lines = |names: Vec<String>| (!names.is_empty()).then(|| names.join("\n"));
+    Ok(vec![
+      (SongField::Title, Some(song.title)),
+      (SongField::Artists, lines(artists)),
+      (SongField::Album, album),
+      (SongField::Genres, lines(genres)),
+      (SongField::YoutubeId, song.youtube_id),
+      (SongField::ThumbnailUrl, song.thumbnail_url),
+      (SongField::TrackNumber, song.track_number.map(|number| number.to_string())),
+      (SongField::DiscNumber, song.disc_number.map(|number| number.to_string())),
+    ])
+  }
+
+  /// Record where the value of a field of the song came from. The value it replaces is kept so
+  /// that it can be reverted, and a value equal to the recorded one keeps its source
+  pub fn record_provenance(
+    &mut self,
+    song_id: i32,
+    field: SongField,
+    source: MetadataSource,
+    value: Option<String>,
+  ) -> Result<()> {
+    self.connection.transaction(|conn| {
+      let current: Option<Provenance> =
+        provenance::table.find((song_id, field.to_string())).select(Provenance::as_select()).first(conn).optional()?;
+      if current.as_ref().is_some_and(|current| current.value == value) {
+        return diesel::QueryResult::Ok(());
+      }
+      let record = Provenance {
+        song_id,
+        field,
+        source,
+        value,
+        previous_source: current.as_ref().map(|current| current.source),
+        previous_value: current.and_then(|current| current.value),
+        recorded_at: Utc::now().naive_utc(),
+      };
+      on_backend!(conn, |conn| {
+        diesel::insert_into(provenance::table)
+          .values(&record)
+          .on_conflict((provenance::song_id, provenance::field))
+          .do_update()
+          .set(&record)
+          .execute(conn)
+      })?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Record the source of every field of the song that has a value
+  pub fn record_song_provenance(&mut self, song_id: i32, source: MetadataSource) -> Result<()> {
+    for (field, value) in self.get_song_field_values(song_id)? {
+      if value.is_some() {
+        self.record_provenance(song_id, field, source, value)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Get where the fields of the song came from, in the order of `SongField`
+  pub fn get_song_provenance(&mut self, song_id: i32) -> Result<Vec<Provenance>> {
+    let mut records: Vec<Provenance> = provenance::table
+      .filter(provenance::song_id.eq(song_id))
+      .select(Provenance::as_select())
+      .load(&mut self.connection)?;
+    records.sort_by_key(|record| record.field);
+    Ok(records)
+  }
+
+  /// Set a field of the song to a recorded value, with artists and genres one name per line
+  ///
+  /// # Returns
+  ///
+  /// * whether the field can hold the value, a song can not be without a title
+  fn set_song_field(&mut self, song_id: i32, field: SongField, value: Option<&str>) -> Result<bool> {
+    let names = value.into_iter().flat_map(str::lines).map(str::to_string);
+    match field {
+      SongField::Title => {
+        let Some(title) = value else {
+          return Ok(false);
+        };
+        diesel::update(song::table.find(song_id)).set(song::title.eq(title)).execute(&mut self.connection)?;
+      },
+      SongField::YoutubeId => {
+        diesel::update(song::table.find(song_id)).set(song::youtube_id.eq(value)).execute(&mut self.connection)?;
+        diesel::delete(link_check::table.find(song_id)).execute(&mut self.connection)?;
+      },
+      SongField::ThumbnailUrl => {
+        diesel::update(song::table.find(song_id)).set(song::thumbnail_url.eq(value)).execute(&mut self.connection)?;
+      },
+      SongField::TrackNumber | SongField::DiscNumber => {
+        let Ok(number) = value.map(str::parse::<i32>).transpose() else {
+          return Ok(false);
+        };
+        let update = diesel::update(song::table.find(song_id));
+        match field {
+          SongField::TrackNumber => update.set(song::track_number.eq(number)).execute(&mut self.connection)?,
+          _ => update.set(song::disc_number.eq(number)).execute(&mut self.connection)?,
+        };
+      },
+      SongField::Artists => {
+        diesel::delete(songs_artists::table.filter(songs_artists::song_id.eq(song_id)))
+          .execute(&mut self.connection)?;
+        for name in names {
+          let artist_id = self.insert_artist(NewArtist { name })?;
+          self.insert_song_artist(SongArtist { song_id, artist_id })?;
+        }
+      },
+      SongField::Album => {
+        diesel::delete(songs_albums::table.filter(songs_albums::song_id.eq(song_id))).execute(&mut self.connection)?;
+        for name in names.take(1) {
+          let album_id = self.insert_album(NewAlbum { name })?;
+          self.insert_song_album(SongAlbum { song_id, album_id })?;
+        }
+      },
+      SongField::Genres => {
+        diesel::delete(songs_genres::table.filter(songs_genres::song_id.eq(song_id))).execute(&mut self.connection)?;
+        for name in names {
+          let genre_id = self.insert_genre(NewGenre { name })?;
+          self.insert_song_genre(SongGenre { song_id, genre_id })?;
+        }
+      },
+    }
+    Ok(true)
+  }
+
+  /// Put back the values the source replaced, for when the source turns out to be wrong. Locked
+  /// fields are left alone, as are fields the source filled in first as there is nothing to go
+  /// back to
+  ///
+  /// # Returns
+  ///
+  /// * the records of the fields reverted, as they were before
+  pub fn revert_source(&mut self, source: MetadataSource) -> Result<Vec<Provenance>> {
+    let records: Vec<Provenance> = provenance::table
+      .filter(provenance::source.eq(source))
+      .filter(provenance::previous_source.is_not_null())
+      .select(Provenance::as_select())
+      .load(&mut self.connection)?;
+    let mut reverted = vec![];
+    for record in records {
+      if self.is_song_field_locked(record.song_id, record.field)?
+        || !self.set_song_field(record.song_id, record.field, record.previous_value.as_deref())?
+      {
+        continue;
+      }
+      diesel::update(provenance::table.find((record.song_id, record.field.to_string())))
+        .set((
+          provenance::source.eq(record.previous_source.unwrap_or_default()),
+          provenance::value.eq(&record.previous_value),
+          provenance::previous_source.eq(None::<MetadataSource>),
+          provenance::previous_value.eq(None::<String>),
+          provenance::recorded_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&mut self.connection)?;
+      reverted.push(record);
+    }
+    Ok(reverted)
+  }
+
+  /// Record the codec and bitrate of the file at the path from the music dir
+  pub fn set_file_quality(&mut self, relative_path: &str, quality: &AudioQuality) -> Result<()> {
+    diesel::update(file::table.filter(file::relative_path.eq(relative_path)))
+      .set((file::codec.eq(&quality.codec), file::bitrate.eq(quality.bitrate.map(|bitrate| bitrate as i32))))
+      .execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Move the file at the path from the music dir to another path, forgetting its quality and
+  /// its verification
+  pub fn move_file(&mut self, relative_path: &str, new_path: &str) -> Result<()> {
+    // the connection of both backends binds nulls as integers, which Postgres does not take for a
+    // timestamp
+    on_backend!(&mut self.connection, |conn| {
+      diesel::update(file::table.filter(file::relative_path.eq(relative_path)))
+        .set((
+          file::relative_path.eq(new_path),
+          file::codec.eq(None::<String>),
+          file::bitrate.eq(None::<i32>),
+          file::checksum.eq(None::<String>),
+          file::verified_at.eq(None::<NaiveDateTime>),
+          file::problem.eq(None::<String>),
+        ))
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get every song with a file along with the file, ordered by title
+  pub fn get_songs_with_file_records(&mut self) -> Result<Vec<(Song, File)>> {
+    let songs = song::table
+      .inner_join(file::table)
+      .select((Song::as_select(), File::as_select()))
+      .order(song::title.asc())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Record the verification of the file, with its checksum and what was found wrong with it
+  pub fn set_file_verification(
+    &mut self,
+    file_id: i32,
+    checksum: Option<&str>,
+    problem: Option<&str>,
+    verified_at: NaiveDateTime,
+  ) -> Result<()> {
+    diesel::update(file::table.find(file_id))
+      .set((file::checksum.eq(checksum), file::problem.eq(problem), file::verified_at.eq(verified_at)))
+      .execute(&mut self.connection)?;
+    Ok(())
+  }
+
+  /// Get the codec and bitrate of the file of a song, `None` if it has no file or they are unknown
+  pub fn get_song_quality(&mut self, song_id: i32) -> Result<Option<AudioQuality>> {
+    let quality: Option<(Option<String>, Option<i32>)> = song::table
+      .find(song_id)
+      .inner_join(file::table)
+      .select((file::codec, file::bitrate))
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(quality.and_then(|(codec, bitrate)| {
+      Some(AudioQuality { codec: codec?, bitrate: bitrate.map(|bitrate| bitrate as u32) })
+    }))
+  }
+
+  /// Record how a file was downloaded, in place of how it was before
+  pub fn set_download_params(&mut self, params: &DownloadParams) -> Result<()> {
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(download_params::table)
+        .values(params)
+        .on_conflict(download_params::file_id)
+        .do_update()
+        .set(params)
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get how the file of a song was downloaded, `None` if it has no file or it was not downloaded
+  pub fn get_song_download_params(&mut self, song_id: i32) -> Result<Option<DownloadParams>> {
+    let params = song::table
+      .find(song_id)
+      .inner_join(file::table.inner_join(download_params::table))
+      .select(DownloadParams::as_select())
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(params)
+  }
+
+  /// Get the paths from the music dir of the files whose quality is not recorded yet
+  pub fn get_files_without_quality(&mut self) -> Result<Vec<String>> {
+    let paths = file::table.filter(file::codec.is_null()).select(file::relative_path).load(&mut self.connection)?;
+    Ok(paths)
+  }
+
+  /// Get the path of the file of a song relative to the music dir, `None` if it has no file
+  pub fn get_song_file(&mut self, song_id: i32) -> Result<Option<String>> {
+    let relative_path = song::table
+      .find(song_id)
+      .inner_join(file::table)
+      .select(file::relative_path)
+      .first(&mut self.connection)
+      .optional()?;
+    Ok(relative_path)
+  }
+
+  /// Get the songs that have a file, along with the path of the file relative to the music dir
+  pub fn get_songs_with_files(&mut self) -> Result<Vec<(Song, String)>> {
+    let songs = song::table
+      .inner_join(file::table)
+      .select((Song::as_select(), file::relative_path))
+      .order(song::title.asc())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Get the names of the artists of every song as pairs of song id and artist name
+  pub fn get_song_artist_names(&mut self) -> Result<Vec<(i32, String)>> {
+    let names = songs_artists::table
+      .inner_join(artist::table)
+      .select((songs_artists::song_id, artist::name))
+      .load(&mut self.connection)?;
+    Ok(names)
+  }
+
+  /// Get the artists of every song as pairs of song id and artist
+  pub fn get_song_artists(&mut self) -> Result<Vec<(i32, Artist)>> {
+    let artists = songs_artists::table
+      .inner_join(artist::table)
+      .select((songs_artists::song_id, Artist::as_select()))
+      .load(&mut self.connection)?;
+    Ok(artists)
+  }
+
+  /// Get the albums of every song as pairs of song id and album
+  pub fn get_song_albums(&mut self) -> Result<Vec<(i32, Album)>> {
+    let albums = songs_albums::table
+      .inner_join(album::table)
+      .select((songs_albums::song_id, Album::as_select()))
+      .load(&mut self.connection)?;
+    Ok(albums)
+  }
+
+  /// Get the names of the genres of every song as pairs of song id and genre name
+  pub fn get_song_genre_names(&mut self) -> Result<Vec<(i32, String)>> {
+    let names = songs_genres::table
+      .inner_join(genre::table)
+      .select((songs_genres::song_id, genre::name))
+      .load(&mut self.connection)?;
+    Ok(names)
+  }
+
+  /// Get the names of the artists of the songs given as pairs of song id and artist name
+  pub fn get_artist_names_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, String)>> {
+    let names = songs_artists::table
+      .inner_join(artist::table)
+      .filter(songs_artists::song_id.eq_any(song_ids))
+      .select((songs_artists::song_id, artist::name))
+      .load(&mut self.connection)?;
+    Ok(names)
+  }
+
+  /// Get the names of the albums of the songs given as pairs of song id and album name
+  pub fn get_album_names_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, String)>> {
+    let names = songs_albums::table
+      .inner_join(album::table)
+      .filter(songs_albums::song_id.eq_any(song_ids))
+      .select((songs_albums::song_id, album::name))
+      .load(&mut self.connection)?;
+    Ok(names)
+  }
+
+  /// Get the songs given that have a file, with the path of the file relative to the music dir
+  pub fn get_songs_with_files_of(&mut self, song_ids: &[i32]) -> Result<Vec<(Song, String)>> {
+    let songs = song::table
+      .inner_join(file::table)
+      .filter(song::id.eq_any(song_ids))
+      .select((Song::as_select(), file::relative_path))
+      .order(song::title.asc())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Get the artists of the songs given as pairs of song id and artist
+  pub fn get_artists_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, Artist)>> {
+    let artists = songs_artists::table
+      .inner_join(artist::table)
+      .filter(songs_artists::song_id.eq_any(song_ids))
+      .select((songs_artists::song_id, Artist::as_select()))
+      .load(&mut self.connection)?;
+    Ok(artists)
+  }
+
+  /// Get the albums of the songs given as pairs of song id and album
+  pub fn get_albums_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, Album)>> {
+    let albums = songs_albums::table
+      .inner_join(album::table)
+      .filter(songs_albums::song_id.eq_any(song_ids))
+      .select((songs_albums::song_id, Album::as_select()))
+      .load(&mut self.connection)?;
+    Ok(albums)
+  }
+
+  /// Get the names of the genres of the songs given as pairs of song id and genre name
+  pub fn get_genre_names_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, String)>> {
+    let names = songs_genres::table
+      .inner_join(genre::table)
+      .filter(songs_genres::song_id.eq_any(song_ids))
+      .select((songs_genres::song_id, genre::name))
+      .load(&mut self.connection)?;
+    Ok(names)
+  }
+
+  /// Get the ids of the songs of an album
+  pub fn get_song_ids_of_album(&mut self, album_id: i32) -> Result<Vec<i32>> {
+    let song_ids = songs_albums::table
+      .filter(songs_albums::album_id.eq(album_id))
+      .select(songs_albums::song_id)
+      .load(&mut self.connection)?;
+    Ok(song_ids)
+  }
+
+  /// Get the ids of the songs of an artist, along with the other songs of the albums they are on
+  pub fn get_song_ids_of_artist(&mut self, artist_id: i32) -> Result<Vec<i32>> {
+    let mut song_ids: Vec<i32> = songs_artists::table
+      .filter(songs_artists::artist_id.eq(artist_id))
+      .select(songs_artists::song_id)
+      .load(&mut self.connection)?;
+    let album_ids: Vec<i32> = songs_albums::table
+      .filter(songs_albums::song_id.eq_any(&song_ids))
+      .select(songs_albums::album_id)
+      .load(&mut self.connection)?;
+    song_ids.extend(
+      songs_albums::table
+        .filter(songs_albums::album_id.eq_any(album_ids))
+        .select(songs_albums::song_id)
+        .load::<i32>(&mut self.connection)?,
+    );
+    song_ids.sort_unstable();
+    song_ids.dedup();
+    Ok(song_ids)
+  }
+
+  /// Get the paths relative to the music dir of the files of the songs given, as pairs of song id
+  /// and path. Songs without a file are left out
+  pub fn get_files_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<(i32, String)>> {
+    let paths = song::table
+      .inner_join(file::table)
+      .filter(song::id.eq_any(song_ids))
+      .select((song::id, file::relative_path))
+      .load(&mut self.connection)?;
+    Ok(paths)
+  }
+
+  /// Get the ids of the songs given whose video was found gone
+  pub fn get_dead_links_of_songs(&mut self, song_ids: &[i32]) -> Result<Vec<i32>> {
+    let dead = link_check::table
+      .filter(link_check::song_id.eq_any(song_ids))
+      .filter(link_check::dead_reason.is_not_null())
+      .select(link_check::song_id)
+      .load(&mut self.connection)?;
+    Ok(dead)
+  }
+
+  /// Record a play of a song, adding to its play count
+  pub fn record_play(&mut self, song_id: i32, played_at: NaiveDateTime) -> Result<()> {
+    self.connection.transaction(|conn| {
+      on_backend!(conn, |conn| diesel::insert_into(play::table).values(NewPlay { song_id, played_at }).execute(conn))?;
+      diesel::update(song::table.find(song_id)).set(song::play_count.eq(song::play_count + 1)).execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Record a play of a song unless one at the same time is recorded already, so that a history
+  /// imported again is not counted twice
+  ///
+  /// # Returns
+  ///
+  /// * whether the play was recorded
+  pub fn record_play_once(&mut self, song_id: i32, played_at: NaiveDateTime) -> Result<bool> {
+    let recorded: i64 = play::table
+      .filter(play::song_id.eq(song_id))
+      .filter(play::played_at.eq(played_at))
+      .count()
+      .get_result(&mut self.connection)?;
+    if recorded > 0 {
+      return Ok(false);
+    }
+    self.record_play(song_id, played_at)?;
+    Ok(true)
+  }
+
+  /// Record the outcome of a finished download
+  ///
+  /// # Arguments
+  ///
+  /// * `youtube_id` - the id of the downloaded video
+  /// * `title` - the title of the downloaded song
+  /// * `outcome` - the format obtained, or why the download failed
+  pub fn record_download(&mut self, youtube_id: &str, title: &str, outcome: Result<String, String>) -> Result<()> {
+    let (format, error) = match outcome {
+      Ok(format) => (Some(format), None),
+      Err(error) => (None, Some(error)),
+    };
+    on_backend!(&mut self.connection, |conn| {
+      diesel::insert_into(download::table)
+        .values(NewDownload {
+          youtube_id: youtube_id.to_string(),
+          title: title.to_string(),
+          error,
+          finished_at: Utc::now().naive_utc(),
+          format,
+        })
+        .execute(conn)
+    })?;
+    Ok(())
+  }
+
+  /// Get the songs added to the library from `from` until before `to`, oldest first
+  pub fn get_songs_added_between(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> Result<Vec<Song>> {
+    let songs = song::table
+      .filter(song::added_at.ge(from).and(song::added_at.lt(to)))
+      .order(song::added_at.asc())
+      .select(Song::as_select())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Get the most played songs from `from` until before `to`
+  ///
+  /// # Returns
+  ///
+  /// * at most `limit` songs with their number of plays, most played first
+  pub fn get_most_played_between(
+    &mut self,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    limit: i64,
+  ) -> Result<Vec<(Song, i64)>> {
+    let most_played = play::table
+      .inner_join(song::table)
+      .filter(play::played_at.ge(from).and(play::played_at.lt(to)))
+      .group_by(song::id)
+      .select((Song::as_select(), diesel::dsl::count(play::id)))
+      .order(diesel::dsl::count(play::id).desc())
+      .limit(limit)
+      .load(&mut self.connection)?;
+    Ok(most_played)
+  }
+
+  /// Get the songs added last, newest first
+  pub fn get_recently_added(&mut self, limit: i64) -> Result<Vec<Song>> {
+    let songs = song::table
+      .filter(song::added_at.is_not_null())
+      .order((song::added_at.desc(), song::id.desc()))
+      .limit(limit)
+      .select(Song::as_select())
+      .load(&mut self.connection)?;
+    Ok(songs)
+  }
+
+  /// Get the songs played last with when they were last played, most recent first
+  pub fn get_recently_played(&mut self, limit: i64) -> Result<Vec<(Song, NaiveDateTime)>> {
+    let last_played = diesel::dsl::max(play::played_at);
+    let songs: Vec<(Song, Option<NaiveDateTime>)> = play::table
+      .inner_join(song::table)
+      .group_by(song::id)
+      .select((Song::as_select(), last_played))
+      .order(last_played.desc())
+      .limit(limit)
+      .load(&mut self.connection)?;
+    Ok(songs.into_iter().filter_map(|(song, played_at)| Some((song, played_at?))).collect())
+  }
+
+  /// Count the songs, artists, albums and plays in the library
+  pub fn get_library_stats(&mut self) -> Result<LibraryStats> {
+    Ok(LibraryStats {
+      songs: song::table.count().get_result(&mut self.connection)?,
+      artists: artist::table.count().get_result(&mut self.connection)?,
+      albums: album::table.count().get_result(&mut self.connection)?,
+      plays: play::table.count().get_result(&mut self.connection)?,
+    })
+  }
+
+  /// Get the downloads that finished last, successfully or not, most recent first
+  pub fn get_recent_downloads(&mut self, limit: i64) -> Result<Vec<Download>> {
+    let downloads = download::table
+      .order((download::finished_at.desc(), download::id.desc()))
+      .limit(limit)
+      .select(Download::as_select())
+      .load(&mut self.connection)?;
+    Ok(downloads)
+  }
+
+  /// Get the downloads that failed from `from` until before `to`, oldest first
+  pub fn get_failed_downloads_between(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> Result<Vec<Download>> {
+    let downloads = download::table
+      .filter(download::error.is_not_null())
+      .filter(download::finished_at.ge(from).and(download::finished_at.lt(to)))
+      .order(download::finished_at.asc())
+      .select(Download::as_select())
+      .load(&mut self.connection)?;
+    Ok(downloads)
+  }
+
+  /// Find pairs of songs that are likely duplicates of each other, that is songs sharing the same
+  /// YouTube id or the same title ignoring case
+  ///
+  /// # Returns
+  ///
+  /// * pairs of duplicate songs, the song with the lower id first
+  pub fn find_duplicate_songs(&mut self) -> Result<Vec<(Song, Song)>> {
+    let songs = self.get_all_songs()?;
+    let mut duplicates = vec![];
+    for (index, left) in songs.iter().enumerate() {
+      for right in songs.iter().skip(index + 1) {
+        let same_youtube_id = left.youtube_id.is_some() && left.youtube_id == right.youtube_id;
+        if same_youtube_id || left.title.to_lowercase() == right.title.to_lowercase() {
+          duplicates.push((left.clone(), right.clone()));
+        }
+      }
+    }
+    Ok(duplicates)
+  }
+
+  /// Find pairs of artists whose names only differ by case
+  ///
+  /// # Returns
+  ///
+  /// * pairs of duplicate artists, the artist with the lower id first
+  pub fn find_duplicate_artists(&mut self) -> Result<Vec<(Artist, Artist)>> {
+    let artists = self.get_all_artists()?;
+    let mut duplicates = vec![];
+    for (index, left) in artists.iter().enumerate() {
+      for right in artists.iter().skip(index + 1) {
+        if left.name.to_lowercase() == right.name.to_lowercase() {
+          duplicates.push((left.clone(), right.clone()));
+        }
+      }
+    }
+    Ok(duplicates)
+  }
+
+  /// Merge two songs in a single transaction. The song `remove_id` is deleted, its artists,
+  /// albums, genres and plays are moved over to `keep_id`, and `keep_id` is updated with `merged`
+  ///
+  /// # Arguments
+  ///
+  /// * `keep_id` - the id of the song to keep
+  /// * `remove_id` - the id of the song to merge into `keep_id`
+  /// * `merged` - the merged metadata of the song
+  pub fn merge_songs(&mut self, keep_id: i32, remove_id: i32, merged: NewSong) -> Result<()> {
+    self.connection.transaction(|conn| {
+      let artist_ids: Vec<i32> = songs_artists::table
+        .filter(songs_artists::song_id.eq(remove_id))
+        .select(songs_artists::artist_id)
+        .load(conn)?;
+      let album_ids: Vec<i32> =
+        songs_albums::table.filter(songs_albums::song_id.eq(remove_id)).select(songs_albums::album_id).load(conn)?;
+      let genre_ids: Vec<i32> =
+        songs_genres::table.filter(songs_genres::song_id.eq(remove_id)).select(songs_genres::genre_id).load(conn)?;
+
+      for artist_id in artist_ids {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_artists::table)
+            .values(SongArtist { song_id: keep_id, artist_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      for album_id in album_ids {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_albums::table)
+            .values(SongAlbum { song_id: keep_id, album_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      for genre_id in genre_ids {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_genres::table)
+            .values(SongGenre { song_id: keep_id, genre_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+
+      diesel::delete(songs_artists::table.filter(songs_artists::song_id.eq(remove_id))).execute(conn)?;
+      diesel::delete(songs_albums::table.filter(songs_albums::song_id.eq(remove_id))).execute(conn)?;
+      diesel::delete(songs_genres::table.filter(songs_genres::song_id.eq(remove_id))).execute(conn)?;
+      diesel::update(play::table.filter(play::song_id.eq(remove_id))).set(play::song_id.eq(keep_id)).execute(conn)?;
+      diesel::update(attachment::table.filter(attachment::song_id.eq(remove_id)))
+        .set(attachment::song_id.eq(keep_id))
+        .execute(conn)?;
+      diesel::update(playlist_songs::table.filter(playlist_songs::song_id.eq(remove_id)))
+        .set(playlist_songs::song_id.eq(keep_id))
+        .execute(conn)?;
+      // a song can not be a version of itself, and is a version of one song at most
+      diesel::delete(
+        song_version::table.filter(
+          (song_version::song_id.eq(remove_id).and(song_version::original_id.eq(keep_id)))
+            .or(song_version::song_id.eq(keep_id).and(song_version::original_id.eq(remove_id))),
+        ),
+      )
+      .execute(conn)?;
+      let keep_is_version = song_version::table.find(keep_id).count().get_result::<i64>(conn)? > 0;
+      if keep_is_version {
+        diesel::delete(song_version::table.find(remove_id)).execute(conn)?;
+      } else {
+        diesel::update(song_version::table.find(remove_id)).set(song_version::song_id.eq(keep_id)).execute(conn)?;
+      }
+      diesel::update(song_version::table.filter(song_version::original_id.eq(remove_id)))
+        .set(song_version::original_id.eq(keep_id))
+        .execute(conn)?;
+      let keep_has_extra = song_extra::table.find(keep_id).count().get_result::<i64>(conn)? > 0;
+      if keep_has_extra {
+        diesel::delete(song_extra::table.find(remove_id)).execute(conn)?;
+      } else {
+        diesel::update(song_extra::table.find(remove_id)).set(song_extra::song_id.eq(keep_id)).execute(conn)?;
+      }
+      // a field takes the source of the song its merged value came from
+      let keep_song: Song = song::table.find(keep_id).select(Song::as_select()).first(conn)?;
+      let remove_song: Song = song::table.find(remove_id).select(Song::as_select()).first(conn)?;
+      let kept: Vec<SongField> =
+        provenance::table.filter(provenance::song_id.eq(keep_id)).select(provenance::field).load(conn)?;
+      let records: Vec<Provenance> =
+        provenance::table.filter(provenance::song_id.eq(remove_id)).select(Provenance::as_select()).load(conn)?;
+      diesel::delete(provenance::table.filter(provenance::song_id.eq(remove_id))).execute(conn)?;
+      for record in records {
+        if kept.contains(&record.field) && !picked_from_removed(&keep_song, &remove_song, &merged, record.field) {
+          continue;
+        }
+        let record = Provenance { song_id: keep_id, ..record };
+        on_backend!(conn, |conn| {
+          diesel::insert_into(provenance::table)
+            .values(&record)
+            .on_conflict((provenance::song_id, provenance::field))
+            .do_update()
+            .set(&record)
+            .execute(conn)
+        })?;
+      }
+      // a field locked on either song stays locked
+      let locks: Vec<SongField> =
+        song_lock::table.filter(song_lock::song_id.eq(remove_id)).select(song_lock::field).load(conn)?;
+      for field in locks {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(song_lock::table)
+            .values(SongLock { song_id: keep_id, field })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      diesel::delete(song_lock::table.filter(song_lock::song_id.eq(remove_id))).execute(conn)?;
+      // the merged song may have the video of either, so it is checked again
+      diesel::delete(link_check::table.filter(link_check::song_id.eq_any([keep_id, remove_id]))).execute(conn)?;
+      // the removed song goes first as file_id is unique
+      diesel::delete(song::table.find(remove_id)).execute(conn)?;
+
+      diesel::update(song::table.find(keep_id))
+        .set((
+          song::title.eq(merged.title),
+          song::youtube_id.eq(merged.youtube_id),
+          song::thumbnail_url.eq(merged.thumbnail_url),
+          song::file_id.eq(merged.file_id),
+          song::track_number.eq(merged.track_number),
+          song::disc_number.eq(merged.disc_number),
+        ))
+        .execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Merge two artists in a single transaction. The songs of `remove_id` are credited to
+  /// `keep_id`, `remove_id` is deleted and `keep_id` is renamed to `name`
+  ///
+  /// # Arguments
+  ///
+  /// * `keep_id` - the id of the artist to keep
+  /// * `remove_id` - the id of the artist to merge into `keep_id`
+  /// * `name` - the merged name of the artist
+  pub fn merge_artists(&mut self, keep_id: i32, remove_id: i32, name: String) -> Result<()> {
+    self.connection.transaction(|conn| {
+      let song_ids: Vec<i32> = songs_artists::table
+        .filter(songs_artists::artist_id.eq(remove_id))
+        .select(songs_artists::song_id)
+        .load(conn)?;
+      for song_id in song_ids {
+        on_backend!(conn, |conn| {
+          diesel::insert_into(songs_artists::table)
+            .values(SongArtist { song_id, artist_id: keep_id })
+            .on_conflict_do_nothing()
+            .execute(conn)
+        })?;
+      }
+      diesel::delete(songs_artists::table.filter(songs_artists::artist_id.eq(remove_id))).execute(conn)?;
+      // the removed artist goes first as the name is unique
+      diesel::delete(artist::table.find(remove_id)).execute(conn)?;
+      diesel::update(artist::table.find(keep_id)).set(artist::name.eq(name)).execute(conn)?;
+      diesel::QueryResult::Ok(())
+    })?;
+    Ok(())
+  }
+
+  /// Apply the merge described by a `MergeCandidate`
+  pub fn apply_merge(&mut self, candidate: &MergeCandidate) -> Result<()> {
+    match candidate.kind {
+      MergeKind::Song => self.merge_songs(candidate.left_id, candidate.right_id, candidate.merged_song()),
+      MergeKind::Artist => {
+        self.merge_artists(
+          candidate.left_id,
+          candidate.right_id,
+          candidate.picked("name").ok_or_else(|| eyre!("merged artist must have a name"))?,
+        )
+      },
+    }
+  }
+}
+
+/// A call run on the thread of a [DatabaseHandle]
+type DatabaseCall = Box<dyn FnOnce(&mut Database) + Send>;
+
+/// The database on a thread of its own, so that slow queries do not hold up the TUI. Calls are run
+/// one at a time in the order they are made, and the thread stops once every handle is dropped
+#[derive(Clone)]
+pub struct DatabaseHandle {
+  calls: std::sync::mpsc::Sender<DatabaseCall>,
+}
+
+impl DatabaseHandle {
+  /// Open the database, see [`Database::new`], on a thread of its own
+  pub async fn new(config: Config) -> Result<Self> {
+    Self::spawn(Database::new(config).await?)
+  }
+
+  /// Move the database onto a thread of its own
+  pub fn spawn(mut database: Database) -> Result<Self> {
+    let (calls, received) = std::sync::mpsc::channel::<DatabaseCall>();
+    std::thread::Builder::new().name("database".to_string()).spawn(move || {
+      for call in received {
+        call(&mut database);
+      }
+    })?;
+    Ok(Self { calls })
+  }
+
+  /// Run the function with the database on its thread
+  pub async fn call<T, F>(&self, f: F) -> Result<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(&mut Database) -> Result<T> + Send + 'static,
+  {
+    let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+    let call: DatabaseCall = Box::new(move |database| {
+      let _ = result_tx.send(f(database));
+    });
+    self.calls.send(call).map_err(|_| eyre!("the database thread has stopped"))?;
+    result_rx.await.map_err(|_| eyre!("the database thread has stopped"))?
+  }
+}
+
+/// Whether the merged value of a field of the song came from the removed song rather than the
+/// kept one. Artists, album and genres are combined, so they keep the source of the kept song
+fn picked_from_removed(keep: &Song, remove: &Song, merged: &NewSong, field: SongField) -> bool {
+  let value = |title: &String,
+               youtube_id: &Option<String>,
+               thumbnail_url: &Option<String>,
+               track: Option<i32>,
+               disc: Option<i32>| {
+    match field {
+      SongField::Title => Some(title.clone()),
+      SongField::YoutubeId => youtube_id.clone(),
+      SongField::ThumbnailUrl => thumbnail_url.clone(),
+      SongField::TrackNumber => track.map(|number| number.to_string()),
+      SongField::DiscNumber => disc.map(|number| number.to_string()),
+      SongField::Artists | SongField::Album | SongField::Genres => None,
+    }
+  };
+  let of_song =
+    |song: &Song| value(&song.title, &song.youtube_id, &song.thumbnail_url, song.track_number, song.disc_number);
+  let merged = value(&merged.title, &merged.youtube_id, &merged.thumbnail_url, merged.track_number, merged.disc_number);
+  merged.is_some() && merged != of_song(keep) && merged == of_song(remove)
+}
+
+/// Whether the url is of a Postgres database rather than a SQLite file
+fn is_postgres_url(url: &str) -> bool {
+  url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Delete the artists, albums and genres without songs, along with the pins of the artists and
+/// albums. Albums with attachments are kept
+fn delete_orphans(conn: &mut DbConnection) -> diesel::QueryResult<usize> {
+  let artists: Vec<String> = artist::table
+    .filter(diesel::dsl::not(artist::id.eq_any(songs_artists::table.select(songs_artists::artist_id))))
+    .select(artist::name)
+    .load(conn)?;
+  let albums: Vec<String> = album::table
+    .filter(diesel::dsl::not(album::id.eq_any(songs_albums::table.select(songs_albums::album_id))))
+    .filter(diesel::dsl::not(album::id.eq_any(
+      attachment::table.filter(attachment::album_id.is_not_null()).select(attachment::album_id.assume_not_null()),
+    )))
+    .select(album::name)
+    .load(conn)?;
+  diesel::delete(pinned::table.filter(pinned::kind.eq(PinKind::Artist)).filter(pinned::name.eq_any(&artists)))
+    .execute(conn)?;
+  diesel::delete(pinned::table.filter(pinned::kind.eq(PinKind::Album)).filter(pinned::name.eq_any(&albums)))
+    .execute(conn)?;
+  let mut deleted = diesel::delete(artist::table.filter(artist::name.eq_any(&artists))).execute(conn)?;
+  deleted += diesel::delete(album::table.filter(album::name.eq_any(&albums))).execute(conn)?;
+  deleted += diesel::delete(
+    genre::table.filter(diesel::dsl::not(genre::id.eq_any(songs_genres::table.select(songs_genres::genre_id)))),
+  )
+  .execute(conn)?;
+  Ok(deleted)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use chrono::{NaiveDate, SubsecRound};
+  use color_eyre::eyre::{Context, Result};
+  use diesel::prelude::*;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    config::Config,
+    models::{
+      Album, AttachmentKind, NewAlbum, NewArtist, NewGenre, NewSong, Song, SongAlbum, SongArtist, SongVersion,
+      VersionKind,
+    },
+  };
+
+  /// Spawns an instance of `Database` with a new instance of in memory sqlite database for tests,
+  /// or with a new schema of the Postgres database at `MUZIK_TEST_POSTGRES_URL` when it is set
+  pub(crate) fn setup_database() -> Result<Database> {
+    #[cfg(feature = "postgres")]
+    if let Some(url) = postgres_test_url()? {
+      return Database::open_migrated(&url, Config::default());
+    }
+    Database::open_migrated(":memory:", Config::default())
+  }
+
+  /// The url of a new schema of the Postgres database at `MUZIK_TEST_POSTGRES_URL`, if it is set
+  #[cfg(feature = "postgres")]
+  fn postgres_test_url() -> Result<Option<String>> {
+    let Ok(url) = std::env::var("MUZIK_TEST_POSTGRES_URL") else {
+      return Ok(None);
+    };
+    let schema = format!("muzik_test_{}", uuid::Uuid::new_v4().simple());
+    diesel::PgConnection::establish(&url)?.batch_execute(&format!("CREATE SCHEMA {schema}"))?;
+    let separator = if url.contains('?') { '&' } else { '?' };
+    Ok(Some(format!("{url}{separator}options=-csearch_path%3D{schema}")))
+  }
+
+  #[test]
+  fn test_database_get_all_songs() -> Result<()> {
+    let mut database = setup_database()?;
+    let insert1 = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let insert2 = database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
+    let insert3 = database.insert_song(NewSong { title: "Loli God Requiem".to_string(), ..Default::default() })?;
+
+    let mut songs = database.get_all_songs()?;
+    // the time a song is added is only known once inserted
+    for song in songs.iter_mut() {
+      assert!(song.added_at.take().is_some());
+    }
+    let songs_check = vec![
+      Song { id: 1, title: "Stellar Stellar".to_string(), ..Default::default() },
+      Song { id: 2, title: "Crossing Field".to_string(), ..Default::default() },
+      Song { id: 3, title: "Loli God Requiem".to_string(), ..Default::default() },
+    ];
+
+    assert_eq!(songs, songs_check);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_get_all_artists_for_song() -> Result<()> {
+    let mut database = setup_database()?;
+
+    let new_song = NewSong { title: "Stellar Stellar".to_string(), ..Default::default() };
+    let song_id = database.insert_song(new_song)?;
+    let artist1_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let artist2_id = database.insert_artist(NewArtist { name: "Comet-chan".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id: artist1_id })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id: artist2_id })?;
+
+    let song = database.get_song_from_id(song_id)?;
+    let artists = database.get_all_artists_for_song(song)?;
+    assert_eq!(artists, vec![Artist { id: 1, name: "Hoshimachi Suisei".to_string() }, Artist {
+      name: "Comet-chan".to_string(),
+      id: 2
+    }]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_insert_full_song() -> Result<()> {
+    let mut database = setup_database()?;
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let song_id = database.insert_full_song(
+      NewSong { title: "Stellar Stellar".to_string(), ..Default::default() },
+      &names(&["Hoshimachi Suisei"]),
+      Some("Still Still Stellar"),
+      &names(&["J-Pop", "Vocaloid"]),
+    )?;
+    let ghost = database.insert_full_song(
+      NewSong { title: "Ghost".to_string(), ..Default::default() },
+      &names(&["Hoshimachi Suisei"]),
+      None,
+      &names(&["J-Pop"]),
+    )?;
+
+    let album = database.get_album_for_song(song_id)?.ok_or_else(|| eyre!("no album"))?;
+    assert_eq!(album.name, "Still Still Stellar");
+    assert_eq!(database.get_album_for_song(ghost)?, None);
+    let genres = |database: &mut Database, song_id| -> Result<Vec<String>> {
+      Ok(database.get_genres_for_song(song_id)?.into_iter().map(|genre| genre.name).collect())
+    };
+    assert_eq!(genres(&mut database, song_id)?, vec!["J-Pop", "Vocaloid"]);
+    assert_eq!(genres(&mut database, ghost)?, vec!["J-Pop"]);
+    assert_eq!(database.get_all_artists().map(|artists| artists.len())?, 1);
+    let song = database.get_song_from_id(ghost)?;
+    assert_eq!(database.get_all_artists_for_song(song)?.len(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_artist_insert_conflict() -> Result<()> {
+    let mut database = setup_database()?;
+    let insert1 = database.insert_artist(NewArtist { name: "Suisei".to_string() })?;
+    let insert2 = database.insert_artist(NewArtist { name: "Suisei".to_string() })?;
+    let insert3 = database.insert_artist(NewArtist { name: "LiSA".to_string() })?;
+    assert_eq!(insert1, insert2);
+    assert_eq!(insert3, 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_album_insert_conflict() -> Result<()> {
+    let mut database = setup_database()?;
+    let insert1 = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let insert2 = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let insert3 = database.insert_album(NewAlbum { name: "Sword Art Online OSTs".to_string() })?;
+    assert_eq!(insert1, insert2);
+    assert_eq!(insert3, 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_genre_insert_conflict() -> Result<()> {
+    let mut database = setup_database()?;
+    let insert1 = database.insert_genre(NewGenre { name: "Japanese Pop".to_string() })?;
+    let insert2 = database.insert_genre(NewGenre { name: "Japanese Pop".to_string() })?;
+    let insert3 = database.insert_genre(NewGenre { name: "Japanese Rock".to_string() })?;
+    assert_eq!(insert1, insert2);
+    assert_eq!(insert3, 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_find_song_by_title_and_artist() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
+    database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
+
+    let found = database.find_song_by_title_and_artist("stellar stellar", "hoshimachi suisei")?;
+    assert_eq!(found.map(|song| song.id), Some(song_id));
+    // falls back to the title when it is unique
+    let found = database.find_song_by_title_and_artist("Stellar Stellar", "Suisei")?;
+    assert_eq!(found.map(|song| song.id), Some(song_id));
+    // ambiguous titles are not matched
+    assert_eq!(database.find_song_by_title_and_artist("Crossing Field", "LiSA")?, None);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_album_release() -> Result<()> {
+    let mut database = setup_database()?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_album(SongAlbum { song_id, album_id })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+
+    database.update_album_release(album_id, Some(2021), Some("VIVIX".to_string()), None)?;
+    database.set_album_release_type(album_id, Some(ReleaseType::Album))?;
+    assert_eq!(database.get_all_albums()?, vec![Album {
+      id: album_id,
+      name: "Still Still Stellar".to_string(),
+      year: Some(2021),
+      label: Some("VIVIX".to_string()),
+      catalog_number: None,
+      release_type: Some(ReleaseType::Album),
+    }]);
+    assert_eq!(database.get_album_by_name("still still stellar")?.map(|album| album.id), Some(album_id));
+    assert_eq!(database.get_album_by_name("Specter")?, None);
+    assert_eq!(database.get_all_artists_for_album(album_id)?, vec![Artist {
+      id: artist_id,
+      name: "Hoshimachi Suisei".to_string()
+    }]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_pins() -> Result<()> {
+    let mut database = setup_database()?;
+    let pin = |kind, name: &str| NewPin { kind, name: name.to_string() };
+    database.add_pin(pin(PinKind::Artist, "Hoshimachi Suisei"))?;
+    database.add_pin(pin(PinKind::Playlist, "Genre - JPop"))?;
+    database.add_pin(pin(PinKind::Artist, "Hoshimachi Suisei"))?;
+    database.add_pin(pin(PinKind::Album, "Hoshimachi Suisei"))?;
+    let pins = |database: &mut Database| -> Result<Vec<(PinKind, String)>> {
+      Ok(database.get_pins()?.into_iter().map(|pin| (pin.kind, pin.name)).collect())
+    };
+    assert_eq!(pins(&mut database)?, vec![
+      (PinKind::Artist, "Hoshimachi Suisei".to_string()),
+      (PinKind::Playlist, "Genre - JPop".to_string()),
+      (PinKind::Album, "Hoshimachi Suisei".to_string()),
+    ]);
+
+    database.remove_pin(PinKind::Artist, "Hoshimachi Suisei")?;
+    assert_eq!(pins(&mut database)?.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_playlists() -> Result<()> {
+    let mut database = setup_database()?;
+    let mut song_ids = vec![];
+    for title in ["Stellar Stellar", "Ghost", "Bluerose"] {
+      song_ids.push(database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?);
+    }
+    let playlist_id = database.create_playlist(" Suisei ")?;
+    assert!(database.create_playlist("Suisei").is_err());
+    assert!(database.create_playlist("  ").is_err());
+    let other_id = database.create_playlist("Covers")?;
+    let names = |database: &mut Database| -> Result<Vec<String>> {
+      Ok(database.get_playlists()?.into_iter().map(|playlist| playlist.name).collect())
+    };
+    assert_eq!(names(&mut database)?, vec!["Covers", "Suisei"]);
+
+    let titles = |database: &mut Database, playlist_id| -> Result<Vec<String>> {
+      Ok(database.get_playlist_songs(playlist_id)?.into_iter().map(|song| song.title).collect())
+    };
+    database.add_playlist_songs(playlist_id, &song_ids[..2])?;
+    database.add_playlist_songs(playlist_id, &[song_ids[2], song_ids[0]])?;
+    database.add_playlist_songs(other_id, &[song_ids[1]])?;
+    assert_eq!(titles(&mut database, playlist_id)?, vec!["Stellar Stellar", "Ghost", "Bluerose", "Stellar Stellar"]);
+    database.move_playlist_song(playlist_id, 2, 0)?;
+    assert_eq!(titles(&mut database, playlist_id)?, vec!["Bluerose", "Stellar Stellar", "Ghost", "Stellar Stellar"]);
+    database.remove_playlist_song(playlist_id, 1)?;
+    assert_eq!(titles(&mut database, playlist_id)?, vec!["Bluerose", "Ghost", "Stellar Stellar"]);
+    assert!(database.remove_playlist_song(playlist_id, 3).is_err());
+    assert!(database.move_playlist_song(playlist_id, 0, 3).is_err());
+    // songs added after a removal still go last
+    database.add_playlist_songs(playlist_id, &[song_ids[1]])?;
+    assert_eq!(titles(&mut database, playlist_id)?, vec!["Bluerose", "Ghost", "Stellar Stellar", "Ghost"]);
+    database.set_playlist_songs(other_id, &[song_ids[2], song_ids[1]])?;
+    assert_eq!(titles(&mut database, other_id)?, vec!["Bluerose", "Ghost"]);
+    database.set_playlist_songs(other_id, &[song_ids[1]])?;
+
+    // a deleted song leaves the playlists
+    database.delete_song(song_ids[1])?;
+    assert_eq!(titles(&mut database, playlist_id)?, vec!["Bluerose", "Stellar Stellar"]);
+    assert_eq!(titles(&mut database, other_id)?, Vec::<String>::new());
+
+    assert!(database.rename_playlist(other_id, "Suisei").is_err());
+    database.rename_playlist(playlist_id, "Hoshimachi Suisei")?;
+    assert_eq!(database.get_playlist_by_name("Hoshimachi Suisei")?.map(|playlist| playlist.id), Some(playlist_id));
+
+    let profiles = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    database.set_playlist_profiles(playlist_id, &profiles(&["mpd", "car", "mpd"]))?;
+    database.set_playlist_profiles(other_id, &profiles(&["car"]))?;
+    database.set_playlist_profiles(other_id, &profiles(&["mpd"]))?;
+    let stored: Vec<_> =
+      database.get_playlist_profiles()?.into_iter().map(|profile| (profile.playlist_id, profile.profile)).collect();
+    assert_eq!(stored, vec![
+      (playlist_id, "car".to_string()),
+      (playlist_id, "mpd".to_string()),
+      (other_id, "mpd".to_string())
+    ]);
+    database.delete_playlist(playlist_id)?;
+    assert_eq!(database.get_playlist_profiles()?.len(), 1);
+    assert!(database.delete_playlist(playlist_id).is_err());
+    assert_eq!(names(&mut database)?, vec!["Covers"]);
+    assert_eq!(database.get_all_songs()?.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_snapshots() -> Result<()> {
+    let mut database = setup_database()?;
+    let song = |song_id, title: &str| {
+      SnapshotSong { song_id, title: title.to_string(), metadata_hash: title.to_string(), ..Default::default() }
+    };
+    let first = database.insert_snapshot(" before import ", vec![song(2, "Ghost"), song(1, "Stellar Stellar")])?;
+    assert_eq!(first.name, "before import");
+    let songs = database.get_snapshot_songs(first.id)?;
+    assert_eq!(songs, vec![SnapshotSong { snapshot_id: first.id, ..song(1, "Stellar Stellar") }, SnapshotSong {
+      snapshot_id: first.id,
+      ..song(2, "Ghost")
+    }]);
+    let second =
+      database.insert_snapshot("after import", (1..=BATCH_SIZE as i32 + 1).map(|id| song(id, "")).collect())?;
+    assert_eq!(database.get_snapshot_songs(second.id)?.len(), BATCH_SIZE + 1);
+    let ids: Vec<_> = database.get_snapshots()?.into_iter().map(|snapshot| snapshot.id).collect();
+    assert_eq!(ids, vec![second.id, first.id]);
+
+    database.delete_snapshot(first.id)?;
+    assert!(database.delete_snapshot(first.id).is_err());
+    assert_eq!(database.get_snapshot_songs(first.id)?, vec![]);
+    assert_eq!(database.get_snapshots()?, vec![second]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_attachments() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let attach = |song_id, album_id, kind, relative_path: &str| {
+      NewAttachment { song_id, album_id, kind, relative_path: relative_path.to_string() }
+    };
+    let lyrics = database.insert_attachment(attach(
+      Some(song_id),
+      None,
+      AttachmentKind::Lyrics,
+      "Attachments/Stellar Stellar/lyrics.lrc",
+    ))?;
+    database.insert_attachment(attach(
+      None,
+      Some(album_id),
+      AttachmentKind::Booklet,
+      "Attachments/Still Still Stellar/booklet.pdf",
+    ))?;
+    // an attachment belongs to either a song or an album
+    assert!(database.insert_attachment(attach(None, None, AttachmentKind::Other, "Attachments/notes.txt")).is_err());
+    assert!(database
+      .insert_attachment(attach(Some(song_id), None, AttachmentKind::Lyrics, &lyrics.relative_path))
+      .is_err());
+
+    assert_eq!(database.get_song_attachments(song_id)?, vec![lyrics.clone()]);
+    assert_eq!(database.get_album_attachments(album_id)?[0].kind, AttachmentKind::Booklet);
+    assert_eq!(database.get_attachment(lyrics.id)?, Some(lyrics.clone()));
+    database.remove_attachment(lyrics.id)?;
+    assert_eq!(database.get_all_attachments()?.len(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_file_quality() -> Result<()> {
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "a51VH9BYzZA.opus".to_string() })?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      file_id: Some(file_id),
+      ..Default::default()
+    })?;
+    assert_eq!(database.get_song_quality(song_id)?, None);
+    assert_eq!(database.get_files_without_quality()?, vec!["a51VH9BYzZA.opus".to_string()]);
+
+    let quality = AudioQuality { codec: "opus".to_string(), bitrate: Some(160) };
+    database.set_file_quality("a51VH9BYzZA.opus", &quality)?;
+    assert_eq!(database.get_song_quality(song_id)?, Some(quality));
+    assert!(database.get_files_without_quality()?.is_empty());
+
+    // Postgres keeps timestamps to the microsecond
+    let verified_at = Utc::now().naive_utc().trunc_subsecs(6);
+    database.set_file_verification(file_id, Some("e3b0c442"), Some("truncated"), verified_at)?;
+    let (_, file) = database.get_songs_with_file_records()?.remove(0);
+    assert_eq!(
+      (file.checksum.as_deref(), file.problem.as_deref(), file.verified_at),
+      (Some("e3b0c442"), Some("truncated"), Some(verified_at))
+    );
+    database.move_file("a51VH9BYzZA.opus", "a51VH9BYzZA.m4a")?;
+    let (_, file) = database.get_songs_with_file_records()?.remove(0);
+    assert_eq!((file.checksum, file.problem, file.verified_at), (None, None, None));
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_download_params() -> Result<()> {
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "a51VH9BYzZA.mp3".to_string() })?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      file_id: Some(file_id),
+      ..Default::default()
+    })?;
+    assert_eq!(database.get_song_download_params(song_id)?, None);
+
+    let mut params = DownloadParams {
+      file_id,
+      youtube_id: "a51VH9BYzZA".to_string(),
+      format: "mp3".to_string(),
+      formats: "opus\nmp3".to_string(),
+      comments: false,
+      proxy: false,
+      trim_silence: true,
+      yt_dlp_version: None,
+      downloaded_at: Utc::now().naive_utc().trunc_subsecs(6),
+    };
+    database.set_download_params(&params)?;
+    assert_eq!(database.get_song_download_params(song_id)?, Some(params.clone()));
+
+    // a new download of the file takes the place of the old one
+    params.format = "opus".to_string();
+    params.yt_dlp_version = Some("2024.01.01".to_string());
+    database.set_download_params(&params)?;
+    assert_eq!(database.get_song_download_params(song_id)?, Some(params));
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_versions() -> Result<()> {
+    let mut database = setup_database()?;
+    let original = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let instrumental =
+      database.insert_song(NewSong { title: "Stellar Stellar (Instrumental)".to_string(), ..Default::default() })?;
+    let duplicate =
+      database.insert_song(NewSong { title: "Stellar Stellar (Off Vocal)".to_string(), ..Default::default() })?;
+    for song_id in [instrumental, duplicate] {
+      database.link_version(SongVersion { song_id, original_id: original, kind: VersionKind::Instrumental })?;
+    }
+    assert!(database
+      .link_version(SongVersion { song_id: original, original_id: original, kind: VersionKind::Instrumental })
+      .is_err());
+
+    let versions = database.get_versions(original)?;
+    assert_eq!(versions.iter().map(|(song, _)| song.id).collect::<Vec<_>>(), vec![instrumental, duplicate]);
+    assert_eq!(
+      database.get_original(instrumental)?.map(|(song, kind)| (song.id, kind)),
+      Some((original, VersionKind::Instrumental))
+    );
+    assert_eq!(database.get_original(original)?, None);
+
+    // merging the two instrumentals keeps one link
+    database.merge_songs(instrumental, duplicate, NewSong {
+      title: "Stellar Stellar (Instrumental)".to_string(),
+      ..Default::default()
+    })?;
+    assert_eq!(database.get_versions(original)?.len(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_recent_activity() -> Result<()> {
+    let mut database = setup_database()?;
+    let stellar = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let ghost = database.insert_song(NewSong { title: "Ghost".to_string(), ..Default::default() })?;
+    let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).expect("a valid date").and_hms_opt(0, 0, 0).expect("a time");
+    database.record_play(stellar, day(1))?;
+    database.record_play(ghost, day(2))?;
+    database.record_play(stellar, day(3))?;
+
+    let played: Vec<_> =
+      database.get_recently_played(5)?.into_iter().map(|(song, played_at)| (song.title, played_at)).collect();
+    assert_eq!(played, vec![("Stellar Stellar".to_string(), day(3)), ("Ghost".to_string(), day(2))]);
+    let added: Vec<_> = database.get_recently_added(1)?.into_iter().map(|song| song.id).collect();
+    assert_eq!(added, vec![ghost]);
+    assert_eq!(database.get_library_stats()?, LibraryStats { songs: 2, artists: 0, albums: 0, plays: 3 });
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_add_play_count() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    database.add_play_count(song_id, 3)?;
+    database.add_play_count(song_id, 2)?;
+    assert_eq!(database.get_song_from_id(song_id)?.play_count, 5);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_album_song_counts_for_artist() -> Result<()> {
+    let mut database = setup_database()?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    for title in ["Stellar Stellar", "Tenkyu, Suisei wa Yoru wo Mataide", "Crossing Field"] {
+      let song_id = database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?;
+      database.insert_song_album(SongAlbum { song_id, album_id })?;
+      if title != "Crossing Field" {
+        database.insert_song_artist(SongArtist { song_id, artist_id })?;
+      }
+    }
+
+    let albums = database.get_album_song_counts_for_artist(artist_id)?;
+    assert_eq!(albums.len(), 1);
+    assert_eq!((albums[0].0.id, albums[0].1), (album_id, 2));
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_migrate() -> Result<()> {
+    let connection = DbConnection::Sqlite(SqliteConnection::establish(":memory:")?);
+    let mut database = Database { connection, config: Config::default() };
+    let pending = database.pending_migrations()?;
+    assert!(!pending.is_empty());
+
+    let migrated = database.migrate()?;
+    assert_eq!(migrated, Migrated { from: None, to: pending.last().cloned(), applied: pending.clone() });
+    assert!(database.pending_migrations()?.is_empty());
+    assert_eq!(database.migrate()?, Migrated { from: migrated.to.clone(), to: migrated.to, applied: vec![] });
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_database_new_migrates() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-{}.db", uuid::Uuid::new_v4()));
+    let mut config = Config::default();
+    config.paths.database = path.clone();
+    config.config.database.auto_migrate = false;
+    assert!(!Database::new(config.clone()).await?.pending_migrations()?.is_empty());
+
+    config.config.database.auto_migrate = true;
+    let mut database = Database::new(config).await?;
+    assert!(database.pending_migrations()?.is_empty());
+    database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    std::fs::remove_file(path)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_data_version() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-{}.db", uuid::Uuid::new_v4()));
+    let open = || -> Result<Database> {
+      let connection = DbConnection::Sqlite(SqliteConnection::establish(&path.display().to_string())?);
+      Ok(Database { connection, config: Config::default() })
+    };
+    let mut database = open()?;
+    database.connection.run_pending_migrations(MIGRATIONS).expect("migration successful");
+    let mut other = open()?;
+
+    let version = database.data_version()?;
+    database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    assert_eq!(database.data_version()?, version);
+    other.insert_song(NewSong { title: "Bluerose".to_string(), ..Default::default() })?;
+    assert_ne!(database.data_version()?, version);
+    std::fs::remove_file(&path)?;
+    Ok(())
+  }
+
+  #[cfg(feature = "postgres")]
+  #[test]
+  fn test_database_data_version_postgres() -> Result<()> {
+    let Some(url) = postgres_test_url()? else {
+      return Ok(());
+    };
+    let mut database = Database::open_migrated(&url, Config::default())?;
+    let mut other = Database::open_migrated(&url, Config::default())?;
+
+    let version = database.data_version()?;
+    database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    assert_eq!(database.data_version()?, version);
+    other.insert_song(NewSong { title: "Bluerose".to_string(), ..Default::default() })?;
+    assert_ne!(database.data_version()?, version);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_concurrent_writes() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-{}.db", uuid::Uuid::new_v4()));
+    let url = path.display().to_string();
+    let mut database = Database::open_migrated(&url, Config::default())?;
+    let mut other = Database::open_migrated(&url, Config::default())?;
+
+    // the other connection writes while the first one is in the middle of a transaction
+    let (locked, wait_locked) = std::sync::mpsc::channel();
+    let writer = std::thread::spawn(move || {
+      database.atomically(|database| {
+        database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+        locked.send(()).ok();
+        std::thread::sleep(Duration::from_millis(300));
+        Ok(())
+      })
+    });
+    wait_locked.recv()?;
+    assert!(other.get_all_songs()?.is_empty());
+    other.insert_song(NewSong { title: "Bluerose".to_string(), ..Default::default() })?;
+    writer.join().expect("the writer does not panic")?;
+    assert_eq!(other.get_all_songs()?.len(), 2);
+
+    for suffix in ["", "-wal", "-shm"] {
+      std::fs::remove_file(format!("{url}{suffix}")).ok();
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_song_extra() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    assert_eq!(database.get_song_extra(song_id)?, None);
+    let mut extra =
+      SongExtra { song_id, description: Some("Composed by Hoshimachi Suisei".to_string()), comments: None };
+    database.set_song_extra(&extra)?;
+    extra.description = Some("Lyrics and composition: Hoshimachi Suisei".to_string());
+    database.set_song_extra(&extra)?;
+    assert_eq!(database.get_song_extra(song_id)?, Some(extra));
+
+    let found = database.query_songs(&Query::parse("description:composition")?)?;
+    assert_eq!(found.into_iter().map(|song| song.id).collect::<Vec<_>>(), vec![song_id]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_set_song_rating() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    database.set_song_rating(song_id, Some(5))?;
+    assert_eq!(database.get_song_from_id(song_id)?.rating, Some(5));
+    assert!(database.set_song_rating(song_id, Some(6)).is_err());
+    database.set_song_rating(song_id, None)?;
+    assert_eq!(database.get_song_from_id(song_id)?.rating, None);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_update_song() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    database.set_link_check(&LinkCheck {
+      song_id,
+      checked_at: Utc::now().naive_utc(),
+      dead_reason: Some("private".to_string()),
+      ..Default::default()
+    })?;
+
+    database.update_song(song_id, "Stellar Stellar", Some("a51VH9BYzZA"))?;
+    assert_eq!(database.get_link_checks()?.len(), 1);
+    database.update_song(song_id, "Stellar Stellar (Live)", Some("dQw4w9WgXcQ"))?;
+    let song = database.get_song_from_id(song_id)?;
+    assert_eq!((song.title.as_str(), song.youtube_id.as_deref()), ("Stellar Stellar (Live)", Some("dQw4w9WgXcQ")));
+    assert_eq!(database.get_link_checks()?, vec![]);
+    assert!(database.update_song(song_id, " ", None).is_err());
+
+    database.set_song_artists(song_id, &["Hoshimachi Suisei".to_string(), "TAKU INOUE".to_string()])?;
+    database.set_song_album(song_id, Some("Still Still Stellar"))?;
+    database.set_song_genres(song_id, &["J-Pop".to_string()])?;
+    let values = database.get_song_field_values(song_id)?;
+    assert_eq!(values[1..4], [
+      (SongField::Artists, Some("Hoshimachi Suisei\nTAKU INOUE".to_string())),
+      (SongField::Album, Some("Still Still Stellar".to_string())),
+      (SongField::Genres, Some("J-Pop".to_string())),
+    ]);
+    assert_eq!(database.get_all_artists()?.len(), 2);
+
+    database.set_song_artists(song_id, &[])?;
+    database.set_song_album(song_id, None)?;
+    assert_eq!(database.get_song_field_values(song_id)?[1..3], [(SongField::Artists, None), (SongField::Album, None)]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_delete_song() -> Result<()> {
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "Stellar Stellar.opus".to_string() })?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      file_id: Some(file_id),
+      ..Default::default()
+    })?;
+    let kept_id = database.insert_song(NewSong { title: "Ghost".to_string(), ..Default::default() })?;
+    let version_id =
+      database.insert_song(NewSong { title: "Stellar Stellar (Off Vocal)".to_string(), ..Default::default() })?;
+    database.link_version(SongVersion {
+      song_id: version_id,
+      original_id: song_id,
+      kind: VersionKind::Instrumental,
+    })?;
+    let suisei = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let inoue = database.insert_artist(NewArtist { name: "TAKU INOUE".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id: suisei })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id: inoue })?;
+    database.insert_song_artist(SongArtist { song_id: kept_id, artist_id: suisei })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.insert_song_album(SongAlbum { song_id, album_id })?;
+    let genre_id = database.insert_genre(NewGenre { name: "J-Pop".to_string() })?;
+    database.insert_song_genre(SongGenre { song_id, genre_id })?;
+    database.record_play(song_id, Utc::now().naive_utc())?;
+    database.set_song_field_locked(song_id, SongField::Title, true)?;
+    database.record_song_provenance(song_id, MetadataSource::YtDlp)?;
+    database.add_pin(NewPin { kind: PinKind::Artist, name: "TAKU INOUE".to_string() })?;
+    database.add_pin(NewPin { kind: PinKind::Artist, name: "Hoshimachi Suisei".to_string() })?;
+
+    assert_eq!(database.delete_song(song_id)?, Some("Stellar Stellar.opus".to_string()));
+    assert!(database.get_song_from_id(song_id).is_err());
+    assert_eq!(database.get_song_provenance(song_id)?, vec![]);
+    assert_eq!(database.get_song_locks(song_id)?, vec![]);
+    assert_eq!(database.get_original(version_id)?, None);
+    assert_eq!(database.get_songs_with_files()?, vec![]);
+    // the artist of the other song stays, along with its pin
+    let artists: Vec<_> = database.get_all_artists()?.into_iter().map(|artist| artist.name).collect();
+    assert_eq!(artists, vec!["Hoshimachi Suisei"]);
+    assert_eq!(database.get_pins()?.into_iter().map(|pin| pin.name).collect::<Vec<_>>(), vec!["Hoshimachi Suisei"]);
+    assert_eq!(database.get_all_albums()?, vec![]);
+    assert_eq!(database.get_library_stats()?.songs, 2);
+    assert_eq!(database.delete_song(kept_id)?, None);
+    assert!(database.delete_song(kept_id).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_batch() -> Result<()> {
+    let mut database = setup_database()?;
+    let mut progress = vec![];
+    let names = ["Ado", "LiSA", "YOASOBI", "aiko", "", "Reol"].map(str::to_string).to_vec();
+    let result = database.batch(
+      names,
+      2,
+      |done, total| progress.push((done, total)),
+      |database, name| {
+        match name.is_empty() {
+          true => Err(eyre!("no name")),
+          false => database.insert_artist(NewArtist { name }),
+        }
+      },
+    );
+    assert!(result.is_err());
+    // the chunk that failed was undone whole, the ones before it were kept
+    assert_eq!(progress, vec![(2, 6), (4, 6)]);
+    let artists: Vec<_> = database.get_all_artists()?.into_iter().map(|artist| artist.name).collect();
+    assert_eq!(artists, vec!["Ado", "LiSA", "YOASOBI", "aiko"]);
+
+    // a change made as one inside another is undone on its own
+    database.atomically(|database| {
+      database.insert_genre(NewGenre { name: "J-Pop".to_string() })?;
+      let failed = database.atomically(|database| {
+        database.insert_genre(NewGenre { name: "Rock".to_string() })?;
+        Err::<(), _>(eyre!("failed"))
+      });
+      assert!(failed.is_err());
+      Ok(())
+    })?;
+    let genres: Vec<String> = genre::table.select(genre::name).load(&mut database.connection)?;
+    assert_eq!(genres, vec!["J-Pop"]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_delete_orphans() -> Result<()> {
+    let mut database = setup_database()?;
+    database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_genre(NewGenre { name: "J-Pop".to_string() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.insert_album(NewAlbum { name: "Specialite".to_string() })?;
+    database.insert_attachment(NewAttachment {
+      song_id: None,
+      album_id: Some(album_id),
+      kind: AttachmentKind::Booklet,
+      relative_path: "booklet.pdf".to_string(),
+    })?;
+    assert_eq!(database.delete_orphans()?, 3);
+    assert_eq!(database.get_all_albums()?.into_iter().map(|album| album.name).collect::<Vec<_>>(), vec![
+      "Still Still Stellar"
+    ]);
+    assert_eq!(database.delete_orphans()?, 0);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_search_songs() -> Result<()> {
+    let mut database = setup_database()?;
+    let stellar = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let idol = database.insert_song(NewSong { title: "Idol".to_string(), ..Default::default() })?;
+    let suisei = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id: stellar, artist_id: suisei })?;
+    let yoasobi = database.insert_artist(NewArtist { name: "YOASOBI".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id: idol, artist_id: yoasobi })?;
+    let genre_id = database.insert_genre(NewGenre { name: "J-Pop".to_string() })?;
+    database.insert_song_genre(SongGenre { song_id: idol, genre_id })?;
+
+    let search = |database: &mut Database, text: &str| -> Result<Vec<String>> {
+      Ok(database.search_songs(text)?.into_iter().map(|song| song.title).collect())
+    };
+    assert_eq!(search(&mut database, "stell")?, vec!["Stellar Stellar"]);
+    assert_eq!(search(&mut database, "machi stellar")?, vec!["Stellar Stellar"]);
+    assert_eq!(search(&mut database, "j-pop")?, vec!["Idol"]);
+    assert_eq!(search(&mut database, "yo id")?, vec!["Idol"]);
+    assert_eq!(search(&mut database, "\"idol OR")?, Vec::<String>::new());
+    assert_eq!(search(&mut database, "  ")?, Vec::<String>::new());
+
+    // the search follows renames and deletions
+    database.update_artist(yoasobi, "Ayase")?;
+    assert_eq!(search(&mut database, "ayase")?, vec!["Idol"]);
+    assert_eq!(search(&mut database, "yoasobi")?, Vec::<String>::new());
+    database.delete_song(stellar)?;
+    assert_eq!(search(&mut database, "stellar")?, Vec::<String>::new());
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_songs_page() -> Result<()> {
+    let mut database = setup_database()?;
+    for (title, play_count) in [("stellar stellar", 3), ("Ghost", 0), ("Bluerose", 3), ("ghost", 1)] {
+      let song_id = database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?;
+      database.add_play_count(song_id, play_count)?;
+    }
+    let page = |database: &mut Database, offset, limit, sort, filter: Option<&Query>| -> Result<Vec<String>> {
+      Ok(database.get_songs_page(offset, limit, sort, filter)?.into_iter().map(|song| song.title).collect())
+    };
+    assert_eq!(page(&mut database, 1, 2, SongSort::Added, None)?, vec!["Ghost", "Bluerose"]);
+    assert_eq!(page(&mut database, 0, 10, SongSort::Title, None)?, vec![
+      "Bluerose",
+      "Ghost",
+      "ghost",
+      "stellar stellar"
+    ]);
+    assert_eq!(page(&mut database, 0, 3, SongSort::PlayCount, None)?, vec!["stellar stellar", "Bluerose", "ghost"]);
+    let query = Query::parse("title:ghost")?;
+    assert_eq!(page(&mut database, 1, 10, SongSort::Added, Some(&query))?, vec!["ghost"]);
+    assert_eq!((database.count_songs(None)?, database.count_songs(Some(&query))?), (4, 2));
+    let titled = database.get_songs_titled(&["GHOST".to_string()], None)?;
+    assert_eq!(titled.into_iter().map(|song| song.title).collect::<Vec<_>>(), vec!["Ghost", "ghost"]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_rename() -> Result<()> {
+    let mut database = setup_database()?;
+    let artist_id = database.insert_artist(NewArtist { name: "Suisei".to_string() })?;
+    database.insert_artist(NewArtist { name: "TAKU INOUE".to_string() })?;
+    database.add_pin(NewPin { kind: PinKind::Artist, name: "Suisei".to_string() })?;
+    database.update_artist(artist_id, " Hoshimachi Suisei ")?;
+    assert_eq!(database.get_pins()?[0].name, "Hoshimachi Suisei");
+    assert_eq!(database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?, artist_id);
+    assert!(database.update_artist(artist_id, "TAKU INOUE").is_err());
+    assert!(database.update_artist(artist_id, "").is_err());
+
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.add_pin(NewPin { kind: PinKind::Album, name: "Still Still Stellar".to_string() })?;
+    database.rename_album(album_id, "Still Still Stellar (Deluxe)")?;
+    assert_eq!(database.get_album_by_name("Still Still Stellar (Deluxe)")?.map(|album| album.id), Some(album_id));
+    assert_eq!(database.get_pins()?[1].name, "Still Still Stellar (Deluxe)");
+
+    let genre_id = database.insert_genre(NewGenre { name: "JPop".to_string() })?;
+    database.insert_genre(NewGenre { name: "Anime".to_string() })?;
+    database.rename_genre(genre_id, "J-Pop")?;
+    assert_eq!(database.insert_genre(NewGenre { name: "J-Pop".to_string() })?, genre_id);
+    assert!(database.rename_genre(genre_id, "Anime").is_err());
+    assert!(database.rename_genre(99, "Rock").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_song_locks() -> Result<()> {
+    let mut database = setup_database()?;
+    let keep_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let remove_id = database.insert_song(NewSong { title: "stellar stellar".to_string(), ..Default::default() })?;
+    database.set_song_field_locked(keep_id, SongField::TrackNumber, true)?;
+    database.set_song_field_locked(keep_id, SongField::Title, true)?;
+    database.set_song_field_locked(keep_id, SongField::Title, true)?;
+    assert_eq!(database.get_song_locks(keep_id)?, vec![SongField::Title, SongField::TrackNumber]);
+    assert!(database.is_song_field_locked(keep_id, SongField::Title)?);
+    database.set_song_field_locked(keep_id, SongField::Title, false)?;
+    assert!(!database.is_song_field_locked(keep_id, SongField::Title)?);
+
+    database.set_song_field_locked(remove_id, SongField::TrackNumber, true)?;
+    database.set_song_field_locked(remove_id, SongField::YoutubeId, true)?;
+    database.merge_songs(keep_id, remove_id, NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    assert_eq!(database.get_song_locks(keep_id)?, vec![SongField::YoutubeId, SongField::TrackNumber]);
+    assert_eq!(database.get_song_locks(remove_id)?, vec![]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_provenance() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    database.record_song_provenance(song_id, MetadataSource::YtDlp)?;
+    let sources = |database: &mut Database| -> Result<Vec<(SongField, MetadataSource, Option<String>)>> {
+      Ok(
+        database
+          .get_song_provenance(song_id)?
+          .into_iter()
+          .map(|record| (record.field, record.source, record.value))
+          .collect(),
+      )
+    };
+    assert_eq!(sources(&mut database)?, vec![
+      (SongField::Title, MetadataSource::YtDlp, Some("Stellar Stellar".to_string())),
+      (SongField::Artists, MetadataSource::YtDlp, Some("Hoshimachi Suisei".to_string())),
+      (SongField::YoutubeId, MetadataSource::YtDlp, Some("a51VH9BYzZA".to_string())),
+    ]);
+
+    // the same value from another source keeps the first source
+    database.record_provenance(
+      song_id,
+      SongField::Title,
+      MetadataSource::MusicBrainz,
+      Some("Stellar Stellar".to_string()),
+    )?;
+    database.set_song_field(song_id, SongField::Artists, Some("Hoshimachi Suisei\nComet-chan"))?;
+    database.record_provenance(
+      song_id,
+      SongField::Artists,
+      MetadataSource::MusicBrainz,
+      Some("Hoshimachi Suisei\nComet-chan".to_string()),
+    )?;
+    database.relink_song(song_id, "abcdefghijk", MetadataSource::MusicBrainz)?;
+    database.set_song_field_locked(song_id, SongField::YoutubeId, true)?;
+    assert_eq!(database.get_song_provenance(song_id)?[0].source, MetadataSource::YtDlp);
+
+    // a rehearsal changes nothing
+    let rehearsed = database.rehearse(|database| database.revert_source(MetadataSource::MusicBrainz))?;
+    assert_eq!(rehearsed.len(), 1);
+    assert_eq!(database.get_song_provenance(song_id)?[1].source, MetadataSource::MusicBrainz);
+    assert_eq!(database.revert_source(MetadataSource::MusicBrainz)?, rehearsed);
+    assert_eq!(
+      database.get_song_field_values(song_id)?[1],
+      (SongField::Artists, Some("Hoshimachi Suisei".to_string()))
+    );
+    assert_eq!(sources(&mut database)?[1..], [
+      (SongField::Artists, MetadataSource::YtDlp, Some("Hoshimachi Suisei".to_string())),
+      (SongField::YoutubeId, MetadataSource::MusicBrainz, Some("abcdefghijk".to_string())),
+    ]);
+    assert_eq!(database.get_song_from_id(song_id)?.youtube_id.as_deref(), Some("abcdefghijk"));
+
+    // the merged title is taken from the removed song, along with its source
+    let remove_id = database.insert_song(NewSong { title: "stellar stellar".to_string(), ..Default::default() })?;
+    database.record_song_provenance(remove_id, MetadataSource::FileTag)?;
+    database.merge_songs(song_id, remove_id, NewSong { title: "stellar stellar".to_string(), ..Default::default() })?;
+    assert_eq!(database.get_song_provenance(song_id)?[0].source, MetadataSource::FileTag);
+    assert_eq!(database.get_song_provenance(remove_id)?, vec![]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_history() -> Result<()> {
+    let mut database = setup_database()?;
+    let now = Utc::now().naive_utc();
+    let from = now - chrono::Duration::days(1);
+    let to = now + chrono::Duration::days(1);
+    let stellar = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let crossing = database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
+    database.record_play(crossing, now)?;
+    database.record_play(stellar, now)?;
+    database.record_play(stellar, now)?;
+    database.record_play(stellar, now - chrono::Duration::days(7))?;
+    database.record_download("a51VH9BYzZA", "Stellar Stellar", Ok("m4a".to_string()))?;
+    database.record_download("abcdefghijk", "Kaikai Kitan", Err("video unavailable".to_string()))?;
+
+    assert_eq!(database.get_songs_added_between(from, to)?.len(), 2);
+    assert!(database.get_songs_added_between(to, to + chrono::Duration::days(1))?.is_empty());
+
+    let most_played = database.get_most_played_between(from, to, 10)?;
+    assert_eq!(most_played.iter().map(|(song, plays)| (song.id, *plays)).collect::<Vec<_>>(), vec![
+      (stellar, 2),
+      (crossing, 1)
+    ]);
+    assert_eq!(database.get_song_from_id(stellar)?.play_count, 3);
+
+    let failed = database.get_failed_downloads_between(from, to)?;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].error, Some("video unavailable".to_string()));
+    let recent: Vec<_> = database.get_recent_downloads(1)?.into_iter().map(|download| download.youtube_id).collect();
+    assert_eq!(recent, vec!["abcdefghijk".to_string()]);
+    assert_eq!(database.get_recent_downloads(2)?[1].format, Some("m4a".to_string()));
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_find_duplicate_songs() -> Result<()> {
+    let mut database = setup_database()?;
+    database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    database.insert_song(NewSong { title: "Crossing Field".to_string(), ..Default::default() })?;
+    database.insert_song(NewSong { title: "stellar stellar".to_string(), ..Default::default() })?;
+
+    let duplicates = database.find_duplicate_songs()?;
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!((duplicates[0].0.id, duplicates[0].1.id), (1, 3));
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_merge_songs() -> Result<()> {
+    let mut database = setup_database()?;
+    let keep_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let remove_id = database.insert_song(NewSong {
+      title: "stellar stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    })?;
+    let artist1_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let artist2_id = database.insert_artist(NewArtist { name: "Comet-chan".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id: keep_id, artist_id: artist1_id })?;
+    database.insert_song_artist(SongArtist { song_id: remove_id, artist_id: artist1_id })?;
+    database.insert_song_artist(SongArtist { song_id: remove_id, artist_id: artist2_id })?;
+
+    database.merge_songs(keep_id, remove_id, NewSong {
+      title: "Stellar Stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    })?;
+
+    assert!(database.get_song_from_id(remove_id).is_err());
+    let song = database.get_song_from_id(keep_id)?;
+    assert_eq!(song.youtube_id, Some("a51VH9BYzZA".to_string()));
+    assert_eq!(database.get_all_artists_for_song(song)?.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_album_order() -> Result<()> {
+    let mut database = setup_database()?;
+    let album_id = database.insert_album(NewAlbum { name: "Shinsei Mokuroku".to_string() })?;
+    for (title, disc_number, track_number) in [
+      ("Bluerose", Some(2), Some(1)),
+      ("Stellar Stellar", Some(1), Some(2)),
+      ("Ghost", None, Some(1)),
+      ("Bonus", None, None),
+    ] {
+      let song_id = database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?;
+      database.set_song_position(song_id, disc_number, track_number)?;
+      database.insert_song_album(SongAlbum { song_id, album_id })?;
+    }
+    assert!(database.set_song_position(1, Some(0), Some(1)).is_err());
+
+    let titles: Vec<_> = database.get_all_songs_for_album(album_id)?.into_iter().map(|song| song.title).collect();
+    assert_eq!(titles, vec!["Ghost", "Stellar Stellar", "Bonus", "Bluerose"]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_merge_artists() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let keep_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let remove_id = database.insert_artist(NewArtist { name: "hoshimachi suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id: remove_id })?;
+    assert_eq!(database.find_duplicate_artists()?.len(), 1);
+
+    database.merge_artists(keep_id, remove_id, "Hoshimachi Suisei".to_string())?;
+
+    let song = database.get_song_from_id(song_id)?;
+    assert_eq!(database.get_all_artists_for_song(song)?, vec![Artist {
+      id: keep_id,
+      name: "Hoshimachi Suisei".to_string()
+    }]);
+    assert!(database.find_duplicate_artists()?.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn test_database_song_artist_insert_conflict() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    // this should return an error
+    assert!(database.insert_song_artist(SongArtist { song_id, artist_id }).is_err());
+
+    Ok(())
+  }
 
+  #[tokio::test]
+  async fn test_database_handle() -> Result<()> {
+    let handle = DatabaseHandle::spawn(setup_database()?)?;
+    let song_id = handle
+      .call(|database| database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() }))
+      .await?;
+    // the calls of every clone go to the same database
+    let titles = handle.clone().call(|database| database.get_all_songs()).await?;
+    assert_eq!(titles.iter().map(|song| (song.id, song.title.as_str())).collect::<Vec<_>>(), vec![(
+      song_id,
+      "Stellar Stellar"
+    )]);
+    assert!(handle.call(move |database| database.get_song_from_id(song_id + 1)).await.is_err());
     Ok(())
   }
 }
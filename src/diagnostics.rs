@@ -0,0 +1,306 @@
+//! Startup self-check
+//!
+//! On launch muzik checks that it can do its work: that yt-dlp is installed and recent enough to
+//! keep up with YouTube, that ffmpeg and ffprobe are installed, that the music dir can be written
+//! to and that the database opens with every migration run. The failed checks are listed on the
+//! Diagnostics screen, with a fix a key away where muzik can make it itself, instead of a download
+//! failing halfway later on.
+
+use std::{fmt, path::Path};
+
+use chrono::{Local, NaiveDate};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tokio::{process::Command, sync::mpsc::UnboundedSender};
+
+use crate::{action::Action, config::Config, database::Database, jobs::JobContext};
+
+/// yt-dlp releases older than this many days are likely to have been broken by YouTube since
+pub const MAX_YT_DLP_AGE_DAYS: i64 = 90;
+/// Where the latest release of yt-dlp is downloaded from, followed by the name of its asset
+const YT_DLP_RELEASES: &str = "https://github.com/yt-dlp/yt-dlp/releases/latest/download";
+/// Written into the music dir and removed again to check that it can be written to
+const WRITE_CHECK_FILE: &str = ".muzik-write-check";
+
+/// What is checked on launch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Check {
+  #[strum(serialize = "yt-dlp")]
+  YtDlp,
+  #[strum(serialize = "ffmpeg")]
+  Ffmpeg,
+  #[strum(serialize = "music dir")]
+  MusicDir,
+  #[strum(serialize = "database")]
+  Database,
+  #[strum(serialize = "migrations")]
+  Migrations,
+}
+
+/// A fix muzik makes itself for a failed check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiagnosticFix {
+  /// Download the latest release of yt-dlp into the bin dir
+  DownloadYtDlp,
+  /// Let yt-dlp update itself
+  UpdateYtDlp,
+  CreateMusicDir,
+  Migrate,
+}
+
+impl fmt::Display for DiagnosticFix {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DiagnosticFix::DownloadYtDlp => write!(f, "download yt-dlp"),
+      DiagnosticFix::UpdateYtDlp => write!(f, "update yt-dlp"),
+      DiagnosticFix::CreateMusicDir => write!(f, "create the music dir"),
+      DiagnosticFix::Migrate => write!(f, "run the migrations"),
+    }
+  }
+}
+
+/// A failed check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub check: Check,
+  pub problem: String,
+  /// `None` when it has to be fixed by hand, as the problem says
+  pub fix: Option<DiagnosticFix>,
+}
+
+/// The name of the yt-dlp release running on this system without Python where there is one
+fn yt_dlp_asset() -> &'static str {
+  if cfg!(target_os = "windows") {
+    "yt-dlp.exe"
+  } else if cfg!(target_os = "macos") {
+    "yt-dlp_macos"
+  } else if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+    "yt-dlp_linux"
+  } else if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+    "yt-dlp_linux_aarch64"
+  } else {
+    "yt-dlp"
+  }
+}
+
+/// How many days old the yt-dlp release is, from its version such as `2024.01.01` or the
+/// `2024.01.01.232654` of a nightly build
+fn yt_dlp_age(version: &str, today: NaiveDate) -> Option<i64> {
+  let mut parts = version.trim().split('.').map(|part| part.parse::<u32>().ok());
+  let (Some(Some(year)), Some(Some(month)), Some(Some(day))) = (parts.next(), parts.next(), parts.next()) else {
+    return None;
+  };
+  let released = NaiveDate::from_ymd_opt(year as i32, month, day)?;
+  Some((today - released).num_days())
+}
+
+/// The first line the program prints when asked for its version, `None` if it does not run
+async fn program_version(program: &str, argument: &str) -> Option<String> {
+  let output = Command::new(program).arg(argument).output().await.ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  Some(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default().trim().to_string())
+}
+
+async fn check_yt_dlp(today: NaiveDate) -> Option<Diagnostic> {
+  let Some(version) = program_version("yt-dlp", "--version").await else {
+    return Some(Diagnostic {
+      check: Check::YtDlp,
+      problem: "yt-dlp is not installed, nothing can be downloaded".to_string(),
+      fix: Some(DiagnosticFix::DownloadYtDlp),
+    });
+  };
+  let age = yt_dlp_age(&version, today).filter(|age| *age > MAX_YT_DLP_AGE_DAYS)?;
+  Some(Diagnostic {
+    check: Check::YtDlp,
+    problem: format!("yt-dlp {version} is {age} days old, YouTube may have broken it since"),
+    fix: Some(DiagnosticFix::UpdateYtDlp),
+  })
+}
+
+async fn check_ffmpeg() -> Option<Diagnostic> {
+  let mut missing = vec![];
+  for program in ["ffmpeg", "ffprobe"] {
+    if program_version(program, "-version").await.is_none() {
+      missing.push(program);
+    }
+  }
+  (!missing.is_empty()).then(|| {
+    Diagnostic {
+      check: Check::Ffmpeg,
+      problem: format!(
+        "{} not installed, install ffmpeg with the package manager to convert, tag and scan songs",
+        match missing.as_slice() {
+          [program] => format!("{program} is"),
+          _ => format!("{} are", missing.join(" and ")),
+        }
+      ),
+      fix: None,
+    }
+  })
+}
+
+fn check_music_dir(music_dir: &Path) -> Option<Diagnostic> {
+  if !music_dir.is_dir() {
+    return Some(Diagnostic {
+      check: Check::MusicDir,
+      problem: format!("the music dir {} does not exist", music_dir.display()),
+      fix: Some(DiagnosticFix::CreateMusicDir),
+    });
+  }
+  let path = music_dir.join(WRITE_CHECK_FILE);
+  match std::fs::write(&path, "").and_then(|_| std::fs::remove_file(&path)) {
+    Ok(()) => None,
+    Err(e) => {
+      Some(Diagnostic {
+        check: Check::MusicDir,
+        problem: format!("the music dir {} cannot be written to: {e}", music_dir.display()),
+        fix: None,
+      })
+    },
+  }
+}
+
+async fn check_database(config: &Config) -> Option<Diagnostic> {
+  let location = match config.config.database.url {
+    // the url is left out of the problem, it can hold a password
+    Some(_) => "in Postgres".to_string(),
+    None => format!("at {}", Database::path(config).display()),
+  };
+  let pending = match Database::new(config.clone()).await {
+    Ok(mut database) => database.pending_migrations(),
+    Err(e) => Err(e),
+  };
+  match pending {
+    Ok(pending) if pending.is_empty() => None,
+    Ok(pending) => {
+      Some(Diagnostic {
+        check: Check::Migrations,
+        problem: format!("the database is missing {} migrations of this version of muzik", pending.len()),
+        fix: Some(DiagnosticFix::Migrate),
+      })
+    },
+    Err(e) => {
+      Some(Diagnostic {
+        check: Check::Database,
+        problem: format!("the database {location} cannot be opened: {e}"),
+        fix: None,
+      })
+    },
+  }
+}
+
+/// Run every check, returning the failed ones
+pub async fn run_checks(config: &Config) -> Vec<Diagnostic> {
+  let today = Local::now().date_naive();
+  [
+    check_yt_dlp(today).await,
+    check_ffmpeg().await,
+    check_music_dir(&config.config.music_dir),
+    check_database(config).await,
+  ]
+  .into_iter()
+  .flatten()
+  .collect()
+}
+
+/// Check the setup and send the failed checks to be shown, to be run as a job
+pub async fn check_setup(config: Config, action_tx: UnboundedSender<Action>, context: JobContext) -> Result<()> {
+  let diagnostics = run_checks(&config).await;
+  for diagnostic in &diagnostics {
+    context.log(format!("{}: {}", diagnostic.check, diagnostic.problem));
+  }
+  context.log(format!("{} checks failed", diagnostics.len()));
+  action_tx.send(Action::Diagnostics(diagnostics))?;
+  Ok(())
+}
+
+/// Download the latest release of yt-dlp into the bin dir, which is searched for programs first
+async fn download_yt_dlp(config: &Config) -> Result<()> {
+  let bin_dir = config.paths.bin_dir();
+  std::fs::create_dir_all(&bin_dir)?;
+  let url = format!("{YT_DLP_RELEASES}/{}", yt_dlp_asset());
+  let response = reqwest::get(&url).await?.error_for_status()?;
+  let path = bin_dir.join(if cfg!(target_os = "windows") { "yt-dlp.exe" } else { "yt-dlp" });
+  std::fs::write(&path, response.bytes().await?)?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+  }
+  Ok(())
+}
+
+/// Fix the failed check, to be run as a job. The checks are run again afterwards
+pub async fn apply_fix(
+  config: Config,
+  fix: DiagnosticFix,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  match fix {
+    DiagnosticFix::DownloadYtDlp => download_yt_dlp(&config).await?,
+    DiagnosticFix::UpdateYtDlp => {
+      let output = Command::new("yt-dlp").arg("-U").output().await?;
+      // installed with a package manager, it tells to update it with that instead
+      if !output.status.success() {
+        return Err(eyre!("yt-dlp failed to update itself: {}", String::from_utf8_lossy(&output.stdout).trim()));
+      }
+    },
+    DiagnosticFix::CreateMusicDir => std::fs::create_dir_all(&config.config.music_dir)?,
+    DiagnosticFix::Migrate => {
+      let migrated = Database::new(config.clone()).await?.migrate()?;
+      context.log(migrated.to_string());
+    },
+  }
+  context.log(format!("{fix} is done"));
+  action_tx.send(Action::DiagnosticsRun)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_yt_dlp_age() {
+    let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    assert_eq!(yt_dlp_age("2024.01.01\n", today), Some(60));
+    assert_eq!(yt_dlp_age("2023.12.01.232654", today), Some(91));
+    assert_eq!(yt_dlp_age("unknown", today), None);
+  }
+
+  #[test]
+  fn test_check_music_dir() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-diagnostics-{}", uuid::Uuid::new_v4()));
+    let diagnostic = check_music_dir(&music_dir).map(|diagnostic| (diagnostic.check, diagnostic.fix));
+    assert_eq!(diagnostic, Some((Check::MusicDir, Some(DiagnosticFix::CreateMusicDir))));
+    std::fs::create_dir_all(&music_dir)?;
+    assert_eq!(check_music_dir(&music_dir), None);
+    assert!(!music_dir.join(WRITE_CHECK_FILE).exists());
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_check_database() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-diagnostics-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let mut config = Config::default();
+    config.paths.database = directory.join("database.db");
+    config.config.database.auto_migrate = false;
+    let diagnostic = check_database(&config).await.map(|diagnostic| (diagnostic.check, diagnostic.fix));
+    assert_eq!(diagnostic, Some((Check::Migrations, Some(DiagnosticFix::Migrate))));
+    Database::new(config.clone()).await?.migrate()?;
+    assert_eq!(check_database(&config).await, None);
+
+    config.paths.database = directory.join("missing").join("database.db");
+    assert_eq!(check_database(&config).await.map(|diagnostic| diagnostic.check), Some(Check::Database));
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+  }
+}
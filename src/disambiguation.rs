@@ -0,0 +1,138 @@
+//! Telling apart the songs of the same title in lists
+//!
+//! Lists show a song by its title, and covers, remixes and songs that happen to share a name would
+//! look the same. When songs of the same title are listed together, what tells them apart is added
+//! after their title: their artists, their album, or both when they share their artists. Searches
+//! rank the songs of an artist named exactly as searched above the songs that only contain the
+//! text.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// What is added to the titles that songs of a list share
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+pub enum Disambiguation {
+  /// Titles are listed as they are
+  Off,
+  /// The artists of the song
+  Artist,
+  /// The album of the song
+  Album,
+  /// The artists of the song, and its album when the artists are shared too
+  #[default]
+  Auto,
+}
+
+/// What a song is listed by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SongLabel<'a> {
+  pub title: &'a str,
+  pub artists: &'a [String],
+  pub album: Option<&'a str>,
+}
+
+impl SongLabel<'_> {
+  fn artists(&self) -> Option<String> {
+    Some(self.artists.join(", ")).filter(|artists| !artists.is_empty())
+  }
+}
+
+/// Titles are shared regardless of case and the spaces around them
+fn title_key(title: &str) -> String {
+  title.trim().to_lowercase()
+}
+
+/// The titles of the songs as listed, in the same order. The titles shared by other songs of the
+/// list are followed by what tells them apart, in parentheses
+pub fn disambiguate(songs: &[SongLabel], by: Disambiguation) -> Vec<String> {
+  let mut shared: HashMap<String, Vec<&SongLabel>> = HashMap::new();
+  if by != Disambiguation::Off {
+    for song in songs {
+      shared.entry(title_key(song.title)).or_default().push(song);
+    }
+  }
+  songs
+    .iter()
+    .map(|song| {
+      let same_title = shared.get(&title_key(song.title)).map(Vec::as_slice).unwrap_or_default();
+      if same_title.len() < 2 {
+        return song.title.to_string();
+      }
+      let extra = match by {
+        Disambiguation::Off => None,
+        Disambiguation::Artist => song.artists(),
+        Disambiguation::Album => song.album.map(str::to_string),
+        Disambiguation::Auto => {
+          let artists_shared = same_title.iter().filter(|other| other.artists == song.artists).take(2).count() > 1
+            || song.artists.is_empty();
+          match (song.artists(), song.album) {
+            (Some(artists), Some(album)) if artists_shared => Some(format!("{artists}, {album}")),
+            (None, Some(album)) => Some(album.to_string()),
+            (artists, _) => artists,
+          }
+        },
+      };
+      match extra {
+        Some(extra) => format!("{} ({extra})", song.title),
+        None => song.title.to_string(),
+      }
+    })
+    .collect()
+}
+
+/// How well a song matches the text searched for, lower first: an artist named exactly as
+/// searched, then a title that is exactly the text, then the songs that only contain it
+pub fn search_rank(text: &str, title: &str, artists: &[String]) -> u8 {
+  let text = title_key(text);
+  if artists.iter().any(|artist| title_key(artist) == text) {
+    0
+  } else if title_key(title) == text {
+    1
+  } else {
+    2
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_disambiguate() {
+    let suisei = vec!["Hoshimachi Suisei".to_string()];
+    let lisa = vec!["LiSA".to_string()];
+    let songs = [
+      SongLabel { title: "Stellar Stellar", artists: &suisei, album: Some("Still Still Stellar") },
+      SongLabel { title: "STELLAR STELLAR", artists: &suisei, album: Some("Specialite") },
+      SongLabel { title: "Stellar Stellar", artists: &lisa, album: None },
+      SongLabel { title: "Stellar Stellar", artists: &[], album: Some("Covers") },
+      SongLabel { title: "Bluerose", artists: &suisei, album: None },
+    ];
+    assert_eq!(disambiguate(&songs, Disambiguation::Auto), vec![
+      "Stellar Stellar (Hoshimachi Suisei, Still Still Stellar)",
+      "STELLAR STELLAR (Hoshimachi Suisei, Specialite)",
+      "Stellar Stellar (LiSA)",
+      "Stellar Stellar (Covers)",
+      "Bluerose",
+    ]);
+    assert_eq!(disambiguate(&songs, Disambiguation::Album), vec![
+      "Stellar Stellar (Still Still Stellar)",
+      "STELLAR STELLAR (Specialite)",
+      "Stellar Stellar",
+      "Stellar Stellar (Covers)",
+      "Bluerose",
+    ]);
+    assert_eq!(disambiguate(&songs, Disambiguation::Off), songs.map(|song| song.title.to_string()));
+  }
+
+  #[test]
+  fn test_search_rank() {
+    let artists = vec!["LiSA".to_string()];
+    assert_eq!(search_rank("lisa", "crossing field", &artists), 0);
+    assert_eq!(search_rank("Crossing Field", "crossing field", &artists), 1);
+    assert_eq!(search_rank("cross", "crossing field", &artists), 2);
+  }
+}
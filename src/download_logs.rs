@@ -0,0 +1,183 @@
+//! The output of yt-dlp for every download
+//!
+//! What yt-dlp writes to its stderr is appended to a log per video in the data dir, one section
+//! per attempt, so that a video that keeps failing can be looked into. Its stdout is the metadata
+//! of the video as JSON, only kept in full when the download fails.
+
+use std::{
+  collections::HashSet,
+  fmt,
+  io::Write,
+  path::{Path, PathBuf},
+  process::Output,
+};
+
+use chrono::{Local, NaiveDateTime};
+use color_eyre::eyre::Result;
+
+use crate::{
+  config::Config,
+  database::Database,
+  queue::{QueueItem, QueueStatus},
+};
+
+/// The number of finished downloads listed along with the queue
+const HISTORY_SIZE: i64 = 50;
+
+/// The folder the logs are written to
+pub fn log_dir(config: &Config) -> PathBuf {
+  config.paths.data_dir.join("download_logs")
+}
+
+/// The log of the downloads of a video
+pub fn log_path(directory: &Path, youtube_id: &str) -> PathBuf {
+  directory.join(format!("{youtube_id}.log"))
+}
+
+/// Append the output of a run of yt-dlp to the log of the video
+pub fn append(directory: &Path, youtube_id: &str, args: &[String], output: &Output) -> Result<()> {
+  std::fs::create_dir_all(directory)?;
+  let mut file = std::fs::OpenOptions::new().create(true).append(true).open(log_path(directory, youtube_id))?;
+  writeln!(file, "=== {} yt-dlp {} ===", Local::now().format("%Y-%m-%d %H:%M:%S"), args.join(" "))?;
+  writeln!(file, "{}", output.status)?;
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  if !stderr.trim().is_empty() {
+    writeln!(file, "--- stderr ---\n{}", stderr.trim_end())?;
+  }
+  match output.status.success() {
+    true => writeln!(file, "--- stdout ---\n({} bytes of video metadata)", output.stdout.len())?,
+    false => writeln!(file, "--- stdout ---\n{}", String::from_utf8_lossy(&output.stdout).trim_end())?,
+  }
+  writeln!(file)?;
+  Ok(())
+}
+
+/// Read the log of the video, `None` if it was never downloaded
+pub fn read(directory: &Path, youtube_id: &str) -> Result<Option<String>> {
+  match std::fs::read_to_string(log_path(directory, youtube_id)) {
+    Ok(log) => Ok(Some(log)),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Where a download is at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadLogStatus {
+  Queued,
+  Downloading,
+  /// with the format obtained
+  Finished(NaiveDateTime, Option<String>),
+  Failed(NaiveDateTime, String),
+}
+
+impl fmt::Display for DownloadLogStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let at = |at: &NaiveDateTime| at.and_utc().with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    match self {
+      DownloadLogStatus::Queued => write!(f, "queued"),
+      DownloadLogStatus::Downloading => write!(f, "downloading"),
+      DownloadLogStatus::Finished(finished_at, Some(format)) => write!(f, "finished {} as {format}", at(finished_at)),
+      DownloadLogStatus::Finished(finished_at, None) => write!(f, "finished {}", at(finished_at)),
+      DownloadLogStatus::Failed(finished_at, error) => write!(f, "failed {}: {error}", at(finished_at)),
+    }
+  }
+}
+
+/// A download whose log can be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadLogEntry {
+  pub youtube_id: String,
+  pub title: String,
+  pub status: DownloadLogStatus,
+}
+
+/// The downloads to pick a log from, with a preselected one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadLogList {
+  pub entries: Vec<DownloadLogEntry>,
+  pub selected: Option<usize>,
+}
+
+/// List the queued and running downloads followed by the last finished ones, most recent first.
+/// A video is listed once, at its latest download
+pub fn load_entries(database: &mut Database, queue: Vec<QueueItem>) -> Result<Vec<DownloadLogEntry>> {
+  let mut listed = HashSet::new();
+  let mut entries = vec![];
+  for item in queue.into_iter().rev() {
+    let status = match item.status {
+      QueueStatus::Pending => DownloadLogStatus::Queued,
+      QueueStatus::Downloading => DownloadLogStatus::Downloading,
+      // finished items are in the history, the others were not downloaded
+      QueueStatus::Finished
+      | QueueStatus::Failed(_)
+      | QueueStatus::Skipped(_)
+      | QueueStatus::Duplicate(_)
+      | QueueStatus::Review(_) => continue,
+    };
+    if listed.insert(item.request.youtube_id.clone()) {
+      entries.push(DownloadLogEntry { youtube_id: item.request.youtube_id, title: item.request.title, status });
+    }
+  }
+  for download in database.get_recent_downloads(HISTORY_SIZE)? {
+    if listed.insert(download.youtube_id.clone()) {
+      let status = match download.error {
+        Some(error) => DownloadLogStatus::Failed(download.finished_at, error),
+        None => DownloadLogStatus::Finished(download.finished_at, download.format),
+      };
+      entries.push(DownloadLogEntry { youtube_id: download.youtube_id, title: download.title, status });
+    }
+  }
+  Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::process::ExitStatusExt;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{database::tests::setup_database, queue::DownloadRequest};
+
+  #[test]
+  fn test_download_logs() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-download-logs-{}", uuid::Uuid::new_v4()));
+    assert_eq!(read(&directory, "abcdefghijk")?, None);
+    let output = Output {
+      status: std::process::ExitStatus::from_raw(1 << 8),
+      stdout: vec![],
+      stderr: b"ERROR: [youtube] abcdefghijk: Video unavailable\n".to_vec(),
+    };
+    let args = vec!["-J".to_string(), "https://www.youtube.com/watch?v=abcdefghijk".to_string()];
+    append(&directory, "abcdefghijk", &args, &output)?;
+    append(&directory, "abcdefghijk", &args, &output)?;
+    let log = read(&directory, "abcdefghijk")?.unwrap_or_default();
+    assert_eq!(log.matches("ERROR: [youtube] abcdefghijk: Video unavailable").count(), 2);
+    assert!(log.contains("yt-dlp -J https://www.youtube.com/watch?v=abcdefghijk"));
+    std::fs::remove_dir_all(directory)?;
+
+    let mut database = setup_database()?;
+    database.record_download("a51VH9BYzZA", "Stellar Stellar", Ok("opus".to_string()))?;
+    database.record_download("abcdefghijk", "Kaikai Kitan", Err("video unavailable".to_string()))?;
+    let item = |id, youtube_id: &str, status| {
+      QueueItem {
+        id,
+        request: DownloadRequest { youtube_id: youtube_id.to_string(), ..Default::default() },
+        status,
+        progress: None,
+      }
+    };
+    let queue = vec![
+      item(1, "abcdefghijk", QueueStatus::Failed("video unavailable".to_string())),
+      item(2, "a51VH9BYzZA", QueueStatus::Downloading),
+      item(3, "2lAe1cqCOXo", QueueStatus::Pending),
+    ];
+    let entries = load_entries(&mut database, queue)?;
+    let listed: Vec<_> = entries.iter().map(|entry| entry.youtube_id.as_str()).collect();
+    assert_eq!(listed, vec!["2lAe1cqCOXo", "a51VH9BYzZA", "abcdefghijk"]);
+    assert_eq!(entries[1].status, DownloadLogStatus::Downloading);
+    assert!(matches!(entries[2].status, DownloadLogStatus::Failed(_, _)));
+    Ok(())
+  }
+}
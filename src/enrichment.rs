@@ -0,0 +1,279 @@
+//! Metadata enrichment from online providers
+//!
+//! Providers are queried in the order given in the config until one of them finds the release.
+//! A single provider can also be picked for a lookup from the song editor of the Manager. Release
+//! types come from MusicBrainz. The genres and styles of a release are given to the songs of the
+//! album that have no genre yet. Every provider is built with the feature of its name, a provider
+//! left out fails its lookups.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+use tokio::{
+  sync::{mpsc::UnboundedSender, Mutex},
+  time::Instant,
+};
+use tracing::{debug, warn};
+
+use crate::{
+  action::Action,
+  config::{Config, EnrichmentConfig},
+  database::Database,
+  jobs::JobContext,
+};
+
+#[cfg(feature = "discogs")]
+pub mod discogs;
+#[cfg(feature = "musicbrainz")]
+pub mod musicbrainz;
+
+/// The online sources metadata can be fetched from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumIter)]
+pub enum Provider {
+  Discogs,
+}
+
+/// What to look up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReleaseQuery {
+  pub title: String,
+  pub artist: Option<String>,
+}
+
+/// Release level metadata found by a provider
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReleaseMetadata {
+  pub provider: Option<Provider>,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub label: Option<String>,
+  pub catalog_number: Option<String>,
+  pub year: Option<i32>,
+  pub genres: Vec<String>,
+  pub styles: Vec<String>,
+}
+
+impl ReleaseMetadata {
+  /// The genres of the release followed by its styles, each once
+  pub fn genre_names(&self) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for name in self.genres.iter().chain(&self.styles) {
+      if !names.iter().any(|known| known.eq_ignore_ascii_case(name)) {
+        names.push(name.clone());
+      }
+    }
+    names
+  }
+}
+
+/// Spaces out requests to stay within the rate limit of a provider
+pub struct RateLimiter {
+  interval: Duration,
+  last_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+  /// Allow at most `requests_per_minute` requests every minute
+  pub fn per_minute(requests_per_minute: u32) -> Self {
+    let interval = Duration::from_secs(60) / requests_per_minute.max(1);
+    Self { interval, last_request: Mutex::new(None) }
+  }
+
+  /// Wait until the next request is allowed
+  pub async fn wait(&self) {
+    let mut last_request = self.last_request.lock().await;
+    if let Some(last) = *last_request {
+      tokio::time::sleep_until(last + self.interval).await;
+    }
+    *last_request = Some(Instant::now());
+  }
+}
+
+/// Looks up metadata from the configured providers
+pub struct Enricher {
+  config: EnrichmentConfig,
+  #[cfg(feature = "discogs")]
+  discogs: discogs::DiscogsClient,
+}
+
+impl Enricher {
+  pub fn new(config: EnrichmentConfig) -> Self {
+    Self {
+      #[cfg(feature = "discogs")]
+      discogs: discogs::DiscogsClient::new(config.discogs.clone()),
+      config,
+    }
+  }
+
+  /// Look up a release from a single provider
+  pub async fn lookup(&self, provider: Provider, query: &ReleaseQuery) -> Result<Option<ReleaseMetadata>> {
+    match provider {
+      #[cfg(feature = "discogs")]
+      Provider::Discogs => self.discogs.lookup(query).await,
+      #[cfg(not(feature = "discogs"))]
+      Provider::Discogs => Err(eyre!("muzik was built without Discogs")),
+    }
+  }
+
+  /// Look up a release from each configured provider in order, stopping at the first match.
+  /// Failing providers are skipped
+  pub async fn lookup_chain(&self, query: &ReleaseQuery) -> Result<Option<ReleaseMetadata>> {
+    for provider in &self.config.providers {
+      match self.lookup(*provider, query).await {
+        Ok(Some(metadata)) => return Ok(Some(metadata)),
+        Ok(None) => debug!("{provider} found nothing for {query:?}"),
+        Err(e) => warn!("{provider} lookup failed: {e}"),
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Fill in the release metadata of albums that have none, to be run as a job
+pub async fn enrich_albums(config: Config, context: JobContext) -> Result<()> {
+  if config.config.enrichment.providers.is_empty() {
+    return Err(eyre!("no enrichment providers are configured"));
+  }
+  let enricher = Enricher::new(config.config.enrichment.clone());
+  let mut database = Database::new(config).await?;
+
+  let albums: Vec<_> = database.get_all_albums()?.into_iter().filter(|album| album.year.is_none()).collect();
+  let total = albums.len() as u64;
+  for (index, album) in albums.into_iter().enumerate() {
+    let artist = database.get_all_artists_for_album(album.id)?.into_iter().next().map(|artist| artist.name);
+    let query = ReleaseQuery { title: album.name.clone(), artist };
+    match enricher.lookup_chain(&query).await? {
+      Some(metadata) => {
+        context.log(format!(
+          "{}: found on {}",
+          album.name,
+          metadata.provider.map(|p| p.to_string()).unwrap_or_default()
+        ));
+        save_release(&mut database, album.id, metadata)?;
+      },
+      None => context.log(format!("{}: not found", album.name)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  Ok(())
+}
+
+/// Save the release found for the album, giving its genres to the songs of the album that have none
+fn save_release(database: &mut Database, album_id: i32, metadata: ReleaseMetadata) -> Result<()> {
+  let genres = metadata.genre_names();
+  database.atomically(|database| {
+    database.update_album_release(album_id, metadata.year, metadata.label, metadata.catalog_number)?;
+    if genres.is_empty() {
+      return Ok(());
+    }
+    for song in database.get_all_songs_for_album(album_id)? {
+      if database.get_genres_for_song(song.id)?.is_empty() {
+        database.set_song_genres(song.id, &genres)?;
+      }
+    }
+    Ok(())
+  })
+}
+
+/// Look up the release of a song from the provider picked in the song editor, which is sent what
+/// was found. To be run as a job
+pub async fn lookup_release(
+  config: Config,
+  provider: Provider,
+  query: ReleaseQuery,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let enricher = Enricher::new(config.config.enrichment.clone());
+  match enricher.lookup(provider, &query).await? {
+    Some(metadata) => {
+      context.log(format!("{}: found on {provider}", query.title));
+      action_tx.send(Action::SongEditorRelease(metadata))?;
+    },
+    None => {
+      context.log(format!("{}: not found", query.title));
+      action_tx.send(Action::Notify(format!("{provider} found nothing for {}", query.title)))?;
+    },
+  }
+  Ok(())
+}
+
+/// Fill in the release type of albums that have none from MusicBrainz, to be run as a job
+#[cfg(feature = "musicbrainz")]
+pub async fn classify_albums(config: Config, context: JobContext) -> Result<()> {
+  let musicbrainz = musicbrainz::MusicBrainzClient::new();
+  let mut database = Database::new(config).await?;
+
+  let albums: Vec<_> = database.get_all_albums()?.into_iter().filter(|album| album.release_type.is_none()).collect();
+  let total = albums.len() as u64;
+  for (index, album) in albums.into_iter().enumerate() {
+    let artist = database.get_all_artists_for_album(album.id)?.into_iter().next().map(|artist| artist.name);
+    let query = ReleaseQuery { title: album.name.clone(), artist };
+    match musicbrainz.find_release_type(&query).await {
+      Ok(Some(release_type)) => {
+        context.log(format!("{}: {release_type}", album.name));
+        database.set_album_release_type(album.id, Some(release_type))?;
+      },
+      Ok(None) => context.log(format!("{}: not found", album.name)),
+      Err(e) => context.log(format!("{}: lookup failed: {e}", album.name)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewSong, SongAlbum},
+  };
+
+  #[test]
+  fn test_save_release() -> Result<()> {
+    let mut database = setup_database()?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let mut song_ids = vec![];
+    for title in ["Stellar Stellar", "Ghost"] {
+      let song_id = database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?;
+      database.insert_song_album(SongAlbum { song_id, album_id })?;
+      song_ids.push(song_id);
+    }
+    database.set_song_genres(song_ids[1], &["Rock".to_string()])?;
+
+    let metadata = ReleaseMetadata {
+      provider: Some(Provider::Discogs),
+      title: "Still Still Stellar".to_string(),
+      year: Some(2021),
+      genres: vec!["Electronic".to_string(), "Pop".to_string()],
+      styles: vec!["J-pop".to_string(), "pop".to_string()],
+      ..Default::default()
+    };
+    save_release(&mut database, album_id, metadata)?;
+    let genres = |database: &mut Database, song_id| -> Result<Vec<String>> {
+      let mut names: Vec<_> = database.get_genres_for_song(song_id)?.into_iter().map(|genre| genre.name).collect();
+      names.sort();
+      Ok(names)
+    };
+    assert_eq!(genres(&mut database, song_ids[0])?, vec!["Electronic", "J-pop", "Pop"]);
+    // genres set before are kept
+    assert_eq!(genres(&mut database, song_ids[1])?, vec!["Rock"]);
+    assert_eq!(database.get_album_by_name("Still Still Stellar")?.and_then(|album| album.year), Some(2021));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_rate_limiter_spaces_requests() {
+    let limiter = RateLimiter::per_minute(3000);
+    let start = Instant::now();
+    limiter.wait().await;
+    limiter.wait().await;
+    limiter.wait().await;
+    assert!(start.elapsed() >= Duration::from_millis(40));
+  }
+}
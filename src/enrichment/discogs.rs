@@ -0,0 +1,147 @@
+//! Discogs metadata provider
+//!
+//! Uses the database search to find a release, then fetches the release for its label, catalog
+//! number, year, genres and styles. Requests are authenticated with the personal access token
+//! from the config when available.
+
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+
+use super::{Provider, RateLimiter, ReleaseMetadata, ReleaseQuery};
+use crate::config::DiscogsConfig;
+
+const API_URL: &str = "https://api.discogs.com";
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+  results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+  id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+  title: String,
+  #[serde(default)]
+  year: Option<i32>,
+  #[serde(default)]
+  artists: Vec<ReleaseArtist>,
+  #[serde(default)]
+  labels: Vec<ReleaseLabel>,
+  #[serde(default)]
+  genres: Vec<String>,
+  #[serde(default)]
+  styles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseArtist {
+  name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseLabel {
+  name: String,
+  #[serde(default)]
+  catno: Option<String>,
+}
+
+impl From<Release> for ReleaseMetadata {
+  fn from(release: Release) -> Self {
+    let label = release.labels.into_iter().next();
+    Self {
+      provider: Some(Provider::Discogs),
+      title: release.title,
+      artists: release.artists.into_iter().map(|artist| artist.name).collect(),
+      catalog_number: label.as_ref().and_then(|label| label.catno.clone()).filter(|catno| catno != "none"),
+      label: label.map(|label| label.name),
+      // discogs uses 0 for unknown years
+      year: release.year.filter(|year| *year > 0),
+      genres: release.genres,
+      styles: release.styles,
+    }
+  }
+}
+
+pub struct DiscogsClient {
+  client: reqwest::Client,
+  config: DiscogsConfig,
+  rate_limiter: RateLimiter,
+}
+
+impl DiscogsClient {
+  pub fn new(config: DiscogsConfig) -> Self {
+    let rate_limiter = RateLimiter::per_minute(config.requests_per_minute);
+    Self { client: reqwest::Client::new(), config, rate_limiter }
+  }
+
+  async fn get<T: for<'de> Deserialize<'de>>(&self, url: String, query: &[(&str, &str)]) -> Result<T> {
+    self.rate_limiter.wait().await;
+    let mut request =
+      self.client.get(url).query(query).header("User-Agent", concat!("muzik/", env!("CARGO_PKG_VERSION")));
+    if let Some(token) = &self.config.token {
+      request = request.header("Authorization", format!("Discogs token={token}"));
+    }
+    Ok(request.send().await?.error_for_status()?.json().await?)
+  }
+
+  /// Find the release best matching the query
+  pub async fn lookup(&self, query: &ReleaseQuery) -> Result<Option<ReleaseMetadata>> {
+    let mut search = vec![("type", "release"), ("release_title", query.title.as_str())];
+    if let Some(artist) = &query.artist {
+      search.push(("artist", artist.as_str()));
+    }
+    let response: SearchResponse = self.get(format!("{API_URL}/database/search"), &search).await?;
+    let Some(result) = response.results.first() else {
+      return Ok(None);
+    };
+    let release: Release = self.get(format!("{API_URL}/releases/{}", result.id), &[]).await?;
+    Ok(Some(release.into()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_release_metadata_from_discogs_release() -> Result<()> {
+    let release: Release = serde_json::from_str(
+      r#"{
+        "title": "Still Still Stellar",
+        "year": 2021,
+        "artists": [{ "name": "Hoshimachi Suisei", "id": 1 }],
+        "labels": [{ "name": "VIVIX", "catno": "VVCL-1234" }],
+        "genres": ["Electronic", "Pop"],
+        "styles": ["J-pop"]
+      }"#,
+    )?;
+    assert_eq!(ReleaseMetadata::from(release), ReleaseMetadata {
+      provider: Some(Provider::Discogs),
+      title: "Still Still Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      label: Some("VIVIX".to_string()),
+      catalog_number: Some("VVCL-1234".to_string()),
+      year: Some(2021),
+      genres: vec!["Electronic".to_string(), "Pop".to_string()],
+      styles: vec!["J-pop".to_string()],
+    });
+    Ok(())
+  }
+
+  #[test]
+  fn test_release_metadata_unknown_fields() -> Result<()> {
+    let release: Release = serde_json::from_str(
+      r#"{ "title": "Demo", "year": 0, "labels": [{ "name": "Not On Label", "catno": "none" }] }"#,
+    )?;
+    let metadata = ReleaseMetadata::from(release);
+    assert_eq!(metadata.year, None);
+    assert_eq!(metadata.catalog_number, None);
+    Ok(())
+  }
+}
@@ -0,0 +1,354 @@
+//! MusicBrainz client
+//!
+//! Finds the albums released by an artist. Only official studio albums are considered, without
+//! compilations, live albums or other secondary types. Also tells what kind of release an album
+//! is from its release group, and lists the tracks of an album. MusicBrainz allows a single request per second and asks for a
+//! meaningful user agent.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+
+use super::{RateLimiter, ReleaseQuery};
+use crate::models::ReleaseType;
+
+const API_URL: &str = "https://musicbrainz.org/ws/2";
+const USER_AGENT: &str = concat!("muzik/", env!("CARGO_PKG_VERSION"), " ( https://github.com/luqmanishere/muzik )");
+/// The maximum number of entities returned per request
+const PAGE_SIZE: u64 = 100;
+/// The minimum search score for an artist to be considered the same
+const MIN_ARTIST_SCORE: u8 = 90;
+/// The minimum search score for a release group to be considered the same album
+const MIN_RELEASE_GROUP_SCORE: u8 = 90;
+/// The minimum search score for a release to be considered the same album
+const MIN_RELEASE_SCORE: u8 = 90;
+
+#[derive(Debug, Deserialize)]
+struct ArtistSearch {
+  artists: Vec<ArtistResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistResult {
+  id: String,
+  #[serde(default)]
+  score: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseBrowse {
+  releases: Vec<Release>,
+  #[serde(rename = "release-count")]
+  release_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+  #[serde(rename = "release-group")]
+  release_group: Option<ReleaseGroup>,
+  #[serde(default)]
+  media: Vec<Medium>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroup {
+  id: String,
+  title: String,
+  #[serde(rename = "primary-type")]
+  primary_type: Option<String>,
+  #[serde(rename = "secondary-types", default)]
+  secondary_types: Vec<String>,
+}
+
+impl ReleaseGroup {
+  /// The kind of release, secondary types such as live taking precedence over the primary type
+  fn release_type(&self) -> Option<ReleaseType> {
+    let secondary = self.secondary_types.iter().find_map(|secondary| {
+      match secondary.as_str() {
+        "Compilation" => Some(ReleaseType::Compilation),
+        "Soundtrack" => Some(ReleaseType::Soundtrack),
+        "Live" => Some(ReleaseType::Live),
+        _ => None,
+      }
+    });
+    secondary.or(match self.primary_type.as_deref() {
+      Some("Album") => Some(ReleaseType::Album),
+      Some("Single") => Some(ReleaseType::Single),
+      Some("EP") => Some(ReleaseType::Ep),
+      _ => None,
+    })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroupSearch {
+  #[serde(rename = "release-groups")]
+  release_groups: Vec<ReleaseGroupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroupResult {
+  #[serde(default)]
+  score: u8,
+  #[serde(flatten)]
+  group: ReleaseGroup,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseSearch {
+  releases: Vec<ReleaseResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseResult {
+  id: String,
+  #[serde(default)]
+  score: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct Medium {
+  #[serde(rename = "track-count")]
+  track_count: u32,
+  /// the disc number
+  #[serde(default)]
+  position: i32,
+  /// only returned when the recordings are asked for
+  #[serde(default)]
+  tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+  title: String,
+  position: i32,
+  /// in milliseconds
+  length: Option<u64>,
+  #[serde(rename = "artist-credit", default)]
+  artist_credit: Vec<ArtistCredit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+  name: String,
+}
+
+/// An album released by an artist
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArtistAlbum {
+  pub title: String,
+  /// the number of tracks of the shortest edition of the album
+  pub track_count: u32,
+}
+
+/// A track of an album
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlbumTrack {
+  pub title: String,
+  pub artists: Vec<String>,
+  pub disc_number: i32,
+  pub track_number: i32,
+  /// in seconds
+  pub duration: Option<u64>,
+}
+
+/// The tracks of every disc of a release, in order
+fn tracks_from_release(release: Release) -> Vec<AlbumTrack> {
+  release
+    .media
+    .into_iter()
+    .flat_map(|medium| {
+      medium.tracks.into_iter().map(move |track| {
+        AlbumTrack {
+          title: track.title,
+          artists: track.artist_credit.into_iter().map(|credit| credit.name).collect(),
+          disc_number: medium.position,
+          track_number: track.position,
+          duration: track.length.map(|length| (length as f64 / 1000.0).round() as u64),
+        }
+      })
+    })
+    .collect()
+}
+
+/// Group the releases by album. Editions of an album often differ in track count, the shortest
+/// one is taken as bonus tracks should not count as missing
+fn albums_from_releases(releases: Vec<Release>) -> Vec<ArtistAlbum> {
+  let mut albums: HashMap<String, ArtistAlbum> = HashMap::new();
+  for release in releases {
+    let Some(group) = release.release_group else {
+      continue;
+    };
+    if group.primary_type.as_deref() != Some("Album") || !group.secondary_types.is_empty() {
+      continue;
+    }
+    let track_count = release.media.iter().map(|medium| medium.track_count).sum();
+    albums
+      .entry(group.id)
+      .and_modify(|album| album.track_count = album.track_count.min(track_count))
+      .or_insert(ArtistAlbum { title: group.title, track_count });
+  }
+  let mut albums: Vec<_> = albums.into_values().collect();
+  albums.sort_by(|left, right| left.title.cmp(&right.title));
+  albums
+}
+
+pub struct MusicBrainzClient {
+  client: reqwest::Client,
+  rate_limiter: RateLimiter,
+}
+
+impl Default for MusicBrainzClient {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MusicBrainzClient {
+  pub fn new() -> Self {
+    Self { client: reqwest::Client::new(), rate_limiter: RateLimiter::per_minute(60) }
+  }
+
+  async fn get<T: for<'de> Deserialize<'de>>(&self, url: String, query: &[(&str, &str)]) -> Result<T> {
+    self.rate_limiter.wait().await;
+    let request = self.client.get(url).query(query).query(&[("fmt", "json")]).header("User-Agent", USER_AGENT);
+    Ok(request.send().await?.error_for_status()?.json().await?)
+  }
+
+  /// Find the MusicBrainz id of an artist by name
+  pub async fn find_artist(&self, name: &str) -> Result<Option<String>> {
+    let query = format!("artist:\"{}\"", name.replace('"', ""));
+    let response: ArtistSearch =
+      self.get(format!("{API_URL}/artist"), &[("query", query.as_str()), ("limit", "1")]).await?;
+    Ok(response.artists.into_iter().find(|artist| artist.score >= MIN_ARTIST_SCORE).map(|artist| artist.id))
+  }
+
+  /// Find what kind of release an album is
+  pub async fn find_release_type(&self, query: &ReleaseQuery) -> Result<Option<ReleaseType>> {
+    let mut search = format!("releasegroup:\"{}\"", query.title.replace('"', ""));
+    if let Some(artist) = &query.artist {
+      search.push_str(&format!(" AND artist:\"{}\"", artist.replace('"', "")));
+    }
+    let response: ReleaseGroupSearch =
+      self.get(format!("{API_URL}/release-group"), &[("query", search.as_str()), ("limit", "1")]).await?;
+    Ok(
+      response
+        .release_groups
+        .into_iter()
+        .find(|result| result.score >= MIN_RELEASE_GROUP_SCORE)
+        .and_then(|result| result.group.release_type()),
+    )
+  }
+
+  /// Get the tracks of an album, from the release matching it best
+  pub async fn album_tracks(&self, query: &ReleaseQuery) -> Result<Option<Vec<AlbumTrack>>> {
+    let mut search = format!("release:\"{}\"", query.title.replace('"', ""));
+    if let Some(artist) = &query.artist {
+      search.push_str(&format!(" AND artist:\"{}\"", artist.replace('"', "")));
+    }
+    let response: ReleaseSearch =
+      self.get(format!("{API_URL}/release"), &[("query", search.as_str()), ("limit", "1")]).await?;
+    let Some(found) = response.releases.into_iter().find(|result| result.score >= MIN_RELEASE_SCORE) else {
+      return Ok(None);
+    };
+    let release: Release =
+      self.get(format!("{API_URL}/release/{}", found.id), &[("inc", "recordings+artist-credits")]).await?;
+    Ok(Some(tracks_from_release(release)))
+  }
+
+  /// Get the official studio albums of an artist
+  pub async fn artist_albums(&self, artist_id: &str) -> Result<Vec<ArtistAlbum>> {
+    let mut releases = vec![];
+    let mut offset = 0;
+    loop {
+      let offset_param = offset.to_string();
+      let limit = PAGE_SIZE.to_string();
+      let page: ReleaseBrowse = self
+        .get(format!("{API_URL}/release"), &[
+          ("artist", artist_id),
+          ("type", "album"),
+          ("status", "official"),
+          ("inc", "release-groups+media"),
+          ("limit", limit.as_str()),
+          ("offset", offset_param.as_str()),
+        ])
+        .await?;
+      offset += page.releases.len() as u64;
+      let done = page.releases.is_empty() || offset >= page.release_count;
+      releases.extend(page.releases);
+      if done {
+        break;
+      }
+    }
+    Ok(albums_from_releases(releases))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_albums_from_releases() -> Result<()> {
+    let page: ReleaseBrowse = serde_json::from_value(serde_json::json!({
+      "release-count": 4,
+      "releases": [
+        { "release-group": { "id": "1", "title": "Still Still Stellar", "primary-type": "Album", "secondary-types": [] },
+          "media": [{ "track-count": 12 }] },
+        { "release-group": { "id": "1", "title": "Still Still Stellar", "primary-type": "Album", "secondary-types": [] },
+          "media": [{ "track-count": 12 }, { "track-count": 3 }] },
+        { "release-group": { "id": "2", "title": "Specter", "primary-type": "Album" },
+          "media": [{ "track-count": 13 }] },
+        { "release-group": { "id": "3", "title": "Live at Budokan", "primary-type": "Album",
+                             "secondary-types": ["Live"] },
+          "media": [{ "track-count": 20 }] }
+      ]
+    }))?;
+    assert_eq!(albums_from_releases(page.releases), vec![
+      ArtistAlbum { title: "Specter".to_string(), track_count: 13 },
+      ArtistAlbum { title: "Still Still Stellar".to_string(), track_count: 12 },
+    ]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_tracks_from_release() -> Result<()> {
+    let release: Release = serde_json::from_value(serde_json::json!({
+      "media": [
+        { "position": 1, "track-count": 2, "tracks": [
+          { "title": "Stellar Stellar", "position": 1, "length": 291_480,
+            "artist-credit": [{ "name": "Hoshimachi Suisei" }] },
+          { "title": "Bluerose", "position": 2, "length": null, "artist-credit": [] }
+        ] },
+        { "position": 2, "track-count": 1, "tracks": [
+          { "title": "Stellar Stellar (Instrumental)", "position": 1, "length": 291_000 }
+        ] }
+      ]
+    }))?;
+    let tracks = tracks_from_release(release);
+    assert_eq!(
+      tracks.iter().map(|track| (track.disc_number, track.track_number, track.duration)).collect::<Vec<_>>(),
+      vec![(1, 1, Some(291)), (1, 2, None), (2, 1, Some(291))]
+    );
+    assert_eq!(tracks[0].artists, vec!["Hoshimachi Suisei".to_string()]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_release_group_type() -> Result<()> {
+    let search: ReleaseGroupSearch = serde_json::from_value(serde_json::json!({
+      "release-groups": [
+        { "id": "1", "title": "Stellar Stellar", "score": 100, "primary-type": "Single" },
+        { "id": "2", "title": "Hololive Best", "score": 95, "primary-type": "Album", "secondary-types": ["Compilation"] },
+        { "id": "3", "title": "Weiss Schnee", "score": 80, "primary-type": "EP" },
+        { "id": "4", "title": "Interview", "score": 80, "primary-type": "Other" }
+      ]
+    }))?;
+    let types: Vec<_> = search.release_groups.iter().map(|result| result.group.release_type()).collect();
+    assert_eq!(types, vec![Some(ReleaseType::Single), Some(ReleaseType::Compilation), Some(ReleaseType::Ep), None]);
+    Ok(())
+  }
+}
@@ -0,0 +1,148 @@
+//! Exit codes and machine-readable errors of the CLI
+//!
+//! Every error is put into a category from the errors in its chain, and the process exits with
+//! the code of the category. With `--json-errors`, the error is written to stderr as a single JSON
+//! object instead of a report meant for humans, so that scripts can react to specific failures.
+
+use color_eyre::eyre::Report;
+use serde::Serialize;
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+  #[strum(to_string = "unexpected error")]
+  General,
+  /// the arguments could not be parsed, like clap reports them
+  #[strum(to_string = "invalid arguments")]
+  Usage,
+  #[strum(to_string = "invalid query")]
+  InvalidQuery,
+  #[strum(to_string = "invalid configuration")]
+  Config,
+  #[strum(to_string = "database error")]
+  Database,
+  #[strum(to_string = "network error")]
+  Network,
+  #[strum(to_string = "file error")]
+  Io,
+}
+
+impl ErrorCategory {
+  pub const ALL: [ErrorCategory; 7] = [
+    ErrorCategory::General,
+    ErrorCategory::Usage,
+    ErrorCategory::InvalidQuery,
+    ErrorCategory::Config,
+    ErrorCategory::Database,
+    ErrorCategory::Network,
+    ErrorCategory::Io,
+  ];
+
+  /// The exit code of the process
+  pub fn code(&self) -> i32 {
+    match self {
+      ErrorCategory::General => 1,
+      ErrorCategory::Usage => 2,
+      ErrorCategory::InvalidQuery => 3,
+      ErrorCategory::Config => 4,
+      ErrorCategory::Database => 5,
+      ErrorCategory::Network => 6,
+      ErrorCategory::Io => 7,
+    }
+  }
+
+  /// Find the category of an error. A category attached with `wrap_err` takes precedence,
+  /// otherwise the first error in the chain with a known type decides
+  pub fn of(error: &Report) -> Self {
+    error
+      .downcast_ref::<ErrorCategory>()
+      .copied()
+      .or_else(|| {
+        error.chain().find_map(|cause| {
+          if cause.is::<clap::Error>() {
+            Some(ErrorCategory::Usage)
+          } else if cause.is::<config::ConfigError>() {
+            Some(ErrorCategory::Config)
+          } else if cause.is::<diesel::result::Error>() || cause.is::<diesel::ConnectionError>() {
+            Some(ErrorCategory::Database)
+          } else if cause.is::<reqwest::Error>() {
+            Some(ErrorCategory::Network)
+          } else if cause.is::<std::io::Error>() {
+            Some(ErrorCategory::Io)
+          } else {
+            None
+          }
+        })
+      })
+      .unwrap_or(ErrorCategory::General)
+  }
+
+  /// The exit codes, listed in the help of the CLI
+  pub fn help() -> String {
+    let mut help = "Exit codes:\n  0  success\n".to_string();
+    for category in Self::ALL {
+      help.push_str(&format!("  {}  {category}\n", category.code()));
+    }
+    help
+  }
+}
+
+/// An error as written with `--json-errors`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonError {
+  pub category: ErrorCategory,
+  pub code: i32,
+  pub message: String,
+  /// the errors that caused it, outermost first
+  pub causes: Vec<String>,
+}
+
+impl From<&Report> for JsonError {
+  fn from(error: &Report) -> Self {
+    let category = ErrorCategory::of(error);
+    let mut chain =
+      error.chain().map(|cause| cause.to_string().trim().to_string()).filter(|cause| *cause != category.to_string());
+    Self {
+      category,
+      code: category.code(),
+      message: chain.next().unwrap_or_else(|| category.to_string()),
+      causes: chain.collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use color_eyre::eyre::{eyre, WrapErr};
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::query::Query;
+
+  #[test]
+  fn test_error_category() {
+    let not_found = Report::new(diesel::result::Error::NotFound).wrap_err("get song 3");
+    assert_eq!(ErrorCategory::of(&not_found), ErrorCategory::Database);
+    assert_eq!(ErrorCategory::of(&not_found).code(), 5);
+
+    let io = Report::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied)).wrap_err("write report");
+    assert_eq!(ErrorCategory::of(&io), ErrorCategory::Io);
+    assert_eq!(ErrorCategory::of(&eyre!("something broke")), ErrorCategory::General);
+  }
+
+  #[test]
+  fn test_json_error() {
+    let error = Query::parse("mood:happy").expect_err("unknown field");
+    assert_eq!(JsonError::from(&error), JsonError {
+      category: ErrorCategory::InvalidQuery,
+      code: 3,
+      message: "unknown field \"mood\"".to_string(),
+      causes: vec![],
+    });
+    assert_eq!(
+      serde_json::to_value(JsonError::from(&error)).expect("serializable")["category"],
+      serde_json::json!("invalid_query")
+    );
+  }
+}
@@ -0,0 +1,297 @@
+//! Exporting songs to a folder outside the music dir
+//!
+//! The songs picked in the Manager are copied to a folder, such as a USB stick for the car, named
+//! after the `name_template` of the `export` settings. When a bitrate is set they are transcoded to
+//! opus on the way, to fit more of them.
+
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{config::Config, database::Database, jobs::JobContext, postprocess, queue::DownloadRequest};
+
+/// Placeholders of the name template, without their braces
+const PLACEHOLDERS: [&str; 6] = ["title", "artist", "album", "disc", "track", "id"];
+/// The longest name of a file or folder in bytes. File systems take 255, some are left for the
+/// extension and the number of a taken name
+const MAX_NAME_BYTES: usize = 240;
+
+/// A song to export, with what its name is made of. Downloads are named the same way
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportSong {
+  pub title: String,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  pub disc_number: Option<i32>,
+  pub track_number: Option<i32>,
+  pub youtube_id: Option<String>,
+  /// path of the file relative to the music dir
+  pub path: String,
+}
+
+impl From<&DownloadRequest> for ExportSong {
+  fn from(request: &DownloadRequest) -> Self {
+    Self {
+      title: request.title.clone(),
+      artists: request.artists.clone(),
+      album: request.album.clone(),
+      disc_number: request.disc_number,
+      track_number: request.track_number,
+      youtube_id: Some(request.youtube_id.clone()),
+      path: String::new(),
+    }
+  }
+}
+
+/// Check that the template only uses known placeholders
+pub fn validate_template(template: &str) -> Result<()> {
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    let end = rest[start..].find('}').ok_or_else(|| eyre!("unclosed {{ in the template {template}"))?;
+    let placeholder = &rest[start + 1..start + end];
+    if !PLACEHOLDERS.contains(&placeholder) {
+      return Err(eyre!(
+        "unknown placeholder {{{placeholder}}} in the template {template}, expected one of {PLACEHOLDERS:?}"
+      ));
+    }
+    rest = &rest[start + end + 1..];
+  }
+  Ok(())
+}
+
+/// Make the value safe to use in a file name, as FAT formatted sticks take fewer characters
+fn sanitize(value: &str) -> String {
+  value
+    .chars()
+    .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+    .collect::<String>()
+    .trim()
+    .to_string()
+}
+
+/// Make a part of the path safe as the name of a file or folder. Long names are cut, and dots and
+/// spaces at the end are dropped as FAT drops them, which also keeps `..` from leaving the
+/// destination
+fn clean_part(part: &str) -> &str {
+  let part = part.trim_start();
+  let mut end = part.len().min(MAX_NAME_BYTES);
+  while !part.is_char_boundary(end) {
+    end -= 1;
+  }
+  part[..end].trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+}
+
+/// The path of the exported song relative to the destination, from the template. Slashes in the
+/// template make folders, while those in the tags are replaced
+pub fn render_name(template: &str, song: &ExportSong, extension: &str) -> String {
+  let number = |number: Option<i32>| number.map(|number| format!("{number:02}")).unwrap_or_default();
+  let artist = if song.artists.is_empty() { "Unknown Artist".to_string() } else { song.artists.join(", ") };
+  let name = template
+    .replace("{title}", &sanitize(&song.title))
+    .replace("{artist}", &sanitize(&artist))
+    .replace("{album}", &sanitize(song.album.as_deref().unwrap_or("Unknown Album")))
+    .replace("{disc}", &number(song.disc_number))
+    .replace("{track}", &number(song.track_number))
+    .replace("{id}", &sanitize(song.youtube_id.as_deref().unwrap_or_default()));
+  let parts: Vec<_> = name.split('/').map(clean_part).filter(|part| !part.is_empty()).collect();
+  match parts.is_empty() {
+    true => format!("Unknown.{extension}"),
+    false => format!("{}.{extension}", parts.join("/")),
+  }
+}
+
+/// The name numbered, keeping its extension if it has one
+fn numbered(stem: &str, number: u32, extension: &str) -> String {
+  match extension.is_empty() {
+    true => format!("{stem} ({number})"),
+    false => format!("{stem} ({number}).{extension}"),
+  }
+}
+
+/// Number the name when a file of the folder already has it, as `unique_name` does
+pub fn unique_path(directory: &Path, name: String) -> String {
+  let (stem, extension) = name.rsplit_once('.').unwrap_or((&name, ""));
+  (1..)
+    .map(|number| {
+      match number {
+        1 => name.clone(),
+        number => numbered(stem, number, extension),
+      }
+    })
+    .find(|name| !directory.join(name).exists())
+    .expect("a free name is found before running out of numbers")
+}
+
+/// Number the name when it is already taken by another exported song
+pub(crate) fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+  let mut unique = name.clone();
+  let (stem, extension) = name.rsplit_once('.').unwrap_or((&name, ""));
+  let mut number = 2;
+  while !taken.insert(unique.clone()) {
+    unique = numbered(stem, number, extension);
+    number += 1;
+  }
+  unique
+}
+
+/// Load the songs to export in the given order, leaving out the songs without a file
+pub fn load_songs(database: &mut Database, song_ids: &[i32]) -> Result<Vec<ExportSong>> {
+  let wanted: HashSet<i32> = song_ids.iter().copied().collect();
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    if wanted.contains(&song_id) {
+      artists.entry(song_id).or_default().push(name);
+    }
+  }
+  let mut albums: HashMap<i32, String> = HashMap::new();
+  for (song_id, album) in database.get_song_albums()? {
+    albums.entry(song_id).or_insert(album.name);
+  }
+  let mut songs: HashMap<i32, ExportSong> = database
+    .get_songs_with_files()?
+    .into_iter()
+    .filter(|(song, _)| wanted.contains(&song.id))
+    .map(|(song, path)| {
+      let export = ExportSong {
+        title: song.title,
+        artists: artists.remove(&song.id).unwrap_or_default(),
+        album: albums.remove(&song.id),
+        disc_number: song.disc_number,
+        track_number: song.track_number,
+        youtube_id: song.youtube_id,
+        path,
+      };
+      (song.id, export)
+    })
+    .collect();
+  Ok(song_ids.iter().filter_map(|song_id| songs.remove(song_id)).collect())
+}
+
+/// The extension of the exported file, opus when it is transcoded
+pub fn exported_extension(path: &str, bitrate: Option<u32>) -> String {
+  match bitrate {
+    Some(_) => "opus".to_string(),
+    None => Path::new(path).extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default(),
+  }
+}
+
+/// Copy the file to the target, or transcode it to opus when a bitrate is given
+pub async fn export_file(source: &Path, target: &Path, bitrate: Option<u32>) -> Result<()> {
+  if let Some(parent) = target.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  match bitrate {
+    Some(bitrate) => postprocess::transcode_file(source, target, bitrate).await,
+    None => std::fs::copy(source, target).map(|_| ()).map_err(Into::into),
+  }
+}
+
+/// Copy or transcode the songs into the destination, to be run as a job
+pub async fn export_songs(config: Config, song_ids: Vec<i32>, destination: PathBuf, context: JobContext) -> Result<()> {
+  let settings = config.config.export.clone();
+  validate_template(&settings.name_template)?;
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let songs = load_songs(&mut database, &song_ids)?;
+  if songs.len() < song_ids.len() {
+    context.log(format!("leaving out {} songs without a file", song_ids.len() - songs.len()));
+  }
+
+  let total = songs.len() as u64;
+  let mut taken = HashSet::new();
+  for (index, song) in songs.iter().enumerate() {
+    context.pace().await;
+    let extension = exported_extension(&song.path, settings.bitrate);
+    let name = unique_name(render_name(&settings.name_template, song, &extension), &mut taken);
+    match export_file(&music_dir.join(&song.path), &destination.join(&name), settings.bitrate).await {
+      Ok(()) => context.log(format!("exported {name}")),
+      Err(e) => context.log(format!("failed to export {}: {e}", song.title)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use proptest::prelude::*;
+
+  use super::*;
+
+  /// Tags as found on videos, with the characters file systems choke on mixed in
+  fn tag() -> impl Strategy<Value = String> {
+    prop_oneof![any::<String>(), "[ ./\\\\:?\\x00\\t]{0,6}", "\\PC{0,4}[. ]{0,3}", "\\PC{250,300}"]
+  }
+
+  proptest! {
+    #[test]
+    fn test_render_name_is_a_safe_path(
+      title in tag(),
+      artists in prop::collection::vec(tag(), 0..3),
+      album in prop::option::of(tag()),
+      track_number in prop::option::of(any::<i32>()),
+      template in prop::sample::select(vec!["{title}", "{artist}/{album}/{track} {title}", "{album}/{disc}/{title}"]),
+    ) {
+      let song = ExportSong { title, artists, album, track_number, ..Default::default() };
+      let name = render_name(template, &song, "opus");
+      let stem = name.strip_suffix(".opus").expect("the name ends with the extension");
+      for part in stem.split('/') {
+        prop_assert!(!part.is_empty() && part != "." && part != "..", "{name:?} has the part {part:?}");
+        prop_assert!(!part.ends_with(|c: char| c == '.' || c.is_whitespace()), "{name:?} has the part {part:?}");
+        prop_assert!(part.len() <= MAX_NAME_BYTES, "{name:?} has a part of {} bytes", part.len());
+        prop_assert!(!part.contains(|c: char| "\\:*?\"<>|".contains(c) || c.is_control()), "{name:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_render_name() -> Result<()> {
+    let song = ExportSong {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      track_number: Some(3),
+      path: "a51VH9BYzZA.opus".to_string(),
+      ..Default::default()
+    };
+    assert_eq!(render_name("{artist} - {title}", &song, "opus"), "Hoshimachi Suisei - Stellar Stellar.opus");
+    assert_eq!(
+      render_name("{artist}/{album}/{track} {title}", &song, "opus"),
+      "Hoshimachi Suisei/Still Still Stellar/03 Stellar Stellar.opus"
+    );
+    let song = ExportSong { title: "AC/DC: Live?".to_string(), artists: vec![], album: None, ..song };
+    assert_eq!(
+      render_name("{album}/{disc}/{artist} - {title}", &song, "mp3"),
+      "Unknown Album/Unknown Artist - AC_DC_ Live_.mp3"
+    );
+    let song = ExportSong { title: "..".to_string(), album: Some("...".to_string()), ..song };
+    assert_eq!(render_name("{album}/{title}", &song, "mp3"), "Unknown.mp3");
+
+    let mut taken = HashSet::new();
+    assert_eq!(unique_name("a.opus".to_string(), &mut taken), "a.opus");
+    assert_eq!(unique_name("a.opus".to_string(), &mut taken), "a (2).opus");
+    // names without an extension are numbered without a trailing dot
+    assert_eq!(unique_name("a".to_string(), &mut taken), "a");
+    assert_eq!(unique_name("a".to_string(), &mut taken), "a (2)");
+
+    let song = ExportSong { youtube_id: Some("a51VH9BYzZA".to_string()), ..song };
+    assert_eq!(render_name("{id}", &song, "opus"), "a51VH9BYzZA.opus");
+    let directory = std::env::temp_dir().join(format!("muzik-unique-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(directory.join("Suisei"))?;
+    std::fs::write(directory.join("Suisei/a.opus"), "")?;
+    assert_eq!(unique_path(&directory, "Suisei/b.opus".to_string()), "Suisei/b.opus");
+    assert_eq!(unique_path(&directory, "Suisei/a.opus".to_string()), "Suisei/a (2).opus");
+    std::fs::write(directory.join("Suisei/c"), "")?;
+    assert_eq!(unique_path(&directory, "Suisei/c".to_string()), "Suisei/c (2)");
+    std::fs::remove_dir_all(&directory)?;
+
+    assert!(validate_template("{artist}/{track} {title}").is_ok());
+    assert!(validate_template("{year} {title}").is_err());
+    assert!(validate_template("{title").is_err());
+    Ok(())
+  }
+}
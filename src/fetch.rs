@@ -0,0 +1,154 @@
+//! Downloads from the command line
+//!
+//! `muzik download` takes the URL of a video or a playlist, or text to search YouTube for, and
+//! downloads the songs into the library without starting the TUI, so that it can be scripted or
+//! run from cron. The metadata YouTube knows of the songs can be replaced with the arguments.
+//! With nobody to answer, a duplicate the policy would ask about is skipped and guessed metadata
+//! is taken as it is. While a daemon runs, the songs are queued on it instead, and handled there
+//! as any other queued song.
+
+use color_eyre::eyre::{eyre, Result};
+use youtube_dl::{SearchOptions, SingleVideo, YoutubeDl, YoutubeDlOutput};
+
+use crate::{
+  config::{Config, DuplicatePolicy},
+  models::MetadataSource,
+  queue::{Doubt, DownloadRequest},
+};
+
+/// The metadata given on the command line, in place of what YouTube knows
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+  pub title: Option<String>,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  /// what to do if a song is in the library already, instead of the duplicate policy
+  pub duplicates: Option<DuplicatePolicy>,
+}
+
+/// Whether the target is the URL of a video or a playlist, rather than text to search for
+fn is_url(target: &str) -> bool {
+  target.contains("://")
+    || ["www.", "youtube.com/", "music.youtube.com/", "youtu.be/"].iter().any(|prefix| target.starts_with(prefix))
+}
+
+/// The videos of the URL, every one of a playlist, or the first result of searching for the text
+pub async fn resolve(target: &str) -> Result<Vec<SingleVideo>> {
+  let output = match is_url(target) {
+    true => YoutubeDl::new(target).run_async().await?,
+    false => YoutubeDl::search_for(&SearchOptions::youtube(target).with_count(1)).run_async().await?,
+  };
+  let videos = match output {
+    YoutubeDlOutput::SingleVideo(video) => vec![*video],
+    YoutubeDlOutput::Playlist(playlist) => playlist.entries.unwrap_or_default(),
+  };
+  match videos.is_empty() {
+    true => Err(eyre!("found nothing to download for {target:?}")),
+    false => Ok(videos),
+  }
+}
+
+/// The requests to download the videos, with the metadata given in place of what YouTube knows.
+/// What is given is no longer doubted
+pub fn requests(videos: &[SingleVideo], overrides: &Overrides) -> Result<Vec<DownloadRequest>> {
+  if overrides.title.is_some() && videos.len() > 1 {
+    return Err(eyre!("a title can only be given to a single video, not to the {} videos of a playlist", videos.len()));
+  }
+  let requests = videos.iter().map(|video| {
+    let mut request = DownloadRequest::from_video(video);
+    let mut given = vec![];
+    if let Some(title) = &overrides.title {
+      request.title = title.clone();
+      given.push(Doubt::TitleFromVideo);
+    }
+    if !overrides.artists.is_empty() {
+      request.artists = overrides.artists.clone();
+      given.push(Doubt::ArtistFromChannel);
+    }
+    if let Some(album) = &overrides.album {
+      request.album = Some(album.clone());
+      given.push(Doubt::NoAlbum);
+    }
+    if !given.is_empty() {
+      request.doubts.retain(|doubt| !given.contains(doubt));
+      request.source = MetadataSource::Manual;
+    }
+    request.duplicates = overrides.duplicates;
+    request
+  });
+  Ok(requests.collect())
+}
+
+/// The request as downloaded with nobody to answer: its metadata is not held for review, and a
+/// duplicate the policy would ask about is skipped
+pub fn unattended(config: &Config, request: DownloadRequest) -> DownloadRequest {
+  let duplicates = match request.duplicates.unwrap_or(config.config.download.duplicates) {
+    DuplicatePolicy::Ask => DuplicatePolicy::Skip,
+    policy => policy,
+  };
+  DownloadRequest { duplicates: Some(duplicates), doubts: vec![], ..request }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn video(id: &str, track: Option<&str>) -> SingleVideo {
+    SingleVideo {
+      id: id.to_string(),
+      title: Some(format!("【MV】{}", track.unwrap_or(id))),
+      track: track.map(str::to_string),
+      channel: Some("Suisei Channel".to_string()),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_is_url() {
+    assert!(is_url("https://www.youtube.com/watch?v=a51VH9BYzZA"));
+    assert!(is_url("youtu.be/a51VH9BYzZA"));
+    assert!(!is_url("Hoshimachi Suisei Stellar Stellar"));
+  }
+
+  #[test]
+  fn test_requests() -> Result<()> {
+    let videos = [video("a51VH9BYzZA", Some("Stellar Stellar")), video("2lAe1cqCOXo", None)];
+    let requests = requests(&videos, &Overrides::default())?;
+    assert_eq!(requests[0].title, "Stellar Stellar");
+    assert_eq!(requests[0].artists, vec!["Suisei Channel"]);
+    assert_eq!(requests[0].doubts, vec![Doubt::ArtistFromChannel, Doubt::NoAlbum]);
+    assert_eq!(requests[0].source, MetadataSource::YtDlp);
+    assert_eq!(requests[1].title, "【MV】2lAe1cqCOXo");
+
+    let overrides = Overrides {
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      duplicates: Some(DuplicatePolicy::ReplaceIfBetter),
+      ..Default::default()
+    };
+    let requests = super::requests(&videos, &overrides)?;
+    assert_eq!(requests[1].artists, vec!["Hoshimachi Suisei"]);
+    assert_eq!(requests[1].album.as_deref(), Some("Still Still Stellar"));
+    assert_eq!(requests[1].doubts, vec![Doubt::TitleFromVideo]);
+    assert_eq!(requests[1].source, MetadataSource::Manual);
+    assert_eq!(requests[1].duplicates, Some(DuplicatePolicy::ReplaceIfBetter));
+
+    let overrides = Overrides { title: Some("Stellar Stellar".to_string()), ..Default::default() };
+    assert!(super::requests(&videos, &overrides).is_err());
+    assert!(super::requests(&videos[1..], &overrides)?[0].doubts.iter().all(|doubt| *doubt != Doubt::TitleFromVideo));
+    Ok(())
+  }
+
+  #[test]
+  fn test_unattended() {
+    let mut config = Config::default();
+    config.config.download.duplicates = DuplicatePolicy::Ask;
+    let request = DownloadRequest { doubts: vec![Doubt::NoAlbum], ..Default::default() };
+    let request = unattended(&config, request);
+    assert_eq!((request.duplicates, request.doubts), (Some(DuplicatePolicy::Skip), vec![]));
+    let request = DownloadRequest { duplicates: Some(DuplicatePolicy::KeepBoth), ..Default::default() };
+    assert_eq!(unattended(&config, request).duplicates, Some(DuplicatePolicy::KeepBoth));
+  }
+}
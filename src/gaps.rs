@@ -0,0 +1,115 @@
+//! Detection of albums missing from the library
+//!
+//! The albums of every artist in the library are fetched from MusicBrainz and compared with the
+//! songs of the artist in the library. Albums without any song are missing entirely, albums with
+//! fewer songs than tracks are missing partially.
+
+use color_eyre::eyre::Result;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[cfg(feature = "musicbrainz")]
+use crate::enrichment::musicbrainz::{ArtistAlbum, MusicBrainzClient};
+use crate::{
+  action::Action, config::Config, database::Database, import::matching::normalize, jobs::JobContext, models::Album,
+};
+
+/// An album of an artist that is not completely in the library
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlbumGap {
+  pub artist: String,
+  pub album: String,
+  pub track_count: u32,
+  /// the number of songs of the album in the library
+  pub owned: u32,
+}
+
+impl AlbumGap {
+  /// Whether no song of the album is in the library
+  pub fn is_missing(&self) -> bool {
+    self.owned == 0
+  }
+
+  /// The query used to search for the album on YouTube
+  pub fn search_query(&self) -> String {
+    format!("{} - {}", self.artist, self.album)
+  }
+}
+
+/// Compare the albums of an artist with the albums in the library
+///
+/// # Arguments
+///
+/// * `artist` - the name of the artist
+/// * `albums` - the albums released by the artist
+/// * `owned` - the albums in the library with the number of songs of the artist on each
+#[cfg(feature = "musicbrainz")]
+pub fn find_gaps(artist: &str, albums: &[ArtistAlbum], owned: &[(Album, i64)]) -> Vec<AlbumGap> {
+  albums
+    .iter()
+    .filter_map(|album| {
+      let title = normalize(&album.title);
+      let owned = owned
+        .iter()
+        .filter(|(owned_album, _)| normalize(&owned_album.name) == title)
+        .map(|(_, count)| *count as u32)
+        .max()
+        .unwrap_or_default();
+      (owned < album.track_count).then(|| {
+        AlbumGap { artist: artist.to_string(), album: album.title.clone(), track_count: album.track_count, owned }
+      })
+    })
+    .collect()
+}
+
+/// Find the albums missing for every artist in the library, to be run as a job. The gaps are
+/// sent to be shown once every artist has been checked
+#[cfg(feature = "musicbrainz")]
+pub async fn find_album_gaps(config: Config, action_tx: UnboundedSender<Action>, context: JobContext) -> Result<()> {
+  let client = MusicBrainzClient::new();
+  let mut database = Database::new(config).await?;
+
+  let artists = database.get_all_artists()?;
+  let total = artists.len() as u64;
+  let mut gaps = vec![];
+  for (index, artist) in artists.into_iter().enumerate() {
+    match client.find_artist(&artist.name).await? {
+      Some(artist_id) => {
+        let albums = client.artist_albums(&artist_id).await?;
+        let owned = database.get_album_song_counts_for_artist(artist.id)?;
+        let artist_gaps = find_gaps(&artist.name, &albums, &owned);
+        context.log(format!("{}: {} of {} albums incomplete", artist.name, artist_gaps.len(), albums.len()));
+        gaps.extend(artist_gaps);
+      },
+      None => context.log(format!("{}: not found on MusicBrainz", artist.name)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  action_tx.send(Action::ManagerAlbumGaps(gaps))?;
+  Ok(())
+}
+
+#[cfg(all(test, feature = "musicbrainz"))]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_find_gaps() {
+    let albums = vec![
+      ArtistAlbum { title: "Still Still Stellar".to_string(), track_count: 12 },
+      ArtistAlbum { title: "Specter".to_string(), track_count: 13 },
+      ArtistAlbum { title: "Shinsei Mokuroku".to_string(), track_count: 10 },
+    ];
+    let owned = vec![
+      (Album { id: 1, name: "still still stellar".to_string(), ..Default::default() }, 12),
+      (Album { id: 2, name: "Specter".to_string(), ..Default::default() }, 4),
+    ];
+    let gaps = find_gaps("Hoshimachi Suisei", &albums, &owned);
+    assert_eq!(gaps.iter().map(|gap| (gap.album.as_str(), gap.owned, gap.is_missing())).collect::<Vec<_>>(), vec![
+      ("Specter", 4, false),
+      ("Shinsei Mokuroku", 0, true)
+    ]);
+    assert_eq!(gaps[1].search_query(), "Hoshimachi Suisei - Shinsei Mokuroku");
+  }
+}
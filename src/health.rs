@@ -0,0 +1,382 @@
+//! Library health
+//!
+//! The problems of the library are gathered into a to-do list, most pressing first: song files
+//! gone from the music dir, songs whose video is gone, duplicate songs and artists, files in the
+//! music dir missing from the library, albums missing their release metadata and songs without any
+//! cover. Every item leads to the workflow fixing it. The problems are also weighed into a health
+//! score out of 100.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+  path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Result;
+use strum::Display;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+  action::Action,
+  config::Config,
+  database::Database,
+  jobs::JobContext,
+  layouts::{Focus, ManagerLayouts, Scenes, TabFilter},
+  mode::Mode,
+  models::AttachmentKind,
+  queue::DownloadRequest,
+  scan::{self, IgnoreRules},
+};
+
+/// How soon a problem should be looked into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
+pub enum Priority {
+  High,
+  Medium,
+  Low,
+}
+
+/// The kinds of problems of the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+pub enum HealthCategory {
+  #[strum(serialize = "missing files")]
+  MissingFiles,
+  #[strum(serialize = "dead videos")]
+  DeadLinks,
+  #[strum(serialize = "duplicates")]
+  Duplicates,
+  #[strum(serialize = "orphan files")]
+  OrphanFiles,
+  #[strum(serialize = "missing metadata")]
+  MissingMetadata,
+  #[strum(serialize = "missing art")]
+  MissingArt,
+}
+
+impl HealthCategory {
+  pub fn priority(&self) -> Priority {
+    match self {
+      HealthCategory::MissingFiles => Priority::High,
+      HealthCategory::DeadLinks | HealthCategory::Duplicates | HealthCategory::OrphanFiles => Priority::Medium,
+      HealthCategory::MissingMetadata | HealthCategory::MissingArt => Priority::Low,
+    }
+  }
+
+  /// How many of the 100 points of the score the category can take away
+  fn weight(&self) -> f64 {
+    match self {
+      HealthCategory::MissingFiles => 40.0,
+      HealthCategory::Duplicates | HealthCategory::MissingMetadata => 20.0,
+      HealthCategory::DeadLinks | HealthCategory::OrphanFiles | HealthCategory::MissingArt => 10.0,
+    }
+  }
+}
+
+/// The workflow fixing a problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+  /// download the songs whose files are gone again
+  Redownload(Vec<DownloadRequest>),
+  /// link the songs whose video is gone to other videos
+  ReviewDeadLinks,
+  FindDuplicates,
+  ScanMusicDir,
+  EnrichAlbums,
+  ClassifyAlbums,
+  /// attach a cover scan to the album
+  AttachCover(String),
+  /// nothing can be done from muzik
+  Manual,
+}
+
+impl Fix {
+  /// The actions opening the workflow
+  pub fn actions(&self) -> Vec<Action> {
+    match self {
+      Fix::Redownload(requests) => vec![Action::DownloadEnqueue(requests.clone())],
+      Fix::ReviewDeadLinks => vec![Action::DeadLinksShow],
+      Fix::FindDuplicates => vec![Action::ManagerFindDuplicates],
+      Fix::ScanMusicDir => vec![Action::ScanMusicDir],
+      Fix::EnrichAlbums => vec![Action::EnrichAlbums],
+      Fix::ClassifyAlbums => vec![Action::ClassifyAlbums],
+      Fix::AttachCover(album) => {
+        vec![
+          Action::ManagerTabOpen(TabFilter::Album(album.clone())),
+          Action::FocusSwitch(Focus { mode: Mode::Manager, scene: Scenes::Manager(ManagerLayouts::SongList) }),
+          Action::ManagerAttachmentsShow,
+        ]
+      },
+      Fix::Manual => vec![],
+    }
+  }
+}
+
+impl fmt::Display for Fix {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Fix::Redownload(requests) => write!(f, "download {} songs again", requests.len()),
+      Fix::ReviewDeadLinks => write!(f, "link them to other videos"),
+      Fix::FindDuplicates => write!(f, "compare and merge the duplicates"),
+      Fix::ScanMusicDir => write!(f, "scan the music dir"),
+      Fix::EnrichAlbums => write!(f, "enrich the albums"),
+      Fix::ClassifyAlbums => write!(f, "classify the albums"),
+      Fix::AttachCover(album) => write!(f, "attach a cover to {album}"),
+      Fix::Manual => write!(f, "fix by hand"),
+    }
+  }
+}
+
+/// A problem of the library and how to fix it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthItem {
+  pub category: HealthCategory,
+  /// the number of songs, files or albums affected
+  pub count: usize,
+  pub summary: String,
+  pub fix: Fix,
+}
+
+impl HealthItem {
+  pub fn priority(&self) -> Priority {
+    self.category.priority()
+  }
+}
+
+/// The health of the library
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Health {
+  /// out of 100
+  pub score: u8,
+  /// the to-do list, most pressing first
+  pub items: Vec<HealthItem>,
+}
+
+impl Health {
+  /// Weigh the problems into a score, each category taking away its weight when every song is
+  /// affected
+  fn new(mut items: Vec<HealthItem>, songs: usize) -> Self {
+    items.sort_by_key(|item| (item.priority(), std::cmp::Reverse(item.count)));
+    let mut counts: HashMap<HealthCategory, usize> = HashMap::new();
+    for item in &items {
+      *counts.entry(item.category).or_default() += item.count;
+    }
+    let penalty: f64 =
+      counts.iter().map(|(category, count)| category.weight() * (*count as f64 / songs.max(1) as f64).min(1.0)).sum();
+    Self { score: (100.0 - penalty).round().max(0.0) as u8, items }
+  }
+
+  /// The to-do list as text, for the CLI
+  pub fn to_text(&self) -> String {
+    let mut text = format!("Library health: {}/100\n", self.score);
+    if self.items.is_empty() {
+      text.push_str("Nothing to do\n");
+    }
+    for item in &self.items {
+      text.push_str(&format!("[{}] {}: {} ({})\n", item.priority(), item.category, item.summary, item.fix));
+    }
+    text
+  }
+}
+
+/// Look the library over for problems
+pub fn check(music_dir: &Path, database: &mut Database, rules: &IgnoreRules) -> Result<Health> {
+  let songs = database.get_all_songs()?;
+  let mut items = vec![];
+  let dead: HashSet<i32> = database
+    .get_link_checks()?
+    .into_iter()
+    .filter(|check| check.dead_reason.is_some())
+    .map(|check| check.song_id)
+    .collect();
+
+  // files gone from the music dir, downloaded again when they came from YouTube and their video is
+  // still up
+  let songs_with_files = database.get_songs_with_files()?;
+  let known: HashSet<PathBuf> = songs_with_files.iter().map(|(_, path)| PathBuf::from(path)).collect();
+  let missing: Vec<_> = songs_with_files.into_iter().filter(|(_, path)| !music_dir.join(path).is_file()).collect();
+  if !missing.is_empty() {
+    let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+    for (song_id, name) in database.get_song_artist_names()? {
+      artists.entry(song_id).or_default().push(name);
+    }
+    let requests: Vec<_> = missing
+      .iter()
+      .filter(|(song, _)| !dead.contains(&song.id))
+      .filter_map(|(song, _)| {
+        Some(DownloadRequest {
+          youtube_id: song.youtube_id.clone()?,
+          title: song.title.clone(),
+          artists: artists.get(&song.id).cloned().unwrap_or_default(),
+          disc_number: song.disc_number,
+          track_number: song.track_number,
+          ..Default::default()
+        })
+      })
+      .collect();
+    let fix = match requests.is_empty() {
+      false => Fix::Redownload(requests),
+      true if missing.iter().any(|(song, _)| dead.contains(&song.id)) => Fix::ReviewDeadLinks,
+      true => Fix::Manual,
+    };
+    let summary = format!("{} songs have no file in the music dir, such as {}", missing.len(), missing[0].1);
+    items.push(HealthItem { category: HealthCategory::MissingFiles, count: missing.len(), summary, fix });
+  }
+
+  if let Some(song) = songs.iter().find(|song| dead.contains(&song.id)) {
+    items.push(HealthItem {
+      category: HealthCategory::DeadLinks,
+      count: dead.len(),
+      summary: format!("{} songs were downloaded from videos that are gone, such as {}", dead.len(), song.title),
+      fix: Fix::ReviewDeadLinks,
+    });
+  }
+
+  let duplicate_songs = database.find_duplicate_songs()?.len();
+  let duplicate_artists = database.find_duplicate_artists()?.len();
+  if duplicate_songs + duplicate_artists > 0 {
+    items.push(HealthItem {
+      category: HealthCategory::Duplicates,
+      count: duplicate_songs + duplicate_artists,
+      summary: format!("{duplicate_songs} pairs of songs and {duplicate_artists} pairs of artists look the same"),
+      fix: Fix::FindDuplicates,
+    });
+  }
+
+  if music_dir.is_dir() {
+    let orphans: Vec<_> =
+      scan::music_files(music_dir, rules)?.into_iter().filter(|path| !known.contains(path)).collect();
+    if !orphans.is_empty() {
+      items.push(HealthItem {
+        category: HealthCategory::OrphanFiles,
+        count: orphans.len(),
+        summary: format!("{} files are not in the library, such as {}", orphans.len(), orphans[0].display()),
+        fix: Fix::ScanMusicDir,
+      });
+    }
+  }
+
+  let albums = database.get_all_albums()?;
+  let without_year = albums.iter().filter(|album| album.year.is_none()).count();
+  if without_year > 0 {
+    items.push(HealthItem {
+      category: HealthCategory::MissingMetadata,
+      count: without_year,
+      summary: format!("{without_year} albums have no release metadata"),
+      fix: Fix::EnrichAlbums,
+    });
+  }
+  let without_type = albums.iter().filter(|album| album.release_type.is_none()).count();
+  if without_type > 0 {
+    items.push(HealthItem {
+      category: HealthCategory::MissingMetadata,
+      count: without_type,
+      summary: format!("{without_type} albums have no release type"),
+      fix: Fix::ClassifyAlbums,
+    });
+  }
+
+  // the songs without a thumbnail, on albums without a cover scan
+  let covered: HashSet<i32> = database
+    .get_all_attachments()?
+    .into_iter()
+    .filter(|attachment| attachment.kind == AttachmentKind::Cover)
+    .filter_map(|attachment| attachment.album_id)
+    .collect();
+  let song_albums: HashMap<i32, _> = database.get_song_albums()?.into_iter().collect();
+  let without_art: Vec<_> = songs
+    .iter()
+    .filter(|song| song.thumbnail_url.is_none())
+    .filter(|song| !song_albums.get(&song.id).is_some_and(|album| covered.contains(&album.id)))
+    .collect();
+  if !without_art.is_empty() {
+    let album = without_art.iter().find_map(|song| song_albums.get(&song.id));
+    let fix = album.map(|album| Fix::AttachCover(album.name.clone())).unwrap_or(Fix::Manual);
+    items.push(HealthItem {
+      category: HealthCategory::MissingArt,
+      count: without_art.len(),
+      summary: format!("{} songs have no cover art", without_art.len()),
+      fix,
+    });
+  }
+
+  Ok(Health::new(items, songs.len()))
+}
+
+/// Check the health of the library and send it to be shown, to be run as a job
+pub async fn check_health(config: Config, action_tx: UnboundedSender<Action>, context: JobContext) -> Result<()> {
+  let rules = IgnoreRules::new(&config.config.scan)?;
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let health = check(&music_dir, &mut database, &rules)?;
+  context.log(format!("health {}/100 with {} things to do", health.score, health.items.len()));
+  action_tx.send(Action::HealthReport(health))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    config::ScanConfig,
+    database::tests::setup_database,
+    models::{LinkCheck, NewAlbum, NewFile, NewSong, SongAlbum},
+  };
+
+  #[test]
+  fn test_check_health() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-health-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    std::fs::write(music_dir.join("present.opus"), "")?;
+    std::fs::write(music_dir.join("orphan.opus"), "")?;
+    let mut database = setup_database()?;
+    let rules = IgnoreRules::new(&ScanConfig::default())?;
+    for (title, path, youtube_id) in
+      [("Stellar Stellar", "present.opus", None), ("Bluerose", "missing.opus", Some("2lAe1cqCOXo"))]
+    {
+      let file_id = database.insert_file(NewFile { relative_path: path.to_string() })?;
+      let song_id = database.insert_song(NewSong {
+        title: title.to_string(),
+        youtube_id: youtube_id.map(str::to_string),
+        thumbnail_url: youtube_id.map(|_| "https://i.ytimg.com/vi/2lAe1cqCOXo/hqdefault.jpg".to_string()),
+        file_id: Some(file_id),
+        ..Default::default()
+      })?;
+      let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+      database.insert_song_album(SongAlbum { song_id, album_id })?;
+    }
+
+    let health = check(&music_dir, &mut database, &rules)?;
+    let categories: Vec<_> = health.items.iter().map(|item| (item.category, item.count)).collect();
+    assert_eq!(categories, vec![
+      (HealthCategory::MissingFiles, 1),
+      (HealthCategory::OrphanFiles, 1),
+      (HealthCategory::MissingMetadata, 1),
+      (HealthCategory::MissingMetadata, 1),
+      (HealthCategory::MissingArt, 1),
+    ]);
+    let Fix::Redownload(requests) = &health.items[0].fix else {
+      panic!("the missing song is not downloaded again");
+    };
+    assert_eq!(requests[0].youtube_id, "2lAe1cqCOXo");
+    assert_eq!(health.items[4].fix, Fix::AttachCover("Still Still Stellar".to_string()));
+    // 40 / 2 + 10 / 2 + 20 + 10 / 2
+    assert_eq!(health.score, 50);
+
+    // the video of the missing song is gone, so it can not be downloaded again
+    let song_id = database.get_all_songs()?.into_iter().find(|song| song.title == "Bluerose").map(|song| song.id);
+    database.set_link_check(&LinkCheck {
+      song_id: song_id.unwrap_or_default(),
+      checked_at: chrono::Utc::now().naive_utc(),
+      dead_reason: Some("removed".to_string()),
+      ..Default::default()
+    })?;
+    let health = check(&music_dir, &mut database, &rules)?;
+    assert_eq!(health.items[0].fix, Fix::ReviewDeadLinks);
+    assert_eq!((health.items[1].category, health.items[1].count), (HealthCategory::DeadLinks, 1));
+
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
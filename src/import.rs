@@ -0,0 +1,181 @@
+//! Playlist import
+//!
+//! Tracks of a playlist from another service are matched to YouTube videos by searching for
+//! them. The matches are reviewed before being queued for download, with confident matches
+//! accepted by default and uncertain ones left for the user to accept.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use youtube_dl::{SearchOptions, YoutubeDl};
+
+use crate::{action::Action, config::Config, jobs::JobContext, models::MetadataSource, queue::DownloadRequest};
+
+pub mod matching;
+pub mod playlist_file;
+pub mod spotify;
+
+use matching::{Candidate, CONFIDENT_SCORE, UNCERTAIN_SCORE};
+
+/// The number of search results considered for every track
+const CANDIDATES_PER_TRACK: usize = 5;
+
+/// The metadata of a track in an imported playlist
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportTrack {
+  pub title: String,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  /// duration in seconds
+  pub duration: Option<u64>,
+  pub disc_number: Option<i32>,
+  pub track_number: Option<i32>,
+  /// where the metadata of the track came from
+  pub source: MetadataSource,
+}
+
+impl ImportTrack {
+  /// The query used to search for the track on YouTube
+  pub fn search_query(&self) -> String {
+    match self.artists.first() {
+      Some(artist) => format!("{artist} - {}", self.title),
+      None => self.title.clone(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, strum::Display)]
+pub enum MatchStatus {
+  Confident,
+  Uncertain,
+  #[default]
+  NotFound,
+}
+
+/// The best YouTube video found for an imported track
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportMatch {
+  pub track: ImportTrack,
+  pub candidate: Option<Candidate>,
+  /// from 0 to 100
+  pub score: u8,
+}
+
+impl ImportMatch {
+  pub fn new(track: ImportTrack, best: Option<(Candidate, u8)>) -> Self {
+    match best {
+      Some((candidate, score)) if score >= UNCERTAIN_SCORE => Self { track, candidate: Some(candidate), score },
+      _ => Self { track, candidate: None, score: 0 },
+    }
+  }
+
+  pub fn status(&self) -> MatchStatus {
+    match (&self.candidate, self.score) {
+      (None, _) => MatchStatus::NotFound,
+      (Some(_), score) if score >= CONFIDENT_SCORE => MatchStatus::Confident,
+      (Some(_), _) => MatchStatus::Uncertain,
+    }
+  }
+
+  /// The download request for the matched video, if any
+  pub fn download_request(&self) -> Option<DownloadRequest> {
+    self.candidate.as_ref().map(|candidate| {
+      DownloadRequest {
+        youtube_id: candidate.youtube_id.clone(),
+        title: self.track.title.clone(),
+        artists: self.track.artists.clone(),
+        album: self.track.album.clone(),
+        disc_number: self.track.disc_number,
+        track_number: self.track.track_number,
+        source: self.track.source,
+        ..Default::default()
+      }
+    })
+  }
+}
+
+/// Read the tracks of a playlist. The source is either the path to a CSV or TSV playlist file or
+/// a Spotify playlist
+pub async fn read_tracks(config: &Config, source: &str) -> Result<Vec<ImportTrack>> {
+  let path = Path::new(source.trim());
+  if path.is_file() {
+    return playlist_file::read_playlist_file(path, &config.config.import);
+  }
+  let playlist_id = spotify::parse_playlist_id(source)?;
+  spotify::SpotifyClient::new(config.config.spotify.clone()).playlist_tracks(&playlist_id).await
+}
+
+/// Search YouTube for the track and pick the best match
+pub async fn match_track(track: ImportTrack) -> Result<ImportMatch> {
+  let output = YoutubeDl::search_for(&SearchOptions::youtube(track.search_query()).with_count(CANDIDATES_PER_TRACK))
+    .run_async()
+    .await?;
+  let candidates = output
+    .into_playlist()
+    .and_then(|playlist| playlist.entries)
+    .ok_or_else(|| eyre!("youtube search did not return a playlist"))?
+    .into_iter()
+    .map(Candidate::from)
+    .collect();
+  let best = matching::best_match(&track, candidates);
+  Ok(ImportMatch::new(track, best))
+}
+
+/// Import a playlist, to be run as a job. The matches are sent for review once every track has
+/// been searched for
+pub async fn import_playlist(
+  config: Config,
+  source: String,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let tracks = read_tracks(&config, &source).await?;
+  let matches = match_tracks(tracks, &context).await;
+  action_tx.send(Action::ImportReview(matches))?;
+  Ok(())
+}
+
+/// Search YouTube for every track, reporting the progress to the job. Tracks whose search failed
+/// are left unmatched
+pub async fn match_tracks(tracks: Vec<ImportTrack>, context: &JobContext) -> Vec<ImportMatch> {
+  context.log(format!("matching {} tracks", tracks.len()));
+  let total = tracks.len() as u64;
+  let mut matches = vec![];
+  for (index, track) in tracks.into_iter().enumerate() {
+    let query = track.search_query();
+    let import_match = match match_track(track.clone()).await {
+      Ok(import_match) => import_match,
+      Err(e) => {
+        context.log(format!("search for \"{query}\" failed: {e}"));
+        ImportMatch::new(track, None)
+      },
+    };
+    context.log(format!("{query}: {} ({}%)", import_match.status(), import_match.score));
+    matches.push(import_match);
+    context.progress(index as u64 + 1, total);
+  }
+  matches
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_match_status() {
+    let track = ImportTrack { title: "Stellar Stellar".to_string(), ..Default::default() };
+    let candidate = Candidate { youtube_id: "a51VH9BYzZA".to_string(), ..Default::default() };
+
+    let confident = ImportMatch::new(track.clone(), Some((candidate.clone(), 90)));
+    assert_eq!(confident.status(), MatchStatus::Confident);
+    assert_eq!(confident.download_request().map(|request| request.youtube_id), Some("a51VH9BYzZA".to_string()));
+    assert_eq!(ImportMatch::new(track.clone(), Some((candidate.clone(), 50))).status(), MatchStatus::Uncertain);
+
+    let not_found = ImportMatch::new(track, Some((candidate, 10)));
+    assert_eq!(not_found.status(), MatchStatus::NotFound);
+    assert_eq!(not_found.download_request(), None);
+  }
+}
@@ -0,0 +1,187 @@
+//! Heuristics for matching imported tracks to YouTube videos
+//!
+//! Each candidate video is scored on how much of the track title it contains, whether an artist
+//! appears in the video title or channel, and how close the durations are. Videos of versions
+//! other than the original (live, covers, karaoke...) are penalised unless the track is one too.
+
+use std::collections::HashSet;
+
+use youtube_dl::SingleVideo;
+
+use super::ImportTrack;
+
+/// Scores at or above this are accepted without asking
+pub const CONFIDENT_SCORE: u8 = 80;
+/// Scores at or above this are offered for review, anything below is considered not found
+pub const UNCERTAIN_SCORE: u8 = 40;
+
+/// Words marking a video as a different version of the song
+const VERSION_WORDS: [&str; 8] = ["live", "cover", "karaoke", "remix", "instrumental", "nightcore", "sped", "slowed"];
+
+/// A YouTube video that might be the imported track
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candidate {
+  pub youtube_id: String,
+  pub title: String,
+  pub channel: Option<String>,
+  /// duration in seconds
+  pub duration: Option<u64>,
+}
+
+impl From<SingleVideo> for Candidate {
+  fn from(video: SingleVideo) -> Self {
+    Self {
+      youtube_id: video.id,
+      title: video.title.unwrap_or_default(),
+      channel: video.channel,
+      duration: video.duration.and_then(|duration| duration.as_f64()).map(|duration| duration.round() as u64),
+    }
+  }
+}
+
+/// Lowercase the text and replace anything that is not a letter or number with a space
+pub fn normalize(text: &str) -> String {
+  text
+    .to_lowercase()
+    .chars()
+    .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+    .collect::<String>()
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+fn words(text: &str) -> HashSet<String> {
+  normalize(text).split(' ').filter(|word| !word.is_empty()).map(str::to_string).collect()
+}
+
+/// Score how likely the candidate is the track, from 0 to 100
+pub fn score(track: &ImportTrack, candidate: &Candidate) -> u8 {
+  let track_words = words(&track.title);
+  let candidate_words = words(&candidate.title);
+  if track_words.is_empty() {
+    return 0;
+  }
+
+  let title_score = track_words.intersection(&candidate_words).count() as f64 / track_words.len() as f64;
+
+  let searched = normalize(&format!("{} {}", candidate.title, candidate.channel.as_deref().unwrap_or_default()));
+  let artist_score = if track
+    .artists
+    .iter()
+    .map(|artist| normalize(artist))
+    .any(|artist| !artist.is_empty() && searched.contains(&artist))
+  {
+    1.0
+  } else {
+    0.0
+  };
+
+  let duration_score = match (track.duration, candidate.duration) {
+    (Some(expected), Some(actual)) => {
+      let difference = expected.abs_diff(actual);
+      if difference <= 3 {
+        1.0
+      } else {
+        (1.0 - (difference - 3) as f64 / 27.0).max(0.0)
+      }
+    },
+    // neither good nor bad
+    _ => 0.5,
+  };
+
+  let penalty = if VERSION_WORDS.iter().any(|word| candidate_words.contains(*word) && !track_words.contains(*word)) {
+    0.5
+  } else {
+    1.0
+  };
+
+  ((0.5 * title_score + 0.25 * artist_score + 0.25 * duration_score) * penalty * 100.0).round() as u8
+}
+
+/// The candidate with the highest score, along with the score
+pub fn best_match(track: &ImportTrack, candidates: Vec<Candidate>) -> Option<(Candidate, u8)> {
+  candidates
+    .into_iter()
+    .map(|candidate| (score(track, &candidate), candidate))
+    .max_by_key(|(score, _)| *score)
+    .map(|(score, candidate)| (candidate, score))
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use proptest::prelude::*;
+
+  use super::*;
+
+  proptest! {
+    #[test]
+    fn test_normalize_keeps_words_only(text in any::<String>()) {
+      let normalized = normalize(&text);
+      prop_assert!(normalized.chars().all(|c| c == ' ' || c.is_alphanumeric()), "{normalized:?}");
+      prop_assert!(normalized.trim() == normalized && !normalized.contains("  "), "{normalized:?}");
+      prop_assert_eq!(normalize(&normalized), normalized);
+    }
+
+    #[test]
+    fn test_score_is_a_percentage(
+      title in any::<String>(),
+      artists in prop::collection::vec(any::<String>(), 0..3),
+      duration in prop::option::of(any::<u64>()),
+      video_title in any::<String>(),
+      channel in prop::option::of(any::<String>()),
+      video_duration in prop::option::of(any::<u64>()),
+    ) {
+      let track = ImportTrack { title, artists, duration, ..Default::default() };
+      let candidate = Candidate { title: video_title, channel, duration: video_duration, ..Default::default() };
+      prop_assert!(score(&track, &candidate) <= 100);
+    }
+  }
+
+  fn track() -> ImportTrack {
+    ImportTrack {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      duration: Some(302),
+      ..Default::default()
+    }
+  }
+
+  fn candidate(title: &str, channel: &str, duration: u64) -> Candidate {
+    Candidate {
+      youtube_id: title.to_string(),
+      title: title.to_string(),
+      channel: Some(channel.to_string()),
+      duration: Some(duration),
+    }
+  }
+
+  #[test]
+  fn test_normalize() {
+    assert_eq!(normalize("  Stellar Stellar / 星街すいせい(official)"), "stellar stellar 星街すいせい official");
+  }
+
+  #[test]
+  fn test_score() {
+    let track = track();
+    assert_eq!(score(&track, &candidate("Stellar Stellar", "Hoshimachi Suisei - Topic", 302)), 100);
+    // the duration is too far off to be the same recording
+    assert_eq!(score(&track, &candidate("Stellar Stellar", "Hoshimachi Suisei - Topic", 402)), 75);
+    assert_eq!(score(&track, &candidate("Stellar Stellar (Live)", "Hoshimachi Suisei", 302)), 50);
+    assert_eq!(score(&track, &candidate("Crossing Field", "LiSA", 250)), 0);
+  }
+
+  #[test]
+  fn test_best_match() {
+    let candidates = vec![
+      candidate("Stellar Stellar (cover)", "someone", 300),
+      candidate("【MV】Stellar Stellar / 星街すいせい", "Hoshimachi Suisei", 305),
+    ];
+    let (best, score) = best_match(&track(), candidates).expect("a match");
+    assert_eq!(best.title, "【MV】Stellar Stellar / 星街すいせい");
+    assert!(score >= CONFIDENT_SCORE);
+    assert_eq!(best_match(&track(), vec![]), None);
+  }
+}
@@ -0,0 +1,195 @@
+//! Playlists exported to CSV or TSV files
+//!
+//! The columns holding the title, artists, album, duration and position on the album are taken from the config, so
+//! exports of any service can be read. Exports made with Exportify are recognized by their
+//! header and read without any configuration. Files are read as UTF-8, or UTF-16 when they start
+//! with a byte order mark as the exports of Apple Music do.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+use serde::Deserialize;
+
+use super::ImportTrack;
+use crate::{config::ImportConfig, models::MetadataSource};
+
+/// The unit of the values in the duration column
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum DurationUnit {
+  /// whole seconds, or minutes and seconds such as 3:45
+  #[default]
+  Seconds,
+  Milliseconds,
+}
+
+/// The columns of a playlist exported with Exportify
+pub fn exportify_columns() -> ImportConfig {
+  ImportConfig {
+    title_column: "Track Name".to_string(),
+    artist_column: "Artist Name(s)".to_string(),
+    album_column: Some("Album Name".to_string()),
+    duration_column: Some("Duration (ms)".to_string()),
+    duration_unit: DurationUnit::Milliseconds,
+    disc_column: Some("Disc Number".to_string()),
+    track_column: Some("Track Number".to_string()),
+    artist_separator: ",".to_string(),
+  }
+}
+
+/// Read the text of a playlist file, decoding UTF-16 if the file starts with a byte order mark
+fn decode(bytes: &[u8]) -> Result<String> {
+  let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).collect();
+    String::from_utf16(&units).map_err(|e| eyre!("invalid utf-16: {e}"))
+  };
+  match bytes {
+    [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+    [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+    [0xEF, 0xBB, 0xBF, rest @ ..] => Ok(String::from_utf8(rest.to_vec())?),
+    _ => Ok(String::from_utf8(bytes.to_vec())?),
+  }
+}
+
+/// Parse a duration in the given unit into seconds
+fn parse_duration(value: &str, unit: DurationUnit) -> Option<u64> {
+  match unit {
+    DurationUnit::Milliseconds => value.parse::<u64>().ok().map(|duration| duration / 1000),
+    DurationUnit::Seconds => value.split(':').try_fold(0, |total, part| Some(total * 60 + part.parse::<u64>().ok()?)),
+  }
+}
+
+/// Read the tracks of a playlist from delimited text
+///
+/// # Arguments
+///
+/// * `text` - the contents of the file, starting with a header row
+/// * `delimiter` - the field delimiter, usually `,` or a tab
+/// * `columns` - the names of the columns to read, matched case insensitively
+pub fn read_playlist(text: &str, delimiter: u8, columns: &ImportConfig) -> Result<Vec<ImportTrack>> {
+  let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(text.as_bytes());
+  let headers = reader.headers()?.clone();
+  let column = |name: &str| {
+    headers
+      .iter()
+      .position(|header| header.trim().eq_ignore_ascii_case(name))
+      .ok_or_else(|| eyre!("missing column \"{name}\""))
+  };
+  let title = column(&columns.title_column)?;
+  let artists = column(&columns.artist_column)?;
+  let album = columns.album_column.as_deref().map(column).transpose()?;
+  let duration = columns.duration_column.as_deref().map(column).transpose()?;
+  let disc = columns.disc_column.as_deref().map(column).transpose()?;
+  let track = columns.track_column.as_deref().map(column).transpose()?;
+
+  let mut tracks = vec![];
+  for record in reader.records() {
+    let record = record?;
+    let field =
+      |index: Option<usize>| index.and_then(|index| record.get(index)).map(str::trim).filter(|value| !value.is_empty());
+    let Some(track_title) = field(Some(title)) else {
+      continue;
+    };
+    tracks.push(ImportTrack {
+      title: track_title.to_string(),
+      artists: field(Some(artists))
+        .map(|artists| {
+          artists
+            .split(columns.artist_separator.as_str())
+            .map(str::trim)
+            .filter(|artist| !artist.is_empty())
+            .map(str::to_string)
+            .collect()
+        })
+        .unwrap_or_default(),
+      album: field(album).map(str::to_string),
+      duration: field(duration).and_then(|duration| parse_duration(duration, columns.duration_unit)),
+      disc_number: field(disc).and_then(|disc| disc.parse().ok()),
+      track_number: field(track).and_then(|track| track.parse().ok()),
+      source: MetadataSource::Import,
+    });
+  }
+  Ok(tracks)
+}
+
+/// Read the tracks of a playlist file. Files ending in `.tsv` or `.txt` are read as tab separated
+pub fn read_playlist_file(path: &Path, columns: &ImportConfig) -> Result<Vec<ImportTrack>> {
+  let text = decode(&std::fs::read(path)?)?;
+  let delimiter = match path.extension().and_then(|extension| extension.to_str()) {
+    Some("tsv" | "txt") => b'\t',
+    _ => b',',
+  };
+  let exportify = exportify_columns();
+  let header = text.lines().next().unwrap_or_default();
+  if header.contains(&exportify.title_column) && header.contains(&exportify.artist_column) {
+    read_playlist(&text, delimiter, &exportify)
+  } else {
+    read_playlist(&text, delimiter, columns)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_read_exportify_csv() -> Result<()> {
+    let csv = "\"Track URI\",\"Track Name\",\"Artist Name(s)\",\"Album Name\",\"Disc Number\",\"Track Number\",\"Duration \
+               (ms)\"\n\"spotify:track:1\",\"Stellar Stellar\",\"Hoshimachi Suisei\",\"Still Still \
+               Stellar\",\"1\",\"2\",\"302000\"\n\"spotify:track:2\",\"Kaikai Kitan\",\"Eve, someone\",\"\",\"\",\"\",\"220500\"\n";
+    assert_eq!(read_playlist(csv, b',', &exportify_columns())?, vec![
+      ImportTrack {
+        title: "Stellar Stellar".to_string(),
+        artists: vec!["Hoshimachi Suisei".to_string()],
+        album: Some("Still Still Stellar".to_string()),
+        duration: Some(302),
+        disc_number: Some(1),
+        track_number: Some(2),
+        source: MetadataSource::Import,
+      },
+      ImportTrack {
+        title: "Kaikai Kitan".to_string(),
+        artists: vec!["Eve".to_string(), "someone".to_string()],
+        album: None,
+        duration: Some(220),
+        disc_number: None,
+        track_number: None,
+        source: MetadataSource::Import,
+      },
+    ]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_read_configured_tsv() -> Result<()> {
+    let columns = ImportConfig {
+      title_column: "Name".to_string(),
+      artist_column: "Artist".to_string(),
+      album_column: None,
+      duration_column: Some("Time".to_string()),
+      artist_separator: "&".to_string(),
+      ..Default::default()
+    };
+    let tsv = "Name\tArtist\tAlbum\tTime\nShinunoga E-Wa\tFujii Kaze\tLOVE ALL SERVE ALL\t3:05\n\
+               Idol\tYOASOBI & someone\t\t213\n";
+    let tracks = read_playlist(tsv, b'\t', &columns)?;
+    assert_eq!(tracks[0].album, None);
+    assert_eq!(tracks[0].duration, Some(185));
+    assert_eq!(tracks[1].artists, vec!["YOASOBI".to_string(), "someone".to_string()]);
+    assert_eq!(tracks[1].duration, Some(213));
+
+    let missing = ImportConfig { title_column: "Title".to_string(), ..columns };
+    assert!(read_playlist(tsv, b'\t', &missing).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_decode_utf16() -> Result<()> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend("Name\tArtist".encode_utf16().flat_map(u16::to_le_bytes));
+    assert_eq!(decode(&bytes)?, "Name\tArtist");
+    assert_eq!(decode("Name".as_bytes())?, "Name");
+    Ok(())
+  }
+}
@@ -0,0 +1,157 @@
+//! Spotify playlists
+//!
+//! Playlists are read from the Web API, authenticated with the client credentials of an app from
+//! the Spotify developer dashboard. Playlists exported with Exportify are read as playlist files.
+
+use color_eyre::eyre::{eyre, Context, Result};
+use serde::Deserialize;
+
+use super::ImportTrack;
+use crate::{config::SpotifyConfig, models::MetadataSource};
+
+const API_URL: &str = "https://api.spotify.com/v1";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaylistItems {
+  items: Vec<PlaylistItem>,
+  next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaylistItem {
+  /// null for tracks that are no longer available
+  track: Option<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+  name: String,
+  artists: Vec<TrackArtist>,
+  album: TrackAlbum,
+  duration_ms: u64,
+  disc_number: Option<i32>,
+  track_number: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackArtist {
+  name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackAlbum {
+  name: String,
+}
+
+impl From<Track> for ImportTrack {
+  fn from(track: Track) -> Self {
+    Self {
+      title: track.name,
+      artists: track.artists.into_iter().map(|artist| artist.name).collect(),
+      album: Some(track.album.name),
+      duration: Some(track.duration_ms / 1000),
+      disc_number: track.disc_number,
+      track_number: track.track_number,
+      source: MetadataSource::Import,
+    }
+  }
+}
+
+/// Get the playlist id from a playlist URL, a `spotify:playlist:` URI or the id itself
+pub fn parse_playlist_id(source: &str) -> Result<String> {
+  let source = source.trim();
+  let id = if let Some((_, rest)) = source.split_once("/playlist/") {
+    rest.split(['?', '/']).next().unwrap_or_default()
+  } else if let Some(id) = source.strip_prefix("spotify:playlist:") {
+    id
+  } else {
+    source
+  };
+  if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(eyre!("{source} is not a spotify playlist"));
+  }
+  Ok(id.to_string())
+}
+
+pub struct SpotifyClient {
+  client: reqwest::Client,
+  config: SpotifyConfig,
+}
+
+impl SpotifyClient {
+  pub fn new(config: SpotifyConfig) -> Self {
+    Self { client: reqwest::Client::new(), config }
+  }
+
+  async fn access_token(&self) -> Result<String> {
+    let (Some(client_id), Some(client_secret)) = (&self.config.client_id, &self.config.client_secret) else {
+      return Err(eyre!("spotify client id and secret are not configured"));
+    };
+    let response: TokenResponse = self
+      .client
+      .post(TOKEN_URL)
+      .basic_auth(client_id, Some(client_secret))
+      .form(&[("grant_type", "client_credentials")])
+      .send()
+      .await?
+      .error_for_status()
+      .wrap_err("authenticate with spotify")?
+      .json()
+      .await?;
+    Ok(response.access_token)
+  }
+
+  /// Get all tracks of a playlist
+  pub async fn playlist_tracks(&self, playlist_id: &str) -> Result<Vec<ImportTrack>> {
+    let token = self.access_token().await?;
+    let mut tracks = vec![];
+    let mut url = Some(format!("{API_URL}/playlists/{playlist_id}/tracks"));
+    while let Some(next) = url {
+      let page: PlaylistItems =
+        self.client.get(next).bearer_auth(&token).send().await?.error_for_status()?.json().await?;
+      tracks.extend(page.items.into_iter().filter_map(|item| item.track).map(ImportTrack::from));
+      url = page.next;
+    }
+    Ok(tracks)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_playlist_id() -> Result<()> {
+    let id = "37i9dQZF1DXcBWIGoYBM5M";
+    assert_eq!(parse_playlist_id(&format!("https://open.spotify.com/playlist/{id}?si=abc"))?, id);
+    assert_eq!(parse_playlist_id(&format!("spotify:playlist:{id}"))?, id);
+    assert_eq!(parse_playlist_id(id)?, id);
+    assert!(parse_playlist_id("https://open.spotify.com/album/").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_playlist_item_without_track() -> Result<()> {
+    let page: PlaylistItems = serde_json::from_value(serde_json::json!({
+      "items": [
+        { "track": null },
+        { "track": { "name": "Stellar Stellar", "artists": [{ "name": "Hoshimachi Suisei" }],
+                     "album": { "name": "Still Still Stellar" }, "duration_ms": 302000 } }
+      ],
+      "next": null
+    }))?;
+    let tracks: Vec<ImportTrack> =
+      page.items.into_iter().filter_map(|item| item.track).map(ImportTrack::from).collect();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].duration, Some(302));
+    Ok(())
+  }
+}
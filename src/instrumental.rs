@@ -0,0 +1,144 @@
+//! Instrumental versions for karaoke
+//!
+//! The instrumental (off vocal) version of a song is searched for on YouTube and queued for
+//! download. Once downloaded it is linked to the original as an alternate version, so that the
+//! pair can be played together in a karaoke mode.
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use youtube_dl::{SearchOptions, YoutubeDl};
+
+use crate::{
+  action::Action,
+  config::Config,
+  database::Database,
+  import::{
+    matching::{self, normalize, Candidate, UNCERTAIN_SCORE},
+    ImportTrack,
+  },
+  jobs::JobContext,
+  models::VersionKind,
+  queue::DownloadRequest,
+};
+
+/// The number of search results considered
+const CANDIDATES: usize = 10;
+
+/// Words marking a video as an instrumental, normalized
+const MARKERS: [&str; 8] =
+  ["instrumental", "off vocal", "offvocal", "karaoke", "inst", "backing track", "オフボーカル", "カラオケ"];
+
+/// The query used to search for the instrumental of the song
+pub fn search_query(track: &ImportTrack) -> String {
+  format!("{} instrumental", track.search_query())
+}
+
+/// Whether the video title marks it as an instrumental
+pub fn is_instrumental(title: &str) -> bool {
+  let title = format!(" {} ", normalize(title));
+  MARKERS.iter().any(|marker| title.contains(&format!(" {marker} ")))
+}
+
+/// The instrumental that best matches the song, along with its score. The markers are left out of
+/// the titles before scoring so that they are not taken for another version of the song
+pub fn best_instrumental(track: &ImportTrack, candidates: Vec<Candidate>) -> Option<(Candidate, u8)> {
+  candidates
+    .into_iter()
+    .filter(|candidate| is_instrumental(&candidate.title))
+    .map(|candidate| {
+      let mut title = format!(" {} ", normalize(&candidate.title));
+      for marker in MARKERS {
+        title = title.replace(&format!(" {marker} "), " ");
+      }
+      let stripped = Candidate { title, ..candidate.clone() };
+      (matching::score(track, &stripped), candidate)
+    })
+    .filter(|(score, _)| *score >= UNCERTAIN_SCORE)
+    .max_by_key(|(score, _)| *score)
+    .map(|(score, candidate)| (candidate, score))
+}
+
+/// Search for the instrumental of the song and queue it for download, to be run as a job
+pub async fn find_instrumental(
+  config: Config,
+  song_id: i32,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let mut database = Database::new(config).await?;
+  let song = database.get_song_from_id(song_id)?;
+  if database.get_versions(song_id)?.iter().any(|(_, kind)| *kind == VersionKind::Instrumental) {
+    return Err(eyre!("{} already has an instrumental", song.title));
+  }
+  if let Some((original, _)) = database.get_original(song_id)? {
+    return Err(eyre!("{} is a version of {}", song.title, original.title));
+  }
+  let artists = database
+    .get_song_artist_names()?
+    .into_iter()
+    .filter(|(id, _)| *id == song_id)
+    .map(|(_, name)| name)
+    .collect::<Vec<_>>();
+  let track = ImportTrack { title: song.title.clone(), artists: artists.clone(), ..Default::default() };
+
+  let query = search_query(&track);
+  context.log(format!("searching for \"{query}\""));
+  let output = YoutubeDl::search_for(&SearchOptions::youtube(query).with_count(CANDIDATES)).run_async().await?;
+  let candidates = output
+    .into_playlist()
+    .and_then(|playlist| playlist.entries)
+    .ok_or_else(|| eyre!("youtube search did not return a playlist"))?
+    .into_iter()
+    .map(Candidate::from)
+    .collect();
+  let (candidate, score) =
+    best_instrumental(&track, candidates).ok_or_else(|| eyre!("no instrumental found for {}", song.title))?;
+  context.log(format!("found {} ({score}%)", candidate.title));
+
+  action_tx.send(Action::DownloadEnqueue(vec![DownloadRequest {
+    youtube_id: candidate.youtube_id,
+    title: format!("{} (Instrumental)", song.title),
+    artists,
+    instrumental_of: Some(song_id),
+    ..Default::default()
+  }]))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn candidate(title: &str) -> Candidate {
+    Candidate {
+      youtube_id: title.to_string(),
+      title: title.to_string(),
+      channel: Some("Hoshimachi Suisei".to_string()),
+      duration: None,
+    }
+  }
+
+  #[test]
+  fn test_best_instrumental() {
+    assert!(is_instrumental("Stellar Stellar (Off Vocal)"));
+    assert!(is_instrumental("【カラオケ】Stellar Stellar"));
+    assert!(!is_instrumental("Stellar Stellar / THE FIRST TAKE"));
+    assert!(!is_instrumental("Instrumentality"));
+
+    let track = ImportTrack {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(search_query(&track), "Hoshimachi Suisei - Stellar Stellar instrumental");
+    let best = best_instrumental(&track, vec![
+      candidate("Stellar Stellar"),
+      candidate("Bluerose (Instrumental)"),
+      candidate("Stellar Stellar (Instrumental)"),
+    ]);
+    assert_eq!(best.map(|(candidate, _)| candidate.title), Some("Stellar Stellar (Instrumental)".to_string()));
+    assert_eq!(best_instrumental(&track, vec![candidate("Stellar Stellar")]), None);
+  }
+}
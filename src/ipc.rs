@@ -0,0 +1,388 @@
+//! Control of `muzik daemon` over a unix socket, or over TCP from other machines
+//!
+//! Clients send one JSON request per line and read one JSON response per line, so the socket can
+//! also be driven by hand with tools such as `socat`. Clients connecting over TCP may have to
+//! authenticate first, see [`crate::auth`], and connect over TLS unless the daemon disables it,
+//! see [`crate::tls`].
+
+use std::{future::Future, path::PathBuf, sync::Arc};
+
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
+  net::{TcpListener, TcpStream, UnixListener, UnixStream},
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error};
+
+use crate::{
+  auth::{AccessControl, Client, Scope},
+  config::{Config, DuplicatePolicy},
+  listing::SongRow,
+  query::Query,
+  queue::{DownloadRequest, QueueItem, Verdict},
+  tls,
+};
+
+type Reader = Box<dyn AsyncRead + Send + Unpin>;
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcRequest {
+  /// Authenticate the connection with a token
+  Authenticate { token: String },
+  /// Add songs to the download queue
+  Enqueue { requests: Vec<DownloadRequest> },
+  /// Get the items of the download queue
+  Queue,
+  /// Tell what to do with a queued song that is in the library already
+  Resolve { id: u64, policy: DuplicatePolicy },
+  /// Approve, correct or reject a queued song whose metadata was guessed
+  Review { id: u64, verdict: Verdict },
+  /// Get a page of the songs in the library, ordered by id
+  Songs { query: Option<Query>, limit: i64, offset: i64 },
+  /// Regenerate the auto-playlists
+  ExportPlaylists,
+  /// Stop the daemon
+  Shutdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcResponse {
+  Ok,
+  Queue { items: Vec<QueueItem> },
+  Songs { songs: Vec<SongRow> },
+  Error { message: String },
+}
+
+impl IpcRequest {
+  /// The scope needed to make the request, `None` if it can always be made
+  fn scope(&self) -> Option<Scope> {
+    match self {
+      IpcRequest::Authenticate { .. } => None,
+      IpcRequest::Queue | IpcRequest::Songs { .. } => Some(Scope::ReadOnly),
+      IpcRequest::Enqueue { .. } | IpcRequest::Resolve { .. } | IpcRequest::Review { .. } => {
+        Some(Scope::QueueDownloads)
+      },
+      IpcRequest::ExportPlaylists | IpcRequest::Shutdown => Some(Scope::Admin),
+    }
+  }
+}
+
+/// The socket the daemon listens on
+pub fn socket_path(config: &Config) -> PathBuf {
+  config.config.daemon.socket.clone().unwrap_or_else(|| config.paths.data_dir.join("daemon.sock"))
+}
+
+/// A daemon on another machine
+#[derive(Debug, Clone, Default)]
+pub struct Remote {
+  /// Where the daemon listens, such as `desktop:7700`
+  pub address: String,
+  /// Authenticates the connection, if the daemon requires it
+  pub token: Option<String>,
+  /// The certificate of the daemon, to connect over TLS. The connection is not encrypted if unset
+  pub certificate: Option<PathBuf>,
+}
+
+/// A connection to a running daemon
+pub struct IpcClient {
+  lines: Lines<BufReader<Reader>>,
+  writer: Writer,
+}
+
+impl IpcClient {
+  fn new(reader: Reader, writer: Writer) -> Self {
+    Self { lines: BufReader::new(reader).lines(), writer }
+  }
+
+  /// Connect to the daemon listening on the socket
+  ///
+  /// # Returns
+  ///
+  /// * the client, or `None` if no daemon is running
+  pub async fn connect(path: &PathBuf) -> Result<Option<Self>> {
+    match UnixStream::connect(path).await {
+      Ok(stream) => {
+        let (reader, writer) = stream.into_split();
+        Ok(Some(Self::new(Box::new(reader), Box::new(writer))))
+      },
+      Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused) => Ok(None),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  /// Connect to a daemon on another machine
+  pub async fn connect_remote(remote: &Remote) -> Result<Self> {
+    let stream = TcpStream::connect(&remote.address).await?;
+    let mut client = match &remote.certificate {
+      Some(certificate) => {
+        let stream = tls::connector(certificate)?
+          .connect(tls::server_name(&remote.address), stream)
+          .await
+          .map_err(|e| eyre!("failed to connect to {} over TLS: {e}", remote.address))?;
+        let (reader, writer) = tokio::io::split(stream);
+        Self::new(Box::new(reader), Box::new(writer))
+      },
+      None => {
+        let (reader, writer) = stream.into_split();
+        Self::new(Box::new(reader), Box::new(writer))
+      },
+    };
+    if let Some(token) = &remote.token {
+      match client.request(&IpcRequest::Authenticate { token: token.clone() }).await? {
+        IpcResponse::Ok => {},
+        IpcResponse::Error { message } => return Err(eyre!("the daemon refused the token: {message}")),
+        response => return Err(eyre!("unexpected response from the daemon: {response:?}")),
+      }
+    }
+    Ok(client)
+  }
+
+  pub async fn request(&mut self, request: &IpcRequest) -> Result<IpcResponse> {
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    self.writer.write_all(line.as_bytes()).await?;
+    let response = self.lines.next_line().await?.ok_or_else(|| eyre!("the daemon closed the connection"))?;
+    Ok(serde_json::from_str(&response)?)
+  }
+}
+
+/// Where the daemon accepts clients
+pub enum IpcListener {
+  Unix(UnixListener),
+  /// Accepts clients over TLS if given an acceptor
+  Tcp(TcpListener, Option<TlsAcceptor>),
+}
+
+/// A client accepted by a listener
+enum Connection {
+  Unix(UnixStream),
+  Tcp(TcpStream),
+  Tls(TcpStream, TlsAcceptor),
+}
+
+impl IpcListener {
+  /// Accept a client, along with its rights before it authenticates
+  async fn accept(&self, access: &AccessControl) -> Result<(Connection, Client)> {
+    Ok(match self {
+      IpcListener::Unix(listener) => (Connection::Unix(listener.accept().await?.0), Client::Trusted),
+      IpcListener::Tcp(listener, acceptor) => {
+        let (stream, address) = listener.accept().await?;
+        debug!("remote client connected from {address}");
+        let connection = match acceptor {
+          Some(acceptor) => Connection::Tls(stream, acceptor.clone()),
+          None => Connection::Tcp(stream),
+        };
+        (connection, access.remote_client(address.ip()))
+      },
+    })
+  }
+}
+
+impl Connection {
+  /// The halves of the connection, after the TLS handshake if any
+  async fn into_split(self) -> Result<(Reader, Writer)> {
+    Ok(match self {
+      Connection::Unix(stream) => {
+        let (reader, writer) = stream.into_split();
+        (Box::new(reader), Box::new(writer))
+      },
+      Connection::Tcp(stream) => {
+        let (reader, writer) = stream.into_split();
+        (Box::new(reader), Box::new(writer))
+      },
+      Connection::Tls(stream, acceptor) => {
+        let (reader, writer) = tokio::io::split(acceptor.accept(stream).await?);
+        (Box::new(reader), Box::new(writer))
+      },
+    })
+  }
+}
+
+/// Answer the requests of every client connecting to the listener until cancelled
+///
+/// # Arguments
+///
+/// * `listener` - the socket clients connect to
+/// * `handler` - answers a request
+/// * `access` - what the clients connecting over TCP may do
+/// * `cancellation_token` - stops accepting clients when cancelled
+pub async fn serve<H, Fut>(
+  listener: IpcListener,
+  handler: H,
+  access: Arc<AccessControl>,
+  cancellation_token: CancellationToken,
+) -> Result<()>
+where
+  H: Fn(IpcRequest) -> Fut + Clone + Send + Sync + 'static,
+  Fut: Future<Output = IpcResponse> + Send,
+{
+  loop {
+    let (connection, client) = tokio::select! {
+      accepted = listener.accept(&access) => accepted?,
+      _ = cancellation_token.cancelled() => return Ok(()),
+    };
+    let handler = handler.clone();
+    let access = access.clone();
+    // the handshake happens here, so that a slow client does not hold the others up
+    tokio::spawn(async move {
+      let result = match connection.into_split().await {
+        Ok((reader, writer)) => handle_client(reader, writer, client, handler, &access).await,
+        Err(e) => Err(e),
+      };
+      if let Err(e) = result {
+        error!("ipc client failed: {e}");
+      }
+    });
+  }
+}
+
+async fn handle_client<H, Fut>(
+  reader: Reader,
+  mut writer: Writer,
+  mut client: Client,
+  handler: H,
+  access: &AccessControl,
+) -> Result<()>
+where
+  H: Fn(IpcRequest) -> Fut,
+  Fut: Future<Output = IpcResponse>,
+{
+  let mut lines = BufReader::new(reader).lines();
+  while let Some(line) = lines.next_line().await? {
+    let response = match serde_json::from_str::<IpcRequest>(&line) {
+      Ok(IpcRequest::Authenticate { token }) => {
+        match access.authenticate(&token) {
+          Ok(authenticated) => {
+            client = authenticated;
+            IpcResponse::Ok
+          },
+          Err(denied) => IpcResponse::Error { message: denied.to_string() },
+        }
+      },
+      Ok(request) => {
+        debug!("ipc request: {line}");
+        match request.scope().map(|scope| access.authorize(&client, scope)) {
+          Some(Err(denied)) => IpcResponse::Error { message: denied.to_string() },
+          _ => handler(request).await,
+        }
+      },
+      Err(e) => IpcResponse::Error { message: format!("invalid request: {e}") },
+    };
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    config::{TlsConfig, TokenConfig},
+    tls::tests::generate_certificate,
+  };
+
+  #[tokio::test]
+  async fn test_ipc_round_trip() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-{}.sock", uuid::Uuid::new_v4()));
+    let listener = IpcListener::Unix(UnixListener::bind(&path)?);
+    let cancellation_token = CancellationToken::new();
+    tokio::spawn(serve(
+      listener,
+      |request| {
+        async move {
+          match request {
+            IpcRequest::Queue => IpcResponse::Queue { items: vec![] },
+            _ => IpcResponse::Ok,
+          }
+        }
+      },
+      Arc::default(),
+      cancellation_token.clone(),
+    ));
+
+    let mut client = IpcClient::connect(&path).await?.expect("the server is listening");
+    assert_eq!(client.request(&IpcRequest::Queue).await?, IpcResponse::Queue { items: vec![] });
+    assert_eq!(client.request(&IpcRequest::Enqueue { requests: vec![] }).await?, IpcResponse::Ok);
+    assert_eq!(serde_json::to_string(&IpcRequest::ExportPlaylists)?, r#"{"type":"export_playlists"}"#);
+
+    cancellation_token.cancel();
+    std::fs::remove_file(&path)?;
+    assert!(IpcClient::connect(&path).await?.is_none());
+    Ok(())
+  }
+
+  /// Answers the songs requests matching a query with one song, whose id is the limit
+  async fn answer_songs(request: IpcRequest) -> IpcResponse {
+    match request {
+      IpcRequest::Songs { query: Some(_), limit, .. } => {
+        IpcResponse::Songs { songs: vec![SongRow { id: limit as i32, ..Default::default() }] }
+      },
+      _ => IpcResponse::Ok,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_ipc_remote() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    let cancellation_token = CancellationToken::new();
+    let token = TokenConfig { token: "secret".to_string(), scope: Scope::ReadOnly, requests_per_minute: None };
+    tokio::spawn(serve(
+      IpcListener::Tcp(listener, None),
+      answer_songs,
+      Arc::new(AccessControl::new(vec![token])),
+      cancellation_token.clone(),
+    ));
+
+    let request = IpcRequest::Songs { query: Some(Query::parse("artist:Suisei")?), limit: 3, offset: 0 };
+    let remote =
+      |token: Option<&str>| Remote { address: address.clone(), token: token.map(str::to_string), certificate: None };
+    let mut unauthenticated = IpcClient::connect_remote(&remote(None)).await?;
+    assert!(matches!(unauthenticated.request(&request).await?, IpcResponse::Error { .. }));
+    assert!(IpcClient::connect_remote(&remote(Some("guess"))).await.is_err());
+
+    let mut client = IpcClient::connect_remote(&remote(Some("secret"))).await?;
+    assert_eq!(client.request(&request).await?, IpcResponse::Songs {
+      songs: vec![SongRow { id: 3, ..Default::default() }]
+    });
+    assert!(matches!(client.request(&IpcRequest::Shutdown).await?, IpcResponse::Error { .. }));
+    cancellation_token.cancel();
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_ipc_remote_tls() -> Result<()> {
+    let (certificate, key) = generate_certificate()?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    let cancellation_token = CancellationToken::new();
+    let acceptor = tls::acceptor(&TlsConfig::default(), &certificate, &key)?;
+    tokio::spawn(serve(IpcListener::Tcp(listener, acceptor), answer_songs, Arc::default(), cancellation_token.clone()));
+
+    let request = IpcRequest::Songs { query: Some(Query::parse("artist:Suisei")?), limit: 3, offset: 0 };
+    let remote = Remote { address: address.clone(), certificate: Some(certificate), ..Default::default() };
+    let mut client = IpcClient::connect_remote(&remote).await?;
+    assert_eq!(client.request(&request).await?, IpcResponse::Songs {
+      songs: vec![SongRow { id: 3, ..Default::default() }]
+    });
+
+    // a daemon presenting another certificate is not trusted
+    let (other, _) = generate_certificate()?;
+    assert!(IpcClient::connect_remote(&Remote { certificate: Some(other), ..remote }).await.is_err());
+    // nor is a client that does not speak TLS answered
+    let mut plaintext = IpcClient::connect_remote(&Remote { address, ..Default::default() }).await?;
+    assert!(plaintext.request(&request).await.is_err());
+    cancellation_token.cancel();
+    Ok(())
+  }
+}
@@ -0,0 +1,561 @@
+//! Registry of background jobs
+//!
+//! Long running work (scans, verifications, enrichment, syncs, imports) is spawned through the
+//! `JobRegistry`, which tracks the state, progress and logs of every job and allows them to be
+//! cancelled or retried.
+//!
+//! Every kind of job has a priority, so that heavy work leaves the machine to the TUI on
+//! single-board computers and phones. Background jobs run up to `jobs.background_limit` at once
+//! and interactive jobs up to `jobs.interactive_limit`, the others waiting queued, while the jobs
+//! the user waits on run right away. Background jobs also pause between their steps while keys are
+//! pressed in the TUI, until it is left alone for `jobs.idle_ms`.
+
+use std::{
+  fmt,
+  future::Future,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{eyre, Result};
+use futures::future::BoxFuture;
+use strum::Display;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error};
+
+use crate::config::JobsConfig;
+
+pub type JobId = usize;
+
+type JobFn = Arc<dyn Fn(JobContext) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Display)]
+pub enum JobKind {
+  #[default]
+  Scan,
+  Verify,
+  Enrich,
+  Sync,
+  Import,
+  Edit,
+}
+
+impl JobKind {
+  /// How the jobs of the kind share the machine with the TUI, unless they are spawned with
+  /// another priority
+  pub fn priority(self) -> JobPriority {
+    match self {
+      JobKind::Edit | JobKind::Import => JobPriority::Interactive,
+      JobKind::Scan | JobKind::Verify | JobKind::Enrich | JobKind::Sync => JobPriority::Background,
+    }
+  }
+}
+
+/// How a job shares the machine with the TUI
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Display)]
+pub enum JobPriority {
+  /// Runs right away, as a screen of the TUI waits on it
+  UiCritical,
+  /// Runs up to `jobs.interactive_limit` at once
+  Interactive,
+  /// Runs up to `jobs.background_limit` at once, and pauses between steps while the TUI is used
+  #[default]
+  Background,
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub enum JobState {
+  #[default]
+  Queued,
+  Running,
+  Finished,
+  Failed(String),
+  Cancelled,
+}
+
+impl fmt::Display for JobState {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      JobState::Queued => write!(f, "Queued"),
+      JobState::Running => write!(f, "Running"),
+      JobState::Finished => write!(f, "Finished"),
+      JobState::Failed(error) => write!(f, "Failed: {error}"),
+      JobState::Cancelled => write!(f, "Cancelled"),
+    }
+  }
+}
+
+impl JobState {
+  /// Whether the job has stopped running, successfully or not
+  pub fn is_done(&self) -> bool {
+    matches!(self, JobState::Finished | JobState::Failed(_) | JobState::Cancelled)
+  }
+}
+
+/// A snapshot of the state of a job
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct JobInfo {
+  pub id: JobId,
+  pub kind: JobKind,
+  pub priority: JobPriority,
+  pub name: String,
+  pub state: JobState,
+  /// the amount of work done out of the total
+  pub progress: Option<(u64, u64)>,
+  pub started_at: Option<DateTime<Local>>,
+  pub logs: Vec<String>,
+}
+
+impl JobInfo {
+  /// The progress of the job in percent
+  pub fn percent(&self) -> Option<u16> {
+    self.progress.map(|(done, total)| (done.min(total) * 100).checked_div(total).unwrap_or(100) as u16)
+  }
+}
+
+struct JobEntry {
+  info: JobInfo,
+  cancellation_token: CancellationToken,
+  task: JobFn,
+  /// the version of the registry when the job last stopped, to drop the jobs stopped first
+  stopped_at: u64,
+}
+
+#[derive(Default)]
+struct RegistryInner {
+  jobs: Vec<JobEntry>,
+  /// the id of the next job spawned
+  next_id: JobId,
+  /// the stopped jobs kept, see [`RegistryInner::prune`]
+  keep_finished: usize,
+  /// incremented on every change so that watchers know when to refresh
+  version: u64,
+  /// messages about stopped jobs, waiting to be shown to the user
+  notifications: Vec<String>,
+}
+
+impl RegistryInner {
+  fn entry_mut(&mut self, id: JobId) -> Option<&mut JobEntry> {
+    self.jobs.iter_mut().find(|entry| entry.info.id == id)
+  }
+
+  /// Drop the jobs stopped first past the stopped jobs kept, so that a long session or daemon
+  /// does not hold on to every job it ever ran
+  fn prune(&mut self) {
+    let mut stopped: Vec<_> =
+      self.jobs.iter().filter(|entry| entry.info.state.is_done()).map(|entry| entry.stopped_at).collect();
+    if stopped.len() <= self.keep_finished {
+      return;
+    }
+    stopped.sort_unstable();
+    let last_dropped = stopped[stopped.len() - self.keep_finished - 1];
+    self.jobs.retain(|entry| !entry.info.state.is_done() || entry.stopped_at > last_dropped);
+  }
+}
+
+/// The jobs allowed to run at once, and when the TUI was last used
+struct Limits {
+  interactive: Arc<Semaphore>,
+  background: Arc<Semaphore>,
+  idle: Duration,
+  last_input: Mutex<Option<Instant>>,
+}
+
+impl Limits {
+  fn new(config: &JobsConfig) -> Self {
+    Self {
+      interactive: Arc::new(Semaphore::new(config.interactive_limit.max(1))),
+      background: Arc::new(Semaphore::new(config.background_limit.max(1))),
+      idle: Duration::from_millis(config.idle_ms),
+      last_input: Mutex::new(None),
+    }
+  }
+
+  /// Wait for the job to be allowed to run. The permit is held while it runs
+  async fn acquire(&self, priority: JobPriority) -> Option<OwnedSemaphorePermit> {
+    let semaphore = match priority {
+      JobPriority::UiCritical => return None,
+      JobPriority::Interactive => &self.interactive,
+      JobPriority::Background => &self.background,
+    };
+    semaphore.clone().acquire_owned().await.ok()
+  }
+
+  /// How long until the TUI is left alone for long enough, if it is used
+  fn busy_for(&self) -> Option<Duration> {
+    let last_input = *self.last_input.lock().expect("job limits lock is never poisoned");
+    last_input.map(|last_input| self.idle.saturating_sub(last_input.elapsed())).filter(|wait| !wait.is_zero())
+  }
+}
+
+/// Handle to the job registry. Cloning the handle gives access to the same registry
+#[derive(Clone)]
+pub struct JobRegistry {
+  inner: Arc<Mutex<RegistryInner>>,
+  limits: Arc<Limits>,
+}
+
+impl Default for JobRegistry {
+  fn default() -> Self {
+    Self::with_config(&JobsConfig::default())
+  }
+}
+
+impl JobRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// A registry running as many jobs at once as the settings allow
+  pub fn with_config(config: &JobsConfig) -> Self {
+    let inner = RegistryInner { keep_finished: config.keep_finished, ..Default::default() };
+    Self { inner: Arc::new(Mutex::new(inner)), limits: Arc::new(Limits::new(config)) }
+  }
+
+  /// Tell the registry that the TUI is being used, pausing the background jobs for a while
+  pub fn user_active(&self) {
+    *self.limits.last_input.lock().expect("job limits lock is never poisoned") = Some(Instant::now());
+  }
+
+  /// Spawn a new job on the tokio runtime
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of the job
+  /// * `name` - a short description of the job
+  /// * `task` - builds the future doing the work. It is called again when the job is retried
+  ///
+  /// # Returns
+  ///
+  /// * the id of the new job
+  pub fn spawn<F, Fut>(&self, kind: JobKind, name: impl Into<String>, task: F) -> JobId
+  where
+    F: Fn(JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+  {
+    self.spawn_with_priority(kind, kind.priority(), name, task)
+  }
+
+  /// Spawn a new job with another priority than that of its kind, see [`JobRegistry::spawn`]
+  pub fn spawn_with_priority<F, Fut>(
+    &self,
+    kind: JobKind,
+    priority: JobPriority,
+    name: impl Into<String>,
+    task: F,
+  ) -> JobId
+  where
+    F: Fn(JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+  {
+    let task: JobFn = Arc::new(move |context| Box::pin(task(context)));
+    let id = self.with_inner(|inner| {
+      let id = inner.next_id;
+      inner.next_id += 1;
+      let info = JobInfo { id, kind, priority, name: name.into(), ..Default::default() };
+      inner.jobs.push(JobEntry { info, cancellation_token: CancellationToken::new(), task, stopped_at: 0 });
+      inner.version += 1;
+      id
+    });
+    self.start(id);
+    id
+  }
+
+  /// Queue the job, then run it once its priority allows
+  fn start(&self, id: JobId) {
+    let cancellation_token = CancellationToken::new();
+    let Some((task, priority)) = self.with_inner(|inner| {
+      let entry = inner.entry_mut(id)?;
+      entry.info.state = JobState::Queued;
+      entry.info.progress = None;
+      entry.cancellation_token = cancellation_token.clone();
+      let started = (entry.task.clone(), entry.info.priority);
+      inner.version += 1;
+      Some(started)
+    }) else {
+      return;
+    };
+    let context = JobContext { id, priority, registry: self.clone(), cancellation_token: cancellation_token.clone() };
+    let registry = self.clone();
+    tokio::spawn(async move {
+      let run = async {
+        let _permit = registry.limits.acquire(priority).await;
+        registry.with_job(id, |info| {
+          info.state = JobState::Running;
+          info.started_at = Some(Local::now());
+        });
+        task(context).await
+      };
+      let state = tokio::select! {
+        _ = cancellation_token.cancelled() => JobState::Cancelled,
+        result = run => match result {
+          Ok(()) => JobState::Finished,
+          Err(e) => {
+            error!("job {id} failed: {e}");
+            JobState::Failed(e.to_string())
+          },
+        },
+      };
+      debug!("job {id} stopped: {state}");
+      registry.with_inner(|inner| {
+        let version = inner.version;
+        if let Some(entry) = inner.entry_mut(id) {
+          let notification = format!("{} job \"{}\": {state}", entry.info.kind, entry.info.name);
+          entry.info.state = state;
+          entry.stopped_at = version;
+          inner.notifications.push(notification);
+          inner.prune();
+          inner.version += 1;
+        }
+      });
+    });
+  }
+
+  /// Cancel a running job
+  pub fn cancel(&self, id: JobId) -> Result<()> {
+    let cancellation_token = self.with_inner(|inner| {
+      let entry = inner.entry_mut(id).ok_or_else(|| eyre!("job {id} does not exist"))?;
+      if entry.info.state.is_done() {
+        return Err(eyre!("job {id} is not running"));
+      }
+      Ok(entry.cancellation_token.clone())
+    })?;
+    cancellation_token.cancel();
+    Ok(())
+  }
+
+  /// Run a failed or cancelled job again
+  pub fn retry(&self, id: JobId) -> Result<()> {
+    self.with_inner(|inner| {
+      let entry = inner.entry_mut(id).ok_or_else(|| eyre!("job {id} does not exist"))?;
+      match entry.info.state {
+        JobState::Failed(_) | JobState::Cancelled => Ok(()),
+        _ => Err(eyre!("only failed or cancelled jobs can be retried")),
+      }
+    })?;
+    self.with_job(id, |info| info.logs.push("retrying".to_string()));
+    self.start(id);
+    Ok(())
+  }
+
+  /// Get a snapshot of the running jobs and the last stopped ones, in the order they were spawned
+  pub fn snapshot(&self) -> Vec<JobInfo> {
+    self.with_inner(|inner| inner.jobs.iter().map(|entry| entry.info.clone()).collect())
+  }
+
+  /// Take the notifications queued since the last call. Notifications are queued whenever a job
+  /// stops, whether or not anything is listening, so none are lost while the UI is suspended
+  pub fn take_notifications(&self) -> Vec<String> {
+    self.with_inner(|inner| std::mem::take(&mut inner.notifications))
+  }
+
+  /// The current version of the registry. Changes whenever any job is updated
+  pub fn version(&self) -> u64 {
+    self.with_inner(|inner| inner.version)
+  }
+
+  fn with_job(&self, id: JobId, f: impl FnOnce(&mut JobInfo)) {
+    self.with_inner(|inner| {
+      if let Some(entry) = inner.entry_mut(id) {
+        f(&mut entry.info);
+        inner.version += 1;
+      }
+    });
+  }
+
+  fn with_inner<T>(&self, f: impl FnOnce(&mut RegistryInner) -> T) -> T {
+    let mut inner = self.inner.lock().expect("job registry lock is never poisoned");
+    f(&mut inner)
+  }
+}
+
+/// Given to every job to report its progress and logs
+#[derive(Clone)]
+pub struct JobContext {
+  id: JobId,
+  priority: JobPriority,
+  registry: JobRegistry,
+  cancellation_token: CancellationToken,
+}
+
+impl JobContext {
+  pub fn id(&self) -> JobId {
+    self.id
+  }
+
+  /// Report the amount of work done out of the total
+  pub fn progress(&self, done: u64, total: u64) {
+    self.registry.with_job(self.id, |info| info.progress = Some((done, total)));
+  }
+
+  /// Append a line to the logs of the job
+  pub fn log(&self, message: impl Into<String>) {
+    let message = message.into();
+    debug!("job {}: {message}", self.id);
+    self.registry.with_job(self.id, |info| info.logs.push(message));
+  }
+
+  /// Whether the job has been cancelled. Jobs doing blocking work should check this regularly
+  pub fn is_cancelled(&self) -> bool {
+    self.cancellation_token.is_cancelled()
+  }
+
+  /// Wait while the TUI is used, if the job runs in the background. Heavy jobs call this between
+  /// their steps
+  pub async fn pace(&self) {
+    if self.priority != JobPriority::Background {
+      return;
+    }
+    while let Some(wait) = self.registry.limits.busy_for() {
+      if self.is_cancelled() {
+        return;
+      }
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+  };
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn job(registry: &JobRegistry, id: JobId) -> JobInfo {
+    registry.snapshot().into_iter().find(|job| job.id == id).expect("the job is listed")
+  }
+
+  async fn wait_until_done(registry: &JobRegistry, id: JobId) -> JobInfo {
+    loop {
+      let info = job(registry, id);
+      if info.state.is_done() {
+        return info;
+      }
+      tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+  }
+
+  #[tokio::test]
+  async fn test_job_progress_and_logs() {
+    let registry = JobRegistry::new();
+    let id = registry.spawn(JobKind::Scan, "scan music dir", |context| {
+      async move {
+        context.log("scanning");
+        context.progress(1, 2);
+        Ok(())
+      }
+    });
+    let info = wait_until_done(&registry, id).await;
+    assert_eq!(info.state, JobState::Finished);
+    assert_eq!(info.percent(), Some(50));
+    assert_eq!(info.logs, vec!["scanning".to_string()]);
+    assert!(info.started_at.is_some());
+    assert_eq!(registry.take_notifications(), vec!["Scan job \"scan music dir\": Finished".to_string()]);
+    assert!(registry.take_notifications().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_job_cancel() -> Result<()> {
+    let registry = JobRegistry::new();
+    let id = registry.spawn(JobKind::Sync, "never ends", |_| std::future::pending());
+    registry.cancel(id)?;
+    assert_eq!(wait_until_done(&registry, id).await.state, JobState::Cancelled);
+    assert!(registry.cancel(id).is_err());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_job_retry() -> Result<()> {
+    let registry = JobRegistry::new();
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let id = registry.spawn(JobKind::Verify, "fails once", move |_| {
+      let attempt = counter.fetch_add(1, Ordering::SeqCst);
+      async move {
+        if attempt == 0 {
+          Err(eyre!("first attempt"))
+        } else {
+          Ok(())
+        }
+      }
+    });
+    let state = wait_until_done(&registry, id).await.state;
+    assert_eq!(state, JobState::Failed("first attempt".to_string()));
+    assert_eq!(state.to_string(), "Failed: first attempt");
+
+    registry.retry(id)?;
+    assert_eq!(wait_until_done(&registry, id).await.state, JobState::Finished);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert!(registry.retry(id).is_err());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_job_priorities() -> Result<()> {
+    let registry = JobRegistry::with_config(&JobsConfig {
+      background_limit: 1,
+      interactive_limit: 1,
+      idle_ms: 60_000,
+      ..Default::default()
+    });
+    let first = registry.spawn(JobKind::Scan, "never ends", |_| std::future::pending());
+    let second = registry.spawn(JobKind::Sync, "waits for the scan", |_| async { Ok(()) });
+    let critical =
+      registry.spawn_with_priority(JobKind::Verify, JobPriority::UiCritical, "runs right away", |_| async { Ok(()) });
+    assert_eq!(wait_until_done(&registry, critical).await.state, JobState::Finished);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(job(&registry, second).state, JobState::Queued);
+    assert_eq!(job(&registry, second).started_at, None);
+
+    // the queued job runs once the other leaves its place
+    registry.cancel(first)?;
+    assert_eq!(wait_until_done(&registry, second).await.state, JobState::Finished);
+
+    // background jobs wait for the TUI to be left alone, the others do not
+    registry.user_active();
+    let paced = registry.spawn(JobKind::Enrich, "paced", |context| {
+      async move {
+        context.pace().await;
+        Ok(())
+      }
+    });
+    let edit = registry.spawn(JobKind::Edit, "not paced", |context| {
+      async move {
+        context.pace().await;
+        Ok(())
+      }
+    });
+    assert_eq!(wait_until_done(&registry, edit).await.state, JobState::Finished);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(job(&registry, paced).state, JobState::Running);
+    registry.cancel(paced)?;
+    assert_eq!(wait_until_done(&registry, paced).await.state, JobState::Cancelled);
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_job_retention() -> Result<()> {
+    let registry = JobRegistry::with_config(&JobsConfig { keep_finished: 2, ..Default::default() });
+    let running = registry.spawn(JobKind::Sync, "never ends", |_| std::future::pending());
+    for name in ["first", "second", "third"] {
+      let id = registry.spawn(JobKind::Verify, name, |_| async { Ok(()) });
+      wait_until_done(&registry, id).await;
+    }
+    let names: Vec<_> = registry.snapshot().into_iter().map(|job| job.name).collect();
+    assert_eq!(names, vec!["never ends", "second", "third"]);
+    assert!(registry.retry(1).is_err());
+
+    registry.cancel(running)?;
+    wait_until_done(&registry, running).await;
+    let names: Vec<_> = registry.snapshot().into_iter().map(|job| job.name).collect();
+    assert_eq!(names, vec!["never ends", "third"]);
+    Ok(())
+  }
+}
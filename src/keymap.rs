@@ -0,0 +1,241 @@
+//! Rebinding keys from the help overlay
+//!
+//! The help overlay lists the key bindings of the current mode along with the global ones, and an
+//! action picked there can be bound to other keys. The keys are refused when they are bound to
+//! another action of the mode or of the global bindings, or when they start or continue another
+//! key sequence, as either would never be reached. Rebinds are saved into `rebinds.json` next to
+//! the config and applied over the config when it is loaded, as rewriting the config itself would
+//! lose its comments and layout.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+use crossterm::event::KeyEvent;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+  action::Action,
+  config::{key_event_to_string, parse_key_sequence, KeyBindings},
+  mode::Mode,
+};
+
+/// The file of the rebinds, in the config dir
+pub const REBINDS_FILE: &str = "rebinds.json";
+
+/// A key sequence bound to an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+  pub mode: Mode,
+  pub keys: Vec<KeyEvent>,
+  pub action: Action,
+}
+
+/// An action bound to other keys from the help overlay, as saved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rebind {
+  pub mode: Mode,
+  pub action: Action,
+  /// the keys as written in the config, such as `<g><b>`
+  pub keys: String,
+}
+
+/// Write the keys as in the config, such as `<g><Shift-t>`
+pub fn keys_to_string(keys: &[KeyEvent]) -> String {
+  keys.iter().map(|key| format!("<{}>", key_event_to_string(key))).collect()
+}
+
+/// The bindings of the mode followed by the global ones, each ordered by action then by keys
+pub fn bindings(keybindings: &KeyBindings, mode: Mode) -> Vec<Binding> {
+  let mut modes = vec![mode];
+  if mode != Mode::Global {
+    modes.push(Mode::Global);
+  }
+  modes
+    .into_iter()
+    .flat_map(|mode| {
+      let mut bindings: Vec<_> = keybindings
+        .get(&mode)
+        .into_iter()
+        .flatten()
+        .map(|(keys, action)| Binding { mode, keys: keys.clone(), action: action.clone() })
+        .collect();
+      bindings.sort_by_cached_key(|binding| (binding.action.to_string(), keys_to_string(&binding.keys)));
+      bindings
+    })
+    .collect()
+}
+
+/// The action the keys would conflict with in the mode: one bound to the same keys in the mode, or
+/// to a sequence that the keys start or continue. Global keys are pressed on their own in every
+/// mode, so they also conflict with any sequence they are part of
+pub fn conflict(keybindings: &KeyBindings, mode: Mode, keys: &[KeyEvent]) -> Option<Action> {
+  let overlaps = |bound: &[KeyEvent]| bound.starts_with(keys) || keys.starts_with(bound);
+  let bound_in = |mode| keybindings.get(&mode).into_iter().flatten();
+  if let Some((_, action)) = bound_in(mode).find(|(bound, _)| overlaps(bound)) {
+    return Some(action.clone());
+  }
+  match mode {
+    Mode::Global => {
+      keybindings
+        .values()
+        .flatten()
+        .find(|(bound, _)| bound.iter().any(|key| keys.contains(key)))
+        .map(|(_, action)| action.clone())
+    },
+    _ => {
+      bound_in(Mode::Global)
+        .find(|(bound, _)| keys.iter().any(|key| bound[..] == [*key]))
+        .map(|(_, action)| action.clone())
+    },
+  }
+}
+
+/// Bind the action to the keys in the mode, in place of the keys it was bound to
+pub fn rebind(keybindings: &mut KeyBindings, mode: Mode, action: &Action, keys: &[KeyEvent]) -> Result<()> {
+  if keys.is_empty() {
+    return Err(eyre!("no keys to bind {action} to"));
+  }
+  if mode == Mode::Global && keys.len() > 1 {
+    return Err(eyre!("global actions are bound to a single key"));
+  }
+  if parse_key_sequence(&keys_to_string(keys)).as_deref() != Ok(keys) {
+    return Err(eyre!("{} can not be written in the config", keys_to_string(keys)));
+  }
+  let mut others = keybindings.clone();
+  if let Some(bindings) = others.get_mut(&mode) {
+    bindings.retain(|_, bound| bound != action);
+  }
+  if let Some(other) = conflict(&others, mode, keys) {
+    return Err(eyre!("{} is taken by {other}", keys_to_string(keys)));
+  }
+  others.entry(mode).or_default().insert(keys.to_vec(), action.clone());
+  *keybindings = others;
+  Ok(())
+}
+
+/// Read the saved rebinds
+pub fn load_rebinds(config_dir: &Path) -> Result<Vec<Rebind>> {
+  match std::fs::read_to_string(config_dir.join(REBINDS_FILE)) {
+    Ok(json) => Ok(serde_json::from_str(&json)?),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Save the rebind, replacing an earlier rebind of the action in the mode
+pub fn save_rebind(config_dir: &Path, rebind: Rebind) -> Result<()> {
+  let mut rebinds = load_rebinds(config_dir)?;
+  rebinds.retain(|saved| saved.mode != rebind.mode || saved.action != rebind.action);
+  rebinds.push(rebind);
+  std::fs::create_dir_all(config_dir)?;
+  std::fs::write(config_dir.join(REBINDS_FILE), serde_json::to_string_pretty(&rebinds)?)?;
+  Ok(())
+}
+
+/// Apply the saved rebinds over the key bindings of the config. A rebind that conflicts with the
+/// config, as it changed since, is skipped
+pub fn apply_rebinds(keybindings: &mut KeyBindings, rebinds: &[Rebind]) {
+  for saved in rebinds {
+    let applied = parse_key_sequence(&saved.keys)
+      .map_err(|e| eyre!(e))
+      .and_then(|keys| rebind(keybindings, saved.mode, &saved.action, &keys));
+    if let Err(e) = applied {
+      warn!("skipped the rebind of {} in {:?}: {e}", saved.action, saved.mode);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crossterm::event::{KeyCode, KeyModifiers};
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn keys(raw: &str) -> Vec<KeyEvent> {
+    parse_key_sequence(raw).unwrap_or_default()
+  }
+
+  fn keybindings() -> KeyBindings {
+    let mut keybindings = KeyBindings::default();
+    keybindings.insert(Mode::Global, [(keys("<q>"), Action::Quit)].into_iter().collect());
+    keybindings.insert(
+      Mode::Manager,
+      [
+        (keys("<g><b>"), Action::BackupsShow),
+        (keys("<g><t>"), Action::ManagerTabNext),
+        (keys("<g><Shift-t>"), Action::ManagerTabPrevious),
+      ]
+      .into_iter()
+      .collect(),
+    );
+    keybindings
+  }
+
+  #[test]
+  fn test_bindings() {
+    let listed: Vec<_> = bindings(&keybindings(), Mode::Manager)
+      .into_iter()
+      .map(|binding| (binding.mode, keys_to_string(&binding.keys), binding.action))
+      .collect();
+    assert_eq!(listed, vec![
+      (Mode::Manager, "<g><b>".to_string(), Action::BackupsShow),
+      (Mode::Manager, "<g><t>".to_string(), Action::ManagerTabNext),
+      (Mode::Manager, "<g><shift-T>".to_string(), Action::ManagerTabPrevious),
+      (Mode::Global, "<q>".to_string(), Action::Quit),
+    ]);
+  }
+
+  #[test]
+  fn test_rebind() -> Result<()> {
+    let mut keybindings = keybindings();
+    // taken in the mode, globally, or the start of another sequence
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<g><t>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<q>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<g>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<g><t><x>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<x><q>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &[]).is_err());
+    assert!(rebind(&mut keybindings, Mode::Global, &Action::Quit, &keys("<g>")).is_err());
+    assert!(rebind(&mut keybindings, Mode::Global, &Action::Quit, &keys("<x><y>")).is_err());
+    let unwritable = [KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE)];
+    assert!(rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &unwritable).is_err());
+    assert_eq!(keybindings.get(&Mode::Manager).map(|bindings| bindings.len()), Some(3));
+
+    // an action can take its own keys again, and leaves its old keys
+    rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<g><b>"))?;
+    rebind(&mut keybindings, Mode::Manager, &Action::BackupsShow, &keys("<Ctrl-b>"))?;
+    let manager = &keybindings[&Mode::Manager];
+    assert_eq!(manager.get(&keys("<Ctrl-b>")), Some(&Action::BackupsShow));
+    assert_eq!(manager.get(&keys("<g><b>")), None);
+    assert_eq!(manager.len(), 3);
+    rebind(&mut keybindings, Mode::Global, &Action::Quit, &keys("<x>"))?;
+    assert_eq!(keybindings[&Mode::Global].keys().collect::<Vec<_>>(), vec![&keys("<x>")]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_rebinds_file() -> Result<()> {
+    let config_dir = std::env::temp_dir().join(format!("muzik-rebinds-{}", uuid::Uuid::new_v4()));
+    assert_eq!(load_rebinds(&config_dir)?, vec![]);
+    let saved = |action, keys: &str| Rebind { mode: Mode::Manager, action, keys: keys.to_string() };
+    save_rebind(&config_dir, saved(Action::BackupsShow, "<g><x>"))?;
+    save_rebind(&config_dir, saved(Action::ManagerTabNext, "<g><q>"))?;
+    save_rebind(&config_dir, saved(Action::BackupsShow, "<g><y>"))?;
+    let rebinds = load_rebinds(&config_dir)?;
+    assert_eq!(rebinds, vec![saved(Action::ManagerTabNext, "<g><q>"), saved(Action::BackupsShow, "<g><y>")]);
+
+    let mut keybindings = keybindings();
+    // the second conflicts with the first rebind and is skipped
+    apply_rebinds(&mut keybindings, &[
+      saved(Action::BackupsShow, "<g><x>"),
+      saved(Action::ManagerTabPrevious, "<g><x>"),
+    ]);
+    let manager = &keybindings[&Mode::Manager];
+    assert_eq!(manager.get(&keys("<g><x>")), Some(&Action::BackupsShow));
+    assert_eq!(manager.get(&keys("<g><Shift-t>")), Some(&Action::ManagerTabPrevious));
+    std::fs::remove_dir_all(&config_dir)?;
+    Ok(())
+  }
+}
@@ -1,11 +1,15 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use color_eyre::eyre::{eyre, OptionExt, Result};
+#[cfg(feature = "tui")]
 use ratatui::layout::{Constraint, Layout, Rect};
 use strum::Display;
 use tracing::{debug, warn};
 
-use crate::{components::Component, mode::Mode};
+use crate::{
+  mode::Mode,
+  models::{NewPin, Pin, PinKind},
+};
 
 /// Enum of screens or individual elements
 #[derive(Hash, Debug, Eq, PartialEq, Display, Clone)]
@@ -13,8 +17,14 @@ pub enum Scenes {
   Home(HomeLayouts),
   Download(DownloadLayouts),
   Manager(ManagerLayouts),
+  Jobs(JobsLayouts),
+  NowPlaying(NowPlayingLayouts),
   InputBar,
   TitleBar,
+  /// The playing song with its position, above the input bar
+  TransportBar,
+  /// The key bindings, shown over any mode
+  Help,
 }
 
 impl Default for Scenes {
@@ -26,7 +36,9 @@ impl Default for Scenes {
 #[derive(Default, Hash, Debug, Eq, PartialEq, Display, Clone)]
 pub enum HomeLayouts {
   #[default]
-  Intro,
+  Dashboard,
+  /// The failed checks of the setup, over the dashboard
+  Diagnostics,
 }
 
 #[derive(Default, Hash, Debug, Eq, PartialEq, Display, Clone)]
@@ -35,15 +47,57 @@ pub enum DownloadLayouts {
   SearchBar,
   SearchResult,
   SearchResultDetails,
+  Queue,
+  ImportReview,
+  QueueConfirm,
 }
 
 #[derive(Default, Hash, Debug, Eq, PartialEq, Display, Clone)]
 pub enum ManagerLayouts {
   #[default]
   SongList,
+  TabBar,
+  Compare,
+  AlbumGaps,
+  Backups,
+  Snapshots,
+  Playlists,
+  ScanResults,
+  Attachments,
+  Trim,
+  Health,
+  DownloadLogs,
+  DeadLinks,
+  Integrity,
+  Upgrade,
+  Duplicates,
+  Review,
+  ArtistDetails,
+  SongEditor,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Hash, Debug, Eq, PartialEq, Display, Clone)]
+pub enum JobsLayouts {
+  #[default]
+  List,
+  Logs,
+}
+
+#[derive(Default, Hash, Debug, Eq, PartialEq, Display, Clone)]
+pub enum NowPlayingLayouts {
+  #[default]
+  Lyrics,
+}
+
+#[cfg(feature = "tui")]
+/// The screens narrower than this stack their panes, such as a phone held upright
+const PORTRAIT_WIDTH: u16 = 80;
+#[cfg(feature = "tui")]
+/// The transport bar takes a line when muzik is built with the player
+const TRANSPORT_BAR_HEIGHT: u16 = if cfg!(feature = "player") { 1 } else { 0 };
+
+#[cfg(feature = "tui")]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Orientation {
   #[default]
   Landscape,
@@ -55,13 +109,17 @@ pub enum Orientation {
 /// If no other componenet is rendering with the layout then the layout should be returned
 ///
 /// If there is a conflict, log the error and provide the layout anyways
+#[cfg(feature = "tui")]
 #[derive(Default)]
 pub struct LayoutManager {
   layout_store: HashMap<Scenes, Rect>,
   screen: Rect,
   orientation: Orientation,
+  /// Tabs opened in the Manager mode along with their view state
+  pub manager_tabs: ManagerTabs,
 }
 
+#[cfg(feature = "tui")]
 impl LayoutManager {
   pub fn new() -> Self {
     Self::default()
@@ -73,12 +131,16 @@ impl LayoutManager {
   }
 
   pub fn get_component_layout(&self, layout_key: Scenes) -> Result<Rect> {
-    return self.layout_store.get(&layout_key).ok_or_eyre("Layout key {layout_key} does not exists").copied();
+    self.layout_store.get(&layout_key).ok_or_eyre("Layout key {layout_key} does not exists").copied()
   }
 
   /// On terminal resize, update the screen sizing then trigger a layout rebuild
   pub fn update(&mut self, screen: Rect) -> Result<()> {
     self.screen = screen;
+    self.orientation = match screen.width < PORTRAIT_WIDTH {
+      true => Orientation::Portrait,
+      false => Orientation::Landscape,
+    };
     self.build_layouts()?;
     Ok(())
   }
@@ -89,12 +151,86 @@ impl LayoutManager {
       .constraints([Constraint::Length(3), Constraint::Min(1)])
       .split(area);
 
-    let horizontal_layout = Layout::new(ratatui::layout::Direction::Horizontal, Constraint::from_percentages([50, 50]))
-      .split(vertical_layout[1]);
+    let (results, details, queue) = match self.orientation {
+      Orientation::Landscape => {
+        let horizontal_layout =
+          Layout::new(ratatui::layout::Direction::Horizontal, Constraint::from_percentages([50, 50]))
+            .split(vertical_layout[1]);
+        let details_layout = Layout::new(ratatui::layout::Direction::Vertical, Constraint::from_percentages([65, 35]))
+          .split(horizontal_layout[1]);
+        (horizontal_layout[0], details_layout[0], details_layout[1])
+      },
+      Orientation::Portrait => {
+        let stacked_layout =
+          Layout::new(ratatui::layout::Direction::Vertical, Constraint::from_percentages([50, 25, 25]))
+            .split(vertical_layout[1]);
+        (stacked_layout[0], stacked_layout[1], stacked_layout[2])
+      },
+    };
 
     self.layout_store.insert(Scenes::Download(DownloadLayouts::SearchBar), vertical_layout[0]);
-    self.layout_store.insert(Scenes::Download(DownloadLayouts::SearchResult), horizontal_layout[0]);
-    self.layout_store.insert(Scenes::Download(DownloadLayouts::SearchResultDetails), horizontal_layout[1]);
+    self.layout_store.insert(Scenes::Download(DownloadLayouts::SearchResult), results);
+    self.layout_store.insert(Scenes::Download(DownloadLayouts::SearchResultDetails), details);
+    self.layout_store.insert(Scenes::Download(DownloadLayouts::Queue), queue);
+    // the import review is shown over the search results
+    self.layout_store.insert(Scenes::Download(DownloadLayouts::ImportReview), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Download(DownloadLayouts::QueueConfirm), vertical_layout[1]);
+    Ok(())
+  }
+
+  fn build_manager_layout(&mut self, area: Rect) -> Result<()> {
+    let vertical_layout = Layout::default()
+      .direction(ratatui::layout::Direction::Vertical)
+      .constraints([Constraint::Length(1), Constraint::Min(1)])
+      .split(area);
+
+    // the songs of an artist are listed next to the pane of the artist
+    let (songs, artist) = match (&self.manager_tabs.active().filter, self.orientation) {
+      (TabFilter::Artist(_), Orientation::Landscape) => {
+        let horizontal_layout =
+          Layout::new(ratatui::layout::Direction::Horizontal, Constraint::from_percentages([70, 30]))
+            .split(vertical_layout[1]);
+        (horizontal_layout[0], horizontal_layout[1])
+      },
+      (TabFilter::Artist(_), Orientation::Portrait) => {
+        let stacked_layout = Layout::new(ratatui::layout::Direction::Vertical, Constraint::from_percentages([70, 30]))
+          .split(vertical_layout[1]);
+        (stacked_layout[0], stacked_layout[1])
+      },
+      _ => (vertical_layout[1], Rect::default()),
+    };
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::TabBar), vertical_layout[0]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::SongList), songs);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::ArtistDetails), artist);
+    // the other views are shown over the song list
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Compare), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::AlbumGaps), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Duplicates), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Review), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Backups), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Snapshots), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Playlists), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::ScanResults), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Attachments), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Trim), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::SongEditor), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Health), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::DownloadLogs), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::DeadLinks), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Integrity), vertical_layout[1]);
+    self.layout_store.insert(Scenes::Manager(ManagerLayouts::Upgrade), vertical_layout[1]);
+    Ok(())
+  }
+
+  fn build_jobs_layout(&mut self, area: Rect) -> Result<()> {
+    let direction = match self.orientation {
+      Orientation::Landscape => ratatui::layout::Direction::Horizontal,
+      Orientation::Portrait => ratatui::layout::Direction::Vertical,
+    };
+    let layout = Layout::new(direction, Constraint::from_percentages([50, 50])).split(area);
+
+    self.layout_store.insert(Scenes::Jobs(JobsLayouts::List), layout[0]);
+    self.layout_store.insert(Scenes::Jobs(JobsLayouts::Logs), layout[1]);
     Ok(())
   }
 
@@ -102,18 +238,31 @@ impl LayoutManager {
   fn build_layouts(&mut self) -> Result<()> {
     let layout = Layout::default()
       .direction(ratatui::layout::Direction::Vertical)
-      .constraints([Constraint::Length(1), Constraint::Min(1), Constraint::Length(3)])
+      .constraints([
+        Constraint::Length(1),
+        Constraint::Min(1),
+        Constraint::Length(TRANSPORT_BAR_HEIGHT),
+        Constraint::Length(3),
+      ])
       .split(self.screen);
     // Default elements present in every screen
     self.layout_store.insert(Scenes::TitleBar, layout[0]);
-    self.layout_store.insert(Scenes::InputBar, layout[2]);
+    self.layout_store.insert(Scenes::TransportBar, layout[2]);
+    self.layout_store.insert(Scenes::InputBar, layout[3]);
 
     let main_render_area = layout[1];
 
     // Screen: Home
-    self.layout_store.insert(Scenes::Home(HomeLayouts::Intro), main_render_area);
+    self.layout_store.insert(Scenes::Home(HomeLayouts::Dashboard), main_render_area);
+    self.layout_store.insert(Scenes::Home(HomeLayouts::Diagnostics), main_render_area);
+    // Screen: Now Playing
+    self.layout_store.insert(Scenes::NowPlaying(NowPlayingLayouts::Lyrics), main_render_area);
+    // Over any screen
+    self.layout_store.insert(Scenes::Help, main_render_area);
 
     self.build_download_layout(main_render_area)?;
+    self.build_manager_layout(main_render_area)?;
+    self.build_jobs_layout(main_render_area)?;
     Ok(())
   }
 }
@@ -123,3 +272,235 @@ pub struct Focus {
   pub mode: Mode,
   pub scene: Scenes,
 }
+
+/// The filter applied to the songs shown in a Manager tab
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub enum TabFilter {
+  #[default]
+  All,
+  Artist(String),
+  Album(String),
+  Genre(String),
+  Playlist(String),
+}
+
+impl TabFilter {
+  /// Parse a filter from user input in the form of `kind:value`, e.g. `genre:Japanese Pop`.
+  /// Empty input results in `TabFilter::All`
+  pub fn parse(input: &str) -> Result<Self> {
+    let input = input.trim();
+    if input.is_empty() {
+      return Ok(Self::All);
+    }
+    let (kind, value) = input.split_once(':').ok_or_eyre("tab filter must be in the form of kind:value")?;
+    let value = value.trim().to_string();
+    if value.is_empty() {
+      return Err(eyre!("tab filter value must not be empty"));
+    }
+    match kind.trim().to_ascii_lowercase().as_str() {
+      "artist" => Ok(Self::Artist(value)),
+      "album" => Ok(Self::Album(value)),
+      "genre" => Ok(Self::Genre(value)),
+      "playlist" => Ok(Self::Playlist(value)),
+      kind => Err(eyre!("unknown tab filter kind: {kind}")),
+    }
+  }
+
+  /// The pin leading to a tab with this filter, if it can be pinned
+  pub fn to_pin(&self) -> Option<NewPin> {
+    let (kind, name) = match self {
+      TabFilter::All | TabFilter::Genre(_) => return None,
+      TabFilter::Artist(name) => (PinKind::Artist, name),
+      TabFilter::Album(name) => (PinKind::Album, name),
+      TabFilter::Playlist(name) => (PinKind::Playlist, name),
+    };
+    Some(NewPin { kind, name: name.clone() })
+  }
+}
+
+impl From<&Pin> for TabFilter {
+  fn from(pin: &Pin) -> Self {
+    match pin.kind {
+      PinKind::Artist => TabFilter::Artist(pin.name.clone()),
+      PinKind::Album => TabFilter::Album(pin.name.clone()),
+      PinKind::Playlist => TabFilter::Playlist(pin.name.clone()),
+    }
+  }
+}
+
+impl fmt::Display for TabFilter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TabFilter::All => write!(f, "All"),
+      TabFilter::Artist(artist) => write!(f, "Artist: {artist}"),
+      TabFilter::Album(album) => write!(f, "Album: {album}"),
+      TabFilter::Genre(genre) => write!(f, "Genre: {genre}"),
+      TabFilter::Playlist(playlist) => write!(f, "Playlist: {playlist}"),
+    }
+  }
+}
+
+/// A single Manager view, with its own selection and scroll state
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct ManagerTab {
+  pub filter: TabFilter,
+  pub selected: Option<usize>,
+  pub offset: usize,
+}
+
+impl ManagerTab {
+  pub fn new(filter: TabFilter) -> Self {
+    Self { filter, ..Default::default() }
+  }
+
+  pub fn name(&self) -> String {
+    self.filter.to_string()
+  }
+}
+
+/// The set of tabs opened in the Manager. There is always at least one tab
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ManagerTabs {
+  tabs: Vec<ManagerTab>,
+  active: usize,
+}
+
+impl Default for ManagerTabs {
+  fn default() -> Self {
+    Self { tabs: vec![ManagerTab::default()], active: 0 }
+  }
+}
+
+impl ManagerTabs {
+  pub fn tabs(&self) -> &[ManagerTab] {
+    &self.tabs
+  }
+
+  pub fn active_index(&self) -> usize {
+    self.active
+  }
+
+  pub fn active(&self) -> &ManagerTab {
+    &self.tabs[self.active]
+  }
+
+  pub fn active_mut(&mut self) -> &mut ManagerTab {
+    &mut self.tabs[self.active]
+  }
+
+  /// Open a new tab after the current one and switch to it
+  pub fn open(&mut self, tab: ManagerTab) {
+    self.active += 1;
+    self.tabs.insert(self.active, tab);
+  }
+
+  /// Close the active tab. The last remaining tab cannot be closed
+  pub fn close_active(&mut self) {
+    if self.tabs.len() > 1 {
+      self.tabs.remove(self.active);
+      self.active = self.active.min(self.tabs.len() - 1);
+    }
+  }
+
+  pub fn next(&mut self) {
+    self.active = (self.active + 1) % self.tabs.len();
+  }
+
+  pub fn previous(&mut self) {
+    self.active = self.active.checked_sub(1).unwrap_or(self.tabs.len() - 1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_tab_filter_parse() -> Result<()> {
+    assert_eq!(TabFilter::parse("")?, TabFilter::All);
+    assert_eq!(TabFilter::parse("genre:Japanese Pop")?, TabFilter::Genre("Japanese Pop".to_string()));
+    assert_eq!(TabFilter::parse(" Album : Still Still Stellar ")?, TabFilter::Album("Still Still Stellar".to_string()));
+    assert!(TabFilter::parse("Suisei").is_err());
+    assert!(TabFilter::parse("year:2021").is_err());
+    assert!(TabFilter::parse("artist:").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_tab_filter_pins() -> Result<()> {
+    let filter = TabFilter::parse("playlist:Rating - 5 stars")?;
+    let pin = filter.to_pin().expect("playlists can be pinned");
+    assert_eq!(TabFilter::from(&Pin { id: 1, kind: pin.kind, name: pin.name }), filter);
+    assert_eq!(TabFilter::All.to_pin(), None);
+    Ok(())
+  }
+
+  #[test]
+  fn test_manager_tabs_navigation() {
+    let mut tabs = ManagerTabs::default();
+    tabs.open(ManagerTab::new(TabFilter::Genre("Rock".to_string())));
+    tabs.open(ManagerTab::new(TabFilter::Artist("LiSA".to_string())));
+    assert_eq!(tabs.active_index(), 2);
+
+    tabs.next();
+    assert_eq!(tabs.active().filter, TabFilter::All);
+    tabs.previous();
+    assert_eq!(tabs.active().filter, TabFilter::Artist("LiSA".to_string()));
+
+    tabs.active_mut().selected = Some(3);
+    tabs.previous();
+    assert_eq!(tabs.active().selected, None);
+    tabs.next();
+    assert_eq!(tabs.active().selected, Some(3));
+  }
+
+  #[cfg(feature = "tui")]
+  #[test]
+  fn test_portrait_layouts() -> Result<()> {
+    let mut layouts = LayoutManager::new();
+    layouts.init(Rect::new(0, 0, 120, 40))?;
+    let results = layouts.get_component_layout(Scenes::Download(DownloadLayouts::SearchResult))?;
+    let queue = layouts.get_component_layout(Scenes::Download(DownloadLayouts::Queue))?;
+    assert!(queue.x > results.x);
+
+    // a phone held upright stacks the panes
+    layouts.update(Rect::new(0, 0, 48, 40))?;
+    let results = layouts.get_component_layout(Scenes::Download(DownloadLayouts::SearchResult))?;
+    let queue = layouts.get_component_layout(Scenes::Download(DownloadLayouts::Queue))?;
+    assert_eq!((queue.x, queue.width), (results.x, results.width));
+    assert!(queue.y > results.y);
+    let logs = layouts.get_component_layout(Scenes::Jobs(JobsLayouts::Logs))?;
+    assert_eq!(logs.width, 48);
+    Ok(())
+  }
+
+  #[cfg(feature = "tui")]
+  #[test]
+  fn test_artist_layouts() -> Result<()> {
+    let mut layouts = LayoutManager::new();
+    layouts.init(Rect::new(0, 0, 120, 40))?;
+    let artist = layouts.get_component_layout(Scenes::Manager(ManagerLayouts::ArtistDetails))?;
+    assert!(artist.is_empty());
+
+    // artist tabs make room for the pane of the artist next to the songs
+    layouts.manager_tabs.open(ManagerTab::new(TabFilter::Artist("LiSA".to_string())));
+    layouts.update(Rect::new(0, 0, 120, 40))?;
+    let songs = layouts.get_component_layout(Scenes::Manager(ManagerLayouts::SongList))?;
+    let artist = layouts.get_component_layout(Scenes::Manager(ManagerLayouts::ArtistDetails))?;
+    assert_eq!((songs.width + artist.width, artist.x), (120, songs.right()));
+    Ok(())
+  }
+
+  #[test]
+  fn test_manager_tabs_close_keeps_last_tab() {
+    let mut tabs = ManagerTabs::default();
+    tabs.open(ManagerTab::new(TabFilter::Genre("Rock".to_string())));
+    tabs.close_active();
+    assert_eq!(tabs.tabs().len(), 1);
+    assert_eq!(tabs.active_index(), 0);
+    tabs.close_active();
+    assert_eq!(tabs.tabs().len(), 1);
+  }
+}
@@ -0,0 +1,82 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+#![allow(unused_variables)]
+
+pub mod action;
+#[cfg(feature = "musicbrainz")]
+pub mod album_completion;
+#[cfg(feature = "tui")]
+pub mod app;
+pub mod art;
+pub mod artist_images;
+pub mod attachments;
+pub mod auth;
+pub mod availability;
+pub mod backup;
+pub mod cache;
+pub mod cli;
+pub mod collation;
+#[cfg(feature = "tui")]
+pub mod components;
+pub mod config;
+pub mod daemon;
+pub mod dashboard;
+pub mod database;
+pub mod diagnostics;
+pub mod disambiguation;
+pub mod download_logs;
+pub mod enrichment;
+pub mod errors;
+pub mod export;
+pub mod fetch;
+pub mod gaps;
+pub mod health;
+pub mod import;
+pub mod instrumental;
+pub mod ipc;
+pub mod jobs;
+#[cfg(feature = "tui")]
+pub mod keymap;
+pub mod layouts;
+pub mod links;
+pub mod listenbrainz;
+pub mod listing;
+pub mod lyrics;
+pub mod merge;
+pub mod mixes;
+pub mod mode;
+pub mod models;
+pub mod mpd;
+pub mod paths;
+#[cfg(feature = "player")]
+pub mod player;
+pub mod playlist_export;
+pub mod playlist_sync;
+pub mod playlists;
+pub mod plays;
+pub mod postprocess;
+pub mod quality;
+pub mod query;
+pub mod queue;
+pub mod report;
+pub mod scan;
+pub mod schema;
+#[cfg(feature = "debug")]
+pub mod seed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod snapshots;
+pub mod song_edit;
+pub mod song_status;
+#[cfg(feature = "server")]
+pub mod subsonic;
+pub mod tagging;
+pub mod termux;
+pub mod tls;
+pub mod trim;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod upgrade;
+pub mod utils;
+pub mod verify;
+pub mod webhooks;
@@ -0,0 +1,248 @@
+//! Stale link detection
+//!
+//! Videos get taken down or made private over the years, so the videos the songs were downloaded
+//! from are checked now and then, least recently checked first. The song of a dead video is
+//! searched for again by its title and artists, and the best match is kept as a suggestion to
+//! link the song to instead. That way downloading a song whose file is gone does not fail long
+//! after its video went away.
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{NaiveDateTime, Utc};
+use color_eyre::eyre::Result;
+
+use crate::{
+  availability,
+  config::Config,
+  database::Database,
+  import::{self, ImportTrack},
+  jobs::{JobContext, JobKind, JobRegistry},
+  models::{LinkCheck, Song},
+  queue::DownloadRequest,
+};
+
+/// How often whether a scheduled check is due is looked at
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// The name of the job checking the videos
+const JOB_NAME: &str = "check the videos of the songs";
+
+/// A video found to link a song with a dead video to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Suggestion {
+  pub youtube_id: String,
+  pub title: String,
+  /// from 0 to 100
+  pub score: u8,
+}
+
+/// A song whose video can not be downloaded anymore
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeadLink {
+  pub song_id: i32,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub youtube_id: String,
+  pub reason: String,
+  pub checked_at: NaiveDateTime,
+  pub suggestion: Option<Suggestion>,
+}
+
+/// The songs with a video to check, least recently checked first. With an interval, only the
+/// songs not checked within it
+pub fn songs_to_check(
+  database: &mut Database,
+  now: NaiveDateTime,
+  interval: Option<chrono::Duration>,
+  limit: usize,
+) -> Result<Vec<Song>> {
+  let checked: HashMap<i32, NaiveDateTime> =
+    database.get_link_checks()?.into_iter().map(|check| (check.song_id, check.checked_at)).collect();
+  let mut songs: Vec<_> = database
+    .get_all_songs()?
+    .into_iter()
+    .filter(|song| song.youtube_id.is_some())
+    .filter(|song| {
+      interval.is_none_or(|interval| checked.get(&song.id).is_none_or(|checked_at| now - *checked_at >= interval))
+    })
+    .collect();
+  // songs never checked go first
+  songs.sort_by_key(|song| checked.get(&song.id).copied());
+  songs.truncate(limit);
+  Ok(songs)
+}
+
+/// Whether the scheduled check has songs to check
+pub fn is_due(config: &Config, database: &mut Database) -> Result<bool> {
+  let Some(days) = config.config.links.check_interval_days else {
+    return Ok(false);
+  };
+  let interval = chrono::Duration::days(days as i64);
+  Ok(!songs_to_check(database, Utc::now().naive_utc(), Some(interval), 1)?.is_empty())
+}
+
+/// The songs whose video was found dead, most recently checked first
+pub fn load_dead_links(database: &mut Database) -> Result<Vec<DeadLink>> {
+  let songs: HashMap<i32, Song> = database.get_all_songs()?.into_iter().map(|song| (song.id, song)).collect();
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  let mut dead_links: Vec<_> = database
+    .get_link_checks()?
+    .into_iter()
+    .filter_map(|check| {
+      let song = songs.get(&check.song_id)?;
+      let suggestion = check.suggestion_id.map(|youtube_id| {
+        Suggestion {
+          youtube_id,
+          title: check.suggestion_title.unwrap_or_default(),
+          score: check.suggestion_score.unwrap_or_default() as u8,
+        }
+      });
+      Some(DeadLink {
+        song_id: song.id,
+        title: song.title.clone(),
+        artists: artists.remove(&song.id).unwrap_or_default(),
+        youtube_id: song.youtube_id.clone()?,
+        reason: check.dead_reason?,
+        checked_at: check.checked_at,
+        suggestion,
+      })
+    })
+    .collect();
+  dead_links.sort_by_key(|dead_link| std::cmp::Reverse(dead_link.checked_at));
+  Ok(dead_links)
+}
+
+/// Search for the song again, leaving out its dead video
+async fn suggest(track: ImportTrack, dead_id: &str) -> Result<Option<Suggestion>> {
+  let import_match = import::match_track(track).await?;
+  Ok(import_match.candidate.filter(|candidate| candidate.youtube_id != dead_id).map(|candidate| {
+    Suggestion { youtube_id: candidate.youtube_id, title: candidate.title, score: import_match.score }
+  }))
+}
+
+/// Check whether the videos of the songs are still up, suggesting another video for the dead ones,
+/// to be run as a job. Only the songs due for a check are checked unless `all` is set
+pub async fn check_links(config: Config, all: bool, context: JobContext) -> Result<()> {
+  let settings = config.config.links.clone();
+  let mut database = Database::new(config.clone()).await?;
+  let now = Utc::now().naive_utc();
+  let interval = match all {
+    true => None,
+    false => settings.check_interval_days.map(|days| chrono::Duration::days(days as i64)),
+  };
+  let songs = songs_to_check(&mut database, now, interval, settings.batch_size)?;
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+
+  let total = songs.len() as u64;
+  let mut dead = 0;
+  for (index, song) in songs.into_iter().enumerate() {
+    let youtube_id = song.youtube_id.clone().unwrap_or_default();
+    let request = DownloadRequest { youtube_id: youtube_id.clone(), title: song.title.clone(), ..Default::default() };
+    let mut check = LinkCheck { song_id: song.id, checked_at: now, ..Default::default() };
+    match availability::check(&config, &request).await {
+      Ok(availability) if availability.is_available() => database.set_link_check(&check)?,
+      Ok(availability) => {
+        dead += 1;
+        let track = ImportTrack {
+          title: song.title.clone(),
+          artists: artists.remove(&song.id).unwrap_or_default(),
+          ..Default::default()
+        };
+        let suggestion = match suggest(track, &youtube_id).await {
+          Ok(suggestion) => suggestion,
+          Err(e) => {
+            context.log(format!("search for {} failed: {e}", song.title));
+            None
+          },
+        };
+        match &suggestion {
+          Some(suggestion) => {
+            context.log(format!("{} is {availability}, found {} ({}%)", song.title, suggestion.title, suggestion.score))
+          },
+          None => context.log(format!("{} is {availability}, found nothing to replace it", song.title)),
+        }
+        check.dead_reason = Some(availability.to_string());
+        check.suggestion_score = suggestion.as_ref().map(|suggestion| suggestion.score as i32);
+        check.suggestion_title = suggestion.as_ref().map(|suggestion| suggestion.title.clone());
+        check.suggestion_id = suggestion.map(|suggestion| suggestion.youtube_id);
+        database.set_link_check(&check)?;
+      },
+      // left to be checked again, YouTube may just be out of reach
+      Err(e) => context.log(format!("failed to check {}: {e}", song.title)),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  context.log(format!("{dead} of {total} videos are dead"));
+  Ok(())
+}
+
+/// Start checking the videos of the songs unless a check is running already. Only the songs due
+/// for a check are checked unless `all` is set
+///
+/// # Returns
+///
+/// * whether a check was started
+pub fn spawn_check(jobs: &JobRegistry, config: Config, all: bool) -> bool {
+  if jobs.snapshot().iter().any(|job| job.name == JOB_NAME && !job.state.is_done()) {
+    return false;
+  }
+  jobs.spawn(JobKind::Verify, JOB_NAME, move |context| check_links(config.clone(), all, context));
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{MetadataSource, NewSong},
+  };
+
+  #[test]
+  fn test_dead_links() -> Result<()> {
+    let mut database = setup_database()?;
+    let mut song_ids = vec![];
+    for (title, youtube_id) in
+      [("Stellar Stellar", Some("a51VH9BYzZA")), ("Bluerose", Some("2lAe1cqCOXo")), ("GHOST", None)]
+    {
+      song_ids.push(database.insert_song(NewSong {
+        title: title.to_string(),
+        youtube_id: youtube_id.map(str::to_string),
+        ..Default::default()
+      })?);
+    }
+    let now = Utc::now().naive_utc();
+    let day = chrono::Duration::days(1);
+    database.set_link_check(&LinkCheck { song_id: song_ids[0], checked_at: now - day * 2, ..Default::default() })?;
+    database.set_link_check(&LinkCheck {
+      song_id: song_ids[1],
+      checked_at: now,
+      dead_reason: Some("removed".to_string()),
+      suggestion_id: Some("abcdefghijk".to_string()),
+      suggestion_title: Some("Bluerose (Official)".to_string()),
+      suggestion_score: Some(85),
+    })?;
+
+    let titles = |songs: Vec<Song>| songs.into_iter().map(|song| song.title).collect::<Vec<_>>();
+    assert_eq!(titles(songs_to_check(&mut database, now, None, 10)?), vec!["Stellar Stellar", "Bluerose"]);
+    assert_eq!(titles(songs_to_check(&mut database, now, Some(day), 10)?), vec!["Stellar Stellar"]);
+    assert_eq!(titles(songs_to_check(&mut database, now, Some(day * 3), 10)?), Vec::<String>::new());
+
+    let dead_links = load_dead_links(&mut database)?;
+    assert_eq!(dead_links.len(), 1);
+    assert_eq!(dead_links[0].reason, "removed");
+    assert_eq!(dead_links[0].suggestion.as_ref().map(|suggestion| suggestion.score), Some(85));
+
+    database.relink_song(song_ids[1], "abcdefghijk", MetadataSource::Manual)?;
+    assert_eq!(load_dead_links(&mut database)?, vec![]);
+    assert_eq!(database.get_song_from_id(song_ids[1])?.youtube_id.as_deref(), Some("abcdefghijk"));
+    Ok(())
+  }
+}
@@ -0,0 +1,319 @@
+//! ListenBrainz integration
+//!
+//! Submits listens to ListenBrainz and imports the listen history of a user to seed the play
+//! counts of songs already in the library. This is configured separately from any other
+//! scrobbling service. Songs are submitted as playing when they start, and as listened to once
+//! their play is counted, see [`crate::plays`]. Songs without an artist are not submitted.
+
+use chrono::DateTime;
+use color_eyre::eyre::{eyre, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+  config::{Config, ListenBrainzConfig},
+  database::Database,
+  jobs::JobContext,
+};
+
+/// The maximum number of listens the API returns per request
+const LISTENS_PER_PAGE: u64 = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+  pub artist_name: String,
+  pub track_name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub release_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listen {
+  /// unix timestamp of the listen. Absent for `playing_now` submissions
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub listened_at: Option<i64>,
+  pub track_metadata: TrackMetadata,
+}
+
+#[derive(Debug, Serialize)]
+struct SubmitListens<'a> {
+  listen_type: &'a str,
+  payload: Vec<&'a Listen>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListensResponse {
+  payload: ListensPayload,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListensPayload {
+  listens: Vec<Listen>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListenCountResponse {
+  payload: ListenCountPayload,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListenCountPayload {
+  count: u64,
+}
+
+pub struct ListenBrainzClient {
+  client: reqwest::Client,
+  config: ListenBrainzConfig,
+}
+
+impl ListenBrainzClient {
+  pub fn new(config: ListenBrainzConfig) -> Self {
+    Self { client: reqwest::Client::new(), config }
+  }
+
+  fn token(&self) -> Result<&str> {
+    self.config.token.as_deref().ok_or_else(|| eyre!("listenbrainz token is not configured"))
+  }
+
+  fn username(&self) -> Result<&str> {
+    self.config.username.as_deref().ok_or_else(|| eyre!("listenbrainz username is not configured"))
+  }
+
+  /// Submit a finished listen. Does nothing if scrobbling to ListenBrainz is disabled
+  ///
+  /// # Arguments
+  ///
+  /// * `listened_at` - unix timestamp of the listen, the same as the play recorded in the library
+  /// * `track_metadata` - the song listened to
+  pub async fn submit_listen(&self, listened_at: i64, track_metadata: TrackMetadata) -> Result<()> {
+    let listen = Listen { listened_at: Some(listened_at), track_metadata };
+    self.submit("single", &listen).await
+  }
+
+  /// Tell ListenBrainz what is currently playing. Does nothing if scrobbling to ListenBrainz is
+  /// disabled
+  pub async fn submit_playing_now(&self, track_metadata: TrackMetadata) -> Result<()> {
+    let listen = Listen { listened_at: None, track_metadata };
+    self.submit("playing_now", &listen).await
+  }
+
+  async fn submit(&self, listen_type: &str, listen: &Listen) -> Result<()> {
+    if !self.config.enabled {
+      return Ok(());
+    }
+    self
+      .client
+      .post(format!("{}/1/submit-listens", self.config.api_url))
+      .header("Authorization", format!("Token {}", self.token()?))
+      .json(&SubmitListens { listen_type, payload: vec![listen] })
+      .send()
+      .await?
+      .error_for_status()
+      .wrap_err("submit listen to listenbrainz")?;
+    Ok(())
+  }
+
+  /// The total number of listens of the configured user
+  pub async fn listen_count(&self) -> Result<u64> {
+    let response: ListenCountResponse = self
+      .client
+      .get(format!("{}/1/user/{}/listen-count", self.config.api_url, self.username()?))
+      .send()
+      .await?
+      .error_for_status()?
+      .json()
+      .await?;
+    Ok(response.payload.count)
+  }
+
+  /// Get a page of listens of the configured user, newest first
+  ///
+  /// # Arguments
+  ///
+  /// * `max_ts` - only return listens before this unix timestamp
+  pub async fn listens(&self, max_ts: Option<i64>) -> Result<Vec<Listen>> {
+    let mut request = self
+      .client
+      .get(format!("{}/1/user/{}/listens", self.config.api_url, self.username()?))
+      .query(&[("count", LISTENS_PER_PAGE)]);
+    if let Some(max_ts) = max_ts {
+      request = request.query(&[("max_ts", max_ts)]);
+    }
+    let response: ListensResponse = request.send().await?.error_for_status()?.json().await?;
+    Ok(response.payload.listens)
+  }
+}
+
+/// The song as ListenBrainz knows it, or `None` if it has no artist
+pub fn track_metadata(database: &mut Database, song_id: i32) -> Result<Option<TrackMetadata>> {
+  let song = database.get_song_from_id(song_id)?;
+  let album = database.get_album_for_song(song_id)?;
+  let artists = database.get_all_artists_for_song(song.clone())?;
+  if artists.is_empty() {
+    return Ok(None);
+  }
+  Ok(Some(TrackMetadata {
+    artist_name: artists.into_iter().map(|artist| artist.name).collect::<Vec<_>>().join(", "),
+    track_name: song.title,
+    release_name: album.map(|album| album.name),
+  }))
+}
+
+/// Submit the song in a task of its own, so that a slow ListenBrainz does not hold up playback.
+/// Does nothing if scrobbling to ListenBrainz is disabled
+///
+/// # Arguments
+///
+/// * `listened_at` - unix timestamp of the listen, or `None` to submit the song as playing now
+pub fn spawn_submit(config: &ListenBrainzConfig, listened_at: Option<i64>, track_metadata: TrackMetadata) {
+  if !config.enabled {
+    return;
+  }
+  let client = ListenBrainzClient::new(config.clone());
+  tokio::spawn(async move {
+    let submitted = match listened_at {
+      Some(listened_at) => client.submit_listen(listened_at, track_metadata).await,
+      None => client.submit_playing_now(track_metadata).await,
+    };
+    if let Err(e) = submitted {
+      warn!("failed to submit to listenbrainz: {e}");
+    }
+  });
+}
+
+/// Record the given listens as plays of the matching songs in the library, all at once. Listens
+/// recorded by an earlier import are skipped, and so are those without a timestamp
+///
+/// # Returns
+///
+/// * the number of listens recorded
+pub fn seed_play_counts(database: &mut Database, listens: &[Listen]) -> Result<usize> {
+  let mut plays = vec![];
+  for listen in listens {
+    let metadata = &listen.track_metadata;
+    let Some(played_at) = listen.listened_at.and_then(|listened_at| DateTime::from_timestamp(listened_at, 0)) else {
+      continue;
+    };
+    if let Some(song) = database.find_song_by_title_and_artist(&metadata.track_name, &metadata.artist_name)? {
+      plays.push((song.id, played_at.naive_utc()));
+    }
+  }
+  database.atomically(|database| {
+    let mut recorded = 0;
+    for (song_id, played_at) in plays {
+      if database.record_play_once(song_id, played_at)? {
+        recorded += 1;
+      }
+    }
+    Ok(recorded)
+  })
+}
+
+/// Import the whole listen history of the configured user, to be run as a job
+pub async fn import_history(config: Config, context: JobContext) -> Result<()> {
+  let client = ListenBrainzClient::new(config.config.listenbrainz.clone());
+  let mut database = Database::new(config).await?;
+
+  let total = client.listen_count().await?;
+  context.log(format!("importing {total} listens"));
+  let mut imported = 0;
+  let mut recorded = 0;
+  let mut max_ts = None;
+  loop {
+    let listens = client.listens(max_ts).await?;
+    if listens.is_empty() {
+      break;
+    }
+    max_ts = listens.iter().filter_map(|listen| listen.listened_at).min();
+    imported += listens.len() as u64;
+    recorded += seed_play_counts(&mut database, &listens)?;
+    debug!("imported {imported} of {total} listens");
+    context.progress(imported, total);
+    if max_ts.is_none() {
+      break;
+    }
+  }
+  context.log(format!("{recorded} of {imported} listens were recorded for songs in the library"));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewSong, SongArtist},
+  };
+
+  fn listen(track_name: &str, artist_name: &str, listened_at: i64) -> Listen {
+    Listen {
+      listened_at: Some(listened_at),
+      track_metadata: TrackMetadata {
+        artist_name: artist_name.to_string(),
+        track_name: track_name.to_string(),
+        release_name: None,
+      },
+    }
+  }
+
+  #[test]
+  fn test_listen_serialization() -> Result<()> {
+    let listen = listen("Stellar Stellar", "Hoshimachi Suisei", 1704067200);
+    let body = serde_json::to_value(SubmitListens { listen_type: "single", payload: vec![&listen] })?;
+    assert_eq!(
+      body,
+      serde_json::json!({
+        "listen_type": "single",
+        "payload": [{
+          "listened_at": 1704067200,
+          "track_metadata": { "artist_name": "Hoshimachi Suisei", "track_name": "Stellar Stellar" }
+        }]
+      })
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_seed_play_counts() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+
+    let listens = vec![
+      listen("Stellar Stellar", "Hoshimachi Suisei", 1704067200),
+      listen("stellar stellar", "hoshimachi suisei", 1704067500),
+      listen("Crossing Field", "LiSA", 1704067800),
+    ];
+    assert_eq!(seed_play_counts(&mut database, &listens)?, 2);
+    assert_eq!(database.get_song_from_id(song_id)?.play_count, 2);
+
+    // the history imported again is not counted twice
+    assert_eq!(seed_play_counts(&mut database, &listens)?, 0);
+    assert_eq!(database.get_song_from_id(song_id)?.play_count, 2);
+    let newer = [listen("Stellar Stellar", "Hoshimachi Suisei", 1704068100)];
+    assert_eq!(seed_play_counts(&mut database, &newer)?, 1);
+    assert_eq!(database.get_song_from_id(song_id)?.play_count, 3);
+    Ok(())
+  }
+
+  #[test]
+  fn test_track_metadata() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    assert_eq!(track_metadata(&mut database, song_id)?, None);
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    assert_eq!(
+      track_metadata(&mut database, song_id)?,
+      Some(TrackMetadata {
+        artist_name: "Hoshimachi Suisei".to_string(),
+        track_name: "Stellar Stellar".to_string(),
+        release_name: None,
+      })
+    );
+    Ok(())
+  }
+}
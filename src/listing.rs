@@ -0,0 +1,167 @@
+//! Output of the songs listed by the CLI
+//!
+//! Songs are written as soon as they are read, so that listing a huge library does not hold it in
+//! memory and the output can be piped into other tools while it is produced. JSON is written as one
+//! object per line, which `jq` reads as a stream.
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::models::Song;
+
+/// The width of the title column of tables, longer titles are cut
+const TABLE_TITLE_WIDTH: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+  /// aligned columns for reading in a terminal
+  #[default]
+  Table,
+  /// one JSON object per line
+  Json,
+  /// comma separated values with a header
+  Csv,
+}
+
+/// A song as written by the CLI
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SongRow {
+  pub id: i32,
+  pub title: String,
+  pub youtube_id: Option<String>,
+  pub play_count: i32,
+  pub rating: Option<i32>,
+  pub added_at: Option<String>,
+}
+
+impl From<Song> for SongRow {
+  fn from(song: Song) -> Self {
+    Self {
+      id: song.id,
+      title: song.title,
+      youtube_id: song.youtube_id,
+      play_count: song.play_count,
+      rating: song.rating,
+      added_at: song.added_at.map(|added_at| added_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+    }
+  }
+}
+
+/// Writes songs one at a time in a format
+pub enum SongWriter<W: Write> {
+  Table { output: W, header_written: bool },
+  Json(W),
+  Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> SongWriter<W> {
+  pub fn new(format: ListFormat, output: W) -> Self {
+    match format {
+      ListFormat::Table => SongWriter::Table { output, header_written: false },
+      ListFormat::Json => SongWriter::Json(output),
+      // the header is written from the fields of the first row
+      ListFormat::Csv => SongWriter::Csv(Box::new(csv::Writer::from_writer(output))),
+    }
+  }
+
+  pub fn write(&mut self, song: Song) -> Result<()> {
+    self.write_row(SongRow::from(song))
+  }
+
+  pub fn write_row(&mut self, row: SongRow) -> Result<()> {
+    match self {
+      SongWriter::Table { output, header_written } => {
+        if !*header_written {
+          writeln!(
+            output,
+            "{:>6}  {:<TABLE_TITLE_WIDTH$}  {:<11}  {:>5}  {:>6}",
+            "ID", "TITLE", "YOUTUBE", "PLAYS", "RATING"
+          )?;
+          *header_written = true;
+        }
+        let mut title: String = row.title.chars().take(TABLE_TITLE_WIDTH).collect();
+        if title.len() < row.title.len() {
+          title.pop();
+          title.push('…');
+        }
+        writeln!(
+          output,
+          "{:>6}  {title:<TABLE_TITLE_WIDTH$}  {:<11}  {:>5}  {:>6}",
+          row.id,
+          row.youtube_id.unwrap_or_default(),
+          row.play_count,
+          row.rating.map(|rating| rating.to_string()).unwrap_or_default()
+        )?;
+        output.flush()?;
+      },
+      SongWriter::Json(output) => {
+        serde_json::to_writer(&mut *output, &row)?;
+        writeln!(output)?;
+        output.flush()?;
+      },
+      SongWriter::Csv(writer) => {
+        writer.serialize(row)?;
+        writer.flush()?;
+      },
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::NaiveDate;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn songs() -> Vec<Song> {
+    vec![
+      Song {
+        id: 1,
+        title: "Stellar Stellar".to_string(),
+        youtube_id: Some("a51VH9BYzZA".to_string()),
+        play_count: 3,
+        rating: Some(5),
+        added_at: NaiveDate::from_ymd_opt(2024, 1, 17).and_then(|date| date.and_hms_opt(12, 0, 0)),
+        ..Default::default()
+      },
+      Song { id: 2, title: "Idol, \"live\"".to_string(), ..Default::default() },
+    ]
+  }
+
+  fn write_all(format: ListFormat) -> Result<String> {
+    let mut output = vec![];
+    let mut writer = SongWriter::new(format, &mut output);
+    for song in songs() {
+      writer.write(song)?;
+    }
+    drop(writer);
+    Ok(String::from_utf8(output)?)
+  }
+
+  #[test]
+  fn test_write_json_lines() -> Result<()> {
+    let lines: Vec<serde_json::Value> =
+      write_all(ListFormat::Json)?.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["added_at"], "2024-01-17 12:00:00");
+    assert_eq!(lines[1]["title"], "Idol, \"live\"");
+    assert_eq!(lines[1]["rating"], serde_json::Value::Null);
+    Ok(())
+  }
+
+  #[test]
+  fn test_write_csv() -> Result<()> {
+    assert_eq!(
+      write_all(ListFormat::Csv)?,
+      "id,title,youtube_id,play_count,rating,added_at\n\
+       1,Stellar Stellar,a51VH9BYzZA,3,5,2024-01-17 12:00:00\n\
+       2,\"Idol, \"\"live\"\"\",,0,,\n"
+    );
+    Ok(())
+  }
+}
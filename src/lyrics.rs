@@ -0,0 +1,127 @@
+//! Synced lyrics
+//!
+//! Lyrics in the LRC format carry the time every line is sung at, so that they can scroll along
+//! with the playing song in the Now Playing scene. The `.lrc` lyrics attached to a song are used,
+//! or else a `.lrc` file next to the file of the song.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::Result;
+
+use crate::{database::Database, models::AttachmentKind, queue};
+
+/// A line of lyrics and when it is sung
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricLine {
+  pub at: Duration,
+  pub text: String,
+}
+
+/// The lines of synced lyrics, in the order they are sung
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lyrics {
+  pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+  /// Parse lyrics in the LRC format. Lines without a timestamp are left out, lines with several
+  /// are repeated, and the `[offset:]` tag is applied
+  pub fn parse(lrc: &str) -> Self {
+    let mut offset_ms = 0;
+    let mut lines = vec![];
+    for line in lrc.lines() {
+      let mut rest = line.trim();
+      let mut timestamps = vec![];
+      while let Some((tag, after)) = rest.strip_prefix('[').and_then(|tag| tag.split_once(']')) {
+        if let Some(offset) = tag.strip_prefix("offset:") {
+          offset_ms = offset.trim().parse().unwrap_or_default();
+        } else if let Some(timestamp) = parse_timestamp(tag) {
+          timestamps.push(timestamp);
+        }
+        rest = after;
+      }
+      for at in timestamps {
+        lines.push(LyricLine { at, text: rest.trim().to_string() });
+      }
+    }
+    // a positive offset shows the lines earlier
+    for line in &mut lines {
+      line.at = shift(line.at, -offset_ms);
+    }
+    lines.sort_by_key(|line| line.at);
+    Self { lines }
+  }
+
+  /// The index of the line sung at the position, `None` before the first line
+  pub fn current_line(&self, position: Duration) -> Option<usize> {
+    self.lines.partition_point(|line| line.at <= position).checked_sub(1)
+  }
+}
+
+/// Parse a `mm:ss.xx` timestamp
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+  let (minutes, seconds) = tag.split_once(':')?;
+  let minutes: u64 = minutes.parse().ok()?;
+  let seconds: f64 = seconds.parse().ok()?;
+  (seconds >= 0.0).then(|| Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Move the time by `milliseconds`, backwards if negative, stopping at zero
+pub fn shift(time: Duration, milliseconds: i64) -> Duration {
+  match milliseconds < 0 {
+    true => time.saturating_sub(Duration::from_millis(milliseconds.unsigned_abs())),
+    false => time + Duration::from_millis(milliseconds as u64),
+  }
+}
+
+/// Find the synced lyrics of the song downloaded from the video, if it has any
+pub fn find_lyrics(music_dir: &Path, database: &mut Database, youtube_id: &str) -> Result<Option<Lyrics>> {
+  let mut paths = vec![];
+  if let Some(song) = database.get_song_by_youtube_id(youtube_id)? {
+    // the lyrics attached last come first
+    for attachment in database.get_song_attachments(song.id)?.into_iter().rev() {
+      if attachment.kind == AttachmentKind::Lyrics {
+        paths.push(music_dir.join(attachment.relative_path));
+      }
+    }
+  }
+  paths.push(queue::song_file(database, music_dir, youtube_id)?.with_extension("lrc"));
+  for path in paths {
+    let Ok(lrc) = std::fs::read_to_string(&path) else {
+      continue;
+    };
+    let lyrics = Lyrics::parse(&lrc);
+    if !lyrics.lines.is_empty() {
+      return Ok(Some(lyrics));
+    }
+  }
+  Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn line(milliseconds: u64, text: &str) -> LyricLine {
+    LyricLine { at: Duration::from_millis(milliseconds), text: text.to_string() }
+  }
+
+  #[test]
+  fn test_parse_lyrics() {
+    let lyrics = Lyrics::parse(
+      "[ti:Stellar Stellar]\n[offset:+500]\n[00:12.50]Sou da yo\n[01:02.00][00:30.00]Stellar Stellar\nplain text\n",
+    );
+    assert_eq!(lyrics.lines, vec![
+      line(12_000, "Sou da yo"),
+      line(29_500, "Stellar Stellar"),
+      line(61_500, "Stellar Stellar")
+    ]);
+    assert_eq!(lyrics.current_line(Duration::from_secs(5)), None);
+    assert_eq!(lyrics.current_line(Duration::from_secs(12)), Some(0));
+    assert_eq!(lyrics.current_line(Duration::from_secs(45)), Some(1));
+    assert_eq!(lyrics.current_line(Duration::from_secs(300)), Some(2));
+    assert!(Lyrics::parse("no timestamps here").lines.is_empty());
+  }
+}
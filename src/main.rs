@@ -1,47 +1,628 @@
-#![allow(dead_code)]
-#![allow(unused_imports)]
-#![allow(unused_variables)]
-
-pub mod action;
-pub mod app;
-pub mod cli;
-pub mod components;
-pub mod config;
-pub mod database;
-pub mod layouts;
-pub mod mode;
-pub mod models;
-pub mod schema;
-pub mod tui;
-pub mod utils;
-
-use clap::Parser;
-use cli::Cli;
-use color_eyre::eyre::Result;
-
-use crate::{
-  app::App,
-  utils::{initialize_logging, initialize_panic_handler, version},
+use std::{
+  collections::{HashMap, HashSet},
+  io::IsTerminal,
+  path::PathBuf,
 };
 
+use clap::{CommandFactory, Parser};
+use color_eyre::eyre::{eyre, Context, Result};
+#[cfg(feature = "tui")]
+use muzik::app::App;
+use muzik::{
+  action::Action,
+  attachments::{self, AttachmentOwner},
+  cache,
+  cli::{CacheCommand, Cli, Command, OutputArgs, PlaylistsCommand, SnapshotsCommand},
+  config::Config,
+  daemon,
+  database::Database,
+  disambiguation,
+  errors::{ErrorCategory, JsonError},
+  fetch, health,
+  ipc::{self, IpcClient, IpcRequest, IpcResponse, Remote},
+  jobs::{JobKind, JobRegistry, JobState},
+  listing::SongWriter,
+  mixes,
+  models::SnapshotSong,
+  paths::{self, Paths},
+  playlist_sync, playlists,
+  query::Query,
+  queue::{self, Doubt, Progress, QueueStatus, YtDlp},
+  report::Report,
+  scan, snapshots, termux,
+  utils::{initialize_logging, initialize_panic_handler},
+};
+#[cfg(feature = "debug")]
+use muzik::{cli::DevCommand, seed};
+
+/// Write the songs matching the query to stdout as they are read from the database
+async fn list_songs(args: &Cli, query: Option<Query>, output: &OutputArgs) -> Result<()> {
+  let mut writer = SongWriter::new(output.format, std::io::stdout().lock());
+  let result = match args.remote() {
+    Some(remote) => list_remote_songs(&remote, query, output, &mut writer).await,
+    None => {
+      let mut database = Database::new(Config::new()?).await?;
+      database.for_each_song(query.as_ref(), output.limit, output.offset, |song| writer.write(song))
+    },
+  };
+  match result {
+    // the reader, such as `head`, stopped reading
+    Err(error) if is_broken_pipe(&error) => Ok(()),
+    result => result,
+  }
+}
+
+/// Write the songs with the text in their title or in the name of one of their artists, the songs
+/// of an artist named exactly as searched first. A remote daemon lists them in the order they were
+/// added
+async fn search_songs(args: &Cli, text: &str, output: &OutputArgs) -> Result<()> {
+  let query = Query::search(text);
+  if args.remote().is_some() {
+    return list_songs(args, Some(query), output).await;
+  }
+  let mut database = Database::new(Config::new()?).await?;
+  let mut songs = vec![];
+  database.for_each_song(Some(&query), None, 0, |song| {
+    songs.push(song);
+    Ok(())
+  })?;
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  songs.sort_by_key(|song| {
+    disambiguation::search_rank(text, &song.title, artists.get(&song.id).map(Vec::as_slice).unwrap_or_default())
+  });
+
+  let mut writer = SongWriter::new(output.format, std::io::stdout().lock());
+  let limit = output.limit.map_or(usize::MAX, |limit| limit.max(0) as usize);
+  let result =
+    songs.into_iter().skip(output.offset.max(0) as usize).take(limit).try_for_each(|song| writer.write(song));
+  match result {
+    // the reader, such as `head`, stopped reading
+    Err(error) if is_broken_pipe(&error) => Ok(()),
+    result => result,
+  }
+}
+
+/// Scan the music dir, or the given files of it, for songs missing from the library as the TUI
+/// does, printing what became of every file but those already in the library
+async fn scan_music_dir(args: &Cli, paths: &[PathBuf]) -> Result<()> {
+  let config = Config::new()?;
+  let paths = (!paths.is_empty()).then(|| paths.to_vec());
+  if args.dry_run {
+    let mut database = Database::new(config.clone()).await?;
+    let known: HashSet<PathBuf> =
+      database.get_songs_with_files()?.into_iter().map(|(_, relative_path)| PathBuf::from(relative_path)).collect();
+    let files = match paths {
+      Some(paths) => paths,
+      None => scan::music_files(&config.config.music_dir, &scan::IgnoreRules::new(&config.config.scan)?)?,
+    };
+    let new: Vec<_> = files.into_iter().filter(|path| !known.contains(path)).collect();
+    for path in &new {
+      println!("{}", path.display());
+    }
+    println!("would scan {} files missing from the library", new.len());
+    return Ok(());
+  }
+
+  let jobs = JobRegistry::with_config(&config.config.jobs);
+  let (action_tx, mut action_rx) = tokio::sync::mpsc::unbounded_channel();
+  let id = jobs.spawn(JobKind::Scan, "scan the music dir", move |context| {
+    scan::scan_music_dir(config.clone(), paths.clone(), action_tx.clone(), context)
+  });
+  // the progress is only worth drawing for someone watching
+  let watched = std::io::stderr().is_terminal();
+  let mut interval = tokio::time::interval(std::time::Duration::from_millis(250));
+  let results = loop {
+    tokio::select! {
+      Some(action) = action_rx.recv() => match action {
+        Action::ScanResults(results) | Action::ScanRetried(results) => break results,
+        _ => continue,
+      },
+      _ = interval.tick() => {},
+    }
+    let job = jobs.snapshot().into_iter().find(|job| job.id == id).ok_or_else(|| eyre!("the scan is gone"))?;
+    match job.state {
+      JobState::Failed(error) => return Err(eyre!("the scan failed: {error}")),
+      JobState::Running if watched => {
+        if let Some(percent) = job.percent() {
+          eprint!("\rscanning {percent}%\x1b[K");
+        }
+      },
+      _ => {},
+    }
+  };
+  if watched {
+    eprint!("\r\x1b[K");
+  }
+  for result in results.iter().filter(|result| result.outcome != scan::ScanOutcome::Duplicate) {
+    println!("{}: {}", result.path.display(), result.outcome);
+  }
+  let [imported, duplicate, too_short, unreadable, unsupported, failed] = scan::count_outcomes(&results);
+  println!(
+    "{imported} imported, {duplicate} duplicates, {too_short} too short, {unreadable} unreadable tags, {unsupported} \
+     unsupported, {failed} failed"
+  );
+  Ok(())
+}
+
+/// Download the songs of the target into the library, or queue them on the daemon if one runs
+async fn download(args: &Cli, target: &str, overrides: &fetch::Overrides) -> Result<()> {
+  let config = Config::new()?;
+  let videos = fetch::resolve(target).await.wrap_err(ErrorCategory::Network)?;
+  let requests = fetch::requests(&videos, overrides).wrap_err(ErrorCategory::Usage)?;
+  if args.dry_run {
+    for request in &requests {
+      println!("would download {} by {} ({})", request.title, request.artists.join(", "), request.url());
+    }
+    return Ok(());
+  }
+  let daemon = match args.remote() {
+    Some(remote) => Some(IpcClient::connect_remote(&remote).await?),
+    None => IpcClient::connect(&ipc::socket_path(&config)).await?,
+  };
+  if let Some(mut client) = daemon {
+    let queued = requests.len();
+    match client.request(&IpcRequest::Enqueue { requests }).await? {
+      IpcResponse::Ok => println!("queued {queued} songs on the daemon"),
+      IpcResponse::Error { message } => return Err(eyre!("the daemon failed to queue the songs: {message}")),
+      response => return Err(eyre!("unexpected response from the daemon: {response:?}")),
+    }
+    return Ok(());
+  }
+
+  termux::check(&config).wrap_err(ErrorCategory::Io)?;
+  let mut database = Database::new(config.clone()).await?;
+  // the progress is only worth drawing for someone watching
+  let watched = std::io::stderr().is_terminal();
+  let total = requests.len();
+  let mut failed = 0;
+  for request in requests {
+    if !request.doubts.is_empty() {
+      let doubts: Vec<_> = request.doubts.iter().map(Doubt::to_string).collect();
+      println!("{}: {}", request.title, doubts.join(", "));
+    }
+    let request = fetch::unattended(&config, request);
+    let progress = |progress: Progress| {
+      if watched {
+        eprint!("\r{}: {progress}\x1b[K", request.title);
+      }
+    };
+    let status = queue::download_now(&config, &mut database, &YtDlp, &request, &progress).await;
+    if watched {
+      eprint!("\r\x1b[K");
+    }
+    match status {
+      QueueStatus::Finished => println!("downloaded {}", request.title),
+      QueueStatus::Skipped(reason) => println!("skipped {}: {reason}", request.title),
+      QueueStatus::Failed(error) => {
+        failed += 1;
+        eprintln!("failed to download {}: {error}", request.title);
+      },
+      status => println!("{}: {status:?}", request.title),
+    }
+  }
+  match failed {
+    0 => Ok(()),
+    failed => Err(eyre!("{failed} of {total} songs failed to download")),
+  }
+}
+
+/// Write the songs of a remote daemon page by page
+async fn list_remote_songs<W: std::io::Write>(
+  remote: &Remote,
+  query: Option<Query>,
+  output: &OutputArgs,
+  writer: &mut SongWriter<W>,
+) -> Result<()> {
+  const PAGE_SIZE: i64 = 500;
+  let mut client = IpcClient::connect_remote(remote).await?;
+  let mut offset = output.offset;
+  let mut remaining = output.limit.unwrap_or(i64::MAX);
+  while remaining > 0 {
+    let request = IpcRequest::Songs { query: query.clone(), limit: remaining.min(PAGE_SIZE), offset };
+    let songs = match client.request(&request).await? {
+      IpcResponse::Songs { songs } => songs,
+      IpcResponse::Error { message } => return Err(eyre!("the daemon failed to list songs: {message}")),
+      response => return Err(eyre!("unexpected response from the daemon: {response:?}")),
+    };
+    if songs.is_empty() {
+      break;
+    }
+    offset += songs.len() as i64;
+    remaining -= songs.len() as i64;
+    for song in songs {
+      writer.write_row(song)?;
+    }
+  }
+  Ok(())
+}
+
+fn is_broken_pipe(error: &color_eyre::eyre::Report) -> bool {
+  error.chain().any(|cause| {
+    cause.downcast_ref::<std::io::Error>().is_some_and(|error| error.kind() == std::io::ErrorKind::BrokenPipe)
+      || cause.downcast_ref::<csv::Error>().is_some_and(
+        |error| matches!(error.kind(), csv::ErrorKind::Io(error) if error.kind() == std::io::ErrorKind::BrokenPipe),
+      )
+  })
+}
+
 async fn tokio_main() -> Result<()> {
   initialize_logging()?;
 
   initialize_panic_handler()?;
 
-  let args = Cli::parse();
-  let mut app = App::new(args.tick_rate, args.frame_rate).await?;
-  app.run().await?;
+  // yt-dlp may have been downloaded by the startup check
+  paths::prepend_bin_dir(&Paths::current())?;
 
-  Ok(())
+  let args = Cli::try_parse().map_err(|error| {
+    // help, version and usage errors are printed by clap, unless usage errors are wanted as JSON
+    if !error.use_stderr() || !json_errors_requested() {
+      error.exit();
+    }
+    color_eyre::eyre::Report::new(error)
+  })?;
+  match args.command {
+    Some(Command::Report { period, format, date }) => {
+      let config = Config::new()?;
+      let mut database = Database::new(config.clone()).await?;
+      let date = date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+      let path = Report::generate(&mut database, period, date)?.write(&config, format)?;
+      println!("report written to {}", path.display());
+      return Ok(());
+    },
+    Some(Command::Scan { ref paths }) => {
+      scan_music_dir(&args, paths).await?;
+      return Ok(());
+    },
+    Some(Command::Health) => {
+      let config = Config::new()?;
+      let rules = scan::IgnoreRules::new(&config.config.scan)?;
+      let mut database = Database::new(config.clone()).await?;
+      print!("{}", health::check(&config.config.music_dir, &mut database, &rules)?.to_text());
+      return Ok(());
+    },
+    Some(Command::List { ref query, ref output }) => {
+      let query = query.as_deref().map(Query::parse).transpose()?;
+      list_songs(&args, query, output).await?;
+      return Ok(());
+    },
+    Some(Command::Search { ref text, ref output }) => {
+      search_songs(&args, text, output).await?;
+      return Ok(());
+    },
+    Some(Command::Download { ref target, ref title, ref artists, ref album, duplicates }) => {
+      let overrides =
+        fetch::Overrides { title: title.clone(), artists: artists.clone(), album: album.clone(), duplicates };
+      download(&args, target, &overrides).await?;
+      return Ok(());
+    },
+    Some(Command::AlbumType { ref album, release_type }) => {
+      let mut database = Database::new(Config::new()?).await?;
+      let found = database
+        .get_album_by_name(album)?
+        .ok_or_else(|| eyre!("no album named {album:?}"))
+        .wrap_err(ErrorCategory::Usage)?;
+      match (release_type, args.dry_run) {
+        (Some(release_type), true) => println!("would make {} a {release_type}", found.name),
+        (None, true) => println!("would clear the release type of {}", found.name),
+        (Some(release_type), false) => {
+          database.set_album_release_type(found.id, Some(release_type))?;
+          println!("{} is now a {release_type}", found.name);
+        },
+        (None, false) => {
+          database.set_album_release_type(found.id, None)?;
+          println!("cleared the release type of {}", found.name);
+        },
+      }
+      return Ok(());
+    },
+    Some(Command::Lock { song, field, unlock }) => {
+      let mut database = Database::new(Config::new()?).await?;
+      let found = database.get_song_from_id(song).wrap_err(ErrorCategory::Usage)?;
+      let verb = if unlock { "unlock" } else { "lock" };
+      match args.dry_run {
+        true => println!("would {verb} the {field} of {}", found.title),
+        false => {
+          database.set_song_field_locked(song, field, !unlock)?;
+          println!("{verb}ed the {field} of {}", found.title);
+        },
+      }
+      return Ok(());
+    },
+    Some(Command::Provenance { song }) => {
+      let mut database = Database::new(Config::new()?).await?;
+      database.get_song_from_id(song).wrap_err(ErrorCategory::Usage)?;
+      for record in database.get_song_provenance(song)? {
+        let value = record.value.as_deref().unwrap_or("None").replace('\n', ", ");
+        print!("{}: {value} (from {})", record.field, record.source);
+        match (record.previous_source, record.previous_value) {
+          (Some(source), value) => {
+            println!(", replaced {} (from {source})", value.as_deref().unwrap_or("None").replace('\n', ", "))
+          },
+          _ => println!(),
+        }
+      }
+      return Ok(());
+    },
+    Some(Command::DownloadParams { song }) => {
+      let mut database = Database::new(Config::new()?).await?;
+      let found = database.get_song_from_id(song).wrap_err(ErrorCategory::Usage)?;
+      let Some(params) = database.get_song_download_params(song)? else {
+        println!("{} was not downloaded by muzik, or before its downloads were recorded", found.title);
+        return Ok(());
+      };
+      println!("video: {}", params.youtube_id);
+      println!("format: {} (tried {})", params.format, params.formats.replace('\n', ", "));
+      println!("yt-dlp: {}", params.yt_dlp_version.as_deref().unwrap_or("unknown"));
+      println!("comments: {}, proxy: {}, silence trimmed: {}", params.comments, params.proxy, params.trim_silence);
+      println!("downloaded at: {}", params.downloaded_at);
+      return Ok(());
+    },
+    Some(Command::Revert { source }) => {
+      let mut database = Database::new(Config::new()?).await?;
+      let reverted = match args.dry_run {
+        true => database.rehearse(|database| database.revert_source(source))?,
+        false => database.revert_source(source)?,
+      };
+      let value = |value: &Option<String>| value.as_deref().unwrap_or("None").replace('\n', ", ");
+      for record in &reverted {
+        println!(
+          "song {} {}: {} -> {}",
+          record.song_id,
+          record.field,
+          value(&record.value),
+          value(&record.previous_value)
+        );
+      }
+      let verb = if args.dry_run { "would revert" } else { "reverted" };
+      println!("{verb} {} fields set by {source}", reverted.len());
+      return Ok(());
+    },
+    Some(Command::Attach { ref file, ref album, song }) => {
+      let config = Config::new()?;
+      let mut database = Database::new(config.clone()).await?;
+      let owner = match (album, song) {
+        (Some(album), _) => {
+          let found = database
+            .get_album_by_name(album)?
+            .ok_or_else(|| eyre!("no album named {album:?}"))
+            .wrap_err(ErrorCategory::Usage)?;
+          AttachmentOwner::Album(found)
+        },
+        (None, Some(song)) => {
+          let found = database.get_song_from_id(song).wrap_err(ErrorCategory::Usage)?;
+          AttachmentOwner::Song(found)
+        },
+        (None, None) => return Err(eyre!("pass an album or a song to attach to")).wrap_err(ErrorCategory::Usage),
+      };
+      if args.dry_run {
+        let attachment = attachments::plan(&config.config.music_dir, &owner, file).wrap_err(ErrorCategory::Usage)?;
+        println!("would copy {} to {} and attach it as {}", file.display(), attachment.relative_path, attachment.kind);
+        return Ok(());
+      }
+      let attachment =
+        attachments::attach(&config.config.music_dir, &mut database, &owner, file).wrap_err(ErrorCategory::Usage)?;
+      println!("attached {} as {}", attachment.kind, attachment.relative_path);
+      return Ok(());
+    },
+    Some(Command::Migrate) => {
+      let mut config = Config::new()?;
+      config.config.database.auto_migrate = false;
+      let mut database = Database::new(config).await?;
+      if args.dry_run {
+        let pending = database.pending_migrations()?;
+        let from = database.schema_version()?.unwrap_or_else(|| "an empty database".to_string());
+        match pending.last() {
+          Some(to) => println!("would migrate the database from {from} to {to}, running {}", pending.join(", ")),
+          None => println!("the database is up to date at {from}"),
+        }
+        return Ok(());
+      }
+      println!("{}", database.migrate()?);
+      return Ok(());
+    },
+    Some(Command::Daemon) => {
+      let config = Config::new()?;
+      termux::check(&config).wrap_err(ErrorCategory::Io)?;
+      daemon::run(config).await?;
+      return Ok(());
+    },
+    Some(Command::SplitMix { video, tracklist, album }) => {
+      let tracklist = if tracklist == std::path::Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+      } else {
+        std::fs::read_to_string(&tracklist)?
+      };
+      let tracks = mixes::parse_tracklist(&tracklist)?;
+      if args.dry_run {
+        let album = album.as_deref().unwrap_or("the title of the video");
+        println!("would download {video} and split it into {} tracks of {album}:", tracks.len());
+        for track in &tracks {
+          println!("{track}");
+        }
+        return Ok(());
+      }
+      let (album, songs) = mixes::split_mix(Config::new()?, &video, &tracks, album).await?;
+      println!("added {} tracks to {album}", songs.len());
+      return Ok(());
+    },
+    #[cfg(feature = "debug")]
+    Some(Command::Dev { command: DevCommand::Seed { artists, seed, files } }) => {
+      let config = Config::new()?;
+      let mut database = Database::new(config.clone()).await?;
+      // the files are only written for real
+      let options = seed::SeedOptions { artists, seed, files: files && !args.dry_run };
+      let seeded = match args.dry_run {
+        true => database.rehearse(|database| seed::seed(database, &config.config.music_dir, options))?,
+        false => seed::seed(&mut database, &config.config.music_dir, options)?,
+      };
+      let verb = if args.dry_run { "would add" } else { "added" };
+      println!("{verb} {} artists, {} albums and {} songs", seeded.artists, seeded.albums, seeded.songs);
+      return Ok(());
+    },
+    Some(Command::Cache { command: CacheCommand::Clean { all } }) => {
+      let config = Config::new()?;
+      let cache_dir = &config.paths.cache_dir;
+      let settings = if all { &cache::EVERYTHING } else { &config.config.cache };
+      let cleaned = match args.dry_run {
+        true => cache::plan(cache::cached_files(cache_dir)?, settings, std::time::SystemTime::now()),
+        false => cache::clean(cache_dir, settings)?,
+      };
+      for file in &cleaned.removed {
+        println!("{}", file.path.display());
+      }
+      let verb = if args.dry_run { "would remove" } else { "removed" };
+      println!(
+        "{verb} {} files, {} KB, leaving {} KB in {}",
+        cleaned.removed.len(),
+        cleaned.freed().div_ceil(1024),
+        cleaned.size.div_ceil(1024),
+        cache_dir.display()
+      );
+      return Ok(());
+    },
+    Some(Command::Snapshots { ref command }) => {
+      let config = Config::new()?;
+      let music_dir = config.config.music_dir.clone();
+      let mut database = Database::new(config).await?;
+      match command {
+        SnapshotsCommand::Take { .. } if args.dry_run => {
+          let songs = snapshots::capture(&mut database, &music_dir)?;
+          println!("would take a snapshot of {} songs", songs.len());
+        },
+        SnapshotsCommand::Take { name } => {
+          let (name, count) = snapshots::take(&mut database, &music_dir, name.as_deref().unwrap_or_default())?;
+          println!("took the snapshot {name} of {count} songs");
+        },
+        SnapshotsCommand::List => {
+          for snapshot in database.get_snapshots()? {
+            println!("{}\t{}\t{}", snapshot.id, snapshot.taken_at.format("%Y-%m-%d %H:%M:%S"), snapshot.name);
+          }
+        },
+        SnapshotsCommand::Diff { from, to } => {
+          let diff = snapshots::compare(&mut database, &music_dir, *from, *to)?;
+          let song = |song: &SnapshotSong| {
+            match song.artists.is_empty() {
+              true => song.title.clone(),
+              false => format!("{} - {}", song.artists, song.title),
+            }
+          };
+          for added in &diff.added {
+            println!("+ {}", song(added));
+          }
+          for removed in &diff.removed {
+            println!("- {}", song(removed));
+          }
+          for changed in &diff.changed {
+            println!("~ {} ({})", song(&changed.after), changed.what());
+          }
+          println!("{}: {}", diff.name, diff.summary());
+        },
+        SnapshotsCommand::Delete { .. } if args.dry_run => println!("would delete the snapshot"),
+        SnapshotsCommand::Delete { snapshot } => {
+          database.delete_snapshot(*snapshot)?;
+          println!("deleted the snapshot {snapshot}");
+        },
+      }
+      return Ok(());
+    },
+    Some(Command::Playlists { command: PlaylistsCommand::Export { ref directory, paths } }) => {
+      let config = Config::new()?;
+      let directory = directory.clone().unwrap_or_else(|| playlists::directory(&config));
+      let path_prefix = playlists::path_prefix(&config, &directory, paths.unwrap_or(config.config.playlists.paths))?;
+      let mut database = Database::new(config).await?;
+      let entries = playlists::load_entries(&mut database)?;
+      let stored = playlists::load_stored(&mut database)?;
+      let all = playlists::all_playlists(&entries, &stored);
+      if args.dry_run {
+        for (name, songs) in &all {
+          println!("{name}: {} songs", songs.len());
+        }
+        println!("would write {} playlists into {}", all.len(), directory.display());
+      } else {
+        let written = playlists::write_playlists(&directory, &all, &path_prefix)?;
+        println!("wrote {written} playlists of {} songs into {}", entries.len(), directory.display());
+      }
+      return Ok(());
+    },
+    Some(Command::Playlists { command: PlaylistsCommand::Sync { ref directory } }) => {
+      let config = Config::new()?;
+      let directory = directory
+        .clone()
+        .or_else(|| playlist_sync::directory(&config))
+        .ok_or_else(|| eyre!("no directory to sync, set playlists.sync_directory or pass --directory"))?;
+      let mut database = Database::new(config.clone()).await?;
+      let report = playlist_sync::sync(&config, &directory, &mut database, args.dry_run)?;
+      for change in report.changes.iter().filter(|change| !change.is_noop()) {
+        match change {
+          playlist_sync::Change::Update { name, song_ids, write_library, write_file, .. } => {
+            let sides = match (write_library, write_file) {
+              (true, true) => "the library and the file",
+              (true, false) => "the library",
+              _ => "the file",
+            };
+            println!("{name}: {} songs written into {sides}", song_ids.len());
+          },
+          playlist_sync::Change::Delete { name, .. } => println!("{name}: deleted"),
+        }
+      }
+      if report.unmatched > 0 {
+        println!("{} lines of the playlists match no song of the library", report.unmatched);
+      }
+      if args.dry_run {
+        println!("dry run, nothing was changed in {}", directory.display());
+      }
+      return Ok(());
+    },
+    Some(Command::Completions { shell }) => {
+      let mut command = Cli::command();
+      clap_complete::generate(shell, &mut command, env!("CARGO_PKG_NAME"), &mut std::io::stdout());
+      return Ok(());
+    },
+    None => {},
+  }
+
+  #[cfg(feature = "tui")]
+  {
+    // the songs of a remote library are downloaded by its daemon
+    if args.remote.is_none() {
+      termux::check(&Config::new()?).wrap_err(ErrorCategory::Io)?;
+    }
+    let (tick_rate, frame_rate) = match termux::is_active() {
+      true => (termux::TICK_RATE, termux::FRAME_RATE),
+      false => (4.0, 24.0),
+    };
+    let tick_rate = args.tick_rate.unwrap_or(tick_rate);
+    let frame_rate = args.frame_rate.unwrap_or(frame_rate);
+    let mut app = App::new(tick_rate, frame_rate, args.remote()).await?;
+    app.run().await?;
+    Ok(())
+  }
+  #[cfg(not(feature = "tui"))]
+  Err(eyre!("muzik was built without the TUI, run a command such as `muzik daemon`")).wrap_err(ErrorCategory::Usage)
+}
+
+/// Whether errors should be written as JSON. Read from the raw arguments, as errors can happen
+/// before or while the arguments are parsed
+fn json_errors_requested() -> bool {
+  std::env::args().any(|arg| arg == "--json-errors")
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
   if let Err(e) = tokio_main().await {
-    eprintln!("{} error: Something went wrong", env!("CARGO_PKG_NAME"));
-    Err(e)
-  } else {
-    Ok(())
+    let category = ErrorCategory::of(&e);
+    if json_errors_requested() {
+      match serde_json::to_string(&JsonError::from(&e)) {
+        Ok(json) => eprintln!("{json}"),
+        Err(_) => eprintln!("{e}"),
+      }
+    } else {
+      eprintln!("{} error: Something went wrong", env!("CARGO_PKG_NAME"));
+      eprintln!("Error: {e:?}");
+    }
+    std::process::exit(category.code());
   }
 }
@@ -0,0 +1,199 @@
+//! Merging of duplicate entities in the library
+//!
+//! A `MergeCandidate` holds the metadata of two entities side by side. Each field can be picked
+//! from either side, and the picked values make up the merged record. A field locked on a song
+//! starts out picked from that song, and the fields of songs show where their values came from
+
+use crate::models::{Artist, MetadataSource, NewSong, Provenance, Song, SongField};
+
+/// Which side of the comparison a field value is taken from
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MergeSide {
+  #[default]
+  Left,
+  Right,
+}
+
+/// The kind of entity being merged
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MergeKind {
+  #[default]
+  Song,
+  Artist,
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct MergeField {
+  pub name: String,
+  pub left: Option<String>,
+  pub right: Option<String>,
+  pub pick: MergeSide,
+  /// whether the field was corrected by hand on either side
+  pub locked: bool,
+  /// where the values of the sides came from, if recorded
+  pub left_source: Option<MetadataSource>,
+  pub right_source: Option<MetadataSource>,
+}
+
+impl MergeField {
+  pub fn new(name: &str, left: Option<String>, right: Option<String>) -> Self {
+    // prefer the side that actually has a value
+    let pick = if left.is_none() && right.is_some() { MergeSide::Right } else { MergeSide::Left };
+    Self { name: name.to_string(), left, right, pick, ..Default::default() }
+  }
+
+  /// The value of the picked side
+  pub fn picked(&self) -> Option<String> {
+    match self.pick {
+      MergeSide::Left => self.left.clone(),
+      MergeSide::Right => self.right.clone(),
+    }
+  }
+}
+
+/// Two entities of the same kind to be merged into one. The entity on the left is kept and the
+/// one on the right is removed once merged
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct MergeCandidate {
+  pub kind: MergeKind,
+  pub left_id: i32,
+  pub right_id: i32,
+  pub fields: Vec<MergeField>,
+}
+
+impl MergeCandidate {
+  pub fn from_songs(left: &Song, right: &Song) -> Self {
+    Self {
+      kind: MergeKind::Song,
+      left_id: left.id,
+      right_id: right.id,
+      fields: vec![
+        MergeField::new("title", Some(left.title.clone()), Some(right.title.clone())),
+        MergeField::new("youtube_id", left.youtube_id.clone(), right.youtube_id.clone()),
+        MergeField::new("thumbnail_url", left.thumbnail_url.clone(), right.thumbnail_url.clone()),
+        MergeField::new("file_id", left.file_id.map(|id| id.to_string()), right.file_id.map(|id| id.to_string())),
+        MergeField::new(
+          "track_number",
+          left.track_number.map(|n| n.to_string()),
+          right.track_number.map(|n| n.to_string()),
+        ),
+        MergeField::new(
+          "disc_number",
+          left.disc_number.map(|n| n.to_string()),
+          right.disc_number.map(|n| n.to_string()),
+        ),
+      ],
+    }
+  }
+
+  /// Mark the fields locked on either song, picking them from the song they are locked on. A field
+  /// locked on both songs keeps its pick
+  pub fn with_locks(mut self, left: &[SongField], right: &[SongField]) -> Self {
+    for field in &mut self.fields {
+      let name = field.name.clone();
+      let is_locked = |locks: &[SongField]| locks.iter().any(|lock| lock.to_string() == name);
+      match (is_locked(left), is_locked(right)) {
+        (false, false) => continue,
+        (true, false) => field.pick = MergeSide::Left,
+        (false, true) => field.pick = MergeSide::Right,
+        (true, true) => {},
+      }
+      field.locked = true;
+    }
+    self
+  }
+
+  /// Show where the values of the fields of either song came from
+  pub fn with_provenance(mut self, left: &[Provenance], right: &[Provenance]) -> Self {
+    for field in &mut self.fields {
+      let source = |records: &[Provenance]| {
+        records.iter().find(|record| record.field.to_string() == field.name).map(|record| record.source)
+      };
+      (field.left_source, field.right_source) = (source(left), source(right));
+    }
+    self
+  }
+
+  pub fn from_artists(left: &Artist, right: &Artist) -> Self {
+    Self {
+      kind: MergeKind::Artist,
+      left_id: left.id,
+      right_id: right.id,
+      fields: vec![MergeField::new("name", Some(left.name.clone()), Some(right.name.clone()))],
+    }
+  }
+
+  /// The picked value of the field with the given name
+  pub fn picked(&self, name: &str) -> Option<String> {
+    self.fields.iter().find(|field| field.name == name).and_then(MergeField::picked)
+  }
+
+  /// The merged song built from the picked fields
+  pub fn merged_song(&self) -> NewSong {
+    NewSong {
+      title: self.picked("title").unwrap_or_default(),
+      youtube_id: self.picked("youtube_id"),
+      thumbnail_url: self.picked("thumbnail_url"),
+      file_id: self.picked("file_id").and_then(|id| id.parse().ok()),
+      track_number: self.picked("track_number").and_then(|number| number.parse().ok()),
+      disc_number: self.picked("disc_number").and_then(|number| number.parse().ok()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_merged_song_from_picks() {
+    let left = Song { id: 1, title: "Stellar Stellar".to_string(), file_id: Some(3), ..Default::default() };
+    let right = Song {
+      id: 2,
+      title: "stellar stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      track_number: Some(1),
+      ..Default::default()
+    };
+    let mut candidate = MergeCandidate::from_songs(&left, &right);
+    // youtube_id only exists on the right so it is picked by default
+    assert_eq!(candidate.fields[1].pick, MergeSide::Right);
+    let locked = candidate.clone().with_locks(&[SongField::YoutubeId], &[SongField::Title]);
+    let picks: Vec<_> = locked.fields.iter().map(|field| (field.name.as_str(), field.pick, field.locked)).collect();
+    assert_eq!(picks[..2], [("title", MergeSide::Right, true), ("youtube_id", MergeSide::Left, true)]);
+    assert!(!locked.fields[2].locked);
+    let record = |song_id, field, source| {
+      Provenance {
+        song_id,
+        field,
+        source,
+        value: None,
+        previous_source: None,
+        previous_value: None,
+        recorded_at: Default::default(),
+      }
+    };
+    let sourced = candidate.clone().with_provenance(&[record(1, SongField::Title, MetadataSource::FileTag)], &[
+      record(2, SongField::Title, MetadataSource::YtDlp),
+      record(2, SongField::YoutubeId, MetadataSource::YtDlp),
+    ]);
+    let sources: Vec<_> = sourced.fields.iter().map(|field| (field.left_source, field.right_source)).collect();
+    assert_eq!(sources[..3], [
+      (Some(MetadataSource::FileTag), Some(MetadataSource::YtDlp)),
+      (None, Some(MetadataSource::YtDlp)),
+      (None, None),
+    ]);
+
+    candidate.fields[3].pick = MergeSide::Right;
+    assert_eq!(candidate.merged_song(), NewSong {
+      title: "Stellar Stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      thumbnail_url: None,
+      file_id: None,
+      track_number: Some(1),
+      disc_number: None,
+    });
+  }
+}
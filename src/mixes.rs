@@ -0,0 +1,242 @@
+//! Splitting of DJ mixes into their tracks
+//!
+//! The tracklist of a mix, as found in the description of the video, is pasted as text with one
+//! track per line, starting with the timestamp of the track:
+//!
+//! ```text
+//! 00:00 Hoshimachi Suisei - Stellar Stellar
+//! 4:51 - Next Color Planet
+//! [1:02:03] Tokoyami Towa – Palette
+//! ```
+//!
+//! The audio of the mix is downloaded once and cut at the timestamps. Every track becomes a song
+//! of its own, linked to an album named after the mix.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use tracing::warn;
+use youtube_dl::YoutubeDl;
+
+use crate::{
+  config::Config,
+  database::Database,
+  download_logs,
+  errors::ErrorCategory,
+  postprocess,
+  queue::{self, DownloadRequest},
+  scan,
+};
+
+/// A track of a mix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixTrack {
+  /// where the track starts in the mix
+  pub start: Duration,
+  pub artist: Option<String>,
+  pub title: String,
+}
+
+impl std::fmt::Display for MixTrack {
+  /// The track as a line of a tracklist
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let seconds = self.start.as_secs();
+    match seconds / 3600 {
+      0 => write!(f, "{}:{:02} ", seconds / 60, seconds % 60)?,
+      hours => write!(f, "{hours}:{:02}:{:02} ", seconds / 60 % 60, seconds % 60)?,
+    }
+    match &self.artist {
+      Some(artist) => write!(f, "{artist} - {}", self.title),
+      None => write!(f, "{}", self.title),
+    }
+  }
+}
+
+/// Parse a timestamp such as `4:51` or `1:02:03`
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+  let parts = timestamp.split(':').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?;
+  if !(2..=3).contains(&parts.len()) || parts[1..].iter().any(|part| *part >= 60) {
+    return None;
+  }
+  let seconds = parts.iter().try_fold(0u64, |seconds, part| seconds.checked_mul(60)?.checked_add(*part))?;
+  Some(Duration::from_secs(seconds))
+}
+
+/// Parse a line of a tracklist, `None` if it does not start with a timestamp
+fn parse_line(line: &str) -> Option<MixTrack> {
+  let mut rest = line.trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '*' | '•'));
+  // a track number before the timestamp, such as `01.`
+  if let Some((number, after)) = rest.split_once(['.', ')']) {
+    if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+      rest = after.trim_start();
+    }
+  }
+  let rest = rest.trim_start_matches(['[', '(']);
+  let end = rest.find(|c: char| !(c.is_ascii_digit() || c == ':')).unwrap_or(rest.len());
+  let start = parse_timestamp(&rest[..end])?;
+  let description =
+    rest[end..].trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ']' | ')' | '-' | '–' | '—' | '|'));
+  let description = description.trim();
+  if description.is_empty() {
+    return None;
+  }
+  let (artist, title) = match [" - ", " – ", " — "].iter().find_map(|separator| description.split_once(separator)) {
+    Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+    None => (None, description.to_string()),
+  };
+  Some(MixTrack { start, artist, title })
+}
+
+/// Parse a timestamped tracklist, skipping the lines without a timestamp
+pub fn parse_tracklist(tracklist: &str) -> Result<Vec<MixTrack>> {
+  let tracks: Vec<_> = tracklist.lines().filter_map(parse_line).collect();
+  if tracks.is_empty() {
+    return Err(eyre!("no timestamped tracks found in the tracklist")).wrap_err(ErrorCategory::Usage);
+  }
+  if let Some(pair) = tracks.windows(2).find(|pair| pair[1].start <= pair[0].start) {
+    return Err(eyre!("\"{}\" does not start after \"{}\"", pair[1].title, pair[0].title))
+      .wrap_err(ErrorCategory::Usage);
+  }
+  Ok(tracks)
+}
+
+/// Download a mix and add its tracks to the library as songs of an album named after the mix
+///
+/// # Arguments
+///
+/// * `video` - the id or URL of the video of the mix
+/// * `tracks` - the tracklist of the mix
+/// * `album` - the name of the album, the title of the video if `None`
+///
+/// # Returns
+///
+/// * the name of the album and the ids of the new songs
+pub async fn split_mix(
+  config: Config,
+  video: &str,
+  tracks: &[MixTrack],
+  album: Option<String>,
+) -> Result<(String, Vec<i32>)> {
+  let mut database = Database::new(config.clone()).await?;
+  let metadata = YoutubeDl::new(video)
+    .run_async()
+    .await?
+    .into_single_video()
+    .ok_or_else(|| eyre!("{video} is a playlist, not a mix"))?;
+  let album = album.unwrap_or_else(|| metadata.title.clone().unwrap_or_else(|| metadata.id.clone()));
+  let mix = DownloadRequest {
+    youtube_id: metadata.id.clone(),
+    title: album.clone(),
+    artists: metadata.uploader.clone().into_iter().collect(),
+    album: Some(album.clone()),
+    ..Default::default()
+  };
+
+  let directory = std::env::temp_dir().join(format!("muzik-mix-{}", uuid::Uuid::new_v4()));
+  let result = async {
+    let log_dir = download_logs::log_dir(&config);
+    let options = queue::DownloadOptions {
+      comments: false,
+      formats: &config.config.download.formats,
+      log_dir: &log_dir,
+      proxy: None,
+      progress: None,
+    };
+    let downloaded = queue::download_audio(&mix, &directory, &options).await?;
+    add_tracks(&config, &mut database, &downloaded.path, &mix, tracks).await
+  }
+  .await;
+  let _ = std::fs::remove_dir_all(&directory);
+  Ok((album, result?))
+}
+
+/// Cut the downloaded mix into its tracks in the music dir and add them to the library
+async fn add_tracks(
+  config: &Config,
+  database: &mut Database,
+  source: &Path,
+  mix: &DownloadRequest,
+  tracks: &[MixTrack],
+) -> Result<Vec<i32>> {
+  let extension = source.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let mut song_ids = vec![];
+  for (index, track) in tracks.iter().enumerate() {
+    let relative_path = format!("{}-{:02}.{extension}", mix.youtube_id, index + 1);
+    let artists = track.artist.clone().map(|artist| vec![artist]).unwrap_or_else(|| mix.artists.clone());
+    let request =
+      DownloadRequest { title: track.title.clone(), artists, track_number: Some(index as i32 + 1), ..mix.clone() };
+    let end = tracks.get(index + 1).map(|next| next.start);
+    postprocess::cut(source, &config.config.music_dir.join(&relative_path), track.start, end, &request.tags()).await?;
+    song_ids.push(queue::add_song(database, relative_path.clone(), &request)?);
+    if let Err(e) = scan::record_quality(database, &config.config.music_dir, &relative_path).await {
+      warn!("failed to read the quality of {relative_path}: {e}");
+    }
+  }
+  Ok(song_ids)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use proptest::prelude::*;
+
+  use super::*;
+
+  proptest! {
+    #[test]
+    fn test_parse_line_never_panics(line in prop_oneof![any::<String>(), "[0-9]{1,25}(:[0-9]{1,3}){1,3} \\PC*"]) {
+      if let Some(track) = parse_line(&line) {
+        prop_assert!(!track.title.is_empty() && track.title.trim() == track.title, "{track:?}");
+        prop_assert!(track.artist.iter().all(|artist| !artist.is_empty()), "{track:?}");
+      }
+    }
+
+    #[test]
+    fn test_parse_line_reads_what_is_written(
+      minutes in 0u64..600,
+      seconds in 0u64..60,
+      artist in "[^\\s\\-–—|\\])][^\\-–—]{0,20}[^\\s\\-–—]",
+      title in "[^\\s\\-–—]([^\\-–—]{0,20}[^\\s\\-–—])?",
+    ) {
+      let track = parse_line(&format!("{minutes}:{seconds:02} {artist} - {title}")).expect("a timestamped line");
+      prop_assert_eq!(track.start, Duration::from_secs(minutes * 60 + seconds));
+      prop_assert_eq!(track.artist.as_deref(), Some(artist.trim()));
+      prop_assert_eq!(track.title, title.trim());
+    }
+  }
+
+  #[test]
+  fn test_parse_tracklist() -> Result<()> {
+    let tracks = parse_tracklist(
+      "Tracklist:\n00:00 Hoshimachi Suisei - Stellar Stellar\n01. 4:51 - Next Color Planet\n[1:02:03] Tokoyami Towa – \
+       Palette\n",
+    )?;
+    assert_eq!(tracks, vec![
+      MixTrack {
+        start: Duration::ZERO,
+        artist: Some("Hoshimachi Suisei".to_string()),
+        title: "Stellar Stellar".to_string()
+      },
+      MixTrack { start: Duration::from_secs(291), artist: None, title: "Next Color Planet".to_string() },
+      MixTrack {
+        start: Duration::from_secs(3723),
+        artist: Some("Tokoyami Towa".to_string()),
+        title: "Palette".to_string()
+      },
+    ]);
+    assert_eq!(tracks.iter().map(MixTrack::to_string).collect::<Vec<_>>(), vec![
+      "0:00 Hoshimachi Suisei - Stellar Stellar",
+      "4:51 Next Color Planet",
+      "1:02:03 Tokoyami Towa - Palette"
+    ]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_parse_tracklist_errors() {
+    assert!(parse_tracklist("no timestamps here").is_err());
+    assert!(parse_tracklist("4:51 Second\n0:00 First").is_err());
+    assert_eq!(parse_timestamp("1:75"), None);
+    assert_eq!(parse_timestamp("999999999999999999:00"), None);
+  }
+}
@@ -7,4 +7,6 @@ pub enum Mode {
   Home,
   Download,
   Manager,
+  Jobs,
+  NowPlaying,
 }
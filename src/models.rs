@@ -1,7 +1,17 @@
-use diesel::prelude::*;
-use serde::Deserialize;
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use diesel::{
+  backend::Backend,
+  deserialize::{self, FromSql, FromSqlRow},
+  expression::AsExpression,
+  prelude::*,
+  serialize::{self, Output, ToSql},
+  sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString, IntoStaticStr};
 
-#[derive(Default, Queryable, Selectable, Identifiable, Debug, PartialEq)]
+#[derive(Default, Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
 #[diesel(table_name=crate::schema::song)]
 pub struct Song {
   pub id: i32,
@@ -9,9 +19,26 @@ pub struct Song {
   pub youtube_id: Option<String>,
   pub thumbnail_url: Option<String>,
   pub file_id: Option<i32>,
+  pub play_count: i32,
+  /// when the song was added to the library. Unknown for songs added before this was tracked
+  pub added_at: Option<NaiveDateTime>,
+  /// from 1 to 5 stars
+  pub rating: Option<i32>,
+  /// the position of the song on its disc
+  pub track_number: Option<i32>,
+  /// the disc of a multi-disc album the song is on
+  pub disc_number: Option<i32>,
 }
 
-#[derive(Default, Associations, Insertable, Deserialize, PartialEq, Eq)]
+impl Song {
+  /// The order of the song on its album, by disc then by track. Songs without a disc are on the
+  /// first one, and songs without a track go after the numbered ones
+  pub fn album_position(&self) -> (i32, i32) {
+    (self.disc_number.unwrap_or(1), self.track_number.unwrap_or(i32::MAX))
+  }
+}
+
+#[derive(Default, Associations, Insertable, Deserialize, Debug, PartialEq, Eq)]
 #[diesel(belongs_to(File))]
 #[diesel(table_name=crate::schema::song)]
 pub struct NewSong {
@@ -19,9 +46,11 @@ pub struct NewSong {
   pub youtube_id: Option<String>,
   pub thumbnail_url: Option<String>,
   pub file_id: Option<i32>,
+  pub track_number: Option<i32>,
+  pub disc_number: Option<i32>,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Debug, PartialEq, Eq)]
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
 #[diesel(table_name=crate::schema::artist)]
 pub struct Artist {
   pub id: i32,
@@ -34,11 +63,64 @@ pub struct NewArtist {
   pub name: String,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Debug)]
+#[derive(Default, Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
 #[diesel(table_name=crate::schema::album)]
 pub struct Album {
   pub id: i32,
   pub name: String,
+  pub year: Option<i32>,
+  pub label: Option<String>,
+  pub catalog_number: Option<String>,
+  pub release_type: Option<ReleaseType>,
+}
+
+/// What kind of release an album is, so that singles can be told apart from full albums
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Display,
+  IntoStaticStr,
+  EnumString,
+  ValueEnum,
+  AsExpression,
+  FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ReleaseType {
+  Album,
+  Single,
+  Ep,
+  Compilation,
+  Soundtrack,
+  Live,
+}
+
+impl<DB> FromSql<Text, DB> for ReleaseType
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for ReleaseType
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -47,7 +129,7 @@ pub struct NewAlbum {
   pub name: String,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Debug)]
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
 #[diesel(table_name=crate::schema::genre)]
 pub struct Genre {
   pub id: i32,
@@ -60,11 +142,20 @@ pub struct NewGenre {
   pub name: String,
 }
 
-#[derive(Identifiable, Selectable, Queryable, Debug)]
+#[derive(Identifiable, Selectable, Queryable, Debug, Clone, Default, PartialEq, Eq)]
 #[diesel(table_name=crate::schema::file)]
 pub struct File {
   pub id: i32,
   pub relative_path: String,
+  /// the audio codec as named by ffprobe, unknown for files recorded before it was
+  pub codec: Option<String>,
+  /// in kbit/s
+  pub bitrate: Option<i32>,
+  /// the SHA-256 checksum of the file as hex when it was last verified
+  pub checksum: Option<String>,
+  pub verified_at: Option<NaiveDateTime>,
+  /// what the last verification found wrong with the file, `None` if nothing
+  pub problem: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -102,3 +193,447 @@ pub struct SongGenre {
   pub song_id: i32,
   pub genre_id: i32,
 }
+
+#[derive(Identifiable, Selectable, Queryable, Associations, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::play)]
+#[diesel(belongs_to(Song))]
+pub struct Play {
+  pub id: i32,
+  pub song_id: i32,
+  pub played_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name=crate::schema::play)]
+pub struct NewPlay {
+  pub song_id: i32,
+  pub played_at: NaiveDateTime,
+}
+
+/// The text of the video a song was downloaded from, kept for the credits it often contains
+#[derive(Default, Queryable, Selectable, Insertable, AsChangeset, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::song_extra)]
+#[diesel(treat_none_as_null = true)]
+pub struct SongExtra {
+  pub song_id: i32,
+  pub description: Option<String>,
+  /// the top comments that look like tracklists or lyrics
+  pub comments: Option<String>,
+}
+
+/// The last check of whether the video of a song is still up
+#[derive(Default, Queryable, Selectable, Insertable, AsChangeset, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::link_check)]
+#[diesel(treat_none_as_null = true)]
+pub struct LinkCheck {
+  pub song_id: i32,
+  pub checked_at: NaiveDateTime,
+  /// why the video can not be downloaded anymore, `None` if it still can
+  pub dead_reason: Option<String>,
+  /// the video found by searching for the song again, to link a dead song to instead
+  pub suggestion_id: Option<String>,
+  pub suggestion_title: Option<String>,
+  /// from 0 to 100
+  pub suggestion_score: Option<i32>,
+}
+
+/// The outcome of a finished download
+#[derive(Identifiable, Selectable, Queryable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::download)]
+pub struct Download {
+  pub id: i32,
+  pub youtube_id: String,
+  pub title: String,
+  /// why the download failed, `None` if it succeeded
+  pub error: Option<String>,
+  pub finished_at: NaiveDateTime,
+  /// the entry of the `formats` download setting that was obtained
+  pub format: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name=crate::schema::download)]
+pub struct NewDownload {
+  pub youtube_id: String,
+  pub title: String,
+  pub error: Option<String>,
+  pub finished_at: NaiveDateTime,
+  pub format: Option<String>,
+}
+
+/// How the file of a song was downloaded, so that a download of it again gets the same audio
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::download_params)]
+#[diesel(treat_none_as_null = true)]
+pub struct DownloadParams {
+  pub file_id: i32,
+  pub youtube_id: String,
+  /// the entry of the `formats` download setting that was obtained
+  pub format: String,
+  /// the `formats` download setting of the time, one format per line
+  pub formats: String,
+  /// whether the top comments were fetched
+  pub comments: bool,
+  /// whether it went through the proxy
+  pub proxy: bool,
+  /// whether the silence at its ends was trimmed
+  pub trim_silence: bool,
+  /// `None` if yt-dlp did not tell
+  pub yt_dlp_version: Option<String>,
+  pub downloaded_at: NaiveDateTime,
+}
+
+/// What a pin on the Home screen leads to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, IntoStaticStr, EnumString, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[strum(serialize_all = "lowercase")]
+pub enum PinKind {
+  Artist,
+  Album,
+  Playlist,
+}
+
+impl<DB> FromSql<Text, DB> for PinKind
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for PinKind
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
+}
+
+/// What a file attached to a song or album holds, told by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, IntoStaticStr, EnumString, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[strum(serialize_all = "lowercase")]
+pub enum AttachmentKind {
+  Booklet,
+  Lyrics,
+  Cover,
+  Other,
+}
+
+impl AttachmentKind {
+  pub fn from_extension(extension: &str) -> Self {
+    match extension.to_lowercase().as_str() {
+      "pdf" => AttachmentKind::Booklet,
+      "lrc" | "txt" => AttachmentKind::Lyrics,
+      "jpg" | "jpeg" | "png" | "webp" => AttachmentKind::Cover,
+      _ => AttachmentKind::Other,
+    }
+  }
+}
+
+impl<DB> FromSql<Text, DB> for AttachmentKind
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for AttachmentKind
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
+}
+
+/// A file that is not a song, such as a booklet or lyrics, attached to either a song or an album
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::attachment)]
+pub struct Attachment {
+  pub id: i32,
+  pub song_id: Option<i32>,
+  pub album_id: Option<i32>,
+  pub kind: AttachmentKind,
+  /// the path of the copy in the music dir
+  pub relative_path: String,
+  pub added_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::attachment)]
+pub struct NewAttachment {
+  pub song_id: Option<i32>,
+  pub album_id: Option<i32>,
+  pub kind: AttachmentKind,
+  pub relative_path: String,
+}
+
+/// How an alternate version of a song differs from the original
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, IntoStaticStr, EnumString, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[strum(serialize_all = "lowercase")]
+pub enum VersionKind {
+  /// the off-vocal version, for karaoke
+  Instrumental,
+  /// a part of the song cut out of its file, such as a concert rip without its intro
+  Trimmed,
+}
+
+impl<DB> FromSql<Text, DB> for VersionKind
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for VersionKind
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
+}
+
+/// A song that is an alternate version of another song in the library
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::song_version)]
+pub struct SongVersion {
+  pub song_id: i32,
+  pub original_id: i32,
+  pub kind: VersionKind,
+}
+
+/// An artist, album or playlist pinned to the Quick Access section of the Home screen
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::pinned)]
+pub struct Pin {
+  pub id: i32,
+  pub kind: PinKind,
+  pub name: String,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::pinned)]
+pub struct NewPin {
+  pub kind: PinKind,
+  pub name: String,
+}
+
+/// A list of songs put together by hand, in the order they were arranged
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::playlist)]
+pub struct Playlist {
+  pub id: i32,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::playlist)]
+pub struct NewPlaylist {
+  pub name: String,
+}
+
+/// A song at a position of a playlist. A song can be in a playlist more than once
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::playlist_songs)]
+pub struct PlaylistSong {
+  pub id: i32,
+  pub playlist_id: i32,
+  pub song_id: i32,
+  /// the songs of a playlist are ordered by their position, which starts at 0
+  pub position: i32,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::playlist_songs)]
+pub struct NewPlaylistSong {
+  pub playlist_id: i32,
+  pub song_id: i32,
+  pub position: i32,
+}
+
+/// An export profile of the settings a playlist is exported with
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::playlist_profiles)]
+pub struct PlaylistProfile {
+  pub playlist_id: i32,
+  /// the name of the profile in the `export.profiles` settings
+  pub profile: String,
+}
+
+/// A metadata field of a song, as locked against changes by automation
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Display,
+  IntoStaticStr,
+  EnumString,
+  ValueEnum,
+  AsExpression,
+  FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[strum(serialize_all = "snake_case")]
+pub enum SongField {
+  Title,
+  Artists,
+  Album,
+  Genres,
+  YoutubeId,
+  ThumbnailUrl,
+  TrackNumber,
+  DiscNumber,
+}
+
+impl<DB> FromSql<Text, DB> for SongField
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for SongField
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
+}
+
+/// A field of a song corrected by hand, left alone by enrichment and re-tagging
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::song_lock)]
+pub struct SongLock {
+  pub song_id: i32,
+  pub field: SongField,
+}
+
+/// Where the value of a metadata field came from
+#[derive(
+  Debug,
+  Default,
+  Clone,
+  Copy,
+  PartialEq,
+  Eq,
+  Hash,
+  Display,
+  IntoStaticStr,
+  EnumString,
+  ValueEnum,
+  Serialize,
+  Deserialize,
+  AsExpression,
+  FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+pub enum MetadataSource {
+  /// the video and its search result
+  #[default]
+  #[strum(serialize = "yt-dlp")]
+  YtDlp,
+  #[strum(serialize = "musicbrainz")]
+  #[value(name = "musicbrainz")]
+  MusicBrainz,
+  /// edited or picked by hand
+  #[strum(serialize = "manual")]
+  Manual,
+  /// the tags of a file found by a scan
+  #[strum(serialize = "file-tag")]
+  FileTag,
+  /// an imported playlist
+  #[strum(serialize = "import")]
+  Import,
+}
+
+impl<DB> FromSql<Text, DB> for MetadataSource
+where
+  DB: Backend,
+  String: FromSql<Text, DB>,
+{
+  fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+    let value = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+    Ok(value.parse()?)
+  }
+}
+
+impl<DB> ToSql<Text, DB> for MetadataSource
+where
+  DB: Backend,
+  str: ToSql<Text, DB>,
+{
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+    <str as ToSql<Text, DB>>::to_sql(self.into(), out)
+  }
+}
+
+/// The source of the current value of a field of a song, and of the value it replaced so that
+/// the change can be reverted
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::provenance)]
+#[diesel(treat_none_as_null = true)]
+pub struct Provenance {
+  pub song_id: i32,
+  pub field: SongField,
+  pub source: MetadataSource,
+  /// artists and genres are stored one name per line
+  pub value: Option<String>,
+  pub previous_source: Option<MetadataSource>,
+  pub previous_value: Option<String>,
+  pub recorded_at: NaiveDateTime,
+}
+
+/// The state of the library at a point in time
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::snapshot)]
+pub struct Snapshot {
+  pub id: i32,
+  pub name: String,
+  pub taken_at: NaiveDateTime,
+}
+
+/// A song of the library as it was when a snapshot was taken. The song may be gone since
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Default, PartialEq, Eq)]
+#[diesel(table_name=crate::schema::snapshot_song)]
+pub struct SnapshotSong {
+  pub snapshot_id: i32,
+  pub song_id: i32,
+  pub title: String,
+  /// the names of the artists joined by commas, to tell the song apart once it is gone
+  pub artists: String,
+  /// the SHA-256 hash as hex of the title, video, artists, album, genres and position of the song
+  pub metadata_hash: String,
+  /// the SHA-256 hash as hex of the path, size and modification time of the file, `None` if the
+  /// song had no file or it was gone
+  pub file_hash: Option<String>,
+}
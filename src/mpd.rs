@@ -0,0 +1,296 @@
+//! Playback with a running MPD
+//!
+//! Instead of the built-in player, songs can be played by an MPD set in `mpd.host`, which is sent
+//! the paths of the files relative to its own music directory. The playlists stored in the library
+//! are loaded into MPD as its stored playlists, replacing those of the same name, and the song MPD
+//! plays is shown in the title bar. MPD is spoken to with its text protocol, over TCP or over its
+//! socket when the host is a path.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+  net::{TcpStream, UnixStream},
+};
+
+use crate::{config::MpdConfig, database::DatabaseHandle, playlists};
+
+/// How long connecting to MPD may take, so that an MPD gone away does not hold up the TUI
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the TUI asks MPD for the song it plays
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Whether MPD is playing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MpdState {
+  Play,
+  Pause,
+  #[default]
+  Stop,
+}
+
+/// What MPD is playing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MpdStatus {
+  pub state: MpdState,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  /// the file of the song, relative to the music directory of MPD
+  pub file: Option<String>,
+  pub elapsed: Option<Duration>,
+  pub duration: Option<Duration>,
+}
+
+impl MpdStatus {
+  /// The song as shown, its artist and title or else its file
+  pub fn label(&self) -> Option<String> {
+    match (&self.artist, &self.title) {
+      (Some(artist), Some(title)) => Some(format!("{artist} - {title}")),
+      (None, Some(title)) => Some(title.clone()),
+      _ => self.file.clone(),
+    }
+  }
+}
+
+/// A connection to MPD
+pub struct MpdClient {
+  reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>,
+  writer: Box<dyn AsyncWrite + Unpin + Send>,
+}
+
+/// Quote an argument of a command, escaping its quotes and backslashes
+fn quote(argument: &str) -> String {
+  format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Seconds as sent by MPD, with a fraction
+fn seconds(value: &str) -> Option<Duration> {
+  value.parse::<f64>().ok().filter(|seconds| *seconds >= 0.0).map(Duration::from_secs_f64)
+}
+
+impl MpdClient {
+  /// Connect to the MPD of the settings, if one is set
+  pub async fn connect(config: &MpdConfig) -> Result<Option<Self>> {
+    let Some(host) = &config.host else {
+      return Ok(None);
+    };
+    let connect = async {
+      let (reader, writer): (Box<dyn AsyncRead + Unpin + Send>, Box<dyn AsyncWrite + Unpin + Send>) =
+        match host.starts_with('/') {
+          true => {
+            let (reader, writer) = UnixStream::connect(host).await?.into_split();
+            (Box::new(reader), Box::new(writer))
+          },
+          false => {
+            let (reader, writer) = TcpStream::connect((host.as_str(), config.port)).await?.into_split();
+            (Box::new(reader), Box::new(writer))
+          },
+        };
+      std::io::Result::Ok(Self { reader: BufReader::new(reader), writer })
+    };
+    let mut client =
+      tokio::time::timeout(CONNECT_TIMEOUT, connect).await.map_err(|_| eyre!("MPD at {host} did not answer"))??;
+    let mut greeting = String::new();
+    client.reader.read_line(&mut greeting).await?;
+    if !greeting.starts_with("OK MPD") {
+      return Err(eyre!("{host} is not MPD, it greeted with {:?}", greeting.trim_end()));
+    }
+    if let Some(password) = &config.password {
+      client.command(&format!("password {}", quote(password))).await?;
+    }
+    Ok(Some(client))
+  }
+
+  /// Send a command, or a command list, and read the fields of the answer
+  pub async fn command(&mut self, command: &str) -> Result<Vec<(String, String)>> {
+    self.writer.write_all(format!("{command}\n").as_bytes()).await?;
+    let mut fields = vec![];
+    loop {
+      let mut line = String::new();
+      if self.reader.read_line(&mut line).await? == 0 {
+        return Err(eyre!("MPD closed the connection"));
+      }
+      let line = line.trim_end_matches('\n');
+      if line == "OK" {
+        return Ok(fields);
+      }
+      if let Some(error) = line.strip_prefix("ACK ") {
+        // `[error@command_listNum] {command} message`
+        let message = error.split_once("} ").map_or(error, |(_, message)| message);
+        return Err(eyre!("MPD refused: {message}"));
+      }
+      if let Some((key, value)) = line.split_once(": ") {
+        fields.push((key.to_string(), value.to_string()));
+      }
+    }
+  }
+
+  /// What MPD is playing
+  pub async fn status(&mut self) -> Result<MpdStatus> {
+    let mut status = MpdStatus::default();
+    for (key, value) in self.command("status").await? {
+      match key.as_str() {
+        "state" => {
+          status.state = match value.as_str() {
+            "play" => MpdState::Play,
+            "pause" => MpdState::Pause,
+            _ => MpdState::Stop,
+          }
+        },
+        "elapsed" => status.elapsed = seconds(&value),
+        "duration" => status.duration = seconds(&value),
+        _ => {},
+      }
+    }
+    if status.state != MpdState::Stop {
+      for (key, value) in self.command("currentsong").await? {
+        match key.as_str() {
+          "file" => status.file = Some(value),
+          "Title" => status.title = Some(value),
+          "Artist" => status.artist = Some(value),
+          _ => {},
+        }
+      }
+    }
+    Ok(status)
+  }
+
+  /// Add the file to the queue of MPD and play it, leaving the rest of the queue as it is
+  pub async fn play_file(&mut self, path: &str) -> Result<()> {
+    let id = self
+      .command(&format!("addid {}", quote(path)))
+      .await?
+      .into_iter()
+      .find_map(|(key, value)| (key == "Id").then_some(value))
+      .ok_or_else(|| eyre!("MPD did not tell the id of {path}"))?;
+    self.command(&format!("playid {id}")).await?;
+    Ok(())
+  }
+
+  /// Replace the stored playlist of MPD with the files, creating it if needed
+  pub async fn save_playlist(&mut self, name: &str, paths: &[String]) -> Result<()> {
+    // a playlist is created by adding to it, and removing one that does not exist is refused
+    self.command(&format!("rm {}", quote(name))).await.ok();
+    if paths.is_empty() {
+      return Ok(());
+    }
+    let mut commands = vec!["command_list_begin".to_string()];
+    commands.extend(paths.iter().map(|path| format!("playlistadd {} {}", quote(name), quote(path))));
+    commands.push("command_list_end".to_string());
+    self.command(&commands.join("\n")).await?;
+    Ok(())
+  }
+}
+
+/// The connection to the MPD of the settings, failing when none is set
+pub async fn connect(config: &MpdConfig) -> Result<MpdClient> {
+  MpdClient::connect(config).await?.ok_or_else(|| eyre!("no MPD is set, see mpd.host"))
+}
+
+/// Play the song with MPD
+///
+/// # Returns
+///
+/// * the title of the song, and the file MPD was told to play
+pub async fn play_song(config: &MpdConfig, database: &DatabaseHandle, song_id: i32) -> Result<(String, String)> {
+  let (title, path) = database
+    .call(move |database| {
+      let song = database.get_song_from_id(song_id)?;
+      let path = database.get_song_file(song_id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+      Ok((song.title, path))
+    })
+    .await?;
+  let file = format!("{}{path}", config.path_prefix);
+  connect(config).await?.play_file(&file).await?;
+  Ok((title, file))
+}
+
+/// Load the playlists stored in the library into MPD, leaving out their songs without a file
+///
+/// # Returns
+///
+/// * the number of playlists loaded
+pub async fn load_playlists(config: &MpdConfig, database: &DatabaseHandle) -> Result<usize> {
+  let (entries, stored) =
+    database.call(|database| Ok((playlists::load_entries(database)?, playlists::load_stored(database)?))).await?;
+  let mut client = connect(config).await?;
+  for (name, song_ids) in &stored {
+    let paths: Vec<String> = song_ids
+      .iter()
+      .filter_map(|song_id| entries.iter().find(|entry| entry.song_id == *song_id))
+      .map(|entry| format!("{}{}", config.path_prefix, entry.path))
+      .collect();
+    client.save_playlist(name, &paths).await?;
+  }
+  Ok(stored.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use tokio::net::TcpListener;
+
+  use super::*;
+
+  /// An MPD answering every command with the next of the answers, keeping the commands received
+  async fn fake_mpd(answers: Vec<&'static str>) -> Result<(MpdConfig, tokio::task::JoinHandle<Vec<String>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.expect("a client");
+      let (reader, mut writer) = stream.into_split();
+      let mut lines = BufReader::new(reader).lines();
+      writer.write_all(b"OK MPD 0.23.5\n").await.expect("the greeting");
+      let mut received = vec![];
+      for answer in answers {
+        let mut command = lines.next_line().await.expect("a command").unwrap_or_default();
+        while command.starts_with("command_list_begin") && !command.ends_with("command_list_end") {
+          command.push('\n');
+          command.push_str(&lines.next_line().await.expect("a command").unwrap_or_default());
+        }
+        received.push(command);
+        writer.write_all(answer.as_bytes()).await.expect("an answer");
+      }
+      received
+    });
+    Ok((MpdConfig { host: Some("127.0.0.1".to_string()), port, ..Default::default() }, server))
+  }
+
+  #[tokio::test]
+  async fn test_mpd_client() -> Result<()> {
+    assert!(MpdClient::connect(&MpdConfig::default()).await?.is_none());
+    let (config, server) = fake_mpd(vec![
+      "volume: 50\nstate: pause\nelapsed: 61.500\nduration: 185.2\nOK\n",
+      "file: Suisei/Stellar Stellar.opus\nTitle: Stellar Stellar\nArtist: Hoshimachi Suisei\nOK\n",
+      "Id: 42\nOK\n",
+      "ACK [50@0] {playid} No such song\n",
+      "ACK [50@0] {rm} No such playlist\n",
+      "OK\n",
+    ])
+    .await?;
+    let mut client = connect(&config).await?;
+    let status = client.status().await?;
+    assert_eq!(status, MpdStatus {
+      state: MpdState::Pause,
+      title: Some("Stellar Stellar".to_string()),
+      artist: Some("Hoshimachi Suisei".to_string()),
+      file: Some("Suisei/Stellar Stellar.opus".to_string()),
+      elapsed: Some(Duration::from_millis(61500)),
+      duration: Some(Duration::from_millis(185200)),
+    });
+    assert_eq!(status.label().as_deref(), Some("Hoshimachi Suisei - Stellar Stellar"));
+    let error = client.play_file("Suisei/\"Ghost\".opus").await.unwrap_err();
+    assert_eq!(error.to_string(), "MPD refused: No such song");
+    client.save_playlist("Drive", &["Suisei/Ghost.opus".to_string()]).await?;
+    assert_eq!(server.await?, vec![
+      "status",
+      "currentsong",
+      "addid \"Suisei/\\\"Ghost\\\".opus\"",
+      "playid 42",
+      "rm \"Drive\"",
+      "command_list_begin\nplaylistadd \"Drive\" \"Suisei/Ghost.opus\"\ncommand_list_end",
+    ]);
+    Ok(())
+  }
+}
@@ -0,0 +1,275 @@
+//! Where muzik keeps its files
+//!
+//! The data dir, config dir, cache dir, music dir and database are resolved here once and carried
+//! by the [Config](crate::config::Config) to everything else. `MUZIK_DATA`, `MUZIK_CONFIG`,
+//! `MUZIK_CACHE` and `MUZIK_MUSIC` override the directories of the platform: the XDG directories
+//! on Linux and Termux, Application Support and Caches on macOS and the local app data on Windows.
+//! On Termux songs go to the shared storage set up by `termux-setup-storage`, where other apps can
+//! play them. The music dir can also be set in the config file.
+
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+use directories::UserDirs;
+
+use crate::attachments::ATTACHMENTS_DIR;
+
+/// Overrides the data dir
+pub const DATA_VAR: &str = "MUZIK_DATA";
+/// Overrides the config dir
+pub const CONFIG_VAR: &str = "MUZIK_CONFIG";
+/// Overrides the cache dir
+pub const CACHE_VAR: &str = "MUZIK_CACHE";
+/// Overrides the default music dir
+pub const MUSIC_VAR: &str = "MUZIK_MUSIC";
+
+/// The name of the directories muzik owns
+const NAME: &str = env!("CARGO_PKG_NAME");
+/// The name of the directories on macOS, made of the qualifier, organization and name
+const BUNDLE_ID: &str = concat!("com.solemnattic.", env!("CARGO_PKG_NAME"));
+const ORGANIZATION: &str = "solemnattic";
+
+/// The system muzik runs on, for where its directories go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+  #[default]
+  Linux,
+  /// Linux running in the Termux app on Android
+  Termux,
+  MacOs,
+  Windows,
+}
+
+impl Platform {
+  /// The platform of this process, Termux being told apart from Linux by its variables or by the
+  /// `termux` feature
+  fn current(vars: &HashMap<String, String>) -> Self {
+    if cfg!(target_os = "macos") {
+      Platform::MacOs
+    } else if cfg!(target_os = "windows") {
+      Platform::Windows
+    } else if cfg!(feature = "termux")
+      || vars.contains_key("TERMUX_VERSION")
+      || vars.get("PREFIX").is_some_and(|prefix| prefix.contains("com.termux"))
+    {
+      Platform::Termux
+    } else {
+      Platform::Linux
+    }
+  }
+}
+
+/// What the paths are resolved from
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+  pub platform: Platform,
+  pub home: Option<PathBuf>,
+  /// the music folder of the user, such as `XDG_MUSIC_DIR` from `user-dirs.dirs`
+  pub audio_dir: Option<PathBuf>,
+  pub vars: HashMap<String, String>,
+  /// debug builds keep their database in the working directory, away from the real library
+  pub debug: bool,
+}
+
+impl Environment {
+  /// The environment of this process
+  pub fn current() -> Self {
+    let vars: HashMap<_, _> = std::env::vars().collect();
+    let user_dirs = UserDirs::new();
+    Self {
+      platform: Platform::current(&vars),
+      home: user_dirs.as_ref().map(|dirs| dirs.home_dir().to_path_buf()),
+      audio_dir: user_dirs.as_ref().and_then(|dirs| dirs.audio_dir().map(Path::to_path_buf)),
+      vars,
+      debug: cfg!(debug_assertions),
+    }
+  }
+
+  /// The variable as a path, if it is set and not empty
+  fn path_var(&self, name: &str) -> Option<PathBuf> {
+    self.vars.get(name).filter(|value| !value.is_empty()).map(PathBuf::from)
+  }
+
+  /// An XDG base directory, which must be absolute, or its default under the home dir
+  fn xdg_dir(&self, name: &str, default: &str) -> Option<PathBuf> {
+    self.path_var(name).filter(|path| path.is_absolute()).or_else(|| Some(self.home.as_ref()?.join(default)))
+  }
+}
+
+/// The files and directories of muzik
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paths {
+  /// the database, logs, backups and other files muzik makes
+  pub data_dir: PathBuf,
+  /// where the config file is looked for
+  pub config_dir: PathBuf,
+  /// the files that can be fetched again, such as cover art
+  pub cache_dir: PathBuf,
+  /// where downloaded songs are stored
+  pub music_dir: PathBuf,
+  /// the attached booklets, lyrics and cover scans, inside the music dir
+  pub attachments_dir: PathBuf,
+  pub database: PathBuf,
+}
+
+impl Paths {
+  /// The paths of this process
+  pub fn current() -> Self {
+    Self::resolve(&Environment::current())
+  }
+
+  pub fn resolve(environment: &Environment) -> Self {
+    let data_dir = environment.path_var(DATA_VAR).or_else(|| platform_dir(environment, Kind::Data));
+    let data_dir = data_dir.unwrap_or_else(|| PathBuf::from(".").join(".data"));
+    let config_dir = environment.path_var(CONFIG_VAR).or_else(|| platform_dir(environment, Kind::Config));
+    let config_dir = config_dir.unwrap_or_else(|| PathBuf::from(".").join(".config"));
+    let cache_dir = environment.path_var(CACHE_VAR).or_else(|| platform_dir(environment, Kind::Cache));
+    let cache_dir = cache_dir.unwrap_or_else(|| PathBuf::from(".").join(".cache"));
+    let music_dir = environment
+      .path_var(MUSIC_VAR)
+      .or_else(|| {
+        match environment.platform {
+          Platform::Termux => Some(environment.home.as_ref()?.join("storage").join("music")),
+          _ => environment.audio_dir.clone(),
+        }
+      })
+      .unwrap_or_else(|| data_dir.join("music"));
+    let database = match environment.debug {
+      true => PathBuf::from(".").join("dev.db"),
+      false => data_dir.join("database.db"),
+    };
+    Self { attachments_dir: music_dir.join(ATTACHMENTS_DIR), data_dir, config_dir, cache_dir, music_dir, database }
+  }
+
+  /// The paths with the music dir set in the config
+  pub fn with_music_dir(self, music_dir: PathBuf) -> Self {
+    Self { attachments_dir: music_dir.join(ATTACHMENTS_DIR), music_dir, ..self }
+  }
+
+  /// Where programs muzik downloads itself, such as yt-dlp, are kept
+  pub fn bin_dir(&self) -> PathBuf {
+    self.data_dir.join("bin")
+  }
+}
+
+/// Look for programs in the bin dir before the directories of the `PATH`, so that the programs
+/// muzik downloads itself are run
+pub fn prepend_bin_dir(paths: &Paths) -> Result<(), std::env::JoinPathsError> {
+  let path = std::env::var_os("PATH").unwrap_or_default();
+  let directories = std::iter::once(paths.bin_dir()).chain(std::env::split_paths(&path));
+  std::env::set_var("PATH", std::env::join_paths(directories)?);
+  Ok(())
+}
+
+enum Kind {
+  Data,
+  Config,
+  Cache,
+}
+
+/// The data or config dir of the platform, the same ones the `directories` crate picks
+fn platform_dir(environment: &Environment, kind: Kind) -> Option<PathBuf> {
+  match (environment.platform, kind) {
+    (Platform::Linux | Platform::Termux, Kind::Data) => {
+      Some(environment.xdg_dir("XDG_DATA_HOME", ".local/share")?.join(NAME))
+    },
+    (Platform::Linux | Platform::Termux, Kind::Config) => {
+      Some(environment.xdg_dir("XDG_CONFIG_HOME", ".config")?.join(NAME))
+    },
+    (Platform::Linux | Platform::Termux, Kind::Cache) => {
+      Some(environment.xdg_dir("XDG_CACHE_HOME", ".cache")?.join(NAME))
+    },
+    (Platform::MacOs, Kind::Cache) => Some(environment.home.as_ref()?.join("Library/Caches").join(BUNDLE_ID)),
+    (Platform::MacOs, _) => Some(environment.home.as_ref()?.join("Library/Application Support").join(BUNDLE_ID)),
+    (Platform::Windows, kind) => {
+      let directory = environment.path_var("LOCALAPPDATA")?.join(ORGANIZATION).join(NAME);
+      Some(directory.join(match kind {
+        Kind::Data => "data",
+        Kind::Config => "config",
+        Kind::Cache => "cache",
+      }))
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn environment(platform: Platform, vars: &[(&str, &str)]) -> Environment {
+    Environment {
+      platform,
+      home: Some(PathBuf::from("/home/suisei")),
+      audio_dir: Some(PathBuf::from("/home/suisei/Music")),
+      vars: vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+      debug: false,
+    }
+  }
+
+  #[test]
+  fn test_resolve_linux() {
+    let paths = Paths::resolve(&environment(Platform::Linux, &[]));
+    assert_eq!(paths, Paths {
+      data_dir: PathBuf::from("/home/suisei/.local/share/muzik"),
+      config_dir: PathBuf::from("/home/suisei/.config/muzik"),
+      cache_dir: PathBuf::from("/home/suisei/.cache/muzik"),
+      music_dir: PathBuf::from("/home/suisei/Music"),
+      attachments_dir: PathBuf::from("/home/suisei/Music/Attachments"),
+      database: PathBuf::from("/home/suisei/.local/share/muzik/database.db"),
+    });
+
+    // relative XDG directories are ignored, as the spec says
+    let paths =
+      Paths::resolve(&environment(Platform::Linux, &[("XDG_DATA_HOME", "/data"), ("XDG_CONFIG_HOME", "config")]));
+    assert_eq!(paths.data_dir, PathBuf::from("/data/muzik"));
+    assert_eq!(paths.config_dir, PathBuf::from("/home/suisei/.config/muzik"));
+  }
+
+  #[test]
+  fn test_resolve_termux() {
+    let paths = Paths::resolve(&environment(Platform::Termux, &[]));
+    assert_eq!(paths.data_dir, PathBuf::from("/home/suisei/.local/share/muzik"));
+    assert_eq!(paths.music_dir, PathBuf::from("/home/suisei/storage/music"));
+    if cfg!(target_os = "linux") && !cfg!(feature = "termux") {
+      let prefix = [("PREFIX".to_string(), "/data/data/com.termux/files/usr".to_string())].into();
+      assert_eq!(Platform::current(&prefix), Platform::Termux);
+      assert_eq!(Platform::current(&HashMap::new()), Platform::Linux);
+    }
+  }
+
+  #[test]
+  fn test_resolve_macos() {
+    let paths = Paths::resolve(&environment(Platform::MacOs, &[]));
+    let support = PathBuf::from("/home/suisei/Library/Application Support/com.solemnattic.muzik");
+    assert_eq!(paths.data_dir, support);
+    assert_eq!(paths.config_dir, support);
+    assert_eq!(paths.cache_dir, PathBuf::from("/home/suisei/Library/Caches/com.solemnattic.muzik"));
+    assert_eq!(paths.database, support.join("database.db"));
+  }
+
+  #[test]
+  fn test_resolve_overrides() {
+    let mut environment = environment(Platform::Linux, &[
+      (DATA_VAR, "/srv/muzik"),
+      (CONFIG_VAR, "/etc/muzik"),
+      (CACHE_VAR, "/var/cache/muzik"),
+      (MUSIC_VAR, "/srv/music"),
+    ]);
+    environment.debug = true;
+    let paths = Paths::resolve(&environment);
+    assert_eq!(paths.data_dir, PathBuf::from("/srv/muzik"));
+    assert_eq!(paths.config_dir, PathBuf::from("/etc/muzik"));
+    assert_eq!(paths.cache_dir, PathBuf::from("/var/cache/muzik"));
+    assert_eq!(paths.music_dir, PathBuf::from("/srv/music"));
+    assert_eq!(paths.database, PathBuf::from("./dev.db"));
+
+    // without a home, everything stays next to the working directory
+    let paths = Paths::resolve(&Environment::default());
+    assert_eq!(paths.music_dir, PathBuf::from("./.data/music"));
+    assert_eq!(paths.with_music_dir(PathBuf::from("/music")).attachments_dir, PathBuf::from("/music/Attachments"));
+  }
+}
@@ -0,0 +1,558 @@
+//! Playback of songs inside muzik
+//!
+//! Songs are decoded with symphonia and played on the default audio output with rodio, so they
+//! are paused and seeked in right away. Files in a format symphonia can not decode, such as opus,
+//! are decoded into the cache dir with ffmpeg first. A song still in the download queue is
+//! streamed instead, with yt-dlp piping the audio into ffmpeg and ffmpeg into the player, so it
+//! can be listened to right away. A stream can not be seeked, so seeking in one waits for the
+//! download to finish and resumes from the file at the new position.
+
+use std::{
+  fs::File,
+  io::{BufReader, Read},
+  path::{Path, PathBuf},
+  process::{Child, ChildStdout, Command, Stdio},
+  sync::mpsc::{self, Receiver, TryRecvError},
+  time::Duration,
+};
+
+use color_eyre::eyre::{eyre, Result};
+#[cfg(test)]
+use rodio::queue::SourcesQueueOutput;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use sha2::{Digest, Sha256};
+
+use crate::{
+  cache,
+  lyrics::Lyrics,
+  queue::{self, DownloadRequest},
+};
+
+/// The program streaming the audio of videos
+const STREAM_PROGRAM: &str = "yt-dlp";
+/// The program decoding the audio symphonia can not
+const DECODE_PROGRAM: &str = "ffmpeg";
+/// The sample rate streams are decoded to
+const STREAM_SAMPLE_RATE: u32 = 48_000;
+/// The channels streams are decoded to
+const STREAM_CHANNELS: u16 = 2;
+/// How many samples of a stream are read from ffmpeg at once
+const STREAM_CHUNK: usize = 4096;
+/// How long a stream is waited for before giving up on it
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the songs are played
+enum Output {
+  /// opened with the first song played
+  Closed,
+  /// the default audio device. The stream can not be moved between threads, so it is kept open by
+  /// a thread of its own, until the sender is dropped
+  Device(OutputStreamHandle, mpsc::Sender<()>),
+  /// no device, the samples are pulled by hand, for tests
+  #[cfg(test)]
+  Idle(Option<SourcesQueueOutput<f32>>),
+}
+
+impl Output {
+  fn open() -> Result<Self> {
+    let (opened_tx, opened) = mpsc::channel();
+    let (close, closed) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+      match OutputStream::try_default() {
+        Ok((stream, handle)) => {
+          opened_tx.send(Ok(handle)).ok();
+          closed.recv().ok();
+          drop(stream);
+        },
+        Err(e) => {
+          opened_tx.send(Err(e)).ok();
+        },
+      }
+    });
+    let handle = opened.recv()?.map_err(|e| eyre!("no audio output: {e}"))?;
+    Ok(Output::Device(handle, close))
+  }
+}
+
+struct Playback {
+  youtube_id: String,
+  /// the file played, `None` while streaming
+  path: Option<PathBuf>,
+  sink: Sink,
+  /// the position playback started at, until the song is seeked in
+  offset: Duration,
+  /// the programs feeding the stream, killed with the playback
+  processes: Vec<Child>,
+}
+
+impl Playback {
+  fn position(&self) -> Duration {
+    self.offset + self.sink.get_pos()
+  }
+}
+
+impl Drop for Playback {
+  fn drop(&mut self) {
+    self.sink.stop();
+    for process in &mut self.processes {
+      process.kill().ok();
+      process.wait().ok();
+    }
+  }
+}
+
+/// The audio of a stream as decoded by ffmpeg, read by a thread of its own so that the audio
+/// output never waits on it
+struct StreamSource {
+  chunks: Receiver<Vec<i16>>,
+  chunk: std::vec::IntoIter<i16>,
+}
+
+impl StreamSource {
+  /// Start reading the decoded audio, waiting for the first of it
+  fn read(mut audio: ChildStdout) -> Result<Self> {
+    let (chunks_tx, chunks) = mpsc::sync_channel(64);
+    std::thread::spawn(move || {
+      let mut bytes = vec![0; STREAM_CHUNK * 2];
+      loop {
+        let read = match audio.read(&mut bytes) {
+          Ok(0) | Err(_) => return,
+          Ok(read) => read,
+        };
+        let samples = bytes[..read - read % 2].chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        if chunks_tx.send(samples.collect()).is_err() {
+          return;
+        }
+      }
+    });
+    let first = chunks.recv_timeout(STREAM_TIMEOUT).map_err(|_| eyre!("the stream did not start"))?;
+    Ok(Self { chunks, chunk: first.into_iter() })
+  }
+}
+
+impl Iterator for StreamSource {
+  type Item = i16;
+
+  fn next(&mut self) -> Option<i16> {
+    loop {
+      if let Some(sample) = self.chunk.next() {
+        return Some(sample);
+      }
+      match self.chunks.try_recv() {
+        Ok(chunk) => self.chunk = chunk.into_iter(),
+        // silence while the download catches up
+        Err(TryRecvError::Empty) => return Some(0),
+        Err(TryRecvError::Disconnected) => return None,
+      }
+    }
+  }
+}
+
+impl Source for StreamSource {
+  fn current_frame_len(&self) -> Option<usize> {
+    None
+  }
+
+  fn channels(&self) -> u16 {
+    STREAM_CHANNELS
+  }
+
+  fn sample_rate(&self) -> u32 {
+    STREAM_SAMPLE_RATE
+  }
+
+  fn total_duration(&self) -> Option<Duration> {
+    None
+  }
+}
+
+/// What happened to a seek
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seek {
+  /// Playback continues from the new position
+  Done,
+  /// The stream plays on until the download finishes, then continues from the new position
+  Pending,
+  /// Nothing is playing
+  NotPlaying,
+}
+
+/// The playing song, as shown in the Now Playing scene
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NowPlaying {
+  pub youtube_id: String,
+  pub title: String,
+  /// the length of the song, if known
+  pub duration: Option<Duration>,
+  /// the synced lyrics of the song, if it has any and they are shown
+  pub lyrics: Option<Lyrics>,
+}
+
+pub struct Player {
+  /// where the files symphonia can not decode are decoded into
+  cache_dir: PathBuf,
+  output: Output,
+  playback: Option<Playback>,
+  /// the position to continue the stream from once its download finishes
+  pending_seek: Option<Duration>,
+}
+
+impl Player {
+  pub fn new(cache_dir: PathBuf) -> Self {
+    Self { cache_dir, output: Output::Closed, playback: None, pending_seek: None }
+  }
+
+  /// A player without an audio device, whose samples are pulled from [Player::idle_output]
+  #[cfg(test)]
+  fn idle(cache_dir: PathBuf) -> Self {
+    Self { output: Output::Idle(None), ..Self::new(cache_dir) }
+  }
+
+  /// The samples of the song playing on a player without an audio device
+  #[cfg(test)]
+  fn idle_output(&mut self) -> Option<&mut SourcesQueueOutput<f32>> {
+    match &mut self.output {
+      Output::Idle(output) => output.as_mut(),
+      _ => None,
+    }
+  }
+
+  /// A sink of its own for the next song, opening the audio output if it is not open yet
+  fn new_sink(&mut self) -> Result<Sink> {
+    if let Output::Closed = self.output {
+      self.output = Output::open()?;
+    }
+    match &mut self.output {
+      Output::Closed => Err(eyre!("no audio output")),
+      Output::Device(handle, _) => Ok(Sink::try_new(handle)?),
+      #[cfg(test)]
+      Output::Idle(output) => {
+        let (sink, samples) = Sink::new_idle();
+        *output = Some(samples);
+        Ok(sink)
+      },
+    }
+  }
+
+  /// Play a downloaded song from the position, stopping the song playing
+  pub async fn play_file(&mut self, youtube_id: &str, path: &Path, offset: Duration) -> Result<()> {
+    self.stop();
+    let mut source = match open_file(path) {
+      Ok(source) => source,
+      Err(_) => open_file(&self.decode(path).await?)?,
+    };
+    if !offset.is_zero() {
+      source.try_seek(offset).map_err(|e| eyre!("failed to seek to {}s: {e}", offset.as_secs()))?;
+    }
+    let sink = self.new_sink()?;
+    sink.append(source);
+    self.playback = Some(Playback {
+      youtube_id: youtube_id.to_string(),
+      path: Some(path.to_path_buf()),
+      sink,
+      offset,
+      processes: vec![],
+    });
+    Ok(())
+  }
+
+  /// The file decoded by ffmpeg from one symphonia can not decode, decoded once and kept in the
+  /// cache dir for the next time it is played
+  async fn decode(&self, path: &Path) -> Result<PathBuf> {
+    let key = Sha256::digest(path.to_string_lossy().as_bytes());
+    let name = key.iter().take(12).map(|byte| format!("{byte:02x}")).collect::<String>();
+    let decoded = self.cache_dir.join("decoded").join(format!("{name}.wav"));
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    if modified(&decoded).is_some() && modified(&decoded) >= modified(path) {
+      cache::touch(&decoded);
+      return Ok(decoded);
+    }
+    std::fs::create_dir_all(self.cache_dir.join("decoded"))?;
+    // decoded next to the cached file first, so that an interrupted decoding is not played
+    let decoding = decoded.with_extension("decoding");
+    let output = tokio::process::Command::new(DECODE_PROGRAM)
+      .args(["-v", "error", "-y", "-i"])
+      .arg(path)
+      .args(["-vn", "-acodec", "pcm_s16le", "-f", "wav"])
+      .arg(&decoding)
+      .stdin(Stdio::null())
+      .output()
+      .await
+      .map_err(|e| eyre!("failed to run {DECODE_PROGRAM}: {e}"))?;
+    if !output.status.success() {
+      std::fs::remove_file(&decoding).ok();
+      return Err(eyre!(
+        "{DECODE_PROGRAM} failed to decode {}: {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+      ));
+    }
+    std::fs::rename(&decoding, &decoded)?;
+    Ok(decoded)
+  }
+
+  /// Play a song from the start while it downloads, stopping the song playing
+  pub async fn play_stream(&mut self, request: &DownloadRequest) -> Result<()> {
+    self.stop();
+    let mut stream = Command::new(STREAM_PROGRAM)
+      .args(["--quiet", "-f", "bestaudio", "-o", "-"])
+      .arg(request.url())
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .map_err(|e| eyre!("failed to run {STREAM_PROGRAM}: {e}"))?;
+    let video = stream.stdout.take().ok_or_else(|| eyre!("{STREAM_PROGRAM} has no output"))?;
+    let (sample_rate, channels) = (STREAM_SAMPLE_RATE.to_string(), STREAM_CHANNELS.to_string());
+    let decoder = Command::new(DECODE_PROGRAM)
+      .args(["-v", "error", "-i", "pipe:0", "-vn", "-f", "s16le", "-acodec", "pcm_s16le"])
+      .args(["-ar", &sample_rate, "-ac", &channels, "pipe:1"])
+      .stdin(video)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn();
+    let mut processes = vec![stream];
+    let mut decoder = match decoder {
+      Ok(decoder) => decoder,
+      Err(e) => {
+        processes.iter_mut().for_each(|process| drop(process.kill()));
+        return Err(eyre!("failed to run {DECODE_PROGRAM}: {e}"));
+      },
+    };
+    let audio = decoder.stdout.take();
+    processes.push(decoder);
+    // dropped along with the programs if the stream does not start
+    let mut playback = Playback {
+      youtube_id: request.youtube_id.clone(),
+      path: None,
+      sink: Sink::new_idle().0,
+      offset: Duration::ZERO,
+      processes,
+    };
+    let audio = audio.ok_or_else(|| eyre!("{DECODE_PROGRAM} has no output"))?;
+    let source = tokio::task::spawn_blocking(move || StreamSource::read(audio)).await??;
+    playback.sink = self.new_sink()?;
+    playback.sink.append(source);
+    self.playback = Some(playback);
+    Ok(())
+  }
+
+  pub fn stop(&mut self) {
+    self.playback = None;
+    self.pending_seek = None;
+  }
+
+  /// The position in the playing song
+  pub fn position(&self) -> Option<Duration> {
+    let playback = self.playback.as_ref()?;
+    Some(self.pending_seek.unwrap_or(playback.position()))
+  }
+
+  /// Pause the playing song, or resume it when paused
+  ///
+  /// # Returns
+  ///
+  /// * whether the song is paused now, or `None` when nothing is playing
+  pub fn toggle_pause(&mut self) -> Option<bool> {
+    let sink = &self.playback.as_ref()?.sink;
+    match sink.is_paused() {
+      true => sink.play(),
+      false => sink.pause(),
+    }
+    Some(sink.is_paused())
+  }
+
+  /// Whether the song played to its end. A stream waiting for its download to be seeked in is not
+  /// finished
+  pub fn finished(&self) -> bool {
+    self.pending_seek.is_none() && self.playback.as_ref().is_some_and(|playback| playback.sink.empty())
+  }
+
+  /// Move the position in the playing song by `seconds`, backwards if negative
+  pub fn seek(&mut self, seconds: i64) -> Result<Seek> {
+    let (Some(playback), Some(position)) = (&self.playback, self.position()) else {
+      return Ok(Seek::NotPlaying);
+    };
+    let position = if seconds < 0 {
+      position.saturating_sub(Duration::from_secs(seconds.unsigned_abs()))
+    } else {
+      position + Duration::from_secs(seconds as u64)
+    };
+    if playback.path.is_none() {
+      self.pending_seek = Some(position);
+      return Ok(Seek::Pending);
+    }
+    let Some(playback) = self.playback.as_mut() else {
+      return Ok(Seek::NotPlaying);
+    };
+    playback.sink.try_seek(position).map_err(|e| eyre!("{e}"))?;
+    // the position of the sink is the one in the song from now on
+    playback.offset = Duration::ZERO;
+    Ok(Seek::Done)
+  }
+
+  /// The song whose download is waited for to seek in it
+  pub fn waiting_for(&self) -> Option<&str> {
+    self.pending_seek.and(self.playback.as_ref()).map(|playback| playback.youtube_id.as_str())
+  }
+
+  /// Continue the streamed song from its downloaded file at the position seeked to
+  pub async fn resume_from_file(&mut self, path: &Path) -> Result<()> {
+    let (Some(youtube_id), Some(position)) = (self.waiting_for().map(str::to_string), self.pending_seek) else {
+      return Ok(());
+    };
+    self.play_file(&youtube_id, path, position).await
+  }
+}
+
+/// The decoder of the file, if symphonia knows its format
+fn open_file(path: &Path) -> Result<Decoder<BufReader<File>>> {
+  Ok(Decoder::new(BufReader::new(File::open(path)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  };
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::{tests::setup_database, DatabaseHandle},
+    models::NewSong,
+    plays::{PlayCounter, PlayedSong},
+  };
+
+  const SAMPLE_RATE: u32 = 8000;
+
+  /// A mono WAV file of silence lasting `seconds`
+  fn write_wav(path: &Path, seconds: u32) -> Result<()> {
+    let data_size = seconds * SAMPLE_RATE * 2;
+    let mut wav = vec![];
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_size).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(SAMPLE_RATE.to_le_bytes());
+    wav.extend((SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_size.to_le_bytes());
+    wav.resize(wav.len() + data_size as usize, 0);
+    std::fs::write(path, wav)?;
+    Ok(())
+  }
+
+  /// Play `seconds` of the song, as the audio output would
+  fn pull(player: &mut Player, seconds: u32) {
+    let output = player.idle_output().expect("a song is playing");
+    for _ in 0..seconds * SAMPLE_RATE {
+      output.next();
+    }
+  }
+
+  #[tokio::test]
+  async fn test_player_position() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-player-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let path = directory.join("a51VH9BYzZA.wav");
+    write_wav(&path, 10)?;
+    let mut player = Player::idle(directory.clone());
+    assert_eq!(player.toggle_pause(), None);
+    assert!(!player.finished());
+
+    player.play_file("a51VH9BYzZA", &path, Duration::from_secs(4)).await?;
+    assert_eq!(player.position(), Some(Duration::from_secs(4)));
+    pull(&mut player, 2);
+    let position = player.position().expect("a song is playing");
+    assert!(position > Duration::from_millis(5900) && position <= Duration::from_secs(6), "{position:?}");
+
+    // a paused song stays where it is, once the output takes the pause in
+    assert_eq!(player.toggle_pause(), Some(true));
+    pull(&mut player, 1);
+    let position = player.position();
+    pull(&mut player, 2);
+    assert_eq!(player.position(), position);
+    assert_eq!(player.toggle_pause(), Some(false));
+
+    pull(&mut player, 5);
+    assert!(player.finished());
+    player.stop();
+    assert_eq!(player.position(), None);
+    std::fs::remove_dir_all(directory)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_player_counts_plays() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-player-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let path = directory.join("a51VH9BYzZA.wav");
+    write_wav(&path, 10)?;
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Ado".to_string(), ..Default::default() })?;
+    let database = DatabaseHandle::spawn(database)?;
+    let (mut player, mut plays) = (Player::idle(directory.clone()), PlayCounter::default());
+
+    player.play_file("a51VH9BYzZA", &path, Duration::ZERO).await?;
+    plays.start(&database, PlayedSong::Song(song_id), Some(Duration::from_secs(10)));
+    pull(&mut player, 4);
+    let position = player.position().expect("a song is playing");
+    assert_eq!(plays.reached(&database, position, None).await?, None);
+    pull(&mut player, 2);
+    let position = player.position().expect("a song is playing");
+    assert_eq!(plays.reached(&database, position, None).await?, Some(song_id));
+    let song = database.call(move |database| database.get_song_from_id(song_id)).await?;
+    assert_eq!(song.play_count, 1);
+    std::fs::remove_dir_all(directory)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_player_seek() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-player-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let path = directory.join("a51VH9BYzZA.wav");
+    write_wav(&path, 600)?;
+    let mut player = Player::idle(directory.clone());
+    assert_eq!(player.seek(10)?, Seek::NotPlaying);
+
+    player.play_file("a51VH9BYzZA", &path, Duration::from_secs(60)).await?;
+    // the audio output plays along on its own, ten times as fast, while the seek waits for it
+    let mut output = match &mut player.output {
+      Output::Idle(output) => output.take().expect("a song is playing"),
+      _ => unreachable!(),
+    };
+    let stopped = Arc::new(AtomicBool::new(false));
+    let playing = std::thread::spawn({
+      let stopped = stopped.clone();
+      move || {
+        while !stopped.load(Ordering::SeqCst) {
+          output.by_ref().take(SAMPLE_RATE as usize / 100).for_each(drop);
+          std::thread::sleep(Duration::from_millis(1));
+        }
+      }
+    });
+    assert_eq!(player.seek(-90)?, Seek::Done);
+    std::thread::sleep(Duration::from_millis(20));
+    let position = player.position().expect("a song is playing");
+    assert!(position < Duration::from_secs(30), "{position:?}");
+    assert_eq!(player.seek(300)?, Seek::Done);
+    std::thread::sleep(Duration::from_millis(20));
+    let position = player.position().expect("a song is playing");
+    assert!(position >= Duration::from_secs(300) && position < Duration::from_secs(330), "{position:?}");
+    assert_eq!(player.waiting_for(), None);
+
+    stopped.store(true, Ordering::SeqCst);
+    playing.join().expect("the output does not panic");
+    player.stop();
+    std::fs::remove_dir_all(directory)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,243 @@
+//! Exporting a playlist with the export profiles it remembers
+//!
+//! A profile of the `export.profiles` settings says where a playlist is written and how: among the
+//! playlists of MPD, pointing at the files in the music dir, or onto a USB stick along with copies
+//! of its songs, transcoded when the profile has a bitrate. Every playlist remembers the profiles
+//! picked for it, so that exporting it again after a change is a single key. Copies already in the
+//! folder are kept unless their file changed since, so only the new songs are copied again.
+
+use std::{
+  collections::{HashMap, HashSet},
+  path::Path,
+};
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{
+  config::{Config, ExportProfile},
+  database::Database,
+  export::{self, ExportSong},
+  jobs::JobContext,
+  playlists::{self, PlaylistEntry},
+};
+
+/// The profile names typed in, separated by commas
+pub fn parse_profiles(input: &str) -> Vec<String> {
+  let mut profiles: Vec<String> = vec![];
+  for profile in input.split(',').map(str::trim).filter(|profile| !profile.is_empty()) {
+    if !profiles.iter().any(|known| known == profile) {
+      profiles.push(profile.to_string());
+    }
+  }
+  profiles
+}
+
+/// Check that every profile is in the `export.profiles` settings
+pub fn validate_profiles(config: &Config, profiles: &[String]) -> Result<()> {
+  let known = &config.config.export.profiles;
+  match profiles.iter().find(|profile| !known.contains_key(*profile)) {
+    Some(profile) => {
+      Err(eyre!("there is no export profile named {profile}, expected one of {:?}", known.keys().collect::<Vec<_>>()))
+    },
+    None => Ok(()),
+  }
+}
+
+/// The songs of the playlist that have a file, in order
+fn playlist_entries(database: &mut Database, playlist_id: i32) -> Result<Vec<PlaylistEntry>> {
+  let entries = playlists::load_entries(database)?;
+  let by_id: HashMap<i32, &PlaylistEntry> = entries.iter().map(|entry| (entry.song_id, entry)).collect();
+  let songs = database.get_playlist_songs(playlist_id)?;
+  Ok(songs.iter().filter_map(|song| by_id.get(&song.id).map(|entry| (*entry).clone())).collect())
+}
+
+/// The songs of the playlist as copied into a folder, named after the template. A song in the
+/// playlist twice is copied once
+///
+/// # Returns
+///
+/// * the entries pointing at the copies, and every copy to make as the path of the file from the
+///   music dir and the path of the copy from the folder
+fn copied_entries(
+  entries: &[PlaylistEntry],
+  songs: &HashMap<i32, ExportSong>,
+  template: &str,
+  bitrate: Option<u32>,
+) -> (Vec<PlaylistEntry>, Vec<(String, String)>) {
+  let mut names: HashMap<i32, String> = HashMap::new();
+  let mut taken = HashSet::new();
+  let mut copies = vec![];
+  let entries = entries
+    .iter()
+    .filter_map(|entry| {
+      let name = match names.get(&entry.song_id) {
+        Some(name) => name.clone(),
+        None => {
+          let song = songs.get(&entry.song_id)?;
+          let extension = export::exported_extension(&song.path, bitrate);
+          let name = export::unique_name(export::render_name(template, song, &extension), &mut taken);
+          copies.push((song.path.clone(), name.clone()));
+          names.insert(entry.song_id, name.clone());
+          name
+        },
+      };
+      Some(PlaylistEntry { path: name, ..entry.clone() })
+    })
+    .collect();
+  (entries, copies)
+}
+
+/// Whether the copy is missing or older than the file it was copied from
+fn outdated(source: &Path, copy: &Path) -> bool {
+  let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  match (modified(source), modified(copy)) {
+    (Some(source), Some(copy)) => copy < source,
+    _ => true,
+  }
+}
+
+/// Write the playlist into the folder of the profile, copying its songs there if the profile says
+/// so
+///
+/// # Returns
+///
+/// * the number of songs copied
+async fn export_with(
+  config: &Config,
+  name: &str,
+  entries: &[PlaylistEntry],
+  songs: &HashMap<i32, ExportSong>,
+  profile: &ExportProfile,
+  context: &JobContext,
+) -> Result<usize> {
+  let directory = &profile.directory;
+  std::fs::create_dir_all(directory)?;
+  if !profile.copy_songs {
+    let path_prefix = playlists::path_prefix(config, directory, profile.paths)?;
+    let entries: Vec<_> = entries.iter().collect();
+    std::fs::write(directory.join(playlists::file_name(name)), playlists::to_m3u(&entries, &path_prefix))?;
+    return Ok(0);
+  }
+  let template = profile.name_template.as_deref().unwrap_or(&config.config.export.name_template);
+  export::validate_template(template)?;
+  let (entries, copies) = copied_entries(entries, songs, template, profile.bitrate);
+  let mut copied = 0;
+  for (index, (path, copy)) in copies.iter().enumerate() {
+    context.pace().await;
+    let (source, target) = (config.config.music_dir.join(path), directory.join(copy));
+    if outdated(&source, &target) {
+      match export::export_file(&source, &target, profile.bitrate).await {
+        Ok(()) => copied += 1,
+        Err(e) => context.log(format!("failed to export {path}: {e}")),
+      }
+    }
+    context.progress(index as u64 + 1, copies.len() as u64);
+  }
+  let entries: Vec<_> = entries.iter().collect();
+  std::fs::write(directory.join(playlists::file_name(name)), playlists::to_m3u(&entries, ""))?;
+  Ok(copied)
+}
+
+/// Export the playlist with every profile it remembers, to be run as a job
+pub async fn export_playlist(config: Config, playlist_id: i32, context: JobContext) -> Result<()> {
+  let mut database = Database::new(config.clone()).await?;
+  let playlist = database
+    .get_playlists()?
+    .into_iter()
+    .find(|playlist| playlist.id == playlist_id)
+    .ok_or_else(|| eyre!("the playlist was deleted"))?;
+  let profiles: Vec<String> = database
+    .get_playlist_profiles()?
+    .into_iter()
+    .filter(|profile| profile.playlist_id == playlist_id)
+    .map(|profile| profile.profile)
+    .collect();
+  if profiles.is_empty() {
+    return Err(eyre!("no export profiles were picked for {}", playlist.name));
+  }
+  validate_profiles(&config, &profiles)?;
+  let entries = playlist_entries(&mut database, playlist_id)?;
+  let mut song_ids: Vec<i32> = entries.iter().map(|entry| entry.song_id).collect();
+  song_ids.sort_unstable();
+  song_ids.dedup();
+  // every song of the entries has a file, so none is left out
+  let songs: HashMap<i32, ExportSong> =
+    song_ids.iter().copied().zip(export::load_songs(&mut database, &song_ids)?).collect();
+  for name in profiles {
+    let profile = &config.config.export.profiles[&name];
+    let copied = export_with(&config, &playlist.name, &entries, &songs, profile, &context).await?;
+    context.log(format!(
+      "exported {} songs of {} to {} with {name}, copying {copied}",
+      entries.len(),
+      playlist.name,
+      profile.directory.display()
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_profiles() {
+    assert_eq!(parse_profiles(" car, mpd,,car "), vec!["car", "mpd"]);
+    assert_eq!(parse_profiles(" "), Vec::<String>::new());
+
+    let mut config = Config::default();
+    let profile = ExportProfile {
+      directory: PathBuf::from("/media/usb"),
+      copy_songs: true,
+      paths: Default::default(),
+      bitrate: None,
+      name_template: None,
+    };
+    config.config.export.profiles.insert("car".to_string(), profile);
+    assert!(validate_profiles(&config, &parse_profiles("car")).is_ok());
+    assert!(validate_profiles(&config, &parse_profiles("car, phone")).is_err());
+  }
+
+  #[test]
+  fn test_copied_entries() {
+    let entry = |song_id: i32, title: &str| {
+      PlaylistEntry { song_id, title: title.to_string(), path: format!("{song_id}.m4a"), ..Default::default() }
+    };
+    let song = |song_id: i32, title: &str| {
+      let song = ExportSong { title: title.to_string(), path: format!("{song_id}.m4a"), ..Default::default() };
+      (song_id, song)
+    };
+    let entries = [entry(1, "Stellar Stellar"), entry(2, "Stellar Stellar"), entry(1, "Stellar Stellar"), entry(3, "")];
+    // the third song has no file
+    let songs = HashMap::from([song(1, "Stellar Stellar"), song(2, "Stellar Stellar")]);
+    let (entries, copies) = copied_entries(&entries, &songs, "{title}", Some(96));
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths, vec!["Stellar Stellar.opus", "Stellar Stellar (2).opus", "Stellar Stellar.opus"]);
+    assert_eq!(copies, vec![
+      ("1.m4a".to_string(), "Stellar Stellar.opus".to_string()),
+      ("2.m4a".to_string(), "Stellar Stellar (2).opus".to_string())
+    ]);
+  }
+
+  #[test]
+  fn test_outdated() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-playlist-export-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let (source, copy) = (directory.join("source.opus"), directory.join("copy.opus"));
+    std::fs::write(&source, "")?;
+    assert!(outdated(&source, &copy));
+    std::fs::write(&copy, "")?;
+    assert!(!outdated(&source, &copy));
+    std::fs::File::options()
+      .write(true)
+      .open(&source)?
+      .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    assert!(outdated(&source, &copy));
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+  }
+}
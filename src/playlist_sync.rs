@@ -0,0 +1,406 @@
+//! Keeping a directory of M3U playlists in sync with the playlists of the library
+//!
+//! When `playlists.sync_directory` is set, every `.m3u` and `.m3u8` playlist in it is imported
+//! into the library, and the playlists of the library are written there in turn, so that they can
+//! be edited with muzik or with any other player. The songs of every playlist at the last sync are
+//! kept in `.muzik-sync.json` in the directory, which tells apart a change on one side from a
+//! change on the other: a playlist changed on one side only is copied to the other, and one changed
+//! on both is merged, keeping the order of the file along with the songs added and removed in
+//! muzik. On the first sync the file wins over the playlist of the library of the same name. A
+//! playlist deleted on one side is deleted on the other, unless it changed there since.
+//!
+//! The lines of a file that match no song of the library are dropped when muzik rewrites it, and
+//! the songs without a file are left out of the file while staying in the library. The playlists
+//! generated by the export are skipped.
+
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+  path::{Component, Path, PathBuf},
+  time::Duration,
+};
+
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  config::Config,
+  database::Database,
+  jobs::JobContext,
+  playlists::{self, PlaylistEntry, GENERATED_MARKER},
+};
+
+/// The file keeping the songs of the playlists at the last sync, in the synced directory
+pub const STATE_FILE: &str = ".muzik-sync.json";
+/// How often the schedulers sync the playlists
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A playlist as it was left by the last sync
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Synced {
+  /// the name of the file of the playlist in the directory
+  pub file: String,
+  /// the songs of the playlist in the library
+  pub song_ids: Vec<i32>,
+}
+
+/// The playlists as they were left by the last sync, by name
+pub type SyncState = BTreeMap<String, Synced>;
+
+/// What a sync does to a playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+  /// The playlist has the songs on both sides, and is written where it differs
+  Update { name: String, file: String, song_ids: Vec<i32>, write_library: bool, write_file: bool },
+  /// The playlist is removed from both sides
+  Delete { name: String, file: Option<String>, from_library: bool },
+}
+
+impl Change {
+  /// Whether the change writes or removes anything
+  pub fn is_noop(&self) -> bool {
+    matches!(self, Change::Update { write_library: false, write_file: false, .. })
+  }
+}
+
+/// The songs of the playlist once both sides are merged, or `None` when it is deleted
+///
+/// # Arguments
+///
+/// * `base` - the songs at the last sync, if it was synced before
+/// * `file` - the songs read from the file, if there is one
+/// * `library` - the songs in the library, if it is stored there
+/// * `has_file` - whether a song has a file, as only those are written in the files
+fn merge(
+  base: Option<&[i32]>,
+  file: Option<&[i32]>,
+  library: Option<&[i32]>,
+  has_file: impl Fn(i32) -> bool,
+) -> Option<Vec<i32>> {
+  let without_file = |songs: &[i32]| songs.iter().copied().filter(|&song_id| !has_file(song_id)).collect::<Vec<_>>();
+  let Some(base) = base else {
+    return file.or(library).map(<[i32]>::to_vec);
+  };
+  let file_changed = |file: &[i32]| base.iter().copied().filter(|&song_id| has_file(song_id)).ne(file.iter().copied());
+  match (file, library) {
+    (None, None) => None,
+    (None, Some(library)) => (library != base).then(|| library.to_vec()),
+    (Some(file), None) => file_changed(file).then(|| file.to_vec()),
+    (Some(file), Some(library)) if library == base => Some([file, &without_file(library)].concat()),
+    (Some(file), Some(library)) if !file_changed(file) => Some(library.to_vec()),
+    (Some(file), Some(library)) => {
+      // the order of the file, with the songs added and removed in muzik
+      let removed: HashSet<i32> = base.iter().copied().filter(|song_id| !library.contains(song_id)).collect();
+      let mut merged: Vec<i32> = file.iter().copied().filter(|song_id| !removed.contains(song_id)).collect();
+      for &song_id in library {
+        if (!base.contains(&song_id) || !has_file(song_id)) && !merged.contains(&song_id) {
+          merged.push(song_id);
+        }
+      }
+      Some(merged)
+    },
+  }
+}
+
+/// What to do to every playlist for both sides to agree
+///
+/// # Arguments
+///
+/// * `state` - the playlists at the last sync
+/// * `files` - the songs read from the files, by playlist name, with the name of the file
+/// * `library` - the songs of the playlists of the library, by name
+/// * `has_file` - whether a song has a file
+pub fn plan(
+  state: &SyncState,
+  files: &BTreeMap<String, (String, Vec<i32>)>,
+  library: &BTreeMap<String, Vec<i32>>,
+  has_file: impl Fn(i32) -> bool,
+) -> Vec<Change> {
+  let names: BTreeSet<&String> = state.keys().chain(files.keys()).chain(library.keys()).collect();
+  names
+    .into_iter()
+    .map(|name| {
+      let base = state.get(name);
+      let file = files.get(name);
+      let stored = library.get(name);
+      let visible = |songs: &[i32]| songs.iter().copied().filter(|&song_id| has_file(song_id)).collect::<Vec<_>>();
+      match merge(
+        base.map(|synced| synced.song_ids.as_slice()),
+        file.map(|(_, songs)| songs.as_slice()),
+        stored.map(Vec::as_slice),
+        &has_file,
+      ) {
+        Some(song_ids) => {
+          Change::Update {
+            name: name.clone(),
+            file: file
+              .map(|(file, _)| file.clone())
+              .or_else(|| base.map(|synced| synced.file.clone()))
+              .unwrap_or_else(|| playlists::file_name(name)),
+            write_library: stored != Some(&song_ids),
+            write_file: file.map(|(_, songs)| songs) != Some(&visible(&song_ids)),
+            song_ids,
+          }
+        },
+        None => {
+          Change::Delete {
+            name: name.clone(),
+            file: file.map(|(file, _)| file.clone()),
+            from_library: stored.is_some(),
+          }
+        },
+      }
+    })
+    .collect()
+}
+
+/// The path with its `.` and `..` taken out, without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {},
+      Component::ParentDir => {
+        normalized.pop();
+      },
+      component => normalized.push(component),
+    }
+  }
+  normalized
+}
+
+/// Finds the songs of the lines of the playlists, written relative to the music dir after the
+/// prefix, relative to the playlist, or as absolute paths
+struct Resolver {
+  music_dir: PathBuf,
+  directory: PathBuf,
+  path_prefix: String,
+  /// the songs by the path of their file, relative to the music dir
+  songs: HashMap<String, i32>,
+}
+
+impl Resolver {
+  fn song_id(&self, line: &str) -> Option<i32> {
+    if let Some(song_id) = line.strip_prefix(&self.path_prefix).and_then(|path| self.songs.get(path)) {
+      return Some(*song_id);
+    }
+    let path = normalize(&self.directory.join(line));
+    let relative = path.strip_prefix(&self.music_dir).ok()?;
+    self.songs.get(relative.to_str()?).copied()
+  }
+
+  /// The songs of the playlist, and the number of lines that match no song
+  fn read(&self, m3u: &str) -> (Vec<i32>, usize) {
+    let (mut song_ids, mut unmatched) = (vec![], 0);
+    for line in m3u.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+      match self.song_id(line) {
+        Some(song_id) => song_ids.push(song_id),
+        None => unmatched += 1,
+      }
+    }
+    (song_ids, unmatched)
+  }
+}
+
+/// What a sync did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+  pub changes: Vec<Change>,
+  /// the lines of the files read that match no song of the library
+  pub unmatched: usize,
+}
+
+/// Where the playlists are synced, if the sync is on
+pub fn directory(config: &Config) -> Option<PathBuf> {
+  config.config.playlists.sync_directory.clone()
+}
+
+/// Sync the playlists of the directory with those of the library
+///
+/// # Arguments
+///
+/// * `dry_run` - only tell what would change
+pub fn sync(config: &Config, directory: &Path, database: &mut Database, dry_run: bool) -> Result<SyncReport> {
+  let directory = std::path::absolute(directory)?;
+  if directory == std::path::absolute(playlists::directory(config))? {
+    return Err(eyre!("the playlists are synced into the directory they are exported to, set another directory"));
+  }
+  std::fs::create_dir_all(&directory)?;
+  let state_path = directory.join(STATE_FILE);
+  let state: SyncState = match std::fs::read_to_string(&state_path) {
+    Ok(json) => serde_json::from_str(&json)?,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => SyncState::new(),
+    Err(e) => return Err(e.into()),
+  };
+
+  let entries = playlists::load_entries(database)?;
+  let by_id: HashMap<i32, &PlaylistEntry> = entries.iter().map(|entry| (entry.song_id, entry)).collect();
+  let path_prefix = playlists::path_prefix(config, &directory, config.config.playlists.paths)?;
+  let resolver = Resolver {
+    music_dir: normalize(&std::path::absolute(&config.config.music_dir)?),
+    directory: directory.clone(),
+    path_prefix: path_prefix.clone(),
+    songs: entries.iter().map(|entry| (entry.path.clone(), entry.song_id)).collect(),
+  };
+
+  // the files keep the name of the playlist they were written for
+  let names: HashMap<&str, &str> = state.iter().map(|(name, synced)| (synced.file.as_str(), name.as_str())).collect();
+  let mut files = BTreeMap::new();
+  let mut unmatched = 0;
+  for dir_entry in std::fs::read_dir(&directory)? {
+    let path = dir_entry?.path();
+    let (Some(file), Some(stem)) = (path.file_name().and_then(|name| name.to_str()), path.file_stem()) else {
+      continue;
+    };
+    if !path.extension().is_some_and(|extension| extension == "m3u" || extension == "m3u8") {
+      continue;
+    }
+    let m3u = std::fs::read_to_string(&path)?;
+    if m3u.lines().nth(1) == Some(GENERATED_MARKER) {
+      continue;
+    }
+    let (song_ids, missing) = resolver.read(&m3u);
+    unmatched += missing;
+    let name = names.get(file).map_or_else(|| stem.to_string_lossy().to_string(), |name| name.to_string());
+    files.insert(name, (file.to_string(), song_ids));
+  }
+  let library: BTreeMap<String, Vec<i32>> = playlists::load_stored(database)?.into_iter().collect();
+
+  let changes = plan(&state, &files, &library, |song_id| by_id.contains_key(&song_id));
+  if dry_run {
+    return Ok(SyncReport { changes, unmatched });
+  }
+  let mut synced = SyncState::new();
+  for change in &changes {
+    match change {
+      Change::Update { name, file, song_ids, write_library, write_file } => {
+        if *write_library {
+          let playlist_id = match database.get_playlist_by_name(name)? {
+            Some(playlist) => playlist.id,
+            None => database.create_playlist(name)?,
+          };
+          database.set_playlist_songs(playlist_id, song_ids)?;
+        }
+        if *write_file {
+          let songs: Vec<_> = song_ids.iter().filter_map(|song_id| by_id.get(song_id).copied()).collect();
+          std::fs::write(directory.join(file), format!("#EXTM3U\n{}", playlists::m3u_entries(&songs, &path_prefix)))?;
+        }
+        synced.insert(name.clone(), Synced { file: file.clone(), song_ids: song_ids.clone() });
+      },
+      Change::Delete { name, file, from_library } => {
+        if let (true, Some(playlist)) = (from_library, database.get_playlist_by_name(name)?) {
+          database.delete_playlist(playlist.id)?;
+        }
+        if let Some(file) = file {
+          std::fs::remove_file(directory.join(file))?;
+        }
+      },
+    }
+  }
+  std::fs::write(&state_path, serde_json::to_string_pretty(&synced)?)?;
+  Ok(SyncReport { changes, unmatched })
+}
+
+/// Sync the playlists into the directory of the settings, to be run as a job
+pub async fn run_sync(config: Config, context: JobContext) -> Result<()> {
+  let Some(directory) = directory(&config) else {
+    return Ok(());
+  };
+  let mut database = Database::new(config.clone()).await?;
+  let report = sync(&config, &directory, &mut database, false)?;
+  let changed = report.changes.iter().filter(|change| !change.is_noop()).count();
+  if changed > 0 {
+    context.log(format!("synced {changed} playlists with {}", directory.display()));
+  }
+  if report.unmatched > 0 {
+    context.log(format!("{} lines of the playlists match no song of the library", report.unmatched));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewFile, NewSong},
+  };
+
+  #[test]
+  fn test_merge() {
+    let has_file = |song_id| song_id != 9;
+    // the first sync takes the file
+    assert_eq!(merge(None, Some(&[1, 2]), Some(&[3]), has_file), Some(vec![1, 2]));
+    assert_eq!(merge(None, None, Some(&[3]), has_file), Some(vec![3]));
+    // changed on one side
+    assert_eq!(merge(Some(&[1, 2, 9]), Some(&[2, 1]), Some(&[1, 2, 9]), has_file), Some(vec![2, 1, 9]));
+    assert_eq!(merge(Some(&[1, 2, 9]), Some(&[1, 2]), Some(&[2, 9, 1, 3]), has_file), Some(vec![2, 9, 1, 3]));
+    // changed on both, the order of the file with the songs added and removed in muzik
+    assert_eq!(merge(Some(&[1, 2, 3]), Some(&[3, 2, 1, 4]), Some(&[1, 3, 5]), has_file), Some(vec![3, 1, 4, 5]));
+    // deleted on one side, unless changed on the other
+    assert_eq!(merge(Some(&[1, 9]), None, Some(&[1, 9]), has_file), None);
+    assert_eq!(merge(Some(&[1]), None, Some(&[1, 2]), has_file), Some(vec![1, 2]));
+    assert_eq!(merge(Some(&[1, 9]), Some(&[1]), None, has_file), None);
+    assert_eq!(merge(Some(&[1]), Some(&[2]), None, has_file), Some(vec![2]));
+    assert_eq!(merge(Some(&[1]), None, None, has_file), None);
+  }
+
+  #[test]
+  fn test_sync() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("muzik-sync-{}", uuid::Uuid::new_v4()));
+    let directory = root.join("Playlists");
+    std::fs::create_dir_all(&directory)?;
+    let mut config = Config::default();
+    config.config.music_dir = root.join("Music");
+    let mut database = setup_database()?;
+    let mut song_ids = vec![];
+    for title in ["Stellar Stellar", "Ghost", "Bluerose"] {
+      let file_id = database.insert_file(NewFile { relative_path: format!("Suisei/{title}.opus") })?;
+      song_ids.push(database.insert_song(NewSong {
+        title: title.to_string(),
+        file_id: Some(file_id),
+        ..Default::default()
+      })?);
+    }
+    let titles = |database: &mut Database, name: &str| -> Result<Vec<String>> {
+      let playlist = database.get_playlist_by_name(name)?.ok_or_else(|| eyre!("no playlist {name}"))?;
+      Ok(database.get_playlist_songs(playlist.id)?.into_iter().map(|song| song.title).collect())
+    };
+
+    // a file written by another player is imported, with its paths in any form
+    let absolute = config.config.music_dir.join("Suisei/Bluerose.opus");
+    std::fs::write(
+      directory.join("Drive.m3u"),
+      format!("#EXTM3U\n../Music/Suisei/Ghost.opus\nSuisei/Gone.opus\n{}\n", absolute.display()),
+    )?;
+    let report = sync(&config, &directory, &mut database, false)?;
+    assert_eq!(report.unmatched, 1);
+    assert_eq!(titles(&mut database, "Drive")?, vec!["Ghost", "Bluerose"]);
+
+    // an edit in muzik rewrites the file, and the file is read back the same
+    let drive = database.get_playlist_by_name("Drive")?.map(|playlist| playlist.id).unwrap_or_default();
+    database.add_playlist_songs(drive, &[song_ids[0]])?;
+    database.create_playlist("Suisei")?;
+    sync(&config, &directory, &mut database, false)?;
+    assert_eq!(
+      std::fs::read_to_string(directory.join("Drive.m3u"))?,
+      "#EXTM3U\n#EXTINF:-1,Ghost\nSuisei/Ghost.opus\n#EXTINF:-1,Bluerose\nSuisei/Bluerose.opus\n#EXTINF:-1,Stellar \
+       Stellar\nSuisei/Stellar Stellar.opus\n"
+    );
+    assert!(directory.join("Suisei.m3u8").exists());
+    assert!(sync(&config, &directory, &mut database, false)?.changes.iter().all(Change::is_noop));
+
+    // an edit of the file is merged into the library, and a deleted file deletes the playlist
+    std::fs::write(directory.join("Drive.m3u"), "#EXTM3U\nSuisei/Stellar Stellar.opus\nSuisei/Ghost.opus\n")?;
+    std::fs::remove_file(directory.join("Suisei.m3u8"))?;
+    sync(&config, &directory, &mut database, false)?;
+    assert_eq!(titles(&mut database, "Drive")?, vec!["Stellar Stellar", "Ghost"]);
+    assert_eq!(database.get_playlist_by_name("Suisei")?, None);
+
+    // the export directory is refused
+    assert!(sync(&config, &playlists::directory(&config), &mut database, true).is_err());
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,340 @@
+//! Playlists for external players
+//!
+//! Regenerates an M3U8 playlist for every genre, every artist and every rating in the library, one
+//! of the whole library and one of every playlist stored in the library, written into a directory
+//! watched by players such as MPD, VLC or Navidrome, or copied to a phone along with the music.
+//! Playlists generated earlier that no longer apply are removed, other files in the directory are
+//! left alone. The lyrics attached to songs are written next to their files at the same time.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Result;
+
+use crate::{
+  attachments,
+  config::{Config, PlaylistPaths},
+  database::Database,
+  jobs::JobContext,
+  utils::safe_file_name,
+};
+
+/// Marks the playlists written by muzik, so that they can be replaced without touching others
+pub(crate) const GENERATED_MARKER: &str = "#GENERATED-BY:muzik";
+/// The name of the playlist of every song with a file
+pub const LIBRARY: &str = "Library";
+
+/// A song with a file, as listed in playlists
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlaylistEntry {
+  pub song_id: i32,
+  pub title: String,
+  /// path of the file relative to the music dir
+  pub path: String,
+  pub artists: Vec<String>,
+  pub genres: Vec<String>,
+  pub rating: Option<i32>,
+}
+
+/// Load every song with a file from the database
+pub fn load_entries(database: &mut Database) -> Result<Vec<PlaylistEntry>> {
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  let mut genres: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_genre_names()? {
+    genres.entry(song_id).or_default().push(name);
+  }
+  Ok(
+    database
+      .get_songs_with_files()?
+      .into_iter()
+      .map(|(song, path)| {
+        PlaylistEntry {
+          artists: artists.remove(&song.id).unwrap_or_default(),
+          genres: genres.remove(&song.id).unwrap_or_default(),
+          song_id: song.id,
+          title: song.title,
+          path,
+          rating: song.rating,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Group the entries into playlists by genre, artist and rating
+///
+/// # Returns
+///
+/// * the entries of every playlist by playlist name
+pub fn build_playlists(entries: &[PlaylistEntry]) -> BTreeMap<String, Vec<&PlaylistEntry>> {
+  let mut playlists: BTreeMap<String, Vec<&PlaylistEntry>> = BTreeMap::new();
+  for entry in entries {
+    for genre in &entry.genres {
+      playlists.entry(format!("Genre - {genre}")).or_default().push(entry);
+    }
+    for artist in &entry.artists {
+      playlists.entry(format!("Artist - {artist}")).or_default().push(entry);
+    }
+    if let Some(rating) = entry.rating {
+      playlists.entry(format!("Rating - {rating} stars")).or_default().push(entry);
+    }
+  }
+  playlists
+}
+
+/// Load the playlists stored in the library, with the ids of their songs in order
+pub fn load_stored(database: &mut Database) -> Result<Vec<(String, Vec<i32>)>> {
+  database
+    .get_playlists()?
+    .into_iter()
+    .map(|playlist| {
+      let song_ids = database.get_playlist_songs(playlist.id)?.into_iter().map(|song| song.id).collect();
+      Ok((playlist.name, song_ids))
+    })
+    .collect()
+}
+
+/// The generated playlists, along with the playlist of the whole library and the stored playlists.
+/// A stored playlist replaces a generated one of the same name, and leaves out its songs without a
+/// file
+///
+/// # Arguments
+///
+/// * `stored` - the stored playlists, as loaded by [`load_stored`]
+pub fn all_playlists<'a>(
+  entries: &'a [PlaylistEntry],
+  stored: &[(String, Vec<i32>)],
+) -> BTreeMap<String, Vec<&'a PlaylistEntry>> {
+  let mut playlists = build_playlists(entries);
+  playlists.insert(LIBRARY.to_string(), entries.iter().collect());
+  let by_id: HashMap<i32, &PlaylistEntry> = entries.iter().map(|entry| (entry.song_id, entry)).collect();
+  for (name, song_ids) in stored {
+    playlists.insert(name.clone(), song_ids.iter().filter_map(|song_id| by_id.get(song_id).copied()).collect());
+  }
+  playlists
+}
+
+/// The path to `to` from the directory `from`, both absolute
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+  let from: Vec<_> = from.components().collect();
+  let to: Vec<_> = to.components().collect();
+  let common = from.iter().zip(&to).take_while(|(left, right)| left == right).count();
+  let mut path: PathBuf = from[common..].iter().map(|_| "..").collect();
+  path.extend(&to[common..]);
+  path
+}
+
+/// What is prepended to the paths of the files, relative to the music dir, for the playlists
+/// written into the directory
+pub fn path_prefix(config: &Config, directory: &Path, paths: PlaylistPaths) -> Result<String> {
+  let music_dir = std::path::absolute(&config.config.music_dir)?;
+  let prefix = match paths {
+    PlaylistPaths::MusicDir => return Ok(config.config.playlists.path_prefix.clone()),
+    PlaylistPaths::Absolute => music_dir,
+    PlaylistPaths::Relative => relative_path(&std::path::absolute(directory)?, &music_dir),
+  };
+  Ok(match prefix.as_os_str().is_empty() {
+    true => String::new(),
+    false => format!("{}/", prefix.display()),
+  })
+}
+
+/// Render a playlist as extended M3U
+///
+/// # Arguments
+///
+/// * `entries` - the songs of the playlist
+/// * `path_prefix` - prepended to the path of every file, so that players resolve them from
+///   their own music directory
+pub fn to_m3u(entries: &[&PlaylistEntry], path_prefix: &str) -> String {
+  format!("#EXTM3U\n{GENERATED_MARKER}\n{}", m3u_entries(entries, path_prefix))
+}
+
+/// The `#EXTINF` line and the path of every song, without the header of the playlist
+pub(crate) fn m3u_entries(entries: &[&PlaylistEntry], path_prefix: &str) -> String {
+  let mut m3u = String::new();
+  for entry in entries {
+    let name = if entry.artists.is_empty() {
+      entry.title.clone()
+    } else {
+      format!("{} - {}", entry.artists.join(", "), entry.title)
+    };
+    m3u.push_str(&format!("#EXTINF:-1,{name}\n{path_prefix}{}\n", entry.path));
+  }
+  m3u
+}
+
+/// The name of the file of the playlist. M3U8 is M3U in UTF-8, which players read the names in
+pub(crate) fn file_name(playlist: &str) -> String {
+  format!("{}.m3u8", safe_file_name(playlist))
+}
+
+/// Write the playlists into the directory, removing playlists written earlier that no longer
+/// apply, including those written as `.m3u` by earlier versions
+///
+/// # Returns
+///
+/// * the number of playlists written
+pub fn write_playlists(
+  directory: &Path,
+  playlists: &BTreeMap<String, Vec<&PlaylistEntry>>,
+  path_prefix: &str,
+) -> Result<usize> {
+  std::fs::create_dir_all(directory)?;
+  let file_names: Vec<String> = playlists.keys().map(|playlist| file_name(playlist)).collect();
+
+  for dir_entry in std::fs::read_dir(directory)? {
+    let path = dir_entry?.path();
+    let stale = path.extension().is_some_and(|extension| extension == "m3u" || extension == "m3u8")
+      && path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| !file_names.iter().any(|file| file == name));
+    if stale && std::fs::read_to_string(&path).is_ok_and(|contents| contents.lines().nth(1) == Some(GENERATED_MARKER)) {
+      std::fs::remove_file(&path)?;
+    }
+  }
+
+  for ((_, playlist), name) in playlists.iter().zip(&file_names) {
+    std::fs::write(directory.join(name), to_m3u(playlist, path_prefix))?;
+  }
+  Ok(playlists.len())
+}
+
+/// Where the playlists are written unless another directory is picked
+pub fn directory(config: &Config) -> PathBuf {
+  config.config.playlists.directory.clone().unwrap_or_else(|| config.paths.data_dir.join("playlists"))
+}
+
+/// Regenerate every playlist, to be run as a job
+///
+/// # Arguments
+///
+/// * `directory` - where to write the playlists, instead of the directory of the settings
+pub async fn export_playlists(config: Config, directory: Option<PathBuf>, context: JobContext) -> Result<()> {
+  let directory = directory.unwrap_or_else(|| self::directory(&config));
+  let path_prefix = path_prefix(&config, &directory, config.config.playlists.paths)?;
+  let mut database = Database::new(config.clone()).await?;
+  let entries = load_entries(&mut database)?;
+  let stored = load_stored(&mut database)?;
+  let written = write_playlists(&directory, &all_playlists(&entries, &stored), &path_prefix)?;
+  context.log(format!("wrote {written} playlists of {} songs into {}", entries.len(), directory.display()));
+  let lyrics = attachments::write_lyrics(&config.config.music_dir, &mut database)?;
+  context.log(format!("wrote the lyrics of {lyrics} songs next to their files"));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewFile, NewGenre, NewSong, SongArtist, SongGenre},
+  };
+
+  fn entry(title: &str, artist: &str, genre: &str, rating: Option<i32>) -> PlaylistEntry {
+    PlaylistEntry {
+      title: title.to_string(),
+      path: format!("{artist}/{title}.opus"),
+      artists: vec![artist.to_string()],
+      genres: vec![genre.to_string()],
+      rating,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_build_playlists() {
+    let entries =
+      vec![entry("Stellar Stellar", "Hoshimachi Suisei", "J-Pop", Some(5)), entry("Idol", "YOASOBI", "J-Pop", None)];
+    let playlists = build_playlists(&entries);
+    assert_eq!(playlists.keys().collect::<Vec<_>>(), vec![
+      "Artist - Hoshimachi Suisei",
+      "Artist - YOASOBI",
+      "Genre - J-Pop",
+      "Rating - 5 stars"
+    ]);
+    assert_eq!(playlists["Genre - J-Pop"].len(), 2);
+    assert_eq!(
+      to_m3u(&playlists["Rating - 5 stars"], "music/"),
+      "#EXTM3U\n#GENERATED-BY:muzik\n#EXTINF:-1,Hoshimachi Suisei - Stellar Stellar\n\
+       music/Hoshimachi Suisei/Stellar Stellar.opus\n"
+    );
+    assert_eq!(file_name("Genre - AC/DC: Live?"), "Genre - AC_DC_ Live_.m3u8");
+
+    let entries = vec![entry("Stellar Stellar", "Hoshimachi Suisei", "J-Pop", None), PlaylistEntry {
+      song_id: 1,
+      ..entry("Idol", "YOASOBI", "J-Pop", None)
+    }];
+    let stored = vec![("Drive".to_string(), vec![1, 2, 0, 1]), ("Artist - YOASOBI".to_string(), vec![0])];
+    let playlists = all_playlists(&entries, &stored);
+    let titles = |playlist: &str| playlists[playlist].iter().map(|entry| entry.title.as_str()).collect::<Vec<_>>();
+    assert_eq!(titles(LIBRARY), vec!["Stellar Stellar", "Idol"]);
+    // the song without a file is left out
+    assert_eq!(titles("Drive"), vec!["Idol", "Stellar Stellar", "Idol"]);
+    assert_eq!(titles("Artist - YOASOBI"), vec!["Stellar Stellar"]);
+  }
+
+  #[test]
+  fn test_path_prefix() -> Result<()> {
+    let mut config = Config::default();
+    config.config.music_dir = PathBuf::from("/home/suisei/Music");
+    config.config.playlists.path_prefix = "/music/".to_string();
+    let directory = Path::new("/home/suisei/.local/share/muzik/playlists");
+    assert_eq!(path_prefix(&config, directory, PlaylistPaths::MusicDir)?, "/music/");
+    assert_eq!(path_prefix(&config, directory, PlaylistPaths::Absolute)?, "/home/suisei/Music/");
+    assert_eq!(path_prefix(&config, directory, PlaylistPaths::Relative)?, "../../../../Music/");
+    assert_eq!(path_prefix(&config, Path::new("/home/suisei/Music"), PlaylistPaths::Relative)?, "");
+    assert_eq!(path_prefix(&config, Path::new("/home/suisei/Music/Playlists"), PlaylistPaths::Relative)?, "../");
+    Ok(())
+  }
+
+  #[test]
+  fn test_write_playlists_removes_stale() -> Result<()> {
+    let directory = std::env::temp_dir().join(format!("muzik-playlists-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    std::fs::write(directory.join("mine.m3u"), "#EXTM3U\nsong.opus\n")?;
+    std::fs::write(directory.join("Artist - Gone.m3u"), format!("#EXTM3U\n{GENERATED_MARKER}\n"))?;
+    std::fs::write(directory.join("Artist - Gone.m3u8"), format!("#EXTM3U\n{GENERATED_MARKER}\n"))?;
+
+    let entries = vec![entry("Idol", "YOASOBI", "J-Pop", None)];
+    assert_eq!(write_playlists(&directory, &all_playlists(&entries, &[]), "")?, 3);
+    assert!(directory.join("mine.m3u").exists());
+    assert!(!directory.join("Artist - Gone.m3u").exists());
+    assert!(!directory.join("Artist - Gone.m3u8").exists());
+    assert!(directory.join("Artist - YOASOBI.m3u8").exists());
+    assert!(directory.join("Library.m3u8").exists());
+    std::fs::remove_dir_all(&directory)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_load_entries() -> Result<()> {
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "YOASOBI/Idol.opus".to_string() })?;
+    let song_id =
+      database.insert_song(NewSong { title: "Idol".to_string(), file_id: Some(file_id), ..Default::default() })?;
+    database.insert_song(NewSong { title: "Not downloaded".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "YOASOBI".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    let genre_id = database.insert_genre(NewGenre { name: "J-Pop".to_string() })?;
+    database.insert_song_genre(SongGenre { song_id, genre_id })?;
+
+    assert_eq!(load_entries(&mut database)?, vec![PlaylistEntry {
+      song_id,
+      title: "Idol".to_string(),
+      path: "YOASOBI/Idol.opus".to_string(),
+      artists: vec!["YOASOBI".to_string()],
+      genres: vec!["J-Pop".to_string()],
+      rating: None,
+    }]);
+    Ok(())
+  }
+}
@@ -0,0 +1,272 @@
+//! Counting the plays of songs
+//!
+//! A song played by muzik counts as played once half of it, or four minutes of it, was heard, the
+//! way ListenBrainz counts listens. The play is then recorded in the play history of the library,
+//! which the dashboard and the reports are made of. Songs stopped or skipped before are not counted.
+//! With `listenbrainz.enabled`, songs are also submitted to ListenBrainz as they start and once
+//! they are counted.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use tracing::warn;
+
+use crate::{
+  config::ListenBrainzConfig,
+  database::{Database, DatabaseHandle},
+  listenbrainz,
+};
+
+/// A song counts as played once this much of it was heard, or half of it if it is shorter
+pub const MAX_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// How much of a song is heard before it counts as played
+pub fn threshold(duration: Option<Duration>) -> Duration {
+  duration.map_or(MAX_THRESHOLD, |duration| (duration / 2).min(MAX_THRESHOLD))
+}
+
+/// The song being played
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayedSong {
+  /// a song of the library, by id
+  Song(i32),
+  /// the song of a video, found in the library once it is downloaded
+  Video(String),
+}
+
+impl PlayedSong {
+  /// The id of the song in the library, if it is there
+  fn find(self, database: &mut Database) -> Result<Option<i32>> {
+    match self {
+      PlayedSong::Song(song_id) => Ok(Some(song_id)),
+      PlayedSong::Video(youtube_id) => Ok(database.get_song_by_youtube_id(&youtube_id)?.map(|song| song.id)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Playing {
+  song: PlayedSong,
+  duration: Option<Duration>,
+}
+
+/// Counts the plays of the songs played one after another by a player
+#[derive(Debug, Default)]
+pub struct PlayCounter {
+  /// the song played, until it is counted
+  playing: Option<Playing>,
+  /// where the songs are submitted, if they are
+  listenbrainz: Option<ListenBrainzConfig>,
+}
+
+impl PlayCounter {
+  /// A counter submitting the songs to ListenBrainz if it is enabled
+  pub fn new(listenbrainz: &ListenBrainzConfig) -> Self {
+    Self { playing: None, listenbrainz: Some(listenbrainz.clone()).filter(|config| config.enabled) }
+  }
+
+  /// A song started playing, in place of the one played before
+  pub fn start(&mut self, database: &DatabaseHandle, song: PlayedSong, duration: Option<Duration>) {
+    self.submit(database, song.clone(), None);
+    self.playing = Some(Playing { song, duration });
+  }
+
+  /// Playback stopped, the song played is not counted if it was not yet
+  pub fn stop(&mut self) {
+    self.playing = None;
+  }
+
+  /// Record a play of the song played once playback is past its threshold. A streamed song is
+  /// counted once it is downloaded
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - how far the song is played
+  /// * `duration` - the duration of the song, if it is known better than when it started
+  ///
+  /// # Returns
+  ///
+  /// * the id of the song if its play was recorded now
+  pub async fn reached(
+    &mut self,
+    database: &DatabaseHandle,
+    position: Duration,
+    duration: Option<Duration>,
+  ) -> Result<Option<i32>> {
+    let Some(playing) = &self.playing else {
+      return Ok(None);
+    };
+    if position < threshold(duration.or(playing.duration)) {
+      return Ok(None);
+    }
+    let song = playing.song.clone();
+    // whole seconds, the same for the play and the listen
+    let listened_at = Utc::now().timestamp();
+    let played_at = DateTime::from_timestamp(listened_at, 0).unwrap_or_default().naive_utc();
+    let recorded = database
+      .call(move |database| {
+        let song_id = song.find(database)?;
+        if let Some(song_id) = song_id {
+          database.record_play(song_id, played_at)?;
+        }
+        Ok(song_id)
+      })
+      .await?;
+    if let Some(song_id) = recorded {
+      self.playing = None;
+      self.submit(database, PlayedSong::Song(song_id), Some(listened_at));
+    }
+    Ok(recorded)
+  }
+
+  /// Submit the song to ListenBrainz as playing, or as listened to at `listened_at`
+  fn submit(&self, database: &DatabaseHandle, song: PlayedSong, listened_at: Option<i64>) {
+    let Some(config) = self.listenbrainz.clone() else {
+      return;
+    };
+    let database = database.clone();
+    tokio::spawn(async move {
+      let metadata = database
+        .call(move |database| {
+          song.find(database)?.map(|song_id| listenbrainz::track_metadata(database, song_id)).transpose()
+        })
+        .await;
+      match metadata {
+        Ok(Some(Some(metadata))) => listenbrainz::spawn_submit(&config, listened_at, metadata),
+        Ok(_) => {},
+        Err(e) => warn!("failed to find the song to submit to listenbrainz: {e}"),
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use serde_json::Value;
+  use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+  };
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewSong, SongArtist},
+  };
+
+  /// A ListenBrainz answering every request, whose bodies are received from the channel
+  async fn fake_listenbrainz() -> Result<(ListenBrainzConfig, mpsc::UnboundedReceiver<Value>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let (body_tx, body_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let mut stream = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+          let mut line = String::new();
+          stream.read_line(&mut line).await.expect("a header");
+          match line.trim_end().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+              length = value.trim().parse().expect("a length");
+            },
+            None if line.trim_end().is_empty() => break,
+            _ => {},
+          }
+        }
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.expect("a body");
+        body_tx.send(serde_json::from_slice(&body).expect("a JSON body")).expect("a receiver");
+        let answer = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}";
+        stream.write_all(answer.as_bytes()).await.expect("an answer");
+      }
+    });
+    let config = ListenBrainzConfig {
+      enabled: true,
+      token: Some("token".to_string()),
+      username: None,
+      api_url: format!("http://127.0.0.1:{port}"),
+    };
+    Ok((config, body_rx))
+  }
+
+  #[test]
+  fn test_threshold() {
+    assert_eq!(threshold(Some(Duration::from_secs(200))), Duration::from_secs(100));
+    assert_eq!(threshold(Some(Duration::from_secs(3600))), MAX_THRESHOLD);
+    assert_eq!(threshold(None), MAX_THRESHOLD);
+  }
+
+  #[tokio::test]
+  async fn test_play_counter() -> Result<()> {
+    let mut database = setup_database()?;
+    let stellar = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let database = DatabaseHandle::spawn(database)?;
+    let mut plays = PlayCounter::default();
+
+    // skipped before half of it was heard
+    plays.start(&database, PlayedSong::Song(stellar), Some(Duration::from_secs(200)));
+    assert_eq!(plays.reached(&database, Duration::from_secs(60), None).await?, None);
+    plays.stop();
+    assert_eq!(plays.reached(&database, Duration::from_secs(150), None).await?, None);
+
+    plays.start(&database, PlayedSong::Song(stellar), Some(Duration::from_secs(200)));
+    assert_eq!(plays.reached(&database, Duration::from_secs(100), None).await?, Some(stellar));
+    assert_eq!(plays.reached(&database, Duration::from_secs(150), None).await?, None);
+
+    // a streamed song is counted once it is in the library
+    plays.start(&database, PlayedSong::Video("4PMabbDrMcs".to_string()), None);
+    assert_eq!(plays.reached(&database, Duration::from_secs(120), Some(Duration::from_secs(200))).await?, None);
+    let bluerose = database
+      .call(|database| {
+        database.insert_song(NewSong {
+          title: "Bluerose".to_string(),
+          youtube_id: Some("4PMabbDrMcs".to_string()),
+          ..Default::default()
+        })
+      })
+      .await?;
+    assert_eq!(
+      plays.reached(&database, Duration::from_secs(120), Some(Duration::from_secs(200))).await?,
+      Some(bluerose)
+    );
+
+    let recent = database.call(|database| database.get_recently_played(10)).await?;
+    let counts: Vec<_> = recent.iter().map(|(song, _)| (song.id, song.play_count)).collect();
+    assert_eq!(counts.len(), 2);
+    assert!(counts.contains(&(stellar, 1)) && counts.contains(&(bluerose, 1)));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_play_counter_submits_listens() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    let database = DatabaseHandle::spawn(database)?;
+    let (config, mut bodies) = fake_listenbrainz().await?;
+    let mut plays = PlayCounter::new(&config);
+
+    plays.start(&database, PlayedSong::Song(song_id), Some(Duration::from_secs(200)));
+    let track_metadata = serde_json::json!({ "artist_name": "Hoshimachi Suisei", "track_name": "Stellar Stellar" });
+    assert_eq!(
+      bodies.recv().await,
+      Some(serde_json::json!({ "listen_type": "playing_now", "payload": [{ "track_metadata": track_metadata }] }))
+    );
+    assert_eq!(plays.reached(&database, Duration::from_secs(100), None).await?, Some(song_id));
+    let recent = database.call(|database| database.get_recently_played(1)).await?;
+    let listened_at = recent[0].1.and_utc().timestamp();
+    assert_eq!(
+      bodies.recv().await,
+      Some(serde_json::json!({
+        "listen_type": "single",
+        "payload": [{ "listened_at": listened_at, "track_metadata": track_metadata }]
+      }))
+    );
+    Ok(())
+  }
+}
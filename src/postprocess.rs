@@ -0,0 +1,172 @@
+//! Post-processing of downloaded songs with ffmpeg
+//!
+//! Rips often start or end with several seconds of dead air. When enabled, it is cut off with the
+//! `silenceremove` filter of ffmpeg once a song is downloaded. Mixes are cut into their tracks
+//! here as well, songs are tagged with their disc and track and get their cover embedded, and songs
+//! streamed at a lower bitrate are transcoded.
+
+use std::{path::Path, process::Stdio, time::Duration};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::process::{Child, Command};
+
+use crate::config::TrimSilenceConfig;
+
+/// The ffmpeg filter removing the silence at the start and the end. `silenceremove` only trims
+/// the start reliably, so the audio is reversed to trim the end the same way
+pub fn silence_filter(config: &TrimSilenceConfig) -> String {
+  let trim_start = format!(
+    "silenceremove=start_periods=1:start_duration={}:start_threshold={}dB",
+    config.min_duration_ms as f64 / 1000.0,
+    config.threshold_db
+  );
+  format!("{trim_start},areverse,{trim_start},areverse")
+}
+
+/// Trim the silence at the start and the end of the audio file, in place
+pub async fn trim_silence(path: &Path, config: &TrimSilenceConfig) -> Result<()> {
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let trimmed = path.with_extension(format!("trimmed.{extension}"));
+  let mut command = ffmpeg();
+  command.arg("-i").arg(path).args(["-map_metadata", "0", "-af", &silence_filter(config)]).arg(&trimmed);
+  if let Err(e) = run(&mut command).await {
+    let _ = std::fs::remove_file(&trimmed);
+    return Err(e);
+  }
+  std::fs::rename(&trimmed, path)?;
+  Ok(())
+}
+
+/// Copy the part of the audio file between `start` and `end` into `destination` with the tags,
+/// or up to the end of the file if `end` is `None`
+pub async fn cut(
+  source: &Path,
+  destination: &Path,
+  start: Duration,
+  end: Option<Duration>,
+  tags: &[(&str, String)],
+) -> Result<()> {
+  let mut command = ffmpeg();
+  command.args(["-ss", &start.as_secs_f64().to_string()]);
+  if let Some(end) = end {
+    command.args(["-to", &end.as_secs_f64().to_string()]);
+  }
+  command.arg("-i").arg(source).args(["-map_metadata", "-1", "-c", "copy"]);
+  for (key, value) in tags {
+    command.arg("-metadata").arg(format!("{key}={value}"));
+  }
+  run(command.arg(destination)).await
+}
+
+/// Write the tags to the audio file in place, keeping its other tags
+pub async fn write_tags(path: &Path, tags: &[(&str, String)]) -> Result<()> {
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let tagged = path.with_extension(format!("tagged.{extension}"));
+  let mut command = ffmpeg();
+  command.arg("-i").arg(path).args(["-map_metadata", "0", "-c", "copy"]);
+  for (key, value) in tags {
+    command.arg("-metadata").arg(format!("{key}={value}"));
+  }
+  if let Err(e) = run(command.arg(&tagged)).await {
+    let _ = std::fs::remove_file(&tagged);
+    return Err(e);
+  }
+  std::fs::rename(&tagged, path)?;
+  Ok(())
+}
+
+/// Embed the picture in the audio file in place as its cover, keeping its tags. Fails for the
+/// containers ffmpeg can not put a picture in
+pub async fn embed_cover(path: &Path, cover: &Path) -> Result<()> {
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let embedded = path.with_extension(format!("embedded.{extension}"));
+  let mut command = ffmpeg();
+  command.arg("-i").arg(path).arg("-i").arg(cover).args([
+    "-map",
+    "0:a",
+    "-map",
+    "1:v",
+    "-map_metadata",
+    "0",
+    "-c",
+    "copy",
+    "-disposition:v",
+    "attached_pic",
+  ]);
+  if let Err(e) = run(command.arg(&embedded)).await {
+    let _ = std::fs::remove_file(&embedded);
+    return Err(e);
+  }
+  std::fs::rename(&embedded, path)?;
+  Ok(())
+}
+
+/// Start transcoding the audio file to opus in an ogg container, written to the stdout of the
+/// process. The process is killed when dropped
+///
+/// # Arguments
+///
+/// * `bitrate` - in kbit/s
+/// * `start` - the position to start from
+pub fn transcode(path: &Path, bitrate: u32, start: Duration) -> Result<Child> {
+  let mut command = ffmpeg();
+  if !start.is_zero() {
+    command.args(["-ss", &start.as_secs_f64().to_string()]);
+  }
+  command.arg("-i").arg(path).args(["-vn", "-c:a", "libopus", "-b:a", &format!("{bitrate}k"), "-f", "ogg", "pipe:1"]);
+  let child = command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).kill_on_drop(true).spawn()?;
+  Ok(child)
+}
+
+/// Transcode the audio file to opus at `bitrate` kbit/s into `destination`, keeping its tags
+pub async fn transcode_file(source: &Path, destination: &Path, bitrate: u32) -> Result<()> {
+  let mut command = ffmpeg();
+  command.arg("-i").arg(source).args(["-map_metadata", "0", "-vn", "-c:a", "libopus", "-b:a", &format!("{bitrate}k")]);
+  run(command.arg(destination)).await
+}
+
+/// Decode the first `seconds` of the audio file, failing on any error ffmpeg reports, as a damaged
+/// file usually decodes with errors rather than not at all
+pub async fn decode(path: &Path, seconds: u32) -> Result<()> {
+  let mut command = ffmpeg();
+  command.args(["-t", &seconds.to_string(), "-i"]).arg(path).args(["-vn", "-f", "null", "-"]);
+  let output = command.stdin(Stdio::null()).output().await?;
+  let errors = String::from_utf8_lossy(&output.stderr);
+  match (output.status.success(), errors.lines().next()) {
+    (true, None) => Ok(()),
+    (_, Some(error)) => Err(eyre!("{}", error.trim())),
+    (false, None) => Err(eyre!("ffmpeg failed with {}", output.status)),
+  }
+}
+
+/// ffmpeg overwriting its output and only printing errors
+fn ffmpeg() -> Command {
+  let mut command = Command::new("ffmpeg");
+  command.args(["-y", "-loglevel", "error"]);
+  command
+}
+
+async fn run(command: &mut Command) -> Result<()> {
+  let output = command.output().await?;
+  if !output.status.success() {
+    return Err(eyre!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_silence_filter() {
+    let config = TrimSilenceConfig { enabled: true, threshold_db: -40, min_duration_ms: 250 };
+    assert_eq!(
+      silence_filter(&config),
+      "silenceremove=start_periods=1:start_duration=0.25:start_threshold=-40dB,areverse,silenceremove=start_periods=1:\
+       start_duration=0.25:start_threshold=-40dB,areverse"
+    );
+  }
+}
@@ -0,0 +1,164 @@
+//! The audio quality of song files
+//!
+//! The codec and bitrate of every file are recorded when it is downloaded or scanned. Songs are
+//! transcoded to opus when streamed at a lower bitrate, and transcoding a lossy file into another
+//! lossy format loses quality twice over. Below the quality floor of the `transcode` settings,
+//! such a transcode is warned about or skipped, serving the file as it is instead. The best audio
+//! YouTube offers for a video is read from the formats yt-dlp lists, to tell uploads apart before
+//! downloading one.
+
+use std::fmt;
+
+use youtube_dl::Format;
+
+use crate::config::{LossyPolicy, TranscodeConfig};
+
+/// Codecs keeping all of the audio, as named by ffprobe
+const LOSSLESS_CODECS: [&str; 6] = ["flac", "alac", "ape", "wavpack", "tta", "mlp"];
+
+/// The codec and bitrate of a song file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioQuality {
+  pub codec: String,
+  /// in kbit/s, unknown for some containers
+  pub bitrate: Option<u32>,
+}
+
+impl AudioQuality {
+  pub fn is_lossless(&self) -> bool {
+    LOSSLESS_CODECS.contains(&self.codec.as_str()) || self.codec.starts_with("pcm_")
+  }
+
+  /// Whether the audio is better than the other audio: lossless over lossy, or a higher bitrate
+  /// between lossy ones. An unknown bitrate is not better than anything
+  pub fn is_better_than(&self, other: &AudioQuality) -> bool {
+    match (self.is_lossless(), other.is_lossless()) {
+      (true, false) => true,
+      (false, false) => self.bitrate.unwrap_or_default() > other.bitrate.unwrap_or_default(),
+      _ => false,
+    }
+  }
+}
+
+impl fmt::Display for AudioQuality {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.bitrate {
+      Some(bitrate) => write!(f, "{} {bitrate} kbit/s", self.codec),
+      None => write!(f, "{}", self.codec),
+    }
+  }
+}
+
+/// The best audio among the formats of a video, as yt-dlp lists them. Formats with an unknown
+/// bitrate only count when no other has audio
+pub fn best_audio(formats: &[Format]) -> Option<AudioQuality> {
+  formats
+    .iter()
+    .filter_map(|format| {
+      let codec = format.acodec.as_deref()?;
+      // yt-dlp names the codec of the container, such as mp4a.40.2 for AAC
+      let codec = match codec.split('.').next().unwrap_or(codec) {
+        "mp4a" => "aac",
+        codec => codec,
+      };
+      let bitrate = format.abr.filter(|abr| *abr > 0.0).map(|abr| abr.round() as u32);
+      Some(AudioQuality { codec: codec.to_string(), bitrate })
+    })
+    .reduce(|best, quality| if quality.is_better_than(&best) || best.bitrate.is_none() { quality } else { best })
+}
+
+/// What to do about transcoding a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeDecision {
+  Transcode,
+  /// transcode anyway, with the reason it loses quality
+  Warn(String),
+  /// serve the file as it is, with the reason
+  Skip(String),
+}
+
+/// Decide whether the file can be transcoded to opus at `bitrate` kbit/s. Files of unknown quality
+/// are transcoded
+pub fn check_transcode(source: Option<&AudioQuality>, bitrate: u32, config: &TranscodeConfig) -> TranscodeDecision {
+  let Some(source) = source.filter(|source| !source.is_lossless()) else {
+    return TranscodeDecision::Transcode;
+  };
+  if bitrate >= config.min_lossy_bitrate {
+    return TranscodeDecision::Transcode;
+  }
+  let reason = format!(
+    "transcoding {source} to opus {bitrate} kbit/s is lossy to lossy below {} kbit/s",
+    config.min_lossy_bitrate
+  );
+  match config.below_floor {
+    LossyPolicy::Warn => TranscodeDecision::Warn(reason),
+    LossyPolicy::Skip => TranscodeDecision::Skip(reason),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_check_transcode() {
+    let opus = AudioQuality { codec: "opus".to_string(), bitrate: Some(160) };
+    let flac = AudioQuality { codec: "flac".to_string(), bitrate: Some(900) };
+    let warn = TranscodeConfig { min_lossy_bitrate: 96, below_floor: LossyPolicy::Warn };
+    let skip = TranscodeConfig { below_floor: LossyPolicy::Skip, ..warn.clone() };
+
+    assert!(flac.is_lossless());
+    assert!(AudioQuality { codec: "pcm_s16le".to_string(), bitrate: None }.is_lossless());
+    assert!(!opus.is_lossless());
+    assert_eq!(check_transcode(Some(&flac), 32, &skip), TranscodeDecision::Transcode);
+    assert_eq!(check_transcode(None, 32, &skip), TranscodeDecision::Transcode);
+    assert_eq!(check_transcode(Some(&opus), 128, &skip), TranscodeDecision::Transcode);
+    assert_eq!(
+      check_transcode(Some(&opus), 64, &warn),
+      TranscodeDecision::Warn(
+        "transcoding opus 160 kbit/s to opus 64 kbit/s is lossy to lossy below 96 kbit/s".to_string()
+      )
+    );
+    assert!(matches!(check_transcode(Some(&opus), 64, &skip), TranscodeDecision::Skip(_)));
+  }
+
+  #[test]
+  fn test_best_audio() {
+    let format =
+      |acodec: Option<&str>, abr: Option<f64>| Format { acodec: acodec.map(str::to_string), abr, ..Default::default() };
+    assert_eq!(best_audio(&[]), None);
+    assert_eq!(best_audio(&[format(None, Some(1200.0))]), None);
+    assert_eq!(
+      best_audio(&[
+        format(Some("mp4a.40.5"), None),
+        format(Some("opus"), Some(52.1)),
+        format(Some("mp4a.40.2"), Some(129.5)),
+        format(Some("opus"), Some(128.0)),
+        format(None, None),
+      ]),
+      Some(AudioQuality { codec: "aac".to_string(), bitrate: Some(130) })
+    );
+    assert_eq!(
+      best_audio(&[format(Some("mp4a.40.5"), None)]),
+      Some(AudioQuality { codec: "aac".to_string(), bitrate: None })
+    );
+  }
+
+  #[test]
+  fn test_is_better_than() {
+    let opus = AudioQuality { codec: "opus".to_string(), bitrate: Some(160) };
+    let mp3 = AudioQuality { codec: "mp3".to_string(), bitrate: Some(128) };
+    let flac = AudioQuality { codec: "flac".to_string(), bitrate: Some(900) };
+    let unknown = AudioQuality { codec: "aac".to_string(), bitrate: None };
+
+    assert!(opus.is_better_than(&mp3));
+    assert!(!mp3.is_better_than(&opus));
+    assert!(flac.is_better_than(&opus));
+    assert!(!opus.is_better_than(&flac));
+    assert!(!flac.is_better_than(&flac));
+    assert!(!unknown.is_better_than(&mp3));
+    assert!(mp3.is_better_than(&unknown));
+  }
+}
@@ -0,0 +1,494 @@
+//! A small query language for filtering songs
+//!
+//! Queries are made of conditions such as `artist:"Suisei"`, `year>=2021` or `genre=JPop`,
+//! combined with `AND`, `OR`, `NOT` and parentheses. Conditions next to each other are combined
+//! with `AND`. A word without a field searches the title.
+//!
+//! `:` matches text containing the value and `=` matches the whole text, both ignoring case.
+//! Numbers can also be compared with `!=`, `<`, `<=`, `>` and `>=`. Parsed queries compile down to
+//! Diesel predicates on the `song` table.
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use diesel::{
+  dsl::not,
+  expression::BoxableExpression,
+  prelude::*,
+  sql_types::{Bool, Nullable},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  database::{lower, MultiBackend},
+  errors::ErrorCategory,
+  schema::{album, artist, genre, song, song_extra, songs_albums, songs_artists, songs_genres},
+};
+
+/// A boxed filter on the `song` table
+pub type SongPredicate = Box<dyn BoxableExpression<song::table, MultiBackend, SqlType = Bool>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Field {
+  Title,
+  Artist,
+  Album,
+  Genre,
+  Year,
+  Rating,
+  Plays,
+  /// the description or the comments of the video the song was downloaded from
+  Description,
+  /// the release type of the album, such as single or ep
+  Type,
+}
+
+impl Field {
+  fn parse(name: &str) -> Result<Self> {
+    match name.to_lowercase().as_str() {
+      "title" => Ok(Field::Title),
+      "artist" => Ok(Field::Artist),
+      "album" => Ok(Field::Album),
+      "genre" => Ok(Field::Genre),
+      "year" => Ok(Field::Year),
+      "rating" => Ok(Field::Rating),
+      "plays" => Ok(Field::Plays),
+      "description" => Ok(Field::Description),
+      "type" => Ok(Field::Type),
+      _ => Err(eyre!("unknown field \"{name}\"")),
+    }
+  }
+
+  fn is_numeric(&self) -> bool {
+    matches!(self, Field::Year | Field::Rating | Field::Plays)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+  /// `:`, text containing the value
+  Contains,
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+  Text(String),
+  Number(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+  pub field: Field,
+  pub op: Op,
+  pub value: Value,
+}
+
+impl Condition {
+  fn new(field: Field, op: Op, value: String) -> Result<Self> {
+    if field.is_numeric() {
+      let number = value.parse().map_err(|_| eyre!("{field:?} must be compared to a number, got \"{value}\""))?;
+      // `:` on numbers has no sensible meaning other than equality
+      let op = if op == Op::Contains { Op::Eq } else { op };
+      return Ok(Self { field, op, value: Value::Number(number) });
+    }
+    if !matches!(op, Op::Contains | Op::Eq | Op::Ne) {
+      return Err(eyre!("{field:?} can only be matched with :, = or !="));
+    }
+    Ok(Self { field, op, value: Value::Text(value) })
+  }
+}
+
+/// The order songs are listed in, a page at a time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SongSort {
+  /// in the order they were added to the library
+  #[default]
+  Added,
+  /// by title regardless of case
+  Title,
+  /// the most played first
+  PlayCount,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Query {
+  Condition(Condition),
+  And(Box<Query>, Box<Query>),
+  Or(Box<Query>, Box<Query>),
+  Not(Box<Query>),
+}
+
+impl Query {
+  pub fn parse(input: &str) -> Result<Self> {
+    let mut parser = Parser { input, position: 0 };
+    let query = parser.parse_or().and_then(|query| {
+      parser.skip_whitespace();
+      if parser.position < input.len() {
+        return Err(eyre!("unexpected \"{}\" at {}", &input[parser.position..], parser.position));
+      }
+      Ok(query)
+    });
+    query.wrap_err(ErrorCategory::InvalidQuery)
+  }
+
+  /// A query matching the songs with the text in their title or in the name of one of their
+  /// artists
+  pub fn search(text: &str) -> Self {
+    let condition =
+      |field| Box::new(Query::Condition(Condition { field, op: Op::Contains, value: Value::Text(text.to_string()) }));
+    Query::Or(condition(Field::Title), condition(Field::Artist))
+  }
+
+  /// Compile the query into a filter on the `song` table
+  pub fn to_predicate(&self) -> SongPredicate {
+    match self {
+      Query::Condition(condition) => condition_predicate(condition),
+      Query::And(left, right) => Box::new(left.to_predicate().and(right.to_predicate())),
+      Query::Or(left, right) => Box::new(left.to_predicate().or(right.to_predicate())),
+      Query::Not(query) => Box::new(not(query.to_predicate())),
+    }
+  }
+}
+
+/// The lowercase value with the wildcards of `LIKE` escaped, matching the whole text
+fn exact_pattern(value: &str) -> String {
+  value.to_lowercase().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Escape the wildcards of a `LIKE` pattern
+pub(crate) fn like_pattern(value: &str) -> String {
+  format!("%{}%", exact_pattern(value))
+}
+
+/// The `LIKE` pattern a text is compared with to match a condition, and whether matching texts are
+/// the ones kept. Conditions compile to `LIKE` and `BETWEEN` alone, so that a predicate has one
+/// type whatever its op and can be nested in the subselects of both backends
+fn text_match(op: Op, value: &str) -> (String, bool) {
+  match op {
+    Op::Contains => (like_pattern(value), true),
+    Op::Ne => (exact_pattern(value), false),
+    _ => (exact_pattern(value), true),
+  }
+}
+
+/// The range of numbers matching a condition, and whether numbers in it are the ones kept
+fn number_range(op: Op, value: i32) -> (i32, i32, bool) {
+  match op {
+    Op::Ne => (value, value, false),
+    Op::Lt => value.checked_sub(1).map_or((i32::MIN, i32::MAX, false), |high| (i32::MIN, high, true)),
+    Op::Le => (i32::MIN, value, true),
+    Op::Gt => value.checked_add(1).map_or((i32::MIN, i32::MAX, false), |low| (low, i32::MAX, true)),
+    Op::Ge => (value, i32::MAX, true),
+    Op::Contains | Op::Eq => (value, value, true),
+  }
+}
+
+/// Compare a text column with a condition
+macro_rules! text_predicate {
+  ($column:expr, $op:expr, $value:expr) => {{
+    let (pattern, matches) = text_match($op, $value);
+    lower($column).like(pattern).escape('\\').eq(matches)
+  }};
+}
+
+/// Compare a number column with a condition
+macro_rules! number_predicate {
+  ($column:expr, $op:expr, $value:expr) => {{
+    let (low, high, matches) = number_range($op, $value);
+    $column.between(low, high).eq(matches)
+  }};
+}
+
+fn condition_predicate(condition: &Condition) -> SongPredicate {
+  let op = condition.op;
+  match (&condition.field, &condition.value) {
+    (Field::Title, Value::Text(value)) => Box::new(text_predicate!(song::title, op, value)),
+    (Field::Artist, Value::Text(value)) => {
+      let song_ids = songs_artists::table
+        .inner_join(artist::table)
+        .filter(text_predicate!(artist::name, op, value))
+        .select(songs_artists::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    (Field::Album, Value::Text(value)) => {
+      let song_ids = songs_albums::table
+        .inner_join(album::table)
+        .filter(text_predicate!(album::name, op, value))
+        .select(songs_albums::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    (Field::Genre, Value::Text(value)) => {
+      let song_ids = songs_genres::table
+        .inner_join(genre::table)
+        .filter(text_predicate!(genre::name, op, value))
+        .select(songs_genres::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    (Field::Year, Value::Number(value)) => {
+      let song_ids = songs_albums::table
+        .inner_join(album::table)
+        .filter(album::year.is_not_null())
+        .filter(number_predicate!(album::year.assume_not_null(), op, *value))
+        .select(songs_albums::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    (Field::Rating, Value::Number(value)) => {
+      Box::new(song::rating.is_not_null().and(number_predicate!(song::rating.assume_not_null(), op, *value)))
+    },
+    (Field::Plays, Value::Number(value)) => Box::new(number_predicate!(song::play_count, op, *value)),
+    (Field::Description, Value::Text(value)) => {
+      let song_ids = song_extra::table
+        .filter(text_predicate!(song_extra::description.assume_not_null(), op, value).or(text_predicate!(
+          song_extra::comments.assume_not_null(),
+          op,
+          value
+        )))
+        .select(song_extra::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    (Field::Type, Value::Text(value)) => {
+      let song_ids = songs_albums::table
+        .inner_join(album::table)
+        .filter(album::release_type.is_not_null())
+        .filter(text_predicate!(album::release_type.assume_not_null(), op, value))
+        .select(songs_albums::song_id);
+      Box::new(song::id.eq_any(song_ids))
+    },
+    // conditions are checked when parsed, so the value always fits the field
+    _ => Box::new(diesel::dsl::sql::<Bool>("1 = 0")),
+  }
+}
+
+struct Parser<'a> {
+  input: &'a str,
+  position: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn rest(&self) -> &'a str {
+    &self.input[self.position..]
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.position += rest.len() - rest.trim_start().len();
+  }
+
+  /// Consume the keyword if it is next, followed by whitespace or a parenthesis
+  fn keyword(&mut self, keyword: &str) -> bool {
+    let rest = self.rest();
+    let followed_by_separator = rest.starts_with(keyword)
+      && rest
+        .get(keyword.len()..)
+        .and_then(|after| after.chars().next())
+        .is_some_and(|c| c.is_whitespace() || c == '(' || c == '"');
+    if followed_by_separator {
+      self.position += keyword.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse_or(&mut self) -> Result<Query> {
+    let mut query = self.parse_and()?;
+    loop {
+      self.skip_whitespace();
+      if !self.keyword("OR") {
+        return Ok(query);
+      }
+      query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+    }
+  }
+
+  fn parse_and(&mut self) -> Result<Query> {
+    let mut query = self.parse_unary()?;
+    loop {
+      self.skip_whitespace();
+      let rest = self.rest();
+      if rest.is_empty() || rest.starts_with(')') || rest.starts_with("OR ") || rest.starts_with("OR(") {
+        return Ok(query);
+      }
+      self.keyword("AND");
+      query = Query::And(Box::new(query), Box::new(self.parse_unary()?));
+    }
+  }
+
+  fn parse_unary(&mut self) -> Result<Query> {
+    self.skip_whitespace();
+    if self.keyword("NOT") {
+      return Ok(Query::Not(Box::new(self.parse_unary()?)));
+    }
+    if self.rest().starts_with('(') {
+      self.position += 1;
+      let query = self.parse_or()?;
+      self.skip_whitespace();
+      if !self.rest().starts_with(')') {
+        return Err(eyre!("missing closing parenthesis at {}", self.position));
+      }
+      self.position += 1;
+      return Ok(query);
+    }
+    self.parse_condition()
+  }
+
+  fn parse_condition(&mut self) -> Result<Query> {
+    let start = self.position;
+    let name_length = self.rest().find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(self.rest().len());
+    let name = &self.rest()[..name_length];
+    self.position += name_length;
+
+    let op = [
+      (">=", Op::Ge),
+      ("<=", Op::Le),
+      ("!=", Op::Ne),
+      (":", Op::Contains),
+      ("=", Op::Eq),
+      ("<", Op::Lt),
+      (">", Op::Gt),
+    ]
+    .into_iter()
+    .find(|(symbol, _)| !name.is_empty() && self.rest().starts_with(symbol));
+    let condition = match op {
+      Some((symbol, op)) => {
+        self.position += symbol.len();
+        let field = Field::parse(name)?;
+        Condition::new(field, op, self.parse_value()?)?
+      },
+      None => {
+        // a bare word searches the title
+        self.position = start;
+        Condition::new(Field::Title, Op::Contains, self.parse_value()?)?
+      },
+    };
+    Ok(Query::Condition(condition))
+  }
+
+  fn parse_value(&mut self) -> Result<String> {
+    let rest = self.rest();
+    if let Some(quoted) = rest.strip_prefix('"') {
+      let mut value = String::new();
+      let mut chars = quoted.char_indices();
+      while let Some((index, c)) = chars.next() {
+        match c {
+          '\\' => value.extend(chars.next().map(|(_, c)| c)),
+          '"' => {
+            self.position += index + 2;
+            return Ok(value);
+          },
+          c => value.push(c),
+        }
+      }
+      return Err(eyre!("missing closing quote at {}", self.position));
+    }
+    let length = rest.find(|c: char| c.is_whitespace() || c == '(' || c == ')').unwrap_or(rest.len());
+    if length == 0 {
+      return Err(eyre!("expected a value at {}", self.position));
+    }
+    self.position += length;
+    Ok(rest[..length].to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use proptest::prelude::*;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewArtist, NewGenre, NewSong, ReleaseType, SongAlbum, SongArtist, SongGenre},
+  };
+
+  fn condition(field: Field, op: Op, value: Value) -> Box<Query> {
+    Box::new(Query::Condition(Condition { field, op, value }))
+  }
+
+  #[test]
+  fn test_parse_query() -> Result<()> {
+    assert_eq!(
+      Query::parse(r#"artist:"Hoshimachi Suisei" AND year>=2021 genre=JPop"#)?,
+      Query::And(
+        Box::new(Query::And(
+          condition(Field::Artist, Op::Contains, Value::Text("Hoshimachi Suisei".to_string())),
+          condition(Field::Year, Op::Ge, Value::Number(2021)),
+        )),
+        condition(Field::Genre, Op::Eq, Value::Text("JPop".to_string())),
+      )
+    );
+    assert_eq!(
+      Query::parse("NOT (stellar OR rating<3)")?,
+      Query::Not(Box::new(Query::Or(
+        condition(Field::Title, Op::Contains, Value::Text("stellar".to_string())),
+        condition(Field::Rating, Op::Lt, Value::Number(3)),
+      )))
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_parse_query_errors() {
+    assert!(Query::parse("year>=soon").is_err());
+    assert!(Query::parse("artist>Suisei").is_err());
+    assert!(Query::parse("mood:happy").is_err());
+    assert!(Query::parse("(artist:Suisei").is_err());
+    assert!(Query::parse(r#"title:"Stellar"#).is_err());
+  }
+
+  proptest! {
+    #[test]
+    fn test_parse_query_never_panics(input in prop_oneof![any::<String>(), "(NOT|AND|OR|[a-z]{1,6}[:=<>]|[()\"]|\\PC){0,12}"]) {
+      let _ = Query::parse(&input);
+    }
+  }
+
+  #[test]
+  fn test_parse_query_multibyte() -> Result<()> {
+    assert_eq!(Query::parse("señorita")?, *condition(Field::Title, Op::Contains, Value::Text("señorita".to_string())));
+    assert_eq!(
+      Query::parse("Noé OR artist:Beyoncé")?,
+      Query::Or(
+        condition(Field::Title, Op::Contains, Value::Text("Noé".to_string())),
+        condition(Field::Artist, Op::Contains, Value::Text("Beyoncé".to_string())),
+      )
+    );
+    assert_eq!(
+      Query::parse("NOT 星街すいせい")?,
+      Query::Not(condition(Field::Title, Op::Contains, Value::Text("星街すいせい".to_string())))
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_query_songs() -> Result<()> {
+    let mut database = setup_database()?;
+    let stellar = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let idol = database.insert_song(NewSong { title: "Idol".to_string(), ..Default::default() })?;
+    let suisei = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id: stellar, artist_id: suisei })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.update_album_release(album_id, Some(2021), None, None)?;
+    database.set_album_release_type(album_id, Some(ReleaseType::Single))?;
+    database.insert_song_album(SongAlbum { song_id: stellar, album_id })?;
+    let genre_id = database.insert_genre(NewGenre { name: "JPop".to_string() })?;
+    database.insert_song_genre(SongGenre { song_id: stellar, genre_id })?;
+    database.insert_song_genre(SongGenre { song_id: idol, genre_id })?;
+    database.set_song_rating(idol, Some(5))?;
+
+    let ids = |database: &mut crate::database::Database, query: &str| -> Result<Vec<i32>> {
+      Ok(database.query_songs(&Query::parse(query)?)?.into_iter().map(|song| song.id).collect())
+    };
+    assert_eq!(ids(&mut database, r#"artist:"suisei" AND year>=2021 AND genre:jpop"#)?, vec![stellar]);
+    assert_eq!(ids(&mut database, "genre=JPop NOT artist:suisei")?, vec![idol]);
+    assert_eq!(ids(&mut database, "rating>=4 OR stellar")?, vec![stellar, idol]);
+    assert_eq!(ids(&mut database, "title:%")?, Vec::<i32>::new());
+    assert_eq!(ids(&mut database, "type=single")?, vec![stellar]);
+    assert_eq!(ids(&mut database, "type:album")?, Vec::<i32>::new());
+    Ok(())
+  }
+}
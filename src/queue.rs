@@ -0,0 +1,1199 @@
+//! The download queue
+//!
+//! Songs are queued from the TUI or over IPC and downloaded one at a time by a worker, either
+//! inside the TUI or inside `muzik daemon`. Every download is recorded in the download history.
+//! A download matching a song in the library is handled by the duplicate policy of the `download`
+//! settings, which a request can override once the user has decided for it. A request whose
+//! metadata was guessed, such as a title taken from the video title, is held for review until it
+//! is approved, corrected or rejected, unless `download.review` is off. The audio is fetched
+//! by a [Downloader], yt-dlp outside of tests. The parameters of every download are kept with its
+//! file, and a song downloaded again is fetched in the formats it was downloaded in. The cover art
+//! of a download is kept as the `download.art` settings say. yt-dlp writes the audio named after the
+//! YouTube id, and the file is then moved to where the `download.file_template` puts it.
+
+use std::{
+  collections::HashMap,
+  fmt,
+  future::Future,
+  path::{Path, PathBuf},
+  process::{Output, Stdio},
+  sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+  process::Command,
+  sync::{broadcast, Notify},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use youtube_dl::{Comment, SingleVideo};
+
+use crate::{
+  art,
+  config::{Config, DuplicatePolicy},
+  database::Database,
+  download_logs,
+  export::{self, ExportSong},
+  import::matching::normalize,
+  models::{DownloadParams, MetadataSource, NewFile, NewSong, Song, SongExtra, SongField, SongVersion, VersionKind},
+  postprocess, scan,
+  tagging::{self, SongTags},
+  upgrade,
+};
+
+/// The extensions of the audio files yt-dlp extracts, in the order they are looked for
+const DOWNLOAD_EXTENSIONS: [&str; 7] = ["opus", "m4a", "mp3", "ogg", "flac", "aac", "wav"];
+/// Asks yt-dlp for the top comments only, as videos can have thousands
+const COMMENTS_EXTRACTOR_ARGS: &str = "youtube:max_comments=20,all,0,0;comment_sort=top";
+/// The lines a comment needs to be kept, fewer are rarely anything but reactions
+const USEFUL_COMMENT_LINES: usize = 4;
+/// The number of stopped items kept for subscribers that fall behind
+const STOPPED_CAPACITY: usize = 64;
+/// Starts the progress lines of yt-dlp, told apart from its other output by it
+const PROGRESS_PREFIX: &str = "muzik-progress ";
+/// Prints the bytes downloaded, the size, the estimated size and the speed of the download
+const PROGRESS_TEMPLATE: &str = "download:muzik-progress %(progress.downloaded_bytes)s %(progress.total_bytes)s \
+                                 %(progress.total_bytes_estimate)s %(progress.speed)s";
+
+/// A song to be downloaded from YouTube, with the metadata it will be stored with
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DownloadRequest {
+  pub youtube_id: String,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  #[serde(default)]
+  pub genres: Vec<String>,
+  /// the disc of a multi-disc album the song is on
+  #[serde(default)]
+  pub disc_number: Option<i32>,
+  #[serde(default)]
+  pub track_number: Option<i32>,
+  /// the song this is the instrumental of, linked to it once downloaded
+  #[serde(default)]
+  pub instrumental_of: Option<i32>,
+  /// download through the proxy of the `download` settings, as the video is region-locked
+  #[serde(default)]
+  pub proxy: bool,
+  /// what to do if the song is in the library already, instead of the duplicate policy
+  #[serde(default)]
+  pub duplicates: Option<DuplicatePolicy>,
+  /// where the metadata of the request came from
+  #[serde(default)]
+  pub source: MetadataSource,
+  /// what of the metadata was guessed, held for review before it is downloaded if anything
+  #[serde(default)]
+  pub doubts: Vec<Doubt>,
+}
+
+/// Metadata of a request that was guessed rather than known
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, strum::Display)]
+pub enum Doubt {
+  #[strum(to_string = "title taken from the video title")]
+  TitleFromVideo,
+  #[strum(to_string = "artist taken from the channel")]
+  ArtistFromChannel,
+  #[strum(to_string = "no album found")]
+  NoAlbum,
+}
+
+/// What to do with a download held for review
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Verdict {
+  /// download it with its metadata as it is
+  Approve,
+  /// download it with the corrected metadata
+  Edit { title: String, artists: Vec<String>, album: Option<String> },
+  /// drop it
+  Reject,
+}
+
+impl DownloadRequest {
+  /// The request to download the video, tagged with what YouTube knows of the song. What is made
+  /// up from the video rather than its music metadata is doubted
+  pub fn from_video(video: &SingleVideo) -> Self {
+    let doubts = [
+      (video.track.is_none(), Doubt::TitleFromVideo),
+      (video.artist.is_none(), Doubt::ArtistFromChannel),
+      (video.album.is_none(), Doubt::NoAlbum),
+    ];
+    DownloadRequest {
+      youtube_id: video.id.clone(),
+      title: video.track.clone().or_else(|| video.title.clone()).unwrap_or_else(|| video.id.clone()),
+      artists: video.artist.clone().or_else(|| video.channel.clone()).into_iter().collect(),
+      album: video.album.clone(),
+      genres: video.genre.clone().into_iter().collect(),
+      doubts: doubts.into_iter().filter(|(doubted, _)| *doubted).map(|(_, doubt)| doubt).collect(),
+      ..Default::default()
+    }
+  }
+
+  pub fn url(&self) -> String {
+    format!("https://www.youtube.com/watch?v={}", self.youtube_id)
+  }
+
+  /// The tags the song file is written with
+  pub fn tags(&self) -> Vec<(&'static str, String)> {
+    SongTags::from(self).pairs()
+  }
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum QueueStatus {
+  #[default]
+  Pending,
+  Downloading,
+  Finished,
+  Failed(String),
+  /// not downloaded as the song is in the library already, with the reason
+  Skipped(String),
+  /// waiting to be told what to do, as it matches the song with the given title
+  Duplicate(String),
+  /// waiting for its metadata to be reviewed, with what of it was guessed
+  Review(String),
+}
+
+/// A request in the queue
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueueItem {
+  pub id: u64,
+  pub request: DownloadRequest,
+  pub status: QueueStatus,
+  /// how far the download is while downloading, if yt-dlp knows the size
+  #[serde(default)]
+  pub progress: Option<Progress>,
+}
+
+/// How far a download is
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+  pub percent: u8,
+  /// in bytes per second, `None` until yt-dlp has measured it
+  pub speed: Option<u64>,
+}
+
+impl fmt::Display for Progress {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}%", self.percent)?;
+    match self.speed {
+      Some(speed) if speed >= 1024 * 1024 => write!(f, " at {:.1} MiB/s", speed as f64 / (1024.0 * 1024.0)),
+      Some(speed) => write!(f, " at {:.0} KiB/s", speed as f64 / 1024.0),
+      None => Ok(()),
+    }
+  }
+}
+
+#[derive(Default)]
+struct QueueInner {
+  items: Vec<QueueItem>,
+  next_id: u64,
+}
+
+/// Handle to a download queue. Cloning the handle gives access to the same queue
+#[derive(Clone)]
+pub struct DownloadQueue {
+  inner: Arc<Mutex<QueueInner>>,
+  /// wakes the worker up when songs are queued
+  queued: Arc<Notify>,
+  /// the items that stopped downloading, successfully or not
+  stopped: broadcast::Sender<QueueItem>,
+}
+
+impl Default for DownloadQueue {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl DownloadQueue {
+  pub fn new() -> Self {
+    Self { inner: Arc::default(), queued: Arc::default(), stopped: broadcast::channel(STOPPED_CAPACITY).0 }
+  }
+
+  /// Receive the items that stop downloading from now on
+  pub fn subscribe(&self) -> broadcast::Receiver<QueueItem> {
+    self.stopped.subscribe()
+  }
+
+  fn with_inner<T>(&self, f: impl FnOnce(&mut QueueInner) -> T) -> T {
+    let mut inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut inner)
+  }
+
+  pub fn enqueue(&self, requests: impl IntoIterator<Item = DownloadRequest>) {
+    self.with_inner(|inner| {
+      for request in requests {
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.items.push(QueueItem { id, request, status: QueueStatus::Pending, progress: None });
+      }
+    });
+    self.queued.notify_one();
+  }
+
+  /// A snapshot of every item in the queue
+  pub fn items(&self) -> Vec<QueueItem> {
+    self.with_inner(|inner| inner.items.clone())
+  }
+
+  /// Take the oldest pending item and mark it as downloading
+  fn start_next(&self) -> Option<QueueItem> {
+    self.with_inner(|inner| {
+      let item = inner.items.iter_mut().find(|item| item.status == QueueStatus::Pending)?;
+      item.status = QueueStatus::Downloading;
+      Some(item.clone())
+    })
+  }
+
+  fn set_status(&self, id: u64, status: QueueStatus) {
+    let stopped = self.with_inner(|inner| {
+      let item = inner.items.iter_mut().find(|item| item.id == id)?;
+      item.status = status;
+      item.progress = None;
+      matches!(item.status, QueueStatus::Finished | QueueStatus::Failed(_) | QueueStatus::Skipped(_))
+        .then(|| item.clone())
+    });
+    if let Some(item) = stopped {
+      // nobody may be listening
+      let _ = self.stopped.send(item);
+    }
+  }
+
+  fn set_progress(&self, id: u64, progress: Progress) {
+    self.with_inner(|inner| {
+      if let Some(item) = inner.items.iter_mut().find(|item| item.id == id && item.status == QueueStatus::Downloading) {
+        item.progress = Some(progress);
+      }
+    });
+  }
+
+  /// Queue the item waiting for a decision again, handling the duplicate with the policy
+  ///
+  /// # Returns
+  ///
+  /// * whether the item was waiting for a decision
+  pub fn resolve(&self, id: u64, policy: DuplicatePolicy) -> bool {
+    let resolved = self.with_inner(|inner| {
+      let Some(item) =
+        inner.items.iter_mut().find(|item| item.id == id && matches!(item.status, QueueStatus::Duplicate(_)))
+      else {
+        return false;
+      };
+      item.request.duplicates = Some(policy);
+      item.status = QueueStatus::Pending;
+      true
+    });
+    if resolved {
+      self.queued.notify_one();
+    }
+    resolved
+  }
+
+  /// Queue the item held for review again with its metadata approved or corrected, or drop it
+  ///
+  /// # Returns
+  ///
+  /// * whether the item was held for review
+  pub fn review(&self, id: u64, verdict: Verdict) -> bool {
+    let reviewed = self.with_inner(|inner| {
+      let Some(item) =
+        inner.items.iter_mut().find(|item| item.id == id && matches!(item.status, QueueStatus::Review(_)))
+      else {
+        return false;
+      };
+      item.request.doubts.clear();
+      if let Verdict::Edit { title, artists, album } = verdict.clone() {
+        item.request =
+          DownloadRequest { title, artists, album, source: MetadataSource::Manual, ..item.request.clone() };
+      }
+      if verdict != Verdict::Reject {
+        item.status = QueueStatus::Pending;
+      }
+      true
+    });
+    match (reviewed, verdict) {
+      (false, _) => {},
+      (true, Verdict::Reject) => self.set_status(id, QueueStatus::Skipped("rejected in review".to_string())),
+      (true, _) => self.queued.notify_one(),
+    }
+    reviewed
+  }
+
+  /// Download the queued songs one at a time with yt-dlp until cancelled
+  pub async fn run(self, config: Config, cancellation_token: CancellationToken) -> Result<()> {
+    let database = Database::new(config.clone()).await?;
+    self.work(config, database, YtDlp, cancellation_token).await
+  }
+
+  /// Download the queued songs one at a time with the downloader until cancelled. A download
+  /// cancelled midway is queued again
+  pub async fn work(
+    self,
+    config: Config,
+    mut database: Database,
+    downloader: impl Downloader,
+    cancellation_token: CancellationToken,
+  ) -> Result<()> {
+    loop {
+      let Some(item) = self.start_next() else {
+        tokio::select! {
+          _ = self.queued.notified() => continue,
+          _ = cancellation_token.cancelled() => return Ok(()),
+        }
+      };
+      info!("downloading {}", item.request.url());
+      let progress = |progress| self.set_progress(item.id, progress);
+      let result = tokio::select! {
+        result = handle(&config, &mut database, &downloader, &item.request, &progress) => result,
+        _ = cancellation_token.cancelled() => {
+          self.set_status(item.id, QueueStatus::Pending);
+          return Ok(());
+        },
+      };
+      self.set_status(item.id, settle(&mut database, &item.request, result));
+    }
+  }
+}
+
+/// Download the request right away rather than through a queue, handling it as a queued request
+/// would be and recording it in the download history
+///
+/// # Returns
+///
+/// * the status the request ends in, as it would in a queue
+pub async fn download_now(
+  config: &Config,
+  database: &mut Database,
+  downloader: &impl Downloader,
+  request: &DownloadRequest,
+  progress: &(dyn Fn(Progress) + Sync),
+) -> QueueStatus {
+  let result = handle(config, database, downloader, request, progress).await;
+  settle(database, request, result)
+}
+
+/// The status a handled request ends in, recording it in the download history if it was
+/// downloaded or failed to be
+fn settle(database: &mut Database, request: &DownloadRequest, result: Result<Handled>) -> QueueStatus {
+  let (status, outcome) = match result {
+    Ok(Handled::Downloaded(format)) => (QueueStatus::Finished, Some(Ok(format))),
+    Ok(Handled::Skipped(reason)) => {
+      info!("skipped {}: {reason}", request.url());
+      (QueueStatus::Skipped(reason), None)
+    },
+    Ok(Handled::Held(title)) => (QueueStatus::Duplicate(title), None),
+    Ok(Handled::Review(doubts)) => (QueueStatus::Review(doubts), None),
+    Err(e) => {
+      error!("failed to download {}: {e}", request.url());
+      (QueueStatus::Failed(e.to_string()), Some(Err(e.to_string())))
+    },
+  };
+  if let Some(outcome) = outcome {
+    if let Err(e) = database.record_download(&request.youtube_id, &request.title, outcome) {
+      error!("failed to record download: {e}");
+    }
+  }
+  status
+}
+
+/// The format selector of yt-dlp for an entry of the `formats` download setting
+fn format_selector(format: &str) -> Result<String> {
+  match format {
+    "best" => Ok("bestaudio/best".to_string()),
+    "m4a" => Ok("bestaudio[ext=m4a]".to_string()),
+    "opus" | "vorbis" | "mp3" | "flac" => Ok(format!("bestaudio[acodec={format}]")),
+    _ => Err(eyre!("unknown audio format {format}, expected one of opus, m4a, mp3, vorbis, flac or best")),
+  }
+}
+
+/// The entry of the `formats` download setting the downloaded format of the video matches
+fn obtained_format(formats: &[String], video: &SingleVideo) -> Option<String> {
+  formats
+    .iter()
+    .find(|format| {
+      match format.as_str() {
+        "best" => true,
+        "m4a" => video.ext.as_deref() == Some("m4a"),
+        codec => video.acodec.as_deref() == Some(codec),
+      }
+    })
+    .cloned()
+}
+
+/// The audio file downloaded for the video in the directory, whatever its format
+pub fn downloaded_file(directory: &Path, youtube_id: &str) -> Option<PathBuf> {
+  DOWNLOAD_EXTENSIONS
+    .iter()
+    .map(|extension| directory.join(format!("{youtube_id}.{extension}")))
+    .find(|path| path.is_file())
+}
+
+/// The file a downloaded song is stored in, whichever format it was downloaded in. Songs not
+/// downloaded yet are looked for as opus
+pub fn file_path(music_dir: &Path, youtube_id: &str) -> PathBuf {
+  downloaded_file(music_dir, youtube_id).unwrap_or_else(|| music_dir.join(format!("{youtube_id}.opus")))
+}
+
+/// The file of the song downloaded from the video, wherever the file template put it. Songs not in
+/// the library are looked for as [file_path] does
+pub fn song_file(database: &mut Database, music_dir: &Path, youtube_id: &str) -> Result<PathBuf> {
+  let relative_path = match database.get_song_by_youtube_id(youtube_id)? {
+    Some(song) => database.get_song_file(song.id)?,
+    None => None,
+  };
+  Ok(
+    relative_path
+      .map(|relative_path| music_dir.join(relative_path))
+      .unwrap_or_else(|| file_path(music_dir, youtube_id)),
+  )
+}
+
+/// Move the downloaded audio of the request to its path from the file template, numbered when
+/// another file has it already
+///
+/// # Returns
+///
+/// * the path of the file relative to the music dir
+fn place_file(music_dir: &Path, template: &str, request: &DownloadRequest, downloaded: &Path) -> Result<String> {
+  let extension = downloaded.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let name = export::render_name(template, &ExportSong::from(request), &extension);
+  if music_dir.join(&name) == downloaded {
+    return Ok(name);
+  }
+  let name = export::unique_path(music_dir, name);
+  let target = music_dir.join(&name);
+  if let Some(parent) = target.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  std::fs::rename(downloaded, &target)?;
+  Ok(name)
+}
+
+/// The arguments of yt-dlp to download the audio of a song into the directory, printing the
+/// metadata of the video as JSON. The formats are tried in order
+fn download_args(
+  request: &DownloadRequest,
+  directory: &Path,
+  comments: bool,
+  formats: &[String],
+  proxy: Option<&str>,
+) -> Result<Vec<String>> {
+  if formats.is_empty() {
+    return Err(eyre!("no audio formats to download"));
+  }
+  let selector = formats.iter().map(|format| format_selector(format)).collect::<Result<Vec<_>>>()?.join("/");
+  let mut args = vec!["-f", &selector, "--extract-audio", "-o", "%(id)s.%(ext)s", "-P"]
+    .into_iter()
+    .map(str::to_string)
+    .collect::<Vec<_>>();
+  args.push(directory.to_string_lossy().to_string());
+  // print the metadata of the video while downloading it
+  args.push("--no-simulate".to_string());
+  if comments {
+    args.extend(["--write-comments", "--extractor-args", COMMENTS_EXTRACTOR_ARGS].map(str::to_string));
+  }
+  if let Some(proxy) = proxy {
+    args.extend(["--proxy".to_string(), proxy.to_string()]);
+  }
+  args.extend(["-J".to_string(), request.url()]);
+  Ok(args)
+}
+
+/// The progress of a download from a progress line printed with [PROGRESS_TEMPLATE], the
+/// estimated size standing in for the size until it is known
+fn parse_progress(line: &str) -> Option<Progress> {
+  let mut fields = line.strip_prefix(PROGRESS_PREFIX)?.split_whitespace().map(|field| field.parse::<f64>().ok());
+  let downloaded = fields.next()??;
+  let (size, estimate) = (fields.next()?, fields.next()?);
+  let total = size.or(estimate).filter(|total| *total > 0.0)?;
+  let speed = fields.next().flatten().map(|speed| speed.max(0.0) as u64);
+  Some(Progress { percent: (downloaded / total * 100.0).clamp(0.0, 100.0) as u8, speed })
+}
+
+/// How the audio of a song is downloaded
+#[derive(Clone, Copy)]
+pub struct DownloadOptions<'a> {
+  /// also fetch the top comments of the video
+  pub comments: bool,
+  /// the formats to try in order, as in the `formats` download setting
+  pub formats: &'a [String],
+  /// where the logs of the downloads are written
+  pub log_dir: &'a Path,
+  /// the proxy to download through
+  pub proxy: Option<&'a str>,
+  /// told how far the download is as it goes
+  pub progress: Option<&'a (dyn Fn(Progress) + Sync)>,
+}
+
+impl DownloadOptions<'_> {
+  /// The parameters of a download with these options, to be kept with the file it wrote
+  pub fn params(&self, file_id: i32, downloaded: &Downloaded, trim_silence: bool) -> DownloadParams {
+    DownloadParams {
+      file_id,
+      youtube_id: downloaded.video.id.clone(),
+      format: downloaded.format.clone(),
+      formats: self.formats.join("\n"),
+      comments: self.comments,
+      proxy: self.proxy.is_some(),
+      trim_silence,
+      yt_dlp_version: downloaded.yt_dlp_version.clone(),
+      downloaded_at: Utc::now().naive_utc(),
+    }
+  }
+}
+
+/// The audio of a song written by a download
+#[derive(Debug, Clone)]
+pub struct Downloaded {
+  pub path: PathBuf,
+  pub video: SingleVideo,
+  /// the entry of the formats that was obtained
+  pub format: String,
+  /// `None` if yt-dlp did not tell
+  pub yt_dlp_version: Option<String>,
+}
+
+/// Download the audio of a song into the directory, named after its YouTube id. The first of the
+/// formats the video is available in is downloaded. The output of yt-dlp is appended to the log of
+/// the video, without the progress lines
+pub async fn download_audio(
+  request: &DownloadRequest,
+  directory: &Path,
+  options: &DownloadOptions<'_>,
+) -> Result<Downloaded> {
+  let (formats, log_dir) = (options.formats, options.log_dir);
+  std::fs::create_dir_all(directory)?;
+  let mut args = download_args(request, directory, options.comments, formats, options.proxy)?;
+  // the JSON of the video takes stdout, so the progress goes to stderr
+  args.extend(["--progress", "--newline", "--progress-template", PROGRESS_TEMPLATE].map(str::to_string));
+  let mut child = Command::new("yt-dlp")
+    .args(&args)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()?;
+  let mut stdout = child.stdout.take().ok_or_else(|| eyre!("the output of yt-dlp is not piped"))?;
+  let stderr = child.stderr.take().ok_or_else(|| eyre!("the errors of yt-dlp are not piped"))?;
+  let read_stdout = async {
+    let mut output = vec![];
+    stdout.read_to_end(&mut output).await.map(|_| output)
+  };
+  let read_stderr = async {
+    let mut lines = BufReader::new(stderr).lines();
+    let mut output = vec![];
+    while let Some(line) = lines.next_line().await? {
+      match (parse_progress(&line), options.progress) {
+        (Some(update), Some(progress)) => progress(update),
+        (Some(_), None) => {},
+        (None, _) => output.extend(line.into_bytes().into_iter().chain([b'\n'])),
+      }
+    }
+    Ok::<_, std::io::Error>(output)
+  };
+  let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+  let output = Output { status: child.wait().await?, stdout, stderr };
+  if let Err(e) = download_logs::append(log_dir, &request.youtube_id, &args, &output) {
+    warn!("failed to write the download log of {}: {e}", request.youtube_id);
+  }
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(eyre!("yt-dlp failed with {}: {}", output.status, stderr.trim()));
+  }
+  let json: serde_json::Value =
+    serde_json::from_slice(&output.stdout).map_err(|e| eyre!("{} is not a video: {e}", request.url()))?;
+  let yt_dlp_version = json.pointer("/_version/version").and_then(|version| version.as_str()).map(str::to_string);
+  let video: SingleVideo = serde_json::from_value(json).map_err(|e| eyre!("{} is not a video: {e}", request.url()))?;
+  let path = downloaded_file(directory, &request.youtube_id)
+    .ok_or_else(|| eyre!("yt-dlp did not write the audio of {} to {}", request.youtube_id, directory.display()))?;
+  let format = obtained_format(formats, &video).unwrap_or_else(|| formats[formats.len() - 1].clone());
+  if formats.first() != Some(&format) {
+    warn!("{} is not available in {}, downloaded it as {format}", request.youtube_id, formats[0]);
+  }
+  Ok(Downloaded { path, video, format, yt_dlp_version })
+}
+
+/// Fetches the audio of songs into a directory
+pub trait Downloader: Send + Sync {
+  /// Download the audio of a song into the directory, named after its YouTube id, see
+  /// [download_audio]
+  fn download_audio(
+    &self,
+    request: &DownloadRequest,
+    directory: &Path,
+    options: &DownloadOptions<'_>,
+  ) -> impl Future<Output = Result<Downloaded>> + Send;
+}
+
+/// Downloads with yt-dlp
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YtDlp;
+
+impl Downloader for YtDlp {
+  async fn download_audio(
+    &self,
+    request: &DownloadRequest,
+    directory: &Path,
+    options: &DownloadOptions<'_>,
+  ) -> Result<Downloaded> {
+    download_audio(request, directory, options).await
+  }
+}
+
+/// The comments kept along with a song, the top ones with several lines as tracklists and lyrics
+/// have
+fn useful_comments(comments: &[Comment]) -> Option<String> {
+  let comments: Vec<_> = comments
+    .iter()
+    .filter(|comment| comment.parent.as_deref().unwrap_or("root") == "root")
+    .filter_map(|comment| comment.text.as_deref())
+    .filter(|text| text.lines().filter(|line| !line.trim().is_empty()).count() >= USEFUL_COMMENT_LINES)
+    .collect();
+  (!comments.is_empty()).then(|| comments.join("\n\n"))
+}
+
+/// Download the audio of a song into the music dir and add it to the library, along with the
+/// description of the video and the parameters of the download. A song that was downloaded before
+/// is downloaded in the formats it was then
+///
+/// # Returns
+///
+/// * the id of the new song and the format obtained
+pub async fn download(
+  config: &Config,
+  database: &mut Database,
+  downloader: &impl Downloader,
+  request: &DownloadRequest,
+  progress: &(dyn Fn(Progress) + Sync),
+) -> Result<(i32, String)> {
+  let download = &config.config.download;
+  export::validate_template(&download.file_template)?;
+  let log_dir = download_logs::log_dir(config);
+  let existing = database.get_song_by_youtube_id(&request.youtube_id)?;
+  let recorded = match &existing {
+    Some(song) => database.get_song_download_params(song.id)?,
+    None => None,
+  };
+  let formats = match recorded {
+    Some(params) => params.formats.lines().map(str::to_string).collect(),
+    None => download.formats.clone(),
+  };
+  let options = DownloadOptions {
+    comments: download.capture_comments,
+    formats: &formats,
+    log_dir: &log_dir,
+    proxy: proxy(config, request)?,
+    progress: Some(progress),
+  };
+  let downloaded = downloader.download_audio(request, &config.config.music_dir, &options).await?;
+  let relative_path = place_file(&config.config.music_dir, &download.file_template, request, &downloaded.path)?;
+  if download.trim_silence.enabled {
+    // the untrimmed song is still worth keeping
+    let path = config.config.music_dir.join(&relative_path);
+    if let Err(e) = postprocess::trim_silence(&path, &download.trim_silence).await {
+      warn!("failed to trim the silence of {relative_path}: {e}");
+    }
+  }
+  // a song whose file went missing gets it back rather than being added again
+  let song_id = match existing {
+    Some(song) => {
+      let file_id = database.insert_file(NewFile { relative_path: relative_path.clone() })?;
+      database.set_song_file(song.id, file_id)?;
+      song.id
+    },
+    None => add_song(database, relative_path.clone(), request)?,
+  };
+  if let Some(file_id) = database.get_song_from_id(song_id)?.file_id {
+    database.set_download_params(&options.params(file_id, &downloaded, download.trim_silence.enabled))?;
+  }
+  // a song downloaded again is tagged with its metadata in the library rather than the request
+  if let Err(e) = tagging::tag_song(config, database, song_id).await {
+    warn!("failed to tag {relative_path}: {e}");
+  }
+  if let Err(e) = scan::record_quality(database, &config.config.music_dir, &relative_path).await {
+    warn!("failed to read the quality of {relative_path}: {e}");
+  }
+  if !download.art.store.is_empty() {
+    let song = database.get_song_from_id(song_id)?;
+    if let Err(e) = art::update_art(&reqwest::Client::new(), config, database, &song).await {
+      warn!("failed to fetch the art of {relative_path}: {e}");
+    }
+  }
+  let Downloaded { video, format, .. } = downloaded;
+  let comments = video.comments.as_deref().and_then(useful_comments);
+  if video.description.is_some() || comments.is_some() {
+    database.set_song_extra(&SongExtra { song_id, description: video.description, comments })?;
+  }
+  Ok((song_id, format))
+}
+
+/// What became of a queued request
+enum Handled {
+  /// downloaded in the given format
+  Downloaded(String),
+  /// dropped, with the reason
+  Skipped(String),
+  /// held until told what to do, as it matches the song with the given title
+  Held(String),
+  /// held until its metadata is reviewed, with what of it was guessed
+  Review(String),
+}
+
+/// The song in the library the request is a duplicate of: a song of the same video, or with the
+/// same title and an artist in common. Only songs whose file is in the music dir count, as a song
+/// whose file went missing gets it back from the download
+pub fn find_duplicate(database: &mut Database, music_dir: &Path, request: &DownloadRequest) -> Result<Option<Song>> {
+  let title = normalize(&request.title);
+  let requested: Vec<_> = request.artists.iter().map(|artist| normalize(artist)).collect();
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(normalize(&name));
+  }
+  for song in database.get_all_songs()? {
+    let same_video = song.youtube_id.as_deref() == Some(request.youtube_id.as_str());
+    let same_artist = match artists.get(&song.id) {
+      Some(names) if !requested.is_empty() => names.iter().any(|name| requested.contains(name)),
+      _ => true,
+    };
+    let same_song = normalize(&song.title) == title && same_artist;
+    if !same_video && !same_song {
+      continue;
+    }
+    if database.get_song_file(song.id)?.is_some_and(|relative_path| music_dir.join(relative_path).exists()) {
+      return Ok(Some(song));
+    }
+  }
+  Ok(None)
+}
+
+/// Download the request unless its metadata is to be reviewed first or it is a duplicate the
+/// policy says to do otherwise with
+async fn handle(
+  config: &Config,
+  database: &mut Database,
+  downloader: &impl Downloader,
+  request: &DownloadRequest,
+  progress: &(dyn Fn(Progress) + Sync),
+) -> Result<Handled> {
+  if config.config.download.review && !request.doubts.is_empty() {
+    let doubts: Vec<_> = request.doubts.iter().map(Doubt::to_string).collect();
+    return Ok(Handled::Review(doubts.join(", ")));
+  }
+  let policy = request.duplicates.unwrap_or(config.config.download.duplicates);
+  let duplicate = match policy {
+    DuplicatePolicy::KeepBoth => None,
+    _ => find_duplicate(database, &config.config.music_dir, request)?,
+  };
+  match (duplicate, policy) {
+    (Some(song), DuplicatePolicy::Skip) => Ok(Handled::Skipped(format!("{} is in the library already", song.title))),
+    (Some(song), DuplicatePolicy::Ask) => Ok(Handled::Held(song.title)),
+    // replacing the file links the song to the video of the download
+    (Some(song), DuplicatePolicy::ReplaceIfBetter)
+      if database.is_song_field_locked(song.id, SongField::YoutubeId)? =>
+    {
+      Ok(Handled::Skipped(format!("{} is in the library already, with its video locked", song.title)))
+    },
+    (Some(song), DuplicatePolicy::ReplaceIfBetter) => {
+      replace_if_better(config, database, downloader, request, &song, progress).await
+    },
+    _ => Ok(Handled::Downloaded(download(config, database, downloader, request, progress).await?.1)),
+  }
+}
+
+/// Download the request outside of the music dir and put it in place of the file of the song if
+/// its audio is better. A file of unknown quality is always replaced
+async fn replace_if_better(
+  config: &Config,
+  database: &mut Database,
+  downloader: &impl Downloader,
+  request: &DownloadRequest,
+  song: &Song,
+  progress: &(dyn Fn(Progress) + Sync),
+) -> Result<Handled> {
+  let music_dir = &config.config.music_dir;
+  let relative_path = database.get_song_file(song.id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+  let current = match database.get_song_quality(song.id)? {
+    Some(quality) => Some(quality),
+    None => scan::probe(&music_dir.join(&relative_path)).await.ok().and_then(|tags| tags.quality),
+  };
+  let directory = std::env::temp_dir().join(format!("muzik-download-{}", uuid::Uuid::new_v4()));
+  let result = async {
+    let log_dir = download_logs::log_dir(config);
+    let options = DownloadOptions {
+      formats: &config.config.download.formats,
+      log_dir: &log_dir,
+      proxy: proxy(config, request)?,
+      comments: false,
+      progress: Some(progress),
+    };
+    let downloaded = downloader.download_audio(request, &directory, &options).await?;
+    let quality = scan::probe(&downloaded.path)
+      .await
+      .map_err(|outcome| eyre!("{outcome}"))?
+      .quality
+      .ok_or_else(|| eyre!("the download of {} has no audio", request.youtube_id))?;
+    if let Some(current) = current.filter(|current| !quality.is_better_than(current)) {
+      return Ok(Handled::Skipped(format!("{} is {current} already, the download is {quality}", song.title)));
+    }
+    upgrade::swap_file(music_dir, database, song.id, &relative_path, &downloaded.path, request).await?;
+    if let Some(file_id) = song.file_id {
+      database.set_download_params(&options.params(file_id, &downloaded, false))?;
+    }
+    Ok(Handled::Downloaded(downloaded.format))
+  }
+  .await;
+  let _ = std::fs::remove_dir_all(&directory);
+  result
+}
+
+/// The proxy to download the request through, if it needs one
+fn proxy<'a>(config: &'a Config, request: &DownloadRequest) -> Result<Option<&'a str>> {
+  match request.proxy {
+    true => {
+      let proxy = config.config.download.proxy.as_deref();
+      Ok(Some(proxy.ok_or_else(|| eyre!("no proxy to download {} through", request.youtube_id))?))
+    },
+    false => Ok(None),
+  }
+}
+
+/// Add a song stored in the music dir to the library, with the metadata of the request
+///
+/// # Returns
+///
+/// * the id of the new song
+pub fn add_song(database: &mut Database, relative_path: String, request: &DownloadRequest) -> Result<i32> {
+  let file_id = database.insert_file(NewFile { relative_path })?;
+  let new_song = NewSong {
+    title: request.title.clone(),
+    youtube_id: Some(request.youtube_id.clone()),
+    file_id: Some(file_id),
+    disc_number: request.disc_number,
+    track_number: request.track_number,
+    ..Default::default()
+  };
+  let song_id = database.insert_full_song(new_song, &request.artists, request.album.as_deref(), &request.genres)?;
+  if let Some(original_id) = request.instrumental_of {
+    database.link_version(SongVersion { song_id, original_id, kind: VersionKind::Instrumental })?;
+  }
+  database.record_song_provenance(song_id, request.source)?;
+  Ok(song_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::database::tests::setup_database;
+
+  /// Writes an empty file for every request, failing the videos told to and never finishing the
+  /// slow ones
+  #[derive(Default)]
+  struct FakeDownloader {
+    unavailable: Vec<String>,
+    slow: Vec<String>,
+    /// told whenever a download starts
+    started: Arc<Notify>,
+  }
+
+  impl Downloader for FakeDownloader {
+    async fn download_audio(
+      &self,
+      request: &DownloadRequest,
+      directory: &Path,
+      options: &DownloadOptions<'_>,
+    ) -> Result<Downloaded> {
+      self.started.notify_one();
+      if let Some(progress) = options.progress {
+        progress(Progress { percent: 50, speed: Some(1024) });
+      }
+      if self.slow.contains(&request.youtube_id) {
+        std::future::pending::<()>().await;
+      }
+      if self.unavailable.contains(&request.youtube_id) {
+        return Err(eyre!("{} is unavailable", request.youtube_id));
+      }
+      let path = directory.join(format!("{}.opus", request.youtube_id));
+      std::fs::write(&path, "")?;
+      let video = SingleVideo { id: request.youtube_id.clone(), ..Default::default() };
+      Ok(Downloaded { path, video, format: "opus".to_string(), yt_dlp_version: Some("2024.01.01".to_string()) })
+    }
+  }
+
+  /// A config downloading into a new music dir, which holds the database as well
+  fn worker_config(name: &str) -> Result<(Config, String)> {
+    let mut config = Config::default();
+    config.config.music_dir = std::env::temp_dir().join(format!("muzik-{name}-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&config.config.music_dir)?;
+    let url = config.config.music_dir.join("database.db").to_string_lossy().to_string();
+    Ok((config, url))
+  }
+
+  #[test]
+  fn test_download_request_tags() {
+    let request = DownloadRequest {
+      title: "Bluerose".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string(), "Comet-chan".to_string()],
+      album: Some("Shinsei Mokuroku".to_string()),
+      disc_number: Some(2),
+      track_number: Some(4),
+      ..Default::default()
+    };
+    assert_eq!(request.tags(), vec![
+      ("title", "Bluerose".to_string()),
+      ("artist", "Hoshimachi Suisei, Comet-chan".to_string()),
+      ("album", "Shinsei Mokuroku".to_string()),
+      ("disc", "2".to_string()),
+      ("track", "4".to_string()),
+    ]);
+  }
+
+  #[test]
+  fn test_download_queue() {
+    let queue = DownloadQueue::new();
+    let request = |youtube_id: &str| DownloadRequest { youtube_id: youtube_id.to_string(), ..Default::default() };
+    queue.enqueue([request("first"), request("second")]);
+
+    let mut stopped = queue.subscribe();
+    let item = queue.start_next().expect("a pending item");
+    assert_eq!(item.request.youtube_id, "first");
+    assert!(stopped.try_recv().is_err());
+    queue.set_status(item.id, QueueStatus::Failed("unavailable".to_string()));
+    assert_eq!(stopped.try_recv().map(|item| item.request.youtube_id), Ok("first".to_string()));
+    assert_eq!(queue.start_next().map(|item| item.request.youtube_id), Some("second".to_string()));
+    assert_eq!(queue.start_next(), None);
+
+    let statuses: Vec<_> = queue.items().into_iter().map(|item| item.status).collect();
+    assert_eq!(statuses, vec![QueueStatus::Failed("unavailable".to_string()), QueueStatus::Downloading]);
+  }
+
+  #[tokio::test]
+  async fn test_download_worker() -> Result<()> {
+    let (config, url) = worker_config("worker")?;
+    let downloader = FakeDownloader { unavailable: vec!["2lAe1cqCOXo".to_string()], ..Default::default() };
+    let queue = DownloadQueue::new();
+    let mut stopped = queue.subscribe();
+    let cancellation = CancellationToken::new();
+    let database = Database::open_migrated(&url, config.clone())?;
+    let worker = tokio::spawn(queue.clone().work(config.clone(), database, downloader, cancellation.clone()));
+
+    let request = |youtube_id: &str, title: &str| {
+      DownloadRequest { youtube_id: youtube_id.to_string(), title: title.to_string(), ..Default::default() }
+    };
+    queue.enqueue([request("a51VH9BYzZA", "Stellar Stellar"), request("2lAe1cqCOXo", "Bluerose")]);
+    assert_eq!(stopped.recv().await?.status, QueueStatus::Finished);
+    assert_eq!(stopped.recv().await?.status, QueueStatus::Failed("2lAe1cqCOXo is unavailable".to_string()));
+    cancellation.cancel();
+    worker.await??;
+
+    let mut database = Database::open_migrated(&url, config.clone())?;
+    let songs = database.get_all_songs()?;
+    assert_eq!(songs.iter().map(|song| song.title.as_str()).collect::<Vec<_>>(), vec!["Stellar Stellar"]);
+    assert_eq!(database.get_song_file(songs[0].id)?, Some("a51VH9BYzZA.opus".to_string()));
+    let params = database.get_song_download_params(songs[0].id)?.ok_or_else(|| eyre!("no download params"))?;
+    assert_eq!(params.format, "opus");
+    assert_eq!(params.formats, config.config.download.formats.join("\n"));
+    assert_eq!(params.yt_dlp_version.as_deref(), Some("2024.01.01"));
+    std::fs::remove_dir_all(&config.config.music_dir)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_cancel_download_worker() -> Result<()> {
+    let (config, url) = worker_config("cancel")?;
+    let downloader = FakeDownloader { slow: vec!["a51VH9BYzZA".to_string()], ..Default::default() };
+    let started = downloader.started.clone();
+    let queue = DownloadQueue::new();
+    let cancellation = CancellationToken::new();
+    let database = Database::open_migrated(&url, config.clone())?;
+    let worker = tokio::spawn(queue.clone().work(config.clone(), database, downloader, cancellation.clone()));
+
+    queue.enqueue([DownloadRequest { youtube_id: "a51VH9BYzZA".to_string(), ..Default::default() }]);
+    started.notified().await;
+    assert_eq!(
+      (queue.items()[0].status.clone(), queue.items()[0].progress),
+      (QueueStatus::Downloading, Some(Progress { percent: 50, speed: Some(1024) }))
+    );
+    cancellation.cancel();
+    worker.await??;
+    // the download is picked up again by the next worker
+    assert_eq!((queue.items()[0].status.clone(), queue.items()[0].progress), (QueueStatus::Pending, None));
+    std::fs::remove_dir_all(&config.config.music_dir)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_resolve_duplicate() {
+    let queue = DownloadQueue::new();
+    queue.enqueue([DownloadRequest { youtube_id: "a51VH9BYzZA".to_string(), ..Default::default() }]);
+    let item = queue.start_next().expect("a pending item");
+    assert!(!queue.resolve(item.id, DuplicatePolicy::Skip));
+
+    queue.set_status(item.id, QueueStatus::Duplicate("Stellar Stellar".to_string()));
+    assert!(queue.resolve(item.id, DuplicatePolicy::ReplaceIfBetter));
+    let item = queue.start_next().expect("the resolved item");
+    assert_eq!(item.request.duplicates, Some(DuplicatePolicy::ReplaceIfBetter));
+  }
+
+  #[test]
+  fn test_review() {
+    let queue = DownloadQueue::new();
+    let request = DownloadRequest {
+      youtube_id: "a51VH9BYzZA".to_string(),
+      title: "【original】Stellar Stellar".to_string(),
+      doubts: vec![Doubt::TitleFromVideo, Doubt::NoAlbum],
+      ..Default::default()
+    };
+    queue.enqueue([request.clone(), request]);
+    let edited = queue.start_next().expect("a pending item");
+    let rejected = queue.start_next().expect("another pending item");
+    assert!(!queue.review(edited.id, Verdict::Approve));
+
+    for item in [&edited, &rejected] {
+      queue.set_status(item.id, QueueStatus::Review("title taken from the video title, no album found".to_string()));
+    }
+    let mut stopped = queue.subscribe();
+    assert!(queue.review(rejected.id, Verdict::Reject));
+    assert_eq!(stopped.try_recv().map(|item| item.status), Ok(QueueStatus::Skipped("rejected in review".to_string())));
+    assert!(queue.review(edited.id, Verdict::Edit {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+    }));
+    let item = queue.start_next().expect("the reviewed item");
+    assert_eq!(item.id, edited.id);
+    assert_eq!(
+      (item.request.title.as_str(), item.request.album.as_deref()),
+      ("Stellar Stellar", Some("Still Still Stellar"))
+    );
+    assert_eq!((item.request.source, item.request.doubts), (MetadataSource::Manual, vec![]));
+  }
+
+  #[test]
+  fn test_find_duplicate() -> Result<()> {
+    let mut database = setup_database()?;
+    let music_dir = std::env::temp_dir().join(format!("muzik-duplicates-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    let stored = DownloadRequest {
+      youtube_id: "a51VH9BYzZA".to_string(),
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      ..Default::default()
+    };
+    add_song(&mut database, "a51VH9BYzZA.opus".to_string(), &stored)?;
+
+    let request = |youtube_id: &str, title: &str, artist: &str| {
+      DownloadRequest {
+        youtube_id: youtube_id.to_string(),
+        title: title.to_string(),
+        artists: vec![artist.to_string()],
+        ..Default::default()
+      }
+    };
+    // the file is missing, so the song gets it back instead
+    assert_eq!(find_duplicate(&mut database, &music_dir, &stored)?, None);
+
+    std::fs::write(music_dir.join("a51VH9BYzZA.opus"), "")?;
+    let title = |request: DownloadRequest, database: &mut Database| -> Result<Option<String>> {
+      Ok(find_duplicate(database, &music_dir, &request)?.map(|song| song.title))
+    };
+    assert_eq!(title(stored.clone(), &mut database)?, Some("Stellar Stellar".to_string()));
+    assert_eq!(
+      title(request("2lAe1cqCOXo", "stellar stellar", "Hoshimachi Suisei"), &mut database)?,
+      Some("Stellar Stellar".to_string())
+    );
+    assert_eq!(title(request("2lAe1cqCOXo", "Stellar Stellar", "Someone Else"), &mut database)?, None);
+    assert_eq!(title(request("2lAe1cqCOXo", "Bluerose", "Hoshimachi Suisei"), &mut database)?, None);
+    std::fs::remove_dir_all(music_dir)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_parse_progress() {
+    let progress = |percent, speed| Some(Progress { percent, speed });
+    assert_eq!(parse_progress("muzik-progress 1024 4096 NA 2048.5"), progress(25, Some(2048)));
+    assert_eq!(parse_progress("muzik-progress 3000 NA 4000.5 NA"), progress(74, None));
+    assert_eq!(parse_progress("muzik-progress 5000 4096 NA NA"), progress(100, None));
+    assert_eq!(parse_progress("muzik-progress 1024 NA NA 2048"), None);
+    assert_eq!(Progress { percent: 25, speed: Some(1536 * 1024) }.to_string(), "25% at 1.5 MiB/s");
+    assert_eq!(Progress { percent: 25, speed: Some(2048) }.to_string(), "25% at 2 KiB/s");
+    assert_eq!(parse_progress("WARNING: [youtube] falling back"), None);
+  }
+
+  #[test]
+  fn test_useful_comments() {
+    let comment = |text: &str, parent: &str| {
+      Comment { text: Some(text.to_string()), parent: Some(parent.to_string()), ..Default::default() }
+    };
+    let tracklist = "0:00 Stellar Stellar\n4:51 Next Color Planet\n9:30 GHOST\n13:02 Bluerose";
+    let comments = [comment("so good", "root"), comment(tracklist, "root"), comment(tracklist, "Ugx1")];
+    assert_eq!(useful_comments(&comments), Some(tracklist.to_string()));
+    assert_eq!(useful_comments(&comments[..1]), None);
+  }
+
+  #[test]
+  fn test_download_formats() -> Result<()> {
+    let request = DownloadRequest { youtube_id: "a51VH9BYzZA".to_string(), ..Default::default() };
+    let formats = ["opus", "m4a", "best"].map(str::to_string).to_vec();
+    let args = download_args(&request, Path::new("/music"), false, &formats, None)?;
+    assert_eq!(args[..2], ["-f".to_string(), "bestaudio[acodec=opus]/bestaudio[ext=m4a]/bestaudio/best".to_string()]);
+    assert!(!args.contains(&"--proxy".to_string()));
+    let args = download_args(&request, Path::new("/music"), false, &formats, Some("socks5://127.0.0.1:1080"))?;
+    assert!(args.windows(2).any(|pair| pair == ["--proxy", "socks5://127.0.0.1:1080"]));
+    assert!(download_args(&request, Path::new("/music"), false, &["wma".to_string()], None).is_err());
+    assert!(download_args(&request, Path::new("/music"), false, &[], None).is_err());
+
+    let video = |acodec: &str, ext: &str| {
+      SingleVideo { acodec: Some(acodec.to_string()), ext: Some(ext.to_string()), ..Default::default() }
+    };
+    assert_eq!(obtained_format(&formats, &video("opus", "webm")), Some("opus".to_string()));
+    assert_eq!(obtained_format(&formats, &video("mp4a.40.2", "m4a")), Some("m4a".to_string()));
+    assert_eq!(obtained_format(&formats, &video("mp3", "mp3")), Some("best".to_string()));
+    assert_eq!(obtained_format(&formats[..2], &video("mp3", "mp3")), None);
+
+    let directory = std::env::temp_dir().join(format!("muzik-queue-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    assert_eq!(downloaded_file(&directory, "a51VH9BYzZA"), None);
+    std::fs::write(directory.join("a51VH9BYzZA.m4a"), "")?;
+    assert_eq!(downloaded_file(&directory, "a51VH9BYzZA"), Some(directory.join("a51VH9BYzZA.m4a")));
+    std::fs::remove_dir_all(directory)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_place_file() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-place-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    let request = DownloadRequest {
+      youtube_id: "a51VH9BYzZA".to_string(),
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      track_number: Some(3),
+      ..Default::default()
+    };
+    let downloaded = music_dir.join("a51VH9BYzZA.opus");
+    std::fs::write(&downloaded, "")?;
+    assert_eq!(place_file(&music_dir, "{id}", &request, &downloaded)?, "a51VH9BYzZA.opus");
+    assert!(downloaded.is_file());
+
+    let template = "{artist}/{album}/{track} {title}";
+    assert_eq!(
+      place_file(&music_dir, template, &request, &downloaded)?,
+      "Hoshimachi Suisei/Still Still Stellar/03 Stellar Stellar.opus"
+    );
+    assert!(!downloaded.exists());
+    std::fs::write(&downloaded, "")?;
+    assert_eq!(
+      place_file(&music_dir, template, &request, &downloaded)?,
+      "Hoshimachi Suisei/Still Still Stellar/03 Stellar Stellar (2).opus"
+    );
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
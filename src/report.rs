@@ -0,0 +1,232 @@
+//! Periodic reports of the library
+//!
+//! Summarizes a week or a month of activity: the songs added, the most played songs and the
+//! downloads that failed. Reports are written as markdown or JSON into the `reports` directory
+//! of the data dir, named after the period they cover.
+
+use std::path::PathBuf;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result};
+use serde::Serialize;
+
+use crate::{config::Config, database::Database};
+
+/// The number of songs listed as most played
+const MOST_PLAYED_COUNT: i64 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportPeriod {
+  /// the ISO week, starting on monday
+  #[default]
+  Weekly,
+  /// the calendar month
+  Monthly,
+}
+
+impl ReportPeriod {
+  /// The start and the end of the period containing the date. The end is exclusive
+  pub fn range(&self, date: NaiveDate) -> Result<(NaiveDateTime, NaiveDateTime)> {
+    let (start, end) = match self {
+      ReportPeriod::Weekly => {
+        let start = date - Duration::days(date.weekday().num_days_from_monday().into());
+        (start, start + Duration::days(7))
+      },
+      ReportPeriod::Monthly => {
+        let start = date.with_day(1).ok_or_else(|| eyre!("invalid date {date}"))?;
+        (start, start.checked_add_months(Months::new(1)).ok_or_else(|| eyre!("date {date} out of range"))?)
+      },
+    };
+    Ok((start.and_hms_opt(0, 0, 0).unwrap_or_default(), end.and_hms_opt(0, 0, 0).unwrap_or_default()))
+  }
+
+  /// The name of the period containing the date, such as `2024-W03` or `2024-01`
+  pub fn name(&self, date: NaiveDate) -> String {
+    match self {
+      ReportPeriod::Weekly => {
+        let week = date.iso_week();
+        format!("{}-W{:02}", week.year(), week.week())
+      },
+      ReportPeriod::Monthly => date.format("%Y-%m").to_string(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+  #[default]
+  Markdown,
+  Json,
+}
+
+impl ReportFormat {
+  fn extension(&self) -> &'static str {
+    match self {
+      ReportFormat::Markdown => "md",
+      ReportFormat::Json => "json",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReportSong {
+  pub title: String,
+  pub artists: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PlayedSong {
+  pub title: String,
+  pub artists: Vec<String>,
+  pub plays: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FailedDownload {
+  pub title: String,
+  pub youtube_id: String,
+  pub error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+  pub name: String,
+  pub from: String,
+  pub to: String,
+  pub new_songs: Vec<ReportSong>,
+  pub most_played: Vec<PlayedSong>,
+  pub failed_downloads: Vec<FailedDownload>,
+}
+
+impl Report {
+  /// Build the report of the period containing the date
+  pub fn generate(database: &mut Database, period: ReportPeriod, date: NaiveDate) -> Result<Self> {
+    let (from, to) = period.range(date)?;
+    let artist_names = |database: &mut Database, song| -> Result<Vec<String>> {
+      Ok(database.get_all_artists_for_song(song)?.into_iter().map(|artist| artist.name).collect())
+    };
+
+    let mut new_songs = vec![];
+    for song in database.get_songs_added_between(from, to)? {
+      new_songs.push(ReportSong { title: song.title.clone(), artists: artist_names(database, song)? });
+    }
+    let mut most_played = vec![];
+    for (song, plays) in database.get_most_played_between(from, to, MOST_PLAYED_COUNT)? {
+      most_played.push(PlayedSong { title: song.title.clone(), artists: artist_names(database, song)?, plays });
+    }
+    let failed_downloads = database
+      .get_failed_downloads_between(from, to)?
+      .into_iter()
+      .map(|download| {
+        FailedDownload {
+          title: download.title,
+          youtube_id: download.youtube_id,
+          error: download.error.unwrap_or_default(),
+        }
+      })
+      .collect();
+
+    Ok(Self {
+      name: period.name(date),
+      from: from.date().to_string(),
+      // the end of the range is exclusive, show the last day of the period instead
+      to: (to.date() - Duration::days(1)).to_string(),
+      new_songs,
+      most_played,
+      failed_downloads,
+    })
+  }
+
+  pub fn to_markdown(&self) -> String {
+    let song_line = |title: &str, artists: &[String]| {
+      if artists.is_empty() {
+        title.to_string()
+      } else {
+        format!("{} - {title}", artists.join(", "))
+      }
+    };
+    let mut markdown = format!("# Muzik report {}\n\n{} to {}\n", self.name, self.from, self.to);
+
+    markdown.push_str(&format!("\n## New songs ({})\n\n", self.new_songs.len()));
+    for song in &self.new_songs {
+      markdown.push_str(&format!("- {}\n", song_line(&song.title, &song.artists)));
+    }
+
+    markdown.push_str("\n## Most played\n\n");
+    for (rank, song) in self.most_played.iter().enumerate() {
+      markdown.push_str(&format!("{}. {} ({} plays)\n", rank + 1, song_line(&song.title, &song.artists), song.plays));
+    }
+
+    markdown.push_str(&format!("\n## Failed downloads ({})\n\n", self.failed_downloads.len()));
+    for download in &self.failed_downloads {
+      markdown.push_str(&format!("- {} ({}): {}\n", download.title, download.youtube_id, download.error));
+    }
+    markdown
+  }
+
+  /// Write the report into the `reports` directory of the data dir
+  ///
+  /// # Returns
+  ///
+  /// * the path of the written report
+  pub fn write(&self, config: &Config, format: ReportFormat) -> Result<PathBuf> {
+    let directory = config.paths.data_dir.join("reports");
+    std::fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("{}.{}", self.name, format.extension()));
+    let contents = match format {
+      ReportFormat::Markdown => self.to_markdown(),
+      ReportFormat::Json => serde_json::to_string_pretty(self)?,
+    };
+    std::fs::write(&path, contents)?;
+    Ok(path)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Utc;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewSong, SongArtist},
+  };
+
+  #[test]
+  fn test_report_period() -> Result<()> {
+    let date = NaiveDate::from_ymd_opt(2024, 1, 17).expect("valid date");
+    let (from, to) = ReportPeriod::Weekly.range(date)?;
+    assert_eq!((from.date().to_string(), to.date().to_string()), ("2024-01-15".to_string(), "2024-01-22".to_string()));
+    assert_eq!(ReportPeriod::Weekly.name(date), "2024-W03");
+
+    let (from, to) = ReportPeriod::Monthly.range(date)?;
+    assert_eq!((from.date().to_string(), to.date().to_string()), ("2024-01-01".to_string(), "2024-02-01".to_string()));
+    assert_eq!(ReportPeriod::Monthly.name(date), "2024-01");
+    Ok(())
+  }
+
+  #[test]
+  fn test_report_generate() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong { title: "Stellar Stellar".to_string(), ..Default::default() })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    database.record_play(song_id, Utc::now().naive_utc())?;
+    database.record_download("abcdefghijk", "Kaikai Kitan", Err("video unavailable".to_string()))?;
+
+    let report = Report::generate(&mut database, ReportPeriod::Weekly, Utc::now().date_naive())?;
+    assert_eq!(report.new_songs, vec![ReportSong {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()]
+    }]);
+    assert_eq!(report.most_played[0].plays, 1);
+
+    let markdown = report.to_markdown();
+    assert!(markdown.contains("- Hoshimachi Suisei - Stellar Stellar\n"));
+    assert!(markdown.contains("1. Hoshimachi Suisei - Stellar Stellar (1 plays)\n"));
+    assert!(markdown.contains("- Kaikai Kitan (abcdefghijk): video unavailable\n"));
+    Ok(())
+  }
+}
@@ -0,0 +1,663 @@
+//! Finding the songs in the music dir and adding them to the library
+//!
+//! Files and folders matching the ignore rules of the `scan` settings are left out, so that
+//! sample packs, ringtones or podcasts kept in the music dir do not end up in the library. As with
+//! the `.ndignore` files of Navidrome, a folder containing a `.muzikignore` file is left out along
+//! with everything in it.
+//!
+//! The tags of the remaining files are read with ffprobe. A file is a duplicate when the library
+//! has a song of its path, or a song whose title, artists and album are those of its tags, such as
+//! the same song in another format. What became of every file is collected
+//! into the results of the scan, so that the files which could not be added can be looked into and
+//! scanned again.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+  path::{Component, Path, PathBuf},
+  time::Duration,
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::{process::Command, sync::mpsc::UnboundedSender};
+
+use crate::{
+  action::Action,
+  config::{Config, ScanConfig},
+  database::{Database, BATCH_SIZE},
+  errors::ErrorCategory,
+  import::matching::normalize,
+  jobs::JobContext,
+  models::{MetadataSource, NewFile, NewSong},
+  quality::AudioQuality,
+};
+
+/// Leaves out the folder it is in
+pub const IGNORE_FILE: &str = ".muzikignore";
+
+/// A glob pattern, as a regular expression
+#[derive(Debug, Clone)]
+struct Glob {
+  regex: Regex,
+  /// matched against the whole path rather than the name of every file and folder in it
+  anchored: bool,
+}
+
+/// What to leave out of the library
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+  globs: Vec<Glob>,
+  regexes: Vec<Regex>,
+  extensions: Vec<String>,
+  min_duration: Option<Duration>,
+}
+
+impl IgnoreRules {
+  pub fn new(config: &ScanConfig) -> Result<Self> {
+    let globs = config
+      .ignore
+      .iter()
+      .map(|glob| {
+        let regex = Regex::new(&glob_to_regex(glob)).wrap_err_with(|| format!("invalid ignore pattern {glob:?}"))?;
+        Ok(Glob { regex, anchored: glob.contains('/') })
+      })
+      .collect::<Result<_>>()
+      .wrap_err(ErrorCategory::Config)?;
+    let regexes = config
+      .ignore_regex
+      .iter()
+      .map(|regex| Regex::new(regex).wrap_err_with(|| format!("invalid ignore regex {regex:?}")))
+      .collect::<Result<_>>()
+      .wrap_err(ErrorCategory::Config)?;
+    Ok(Self {
+      globs,
+      regexes,
+      extensions: config.extensions.iter().map(|extension| extension.trim_start_matches('.').to_lowercase()).collect(),
+      min_duration: config.min_duration_seconds.map(Duration::from_secs),
+    })
+  }
+
+  /// Whether the file or folder is left out
+  ///
+  /// # Arguments
+  ///
+  /// * `relative_path` - the path from the music dir
+  /// * `is_dir` - folders are not checked against the extensions
+  pub fn ignores(&self, relative_path: &Path, is_dir: bool) -> bool {
+    let path = relative_path
+      .components()
+      .filter_map(|component| {
+        match component {
+          Component::Normal(name) => Some(name.to_string_lossy()),
+          _ => None,
+        }
+      })
+      .collect::<Vec<_>>();
+    let joined = path.join("/");
+    let matches_glob = self.globs.iter().any(|glob| {
+      if glob.anchored {
+        glob.regex.is_match(&joined)
+      } else {
+        path.iter().any(|name| glob.regex.is_match(name))
+      }
+    });
+    if matches_glob || self.regexes.iter().any(|regex| regex.is_match(&joined)) {
+      return true;
+    }
+    !is_dir && !self.has_song_extension(relative_path)
+  }
+
+  fn has_song_extension(&self, path: &Path) -> bool {
+    path
+      .extension()
+      .map(|extension| extension.to_string_lossy().to_lowercase())
+      .is_some_and(|extension| self.extensions.contains(&extension))
+  }
+
+  /// Whether a song this long is left out
+  pub fn ignores_duration(&self, duration: Duration) -> bool {
+    self.min_duration.is_some_and(|min_duration| duration < min_duration)
+  }
+}
+
+/// The regular expression matching the same paths as the glob. `**` matches across folders, `*`
+/// and `?` within a name
+fn glob_to_regex(glob: &str) -> String {
+  let mut regex = String::from("^");
+  let mut chars = glob.trim_matches('/').chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '*' if chars.peek() == Some(&'*') => {
+        chars.next();
+        // `**/` also matches no folder at all
+        if chars.peek() == Some(&'/') {
+          chars.next();
+          regex.push_str("(?:.*/)?");
+        } else {
+          regex.push_str(".*");
+        }
+      },
+      '*' => regex.push_str("[^/]*"),
+      '?' => regex.push_str("[^/]"),
+      c => regex.push_str(&regex::escape(&c.to_string())),
+    }
+  }
+  regex.push('$');
+  regex
+}
+
+/// The files of the songs in the music dir that are not ignored, as paths from the music dir
+pub fn music_files(music_dir: &Path, rules: &IgnoreRules) -> Result<Vec<PathBuf>> {
+  let mut files = vec![];
+  let mut directories = vec![PathBuf::new()];
+  while let Some(directory) = directories.pop() {
+    let absolute = music_dir.join(&directory);
+    if absolute.join(IGNORE_FILE).exists() {
+      continue;
+    }
+    for entry in std::fs::read_dir(&absolute).wrap_err_with(|| format!("failed to read {}", absolute.display()))? {
+      let entry = entry?;
+      let relative_path = directory.join(entry.file_name());
+      // symbolic links are not followed, so that loops can not be walked forever
+      let file_type = entry.file_type()?;
+      if rules.ignores(&relative_path, file_type.is_dir()) {
+        continue;
+      }
+      if file_type.is_dir() {
+        directories.push(relative_path);
+      } else if file_type.is_file() {
+        files.push(relative_path);
+      }
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+/// What became of a file found by a scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanOutcome {
+  /// added to the library as a new song
+  Imported,
+  /// already in the library, by its path or by its tags
+  Duplicate,
+  /// shorter than `scan.min_duration_seconds`
+  TooShort,
+  /// ffprobe could not read the file, it may be damaged or still being written
+  UnreadableTags(String),
+  /// the file has no audio
+  UnsupportedFormat,
+  /// adding the song to the library failed
+  Failed(String),
+}
+
+impl ScanOutcome {
+  /// Whether scanning the file again may succeed
+  pub fn is_recoverable(&self) -> bool {
+    matches!(self, ScanOutcome::UnreadableTags(_) | ScanOutcome::Failed(_))
+  }
+
+  /// Whether the file was added or left out on purpose
+  pub fn is_ok(&self) -> bool {
+    matches!(self, ScanOutcome::Imported | ScanOutcome::Duplicate | ScanOutcome::TooShort)
+  }
+}
+
+impl fmt::Display for ScanOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ScanOutcome::Imported => write!(f, "imported"),
+      ScanOutcome::Duplicate => write!(f, "skipped duplicate"),
+      ScanOutcome::TooShort => write!(f, "skipped, too short"),
+      ScanOutcome::UnreadableTags(e) => write!(f, "unreadable tags: {e}"),
+      ScanOutcome::UnsupportedFormat => write!(f, "unsupported format"),
+      ScanOutcome::Failed(e) => write!(f, "failed: {e}"),
+    }
+  }
+}
+
+/// A file found by a scan and what became of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+  /// the path from the music dir
+  pub path: PathBuf,
+  pub outcome: ScanOutcome,
+}
+
+/// The number of files with every outcome, as `imported, duplicate, too short, unreadable tags,
+/// unsupported, failed`
+pub fn count_outcomes(results: &[ScanResult]) -> [usize; 6] {
+  let mut counts = [0; 6];
+  for result in results {
+    let index = match result.outcome {
+      ScanOutcome::Imported => 0,
+      ScanOutcome::Duplicate => 1,
+      ScanOutcome::TooShort => 2,
+      ScanOutcome::UnreadableTags(_) => 3,
+      ScanOutcome::UnsupportedFormat => 4,
+      ScanOutcome::Failed(_) => 5,
+    };
+    counts[index] += 1;
+  }
+  counts
+}
+
+/// Replace the results of the files scanned again with their new results
+pub fn merge_results(results: &mut [ScanResult], retried: Vec<ScanResult>) {
+  let mut retried: HashMap<_, _> = retried.into_iter().map(|result| (result.path, result.outcome)).collect();
+  for result in results.iter_mut() {
+    if let Some(outcome) = retried.remove(&result.path) {
+      result.outcome = outcome;
+    }
+  }
+}
+
+/// The tags and streams of a file as reported by ffprobe
+#[derive(Debug, Default, Deserialize)]
+struct Probe {
+  #[serde(default)]
+  streams: Vec<ProbeStream>,
+  #[serde(default)]
+  format: ProbeFormat,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeStream {
+  codec_type: Option<String>,
+  codec_name: Option<String>,
+  /// in bit/s
+  bit_rate: Option<String>,
+  #[serde(default)]
+  tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeFormat {
+  duration: Option<String>,
+  /// in bit/s, of the whole file
+  bit_rate: Option<String>,
+  #[serde(default)]
+  tags: HashMap<String, String>,
+}
+
+/// The metadata of a song file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileTags {
+  pub title: Option<String>,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  pub genre: Option<String>,
+  pub disc_number: Option<i32>,
+  pub track_number: Option<i32>,
+  pub duration: Option<Duration>,
+  pub has_audio: bool,
+  /// the codec and bitrate of the audio stream
+  pub quality: Option<AudioQuality>,
+}
+
+impl FileTags {
+  /// The fingerprint of the tags, `None` without a title and artists to tell the song by
+  pub fn fingerprint(&self) -> Option<String> {
+    let title = self.title.as_deref()?;
+    (!self.artists.is_empty()).then(|| fingerprint(title, &self.artists, self.album.as_deref()))
+  }
+
+  /// Read the tags from the JSON output of ffprobe. Opus and Vorbis files keep their tags on the
+  /// audio stream rather than the container
+  fn from_probe(json: &str) -> Result<Self> {
+    let probe: Probe = serde_json::from_str(json)?;
+    // the keys are upper case in some formats
+    let mut tags: HashMap<String, String> = HashMap::new();
+    for (key, value) in probe.streams.iter().flat_map(|stream| &stream.tags).chain(&probe.format.tags) {
+      let value = value.trim();
+      if !value.is_empty() {
+        tags.entry(key.to_lowercase()).or_insert_with(|| value.to_string());
+      }
+    }
+    let number = |key: &str| tags.get(key).and_then(|value| value.split('/').next()?.trim().parse().ok());
+    let artists = tags
+      .get("artist")
+      .map(|artists| {
+        artists.split(';').map(str::trim).filter(|artist| !artist.is_empty()).map(str::to_string).collect()
+      })
+      .unwrap_or_default();
+    let audio = probe.streams.iter().find(|stream| stream.codec_type.as_deref() == Some("audio"));
+    // the streams of some containers, such as ogg, have no bitrate of their own
+    let bitrate = audio
+      .and_then(|stream| stream.bit_rate.as_ref())
+      .or(probe.format.bit_rate.as_ref())
+      .and_then(|bitrate| bitrate.parse::<u32>().ok())
+      .map(|bitrate| (bitrate as f64 / 1000.0).round() as u32);
+    let quality = audio.and_then(|stream| stream.codec_name.clone()).map(|codec| AudioQuality { codec, bitrate });
+    Ok(Self {
+      title: tags.get("title").cloned(),
+      artists,
+      album: tags.get("album").cloned(),
+      genre: tags.get("genre").cloned(),
+      disc_number: number("disc"),
+      track_number: number("track"),
+      duration: probe.format.duration.and_then(|duration| duration.parse().ok()).map(Duration::from_secs_f64),
+      has_audio: audio.is_some(),
+      quality,
+    })
+  }
+}
+
+/// What tells copies of a song apart from other songs: its title, artists and album, whatever their
+/// case, punctuation and order
+pub fn fingerprint(title: &str, artists: &[String], album: Option<&str>) -> String {
+  let mut artists: Vec<_> = artists.iter().map(|artist| normalize(artist)).collect();
+  artists.sort();
+  format!("{}|{}|{}", normalize(title), artists.join(";"), album.map(normalize).unwrap_or_default())
+}
+
+/// The fingerprints of the songs of the library whose file is in the music dir
+fn library_fingerprints(database: &mut Database, music_dir: &Path) -> Result<HashSet<String>> {
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  let albums: HashMap<_, _> =
+    database.get_song_albums()?.into_iter().map(|(song_id, album)| (song_id, album.name)).collect();
+  Ok(
+    database
+      .get_songs_with_files()?
+      .into_iter()
+      .filter(|(_, relative_path)| music_dir.join(relative_path).exists())
+      .filter_map(|(song, _)| {
+        let artists = artists.get(&song.id).filter(|artists| !artists.is_empty())?;
+        Some(fingerprint(&song.title, artists, albums.get(&song.id).map(String::as_str)))
+      })
+      .collect(),
+  )
+}
+
+/// Read the tags of the file with ffprobe
+pub async fn probe(path: &Path) -> Result<FileTags, ScanOutcome> {
+  let output = Command::new("ffprobe")
+    .args([
+      "-v",
+      "error",
+      "-show_entries",
+      "format=duration,bit_rate:format_tags:stream=codec_type,codec_name,bit_rate:stream_tags",
+      "-of",
+      "json",
+    ])
+    .arg(path)
+    .output()
+    .await
+    .map_err(|e| ScanOutcome::Failed(format!("failed to run ffprobe: {e}")))?;
+  if !output.status.success() {
+    return Err(ScanOutcome::UnreadableTags(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+  }
+  FileTags::from_probe(&String::from_utf8_lossy(&output.stdout)).map_err(|e| ScanOutcome::UnreadableTags(e.to_string()))
+}
+
+/// Read the codec and bitrate of the file with ffprobe and record them in the library
+///
+/// # Arguments
+///
+/// * `relative_path` - the path of the file from the music dir
+pub async fn record_quality(database: &mut Database, music_dir: &Path, relative_path: &str) -> Result<()> {
+  let tags = probe(&music_dir.join(relative_path)).await;
+  save_quality(database, relative_path, tags)
+}
+
+/// Record the codec and bitrate read from the tags of the file in the library
+fn save_quality(database: &mut Database, relative_path: &str, tags: Result<FileTags, ScanOutcome>) -> Result<()> {
+  let tags = tags.map_err(|outcome| eyre!("{outcome}"))?;
+  let quality = tags.quality.ok_or_else(|| eyre!("{relative_path} has no audio"))?;
+  database.set_file_quality(relative_path, &quality)
+}
+
+/// Add the song in the file to the library
+///
+/// # Arguments
+///
+/// * `relative_path` - the path of the file from the music dir
+/// * `tags` - the tags of the file, the title defaults to the name of the file
+fn add_file(database: &mut Database, relative_path: &Path, tags: FileTags) -> Result<()> {
+  let title = tags
+    .title
+    .or_else(|| relative_path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+    .ok_or_else(|| eyre!("the file has no name"))?;
+  let file_id = database.insert_file(NewFile { relative_path: relative_path.to_string_lossy().to_string() })?;
+  if let Some(quality) = &tags.quality {
+    database.set_file_quality(&relative_path.to_string_lossy(), quality)?;
+  }
+  let new_song = NewSong {
+    title,
+    file_id: Some(file_id),
+    disc_number: tags.disc_number,
+    track_number: tags.track_number,
+    ..Default::default()
+  };
+  let genres: Vec<String> = tags.genre.into_iter().collect();
+  let song_id = database.insert_full_song(new_song, &tags.artists, tags.album.as_deref(), &genres)?;
+  database.record_song_provenance(song_id, MetadataSource::FileTag)
+}
+
+/// Decide what becomes of a file once its tags are read, adding it to the library if it is a new
+/// song
+///
+/// # Arguments
+///
+/// * `fingerprints` - the fingerprints of the songs in the library, the song added gets its own
+fn import_file(
+  database: &mut Database,
+  rules: &IgnoreRules,
+  fingerprints: &mut HashSet<String>,
+  relative_path: &Path,
+  tags: Result<FileTags, ScanOutcome>,
+) -> ScanOutcome {
+  let tags = match tags {
+    Ok(tags) => tags,
+    Err(outcome) => return outcome,
+  };
+  if !tags.has_audio {
+    return ScanOutcome::UnsupportedFormat;
+  }
+  if tags.duration.is_some_and(|duration| rules.ignores_duration(duration)) {
+    return ScanOutcome::TooShort;
+  }
+  let fingerprint = tags.fingerprint();
+  if fingerprint.as_ref().is_some_and(|fingerprint| fingerprints.contains(fingerprint)) {
+    return ScanOutcome::Duplicate;
+  }
+  // a song is added whole or not at all
+  match database.atomically(|database| add_file(database, relative_path, tags)) {
+    Ok(()) => {
+      fingerprints.extend(fingerprint);
+      ScanOutcome::Imported
+    },
+    Err(e) => ScanOutcome::Failed(e.to_string()),
+  }
+}
+
+/// Scan the music dir for songs missing from the library, to be run as a job. The files are read a
+/// chunk at a time and the songs of a chunk are added in one transaction, so an interrupted scan
+/// only keeps whole chunks. The results are sent once every file has been scanned
+///
+/// # Arguments
+///
+/// * `paths` - the files to scan again, from the music dir. The whole music dir if `None`
+pub async fn scan_music_dir(
+  config: Config,
+  paths: Option<Vec<PathBuf>>,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let music_dir = &config.config.music_dir;
+  let rules = IgnoreRules::new(&config.config.scan)?;
+  let retry = paths.is_some();
+  let files = match paths {
+    Some(paths) => paths,
+    None => music_files(music_dir, &rules)?,
+  };
+  let mut database = Database::new(config.clone()).await?;
+  let known: HashSet<PathBuf> =
+    database.get_songs_with_files()?.into_iter().map(|(_, relative_path)| PathBuf::from(relative_path)).collect();
+  let without_quality: HashSet<PathBuf> =
+    database.get_files_without_quality()?.into_iter().map(PathBuf::from).collect();
+  let mut fingerprints = library_fingerprints(&mut database, music_dir)?;
+  context.log(format!("scanning {} files", files.len()));
+
+  let total = files.len() as u64;
+  let mut results: Vec<ScanResult> = vec![];
+  for chunk in files.chunks(BATCH_SIZE) {
+    let mut probed = vec![];
+    for path in chunk {
+      context.pace().await;
+      // files added before their quality was recorded are read again for it
+      let tags = match known.contains(path) && !without_quality.contains(path) {
+        true => None,
+        false => Some(probe(&music_dir.join(path)).await),
+      };
+      probed.push((path.clone(), tags));
+      context.progress((results.len() + probed.len()) as u64, total);
+    }
+    let scanned = database.atomically(|database| {
+      let mut scanned = vec![];
+      for (path, tags) in probed {
+        let outcome = match tags {
+          None => ScanOutcome::Duplicate,
+          Some(tags) if known.contains(&path) => {
+            if let Err(e) = save_quality(database, &path.to_string_lossy(), tags) {
+              context.log(format!("{}: failed to read the quality: {e}", path.display()));
+            }
+            ScanOutcome::Duplicate
+          },
+          Some(tags) => import_file(database, &rules, &mut fingerprints, &path, tags),
+        };
+        if !outcome.is_ok() {
+          context.log(format!("{}: {outcome}", path.display()));
+        }
+        scanned.push(ScanResult { path, outcome });
+      }
+      Ok(scanned)
+    })?;
+    results.extend(scanned);
+  }
+  let [imported, ..] = count_outcomes(&results);
+  context.log(format!("imported {imported} songs"));
+  action_tx.send(match retry {
+    true => Action::ScanRetried(results),
+    false => Action::ScanResults(results),
+  })?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_ignore_rules() -> Result<()> {
+    let rules = IgnoreRules::new(&ScanConfig {
+      ignore: vec!["Podcasts".to_string(), "*.m4r".to_string(), "Samples/**/*.wav".to_string()],
+      ignore_regex: vec!["(?i)ringtone".to_string()],
+      min_duration_seconds: Some(30),
+      ..Default::default()
+    })?;
+    assert!(rules.ignores(Path::new("Podcasts"), true));
+    assert!(rules.ignores(Path::new("Talk/Podcasts/episode.mp3"), false));
+    assert!(rules.ignores(Path::new("phone/alarm.m4r"), false));
+    assert!(rules.ignores(Path::new("Samples/kick.wav"), false));
+    assert!(rules.ignores(Path::new("Samples/drums/kick.wav"), false));
+    assert!(rules.ignores(Path::new("My Ringtones/song.mp3"), false));
+    assert!(rules.ignores(Path::new("cover.jpg"), false));
+    assert!(!rules.ignores(Path::new("Samples/intro.flac"), false));
+    assert!(!rules.ignores(Path::new("YOASOBI/Idol.OPUS"), false));
+    assert!(rules.ignores_duration(Duration::from_secs(12)));
+    assert!(!rules.ignores_duration(Duration::from_secs(240)));
+    assert!(IgnoreRules::new(&ScanConfig { ignore_regex: vec!["(".to_string()], ..Default::default() }).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_music_files() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-scan-{}", uuid::Uuid::new_v4()));
+    for path in ["YOASOBI/Idol.opus", "YOASOBI/cover.jpg", "Podcasts/episode.mp3", "Packs/kick.wav", "song.mp3"] {
+      let path = music_dir.join(path);
+      std::fs::create_dir_all(path.parent().expect("a parent"))?;
+      std::fs::write(path, "")?;
+    }
+    std::fs::write(music_dir.join("Packs").join(IGNORE_FILE), "")?;
+
+    let rules = IgnoreRules::new(&ScanConfig { ignore: vec!["Podcasts".to_string()], ..Default::default() })?;
+    assert_eq!(music_files(&music_dir, &rules)?, vec![PathBuf::from("YOASOBI/Idol.opus"), PathBuf::from("song.mp3")]);
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_file_tags_from_probe() -> Result<()> {
+    let json = r#"{
+      "streams": [{
+        "codec_type": "audio",
+        "codec_name": "opus",
+        "tags": { "TITLE": "Idol", "ARTIST": "YOASOBI; Ayase", "track": "3/12" }
+      }],
+      "format": { "duration": "213.5", "bit_rate": "131520", "tags": { "album": "THE BOOK 3", "title": "ignored" } }
+    }"#;
+    assert_eq!(FileTags::from_probe(json)?, FileTags {
+      title: Some("Idol".to_string()),
+      artists: vec!["YOASOBI".to_string(), "Ayase".to_string()],
+      album: Some("THE BOOK 3".to_string()),
+      track_number: Some(3),
+      duration: Some(Duration::from_secs_f64(213.5)),
+      has_audio: true,
+      quality: Some(AudioQuality { codec: "opus".to_string(), bitrate: Some(132) }),
+      ..Default::default()
+    });
+    assert!(!FileTags::from_probe(r#"{ "streams": [{ "codec_type": "video" }], "format": {} }"#)?.has_audio);
+    Ok(())
+  }
+
+  #[test]
+  fn test_import_file() -> Result<()> {
+    let mut database = crate::database::tests::setup_database()?;
+    let rules = IgnoreRules::new(&ScanConfig { min_duration_seconds: Some(30), ..Default::default() })?;
+    let song =
+      |seconds| FileTags { duration: Some(Duration::from_secs(seconds)), has_audio: true, ..Default::default() };
+
+    let mut fingerprints = HashSet::new();
+    let mut import = |path: &str, tags| import_file(&mut database, &rules, &mut fingerprints, Path::new(path), tags);
+
+    assert_eq!(import("YOASOBI/Idol.opus", Ok(song(213))), ScanOutcome::Imported);
+    assert_eq!(import("ring.mp3", Ok(song(12))), ScanOutcome::TooShort);
+    assert_eq!(import("video.webm", Ok(FileTags::default())), ScanOutcome::UnsupportedFormat);
+    let unreadable = ScanOutcome::UnreadableTags("Invalid data found when processing input".to_string());
+    assert_eq!(import("half.flac", Err(unreadable.clone())), unreadable);
+    assert!(unreadable.is_recoverable());
+    assert!(!ScanOutcome::UnsupportedFormat.is_recoverable());
+
+    // the same song in another format is told by its tags
+    let tagged = |title: &str, artist: &str| {
+      FileTags { title: Some(title.to_string()), artists: vec![artist.to_string()], album: None, ..song(213) }
+    };
+    assert_eq!(import("Suisei/Bluerose.opus", Ok(tagged("Bluerose", "Hoshimachi Suisei"))), ScanOutcome::Imported);
+    assert_eq!(import("Suisei/Bluerose.flac", Ok(tagged("BLUEROSE", "Hoshimachi Suisei"))), ScanOutcome::Duplicate);
+    assert_eq!(import("Suisei/Bluerose (cover).opus", Ok(tagged("Bluerose", "Someone Else"))), ScanOutcome::Imported);
+    let mut titles: Vec<_> = database.get_songs_with_files()?.into_iter().map(|(song, _)| song.title).collect();
+    titles.sort();
+    assert_eq!(titles, vec!["Bluerose", "Bluerose", "Idol"]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_merge_results() {
+    let result = |path: &str, outcome| ScanResult { path: PathBuf::from(path), outcome };
+    let mut results = vec![
+      result("Idol.opus", ScanOutcome::Imported),
+      result("half.flac", ScanOutcome::UnreadableTags("truncated".to_string())),
+    ];
+    merge_results(&mut results, vec![result("half.flac", ScanOutcome::Imported)]);
+    assert_eq!(results, vec![result("Idol.opus", ScanOutcome::Imported), result("half.flac", ScanOutcome::Imported)]);
+    assert_eq!(count_outcomes(&results), [2, 0, 0, 0, 0, 0]);
+  }
+}
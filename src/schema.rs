@@ -4,6 +4,10 @@ diesel::table! {
     album (id) {
         id -> Integer,
         name -> Text,
+        year -> Nullable<Integer>,
+        label -> Nullable<Text>,
+        catalog_number -> Nullable<Text>,
+        release_type -> Nullable<Text>,
     }
 }
 
@@ -14,10 +18,51 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    attachment (id) {
+        id -> Integer,
+        song_id -> Nullable<Integer>,
+        album_id -> Nullable<Integer>,
+        kind -> Text,
+        relative_path -> Text,
+        added_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    download (id) {
+        id -> Integer,
+        youtube_id -> Text,
+        title -> Text,
+        error -> Nullable<Text>,
+        finished_at -> Timestamp,
+        format -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    download_params (file_id) {
+        file_id -> Integer,
+        youtube_id -> Text,
+        format -> Text,
+        formats -> Text,
+        comments -> Bool,
+        proxy -> Bool,
+        trim_silence -> Bool,
+        yt_dlp_version -> Nullable<Text>,
+        downloaded_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     file (id) {
         id -> Integer,
         relative_path -> Text,
+        codec -> Nullable<Text>,
+        bitrate -> Nullable<Integer>,
+        checksum -> Nullable<Text>,
+        verified_at -> Nullable<Timestamp>,
+        problem -> Nullable<Text>,
     }
 }
 
@@ -28,6 +73,88 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    link_check (song_id) {
+        song_id -> Integer,
+        checked_at -> Timestamp,
+        dead_reason -> Nullable<Text>,
+        suggestion_id -> Nullable<Text>,
+        suggestion_title -> Nullable<Text>,
+        suggestion_score -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    pinned (id) {
+        id -> Integer,
+        kind -> Text,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    play (id) {
+        id -> Integer,
+        song_id -> Integer,
+        played_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    playlist (id) {
+        id -> Integer,
+        name -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    playlist_songs (id) {
+        id -> Integer,
+        playlist_id -> Integer,
+        song_id -> Integer,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    playlist_profiles (playlist_id, profile) {
+        playlist_id -> Integer,
+        profile -> Text,
+    }
+}
+
+diesel::table! {
+    provenance (song_id, field) {
+        song_id -> Integer,
+        field -> Text,
+        source -> Text,
+        value -> Nullable<Text>,
+        previous_source -> Nullable<Text>,
+        previous_value -> Nullable<Text>,
+        recorded_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    snapshot (id) {
+        id -> Integer,
+        name -> Text,
+        taken_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    snapshot_song (snapshot_id, song_id) {
+        snapshot_id -> Integer,
+        song_id -> Integer,
+        title -> Text,
+        artists -> Text,
+        metadata_hash -> Text,
+        file_hash -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     song (id) {
         id -> Integer,
@@ -36,6 +163,34 @@ diesel::table! {
         youtube_id -> Nullable<Text>,
         thumbnail_url -> Nullable<Text>,
         file_id -> Nullable<Integer>,
+        play_count -> Integer,
+        added_at -> Nullable<Timestamp>,
+        rating -> Nullable<Integer>,
+        track_number -> Nullable<Integer>,
+        disc_number -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    song_extra (song_id) {
+        song_id -> Integer,
+        description -> Nullable<Text>,
+        comments -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    song_lock (song_id, field) {
+        song_id -> Integer,
+        field -> Text,
+    }
+}
+
+diesel::table! {
+    song_version (song_id) {
+        song_id -> Integer,
+        original_id -> Integer,
+        kind -> Text,
     }
 }
 
@@ -60,7 +215,20 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(attachment -> album (album_id));
+diesel::joinable!(attachment -> song (song_id));
+diesel::joinable!(download_params -> file (file_id));
+diesel::joinable!(link_check -> song (song_id));
+diesel::joinable!(play -> song (song_id));
+diesel::joinable!(playlist_profiles -> playlist (playlist_id));
+diesel::joinable!(playlist_songs -> playlist (playlist_id));
+diesel::joinable!(playlist_songs -> song (song_id));
+diesel::joinable!(provenance -> song (song_id));
+diesel::joinable!(snapshot_song -> snapshot (snapshot_id));
 diesel::joinable!(song -> file (file_id));
+diesel::joinable!(song_extra -> song (song_id));
+diesel::joinable!(song_lock -> song (song_id));
+diesel::joinable!(song_version -> song (song_id));
 diesel::joinable!(songs_albums -> album (album_id));
 diesel::joinable!(songs_albums -> song (song_id));
 diesel::joinable!(songs_artists -> artist (artist_id));
@@ -71,9 +239,24 @@ diesel::joinable!(songs_genres -> song (song_id));
 diesel::allow_tables_to_appear_in_same_query!(
   album,
   artist,
+  attachment,
+  download,
+  download_params,
   file,
   genre,
+  link_check,
+  pinned,
+  play,
+  playlist,
+  playlist_profiles,
+  playlist_songs,
+  provenance,
+  snapshot,
+  snapshot_song,
   song,
+  song_extra,
+  song_lock,
+  song_version,
   songs_albums,
   songs_artists,
   songs_genres,
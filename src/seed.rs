@@ -0,0 +1,225 @@
+//! Made up libraries for development
+//!
+//! `muzik dev seed` fills the database with artists, albums and songs that look real enough for
+//! UI work and screenshots, without a real library. The same seed makes the same library. Silent
+//! WAV files can be written for the songs, as long as the songs are, so that the player and the
+//! scanner have something to read. The benchmarks build their fixtures from here too.
+
+use std::{
+  collections::HashSet,
+  io::{Read, Write},
+  path::Path,
+  time::Duration,
+};
+
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+  database::{Database, BATCH_SIZE},
+  models::{NewAlbum, NewArtist, NewFile, NewGenre, NewSong, SongAlbum, SongArtist, SongGenre},
+  quality::AudioQuality,
+};
+
+const ADJECTIVES: [&str; 24] = [
+  "Midnight", "Velvet", "Neon", "Paper", "Silver", "Glass", "Golden", "Hollow", "Electric", "Quiet", "Crimson",
+  "Lunar", "Wild", "Distant", "Broken", "Summer", "Static", "Northern", "Blue", "Secret", "Falling", "Endless",
+  "Violet", "Last",
+];
+const NOUNS: [&str; 24] = [
+  "Harbor", "Echo", "Parade", "Lanterns", "Skyline", "Tide", "Garden", "Signal", "Comet", "Highway", "Rain", "Mirror",
+  "Fever", "Horizon", "Letters", "Orbit", "Orchard", "Engine", "Ghost", "Season", "Island", "River", "Machine",
+  "Hearts",
+];
+const GENRES: [&str; 10] =
+  ["Pop", "Rock", "Indie", "Electronic", "J-Pop", "Hip Hop", "Jazz", "Folk", "Ambient", "Metal"];
+/// The characters of YouTube video ids
+const VIDEO_ID_CHARACTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+/// Of the silent files, low as they only need a length
+const SAMPLE_RATE: u32 = 8000;
+
+/// How the library is made up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeedOptions {
+  pub artists: usize,
+  pub seed: u64,
+  /// write a silent file into the music dir for every song
+  pub files: bool,
+}
+
+/// What was added to the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Seeded {
+  pub artists: usize,
+  pub albums: usize,
+  pub songs: usize,
+}
+
+/// A name of two to three words not taken yet, numbered if the words are
+fn unique_name(rng: &mut StdRng, taken: &mut HashSet<String>) -> String {
+  let adjective = ADJECTIVES.choose(rng).expect("adjectives are not empty");
+  let noun = NOUNS.choose(rng).expect("nouns are not empty");
+  let name = match rng.gen_range(0..4) {
+    0 => format!("The {adjective} {noun}"),
+    1 => format!("{noun} of {adjective}"),
+    _ => format!("{adjective} {noun}"),
+  };
+  let mut unique = name.clone();
+  for number in 2.. {
+    if taken.insert(unique.clone()) {
+      break;
+    }
+    unique = format!("{name} {number}");
+  }
+  unique
+}
+
+fn video_id(rng: &mut StdRng) -> String {
+  (0..11).map(|_| *VIDEO_ID_CHARACTERS.choose(rng).expect("characters are not empty") as char).collect()
+}
+
+/// Write a mono 8-bit WAV file of silence
+fn write_silence(path: &Path, duration: Duration) -> Result<()> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let samples = (duration.as_secs_f64() * SAMPLE_RATE as f64) as u32;
+  let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+  file.write_all(b"RIFF")?;
+  file.write_all(&(36 + samples).to_le_bytes())?;
+  file.write_all(b"WAVEfmt ")?;
+  file.write_all(&16u32.to_le_bytes())?;
+  // PCM, one channel
+  file.write_all(&1u16.to_le_bytes())?;
+  file.write_all(&1u16.to_le_bytes())?;
+  file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+  // the byte rate, block align and bits per sample of one 8-bit channel
+  file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+  file.write_all(&1u16.to_le_bytes())?;
+  file.write_all(&8u16.to_le_bytes())?;
+  file.write_all(b"data")?;
+  file.write_all(&samples.to_le_bytes())?;
+  // 8-bit samples are unsigned, silence is the middle
+  std::io::copy(&mut std::io::repeat(0x80).take(samples as u64), &mut file)?;
+  file.flush()?;
+  Ok(())
+}
+
+/// Write empty song files into the music dir for walking it in benchmarks, ten to an album and
+/// five albums to an artist. Every album has a cover next to it, which a scan leaves out
+pub fn write_placeholder_files(music_dir: &Path, files: usize) -> Result<()> {
+  for index in 0..files {
+    let album = music_dir.join(format!("Artist {:03}/Album {:03}", index / 50, index / 10));
+    if index % 10 == 0 {
+      std::fs::create_dir_all(&album)?;
+      std::fs::File::create(album.join("cover.jpg"))?;
+    }
+    std::fs::File::create(album.join(format!("{:02} Song {index}.opus", index % 10 + 1)))?;
+  }
+  Ok(())
+}
+
+/// Fill the library with made up artists, each with a few albums of songs that have plays and
+/// ratings
+///
+/// # Arguments
+///
+/// * `music_dir` - where the silent files are written, if asked for
+pub fn seed(database: &mut Database, music_dir: &Path, options: SeedOptions) -> Result<Seeded> {
+  let mut rng = StdRng::seed_from_u64(options.seed);
+  let mut names = HashSet::new();
+  let mut seeded = Seeded::default();
+  let now = Utc::now().naive_utc();
+
+  // every artist is added whole, a chunk of artists at a time
+  let artists = vec![(); options.artists];
+  database.batch(
+    artists,
+    BATCH_SIZE,
+    |_, _| {},
+    |database, ()| {
+      let artist = unique_name(&mut rng, &mut names);
+      let artist_id = database.insert_artist(NewArtist { name: artist.clone() })?;
+      let genre = GENRES.choose(&mut rng).expect("genres are not empty").to_string();
+      let genre_id = database.insert_genre(NewGenre { name: genre })?;
+      seeded.artists += 1;
+
+      for _ in 0..rng.gen_range(1..=3) {
+        let album = unique_name(&mut rng, &mut names);
+        let album_id = database.insert_album(NewAlbum { name: album.clone() })?;
+        seeded.albums += 1;
+        // titles only need to differ within an album, for the paths of their files
+        let mut titles = HashSet::new();
+
+        for track_number in 1..=rng.gen_range(4..=12) {
+          let title = unique_name(&mut rng, &mut titles);
+          let duration = Duration::from_secs(rng.gen_range(120..=300));
+          let file_id = match options.files {
+            true => {
+              let relative_path = format!("{artist}/{album}/{track_number:02} {title}.wav");
+              write_silence(&music_dir.join(&relative_path), duration)?;
+              let file_id = database.insert_file(NewFile { relative_path: relative_path.clone() })?;
+              database
+                .set_file_quality(&relative_path, &AudioQuality { codec: "pcm_u8".to_string(), bitrate: Some(64) })?;
+              Some(file_id)
+            },
+            false => None,
+          };
+          let song_id = database.insert_song(NewSong {
+            title,
+            youtube_id: Some(video_id(&mut rng)),
+            file_id,
+            track_number: Some(track_number),
+            disc_number: Some(1),
+            ..Default::default()
+          })?;
+          database.insert_song_artist(SongArtist { song_id, artist_id })?;
+          database.insert_song_album(SongAlbum { song_id, album_id })?;
+          database.insert_song_genre(SongGenre { song_id, genre_id })?;
+          for _ in 0..rng.gen_range(0..20) {
+            let played_at = now - chrono::Duration::minutes(rng.gen_range(0..90 * 24 * 60));
+            database.record_play(song_id, played_at)?;
+          }
+          if rng.gen_bool(0.3) {
+            database.set_song_rating(song_id, Some(rng.gen_range(1..=5)))?;
+          }
+          seeded.songs += 1;
+        }
+      }
+      Ok(())
+    },
+  )?;
+  Ok(seeded)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::database::tests::setup_database;
+
+  #[test]
+  fn test_seed() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-seed-{}", uuid::Uuid::new_v4()));
+    let options = SeedOptions { artists: 3, seed: 7, files: true };
+    let mut database = setup_database()?;
+    let seeded = seed(&mut database, &music_dir, options)?;
+    let songs = database.get_all_songs()?;
+    assert_eq!(songs.len(), seeded.songs);
+    assert_eq!(database.get_all_artists()?.len(), 3);
+
+    let relative_path = database.get_song_file(songs[0].id)?.expect("seeded songs have files");
+    let size = std::fs::metadata(music_dir.join(&relative_path))?.len();
+    assert!((44 + 120 * SAMPLE_RATE as u64..=44 + 300 * SAMPLE_RATE as u64).contains(&size));
+
+    // the same seed makes the same library
+    let mut other = setup_database()?;
+    seed(&mut other, &music_dir, SeedOptions { files: false, ..options })?;
+    let titles = |songs: Vec<crate::models::Song>| songs.into_iter().map(|song| song.title).collect::<Vec<_>>();
+    assert_eq!(titles(other.get_all_songs()?), titles(songs));
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
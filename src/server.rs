@@ -0,0 +1,451 @@
+//! Streaming of the library over HTTP
+//!
+//! With `daemon.http_listen` set, the daemon serves the file of every song at
+//! `/songs/{id}/stream`, over TLS unless disabled. Range requests are honoured, so browsers and
+//! players can seek. With `?bitrate=KBPS`, the song is transcoded to opus on the fly instead, for
+//! clients on slow connections. A transcoded stream can not be seeked by range, but starts at
+//! `?start=SECONDS`. Lossy files are served as they are rather than transcoded below the quality
+//! floor when the `transcode` settings say so, see [`crate::quality`]. Clients authenticate with a token of the daemon, given as `?token=` or as a
+//! bearer `Authorization` header. The files attached to songs and albums are listed at
+//! `/songs/{id}/attachments` and `/albums/{id}/attachments`, and served at `/attachments/{id}`.
+//! The Subsonic API for mobile apps is served at `/rest`, see [`crate::subsonic`].
+
+use std::{
+  collections::HashMap, convert::Infallible, io::SeekFrom, net::IpAddr, ops::RangeInclusive, path::Path, sync::Arc,
+  time::Duration,
+};
+
+use bytes::Bytes;
+use color_eyre::eyre::{eyre, Result};
+use futures::{StreamExt, TryStreamExt};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, StreamBody};
+use hyper::{
+  body::{Frame, Incoming},
+  header::{ACCEPT_RANGES, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+  server::conn::http1,
+  service::service_fn,
+  HeaderMap, Method, Request, Response, StatusCode, Uri,
+};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use tokio::{
+  fs::File,
+  io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite},
+  net::TcpListener,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{io::ReaderStream, sync::CancellationToken};
+use tracing::{debug, error, warn};
+
+use crate::{
+  auth::{AccessControl, Denied, Scope},
+  config::Config,
+  database::{Database, DatabaseHandle},
+  postprocess,
+  quality::{self, TranscodeDecision},
+  subsonic,
+};
+
+pub type Body = BoxBody<Bytes, std::io::Error>;
+
+/// The bitrates songs can be transcoded to in kbit/s, the ones opus supports
+pub const BITRATES: RangeInclusive<u32> = 6..=510;
+
+/// The part of a file a client asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ByteRange {
+  Full,
+  Partial(RangeInclusive<u64>),
+  Unsatisfiable,
+}
+
+#[derive(Clone)]
+struct StreamServer {
+  config: Config,
+  /// shared by the requests of every client
+  database: DatabaseHandle,
+  access: Arc<AccessControl>,
+}
+
+/// Answer the HTTP requests of every client connecting to the listener until cancelled
+///
+/// # Arguments
+///
+/// * `database` - the library the clients are answered from
+/// * `acceptor` - serves the clients over TLS, if given
+/// * `access` - what the clients may do
+pub async fn serve(
+  config: Config,
+  database: DatabaseHandle,
+  listener: TcpListener,
+  acceptor: Option<TlsAcceptor>,
+  access: Arc<AccessControl>,
+  cancellation_token: CancellationToken,
+) -> Result<()> {
+  let server = StreamServer { config, database, access };
+  loop {
+    let (stream, address) = tokio::select! {
+      accepted = listener.accept() => accepted?,
+      _ = cancellation_token.cancelled() => return Ok(()),
+    };
+    debug!("http client connected from {address}");
+    let server = server.clone();
+    let acceptor = acceptor.clone();
+    tokio::spawn(async move {
+      let result = match acceptor {
+        Some(acceptor) => {
+          match acceptor.accept(stream).await {
+            Ok(stream) => server.serve_connection(stream, address.ip()).await,
+            Err(e) => Err(e.into()),
+          }
+        },
+        None => server.serve_connection(stream, address.ip()).await,
+      };
+      // clients disconnecting mid-stream are common, such as when seeking
+      if let Err(e) = result {
+        debug!("http client {address} failed: {e}");
+      }
+    });
+  }
+}
+
+impl StreamServer {
+  async fn serve_connection<S>(self, stream: S, address: IpAddr) -> Result<()>
+  where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+  {
+    let service = service_fn(move |request| {
+      let server = self.clone();
+      async move { Ok::<_, Infallible>(server.handle(address, request).await) }
+    });
+    http1::Builder::new().serve_connection(TokioIo::new(stream), service).await?;
+    Ok(())
+  }
+
+  async fn handle(&self, address: IpAddr, request: Request<Incoming>) -> Response<Body> {
+    let parameters = parameters(request.uri());
+    let segments: Vec<_> = request.uri().path().trim_matches('/').split('/').collect();
+    // Subsonic clients authenticate their own way
+    if let (&Method::GET | &Method::POST, ["rest", endpoint]) = (request.method(), segments.as_slice()) {
+      let headers = request.headers();
+      return subsonic::handle(&self.config, &self.database, &self.access, address, endpoint, &parameters, headers)
+        .await;
+    }
+    if let Err(denied) = self.authorize(address, request.headers(), &parameters) {
+      return denied_response(denied);
+    }
+    match (request.method(), segments.as_slice()) {
+      (&Method::GET | &Method::HEAD, ["songs", id, "stream"]) => {
+        let Ok(id) = id.parse() else {
+          return status_response(StatusCode::NOT_FOUND);
+        };
+        match self.stream(id, &parameters, request.headers()).await {
+          Ok(response) => response,
+          Err(e) => {
+            error!("failed to stream song {id}: {e}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+          },
+        }
+      },
+      (&Method::GET, [owner @ ("songs" | "albums"), id, "attachments"]) => {
+        let Ok(id) = id.parse() else {
+          return status_response(StatusCode::NOT_FOUND);
+        };
+        match self.attachments(owner, id).await {
+          Ok(response) => response,
+          Err(e) => {
+            error!("failed to list the attachments of {owner} {id}: {e}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+          },
+        }
+      },
+      (&Method::GET | &Method::HEAD, ["attachments", id]) => {
+        let Ok(id) = id.parse() else {
+          return status_response(StatusCode::NOT_FOUND);
+        };
+        match self.attachment(id, request.headers()).await {
+          Ok(response) => response,
+          Err(e) => {
+            error!("failed to send attachment {id}: {e}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+          },
+        }
+      },
+      _ => status_response(StatusCode::NOT_FOUND),
+    }
+  }
+
+  /// The files attached to the song or album, as JSON
+  async fn attachments(&self, owner: &str, id: i32) -> Result<Response<Body>> {
+    let songs = owner == "songs";
+    let attachments = self
+      .database
+      .call(move |database| {
+        match songs {
+          true => database.get_song_attachments(id),
+          false => database.get_album_attachments(id),
+        }
+      })
+      .await?;
+    let attachments: Vec<_> = attachments
+      .into_iter()
+      .map(|attachment| {
+        let name = Path::new(&attachment.relative_path).file_name().map(|name| name.to_string_lossy().to_string());
+        json!({
+          "id": attachment.id,
+          "kind": attachment.kind.to_string(),
+          "name": name,
+          "url": format!("/attachments/{}", attachment.id),
+        })
+      })
+      .collect();
+    let response = Response::builder().header(CONTENT_TYPE, "application/json");
+    Ok(response.body(Full::new(Bytes::from(json!(attachments).to_string())).map_err(|never| match never {}).boxed())?)
+  }
+
+  /// The file of the attachment
+  async fn attachment(&self, attachment_id: i32, headers: &HeaderMap) -> Result<Response<Body>> {
+    let Some(attachment) = self.database.call(move |database| database.get_attachment(attachment_id)).await? else {
+      return Ok(status_response(StatusCode::NOT_FOUND));
+    };
+    let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+    file_response(&self.config.config.music_dir.join(attachment.relative_path), range).await
+  }
+
+  fn authorize(
+    &self,
+    address: IpAddr,
+    headers: &HeaderMap,
+    parameters: &HashMap<String, String>,
+  ) -> Result<(), Denied> {
+    let bearer =
+      headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+    let client = match parameters.get("token").map(String::as_str).or(bearer) {
+      Some(token) => self.access.authenticate(token)?,
+      None => self.access.remote_client(address),
+    };
+    self.access.authorize(&client, Scope::ReadOnly)
+  }
+
+  /// The audio of the song, transcoded if a bitrate is asked for
+  async fn stream(
+    &self,
+    song_id: i32,
+    parameters: &HashMap<String, String>,
+    headers: &HeaderMap,
+  ) -> Result<Response<Body>> {
+    let Some(relative_path) = self.database.call(move |database| database.get_song_file(song_id)).await? else {
+      return Ok(status_response(StatusCode::NOT_FOUND));
+    };
+    let path = self.config.config.music_dir.join(relative_path);
+    let Some(bitrate) = parameters.get("bitrate") else {
+      let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+      return file_response(&path, range).await;
+    };
+    let bitrate = match bitrate.parse() {
+      Ok(bitrate) if BITRATES.contains(&bitrate) => bitrate,
+      _ => {
+        let message = format!("the bitrate must be between {} and {} kbit/s", BITRATES.start(), BITRATES.end());
+        return Ok(message_response(StatusCode::BAD_REQUEST, message));
+      },
+    };
+    let start = parameters.get("start").map_or(Some(Duration::ZERO), |start| {
+      start.parse().ok().and_then(|start| Duration::try_from_secs_f64(start).ok())
+    });
+    let Some(start) = start else {
+      return Ok(message_response(StatusCode::BAD_REQUEST, "the start must be a position in seconds".to_string()));
+    };
+    let config = self.config.clone();
+    let transcode = self.database.call(move |database| should_transcode(database, &config, song_id, bitrate)).await?;
+    if !transcode {
+      let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+      return file_response(&path, range).await;
+    }
+    transcoded_response(&path, bitrate, start)
+  }
+}
+
+/// Whether the song is transcoded to opus at `bitrate` kbit/s, or served as it is because the
+/// transcode would be lossy to lossy below the quality floor
+pub fn should_transcode(database: &mut Database, config: &Config, song_id: i32, bitrate: u32) -> Result<bool> {
+  let source = database.get_song_quality(song_id)?;
+  match quality::check_transcode(source.as_ref(), bitrate, &config.config.transcode) {
+    TranscodeDecision::Transcode => Ok(true),
+    TranscodeDecision::Warn(reason) => {
+      warn!("song {song_id}: {reason}");
+      Ok(true)
+    },
+    TranscodeDecision::Skip(reason) => {
+      debug!("song {song_id}: {reason}, serving the file as it is");
+      Ok(false)
+    },
+  }
+}
+
+/// The parameters in the query of the URI
+fn parameters(uri: &Uri) -> HashMap<String, String> {
+  url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes()).into_owned().collect()
+}
+
+/// The part of a file of `size` bytes asked for by a `Range` header. Headers that can not be
+/// parsed are ignored, as HTTP allows
+fn parse_range(header: &str, size: u64) -> ByteRange {
+  let Some(spec) = header.trim().strip_prefix("bytes=") else {
+    return ByteRange::Full;
+  };
+  // several ranges would need a multipart response
+  let Some((start, end)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+    return ByteRange::Full;
+  };
+  let last = size.saturating_sub(1);
+  let (start, end) = match (start.trim(), end.trim()) {
+    ("", "") => return ByteRange::Full,
+    // the last bytes of the file
+    ("", suffix) => {
+      match suffix.parse::<u64>() {
+        Ok(0) => return ByteRange::Unsatisfiable,
+        Ok(length) => (size.saturating_sub(length), last),
+        Err(_) => return ByteRange::Full,
+      }
+    },
+    (start, "") => {
+      match start.parse() {
+        Ok(start) => (start, last),
+        Err(_) => return ByteRange::Full,
+      }
+    },
+    (start, end) => {
+      match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(last)),
+        _ => return ByteRange::Full,
+      }
+    },
+  };
+  if start >= size {
+    ByteRange::Unsatisfiable
+  } else {
+    ByteRange::Partial(start..=end)
+  }
+}
+
+/// The file, or the part of it asked for by the `Range` header
+pub async fn file_response(path: &Path, range: Option<&str>) -> Result<Response<Body>> {
+  let mut file = match File::open(path).await {
+    Ok(file) => file,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(status_response(StatusCode::NOT_FOUND)),
+    Err(e) => return Err(e.into()),
+  };
+  let size = file.metadata().await?.len();
+  let response = Response::builder().header(CONTENT_TYPE, content_type(path)).header(ACCEPT_RANGES, "bytes");
+  let (response, start, length) = match range.map_or(ByteRange::Full, |range| parse_range(range, size)) {
+    ByteRange::Full => (response.status(StatusCode::OK), 0, size),
+    ByteRange::Partial(range) => {
+      let content_range = format!("bytes {}-{}/{size}", range.start(), range.end());
+      let response = response.status(StatusCode::PARTIAL_CONTENT).header(CONTENT_RANGE, content_range);
+      (response, *range.start(), range.end() - range.start() + 1)
+    },
+    ByteRange::Unsatisfiable => {
+      let response =
+        response.status(StatusCode::RANGE_NOT_SATISFIABLE).header(CONTENT_RANGE, format!("bytes */{size}"));
+      return Ok(response.body(empty())?);
+    },
+  };
+  file.seek(SeekFrom::Start(start)).await?;
+  let body = StreamBody::new(ReaderStream::new(file.take(length)).map_ok(Frame::data));
+  Ok(response.header(CONTENT_LENGTH, length).body(BodyExt::boxed(body))?)
+}
+
+/// The audio of the file transcoded to opus as it is read
+pub fn transcoded_response(path: &Path, bitrate: u32, start: Duration) -> Result<Response<Body>> {
+  if !path.exists() {
+    return Ok(status_response(StatusCode::NOT_FOUND));
+  }
+  let mut ffmpeg = postprocess::transcode(path, bitrate, start)?;
+  let output = ffmpeg.stdout.take().ok_or_else(|| eyre!("ffmpeg has no output"))?;
+  // the body owns ffmpeg, so that it is killed once the client stops listening
+  let chunks = ReaderStream::new(output).map(move |chunk| {
+    let _ffmpeg = &ffmpeg;
+    chunk.map(Frame::data)
+  });
+  let response = Response::builder().header(CONTENT_TYPE, "audio/ogg").header(ACCEPT_RANGES, "none");
+  Ok(response.body(BodyExt::boxed(StreamBody::new(chunks)))?)
+}
+
+pub fn content_type(path: &Path) -> &'static str {
+  match path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase).as_deref() {
+    Some("opus" | "ogg") => "audio/ogg",
+    Some("mp3") => "audio/mpeg",
+    Some("m4a" | "mp4") => "audio/mp4",
+    Some("flac") => "audio/flac",
+    Some("webm") => "audio/webm",
+    Some("wav") => "audio/wav",
+    Some("pdf") => "application/pdf",
+    Some("lrc" | "txt") => "text/plain; charset=utf-8",
+    Some("jpg" | "jpeg") => "image/jpeg",
+    Some("png") => "image/png",
+    Some("webp") => "image/webp",
+    _ => "application/octet-stream",
+  }
+}
+
+pub fn empty() -> Body {
+  Empty::new().map_err(|never| match never {}).boxed()
+}
+
+pub fn message_response(status: StatusCode, message: String) -> Response<Body> {
+  let mut response = Response::new(Full::new(Bytes::from(message)).map_err(|never| match never {}).boxed());
+  *response.status_mut() = status;
+  response
+}
+
+pub fn status_response(status: StatusCode) -> Response<Body> {
+  message_response(status, status.canonical_reason().unwrap_or_default().to_string())
+}
+
+fn denied_response(denied: Denied) -> Response<Body> {
+  let status = match denied {
+    Denied::UnknownToken | Denied::Unauthenticated => StatusCode::UNAUTHORIZED,
+    Denied::NoTokens | Denied::Scope(_) => StatusCode::FORBIDDEN,
+    Denied::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+  };
+  message_response(status, denied.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_range() {
+    assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0..=99));
+    assert_eq!(parse_range("bytes=900-", 1000), ByteRange::Partial(900..=999));
+    assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Partial(900..=999));
+    assert_eq!(parse_range("bytes=500-5000", 1000), ByteRange::Partial(500..=999));
+    assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-1,5-9", 1000), ByteRange::Full);
+    assert_eq!(parse_range("bytes=9-5", 1000), ByteRange::Full);
+    assert_eq!(parse_range("items=0-9", 1000), ByteRange::Full);
+  }
+
+  #[tokio::test]
+  async fn test_file_response() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-{}.opus", uuid::Uuid::new_v4()));
+    std::fs::write(&path, b"0123456789")?;
+
+    let response = file_response(&path, Some("bytes=2-5")).await?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-5/10");
+    assert_eq!(response.headers()[CONTENT_TYPE], "audio/ogg");
+    assert_eq!(response.into_body().collect().await?.to_bytes(), Bytes::from_static(b"2345"));
+
+    let response = file_response(&path, None).await?;
+    assert_eq!(response.headers()[CONTENT_LENGTH], "10");
+    assert_eq!(response.into_body().collect().await?.to_bytes(), Bytes::from_static(b"0123456789"));
+    assert_eq!(file_response(&path, Some("bytes=10-")).await?.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    std::fs::remove_file(&path)?;
+    assert_eq!(file_response(&path, None).await?.status(), StatusCode::NOT_FOUND);
+    Ok(())
+  }
+}
@@ -0,0 +1,300 @@
+//! Library snapshots
+//!
+//! A snapshot records every song of the library at a point in time, with a hash of its metadata
+//! and one of its file. Two snapshots, or a snapshot and the library as it is now, are compared to
+//! list the songs added, removed and changed in between, such as before and after a large import
+//! or a bulk edit. The file is hashed from its path, size and modification time rather than its
+//! content, so that a snapshot of a large library is taken in seconds.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::Path,
+  time::UNIX_EPOCH,
+};
+
+use chrono::Local;
+use color_eyre::eyre::{eyre, Result};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+  action::Action,
+  config::Config,
+  database::Database,
+  jobs::JobContext,
+  models::{SnapshotSong, Song},
+};
+
+/// A song found in both snapshots whose metadata or file changed in between
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedSong {
+  pub before: SnapshotSong,
+  pub after: SnapshotSong,
+}
+
+impl ChangedSong {
+  /// What changed about the song, for display
+  pub fn what(&self) -> &'static str {
+    match (self.before.metadata_hash != self.after.metadata_hash, self.before.file_hash != self.after.file_hash) {
+      (true, true) => "metadata and file",
+      (true, false) => "metadata",
+      (false, _) => "file",
+    }
+  }
+}
+
+/// The songs added, removed and changed from one snapshot to another, each ordered by title
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+  /// the name of what was compared, such as `before import → now`
+  pub name: String,
+  pub added: Vec<SnapshotSong>,
+  pub removed: Vec<SnapshotSong>,
+  pub changed: Vec<ChangedSong>,
+}
+
+impl SnapshotDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+
+  pub fn summary(&self) -> String {
+    format!("{} added, {} removed, {} changed", self.added.len(), self.removed.len(), self.changed.len())
+  }
+}
+
+/// The SHA-256 hash as hex of the fields, each ended so that moving text across them changes it
+fn hash<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+  let mut hasher = Sha256::new();
+  for field in fields {
+    hasher.update(field.as_bytes());
+    hasher.update([0]);
+  }
+  hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn metadata_hash(song: &Song, artists: &[String], album: Option<&str>, genres: &[String]) -> String {
+  let (track_number, disc_number) = (song.track_number.map(|n| n.to_string()), song.disc_number.map(|n| n.to_string()));
+  hash([
+    song.title.as_str(),
+    song.youtube_id.as_deref().unwrap_or_default(),
+    &artists.join("\n"),
+    album.unwrap_or_default(),
+    &genres.join("\n"),
+    track_number.as_deref().unwrap_or_default(),
+    disc_number.as_deref().unwrap_or_default(),
+  ])
+}
+
+/// The hash of the file in the music dir from its path, size and modification time, `None` if it
+/// is gone
+fn file_hash(music_dir: &Path, relative_path: &str) -> Option<String> {
+  let metadata = std::fs::metadata(music_dir.join(relative_path)).ok().filter(|metadata| metadata.is_file())?;
+  let modified = metadata.modified().ok().and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+  let modified = modified.map(|modified| modified.as_nanos().to_string()).unwrap_or_default();
+  Some(hash([relative_path, &metadata.len().to_string(), &modified]))
+}
+
+/// Every song of the library as it is now, ordered by song id, to be recorded as a snapshot
+pub fn capture(database: &mut Database, music_dir: &Path) -> Result<Vec<SnapshotSong>> {
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  let mut genres: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_genre_names()? {
+    genres.entry(song_id).or_default().push(name);
+  }
+  let albums: HashMap<i32, String> =
+    database.get_song_albums()?.into_iter().map(|(song_id, album)| (song_id, album.name)).collect();
+  let files: HashMap<i32, String> =
+    database.get_songs_with_files()?.into_iter().map(|(song, relative_path)| (song.id, relative_path)).collect();
+
+  let mut songs = database.get_all_songs()?;
+  songs.sort_by_key(|song| song.id);
+  Ok(
+    songs
+      .into_iter()
+      .map(|song| {
+        // the order of the links is not part of the metadata
+        let mut song_artists = artists.remove(&song.id).unwrap_or_default();
+        song_artists.sort();
+        let mut song_genres = genres.remove(&song.id).unwrap_or_default();
+        song_genres.sort();
+        let metadata_hash = metadata_hash(&song, &song_artists, albums.get(&song.id).map(String::as_str), &song_genres);
+        SnapshotSong {
+          snapshot_id: 0,
+          song_id: song.id,
+          file_hash: files.get(&song.id).and_then(|relative_path| file_hash(music_dir, relative_path)),
+          title: song.title,
+          artists: song_artists.join(", "),
+          metadata_hash,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// The songs added, removed and changed from `before` to `after`. Songs are told apart by their id
+pub fn diff(name: String, before: Vec<SnapshotSong>, after: Vec<SnapshotSong>) -> SnapshotDiff {
+  let mut before: BTreeMap<i32, SnapshotSong> = before.into_iter().map(|song| (song.song_id, song)).collect();
+  let mut diff = SnapshotDiff { name, ..Default::default() };
+  for song in after {
+    match before.remove(&song.song_id) {
+      None => diff.added.push(song),
+      Some(old) if old.metadata_hash != song.metadata_hash || old.file_hash != song.file_hash => {
+        diff.changed.push(ChangedSong { before: old, after: song });
+      },
+      Some(_) => {},
+    }
+  }
+  diff.removed = before.into_values().collect();
+  diff.added.sort_by(|a, b| a.title.cmp(&b.title));
+  diff.removed.sort_by(|a, b| a.title.cmp(&b.title));
+  diff.changed.sort_by(|a, b| a.after.title.cmp(&b.after.title));
+  diff
+}
+
+/// Compare the snapshot `from` with the snapshot `to`, or with the library as it is now when `to`
+/// is `None`
+pub fn compare(database: &mut Database, music_dir: &Path, from: i32, to: Option<i32>) -> Result<SnapshotDiff> {
+  let snapshots = database.get_snapshots()?;
+  let name = |id: i32| {
+    let snapshot = snapshots.iter().find(|snapshot| snapshot.id == id);
+    snapshot.map(|snapshot| snapshot.name.clone()).ok_or_else(|| eyre!("there is no snapshot {id}"))
+  };
+  let name = format!("{} → {}", name(from)?, to.map(name).transpose()?.unwrap_or_else(|| "now".to_string()));
+  let before = database.get_snapshot_songs(from)?;
+  let after = match to {
+    Some(to) => database.get_snapshot_songs(to)?,
+    None => capture(database, music_dir)?,
+  };
+  Ok(diff(name, before, after))
+}
+
+/// Record a snapshot of the library, named after the time it is taken when no name is given
+///
+/// # Returns
+///
+/// * the name of the snapshot and how many songs it holds
+pub fn take(database: &mut Database, music_dir: &Path, name: &str) -> Result<(String, usize)> {
+  let name = match name.trim() {
+    "" => Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    name => name.to_string(),
+  };
+  let songs = capture(database, music_dir)?;
+  let count = songs.len();
+  let snapshot = database.insert_snapshot(&name, songs)?;
+  Ok((snapshot.name, count))
+}
+
+/// Take a snapshot of the library and send the snapshots to be shown, to be run as a job
+pub async fn take_snapshot(
+  config: Config,
+  name: String,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let (name, count) = take(&mut database, &music_dir, &name)?;
+  context.log(format!("took the snapshot {name} of {count} songs"));
+  action_tx.send(Action::SnapshotsUpdate(database.get_snapshots()?))?;
+  Ok(())
+}
+
+/// Compare two snapshots, or a snapshot with the library now, and send the changes to be shown, to
+/// be run as a job
+pub async fn compare_snapshots(
+  config: Config,
+  from: i32,
+  to: Option<i32>,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let diff = compare(&mut database, &music_dir, from, to)?;
+  context.log(format!("{}: {}", diff.name, diff.summary()));
+  action_tx.send(Action::SnapshotDiffUpdate(diff))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewFile, NewSong},
+  };
+
+  #[test]
+  fn test_diff() {
+    let song = |song_id, title: &str, metadata_hash: &str, file_hash: Option<&str>| {
+      SnapshotSong {
+        song_id,
+        title: title.to_string(),
+        metadata_hash: metadata_hash.to_string(),
+        file_hash: file_hash.map(str::to_string),
+        ..Default::default()
+      }
+    };
+    let before = vec![
+      song(1, "Stellar Stellar", "a", Some("f")),
+      song(2, "Ghost", "b", Some("g")),
+      song(3, "Bluerose", "c", None),
+      song(4, "Comet", "d", None),
+    ];
+    let after = vec![
+      song(1, "Stellar Stellar", "a", Some("f")),
+      song(2, "GHOST", "e", Some("h")),
+      song(4, "Comet", "d", Some("i")),
+      song(6, "Template", "j", None),
+      song(5, "Michizure", "k", None),
+    ];
+    let diff = diff("before → after".to_string(), before, after);
+    let titles = |songs: &[SnapshotSong]| songs.iter().map(|song| song.title.clone()).collect::<Vec<_>>();
+    assert_eq!(titles(&diff.added), vec!["Michizure", "Template"]);
+    assert_eq!(titles(&diff.removed), vec!["Bluerose"]);
+    let changed: Vec<_> = diff.changed.iter().map(|change| (change.after.title.as_str(), change.what())).collect();
+    assert_eq!(changed, vec![("Comet", "file"), ("GHOST", "metadata and file")]);
+    assert_eq!(diff.summary(), "2 added, 1 removed, 2 changed");
+  }
+
+  #[test]
+  fn test_capture() -> Result<()> {
+    let mut database = setup_database()?;
+    let music_dir = std::env::temp_dir().join(format!("muzik-snapshots-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    std::fs::write(music_dir.join("present.opus"), "")?;
+    let present = database.insert_file(NewFile { relative_path: "present.opus".to_string() })?;
+    let missing = database.insert_file(NewFile { relative_path: "missing.opus".to_string() })?;
+    let stellar = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      file_id: Some(present),
+      ..Default::default()
+    })?;
+    database.insert_song(NewSong { title: "Ghost".to_string(), file_id: Some(missing), ..Default::default() })?;
+    database.set_song_artists(stellar, &["Hoshimachi Suisei".to_string()])?;
+
+    let (name, count) = take(&mut database, &music_dir, "before import")?;
+    assert_eq!((name.as_str(), count), ("before import", 2));
+    let snapshot = database.get_snapshots()?.remove(0);
+    let songs = database.get_snapshot_songs(snapshot.id)?;
+    let captured: Vec<_> = songs.iter().map(|song| (song.artists.as_str(), song.file_hash.is_some())).collect();
+    assert_eq!(captured, vec![("Hoshimachi Suisei", true), ("", false)]);
+    assert!(compare(&mut database, &music_dir, snapshot.id, None)?.is_empty());
+
+    database.set_song_genres(stellar, &["J-Pop".to_string()])?;
+    database.insert_song(NewSong { title: "Bluerose".to_string(), ..Default::default() })?;
+    let diff = compare(&mut database, &music_dir, snapshot.id, None)?;
+    assert_eq!(diff.name, "before import → now");
+    assert_eq!(diff.summary(), "1 added, 0 removed, 1 changed");
+    assert_eq!(diff.changed[0].what(), "metadata");
+    assert!(compare(&mut database, &music_dir, snapshot.id + 1, None).is_err());
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,341 @@
+//! Editing the metadata of songs by hand
+//!
+//! The song editor of the Manager edits the title, artists, album, genres and video of a song. The
+//! fields that were changed are recorded as edited by hand, and the tags of the file can be written
+//! again once the edit is saved. The album, artists and genres can be filled in from the release
+//! of the song, looked up on a provider picked in the editor. A song, or every selected song, can
+//! also be edited as TOML in the editor of `$VISUAL` or `$EDITOR`, and the file is checked before
+//! any song is saved.
+
+use std::collections::HashSet;
+
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  database::Database,
+  enrichment::{ReleaseMetadata, ReleaseQuery},
+  models::{MetadataSource, SongField},
+};
+
+/// The fields of a song that can be edited, in the order they are shown
+pub const EDITED_FIELDS: [SongField; 5] =
+  [SongField::Title, SongField::Artists, SongField::Album, SongField::Genres, SongField::YoutubeId];
+
+/// The metadata of a song being edited
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongEdit {
+  pub song_id: i32,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  pub genres: Vec<String>,
+  pub youtube_id: Option<String>,
+  /// write the metadata into the tags of the file once saved
+  pub write_tags: bool,
+}
+
+impl SongEdit {
+  /// The value of the field as typed in the editor, with artists and genres separated by commas
+  pub fn value(&self, field: SongField) -> String {
+    match field {
+      SongField::Title => self.title.clone(),
+      SongField::Artists => self.artists.join(", "),
+      SongField::Album => self.album.clone().unwrap_or_default(),
+      SongField::Genres => self.genres.join(", "),
+      SongField::YoutubeId => self.youtube_id.clone().unwrap_or_default(),
+      _ => String::new(),
+    }
+  }
+
+  /// Set the field from the text typed in the editor. An empty title is left as it was
+  pub fn set_value(&mut self, field: SongField, text: &str) {
+    let text = text.trim();
+    let names = || text.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect();
+    let optional = || Some(text.to_string()).filter(|text| !text.is_empty());
+    match field {
+      SongField::Title if !text.is_empty() => self.title = text.to_string(),
+      SongField::Artists => self.artists = names(),
+      SongField::Album => self.album = optional(),
+      SongField::Genres => self.genres = names(),
+      SongField::YoutubeId => self.youtube_id = optional(),
+      _ => {},
+    }
+  }
+
+  /// The release of the song to look up, its album or else the song itself
+  pub fn release_query(&self) -> ReleaseQuery {
+    ReleaseQuery { title: self.album.clone().unwrap_or(self.title.clone()), artist: self.artists.first().cloned() }
+  }
+
+  /// Fill in the album, artists and genres from the release found, keeping those it has none of
+  pub fn apply_release(&mut self, release: &ReleaseMetadata) {
+    if !release.title.is_empty() {
+      self.album = Some(release.title.clone());
+    }
+    if !release.artists.is_empty() {
+      self.artists = release.artists.clone();
+    }
+    let genres = release.genre_names();
+    if !genres.is_empty() {
+      self.genres = genres;
+    }
+  }
+}
+
+/// Tells how the file opened in the editor is edited
+const EDIT_FILE_HEADER: &str = "# Edit the metadata of the songs, then save and quit the editor. A song removed from the \
+                                file is left as it is.\n# Artists and genres are lists, such as [\"Hoshimachi Suisei\", \
+                                \"TAKU INOUE\"].\n\n";
+
+/// A song as written in the file opened in the editor
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditFileSong {
+  id: i32,
+  title: String,
+  #[serde(default)]
+  artists: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  album: Option<String>,
+  #[serde(default)]
+  genres: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  youtube_id: Option<String>,
+}
+
+/// The file opened in the editor
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditFile {
+  /// write the metadata into the tags of the files once saved
+  #[serde(default)]
+  write_tags: bool,
+  #[serde(default)]
+  song: Vec<EditFileSong>,
+}
+
+/// The command to edit metadata with: the one of the config, else `$VISUAL`, `$EDITOR` or vi
+pub fn editor(configured: Option<&str>) -> String {
+  configured
+    .map(str::to_string)
+    .or_else(|| std::env::var("VISUAL").ok())
+    .or_else(|| std::env::var("EDITOR").ok())
+    .filter(|editor| !editor.trim().is_empty())
+    .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Write the metadata of the songs as the TOML file opened in the editor
+pub fn to_toml(edits: &[SongEdit]) -> Result<String> {
+  let file = EditFile {
+    write_tags: false,
+    song: edits
+      .iter()
+      .map(|edit| {
+        EditFileSong {
+          id: edit.song_id,
+          title: edit.title.clone(),
+          artists: edit.artists.clone(),
+          album: edit.album.clone(),
+          genres: edit.genres.clone(),
+          youtube_id: edit.youtube_id.clone(),
+        }
+      })
+      .collect(),
+  };
+  Ok(format!("{EDIT_FILE_HEADER}{}", toml::to_string(&file)?))
+}
+
+/// Read the songs edited in the file opened in the editor, refusing the whole file if a song in it
+/// is not one of those opened or is left without a title
+///
+/// # Returns
+///
+/// * the songs that were changed
+pub fn from_toml(text: &str, opened: &[SongEdit]) -> Result<Vec<SongEdit>> {
+  let file: EditFile = toml::from_str(text).map_err(|e| eyre!("the file is not valid: {}", e.message()))?;
+  let names = |names: Vec<String>| -> Vec<String> {
+    names.iter().map(|name| name.trim()).filter(|name| !name.is_empty()).map(str::to_string).collect()
+  };
+  let optional = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+  let mut seen = HashSet::new();
+  let mut changed = vec![];
+  for song in file.song {
+    let original = opened
+      .iter()
+      .find(|edit| edit.song_id == song.id)
+      .ok_or_else(|| eyre!("song {} was not opened for editing", song.id))?;
+    if !seen.insert(song.id) {
+      return Err(eyre!("song {} is in the file twice", song.id));
+    }
+    let title = song.title.trim().to_string();
+    if title.is_empty() {
+      return Err(eyre!("song {} needs a title", song.id));
+    }
+    let edit = SongEdit {
+      song_id: song.id,
+      title,
+      artists: names(song.artists),
+      album: optional(song.album),
+      genres: names(song.genres),
+      youtube_id: optional(song.youtube_id),
+      write_tags: file.write_tags,
+    };
+    let unchanged = SongEdit { write_tags: file.write_tags, ..original.clone() };
+    if edit != unchanged {
+      changed.push(edit);
+    }
+  }
+  Ok(changed)
+}
+
+/// Load the metadata of the song to edit
+pub fn load_song(database: &mut Database, song_id: i32) -> Result<SongEdit> {
+  let mut edit = SongEdit { song_id, ..Default::default() };
+  let lines =
+    |value: Option<String>| value.map(|value| value.lines().map(str::to_string).collect()).unwrap_or_default();
+  for (field, value) in database.get_song_field_values(song_id)? {
+    match field {
+      SongField::Title => edit.title = value.unwrap_or_default(),
+      SongField::Artists => edit.artists = lines(value),
+      SongField::Album => edit.album = value,
+      SongField::Genres => edit.genres = lines(value),
+      SongField::YoutubeId => edit.youtube_id = value,
+      _ => {},
+    }
+  }
+  Ok(edit)
+}
+
+/// Save the edited metadata of the song, recording the changed fields as edited by hand
+pub fn save_song(database: &mut Database, edit: &SongEdit) -> Result<()> {
+  let before = database.get_song_field_values(edit.song_id)?;
+  database.update_song(edit.song_id, &edit.title, edit.youtube_id.as_deref())?;
+  database.set_song_artists(edit.song_id, &edit.artists)?;
+  database.set_song_album(edit.song_id, edit.album.as_deref())?;
+  database.set_song_genres(edit.song_id, &edit.genres)?;
+  let after = database.get_song_field_values(edit.song_id)?;
+  for ((field, before), (_, after)) in before.into_iter().zip(after) {
+    if before != after {
+      database.record_provenance(edit.song_id, field, MetadataSource::Manual, after)?;
+    }
+  }
+  Ok(())
+}
+
+/// Save the edited metadata of the songs, all of them or none
+pub fn save_songs(database: &mut Database, edits: &[SongEdit]) -> Result<()> {
+  database.atomically(|database| edits.iter().try_for_each(|edit| save_song(database, edit)))
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewArtist, NewSong, SongArtist},
+  };
+
+  #[test]
+  fn test_edit_file() -> Result<()> {
+    let stellar = SongEdit {
+      song_id: 1,
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    };
+    let ghost = SongEdit { song_id: 2, title: "Ghost".to_string(), ..Default::default() };
+    let opened = vec![stellar.clone(), ghost.clone()];
+    let text = to_toml(&opened)?;
+    assert!(text.starts_with("# Edit the metadata"));
+    assert_eq!(from_toml(&text, &opened)?, vec![]);
+
+    let edited = text.replace("write_tags = false", "write_tags = true").replace(
+      "artists = [\"Hoshimachi Suisei\"]",
+      "artists = [\"Hoshimachi Suisei\", \" TAKU INOUE \", \"\"]\nalbum = \"Still Still Stellar\"",
+    );
+    assert_eq!(from_toml(&edited, &opened)?, vec![SongEdit {
+      artists: vec!["Hoshimachi Suisei".to_string(), "TAKU INOUE".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      write_tags: true,
+      ..stellar
+    }]);
+
+    // a song left out of the file is left as it is
+    assert_eq!(from_toml("[[song]]\nid = 2\ntitle = \"Ghost\"\nalbum = \" \"", &opened)?, vec![]);
+    let error = |text: &str| from_toml(text, &opened).map(|_| ()).unwrap_err().to_string();
+    assert_eq!(error("[[song]]\nid = 3\ntitle = \"Bluerose\""), "song 3 was not opened for editing");
+    assert_eq!(error("[[song]]\nid = 2\ntitle = \" \""), "song 2 needs a title");
+    assert_eq!(
+      error("[[song]]\nid = 2\ntitle = \"Ghost\"\n[[song]]\nid = 2\ntitle = \"Ghost\""),
+      "song 2 is in the file twice"
+    );
+    assert!(error("[[song]]\nid = 2\ntitel = \"Ghost\"").starts_with("the file is not valid"));
+    Ok(())
+  }
+
+  #[test]
+  fn test_apply_release() {
+    let mut edit = SongEdit {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      genres: vec!["Pop".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(edit.release_query(), ReleaseQuery {
+      title: "Stellar Stellar".to_string(),
+      artist: Some("Hoshimachi Suisei".to_string())
+    });
+    edit.apply_release(&ReleaseMetadata {
+      title: "Still Still Stellar".to_string(),
+      genres: vec!["Electronic".to_string()],
+      styles: vec!["J-pop".to_string()],
+      ..Default::default()
+    });
+    assert_eq!(edit.album.as_deref(), Some("Still Still Stellar"));
+    assert_eq!(edit.artists, vec!["Hoshimachi Suisei"]);
+    assert_eq!(edit.genres, vec!["Electronic", "J-pop"]);
+    assert_eq!(edit.release_query().title, "Still Still Stellar");
+  }
+
+  #[test]
+  fn test_save_song() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      ..Default::default()
+    })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+
+    let mut edit = load_song(&mut database, song_id)?;
+    assert_eq!(edit.value(SongField::Artists), "Hoshimachi Suisei");
+    edit.set_value(SongField::Artists, "Hoshimachi Suisei, TAKU INOUE ,");
+    edit.set_value(SongField::Album, " Still Still Stellar ");
+    edit.set_value(SongField::Title, "");
+    save_song(&mut database, &edit)?;
+
+    assert_eq!(load_song(&mut database, song_id)?, SongEdit {
+      song_id,
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string(), "TAKU INOUE".to_string()],
+      album: Some("Still Still Stellar".to_string()),
+      genres: vec![],
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      write_tags: false,
+    });
+    // only the changed fields were edited by hand
+    let edited: Vec<_> = database
+      .get_song_provenance(song_id)?
+      .into_iter()
+      .filter(|record| record.source == MetadataSource::Manual)
+      .map(|record| record.field)
+      .collect();
+    assert_eq!(edited, vec![SongField::Artists, SongField::Album]);
+    Ok(())
+  }
+}
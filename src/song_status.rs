@@ -0,0 +1,490 @@
+//! Status flags of the songs listed in the Manager
+//!
+//! The flags are worked out once when the songs of a tab are loaded, so that drawing the list
+//! stays cheap: whether the file is gone from the music dir, whether its video was found gone,
+//! whether the artist or album is missing, and whether the song was added recently. Whether a song is queued for download or
+//! playing changes more often and is kept up to date by the song list itself.
+//!
+//! The display mode picks the songs listed: those whose file is in the music dir, every song of
+//! the library, or every song along with the audio files of the music dir missing from the library.
+//! Every song of the library is loaded a page at a time as the list is scrolled, so that large
+//! libraries open right away. The songs of playlists, searches and the other display modes are
+//! loaded at once.
+
+use std::{
+  collections::{HashMap, HashSet},
+  path::Path,
+};
+
+use chrono::{Duration, NaiveDateTime};
+use color_eyre::eyre::Result;
+#[cfg(feature = "tui")]
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  database::Database,
+  disambiguation::{self, Disambiguation, SongLabel},
+  layouts::TabFilter,
+  models::Song,
+  playlists,
+  query::{Condition, Field, Op, Query, SongSort, Value},
+  scan::{self, IgnoreRules},
+};
+
+/// Songs added within this many days are recent
+const RECENT_DAYS: i64 = 7;
+/// How many songs are loaded at a time when the songs of a tab are loaded a page at a time
+pub const PAGE_SIZE: usize = 200;
+
+/// Which songs the Manager lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+pub enum DisplayMode {
+  /// the songs whose file is in the music dir
+  #[strum(to_string = "local")]
+  Local,
+  /// every song of the library
+  #[default]
+  #[strum(to_string = "database")]
+  Database,
+  /// every song of the library and the files of the music dir missing from it
+  #[strum(to_string = "all")]
+  All,
+}
+
+impl DisplayMode {
+  pub fn next(self) -> Self {
+    match self {
+      DisplayMode::Local => DisplayMode::Database,
+      DisplayMode::Database => DisplayMode::All,
+      DisplayMode::All => DisplayMode::Local,
+    }
+  }
+}
+
+/// What is worth knowing about a song at a glance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SongFlags {
+  pub playing: bool,
+  /// the song has no file, or its file is gone from the music dir
+  pub file_missing: bool,
+  /// the video the song was downloaded from is gone
+  pub dead_link: bool,
+  pub pending_download: bool,
+  /// the song has no artist or no album
+  pub incomplete: bool,
+  pub recently_added: bool,
+  /// a file of the music dir that is not in the library
+  pub untracked: bool,
+}
+
+impl SongFlags {
+  /// The glyph and color of every status of the song, in a fixed column each so that the rows line
+  /// up. Statuses the song does not have are blank
+  #[cfg(feature = "tui")]
+  pub fn glyphs(&self) -> [(char, Color); 7] {
+    let glyph = |set: bool, glyph, color| if set { (glyph, color) } else { (' ', Color::Reset) };
+    [
+      glyph(self.playing, '▶', Color::Green),
+      glyph(self.file_missing, '✗', Color::Red),
+      glyph(self.dead_link, '⊘', Color::Magenta),
+      glyph(self.pending_download, '↓', Color::Blue),
+      glyph(self.incomplete, '!', Color::Yellow),
+      glyph(self.recently_added, '+', Color::Cyan),
+      glyph(self.untracked, '?', Color::DarkGray),
+    ]
+  }
+}
+
+/// A song as listed in the Manager
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongListRow {
+  /// 0 for the files missing from the library
+  pub id: i32,
+  pub title: String,
+  /// the title as listed, followed by what tells it apart from the other songs of the same title
+  pub label: String,
+  pub youtube_id: Option<String>,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  /// the path of the file from the music dir
+  pub path: Option<String>,
+  pub flags: SongFlags,
+}
+
+impl SongListRow {
+  /// Where the file of the song is
+  pub fn file_status(&self) -> &'static str {
+    match (self.flags.untracked, self.flags.file_missing, &self.path) {
+      (true, ..) => "not in library",
+      (false, true, None) => "no file",
+      (false, true, Some(_)) => "missing",
+      (false, false, _) => "local",
+    }
+  }
+}
+
+/// A page of the songs of the active Manager tab
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongPage {
+  /// the songs of the tab are loaded again when they may have changed, the pages of an older load
+  /// are outdated
+  pub generation: u64,
+  /// how many songs the tab has
+  pub total: usize,
+  /// the index in the tab of the first song of the page
+  pub offset: usize,
+  pub rows: Vec<SongListRow>,
+}
+
+/// Whether the songs of a tab are loaded a page at a time. Playlists keep their own order, searches
+/// rank their songs and the other display modes check the music dir, so they are loaded at once
+pub fn is_paged(filter: &TabFilter, mode: DisplayMode, searching: bool) -> bool {
+  mode == DisplayMode::Database && !searching && !matches!(filter, TabFilter::Playlist(_))
+}
+
+/// Keep the songs found by a search, best matches first
+pub fn keep_found(rows: Vec<SongListRow>, found: &[i32]) -> Vec<SongListRow> {
+  let order: HashMap<i32, usize> = found.iter().enumerate().map(|(index, id)| (*id, index)).collect();
+  let mut rows: Vec<_> = rows.into_iter().filter(|row| !row.flags.untracked && order.contains_key(&row.id)).collect();
+  rows.sort_by_key(|row| order[&row.id]);
+  rows
+}
+
+/// The query picking the songs of a tab, `None` for every song. Playlists are not picked by a query
+fn tab_query(filter: &TabFilter) -> Option<Query> {
+  let condition =
+    |field, name: &String| Some(Query::Condition(Condition { field, op: Op::Eq, value: Value::Text(name.clone()) }));
+  match filter {
+    TabFilter::All | TabFilter::Playlist(_) => None,
+    TabFilter::Artist(name) => condition(Field::Artist, name),
+    TabFilter::Album(name) => condition(Field::Album, name),
+    TabFilter::Genre(name) => condition(Field::Genre, name),
+  }
+}
+
+/// The songs of a tab, in the order they were added, or in the order of the playlist
+fn tab_songs(database: &mut Database, filter: &TabFilter) -> Result<Vec<Song>> {
+  // the playlists stored in the library come with their songs, the generated ones are picked out
+  // by id
+  let mut playlist = None;
+  let query = match filter {
+    TabFilter::Playlist(name) => {
+      if let Some(stored) = database.get_playlist_by_name(name)? {
+        return database.get_playlist_songs(stored.id);
+      }
+      let entries = playlists::load_entries(database)?;
+      let song_ids: HashSet<i32> = playlists::build_playlists(&entries)
+        .remove(name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| entry.song_id)
+        .collect();
+      playlist = Some(song_ids);
+      None
+    },
+    _ => tab_query(filter),
+  };
+  let mut songs = vec![];
+  database.for_each_song(query.as_ref(), None, 0, |song| {
+    if playlist.as_ref().is_none_or(|song_ids| song_ids.contains(&song.id)) {
+      songs.push(song);
+    }
+    Ok(())
+  })?;
+  Ok(songs)
+}
+
+/// The ids of the songs of a tab, in the order they are listed
+pub fn tab_song_ids(database: &mut Database, filter: &TabFilter) -> Result<Vec<i32>> {
+  Ok(tab_songs(database, filter)?.into_iter().map(|song| song.id).collect())
+}
+
+/// What the rows of songs are made of besides the songs, by song id
+#[derive(Default)]
+struct Details {
+  artists: HashMap<i32, Vec<String>>,
+  albums: HashMap<i32, String>,
+  files: HashMap<i32, String>,
+  dead: HashSet<i32>,
+}
+
+impl Details {
+  /// The details of the songs given only
+  fn of_songs(database: &mut Database, song_ids: &[i32]) -> Result<Self> {
+    let mut details = Details::default();
+    for (song_id, name) in database.get_artist_names_of_songs(song_ids)? {
+      details.artists.entry(song_id).or_default().push(name);
+    }
+    details.albums = database.get_album_names_of_songs(song_ids)?.into_iter().collect();
+    details.files = database.get_files_of_songs(song_ids)?.into_iter().collect();
+    details.dead = database.get_dead_links_of_songs(song_ids)?.into_iter().collect();
+    Ok(details)
+  }
+
+  fn label<'a>(&'a self, song: &'a Song) -> SongLabel<'a> {
+    SongLabel {
+      title: &song.title,
+      artists: self.artists.get(&song.id).map(Vec::as_slice).unwrap_or_default(),
+      album: self.albums.get(&song.id).map(String::as_str),
+    }
+  }
+
+  /// The row of a song, without its label
+  fn row(&self, song: Song, music_dir: &Path, now: NaiveDateTime) -> SongListRow {
+    // a song can be listed more than once in a playlist
+    let artists = self.artists.get(&song.id).cloned().unwrap_or_default();
+    let album = self.albums.get(&song.id).cloned();
+    let path = self.files.get(&song.id).cloned();
+    let flags = SongFlags {
+      file_missing: !path.as_ref().is_some_and(|path| music_dir.join(path).is_file()),
+      dead_link: self.dead.contains(&song.id),
+      incomplete: artists.is_empty() || album.is_none(),
+      recently_added: song.added_at.is_some_and(|added_at| now - added_at < Duration::days(RECENT_DAYS)),
+      ..Default::default()
+    };
+    SongListRow {
+      id: song.id,
+      title: song.title,
+      youtube_id: song.youtube_id,
+      artists,
+      album,
+      path,
+      flags,
+      ..Default::default()
+    }
+  }
+}
+
+/// Label the rows, telling them apart from each other and from the songs of the same title listed
+/// elsewhere
+fn label_rows(rows: &mut [SongListRow], elsewhere: &[SongLabel], disambiguation: Disambiguation) {
+  let labels: Vec<_> = rows
+    .iter()
+    .map(|row| SongLabel { title: &row.title, artists: &row.artists, album: row.album.as_deref() })
+    .chain(elsewhere.iter().copied())
+    .collect();
+  let labels = disambiguation::disambiguate(&labels, disambiguation);
+  for (row, label) in rows.iter_mut().zip(labels) {
+    row.label = label;
+  }
+}
+
+/// Load a page of the songs of a tab listing every song of the library along with their flags and
+/// labels, in the order they were added
+///
+/// # Returns
+///
+/// * how many songs the tab has, and the rows of the page
+pub fn load_page(
+  database: &mut Database,
+  music_dir: &Path,
+  filter: &TabFilter,
+  offset: usize,
+  limit: usize,
+  now: NaiveDateTime,
+  disambiguation: Disambiguation,
+) -> Result<(usize, Vec<SongListRow>)> {
+  let query = tab_query(filter);
+  let total = database.count_songs(query.as_ref())? as usize;
+  let songs = database.get_songs_page(offset as i64, limit as i64, SongSort::Added, query.as_ref())?;
+  // the songs of the tab on the other pages that share a title with a song of this one
+  let elsewhere: Vec<Song> = match disambiguation {
+    Disambiguation::Off => vec![],
+    _ => {
+      let titles: Vec<_> = songs.iter().map(|song| song.title.clone()).collect();
+      let page: HashSet<i32> = songs.iter().map(|song| song.id).collect();
+      database.get_songs_titled(&titles, query.as_ref())?.into_iter().filter(|song| !page.contains(&song.id)).collect()
+    },
+  };
+  let song_ids: Vec<_> = songs.iter().chain(&elsewhere).map(|song| song.id).collect();
+  let details = Details::of_songs(database, &song_ids)?;
+  let elsewhere: Vec<_> = elsewhere.iter().map(|song| details.label(song)).collect();
+  let mut rows: Vec<_> = songs.into_iter().map(|song| details.row(song, music_dir, now)).collect();
+  label_rows(&mut rows, &elsewhere, disambiguation);
+  Ok((total, rows))
+}
+
+/// Load the songs of a tab along with their flags and labels, as the display mode picks them. The
+/// files missing from the library are only listed in the tab of every song, as they have no
+/// metadata to filter by
+///
+/// # Arguments
+///
+/// * `rules` - what to leave out of the files of the music dir
+pub fn load_rows(
+  database: &mut Database,
+  music_dir: &Path,
+  filter: &TabFilter,
+  mode: DisplayMode,
+  rules: &IgnoreRules,
+  now: NaiveDateTime,
+  disambiguation: Disambiguation,
+) -> Result<Vec<SongListRow>> {
+  let songs = tab_songs(database, filter)?;
+
+  let mut details = Details::default();
+  for (song_id, name) in database.get_song_artist_names()? {
+    details.artists.entry(song_id).or_default().push(name);
+  }
+  details.albums = database.get_song_albums()?.into_iter().map(|(song_id, album)| (song_id, album.name)).collect();
+  details.files = database.get_songs_with_files()?.into_iter().map(|(song, path)| (song.id, path)).collect();
+  let untracked = match (mode, filter) {
+    (DisplayMode::All, TabFilter::All) => {
+      let known: HashSet<&str> = details.files.values().map(String::as_str).collect();
+      scan::music_files(music_dir, rules)?
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .filter(|path| !known.contains(path.as_str()))
+        .collect()
+    },
+    _ => vec![],
+  };
+  details.dead = database
+    .get_link_checks()?
+    .into_iter()
+    .filter(|check| check.dead_reason.is_some())
+    .map(|check| check.song_id)
+    .collect();
+
+  let rows = songs.into_iter().map(|song| details.row(song, music_dir, now));
+  let untracked = untracked.into_iter().map(|path| {
+    SongListRow {
+      title: Path::new(&path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default(),
+      path: Some(path),
+      flags: SongFlags { untracked: true, ..Default::default() },
+      ..Default::default()
+    }
+  });
+  let mut rows: Vec<_> = match mode {
+    DisplayMode::Local => rows.filter(|row| !row.flags.file_missing).collect(),
+    DisplayMode::Database => rows.collect(),
+    DisplayMode::All => rows.chain(untracked).collect(),
+  };
+  label_rows(&mut rows, &[], disambiguation);
+  Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewArtist, NewFile, NewSong, SongAlbum, SongArtist},
+  };
+
+  #[test]
+  fn test_load_rows() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-status-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    std::fs::write(music_dir.join("present.opus"), "")?;
+    let mut database = setup_database()?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    for (title, path) in [("Stellar Stellar", "present.opus"), ("Bluerose", "missing.opus")] {
+      let file_id = database.insert_file(NewFile { relative_path: path.to_string() })?;
+      let song_id =
+        database.insert_song(NewSong { title: title.to_string(), file_id: Some(file_id), ..Default::default() })?;
+      database.insert_song_artist(SongArtist { song_id, artist_id })?;
+      if path == "present.opus" {
+        database.insert_song_album(SongAlbum { song_id, album_id })?;
+      }
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let rules = IgnoreRules::new(&Default::default())?;
+    let load = |database: &mut Database, filter: &TabFilter, mode, now| {
+      load_rows(database, &music_dir, filter, mode, &rules, now, Disambiguation::Auto)
+    };
+    let rows = load(&mut database, &TabFilter::All, DisplayMode::Database, now)?;
+    let flags: Vec<_> = rows.iter().map(|row| (row.title.as_str(), row.flags)).collect();
+    assert_eq!(flags, vec![
+      ("Stellar Stellar", SongFlags { recently_added: true, ..Default::default() }),
+      ("Bluerose", SongFlags { file_missing: true, incomplete: true, recently_added: true, ..Default::default() }),
+    ]);
+    assert_eq!(rows[0].artists, vec!["Hoshimachi Suisei".to_string()]);
+    assert_eq!(rows[0].album.as_deref(), Some("Still Still Stellar"));
+    assert_eq!(rows[1].label, "Bluerose");
+    assert_eq!((rows[0].file_status(), rows[1].file_status()), ("local", "missing"));
+
+    let album = TabFilter::Album("Still Still Stellar".to_string());
+    let rows = load(&mut database, &album, DisplayMode::Database, now)?;
+    assert_eq!(rows.iter().map(|row| row.title.as_str()).collect::<Vec<_>>(), vec!["Stellar Stellar"]);
+    let later = now + Duration::days(RECENT_DAYS);
+    assert!(!load(&mut database, &TabFilter::All, DisplayMode::Database, later)?[0].flags.recently_added);
+
+    // the display modes pick the songs on disk, or add the files missing from the library
+    std::fs::write(music_dir.join("Idol.opus"), "")?;
+    let titles =
+      |rows: Vec<SongListRow>| rows.into_iter().map(|row| (row.title.clone(), row.file_status())).collect::<Vec<_>>();
+    assert_eq!(titles(load(&mut database, &TabFilter::All, DisplayMode::Local, now)?), vec![(
+      "Stellar Stellar".to_string(),
+      "local"
+    )]);
+    assert_eq!(titles(load(&mut database, &TabFilter::All, DisplayMode::All, now)?), vec![
+      ("Stellar Stellar".to_string(), "local"),
+      ("Bluerose".to_string(), "missing"),
+      ("Idol".to_string(), "not in library"),
+    ]);
+    assert_eq!(load(&mut database, &album, DisplayMode::All, now)?.len(), 1);
+    let rows = load(&mut database, &TabFilter::All, DisplayMode::All, now)?;
+    let found = keep_found(rows.clone(), &[rows[1].id, rows[0].id, 0]);
+    assert_eq!(found.iter().map(|row| row.title.as_str()).collect::<Vec<_>>(), vec!["Bluerose", "Stellar Stellar"]);
+    // a stored playlist lists its songs in its order, as many times as they were added
+    let playlist_id = database.create_playlist("Suisei")?;
+    database.add_playlist_songs(playlist_id, &[rows[1].id, rows[0].id, rows[1].id])?;
+    let playlist = load(&mut database, &TabFilter::Playlist("Suisei".to_string()), DisplayMode::Database, now)?;
+    let playlist: Vec<_> = playlist.iter().map(|row| (row.title.as_str(), row.artists.len())).collect();
+    assert_eq!(playlist, vec![("Bluerose", 1), ("Stellar Stellar", 1), ("Bluerose", 1)]);
+    assert_eq!(DisplayMode::All.next(), DisplayMode::Local);
+    #[cfg(feature = "tui")]
+    assert_eq!(SongFlags { playing: true, ..Default::default() }.glyphs()[..2], [
+      ('▶', Color::Green),
+      (' ', Color::Reset)
+    ]);
+    std::fs::remove_dir_all(music_dir)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_load_page() -> Result<()> {
+    let music_dir = std::env::temp_dir().join(format!("muzik-status-{}", uuid::Uuid::new_v4()));
+    let mut database = setup_database()?;
+    let songs = [
+      ("Ghost", "Hoshimachi Suisei"),
+      ("Stellar Stellar", "Hoshimachi Suisei"),
+      ("Bluerose", "Hoshimachi Suisei"),
+      ("ghost", "Mori Calliope"),
+      ("Template", "Mori Calliope"),
+    ];
+    for (title, artist) in songs {
+      let song_id = database.insert_song(NewSong { title: title.to_string(), ..Default::default() })?;
+      database.set_song_artists(song_id, &[artist.to_string()])?;
+    }
+    let now = chrono::Utc::now().naive_utc();
+    let rules = IgnoreRules::new(&Default::default())?;
+    // the pages put together are the songs loaded at once, labels included
+    for filter in [TabFilter::All, TabFilter::Artist("Mori Calliope".to_string())] {
+      let all =
+        load_rows(&mut database, &music_dir, &filter, DisplayMode::Database, &rules, now, Disambiguation::Auto)?;
+      let mut paged = vec![];
+      for offset in (0..all.len()).step_by(2) {
+        let (total, rows) = load_page(&mut database, &music_dir, &filter, offset, 2, now, Disambiguation::Auto)?;
+        assert_eq!(total, all.len());
+        paged.extend(rows);
+      }
+      assert_eq!(paged, all);
+    }
+    let (_, rows) = load_page(&mut database, &music_dir, &TabFilter::All, 2, 2, now, Disambiguation::Auto)?;
+    let labels: Vec<_> = rows.iter().map(|row| row.label.as_str()).collect();
+    assert_eq!(labels, vec!["Bluerose", "ghost (Mori Calliope)"]);
+
+    assert!(is_paged(&TabFilter::All, DisplayMode::Database, false));
+    assert!(!is_paged(&TabFilter::All, DisplayMode::Database, true));
+    assert!(!is_paged(&TabFilter::All, DisplayMode::Local, false));
+    assert!(!is_paged(&TabFilter::Playlist("Suisei".to_string()), DisplayMode::Database, false));
+    Ok(())
+  }
+}
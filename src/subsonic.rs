@@ -0,0 +1,1089 @@
+//! An (Open)Subsonic compatible API for mobile apps
+//!
+//! The HTTP server of the daemon answers enough of the Subsonic REST API at `/rest` for apps such
+//! as DSub or Symfonium to browse and stream the library. Apps log in with any user name and a
+//! token of the daemon as the password. A daemon without tokens accepts any credentials from apps
+//! on the same machine and refuses the others.
+//! Artists and albums are those of the songs that have a file, and the auto-playlists of
+//! [`crate::playlists`] are served as playlists. Every request reads the songs it answers with from
+//! the library, so apps always see the songs downloaded since.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  net::IpAddr,
+  path::Path,
+  time::Duration,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use hyper::{
+  header::{CONTENT_TYPE, LOCATION, RANGE},
+  HeaderMap, Response, StatusCode,
+};
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+use tracing::error;
+
+use crate::{
+  art,
+  auth::{AccessControl, Client, Denied, Scope},
+  cache,
+  collation::Collation,
+  config::{CollationConfig, Config},
+  database::{Database, DatabaseHandle},
+  listenbrainz,
+  models::{Album, Artist, Song},
+  playlists,
+  server::{self, Body},
+};
+
+/// The version of the Subsonic API answered
+const API_VERSION: &str = "1.16.1";
+const XML_NAMESPACE: &str = "http://subsonic.org/restapi";
+const ARTIST_PREFIX: &str = "ar-";
+const ALBUM_PREFIX: &str = "al-";
+const PLAYLIST_PREFIX: &str = "pl-";
+/// The bitrate songs are transcoded to when a client asks for another format without a bitrate
+const DEFAULT_BITRATE: u32 = 128;
+const DEFAULT_LIST_SIZE: usize = 10;
+const MAX_LIST_SIZE: usize = 500;
+
+/// The errors of the API, with the codes clients know them by
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ApiError {
+  Generic(String),
+  MissingParameter(&'static str),
+  WrongCredentials,
+  NotAuthorized(String),
+  NotFound(&'static str),
+}
+
+impl ApiError {
+  fn code(&self) -> u32 {
+    match self {
+      ApiError::Generic(_) => 0,
+      ApiError::MissingParameter(_) => 10,
+      ApiError::WrongCredentials => 40,
+      ApiError::NotAuthorized(_) => 50,
+      ApiError::NotFound(_) => 70,
+    }
+  }
+}
+
+impl std::fmt::Display for ApiError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ApiError::Generic(message) | ApiError::NotAuthorized(message) => write!(f, "{message}"),
+      ApiError::MissingParameter(name) => write!(f, "required parameter is missing: {name}"),
+      ApiError::WrongCredentials => write!(f, "wrong username or password"),
+      ApiError::NotFound(what) => write!(f, "{what} not found"),
+    }
+  }
+}
+
+impl std::error::Error for ApiError {
+}
+
+impl From<color_eyre::Report> for ApiError {
+  fn from(report: color_eyre::Report) -> Self {
+    error!("subsonic request failed: {report}");
+    ApiError::Generic(report.to_string())
+  }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// What an endpoint answers with
+enum Reply {
+  /// Fields added to the `subsonic-response` object
+  Payload(Value),
+  /// The response itself, such as audio
+  Media(Response<Body>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Xml,
+  Json,
+}
+
+/// Answer a request made to `/rest/{endpoint}` by a client connecting from the address
+pub async fn handle(
+  config: &Config,
+  database: &DatabaseHandle,
+  access: &AccessControl,
+  address: IpAddr,
+  endpoint: &str,
+  parameters: &HashMap<String, String>,
+  headers: &HeaderMap,
+) -> Response<Body> {
+  let format = match parameters.get("f").map(String::as_str) {
+    Some("json" | "jsonp") => Format::Json,
+    _ => Format::Xml,
+  };
+  let endpoint = endpoint.trim_end_matches(".view");
+  let scope = if endpoint == "scrobble" { Scope::QueueDownloads } else { Scope::ReadOnly };
+  let result = match authenticate(access, address, parameters) {
+    Ok(client) => {
+      match access.authorize(&client, scope) {
+        Ok(()) => answer(config, database, endpoint, parameters, headers).await,
+        Err(denied) => Err(ApiError::NotAuthorized(denied.to_string())),
+      }
+    },
+    Err(e) => Err(e),
+  };
+  match result {
+    Ok(Reply::Media(response)) => response,
+    Ok(Reply::Payload(payload)) => render(format, envelope(Ok(payload))),
+    Err(e) => render(format, envelope(Err(e))),
+  }
+}
+
+/// The rights of the client from its credentials, the token of the daemon as a password in clear,
+/// hex encoded, or salted and hashed as Subsonic does, or as an OpenSubsonic API key
+fn authenticate(access: &AccessControl, address: IpAddr, parameters: &HashMap<String, String>) -> ApiResult<Client> {
+  // any credentials are accepted from apps on the same machine by a daemon without tokens
+  if access.remote_client(address) == Client::Trusted {
+    return Ok(Client::Trusted);
+  }
+  let client = if let Some(key) = parameters.get("apiKey") {
+    access.authenticate(key)
+  } else if let (Some(hash), Some(salt)) = (parameters.get("t"), parameters.get("s")) {
+    access.authenticate_matching(|token| md5_hex(format!("{token}{salt}").as_bytes()).eq_ignore_ascii_case(hash))
+  } else if let Some(password) = parameters.get("p") {
+    access.authenticate(&decode_password(password).ok_or(ApiError::WrongCredentials)?)
+  } else {
+    Err(Denied::Unauthenticated)
+  };
+  client.map_err(|denied| {
+    match denied {
+      Denied::Unauthenticated => ApiError::MissingParameter("p"),
+      Denied::NoTokens => ApiError::NotAuthorized(denied.to_string()),
+      _ => ApiError::WrongCredentials,
+    }
+  })
+}
+
+/// The password in clear, from passwords sent as is or hex encoded after `enc:`
+fn decode_password(password: &str) -> Option<String> {
+  let Some(hex) = password.strip_prefix("enc:") else {
+    return Some(password.to_string());
+  };
+  let bytes = (0..hex.len())
+    .step_by(2)
+    .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+    .collect::<Option<Vec<u8>>>()?;
+  String::from_utf8(bytes).ok()
+}
+
+async fn answer(
+  config: &Config,
+  database: &DatabaseHandle,
+  endpoint: &str,
+  parameters: &HashMap<String, String>,
+  headers: &HeaderMap,
+) -> ApiResult<Reply> {
+  let payload = match endpoint {
+    "ping" => json!({}),
+    "getLicense" => json!({ "license": { "valid": true } }),
+    "getOpenSubsonicExtensions" => {
+      json!({ "openSubsonicExtensions": [
+        { "name": "apiKeyAuthentication", "versions": [1] },
+        { "name": "transcodeOffset", "versions": [1] },
+      ] })
+    },
+    "getMusicFolders" => json!({ "musicFolders": { "musicFolder": [{ "id": 1, "name": "Music" }] } }),
+    "getUser" => user(parameters.get("username").map_or("muzik", String::as_str)),
+    "getStarred" => json!({ "starred": {} }),
+    "getStarred2" => json!({ "starred2": {} }),
+    "scrobble" => {
+      scrobble(config, database, parameters).await?;
+      json!({})
+    },
+    "stream" | "download" | "getCoverArt" => return media(config, database, endpoint, parameters, headers).await,
+    _ => {
+      let (collation, music_dir) = (config.config.collation.clone(), config.config.music_dir.clone());
+      let (endpoint, parameters) = (endpoint.to_string(), parameters.clone());
+      database
+        .call(move |database| Ok(library_answer(database, &collation, &endpoint, &parameters, &music_dir)))
+        .await??
+    },
+  };
+  Ok(Reply::Payload(payload))
+}
+
+/// Answer from the songs of the library the endpoint lists
+fn library_answer(
+  database: &mut Database,
+  collation: &CollationConfig,
+  endpoint: &str,
+  parameters: &HashMap<String, String>,
+  music_dir: &Path,
+) -> ApiResult<Value> {
+  let selection = Selection::of(database, endpoint, parameters)?;
+  Library::load(database, collation, &selection)?.answer(endpoint, parameters, music_dir)
+}
+
+/// The rights of the user, which are the same for everyone
+fn user(username: &str) -> Value {
+  json!({ "user": {
+    "username": username,
+    "scrobblingEnabled": true,
+    "adminRole": false,
+    "settingsRole": false,
+    "downloadRole": true,
+    "uploadRole": false,
+    "playlistRole": false,
+    "coverArtRole": true,
+    "commentRole": false,
+    "podcastRole": false,
+    "streamRole": true,
+    "jukeboxRole": false,
+    "shareRole": false,
+    "videoConversionRole": false,
+    "folder": [1],
+  } })
+}
+
+/// Record a play of a song, and submit it to ListenBrainz. Reports of the song starting to play
+/// are only submitted
+async fn scrobble(config: &Config, database: &DatabaseHandle, parameters: &HashMap<String, String>) -> ApiResult<()> {
+  let song_id = song_id(parameters)?;
+  let submission = parameters.get("submission").is_none_or(|submission| submission != "false");
+  // whole seconds, the same for the play and the listen
+  let listened_at = parameters
+    .get("time")
+    .and_then(|time| time.parse::<i64>().ok())
+    .map(|time| time.div_euclid(1000))
+    .unwrap_or_else(|| Utc::now().timestamp());
+  let played_at = DateTime::from_timestamp(listened_at, 0).ok_or(ApiError::Generic("invalid time".to_string()))?;
+  let listenbrainz = config.config.listenbrainz.clone();
+  let submitted = listenbrainz.enabled;
+  let track_metadata = database
+    .call(move |database| {
+      if database.get_song_from_id(song_id).is_err() {
+        return Ok(Err(ApiError::NotFound("song")));
+      }
+      if submission {
+        database.record_play(song_id, played_at.naive_utc())?;
+      }
+      Ok(Ok(if submitted { listenbrainz::track_metadata(database, song_id)? } else { None }))
+    })
+    .await??;
+  if let Some(track_metadata) = track_metadata {
+    listenbrainz::spawn_submit(&listenbrainz, submission.then_some(listened_at), track_metadata);
+  }
+  Ok(())
+}
+
+/// The audio or the cover of a song
+async fn media(
+  config: &Config,
+  database: &DatabaseHandle,
+  endpoint: &str,
+  parameters: &HashMap<String, String>,
+  headers: &HeaderMap,
+) -> ApiResult<Reply> {
+  let song_id = song_id(parameters)?;
+  if endpoint == "getCoverArt" {
+    // covers are the thumbnails of the videos, served from the cache when they are kept there
+    let song = database.call(move |database| Ok(database.get_song_from_id(song_id).ok())).await?;
+    let song = song.ok_or(ApiError::NotFound("cover"))?;
+    if let Some(cached) = song.youtube_id.map(|youtube_id| art::cache_path(&config.paths, &youtube_id)) {
+      if cached.exists() {
+        cache::touch(&cached);
+        return Ok(Reply::Media(server::file_response(&cached, None).await?));
+      }
+    }
+    let url = song.thumbnail_url.ok_or(ApiError::NotFound("cover"))?;
+    let response = Response::builder().status(StatusCode::FOUND).header(LOCATION, url).body(server::empty());
+    return Ok(Reply::Media(response.map_err(|e| ApiError::Generic(e.to_string()))?));
+  }
+  let bitrate =
+    parameters.get("maxBitRate").and_then(|bitrate| bitrate.parse::<u32>().ok()).filter(|&bitrate| bitrate > 0);
+  let transcode = match parameters.get("format").map(String::as_str) {
+    _ if endpoint == "download" => false,
+    Some("raw") | None => bitrate.is_some(),
+    Some(_) => true,
+  };
+  let bitrate = bitrate.unwrap_or(DEFAULT_BITRATE).clamp(*server::BITRATES.start(), *server::BITRATES.end());
+  let settings = config.clone();
+  let file = database
+    .call(move |database| {
+      let Some(relative_path) = database.get_song_file(song_id)? else {
+        return Ok(None);
+      };
+      let transcode = transcode && server::should_transcode(database, &settings, song_id, bitrate)?;
+      Ok(Some((relative_path, transcode)))
+    })
+    .await?;
+  let (relative_path, transcode) = file.ok_or(ApiError::NotFound("song"))?;
+  let path = config.config.music_dir.join(relative_path);
+  let response = if transcode {
+    let start = parameters
+      .get("timeOffset")
+      .and_then(|offset| offset.parse().ok())
+      .and_then(|offset| Duration::try_from_secs_f64(offset).ok())
+      .unwrap_or_default();
+    server::transcoded_response(&path, bitrate, start)?
+  } else {
+    let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+    server::file_response(&path, range).await?
+  };
+  Ok(Reply::Media(response))
+}
+
+fn parameter<'a>(parameters: &'a HashMap<String, String>, name: &'static str) -> ApiResult<&'a str> {
+  parameters.get(name).map(String::as_str).ok_or(ApiError::MissingParameter(name))
+}
+
+fn song_id(parameters: &HashMap<String, String>) -> ApiResult<i32> {
+  parameter(parameters, "id")?.parse().map_err(|_| ApiError::NotFound("song"))
+}
+
+/// The id of an artist, album or playlist, which are told apart by their prefix
+fn prefixed_id(id: &str, prefix: &str, what: &'static str) -> ApiResult<i32> {
+  id.strip_prefix(prefix).and_then(|id| id.parse().ok()).ok_or(ApiError::NotFound(what))
+}
+
+/// The number of items asked for in the parameter, at most [`MAX_LIST_SIZE`]
+fn count(parameters: &HashMap<String, String>, name: &str, default: usize) -> usize {
+  parameters.get(name).and_then(|count| count.parse().ok()).unwrap_or(default).min(MAX_LIST_SIZE)
+}
+
+fn offset(parameters: &HashMap<String, String>, name: &str) -> usize {
+  parameters.get(name).and_then(|offset| offset.parse().ok()).unwrap_or_default()
+}
+
+/// The songs the library is read for, only those the endpoint answers with when it is about one
+/// song, album or artist
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selection {
+  Everything,
+  /// the songs by id
+  Songs(Vec<i32>),
+}
+
+impl Selection {
+  fn of(database: &mut Database, endpoint: &str, parameters: &HashMap<String, String>) -> ApiResult<Self> {
+    let song_ids = match endpoint {
+      "getSong" => vec![song_id(parameters)?],
+      "getAlbum" => {
+        database.get_song_ids_of_album(prefixed_id(parameter(parameters, "id")?, ALBUM_PREFIX, "album")?)?
+      },
+      // the albums of the artist are listed with their songs by other artists
+      "getArtist" => {
+        database.get_song_ids_of_artist(prefixed_id(parameter(parameters, "id")?, ARTIST_PREFIX, "artist")?)?
+      },
+      "getMusicDirectory" => {
+        let id = parameter(parameters, "id")?;
+        match id.starts_with(ARTIST_PREFIX) {
+          true => database.get_song_ids_of_artist(prefixed_id(id, ARTIST_PREFIX, "artist")?)?,
+          false => database.get_song_ids_of_album(prefixed_id(id, ALBUM_PREFIX, "directory")?)?,
+        }
+      },
+      _ => return Ok(Selection::Everything),
+    };
+    Ok(Selection::Songs(song_ids))
+  }
+}
+
+/// A song with a file, with everything it is listed with
+struct LibrarySong {
+  song: Song,
+  /// the path of the file relative to the music dir
+  path: String,
+  artists: Vec<Artist>,
+  album: Option<Album>,
+  genres: Vec<String>,
+}
+
+/// The songs with a file, from which artists and albums are listed
+struct Library {
+  songs: Vec<LibrarySong>,
+  collation: Collation,
+}
+
+impl Library {
+  fn load(database: &mut Database, collation: &CollationConfig, selection: &Selection) -> color_eyre::Result<Self> {
+    let collation = Collation::new(collation)?;
+    let (song_artists, song_albums, song_genres, songs) = match selection {
+      Selection::Everything => {
+        let song_artists = database.get_song_artists()?;
+        let song_albums = database.get_song_albums()?;
+        (song_artists, song_albums, database.get_song_genre_names()?, database.get_songs_with_files()?)
+      },
+      Selection::Songs(song_ids) => {
+        let song_artists = database.get_artists_of_songs(song_ids)?;
+        let song_albums = database.get_albums_of_songs(song_ids)?;
+        (
+          song_artists,
+          song_albums,
+          database.get_genre_names_of_songs(song_ids)?,
+          database.get_songs_with_files_of(song_ids)?,
+        )
+      },
+    };
+    let mut artists: HashMap<i32, Vec<Artist>> = HashMap::new();
+    for (song_id, artist) in song_artists {
+      artists.entry(song_id).or_default().push(artist);
+    }
+    let mut albums: HashMap<i32, Album> = song_albums.into_iter().collect();
+    let mut genres: HashMap<i32, Vec<String>> = HashMap::new();
+    for (song_id, name) in song_genres {
+      genres.entry(song_id).or_default().push(name);
+    }
+    let mut songs: Vec<_> = songs
+      .into_iter()
+      .map(|(song, path)| {
+        LibrarySong {
+          artists: artists.remove(&song.id).unwrap_or_default(),
+          album: albums.remove(&song.id),
+          genres: genres.remove(&song.id).unwrap_or_default(),
+          song,
+          path,
+        }
+      })
+      .collect();
+    collation.sort_by_name(&mut songs, |song| &song.song.title);
+    Ok(Self { songs, collation })
+  }
+
+  fn answer(&self, endpoint: &str, parameters: &HashMap<String, String>, music_dir: &Path) -> ApiResult<Value> {
+    let child = |song: &LibrarySong| child(song, music_dir);
+    let payload = match endpoint {
+      "getArtists" => json!({ "artists": { "ignoredArticles": "", "index": self.artist_index() } }),
+      "getIndexes" => {
+        json!({ "indexes": { "ignoredArticles": "", "lastModified": 0, "index": self.artist_index() } })
+      },
+      "getArtist" => {
+        let id = prefixed_id(parameter(parameters, "id")?, ARTIST_PREFIX, "artist")?;
+        let artist = self.artists().into_iter().find(|artist| artist.id == id).ok_or(ApiError::NotFound("artist"))?;
+        let albums: Vec<_> = self.artist_albums(id).into_iter().map(|album| self.album(album)).collect();
+        let mut artist = artist_json(artist, albums.len());
+        artist["album"] = json!(albums);
+        json!({ "artist": artist })
+      },
+      "getAlbum" => {
+        let id = prefixed_id(parameter(parameters, "id")?, ALBUM_PREFIX, "album")?;
+        let album = self.albums().into_iter().find(|album| album.id == id).ok_or(ApiError::NotFound("album"))?;
+        let mut album_json = self.album(album);
+        album_json["song"] = self.album_songs(id).into_iter().map(child).collect();
+        json!({ "album": album_json })
+      },
+      "getMusicDirectory" => self.directory(parameter(parameters, "id")?, music_dir)?,
+      "getAlbumList" | "getAlbumList2" => {
+        let albums: Vec<_> = self
+          .album_list(parameters)?
+          .into_iter()
+          .skip(offset(parameters, "offset"))
+          .take(count(parameters, "size", DEFAULT_LIST_SIZE))
+          .map(|album| self.album(album))
+          .collect();
+        let key = if endpoint == "getAlbumList" { "albumList" } else { "albumList2" };
+        json!({ key: { "album": albums } })
+      },
+      "getSong" => {
+        let id = song_id(parameters)?;
+        let song = self.songs.iter().find(|song| song.song.id == id).ok_or(ApiError::NotFound("song"))?;
+        json!({ "song": child(song) })
+      },
+      "getRandomSongs" => {
+        let mut songs: Vec<_> = self
+          .songs
+          .iter()
+          .filter(|song| parameters.get("genre").is_none_or(|genre| song.genres.contains(genre)))
+          .collect();
+        songs.shuffle(&mut rand::thread_rng());
+        let songs: Vec<_> = songs.into_iter().take(count(parameters, "size", DEFAULT_LIST_SIZE)).map(child).collect();
+        json!({ "randomSongs": { "song": songs } })
+      },
+      "search2" | "search3" => {
+        let result = self.search(parameters, music_dir);
+        let key = if endpoint == "search2" { "searchResult2" } else { "searchResult3" };
+        json!({ key: result })
+      },
+      "getGenres" => {
+        let mut genres: BTreeMap<&str, usize> = BTreeMap::new();
+        for genre in self.songs.iter().flat_map(|song| &song.genres) {
+          *genres.entry(genre).or_default() += 1;
+        }
+        let genres: Vec<_> =
+          genres.into_iter().map(|(name, count)| json!({ "value": name, "songCount": count })).collect();
+        json!({ "genres": { "genre": genres } })
+      },
+      "getPlaylists" => {
+        let playlists: Vec<_> = self
+          .playlists()
+          .iter()
+          .enumerate()
+          .map(|(index, (name, songs))| playlist_json(index, name, songs.len()))
+          .collect();
+        json!({ "playlists": { "playlist": playlists } })
+      },
+      "getPlaylist" => {
+        let index = prefixed_id(parameter(parameters, "id")?, PLAYLIST_PREFIX, "playlist")?;
+        let playlists = self.playlists();
+        let (name, songs) = usize::try_from(index)
+          .ok()
+          .and_then(|index| playlists.iter().nth(index))
+          .ok_or(ApiError::NotFound("playlist"))?;
+        let mut playlist = playlist_json(index as usize, name, songs.len());
+        playlist["entry"] =
+          songs.iter().filter_map(|&id| self.songs.iter().find(|song| song.song.id == id)).map(child).collect();
+        json!({ "playlist": playlist })
+      },
+      _ => return Err(ApiError::NotFound("endpoint")),
+    };
+    Ok(payload)
+  }
+
+  /// The artists of the songs by name
+  fn artists(&self) -> Vec<&Artist> {
+    let mut artists: Vec<&Artist> = self.songs.iter().flat_map(|song| &song.artists).collect();
+    artists.sort_by_key(|artist| artist.id);
+    artists.dedup_by_key(|artist| artist.id);
+    self.collation.sort_by_name(&mut artists, |artist| &artist.name);
+    artists
+  }
+
+  /// The albums of the songs by name
+  fn albums(&self) -> Vec<&Album> {
+    let mut albums: Vec<&Album> = self.songs.iter().filter_map(|song| song.album.as_ref()).collect();
+    albums.sort_by_key(|album| album.id);
+    albums.dedup_by_key(|album| album.id);
+    self.collation.sort_by_name(&mut albums, |album| &album.name);
+    albums
+  }
+
+  /// The songs of the album by disc then by track
+  fn album_songs(&self, album_id: i32) -> Vec<&LibrarySong> {
+    let mut songs: Vec<_> =
+      self.songs.iter().filter(|song| song.album.as_ref().is_some_and(|album| album.id == album_id)).collect();
+    songs.sort_by(|a, b| {
+      a.song.album_position().cmp(&b.song.album_position()).then_with(|| a.song.title.cmp(&b.song.title))
+    });
+    songs
+  }
+
+  /// The albums with a song of the artist
+  fn artist_albums(&self, artist_id: i32) -> Vec<&Album> {
+    self
+      .albums()
+      .into_iter()
+      .filter(|album| {
+        self.album_songs(album.id).iter().any(|song| song.artists.iter().any(|artist| artist.id == artist_id))
+      })
+      .collect()
+  }
+
+  /// The artists grouped by the first letter of their name, in the order of the artists
+  fn artist_index(&self) -> Value {
+    let mut index: Vec<(String, Vec<Value>)> = vec![];
+    for artist in self.artists() {
+      let letter = self.collation.group(&artist.name);
+      let artist = artist_json(artist, self.artist_albums(artist.id).len());
+      match index.iter_mut().find(|(name, _)| *name == letter) {
+        Some((_, artists)) => artists.push(artist),
+        None => index.push((letter, vec![artist])),
+      }
+    }
+    index.into_iter().map(|(name, artists)| json!({ "name": name, "artist": artists })).collect()
+  }
+
+  fn album(&self, album: &Album) -> Value {
+    let songs = self.album_songs(album.id);
+    let mut json = json!({
+      "id": format!("{ALBUM_PREFIX}{}", album.id),
+      "name": album.name,
+      "title": album.name,
+      "isDir": true,
+      "songCount": songs.len(),
+      "duration": 0,
+      "playCount": songs.iter().map(|song| song.song.play_count).sum::<i32>(),
+    });
+    if let Some(artist) = songs.iter().find_map(|song| song.artists.first()) {
+      json["artist"] = json!(artist.name);
+      json["artistId"] = json!(format!("{ARTIST_PREFIX}{}", artist.id));
+      json["parent"] = json["artistId"].clone();
+    }
+    if let Some(year) = album.year {
+      json["year"] = json!(year);
+    }
+    // OpenSubsonic clients show singles and EPs apart from albums with this
+    if let Some(release_type) = album.release_type {
+      json["releaseTypes"] = json!([release_type.to_string()]);
+    }
+    if let Some(genre) = songs.iter().find_map(|song| song.genres.first()) {
+      json["genre"] = json!(genre);
+    }
+    if let Some(song) = songs.iter().find(|song| song.song.thumbnail_url.is_some()) {
+      json["coverArt"] = json!(song.song.id.to_string());
+    }
+    if let Some(created) = songs.iter().filter_map(|song| song.song.added_at).min() {
+      json["created"] = json!(timestamp(created));
+    }
+    json
+  }
+
+  /// The albums ordered as the `type` of list asks
+  fn album_list(&self, parameters: &HashMap<String, String>) -> ApiResult<Vec<&Album>> {
+    let mut albums = self.albums();
+    let added_at = |album: &Album| self.album_songs(album.id).iter().filter_map(|song| song.song.added_at).max();
+    let plays = |album: &Album| self.album_songs(album.id).iter().map(|song| song.song.play_count).sum::<i32>();
+    match parameter(parameters, "type")? {
+      "alphabeticalByName" => {},
+      "alphabeticalByArtist" => {
+        let artist = |album: &Album| {
+          self.album_songs(album.id).iter().find_map(|song| song.artists.first()).map(|artist| artist.name.clone())
+        };
+        let mut by_artist: Vec<_> = albums.into_iter().map(|album| (artist(album), album)).collect();
+        by_artist.sort_by(|(left, _), (right, _)| {
+          match (left, right) {
+            (Some(left), Some(right)) => self.collation.compare(left, right),
+            _ => left.is_some().cmp(&right.is_some()),
+          }
+        });
+        albums = by_artist.into_iter().map(|(_, album)| album).collect();
+      },
+      "newest" | "recent" => albums.sort_by_cached_key(|album| std::cmp::Reverse(added_at(album))),
+      "frequent" | "highest" => albums.sort_by_cached_key(|album| std::cmp::Reverse(plays(album))),
+      "random" => albums.shuffle(&mut rand::thread_rng()),
+      "byYear" => {
+        let year = |name| {
+          parameter(parameters, name).and_then(|year| year.parse::<i32>().map_err(|_| ApiError::MissingParameter(name)))
+        };
+        let (from, to) = (year("fromYear")?, year("toYear")?);
+        albums.retain(|album| album.year.is_some_and(|year| year >= from.min(to) && year <= from.max(to)));
+        albums.sort_by_key(|album| album.year);
+        if from > to {
+          albums.reverse();
+        }
+      },
+      "byGenre" => {
+        let genre = parameter(parameters, "genre")?;
+        albums
+          .retain(|album| self.album_songs(album.id).iter().any(|song| song.genres.iter().any(|name| name == genre)));
+      },
+      "starred" => albums.clear(),
+      _ => return Err(ApiError::Generic("unknown type of album list".to_string())),
+    }
+    Ok(albums)
+  }
+
+  /// The artists, albums and songs whose name contains the query. Apps syncing the whole library
+  /// search for nothing, which finds everything
+  fn search(&self, parameters: &HashMap<String, String>, music_dir: &Path) -> Value {
+    let query = parameters.get("query").map(|query| query.trim_matches('"').to_lowercase()).unwrap_or_default();
+    let matches = |name: &str| name.to_lowercase().contains(&query);
+    let page =
+      |kind: &str| (offset(parameters, &format!("{kind}Offset")), count(parameters, &format!("{kind}Count"), 20));
+
+    let (skip, take) = page("artist");
+    let artists: Vec<_> = self
+      .artists()
+      .into_iter()
+      .filter(|artist| matches(&artist.name))
+      .skip(skip)
+      .take(take)
+      .map(|artist| artist_json(artist, self.artist_albums(artist.id).len()))
+      .collect();
+    let (skip, take) = page("album");
+    let albums: Vec<_> = self
+      .albums()
+      .into_iter()
+      .filter(|album| matches(&album.name))
+      .skip(skip)
+      .take(take)
+      .map(|album| self.album(album))
+      .collect();
+    let (skip, take) = page("song");
+    let songs: Vec<_> = self
+      .songs
+      .iter()
+      .filter(|song| matches(&song.song.title) || song.artists.iter().any(|artist| matches(&artist.name)))
+      .skip(skip)
+      .take(take)
+      .map(|song| child(song, music_dir))
+      .collect();
+    json!({ "artist": artists, "album": albums, "song": songs })
+  }
+
+  /// The albums of an artist, or the songs of an album, for clients browsing by folder
+  fn directory(&self, id: &str, music_dir: &Path) -> ApiResult<Value> {
+    if id.starts_with(ARTIST_PREFIX) {
+      let artist_id = prefixed_id(id, ARTIST_PREFIX, "artist")?;
+      let artist =
+        self.artists().into_iter().find(|artist| artist.id == artist_id).ok_or(ApiError::NotFound("artist"))?;
+      let albums: Vec<_> = self.artist_albums(artist_id).into_iter().map(|album| self.album(album)).collect();
+      Ok(json!({ "directory": { "id": id, "name": artist.name, "child": albums } }))
+    } else {
+      let album_id = prefixed_id(id, ALBUM_PREFIX, "directory")?;
+      let album = self.albums().into_iter().find(|album| album.id == album_id).ok_or(ApiError::NotFound("album"))?;
+      let songs: Vec<_> = self.album_songs(album_id).into_iter().map(|song| child(song, music_dir)).collect();
+      Ok(json!({ "directory": { "id": id, "name": album.name, "child": songs } }))
+    }
+  }
+
+  /// The auto-playlists with the ids of their songs, by name
+  fn playlists(&self) -> BTreeMap<String, Vec<i32>> {
+    let entries: Vec<_> = self
+      .songs
+      .iter()
+      .map(|song| {
+        playlists::PlaylistEntry {
+          song_id: song.song.id,
+          title: song.song.title.clone(),
+          path: song.path.clone(),
+          artists: song.artists.iter().map(|artist| artist.name.clone()).collect(),
+          genres: song.genres.clone(),
+          rating: song.song.rating,
+        }
+      })
+      .collect();
+    playlists::build_playlists(&entries)
+      .into_iter()
+      .map(|(name, entries)| (name, entries.into_iter().map(|entry| entry.song_id).collect()))
+      .collect()
+  }
+}
+
+fn artist_json(artist: &Artist, album_count: usize) -> Value {
+  json!({ "id": format!("{ARTIST_PREFIX}{}", artist.id), "name": artist.name, "albumCount": album_count })
+}
+
+/// A playlist, whose id is its place among the playlists ordered by name
+fn playlist_json(index: usize, name: &str, song_count: usize) -> Value {
+  json!({
+    "id": format!("{PLAYLIST_PREFIX}{index}"),
+    "name": name,
+    "songCount": song_count,
+    "duration": 0,
+    "owner": "muzik",
+    "public": true,
+    "created": timestamp(DateTime::UNIX_EPOCH.naive_utc()),
+    "changed": timestamp(Utc::now().naive_utc()),
+  })
+}
+
+/// A song as Subsonic lists it
+fn child(song: &LibrarySong, music_dir: &Path) -> Value {
+  let path = Path::new(&song.path);
+  let mut child = json!({
+    "id": song.song.id.to_string(),
+    "isDir": false,
+    "title": song.song.title,
+    "artist": song.artists.iter().map(|artist| artist.name.as_str()).collect::<Vec<_>>().join(", "),
+    "path": song.path,
+    "suffix": path.extension().map(|extension| extension.to_string_lossy()).unwrap_or_default(),
+    "contentType": server::content_type(path),
+    "playCount": song.song.play_count,
+    "type": "music",
+    "isVideo": false,
+  });
+  if let Some(artist) = song.artists.first() {
+    child["artistId"] = json!(format!("{ARTIST_PREFIX}{}", artist.id));
+  }
+  if let Some(album) = &song.album {
+    child["album"] = json!(album.name);
+    child["albumId"] = json!(format!("{ALBUM_PREFIX}{}", album.id));
+    child["parent"] = child["albumId"].clone();
+    if let Some(year) = album.year {
+      child["year"] = json!(year);
+    }
+  }
+  if let Some(track) = song.song.track_number {
+    child["track"] = json!(track);
+  }
+  if let Some(disc) = song.song.disc_number {
+    child["discNumber"] = json!(disc);
+  }
+  if let Some(genre) = song.genres.first() {
+    child["genre"] = json!(genre);
+  }
+  if song.song.thumbnail_url.is_some() {
+    child["coverArt"] = json!(song.song.id.to_string());
+  }
+  if let Some(rating) = song.song.rating {
+    child["userRating"] = json!(rating);
+  }
+  if let Some(added_at) = song.song.added_at {
+    child["created"] = json!(timestamp(added_at));
+  }
+  if let Ok(metadata) = std::fs::metadata(music_dir.join(path)) {
+    child["size"] = json!(metadata.len());
+  }
+  child
+}
+
+fn timestamp(time: NaiveDateTime) -> String {
+  time.format("%Y-%m-%dT%H:%M:%S.000Z").to_string()
+}
+
+/// The `subsonic-response` object, with the payload or the error
+fn envelope(result: ApiResult<Value>) -> Value {
+  let mut response = json!({
+    "status": "ok",
+    "version": API_VERSION,
+    "type": "muzik",
+    "serverVersion": env!("CARGO_PKG_VERSION"),
+    "openSubsonic": true,
+  });
+  match result {
+    Ok(Value::Object(payload)) => {
+      for (key, value) in payload {
+        response[key] = value;
+      }
+    },
+    Ok(_) => {},
+    Err(e) => {
+      response["status"] = json!("failed");
+      response["error"] = json!({ "code": e.code(), "message": e.to_string() });
+    },
+  }
+  response
+}
+
+/// The response in the format the client asked for. Errors are answered with 200 too, as Subsonic
+/// does
+fn render(format: Format, mut response: Value) -> Response<Body> {
+  let (content_type, body) = match format {
+    Format::Json => ("application/json", json!({ "subsonic-response": response }).to_string()),
+    Format::Xml => {
+      response["xmlns"] = json!(XML_NAMESPACE);
+      let mut xml = r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string();
+      write_xml("subsonic-response", &response, &mut xml);
+      ("text/xml; charset=utf-8", xml)
+    },
+  };
+  let mut response = server::message_response(StatusCode::OK, body);
+  response.headers_mut().insert(CONTENT_TYPE, content_type.parse().expect("a valid header"));
+  response
+}
+
+/// Write the value as Subsonic does in XML: the scalar fields of objects as attributes, the other
+/// fields as child elements, and every item of arrays as an element named after the field
+fn write_xml(name: &str, value: &Value, xml: &mut String) {
+  match value {
+    Value::Object(fields) => {
+      xml.push_str(&format!("<{name}"));
+      for (key, value) in fields {
+        if let Some(text) = scalar(value) {
+          xml.push_str(&format!(" {key}=\"{}\"", escape_xml(&text)));
+        }
+      }
+      let children: Vec<_> = fields.iter().filter(|(_, value)| value.is_object() || value.is_array()).collect();
+      if children.is_empty() {
+        xml.push_str("/>");
+        return;
+      }
+      xml.push('>');
+      for (key, value) in children {
+        write_xml(key, value, xml);
+      }
+      xml.push_str(&format!("</{name}>"));
+    },
+    Value::Array(items) => {
+      for item in items {
+        write_xml(name, item, xml);
+      }
+    },
+    Value::Null => {},
+    value => {
+      let text = scalar(value).unwrap_or_default();
+      xml.push_str(&format!("<{name}>{}</{name}>", escape_xml(&text)));
+    },
+  }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+  match value {
+    Value::String(text) => Some(text.clone()),
+    Value::Number(number) => Some(number.to_string()),
+    Value::Bool(boolean) => Some(boolean.to_string()),
+    _ => None,
+  }
+}
+
+fn escape_xml(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// The MD5 digest of the data in hex, which Subsonic clients prove they know the password with
+fn md5_hex(data: &[u8]) -> String {
+  const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+  const CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501, 0x698098d8,
+    0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340,
+    0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87,
+    0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039,
+    0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92,
+    0xffeff47d, 0x85845dd1, 0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+    0xeb86d391,
+  ];
+  let mut message = data.to_vec();
+  message.push(0x80);
+  while message.len() % 64 != 56 {
+    message.push(0);
+  }
+  message.extend((data.len() as u64).wrapping_mul(8).to_le_bytes());
+
+  let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+  for block in message.chunks(64) {
+    let words: Vec<u32> =
+      block.chunks(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+    let [mut a, mut b, mut c, mut d] = state;
+    for i in 0..64 {
+      let (f, g) = match i / 16 {
+        0 => ((b & c) | (!b & d), i),
+        1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+        2 => (b ^ c ^ d, (3 * i + 5) % 16),
+        _ => (c ^ (b | !d), (7 * i) % 16),
+      };
+      let f = f.wrapping_add(a).wrapping_add(CONSTANTS[i]).wrapping_add(words[g]);
+      a = d;
+      d = c;
+      c = b;
+      b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+      *word = word.wrapping_add(value);
+    }
+  }
+  state.iter().flat_map(|word| word.to_le_bytes()).map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    config::TokenConfig,
+    database::tests::setup_database,
+    models::{NewAlbum, NewArtist, NewFile, NewSong, SongAlbum, SongArtist},
+  };
+
+  fn parameters(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+  }
+
+  #[test]
+  fn test_subsonic_authentication() {
+    // the example of the Subsonic documentation
+    assert_eq!(md5_hex(b"sesamec19b2d"), "26719a1196d2a940705a59634eb18eab");
+    assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(decode_password("enc:736573616d65"), Some("sesame".to_string()));
+
+    let token = TokenConfig { token: "sesame".to_string(), scope: Scope::ReadOnly, requests_per_minute: None };
+    let access = AccessControl::new(vec![token.clone()]);
+    let client = Client::Token(token);
+    let lan = IpAddr::from([192, 168, 1, 20]);
+    assert_eq!(
+      authenticate(
+        &access,
+        lan,
+        &parameters(&[("u", "me"), ("t", "26719a1196d2a940705a59634eb18eab"), ("s", "c19b2d")])
+      ),
+      Ok(client.clone())
+    );
+    assert_eq!(authenticate(&access, lan, &parameters(&[("u", "me"), ("p", "enc:736573616d65")])), Ok(client.clone()));
+    assert_eq!(authenticate(&access, lan, &parameters(&[("apiKey", "sesame")])), Ok(client));
+    assert_eq!(
+      authenticate(&access, lan, &parameters(&[("u", "me"), ("p", "guess")])),
+      Err(ApiError::WrongCredentials)
+    );
+    assert_eq!(authenticate(&access, lan, &parameters(&[("u", "me")])), Err(ApiError::MissingParameter("p")));
+
+    // without tokens, apps on the same machine need no credentials and the others are refused
+    let open = AccessControl::default();
+    let loopback = IpAddr::from([127, 0, 0, 1]);
+    assert_eq!(authenticate(&open, loopback, &parameters(&[])), Ok(Client::Trusted));
+    assert!(authenticate(&open, lan, &parameters(&[])).is_err());
+    assert!(matches!(
+      authenticate(&open, lan, &parameters(&[("u", "me"), ("p", "anything")])),
+      Err(ApiError::NotAuthorized(_))
+    ));
+  }
+
+  #[test]
+  fn test_subsonic_library() -> color_eyre::Result<()> {
+    let mut database = setup_database()?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    let mut song_ids = vec![];
+    for (title, disc_number) in [("Ghost", 2), ("Stellar Stellar", 1)] {
+      let file_id = database.insert_file(NewFile { relative_path: format!("{title}.opus") })?;
+      let song_id = database.insert_song(NewSong {
+        title: title.to_string(),
+        file_id: Some(file_id),
+        disc_number: Some(disc_number),
+        track_number: Some(1),
+        ..Default::default()
+      })?;
+      database.insert_song_artist(SongArtist { song_id, artist_id })?;
+      database.insert_song_album(SongAlbum { song_id, album_id })?;
+      song_ids.push(song_id.to_string());
+    }
+    // songs without a file can not be streamed
+    let bluerose = database.insert_song(NewSong { title: "Bluerose".to_string(), ..Default::default() })?;
+    let library = Library::load(&mut database, &Default::default(), &Selection::Everything)?;
+    let music_dir = std::env::temp_dir();
+
+    let artists = library.answer("getArtists", &parameters(&[]), &music_dir)?;
+    assert_eq!(artists["artists"]["index"][0]["name"], "H");
+    assert_eq!(artists["artists"]["index"][0]["artist"][0]["albumCount"], 1);
+
+    let album = library.answer("getAlbum", &parameters(&[("id", &format!("al-{album_id}"))]), &music_dir)?;
+    assert_eq!(album["album"]["songCount"], 2);
+    assert_eq!(album["album"]["song"][0]["title"], "Stellar Stellar");
+    assert_eq!(album["album"]["song"][1]["discNumber"], 2);
+    assert_eq!(album["album"]["artist"], "Hoshimachi Suisei");
+
+    let search = library.answer("search3", &parameters(&[("query", "stellar")]), &music_dir)?;
+    assert_eq!(search["song"].as_array().map(Vec::len), None);
+    assert_eq!(search["searchResult3"]["song"].as_array().map(Vec::len), Some(1));
+    assert_eq!(search["searchResult3"]["album"][0]["name"], "Still Still Stellar");
+    let everything = library.answer("search3", &parameters(&[("query", "\"\"")]), &music_dir)?;
+    assert_eq!(everything["searchResult3"]["song"].as_array().map(Vec::len), Some(2));
+
+    let playlists = library.answer("getPlaylists", &parameters(&[]), &music_dir)?;
+    assert_eq!(playlists["playlists"]["playlist"][0]["name"], "Artist - Hoshimachi Suisei");
+    assert!(matches!(
+      library.answer("getAlbum", &parameters(&[("id", "al-99")]), &music_dir),
+      Err(ApiError::NotFound(_))
+    ));
+
+    // an album or a song is answered from its songs alone
+    let collation = Default::default();
+    let album_parameters = parameters(&[("id", &format!("al-{album_id}"))]);
+    let selection = Selection::of(&mut database, "getAlbum", &album_parameters)?;
+    assert!(matches!(&selection, Selection::Songs(song_ids) if song_ids.len() == 2));
+    assert_eq!(library_answer(&mut database, &collation, "getAlbum", &album_parameters, &music_dir)?, album);
+    let artist_parameters = parameters(&[("id", &format!("ar-{artist_id}"))]);
+    let artist = library_answer(&mut database, &collation, "getArtist", &artist_parameters, &music_dir)?;
+    assert_eq!(artist["artist"]["album"][0]["songCount"], 2);
+    let song = library_answer(&mut database, &collation, "getSong", &parameters(&[("id", &song_ids[0])]), &music_dir)?;
+    assert_eq!(song["song"]["album"], "Still Still Stellar");
+    let bluerose = parameters(&[("id", &bluerose.to_string())]);
+    assert!(matches!(
+      library_answer(&mut database, &collation, "getSong", &bluerose, &music_dir),
+      Err(ApiError::NotFound(_))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn test_subsonic_xml() {
+    let response =
+      envelope(Ok(json!({ "artists": { "index": [{ "name": "H", "artist": [{ "id": "ar-1", "name": "A&B" }] }] } })));
+    let mut xml = String::new();
+    write_xml("subsonic-response", &response, &mut xml);
+    assert_eq!(
+      xml,
+      r#"<subsonic-response openSubsonic="true" serverVersion="0.1.0" status="ok" type="muzik" version="1.16.1"><artists><index name="H"><artist id="ar-1" name="A&amp;B"/></index></artists></subsonic-response>"#
+    );
+    assert_eq!(envelope(Err(ApiError::NotFound("song")))["error"], json!({ "code": 70, "message": "song not found" }));
+  }
+}
@@ -0,0 +1,166 @@
+//! Tags of song files
+//!
+//! The metadata of the library is written into the tags of the song files, ID3 or Vorbis comments
+//! depending on their format, so that other players show the same title, artists, album and
+//! genres. Songs are tagged once downloaded, and the songs picked in the Manager are tagged again
+//! to push the edits of their metadata into their files. The cover kept in the cache dir is
+//! embedded along with the tags when the art is to be embedded.
+
+use std::path::Path;
+
+use color_eyre::eyre::Result;
+
+use crate::{
+  art, cache,
+  config::{ArtStore, Config},
+  database::Database,
+  jobs::JobContext,
+  models::SongField,
+  postprocess,
+  queue::DownloadRequest,
+};
+
+/// The metadata written into the tags of a song file
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct SongTags {
+  pub title: String,
+  pub artists: Vec<String>,
+  pub album: Option<String>,
+  pub genres: Vec<String>,
+  pub disc_number: Option<i32>,
+  pub track_number: Option<i32>,
+}
+
+impl SongTags {
+  /// The tags of a song of the library
+  pub fn of_song(database: &mut Database, song_id: i32) -> Result<Self> {
+    let mut tags = SongTags::default();
+    for (field, value) in database.get_song_field_values(song_id)? {
+      let Some(value) = value else {
+        continue;
+      };
+      match field {
+        SongField::Title => tags.title = value,
+        SongField::Artists => tags.artists = value.lines().map(str::to_string).collect(),
+        SongField::Album => tags.album = Some(value),
+        SongField::Genres => tags.genres = value.lines().map(str::to_string).collect(),
+        SongField::DiscNumber => tags.disc_number = value.parse().ok(),
+        SongField::TrackNumber => tags.track_number = value.parse().ok(),
+        SongField::YoutubeId | SongField::ThumbnailUrl => {},
+      }
+    }
+    Ok(tags)
+  }
+
+  /// The tags as the metadata keys of ffmpeg and their values, the tags without a value left out
+  pub fn pairs(&self) -> Vec<(&'static str, String)> {
+    let mut pairs = vec![("title", self.title.clone()), ("artist", self.artists.join(", "))];
+    pairs.extend(self.album.clone().map(|album| ("album", album)));
+    if !self.genres.is_empty() {
+      pairs.push(("genre", self.genres.join(", ")));
+    }
+    pairs.extend(self.disc_number.map(|disc| ("disc", disc.to_string())));
+    pairs.extend(self.track_number.map(|track| ("track", track.to_string())));
+    pairs
+  }
+}
+
+impl From<&DownloadRequest> for SongTags {
+  fn from(request: &DownloadRequest) -> Self {
+    Self {
+      title: request.title.clone(),
+      artists: request.artists.clone(),
+      album: request.album.clone(),
+      genres: request.genres.clone(),
+      disc_number: request.disc_number,
+      track_number: request.track_number,
+    }
+  }
+}
+
+/// Write the tags into the audio file in place, along with the cover if given
+pub async fn tag_file(path: &Path, tags: &SongTags, cover: Option<&Path>) -> Result<()> {
+  postprocess::write_tags(path, &tags.pairs()).await?;
+  if let Some(cover) = cover {
+    postprocess::embed_cover(path, cover).await?;
+  }
+  Ok(())
+}
+
+/// Write the metadata of the song in the library into the tags of its file
+///
+/// # Returns
+///
+/// * whether the song has a file to tag
+pub async fn tag_song(config: &Config, database: &mut Database, song_id: i32) -> Result<bool> {
+  let Some(relative_path) = database.get_song_file(song_id)? else {
+    return Ok(false);
+  };
+  let song = database.get_song_from_id(song_id)?;
+  let cover = match config.config.download.art.store.contains(&ArtStore::Embedded) {
+    true => song.youtube_id.map(|youtube_id| art::cache_path(&config.paths, &youtube_id)).filter(|path| path.exists()),
+    false => None,
+  };
+  if let Some(cover) = &cover {
+    cache::touch(cover);
+  }
+  let tags = SongTags::of_song(database, song_id)?;
+  tag_file(&config.config.music_dir.join(relative_path), &tags, cover.as_deref()).await?;
+  Ok(true)
+}
+
+/// Write the metadata of the songs into the tags of their files, to be run as a job
+pub async fn tag_songs(config: Config, song_ids: Vec<i32>, context: JobContext) -> Result<()> {
+  let mut database = Database::new(config.clone()).await?;
+  let total = song_ids.len() as u64;
+  let mut tagged = 0;
+  for (index, song_id) in song_ids.into_iter().enumerate() {
+    match tag_song(&config, &mut database, song_id).await {
+      Ok(true) => tagged += 1,
+      Ok(false) => context.log(format!("song {song_id} has no file to tag")),
+      Err(e) => context.log(format!("failed to tag song {song_id}: {e}")),
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  context.log(format!("tagged {tagged} of {total} songs"));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewArtist, NewGenre, NewSong, SongAlbum, SongArtist, SongGenre},
+  };
+
+  #[test]
+  fn test_song_tags() -> Result<()> {
+    let mut database = setup_database()?;
+    let song_id = database.insert_song(NewSong {
+      title: "Stellar Stellar".to_string(),
+      track_number: Some(1),
+      ..Default::default()
+    })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id, artist_id })?;
+    let album_id = database.insert_album(NewAlbum { name: "Still Still Stellar".to_string() })?;
+    database.insert_song_album(SongAlbum { song_id, album_id })?;
+    for name in ["J-Pop", "Vtuber"] {
+      let genre_id = database.insert_genre(NewGenre { name: name.to_string() })?;
+      database.insert_song_genre(SongGenre { song_id, genre_id })?;
+    }
+
+    let tags = SongTags::of_song(&mut database, song_id)?;
+    assert_eq!(tags.pairs(), vec![
+      ("title", "Stellar Stellar".to_string()),
+      ("artist", "Hoshimachi Suisei".to_string()),
+      ("album", "Still Still Stellar".to_string()),
+      ("genre", "J-Pop, Vtuber".to_string()),
+      ("track", "1".to_string()),
+    ]);
+    Ok(())
+  }
+}
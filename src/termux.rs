@@ -0,0 +1,81 @@
+//! The Termux profile
+//!
+//! On Android muzik runs in Termux. The profile is on when Termux is detected, and always in builds
+//! with the `termux` feature. The TUI ticks and draws less often to spare the battery, and the
+//! shared storage the songs go to is checked before muzik starts, telling how to fix it. The
+//! layouts stack their panes on narrow screens whatever the profile.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{
+  config::Config,
+  paths::{Environment, Platform, MUSIC_VAR},
+};
+
+/// The ticks per second of the TUI, enough for the queue and the player to keep up
+pub const TICK_RATE: f64 = 1.0;
+/// The frames per second of the TUI
+pub const FRAME_RATE: f64 = 8.0;
+
+/// Whether muzik runs in Termux
+pub fn is_active() -> bool {
+  Environment::current().platform == Platform::Termux
+}
+
+/// Check that the songs can be written to the music dir, if muzik runs in Termux
+pub fn check(config: &Config) -> Result<()> {
+  let environment = Environment::current();
+  match (environment.platform, environment.home) {
+    (Platform::Termux, Some(home)) => check_storage(&config.paths.music_dir, &home.join("storage")),
+    _ => Ok(()),
+  }
+}
+
+/// Check that the music dir can be written, with the steps to allow it if not
+///
+/// # Arguments
+///
+/// * `storage` - the links to the shared storage made by `termux-setup-storage`
+pub fn check_storage(music_dir: &Path, storage: &Path) -> Result<()> {
+  if music_dir.starts_with(storage) && !storage.exists() {
+    return Err(eyre!(
+      "the shared storage is not set up: run `termux-setup-storage` and allow the storage permission, then start \
+       muzik again. To keep the songs inside Termux instead, set {MUSIC_VAR} to a directory in its home"
+    ));
+  }
+  let probe = music_dir.join(".muzik-write-test");
+  std::fs::create_dir_all(music_dir).and_then(|_| std::fs::write(&probe, "")).map_err(|e| {
+    eyre!(
+      "Termux may not write to {}: {e}. Allow the storage permission of Termux in the settings of Android, then run \
+       `termux-setup-storage` again",
+      music_dir.display()
+    )
+  })?;
+  let _ = std::fs::remove_file(probe);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_check_storage() -> Result<()> {
+    let home = std::env::temp_dir().join(format!("muzik-termux-{}", uuid::Uuid::new_v4()));
+    let storage = home.join("storage");
+    let music_dir = storage.join("music");
+    let error = check_storage(&music_dir, &storage).unwrap_err();
+    assert!(error.to_string().contains("termux-setup-storage"));
+
+    std::fs::create_dir_all(&storage)?;
+    check_storage(&music_dir, &storage)?;
+    assert!(music_dir.is_dir());
+    assert!(!music_dir.join(".muzik-write-test").exists());
+    // a music dir outside of the shared storage does not need it
+    check_storage(&home.join("music"), &home.join("elsewhere"))?;
+    std::fs::remove_dir_all(&home)?;
+    Ok(())
+  }
+}
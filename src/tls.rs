@@ -0,0 +1,192 @@
+//! TLS for the clients of `muzik daemon` on other machines
+//!
+//! The daemon serves its remote listener over TLS with the configured certificate, or with a
+//! self-signed one generated with `openssl` on first start. As nobody signs the certificate of a
+//! personal daemon, clients are given a copy of it and trust the daemon presenting exactly that
+//! certificate, instead of checking it against certificate authorities.
+
+use std::{
+  io::BufReader,
+  path::{Path, PathBuf},
+  process::Command,
+  sync::Arc,
+};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio_rustls::{
+  rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, ring, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, Error, ServerConfig, SignatureScheme,
+  },
+  TlsAcceptor, TlsConnector,
+};
+
+use crate::config::{Config, TlsConfig};
+
+/// The name the generated certificate is issued to
+const SELF_SIGNED_NAME: &str = "muzik";
+const SELF_SIGNED_DAYS: &str = "3650";
+
+/// The certificate and the key the daemon serves, generating them if none are configured
+///
+/// # Returns
+///
+/// * the paths of the certificate and the key
+pub fn certificate_paths(config: &Config) -> Result<(PathBuf, PathBuf)> {
+  let tls = &config.config.daemon.tls;
+  match (&tls.certificate, &tls.key) {
+    (Some(certificate), Some(key)) => Ok((certificate.clone(), key.clone())),
+    (None, None) => {
+      let directory = config.paths.data_dir.join("tls");
+      let paths = (directory.join("certificate.pem"), directory.join("key.pem"));
+      if !paths.0.exists() || !paths.1.exists() {
+        std::fs::create_dir_all(&directory)?;
+        generate_self_signed(&paths.0, &paths.1)?;
+      }
+      Ok(paths)
+    },
+    _ => Err(eyre!("daemon.tls needs both a certificate and a key")),
+  }
+}
+
+/// Write a new self-signed certificate and its key
+fn generate_self_signed(certificate: &Path, key: &Path) -> Result<()> {
+  let output = Command::new("openssl")
+    .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes"])
+    .args(["-days", SELF_SIGNED_DAYS, "-subj", &format!("/CN={SELF_SIGNED_NAME}")])
+    .arg("-keyout")
+    .arg(key)
+    .arg("-out")
+    .arg(certificate)
+    .output()
+    .map_err(|e| eyre!("failed to run openssl to generate a certificate: {e}"))?;
+  if !output.status.success() {
+    return Err(eyre!("openssl failed to generate a certificate: {}", String::from_utf8_lossy(&output.stderr).trim()));
+  }
+  Ok(())
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+  let mut reader = BufReader::new(std::fs::File::open(path)?);
+  let certificates = rustls_pemfile::certs(&mut reader).collect::<std::io::Result<Vec<_>>>()?;
+  if certificates.is_empty() {
+    return Err(eyre!("no certificate in {}", path.display()));
+  }
+  Ok(certificates)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+  let mut reader = BufReader::new(std::fs::File::open(path)?);
+  rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| eyre!("no private key in {}", path.display()))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+  Arc::new(ring::default_provider())
+}
+
+/// Accepts the TLS connections of clients, `None` if TLS is disabled
+pub fn acceptor(tls: &TlsConfig, certificate: &Path, key: &Path) -> Result<Option<TlsAcceptor>> {
+  if !tls.enabled {
+    return Ok(None);
+  }
+  let config = ServerConfig::builder_with_provider(provider())
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(load_certificates(certificate)?, load_key(key)?)?;
+  Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// Connects to a daemon presenting the certificate in the file
+pub fn connector(certificate: &Path) -> Result<TlsConnector> {
+  let provider = provider();
+  let verifier =
+    PinnedCertificate { certificate: load_certificates(certificate)?.remove(0), provider: provider.clone() };
+  let config = ClientConfig::builder_with_provider(provider)
+    .with_safe_default_protocol_versions()?
+    .dangerous()
+    .with_custom_certificate_verifier(Arc::new(verifier))
+    .with_no_client_auth();
+  Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// The name to connect to `address` with. The name is not checked against the certificate, but
+/// has to be valid
+pub fn server_name(address: &str) -> ServerName<'static> {
+  let host = address.rsplit_once(':').map_or(address, |(host, _)| host).trim_start_matches('[').trim_end_matches(']');
+  ServerName::try_from(host.to_string())
+    .unwrap_or_else(|_| ServerName::try_from(SELF_SIGNED_NAME).expect("a valid name").to_owned())
+}
+
+/// Trusts the server presenting exactly the certificate, whoever issued it
+#[derive(Debug)]
+struct PinnedCertificate {
+  certificate: CertificateDer<'static>,
+  provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>,
+    _ocsp_response: &[u8],
+    _now: UnixTime,
+  ) -> Result<ServerCertVerified, Error> {
+    if end_entity.as_ref() == self.certificate.as_ref() {
+      Ok(ServerCertVerified::assertion())
+    } else {
+      Err(Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+    }
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    certificate: &CertificateDer<'_>,
+    signature: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    crypto::verify_tls12_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    certificate: &CertificateDer<'_>,
+    signature: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    crypto::verify_tls13_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.provider.signature_verification_algorithms.supported_schemes()
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  /// A self-signed certificate and its key in a new directory
+  pub fn generate_certificate() -> Result<(PathBuf, PathBuf)> {
+    let directory = std::env::temp_dir().join(format!("muzik-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory)?;
+    let paths = (directory.join("certificate.pem"), directory.join("key.pem"));
+    generate_self_signed(&paths.0, &paths.1)?;
+    Ok(paths)
+  }
+
+  #[test]
+  fn test_tls_certificates() -> Result<()> {
+    let (certificate, key) = generate_certificate()?;
+    assert!(acceptor(&TlsConfig::default(), &certificate, &key)?.is_some());
+    assert!(acceptor(&TlsConfig { enabled: false, ..Default::default() }, &certificate, &key)?.is_none());
+    assert!(connector(&key).is_err());
+    assert!(matches!(server_name("192.168.1.2:7700"), ServerName::IpAddress(_)));
+    assert!(matches!(server_name("[::1]:7700"), ServerName::IpAddress(_)));
+    assert!(matches!(server_name("desktop:7700"), ServerName::DnsName(_)));
+    Ok(())
+  }
+}
@@ -0,0 +1,195 @@
+//! Trimming downloaded songs
+//!
+//! Concert rips often come with long intros and outros. The part of a song between a start and an
+//! end is rendered with ffmpeg into a new file, added to the library as a trimmed version of the
+//! song. The original file is left as it is.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{
+  config::Config,
+  database::Database,
+  jobs::JobContext,
+  models::{NewFile, NewSong, SongAlbum, SongArtist, SongVersion, VersionKind},
+  postprocess, scan,
+};
+
+/// A downloaded song to be trimmed, as shown in the trim view
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrimSong {
+  pub song_id: i32,
+  pub title: String,
+  pub duration: Duration,
+}
+
+/// The part of a song to keep
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrimRange {
+  pub song_id: i32,
+  pub start: Duration,
+  pub end: Duration,
+}
+
+impl TrimRange {
+  /// Check that the range keeps something of the song
+  pub fn validate(&self, duration: Duration) -> Result<()> {
+    if self.start >= self.end {
+      return Err(eyre!("the start must come before the end"));
+    }
+    if self.end > duration {
+      return Err(eyre!("the end is past the end of the song"));
+    }
+    if self.start.is_zero() && self.end == duration {
+      return Err(eyre!("nothing would be trimmed"));
+    }
+    Ok(())
+  }
+}
+
+/// The title of the song to trim and the path of its file from the music dir
+pub fn song_file(database: &mut Database, song_id: i32) -> Result<(String, String)> {
+  let song = database.get_song_from_id(song_id)?;
+  let relative_path = database.get_song_file(song_id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+  Ok((song.title, relative_path))
+}
+
+/// Load the song to trim, reading its duration from its file
+pub async fn load_song(music_dir: &Path, song_id: i32, title: String, relative_path: &str) -> Result<TrimSong> {
+  let tags = scan::probe(&music_dir.join(relative_path)).await.map_err(|outcome| eyre!("{outcome}"))?;
+  let duration = tags.duration.ok_or_else(|| eyre!("the length of {title} is unknown"))?;
+  Ok(TrimSong { song_id, title, duration })
+}
+
+/// The path from the music dir to render the trimmed song to, numbered when the name is taken
+pub fn trimmed_path(music_dir: &Path, relative_path: &str) -> String {
+  let path = Path::new(relative_path);
+  let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+  let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+  let directory = path.parent().unwrap_or(Path::new(""));
+  let mut trimmed = directory.join(format!("{stem}-trimmed{extension}"));
+  let mut number = 2;
+  while music_dir.join(&trimmed).exists() {
+    trimmed = directory.join(format!("{stem}-trimmed-{number}{extension}"));
+    number += 1;
+  }
+  trimmed.to_string_lossy().to_string()
+}
+
+/// Add the rendered file to the library as a trimmed version of the song, with its artists and
+/// albums. The title is marked so that the two are not taken for duplicates
+///
+/// # Returns
+///
+/// * the id of the trimmed song
+pub fn add_trimmed_song(database: &mut Database, original_id: i32, relative_path: String) -> Result<i32> {
+  let original = database.get_song_from_id(original_id)?;
+  let file_id = database.insert_file(NewFile { relative_path })?;
+  let song_id = database.insert_song(NewSong {
+    title: format!("{} (Trimmed)", original.title),
+    thumbnail_url: original.thumbnail_url,
+    file_id: Some(file_id),
+    disc_number: original.disc_number,
+    track_number: original.track_number,
+    ..Default::default()
+  })?;
+  for (id, artist) in database.get_song_artists()? {
+    if id == original_id {
+      database.insert_song_artist(SongArtist { song_id, artist_id: artist.id })?;
+    }
+  }
+  for (id, album) in database.get_song_albums()? {
+    if id == original_id {
+      database.insert_song_album(SongAlbum { song_id, album_id: album.id })?;
+    }
+  }
+  database.link_version(SongVersion { song_id, original_id, kind: VersionKind::Trimmed })?;
+  Ok(song_id)
+}
+
+/// Render the range of the song into a new file and add it to the library, to be run as a job
+pub async fn render_trim(config: Config, range: TrimRange, context: JobContext) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let (title, relative_path) = song_file(&mut database, range.song_id)?;
+  let song = load_song(&music_dir, range.song_id, title, &relative_path).await?;
+  range.validate(song.duration)?;
+  let trimmed = trimmed_path(&music_dir, &relative_path);
+  context.log(format!("rendering {} to {trimmed}", song.title));
+
+  let artists = database
+    .get_song_artist_names()?
+    .into_iter()
+    .filter(|(id, _)| *id == range.song_id)
+    .map(|(_, name)| name)
+    .collect::<Vec<_>>();
+  let tags = [("title", format!("{} (Trimmed)", song.title)), ("artist", artists.join(", "))];
+  postprocess::cut(&music_dir.join(&relative_path), &music_dir.join(&trimmed), range.start, Some(range.end), &tags)
+    .await?;
+  match add_trimmed_song(&mut database, range.song_id, trimmed.clone()) {
+    Ok(song_id) => {
+      context.log(format!("added the trimmed version as song {song_id}"));
+      if let Err(e) = scan::record_quality(&mut database, &music_dir, &trimmed).await {
+        context.log(format!("failed to read the quality of {trimmed}: {e}"));
+      }
+    },
+    Err(e) => {
+      let _ = std::fs::remove_file(music_dir.join(&trimmed));
+      return Err(e);
+    },
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewAlbum, NewArtist},
+  };
+
+  #[test]
+  fn test_trim_range() {
+    let duration = Duration::from_secs(300);
+    let range = |start, end| TrimRange { song_id: 1, start: Duration::from_secs(start), end: Duration::from_secs(end) };
+    assert!(range(30, 280).validate(duration).is_ok());
+    assert!(range(0, 280).validate(duration).is_ok());
+    assert!(range(280, 30).validate(duration).is_err());
+    assert!(range(30, 301).validate(duration).is_err());
+    assert!(range(0, 300).validate(duration).is_err());
+  }
+
+  #[test]
+  fn test_add_trimmed_song() -> Result<()> {
+    let mut database = setup_database()?;
+    let file_id = database.insert_file(NewFile { relative_path: "a51VH9BYzZA.opus".to_string() })?;
+    let original = database.insert_song(NewSong {
+      title: "Stellar Stellar (Live)".to_string(),
+      youtube_id: Some("a51VH9BYzZA".to_string()),
+      file_id: Some(file_id),
+      ..Default::default()
+    })?;
+    let artist_id = database.insert_artist(NewArtist { name: "Hoshimachi Suisei".to_string() })?;
+    database.insert_song_artist(SongArtist { song_id: original, artist_id })?;
+    let album_id = database.insert_album(NewAlbum { name: "Stellar into the Galaxy".to_string() })?;
+    database.insert_song_album(SongAlbum { song_id: original, album_id })?;
+
+    assert_eq!(trimmed_path(&std::env::temp_dir(), "Live/a51VH9BYzZA.opus"), "Live/a51VH9BYzZA-trimmed.opus");
+    let song_id = add_trimmed_song(&mut database, original, "a51VH9BYzZA-trimmed.opus".to_string())?;
+    let song = database.get_song_from_id(song_id)?;
+    assert_eq!(song.title, "Stellar Stellar (Live) (Trimmed)");
+    assert_eq!(song.youtube_id, None);
+    assert_eq!(database.get_song_file(song_id)?, Some("a51VH9BYzZA-trimmed.opus".to_string()));
+    assert_eq!(database.get_song_artist_names()?.into_iter().filter(|(id, _)| *id == song_id).count(), 1);
+    assert_eq!(database.get_song_albums()?.into_iter().filter(|(id, _)| *id == song_id).count(), 1);
+    assert_eq!(
+      database.get_original(song_id)?.map(|(song, kind)| (song.id, kind)),
+      Some((original, VersionKind::Trimmed))
+    );
+    Ok(())
+  }
+}
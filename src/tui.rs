@@ -1,9 +1,10 @@
 use std::{
   ops::{Deref, DerefMut},
+  path::Path,
   time::Duration,
 };
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use crossterm::{
   cursor,
   event::{
@@ -210,6 +211,28 @@ impl Tui {
     Ok(())
   }
 
+  /// Leave the terminal to an interactive `$SHELL` until it exits. Unlike `suspend`, the process
+  /// keeps running so background tasks carry on while the shell is open
+  pub async fn shell(&mut self) -> Result<()> {
+    self.exit()?;
+    let shell = std::env::var("SHELL").unwrap_or("sh".to_string());
+    eprintln!("muzik is running in the background. Exit the shell to return.");
+    tokio::process::Command::new(shell).status().await?;
+    Ok(())
+  }
+
+  /// Leave the terminal to the editor until it exits, editing the file. The editor is run by the
+  /// shell, as it can come with arguments such as `code --wait`
+  pub async fn edit(&mut self, editor: &str, path: &Path) -> Result<()> {
+    self.exit()?;
+    let status =
+      tokio::process::Command::new("sh").arg("-c").arg(format!("{editor} \"$1\"")).arg("sh").arg(path).status().await?;
+    match status.success() {
+      true => Ok(()),
+      false => Err(eyre!("{editor} exited with {status}")),
+    }
+  }
+
   pub fn resume(&mut self) -> Result<()> {
     self.enter()?;
     Ok(())
@@ -0,0 +1,302 @@
+//! Better versions of low quality songs
+//!
+//! A song whose file is below the `min_bitrate` of the `upgrade` settings is searched for on
+//! YouTube again. The uploads with better audio than the file are offered, as long as their length
+//! is close to the length of the file so that a live version or an extended mix is not taken for
+//! the song. The picked upload then replaces the file, keeping the song along with its metadata,
+//! plays and rating.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+use youtube_dl::{SearchOptions, SingleVideo, YoutubeDl};
+
+use crate::{
+  action::Action,
+  config::Config,
+  database::Database,
+  download_logs,
+  import::{
+    matching::{self, Candidate, UNCERTAIN_SCORE},
+    ImportTrack,
+  },
+  jobs::JobContext,
+  models::MetadataSource,
+  postprocess,
+  quality::AudioQuality,
+  queue::{self, DownloadRequest},
+  scan,
+};
+
+/// The number of search results considered
+const CANDIDATES: usize = 10;
+
+/// An upload with better audio than the file of a song
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Upgrade {
+  pub youtube_id: String,
+  pub title: String,
+  pub channel: Option<String>,
+  /// of the best audio of the upload, in kbit/s
+  pub bitrate: u32,
+  pub duration: Option<Duration>,
+  /// how well the upload matches the song, from 0 to 100
+  pub score: u8,
+}
+
+/// The uploads found for a low quality song, best first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpgradeOffer {
+  pub song_id: i32,
+  pub title: String,
+  pub quality: Option<AudioQuality>,
+  pub duration: Option<Duration>,
+  pub upgrades: Vec<Upgrade>,
+}
+
+/// The bitrate of the best audio of the video in kbit/s, from its audio formats
+fn best_audio_bitrate(video: &SingleVideo) -> Option<u32> {
+  video
+    .formats
+    .iter()
+    .flatten()
+    .filter(|format| format.acodec.as_deref().is_some_and(|acodec| acodec != "none"))
+    .filter_map(|format| format.abr)
+    .chain(video.abr)
+    .max_by(f64::total_cmp)
+    .map(|bitrate| bitrate.round() as u32)
+}
+
+/// The uploads with better audio than the file whose length is within the tolerance of the file,
+/// best audio first
+pub fn pick_upgrades(
+  track: &ImportTrack,
+  quality: Option<&AudioQuality>,
+  tolerance: Duration,
+  videos: Vec<SingleVideo>,
+) -> Vec<Upgrade> {
+  let current = quality.and_then(|quality| quality.bitrate).unwrap_or_default();
+  let mut upgrades: Vec<_> = videos
+    .into_iter()
+    .filter_map(|video| {
+      let bitrate = best_audio_bitrate(&video).filter(|bitrate| *bitrate > current)?;
+      let candidate = Candidate::from(video);
+      let duration = candidate.duration.map(Duration::from_secs);
+      if let (Some(expected), Some(actual)) = (track.duration, candidate.duration) {
+        if Duration::from_secs(expected.abs_diff(actual)) > tolerance {
+          return None;
+        }
+      }
+      let score = matching::score(track, &candidate);
+      (score >= UNCERTAIN_SCORE).then_some(Upgrade {
+        youtube_id: candidate.youtube_id,
+        title: candidate.title,
+        channel: candidate.channel,
+        bitrate,
+        duration,
+        score,
+      })
+    })
+    .collect();
+  upgrades.sort_by_key(|upgrade| std::cmp::Reverse((upgrade.bitrate, upgrade.score)));
+  upgrades
+}
+
+/// The song to upgrade as a track to search for, with the length and quality of its file
+async fn load_track(
+  database: &mut Database,
+  music_dir: &Path,
+  song_id: i32,
+) -> Result<(ImportTrack, Option<AudioQuality>)> {
+  let song = database.get_song_from_id(song_id)?;
+  let relative_path = database.get_song_file(song_id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+  let tags = scan::probe(&music_dir.join(relative_path)).await.map_err(|outcome| eyre!("{outcome}"))?;
+  let artists =
+    database.get_song_artist_names()?.into_iter().filter(|(id, _)| *id == song_id).map(|(_, name)| name).collect();
+  let quality = database.get_song_quality(song_id)?.or(tags.quality);
+  let track = ImportTrack {
+    title: song.title,
+    artists,
+    duration: tags.duration.map(|duration| duration.as_secs_f64().round() as u64),
+    ..Default::default()
+  };
+  Ok((track, quality))
+}
+
+/// Search for better uploads of the song and send them to be picked from, to be run as a job
+pub async fn find_upgrades(
+  config: Config,
+  song_id: i32,
+  action_tx: UnboundedSender<Action>,
+  context: JobContext,
+) -> Result<()> {
+  let settings = config.config.upgrade.clone();
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let (track, quality) = load_track(&mut database, &music_dir, song_id).await?;
+  if let Some(quality) = &quality {
+    if quality.is_lossless() || quality.bitrate.is_some_and(|bitrate| bitrate >= settings.min_bitrate) {
+      return Err(eyre!("{} is {quality} already, at or above {} kbit/s", track.title, settings.min_bitrate));
+    }
+  }
+
+  let query = track.search_query();
+  context.log(format!("searching for \"{query}\""));
+  let output = YoutubeDl::search_for(&SearchOptions::youtube(query).with_count(CANDIDATES)).run_async().await?;
+  let videos = output
+    .into_playlist()
+    .and_then(|playlist| playlist.entries)
+    .ok_or_else(|| eyre!("youtube search did not return a playlist"))?;
+  let tolerance = Duration::from_secs(settings.max_duration_difference_seconds);
+  let upgrades = pick_upgrades(&track, quality.as_ref(), tolerance, videos);
+  if upgrades.is_empty() {
+    return Err(eyre!("no better upload of {} found", track.title));
+  }
+  context.log(format!("found {} better uploads", upgrades.len()));
+  action_tx.send(Action::UpgradeOffer(UpgradeOffer {
+    song_id,
+    title: track.title,
+    quality,
+    duration: track.duration.map(Duration::from_secs),
+    upgrades,
+  }))?;
+  Ok(())
+}
+
+/// Put the downloaded file in place of the file of the song, next to the old file and named after
+/// the video like other downloads. The song is linked to the video of the request, and the old
+/// file is deleted once the new one is in place
+///
+/// # Arguments
+///
+/// * `relative_path` - the path of the old file from the music dir
+/// * `source` - the downloaded file
+/// * `request` - the download, whose metadata the new file is tagged with
+///
+/// # Returns
+///
+/// * the path of the new file from the music dir
+pub async fn swap_file(
+  music_dir: &Path,
+  database: &mut Database,
+  song_id: i32,
+  relative_path: &str,
+  source: &Path,
+  request: &DownloadRequest,
+) -> Result<String> {
+  let extension = source.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+  let new_path = Path::new(relative_path)
+    .with_file_name(format!("{}.{extension}", request.youtube_id))
+    .to_string_lossy()
+    .to_string();
+  if new_path != relative_path && music_dir.join(&new_path).exists() {
+    return Err(eyre!("{new_path} is in the music dir already"));
+  }
+  std::fs::copy(source, music_dir.join(&new_path))?;
+  postprocess::write_tags(&music_dir.join(&new_path), &request.tags()).await?;
+
+  database.move_file(relative_path, &new_path)?;
+  database.relink_song(song_id, &request.youtube_id, request.source)?;
+  if new_path != relative_path {
+    if let Err(e) = std::fs::remove_file(music_dir.join(relative_path)) {
+      warn!("failed to delete the old file {relative_path}: {e}");
+    }
+  }
+  if let Err(e) = scan::record_quality(database, music_dir, &new_path).await {
+    warn!("failed to read the quality of {new_path}: {e}");
+  }
+  Ok(new_path)
+}
+
+/// Download the upload and replace the file of the song with it, to be run as a job. The song is
+/// linked to the upload, and the old file is deleted once the new one is in place
+pub async fn replace_file(config: Config, song_id: i32, youtube_id: String, context: JobContext) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config.clone()).await?;
+  let song = database.get_song_from_id(song_id)?;
+  let relative_path = database.get_song_file(song_id)?.ok_or_else(|| eyre!("{} is not downloaded", song.title))?;
+  let artists =
+    database.get_song_artist_names()?.into_iter().filter(|(id, _)| *id == song_id).map(|(_, name)| name).collect();
+  let album = database.get_album_for_song(song_id)?.map(|album| album.name);
+  let genres = database.get_genres_for_song(song_id)?.into_iter().map(|genre| genre.name).collect();
+  let request = DownloadRequest {
+    youtube_id,
+    title: song.title.clone(),
+    artists,
+    album,
+    genres,
+    disc_number: song.disc_number,
+    track_number: song.track_number,
+    source: MetadataSource::Manual,
+    ..Default::default()
+  };
+
+  context.log(format!("downloading {}", request.url()));
+  let directory = std::env::temp_dir().join(format!("muzik-upgrade-{}", uuid::Uuid::new_v4()));
+  let result = async {
+    let log_dir = download_logs::log_dir(&config);
+    let options = queue::DownloadOptions {
+      comments: false,
+      formats: &config.config.download.formats,
+      log_dir: &log_dir,
+      proxy: None,
+      progress: None,
+    };
+    let downloaded = queue::download_audio(&request, &directory, &options).await?;
+    let new_path = swap_file(&music_dir, &mut database, song_id, &relative_path, &downloaded.path, &request).await?;
+    if let Some(file_id) = song.file_id {
+      database.set_download_params(&options.params(file_id, &downloaded, false))?;
+    }
+    Ok::<_, color_eyre::eyre::Report>((new_path, downloaded.format))
+  }
+  .await;
+  let _ = std::fs::remove_dir_all(&directory);
+  let (new_path, format) = result?;
+  context.log(format!("replaced {relative_path} with {new_path} ({format})"));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use youtube_dl::model::Format;
+
+  use super::*;
+
+  #[test]
+  fn test_pick_upgrades() {
+    let video = |id: &str, title: &str, seconds: u64, bitrates: &[f64]| {
+      SingleVideo {
+        id: id.to_string(),
+        title: Some(title.to_string()),
+        duration: Some(seconds.into()),
+        formats: Some(
+          bitrates
+            .iter()
+            .map(|abr| Format { abr: Some(*abr), acodec: Some("opus".to_string()), ..Default::default() })
+            .collect(),
+        ),
+        ..Default::default()
+      }
+    };
+    let track = ImportTrack {
+      title: "Stellar Stellar".to_string(),
+      artists: vec!["Hoshimachi Suisei".to_string()],
+      duration: Some(291),
+      ..Default::default()
+    };
+    let quality = AudioQuality { codec: "mp3".to_string(), bitrate: Some(64) };
+    let videos = vec![
+      video("a51VH9BYzZA", "Hoshimachi Suisei - Stellar Stellar", 292, &[48.0, 129.5]),
+      video("abcdefghijk", "Stellar Stellar (Live)", 340, &[160.0]),
+      video("2lAe1cqCOXo", "Stellar Stellar Hoshimachi Suisei", 291, &[160.0]),
+      video("aaaaaaaaaaa", "Stellar Stellar", 291, &[48.0]),
+    ];
+    let upgrades = pick_upgrades(&track, Some(&quality), Duration::from_secs(5), videos);
+    let picked: Vec<_> = upgrades.iter().map(|upgrade| (upgrade.youtube_id.as_str(), upgrade.bitrate)).collect();
+    assert_eq!(picked, vec![("2lAe1cqCOXo", 160), ("a51VH9BYzZA", 130)]);
+    assert_eq!(upgrades[1].duration, Some(Duration::from_secs(292)));
+  }
+}
@@ -1,28 +1,21 @@
 use std::path::PathBuf;
 
 use color_eyre::eyre::Result;
-use directories::ProjectDirs;
 use lazy_static::lazy_static;
 use tracing::error;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{self, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, Layer};
 
+use crate::paths::Paths;
+
 pub static GIT_COMMIT_HASH: &str = env!("_GIT_INFO");
 
 lazy_static! {
   pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
-  pub static ref DATA_FOLDER: Option<PathBuf> =
-    std::env::var(format!("{}_DATA", PROJECT_NAME.clone())).ok().map(PathBuf::from);
-  pub static ref CONFIG_FOLDER: Option<PathBuf> =
-    std::env::var(format!("{}_CONFIG", PROJECT_NAME.clone())).ok().map(PathBuf::from);
   pub static ref LOG_ENV: String = format!("{}_LOGLEVEL", PROJECT_NAME.clone());
   pub static ref LOG_FILE: String = format!("{}.log", env!("CARGO_PKG_NAME"));
 }
 
-fn project_directory() -> Option<ProjectDirs> {
-  ProjectDirs::from("com", "solemnattic", env!("CARGO_PKG_NAME"))
-}
-
 pub fn initialize_panic_handler() -> Result<()> {
   let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default()
     .panic_section(format!("This is a bug. Consider reporting it at {}", env!("CARGO_PKG_REPOSITORY")))
@@ -32,6 +25,7 @@ pub fn initialize_panic_handler() -> Result<()> {
     .into_hooks();
   eyre_hook.install()?;
   std::panic::set_hook(Box::new(move |panic_info| {
+    #[cfg(feature = "tui")]
     if let Ok(mut t) = crate::tui::Tui::new() {
       if let Err(r) = t.exit() {
         error!("Unable to exit Terminal: {:?}", r);
@@ -71,30 +65,17 @@ pub fn initialize_panic_handler() -> Result<()> {
   Ok(())
 }
 
-pub fn get_data_dir() -> PathBuf {
-  let directory = if let Some(s) = DATA_FOLDER.clone() {
-    s
-  } else if let Some(proj_dirs) = project_directory() {
-    proj_dirs.data_local_dir().to_path_buf()
-  } else {
-    PathBuf::from(".").join(".data")
-  };
-  directory
-}
-
-pub fn get_config_dir() -> PathBuf {
-  let directory = if let Some(s) = CONFIG_FOLDER.clone() {
-    s
-  } else if let Some(proj_dirs) = project_directory() {
-    proj_dirs.config_local_dir().to_path_buf()
-  } else {
-    PathBuf::from(".").join(".config")
-  };
-  directory
+/// Replace the characters that are not allowed in file names, and the leading dots hiding a file
+pub fn safe_file_name(name: &str) -> String {
+  let name: String = name
+    .chars()
+    .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() { '_' } else { c })
+    .collect();
+  name.trim_start_matches('.').to_string()
 }
 
 pub fn initialize_logging() -> Result<()> {
-  let directory = get_data_dir();
+  let directory = Paths::current().data_dir;
   std::fs::create_dir_all(directory.clone())?;
   let log_path = directory.join(LOG_FILE.clone());
   let log_file = std::fs::File::create(log_path)?;
@@ -144,11 +125,12 @@ macro_rules! trace_dbg {
 pub fn version() -> String {
   let author = clap::crate_authors!();
 
-  let commit_hash = GIT_COMMIT_HASH.clone();
+  let commit_hash = GIT_COMMIT_HASH;
 
   // let current_exe_path = PathBuf::from(clap::crate_name!()).display().to_string();
-  let config_dir_path = get_config_dir().display().to_string();
-  let data_dir_path = get_data_dir().display().to_string();
+  let paths = Paths::current();
+  let config_dir_path = paths.config_dir.display().to_string();
+  let data_dir_path = paths.data_dir.display().to_string();
 
   format!(
     "\
@@ -0,0 +1,298 @@
+//! Song integrity verification
+//!
+//! The file of every song is checked: that it is still in the music dir, that ffprobe can read its
+//! headers and, when `verify.decode_seconds` is set, that ffmpeg decodes its first seconds without
+//! errors. The SHA-256 checksum of every file is recorded as it is verified. A file whose checksum
+//! changed since, while it was not written to, is damaged: the files muzik rewrites, such as when
+//! writing their tags, are written to after their verification, so their checksum is recorded
+//! again instead. What is wrong with every file is kept with it and listed in the Integrity view of
+//! the Manager, along with the files gone from the music dir. The songs whose file is gone are
+//! downloaded again from their video, one at a time or all at once.
+
+use std::{
+  collections::HashMap,
+  io::Read,
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+  action::Action,
+  config::{Config, DuplicatePolicy},
+  database::Database,
+  jobs::{JobContext, JobKind, JobRegistry},
+  models::File,
+  postprocess,
+  queue::DownloadRequest,
+  scan,
+};
+
+/// The name of the job verifying the files
+const JOB_NAME: &str = "verify the files of the songs";
+
+/// A song whose file is gone or damaged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityProblem {
+  pub song_id: i32,
+  pub title: String,
+  pub artists: Vec<String>,
+  pub youtube_id: Option<String>,
+  /// path of the file relative to the music dir
+  pub path: String,
+  /// the file is gone rather than damaged
+  pub missing: bool,
+  /// what is wrong with the file
+  pub problem: String,
+}
+
+/// The SHA-256 checksum of the file as hex
+pub fn checksum(path: &Path) -> Result<String> {
+  let mut file = std::fs::File::open(path)?;
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0; 64 * 1024];
+  loop {
+    let read = file.read(&mut buffer)?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+  }
+  Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Whether the file was damaged since it was last verified: its checksum changed while it was not
+/// written to
+fn damaged(file: &File, checksum: &str, modified: Option<SystemTime>) -> bool {
+  let (Some(recorded), Some(verified_at)) = (&file.checksum, file.verified_at) else {
+    return false;
+  };
+  let written_since = modified.is_some_and(|modified| DateTime::<Utc>::from(modified).naive_utc() > verified_at);
+  recorded != checksum && !written_since
+}
+
+/// The songs whose file is gone from the music dir or was found damaged, ordered by title
+pub fn load_problems(database: &mut Database, music_dir: &Path) -> Result<Vec<IntegrityProblem>> {
+  let mut artists: HashMap<i32, Vec<String>> = HashMap::new();
+  for (song_id, name) in database.get_song_artist_names()? {
+    artists.entry(song_id).or_default().push(name);
+  }
+  Ok(
+    database
+      .get_songs_with_file_records()?
+      .into_iter()
+      .filter_map(|(song, file)| {
+        let missing = !music_dir.join(&file.relative_path).is_file();
+        let problem = match missing {
+          true => "the file is gone".to_string(),
+          false => file.problem?,
+        };
+        Some(IntegrityProblem {
+          song_id: song.id,
+          title: song.title,
+          artists: artists.remove(&song.id).unwrap_or_default(),
+          youtube_id: song.youtube_id,
+          path: file.relative_path,
+          missing,
+          problem,
+        })
+      })
+      .collect(),
+  )
+}
+
+/// The songs whose file is gone as requests downloading them again from their video, and the
+/// titles of those that were not downloaded from one
+pub fn missing_requests(database: &mut Database, music_dir: &Path) -> Result<(Vec<DownloadRequest>, Vec<String>)> {
+  let (mut requests, mut without_video) = (vec![], vec![]);
+  for problem in load_problems(database, music_dir)?.into_iter().filter(|problem| problem.missing) {
+    match problem.youtube_id {
+      Some(youtube_id) => {
+        requests.push(DownloadRequest {
+          youtube_id,
+          title: problem.title,
+          artists: problem.artists,
+          // another song of the same title is no reason to leave the song without its file
+          duplicates: Some(DuplicatePolicy::KeepBoth),
+          ..Default::default()
+        });
+      },
+      None => without_video.push(problem.title),
+    }
+  }
+  Ok((requests, without_video))
+}
+
+/// Queue the songs whose file is gone to be downloaded again from their video, which links them to
+/// the new file, to be run as a job
+pub async fn redownload_missing(config: Config, action_tx: UnboundedSender<Action>, context: JobContext) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let mut database = Database::new(config).await?;
+  let (requests, without_video) = missing_requests(&mut database, &music_dir)?;
+  for title in &without_video {
+    context.log(format!("{title} was not downloaded from a video"));
+  }
+  context.log(format!("downloading {} songs again, {} have no video", requests.len(), without_video.len()));
+  if !requests.is_empty() {
+    action_tx.send(Action::DownloadEnqueue(requests))?;
+  }
+  Ok(())
+}
+
+/// What is wrong with the file in the music dir, if anything
+///
+/// # Returns
+///
+/// * the checksum to record, the one recorded before when the file was damaged since, and the
+///   problem found
+async fn verify_file(path: &Path, file: &File, decode_seconds: Option<u32>) -> Result<(String, Option<String>)> {
+  let blocking_path = path.to_path_buf();
+  let checksum = tokio::task::spawn_blocking(move || checksum(&blocking_path)).await??;
+  let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  if damaged(file, &checksum, modified) {
+    let verified_at = file.verified_at.map(|verified_at| verified_at.format("%Y-%m-%d").to_string());
+    let problem = format!("the file changed since it was verified on {}", verified_at.unwrap_or_default());
+    return Ok((file.checksum.clone().unwrap_or(checksum), Some(problem)));
+  }
+  if let Err(outcome) = scan::probe(path).await {
+    return Ok((checksum, Some(outcome.to_string())));
+  }
+  if let Some(seconds) = decode_seconds {
+    if let Err(e) = postprocess::decode(path, seconds).await {
+      return Ok((checksum, Some(format!("failed to decode: {e}"))));
+    }
+  }
+  Ok((checksum, None))
+}
+
+/// Verify the file of every song, recording its checksum and what is wrong with it, to be run as
+/// a job
+pub async fn verify_files(config: Config, context: JobContext) -> Result<()> {
+  let music_dir = config.config.music_dir.clone();
+  let decode_seconds = config.config.verify.decode_seconds;
+  let mut database = Database::new(config).await?;
+  let files = database.get_songs_with_file_records()?;
+  let total = files.len() as u64;
+  let (mut missing, mut damaged) = (0, 0);
+  for (index, (song, file)) in files.into_iter().enumerate() {
+    context.pace().await;
+    if context.is_cancelled() {
+      break;
+    }
+    let path: PathBuf = music_dir.join(&file.relative_path);
+    if !path.is_file() {
+      missing += 1;
+      context.log(format!("the file of {} is gone: {}", song.title, file.relative_path));
+    } else {
+      match verify_file(&path, &file, decode_seconds).await {
+        Ok((checksum, problem)) => {
+          if let Some(problem) = &problem {
+            damaged += 1;
+            context.log(format!("{}: {problem}", file.relative_path));
+          }
+          let verified_at = Utc::now().naive_utc();
+          database.set_file_verification(file.id, Some(&checksum), problem.as_deref(), verified_at)?;
+        },
+        Err(e) => context.log(format!("failed to verify {}: {e}", file.relative_path)),
+      }
+    }
+    context.progress(index as u64 + 1, total);
+  }
+  context.log(format!("verified {total} files, {missing} are gone and {damaged} are damaged"));
+  Ok(())
+}
+
+/// Start verifying the files of the songs unless they are being verified already
+///
+/// # Returns
+///
+/// * whether the verification was started
+pub fn spawn_verify(jobs: &JobRegistry, config: Config) -> bool {
+  if jobs.snapshot().iter().any(|job| job.name == JOB_NAME && !job.state.is_done()) {
+    return false;
+  }
+  jobs.spawn(JobKind::Verify, JOB_NAME, move |context| verify_files(config.clone(), context));
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{
+    database::tests::setup_database,
+    models::{NewFile, NewSong},
+  };
+
+  #[test]
+  fn test_checksum() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("muzik-checksum-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "")?;
+    assert_eq!(checksum(&path)?, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    std::fs::remove_file(&path)?;
+    assert!(checksum(&path).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_damaged() {
+    let verified_at = DateTime::from_timestamp(1_700_000_000, 0).map(|time| time.naive_utc());
+    let file = File {
+      relative_path: "a51VH9BYzZA.opus".to_string(),
+      checksum: Some("e3b0c442".to_string()),
+      verified_at,
+      ..File::default()
+    };
+    let before = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    let after = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+    assert!(!damaged(&file, "e3b0c442", before));
+    assert!(damaged(&file, "5feceb66", before));
+    // written to by muzik since
+    assert!(!damaged(&file, "5feceb66", after));
+    // never verified
+    assert!(!damaged(&File { checksum: None, ..file }, "5feceb66", before));
+  }
+
+  #[test]
+  fn test_load_problems() -> Result<()> {
+    let mut database = setup_database()?;
+    let music_dir = std::env::temp_dir().join(format!("muzik-verify-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&music_dir)?;
+    for (title, path) in [("Stellar Stellar", "present.opus"), ("Bluerose", "missing.opus"), ("Ghost", "damaged.opus")]
+    {
+      let file_id = database.insert_file(NewFile { relative_path: path.to_string() })?;
+      database.insert_song(NewSong { title: title.to_string(), file_id: Some(file_id), ..Default::default() })?;
+      if path != "missing.opus" {
+        std::fs::write(music_dir.join(path), "")?;
+      }
+      let problem = (path == "damaged.opus").then_some("moov atom not found");
+      database.set_file_verification(file_id, Some("e3b0c442"), problem, Utc::now().naive_utc())?;
+    }
+    let problems: Vec<_> = load_problems(&mut database, &music_dir)?
+      .into_iter()
+      .map(|problem| (problem.title, problem.missing, problem.problem))
+      .collect();
+    assert_eq!(problems, vec![
+      ("Bluerose".to_string(), true, "the file is gone".to_string()),
+      ("Ghost".to_string(), false, "moov atom not found".to_string())
+    ]);
+
+    // only the song whose file is gone is downloaded again
+    let (requests, without_video) = missing_requests(&mut database, &music_dir)?;
+    assert_eq!((requests, without_video), (vec![], vec!["Bluerose".to_string()]));
+    let bluerose = database.get_all_songs()?.into_iter().find(|song| song.title == "Bluerose").map(|song| song.id);
+    database.update_song(bluerose.unwrap_or_default(), "Bluerose", Some("a51VH9BYzZA"))?;
+    let (requests, _) = missing_requests(&mut database, &music_dir)?;
+    let requested: Vec<_> = requests.iter().map(|request| (request.youtube_id.as_str(), request.duplicates)).collect();
+    assert_eq!(requested, vec![("a51VH9BYzZA", Some(DuplicatePolicy::KeepBoth))]);
+    std::fs::remove_dir_all(&music_dir)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,176 @@
+//! Outgoing webhooks for library events
+//!
+//! The daemon posts a JSON payload to every configured URL when a download or a job stops, for
+//! integrations such as Home Assistant automations. Webhooks in the Discord format are sent as a
+//! chat message instead.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use crate::{
+  config::{WebhookConfig, WebhookFormat},
+  jobs::{JobInfo, JobState},
+  queue::{QueueItem, QueueStatus},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+  DownloadFinished,
+  DownloadFailed,
+  JobFinished,
+  JobFailed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+  DownloadFinished { youtube_id: String, title: String, artists: Vec<String> },
+  DownloadFailed { youtube_id: String, title: String, error: String },
+  JobFinished { kind: String, name: String },
+  JobFailed { kind: String, name: String, error: String },
+}
+
+impl WebhookEvent {
+  /// The event of a download that stopped, `None` while it is still queued or downloading
+  pub fn from_download(item: &QueueItem) -> Option<Self> {
+    let request = item.request.clone();
+    match &item.status {
+      QueueStatus::Finished => {
+        Some(WebhookEvent::DownloadFinished {
+          youtube_id: request.youtube_id,
+          title: request.title,
+          artists: request.artists,
+        })
+      },
+      QueueStatus::Failed(error) => {
+        Some(WebhookEvent::DownloadFailed {
+          youtube_id: request.youtube_id,
+          title: request.title,
+          error: error.clone(),
+        })
+      },
+      QueueStatus::Pending
+      | QueueStatus::Downloading
+      | QueueStatus::Skipped(_)
+      | QueueStatus::Duplicate(_)
+      | QueueStatus::Review(_) => None,
+    }
+  }
+
+  /// The event of a job that stopped, `None` while it is still running. Cancelled jobs are not
+  /// reported
+  pub fn from_job(job: &JobInfo) -> Option<Self> {
+    let (kind, name) = (job.kind.to_string(), job.name.clone());
+    match &job.state {
+      JobState::Finished => Some(WebhookEvent::JobFinished { kind, name }),
+      JobState::Failed(error) => Some(WebhookEvent::JobFailed { kind, name, error: error.clone() }),
+      JobState::Queued | JobState::Running | JobState::Cancelled => None,
+    }
+  }
+
+  pub fn kind(&self) -> WebhookEventKind {
+    match self {
+      WebhookEvent::DownloadFinished { .. } => WebhookEventKind::DownloadFinished,
+      WebhookEvent::DownloadFailed { .. } => WebhookEventKind::DownloadFailed,
+      WebhookEvent::JobFinished { .. } => WebhookEventKind::JobFinished,
+      WebhookEvent::JobFailed { .. } => WebhookEventKind::JobFailed,
+    }
+  }
+
+  /// A sentence describing the event, for chat messages
+  pub fn summary(&self) -> String {
+    match self {
+      WebhookEvent::DownloadFinished { title, artists, .. } if !artists.is_empty() => {
+        format!("Downloaded {} - {title}", artists.join(", "))
+      },
+      WebhookEvent::DownloadFinished { title, .. } => format!("Downloaded {title}"),
+      WebhookEvent::DownloadFailed { title, error, .. } => format!("Failed to download {title}: {error}"),
+      WebhookEvent::JobFinished { kind, name } => format!("{kind} job finished: {name}"),
+      WebhookEvent::JobFailed { kind, name, error } => format!("{kind} job failed: {name}: {error}"),
+    }
+  }
+
+  /// The body posted to a webhook
+  pub fn payload(&self, format: WebhookFormat) -> Value {
+    match format {
+      WebhookFormat::Json => {
+        let mut payload = serde_json::to_value(self).unwrap_or_else(|_| json!({}));
+        payload["timestamp"] = json!(Utc::now().to_rfc3339());
+        payload
+      },
+      WebhookFormat::Discord => json!({ "content": self.summary() }),
+    }
+  }
+}
+
+pub struct Webhooks {
+  client: reqwest::Client,
+  hooks: Vec<WebhookConfig>,
+}
+
+impl Webhooks {
+  pub fn new(hooks: Vec<WebhookConfig>) -> Self {
+    Self { client: reqwest::Client::new(), hooks }
+  }
+
+  /// Post the event to every webhook subscribed to it. Failures are logged, not returned, as
+  /// nothing waits on webhooks
+  pub async fn send(&self, event: &WebhookEvent) {
+    let hooks = self.hooks.iter().filter(|hook| hook.events.is_empty() || hook.events.contains(&event.kind()));
+    for hook in hooks {
+      debug!("sending {:?} to {}", event.kind(), hook.url);
+      let response = self.client.post(&hook.url).json(&event.payload(hook.format)).send().await;
+      if let Err(e) = response.and_then(|response| response.error_for_status()) {
+        error!("failed to send webhook to {}: {e}", hook.url);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::{jobs::JobKind, queue::DownloadRequest};
+
+  #[test]
+  fn test_webhook_payload() {
+    let item = QueueItem {
+      id: 0,
+      request: DownloadRequest {
+        youtube_id: "a51VH9BYzZA".to_string(),
+        title: "Stellar Stellar".to_string(),
+        artists: vec!["Hoshimachi Suisei".to_string()],
+        album: None,
+        ..Default::default()
+      },
+      status: QueueStatus::Finished,
+      progress: None,
+    };
+    let event = WebhookEvent::from_download(&item).expect("the download stopped");
+    let payload = event.payload(WebhookFormat::Json);
+    assert_eq!(payload["event"], "download_finished");
+    assert_eq!(payload["youtube_id"], "a51VH9BYzZA");
+    assert!(payload["timestamp"].is_string());
+    assert_eq!(
+      event.payload(WebhookFormat::Discord),
+      json!({ "content": "Downloaded Hoshimachi Suisei - Stellar Stellar" })
+    );
+  }
+
+  #[test]
+  fn test_webhook_job_events() {
+    let mut job = JobInfo { kind: JobKind::Scan, name: "scan library".to_string(), ..Default::default() };
+    job.state = JobState::Running;
+    assert_eq!(WebhookEvent::from_job(&job), None);
+    job.state = JobState::Failed("no music dir".to_string());
+    assert_eq!(
+      WebhookEvent::from_job(&job).map(|event| event.summary()),
+      Some("Scan job failed: scan library: no music dir".to_string())
+    );
+  }
+}